@@ -0,0 +1,141 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::Rng;
+
+use algebra::bls12_377::G1Projective;
+
+use bls_crypto::{
+    hash_to_curve::try_and_increment::{COMPOSITE_HASH_TO_G1, DIRECT_HASH_TO_G1},
+    HashToCurve, PrivateKey, PublicKey, Signature, SIG_DOMAIN,
+};
+
+/// The message lengths of the hashing benchmarks
+const MESSAGE_LENGTHS: &[usize] = &[32, 256, 1024];
+/// The number of keys or signatures of the aggregation and batch verification benchmarks
+const BATCH_SIZES: &[usize] = &[1, 10, 100];
+
+fn random_bytes(length: usize) -> Vec<u8> {
+    let rng = &mut rand::thread_rng();
+    (0..length).map(|_| rng.gen()).collect()
+}
+
+fn hash_to_curve(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash_to_g1");
+    for length in MESSAGE_LENGTHS {
+        let message = random_bytes(*length);
+        group.throughput(Throughput::Bytes(*length as u64));
+        group.bench_with_input(
+            BenchmarkId::new("direct", length),
+            &message,
+            |b, message| b.iter(|| DIRECT_HASH_TO_G1.hash(SIG_DOMAIN, message, &[]).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("composite", length),
+            &message,
+            |b, message| b.iter(|| COMPOSITE_HASH_TO_G1.hash(SIG_DOMAIN, message, &[]).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn sign_and_verify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("signature");
+    let try_and_increment = &*COMPOSITE_HASH_TO_G1;
+    let rng = &mut rand::thread_rng();
+    let key = PrivateKey::generate(rng);
+    let public_key = key.to_public();
+    let message = random_bytes(32);
+    let signature = key.sign(&message, &[], try_and_increment).unwrap();
+
+    group.bench_function("sign", |b| {
+        b.iter(|| key.sign(&message, &[], try_and_increment).unwrap())
+    });
+    group.bench_function("verify", |b| {
+        b.iter(|| {
+            public_key
+                .verify(&message, &[], &signature, try_and_increment)
+                .unwrap()
+        })
+    });
+
+    // the scalar multiplication alone, without hashing the message
+    let hash: G1Projective = try_and_increment.hash(SIG_DOMAIN, &message, &[]).unwrap();
+    group.bench_function("sign prehashed", |b| b.iter(|| key.sign_hashed(&hash)));
+    group.finish();
+}
+
+fn aggregate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("aggregate");
+    let try_and_increment = &*COMPOSITE_HASH_TO_G1;
+    let rng = &mut rand::thread_rng();
+    let message = random_bytes(32);
+    for size in BATCH_SIZES {
+        let keys = (0..*size)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let public_keys = keys.iter().map(|key| key.to_public()).collect::<Vec<_>>();
+        let signatures = keys
+            .iter()
+            .map(|key| key.sign(&message, &[], try_and_increment).unwrap())
+            .collect::<Vec<_>>();
+
+        group.throughput(Throughput::Elements(*size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("public keys", size),
+            &public_keys,
+            |b, public_keys| b.iter(|| PublicKey::aggregate(public_keys)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("signatures", size),
+            &signatures,
+            |b, signatures| b.iter(|| Signature::aggregate(signatures)),
+        );
+    }
+    group.finish();
+}
+
+fn batch_verify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch_verify");
+    group.sample_size(10);
+    let try_and_increment = &*COMPOSITE_HASH_TO_G1;
+    let rng = &mut rand::thread_rng();
+    for size in BATCH_SIZES {
+        // one signer per message, as the batch verification hashes each message once
+        let messages = (0..*size)
+            .map(|_| (random_bytes(32), random_bytes(32)))
+            .collect::<Vec<_>>();
+        let messages = messages
+            .iter()
+            .map(|(message, extra_data)| (message.as_ref(), extra_data.as_ref()))
+            .collect::<Vec<_>>();
+        let keys = (0..*size)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let public_keys = keys.iter().map(|key| key.to_public()).collect::<Vec<_>>();
+        let signatures = keys
+            .iter()
+            .zip(&messages)
+            .map(|(key, (message, extra_data))| {
+                key.sign(message, extra_data, try_and_increment).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let asig = Signature::aggregate(&signatures);
+
+        group.throughput(Throughput::Elements(*size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &asig, |b, asig| {
+            b.iter(|| {
+                asig.batch_verify(&public_keys, SIG_DOMAIN, &messages, try_and_increment)
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    hash_to_curve,
+    sign_and_verify,
+    aggregate,
+    batch_verify
+);
+criterion_main!(benches);
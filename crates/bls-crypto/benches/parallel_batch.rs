@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::Rng;
+
+use bls_crypto::{
+    hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1, PrivateKey, Signature, SIG_DOMAIN,
+};
+
+fn parallel_batch_verification(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel batch verification");
+    group.sample_size(10);
+    // One aggregate signature over the blocks of a light client sync
+    const NUM_BLOCKS: usize = 500;
+    let rng = &mut rand::thread_rng();
+    let try_and_increment = &*COMPOSITE_HASH_TO_G1;
+
+    let msgs = (0..NUM_BLOCKS)
+        .map(|_| {
+            let message = (0..32).map(|_| rng.gen()).collect::<Vec<u8>>();
+            let extra_data = (0..32).map(|_| rng.gen()).collect::<Vec<u8>>();
+            (message, extra_data)
+        })
+        .collect::<Vec<_>>();
+    let msgs = msgs
+        .iter()
+        .map(|(m, d)| (m.as_ref(), d.as_ref()))
+        .collect::<Vec<_>>();
+
+    let keys = (0..NUM_BLOCKS)
+        .map(|_| PrivateKey::generate(rng))
+        .collect::<Vec<_>>();
+    let pubkeys = keys.iter().map(|key| key.to_public()).collect::<Vec<_>>();
+    let asig = Signature::aggregate(
+        keys.iter()
+            .zip(&msgs)
+            .map(|(key, msg)| key.sign(msg.0, msg.1, try_and_increment).unwrap()),
+    );
+
+    group.bench_function("serial", |b| {
+        b.iter(|| {
+            asig.batch_verify(&pubkeys, SIG_DOMAIN, &msgs, try_and_increment)
+                .unwrap()
+        })
+    });
+
+    for chunk_size in &[4, 16, 64, 256] {
+        group.bench_with_input(
+            BenchmarkId::new("parallel", chunk_size),
+            chunk_size,
+            |b, chunk_size| {
+                b.iter(|| {
+                    asig.batch_verify_parallel(
+                        &pubkeys,
+                        SIG_DOMAIN,
+                        &msgs,
+                        try_and_increment,
+                        *chunk_size,
+                    )
+                    .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parallel_batch_verification);
+criterion_main!(benches);
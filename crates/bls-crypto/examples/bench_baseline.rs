@@ -0,0 +1,87 @@
+use bls_crypto::baseline::{compare, run, Baseline};
+
+use clap::{App, Arg, SubCommand};
+use std::fs;
+
+fn main() {
+    let matches = App::new("BLS benchmark baselines")
+        .about("Records machine-readable benchmark baselines and compares them")
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Runs the benchmark set and writes the baseline as JSON")
+                .arg(
+                    Arg::with_name("iterations")
+                        .short("i")
+                        .value_name("ITERATIONS")
+                        .help("Sets the number of iterations per operation")
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .short("o")
+                        .value_name("OUT")
+                        .help("Sets the output file path, prints to stdout if not set"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compare")
+                .about("Compares two baselines, exits with an error on regressions")
+                .arg(
+                    Arg::with_name("baseline")
+                        .short("b")
+                        .value_name("BASELINE")
+                        .help("Sets the path of the previous baseline")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("current")
+                        .short("c")
+                        .value_name("CURRENT")
+                        .help("Sets the path of the current baseline")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("threshold")
+                        .short("t")
+                        .value_name("PERCENT")
+                        .help("Sets the maximum allowed slowdown in percent")
+                        .default_value("10"),
+                ),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        ("run", Some(matches)) => {
+            let iterations: usize = matches.value_of("iterations").unwrap().parse().unwrap();
+            let json = run(iterations).to_json().unwrap();
+            match matches.value_of("out") {
+                Some(out) => fs::write(out, json).unwrap(),
+                None => println!("{}", json),
+            }
+        }
+        ("compare", Some(matches)) => {
+            let read = |arg| {
+                let json = fs::read_to_string(matches.value_of(arg).unwrap()).unwrap();
+                Baseline::from_json(&json).unwrap()
+            };
+            let threshold: f64 = matches.value_of("threshold").unwrap().parse().unwrap();
+            let regressions = compare(&read("baseline"), &read("current"), threshold);
+            for regression in &regressions {
+                println!(
+                    "{}: {:.2} -> {:.2} ops/sec ({:.1}% slower)",
+                    regression.operation,
+                    regression.baseline_ops_per_sec,
+                    regression.current_ops_per_sec,
+                    regression.slowdown_percent
+                );
+            }
+            if !regressions.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("{}", matches.usage());
+            std::process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Selection of the base field arithmetic used by verification.
+//!
+//! With the `asm` feature, field multiplication uses algebra's x86_64 assembly, which relies
+//! on the ADX and BMI2 instructions. The assembly is only compiled in when these instructions
+//! are enabled for the build, e.g. with `RUSTFLAGS="-C target-feature=+bmi2,+adx"`; other
+//! builds fall back to the portable arithmetic. algebra selects its arithmetic at compile
+//! time, so a binary contains a single backend and there is no runtime dispatch: a binary
+//! built with the assembly crashes on CPUs without these instructions. Long-running services
+//! should call `check` on startup, and use `best_available` to pick between a portable and
+//! an assembly binary.
+use crate::{BLSError, BlsResult};
+use std::fmt;
+
+/// The implementation of the base field arithmetic
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldBackend {
+    /// algebra's x86_64 assembly, using the ADX and BMI2 instructions
+    Assembly,
+    /// The portable Rust arithmetic
+    Portable,
+}
+
+impl fmt::Display for FieldBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldBackend::Assembly => write!(f, "assembly"),
+            FieldBackend::Portable => write!(f, "portable"),
+        }
+    }
+}
+
+/// The backend compiled into this build
+#[cfg(all(
+    feature = "asm",
+    target_arch = "x86_64",
+    target_feature = "adx",
+    target_feature = "bmi2"
+))]
+pub const COMPILED: FieldBackend = FieldBackend::Assembly;
+
+/// The backend compiled into this build
+#[cfg(not(all(
+    feature = "asm",
+    target_arch = "x86_64",
+    target_feature = "adx",
+    target_feature = "bmi2"
+)))]
+pub const COMPILED: FieldBackend = FieldBackend::Portable;
+
+/// Returns true if the CPU supports the instructions used by the assembly backend
+pub fn cpu_supports_assembly() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("adx") && is_x86_feature_detected!("bmi2")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+/// Returns the backend compiled into this build, or an error if the CPU cannot run it
+pub fn check() -> BlsResult<FieldBackend> {
+    if COMPILED == FieldBackend::Assembly && !cpu_supports_assembly() {
+        return Err(BLSError::UnsupportedCpu(
+            "the field arithmetic was compiled for ADX and BMI2",
+        ));
+    }
+    Ok(COMPILED)
+}
+
+/// Returns the backend which a build with the `asm` feature and the ADX and BMI2 target
+/// features would use on this CPU. Useful to pick between portable and assembly binaries.
+pub fn best_available() -> FieldBackend {
+    if cpu_supports_assembly() {
+        FieldBackend::Assembly
+    } else {
+        FieldBackend::Portable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiled_backend_runs() {
+        // the tests could not run if the CPU did not support the compiled backend
+        assert_eq!(check().unwrap(), COMPILED);
+        if COMPILED == FieldBackend::Assembly {
+            assert_eq!(best_available(), FieldBackend::Assembly);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Machine-readable performance baselines.
+//!
+//! `run` measures a fixed set of operations and returns their throughput together with
+//! information about the machine, which can be stored as JSON. A baseline recorded for a
+//! previous release can then be compared against the current one with `compare`, which
+//! returns every operation whose throughput dropped by more than the allowed percentage.
+//!
+//! Enabled with the `bench-baseline` feature. The `bench_baseline` example wraps this module
+//! in a command line tool.
+use crate::{
+    hash_to_curve::{
+        try_and_increment::{COMPOSITE_HASH_TO_G1, DIRECT_HASH_TO_G1},
+        HashToCurve,
+    },
+    PrivateKey, PublicKey, Signature, SIG_DOMAIN,
+};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Number of keys and messages in the aggregation and batch verification benchmarks
+const BATCH_SIZE: usize = 10;
+
+/// Information about the machine which recorded a baseline
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MachineInfo {
+    /// The operating system, e.g. `linux`
+    pub os: String,
+    /// The CPU architecture, e.g. `x86_64`
+    pub arch: String,
+    /// The number of logical CPUs
+    pub num_cpus: usize,
+    /// The version of this crate
+    pub crate_version: String,
+}
+
+impl MachineInfo {
+    /// Returns the information of the current machine
+    pub fn current() -> Self {
+        Self {
+            os: std::env::consts::OS.to_owned(),
+            arch: std::env::consts::ARCH.to_owned(),
+            num_cpus: num_cpus::get(),
+            crate_version: env!("CARGO_PKG_VERSION").to_owned(),
+        }
+    }
+}
+
+/// The throughput of a single operation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Measurement {
+    /// The name of the operation
+    pub operation: String,
+    /// The number of operations per second
+    pub ops_per_sec: f64,
+}
+
+/// The throughput of all benchmarked operations on a machine
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    /// The machine which ran the benchmarks
+    pub machine: MachineInfo,
+    /// The number of times each operation was run
+    pub iterations: usize,
+    /// The measurements, one per operation
+    pub measurements: Vec<Measurement>,
+}
+
+impl Baseline {
+    /// Returns the measurement of the provided operation, if any
+    pub fn get(&self, operation: &str) -> Option<&Measurement> {
+        self.measurements.iter().find(|m| m.operation == operation)
+    }
+
+    /// Serializes the baseline to pretty-printed JSON
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Deserializes a baseline from JSON
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
+/// An operation whose throughput dropped by more than the allowed percentage
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Regression {
+    /// The name of the operation
+    pub operation: String,
+    /// The throughput in the baseline
+    pub baseline_ops_per_sec: f64,
+    /// The current throughput
+    pub current_ops_per_sec: f64,
+    /// The throughput drop in percent of the baseline
+    pub slowdown_percent: f64,
+}
+
+/// Runs each operation of the benchmark set `iterations` times and returns their throughput.
+/// The inputs are generated from a fixed seed, so that all runs measure the same work.
+pub fn run(iterations: usize) -> Baseline {
+    let iterations = iterations.max(1);
+    let rng = &mut ChaChaRng::from_seed([0; 32]);
+    let direct = &*DIRECT_HASH_TO_G1;
+    let composite = &*COMPOSITE_HASH_TO_G1;
+
+    let keys = (0..BATCH_SIZE)
+        .map(|_| PrivateKey::generate(rng))
+        .collect::<Vec<_>>();
+    let public_keys = keys.iter().map(|k| k.to_public()).collect::<Vec<_>>();
+    let messages = (0..BATCH_SIZE)
+        .map(|_| (0..32).map(|_| rng.gen()).collect::<Vec<u8>>())
+        .collect::<Vec<_>>();
+    let signatures = keys
+        .iter()
+        .zip(&messages)
+        .map(|(key, message)| key.sign(message, &[], direct).unwrap())
+        .collect::<Vec<_>>();
+    let message_hashes = messages
+        .iter()
+        .map(|message| direct.hash(SIG_DOMAIN, message, &[]).unwrap())
+        .collect::<Vec<_>>();
+    let aggregate = Signature::aggregate(&signatures);
+
+    let mut measurements = Vec::new();
+    let mut measure = |operation: &str, f: &mut dyn FnMut()| {
+        let start = Instant::now();
+        for _ in 0..iterations {
+            f();
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        measurements.push(Measurement {
+            operation: operation.to_owned(),
+            ops_per_sec: iterations as f64 / elapsed.max(f64::EPSILON),
+        });
+    };
+
+    measure("hash_to_g1_direct", &mut || {
+        direct.hash(SIG_DOMAIN, &messages[0], &[]).unwrap();
+    });
+    measure("hash_to_g1_composite", &mut || {
+        composite.hash(SIG_DOMAIN, &messages[0], &[]).unwrap();
+    });
+    measure("sign", &mut || {
+        keys[0].sign(&messages[0], &[], direct).unwrap();
+    });
+    measure("verify", &mut || {
+        public_keys[0]
+            .verify(&messages[0], &[], &signatures[0], direct)
+            .unwrap();
+    });
+    measure("aggregate_public_keys", &mut || {
+        PublicKey::aggregate(&public_keys);
+    });
+    measure("batch_verify_hashes", &mut || {
+        aggregate
+            .batch_verify_hashes(&public_keys, &message_hashes)
+            .unwrap();
+    });
+
+    Baseline {
+        machine: MachineInfo::current(),
+        iterations,
+        measurements,
+    }
+}
+
+/// Compares the current measurements against a baseline, returning every operation whose
+/// throughput dropped by more than `max_slowdown_percent`. Operations which are missing
+/// from either side are skipped.
+pub fn compare(
+    baseline: &Baseline,
+    current: &Baseline,
+    max_slowdown_percent: f64,
+) -> Vec<Regression> {
+    baseline
+        .measurements
+        .iter()
+        .filter_map(|before| {
+            let after = current.get(&before.operation)?;
+            let slowdown_percent =
+                (before.ops_per_sec - after.ops_per_sec) / before.ops_per_sec * 100.0;
+            if slowdown_percent > max_slowdown_percent {
+                Some(Regression {
+                    operation: before.operation.clone(),
+                    baseline_ops_per_sec: before.ops_per_sec,
+                    current_ops_per_sec: after.ops_per_sec,
+                    slowdown_percent,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline(measurements: &[(&str, f64)]) -> Baseline {
+        Baseline {
+            machine: MachineInfo::current(),
+            iterations: 1,
+            measurements: measurements
+                .iter()
+                .map(|(operation, ops_per_sec)| Measurement {
+                    operation: operation.to_string(),
+                    ops_per_sec: *ops_per_sec,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn flags_slowdowns_above_threshold() {
+        let before = baseline(&[("sign", 100.0), ("verify", 50.0), ("removed", 10.0)]);
+        let after = baseline(&[("sign", 94.0), ("verify", 40.0), ("added", 1.0)]);
+
+        let regressions = compare(&before, &after, 10.0);
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].operation, "verify");
+        assert!((regressions[0].slowdown_percent - 20.0).abs() < 1e-9);
+
+        assert_eq!(compare(&before, &after, 5.0).len(), 2);
+        // speedups are never regressions
+        assert!(compare(&after, &before, 0.0).is_empty());
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let before = baseline(&[("sign", 100.0)]);
+        let json = before.to_json().unwrap();
+        assert_eq!(Baseline::from_json(&json).unwrap(), before);
+    }
+
+    #[test]
+    fn runs_all_operations() {
+        let result = run(1);
+        assert_eq!(result.measurements.len(), 6);
+        assert!(result.measurements.iter().all(|m| m.ops_per_sec > 0.0));
+    }
+}
@@ -0,0 +1,407 @@
+//! BLS signatures are unique: for a given key and message there is exactly one valid
+//! signature, so signing a public round message acts as a verifiable random function whose
+//! output can be checked by anyone holding the public key. When the signing key is shared
+//! among validators with a `t`-of-`n` Shamir sharing, each validator produces a partial
+//! evaluation by signing the round message with its share. Any `t` valid partial evaluations
+//! are combined with Lagrange interpolation into the signature under the group key, which
+//! does not depend on which validators contributed. The round's random value is the hash of
+//! that signature, and the signature itself is the proof.
+//!
+//! Each round's message commits to the previous round's randomness, so the beacon forms a
+//! chain which cannot be computed ahead of time.
+use crate::{
+    threshold::{self, PartialSignature},
+    BLSError, BlsResult, HashToCurve, PrivateKey, PublicKey, Signature,
+};
+
+use algebra::{
+    bls12_377::{Fr, G1Projective},
+    CanonicalSerialize, Field, One, Zero,
+};
+use lru::LruCache;
+
+/// Domain separator for the beacon's round messages
+pub const BEACON_DOMAIN: &[u8] = b"ULbeacon";
+
+/// Personalization of the hash which derives the randomness from the round signature
+const RANDOMNESS_PERSONALIZATION: &[u8] = b"ULrandom";
+
+/// Length of the random value produced in each round
+pub const RANDOMNESS_LENGTH: usize = 32;
+
+/// Returns the message signed in the provided round, which binds the round number to the
+/// randomness of the previous round
+pub fn round_message(round: u64, previous: &[u8; RANDOMNESS_LENGTH]) -> Vec<u8> {
+    let mut message = round.to_le_bytes().to_vec();
+    message.extend_from_slice(previous);
+    message
+}
+
+/// A validator's evaluation of a round, produced with its share of the group key
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialEvaluation {
+    /// The index of the validator's share, starting from 1
+    pub index: u64,
+    /// The signature over the round message with the validator's share
+    pub signature: Signature,
+}
+
+impl PartialEvaluation {
+    /// Evaluates the round with the share of the group key at the provided index
+    pub fn new<H: HashToCurve<Output = G1Projective>>(
+        share: &PrivateKey,
+        index: u64,
+        round: u64,
+        previous: &[u8; RANDOMNESS_LENGTH],
+        hash_to_g1: &H,
+    ) -> BlsResult<Self> {
+        let hash = hash_round(round, previous, hash_to_g1)?;
+        Ok(Self {
+            index,
+            signature: share.sign_hashed(&hash),
+        })
+    }
+
+    /// Verifies the evaluation against the public key of the validator's share
+    pub fn verify<H: HashToCurve<Output = G1Projective>>(
+        &self,
+        share_public_key: &PublicKey,
+        round: u64,
+        previous: &[u8; RANDOMNESS_LENGTH],
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        let hash = hash_round(round, previous, hash_to_g1)?;
+        self.signature
+            .batch_verify_hashes(&[share_public_key], &[hash])
+    }
+}
+
+/// The output of a beacon round
+#[derive(Clone, Debug, PartialEq)]
+pub struct BeaconOutput {
+    /// The round number
+    pub round: u64,
+    /// The signature over the round message under the group key, which proves the randomness
+    pub signature: Signature,
+    /// The random value of the round
+    pub randomness: [u8; RANDOMNESS_LENGTH],
+}
+
+impl BeaconOutput {
+    /// Combines `threshold` partial evaluations of the round into the round's output. The
+    /// partial evaluations must have been verified individually beforehand, extra evaluations
+    /// are ignored.
+    pub fn combine(
+        round: u64,
+        threshold: usize,
+        partials: &[PartialEvaluation],
+    ) -> BlsResult<Self> {
+        let signature = threshold::combine(threshold, &partial_signatures(partials))?;
+        Ok(Self::from_signature(round, signature))
+    }
+
+    /// Same as `combine`, but takes the Lagrange coefficients of the signer set from the
+    /// cache, so that combining the partial evaluations of a signer set which was combined
+    /// before only costs `threshold` scalar multiplications.
+    pub fn combine_cached(
+        round: u64,
+        threshold: usize,
+        partials: &[PartialEvaluation],
+        cache: &mut LagrangeCache,
+    ) -> BlsResult<Self> {
+        let signature = threshold::combine_cached(threshold, &partial_signatures(partials), cache)?;
+        Ok(Self::from_signature(round, signature))
+    }
+
+    /// Derives the round's output from the signature under the group key
+    pub fn from_signature(round: u64, signature: Signature) -> Self {
+        let mut bytes = vec![];
+        signature
+            .serialize(&mut bytes)
+            .expect("serializing to a vector cannot fail");
+        let hash = blake2s_simd::Params::new()
+            .hash_length(RANDOMNESS_LENGTH)
+            .personal(RANDOMNESS_PERSONALIZATION)
+            .hash(&bytes);
+        let mut randomness = [0u8; RANDOMNESS_LENGTH];
+        randomness.copy_from_slice(hash.as_bytes());
+
+        Self {
+            round,
+            signature,
+            randomness,
+        }
+    }
+
+    /// Verifies the output against the group public key and the previous round's randomness
+    pub fn verify<H: HashToCurve<Output = G1Projective>>(
+        &self,
+        group_public_key: &PublicKey,
+        previous: &[u8; RANDOMNESS_LENGTH],
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        if Self::from_signature(self.round, self.signature.clone()).randomness != self.randomness {
+            return Err(BLSError::VerificationFailed);
+        }
+        let hash = hash_round(self.round, previous, hash_to_g1)?;
+        self.signature
+            .batch_verify_hashes(&[group_public_key], &[hash])
+    }
+}
+
+/// Lagrange coefficients at zero of recently combined signer sets.
+///
+/// The signers online in consecutive rounds rarely change, while computing the coefficients
+/// of `t` signers takes `O(t^2)` multiplications and `t` inversions. The coefficients are
+/// cached per signer set and the least recently used sets are evicted.
+pub struct LagrangeCache {
+    coefficients: LruCache<Vec<u64>, Vec<Fr>>,
+}
+
+impl Default for LagrangeCache {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl LagrangeCache {
+    /// Creates a cache holding the coefficients of up to `capacity` signer sets. A cache
+    /// always holds at least one set, so a zero capacity is raised to one.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            coefficients: LruCache::new(capacity.max(1)),
+        }
+    }
+
+    /// Returns the coefficients of the signers with the provided share indices, in the
+    /// order of the indices. The indices must be sorted, distinct and non-zero.
+    pub fn coefficients(&mut self, indices: &[u64]) -> BlsResult<&[Fr]> {
+        if let Some(i) =
+            (0..indices.len()).find(|&i| indices[i] == 0 || (i > 0 && indices[i - 1] >= indices[i]))
+        {
+            return Err(BLSError::InvalidShareIndex(indices[i]));
+        }
+
+        if !self.coefficients.contains(indices) {
+            let points = indices.iter().map(|i| Fr::from(*i)).collect::<Vec<_>>();
+            let coefficients = (0..points.len())
+                .map(|i| lagrange_coefficient_at_zero(&points, i))
+                .collect();
+            self.coefficients.put(indices.to_vec(), coefficients);
+        }
+        Ok(self
+            .coefficients
+            .get(indices)
+            .expect("the coefficients were just inserted")
+            .as_slice())
+    }
+
+    /// Discards the coefficients of the signer set, e.g. after one of its signers went
+    /// offline. Returns whether they were cached.
+    pub fn invalidate(&mut self, indices: &[u64]) -> bool {
+        self.coefficients.pop(indices).is_some()
+    }
+
+    /// Discards the coefficients of all signer sets, e.g. after the key was reshared
+    pub fn clear(&mut self) {
+        self.coefficients.clear();
+    }
+
+    /// The number of cached signer sets
+    pub fn len(&self) -> usize {
+        self.coefficients.len()
+    }
+
+    /// Returns true if no signer set is cached
+    pub fn is_empty(&self) -> bool {
+        self.coefficients.is_empty()
+    }
+}
+
+// The partial evaluations are the partial signatures of the round message, which are combined
+// like any other threshold signature
+fn partial_signatures(partials: &[PartialEvaluation]) -> Vec<PartialSignature> {
+    partials
+        .iter()
+        .map(|partial| PartialSignature {
+            index: partial.index,
+            signature: partial.signature.clone(),
+        })
+        .collect()
+}
+
+fn hash_round<H: HashToCurve<Output = G1Projective>>(
+    round: u64,
+    previous: &[u8; RANDOMNESS_LENGTH],
+    hash_to_g1: &H,
+) -> BlsResult<G1Projective> {
+    hash_to_g1.hash(BEACON_DOMAIN, &round_message(round, previous), &[])
+}
+
+// Returns the Lagrange coefficient of the `i`-th point, evaluated at zero. The indices must
+// be distinct and non-zero.
+pub(crate) fn lagrange_coefficient_at_zero(indices: &[Fr], i: usize) -> Fr {
+    let mut numerator = Fr::one();
+    let mut denominator = Fr::one();
+    for (j, index) in indices.iter().enumerate() {
+        if j == i {
+            continue;
+        }
+        numerator *= index;
+        denominator *= &(*index - &indices[i]);
+    }
+    debug_assert!(!denominator.is_zero());
+    numerator * &denominator.inverse().expect("indices must be distinct")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, threshold::deal};
+    use rand::{seq::SliceRandom, thread_rng};
+
+    #[test]
+    fn combines_any_subset_to_the_same_output() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let group_key = PrivateKey::generate(rng);
+        let (_, shares) = deal(&group_key, 3, 5, rng).unwrap();
+        let previous = [7u8; RANDOMNESS_LENGTH];
+
+        let mut partials = shares
+            .iter()
+            .map(|share| {
+                let partial =
+                    PartialEvaluation::new(share.key(), share.index, 1, &previous, hasher).unwrap();
+                partial
+                    .verify(&share.to_public().public_key, 1, &previous, hasher)
+                    .unwrap();
+                partial
+            })
+            .collect::<Vec<_>>();
+
+        let output = BeaconOutput::combine(1, 3, &partials).unwrap();
+        output
+            .verify(&group_key.to_public(), &previous, hasher)
+            .unwrap();
+
+        partials.shuffle(rng);
+        let other = BeaconOutput::combine(1, 3, &partials[..3]).unwrap();
+        assert_eq!(output, other);
+
+        // the next round chains on this round's randomness
+        let next =
+            PartialEvaluation::new(shares[0].key(), 1, 2, &output.randomness, hasher).unwrap();
+        next.verify(&shares[0].to_public().public_key, 2, &previous, hasher)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn rejects_invalid_partials() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let (_, shares) = deal(&PrivateKey::generate(rng), 2, 3, rng).unwrap();
+        let previous = [0u8; RANDOMNESS_LENGTH];
+        let partial = PartialEvaluation::new(shares[0].key(), 1, 1, &previous, hasher).unwrap();
+
+        // wrong share key
+        partial
+            .verify(&shares[1].to_public().public_key, 1, &previous, hasher)
+            .unwrap_err();
+
+        match BeaconOutput::combine(1, 2, &[partial.clone()]) {
+            Err(BLSError::NotEnoughPartials(1, 2)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match BeaconOutput::combine(1, 2, &[partial.clone(), partial]) {
+            Err(BLSError::InvalidShareIndex(1)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cached_combination_matches() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let (_, shares) = deal(&PrivateKey::generate(rng), 3, 5, rng).unwrap();
+        let previous = [3u8; RANDOMNESS_LENGTH];
+        let mut cache = LagrangeCache::new(2);
+
+        for round in 1..4 {
+            let mut partials = shares
+                .iter()
+                .map(|share| {
+                    PartialEvaluation::new(share.key(), share.index, round, &previous, hasher)
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+            partials.shuffle(rng);
+            // the same online subset in a different order hits the cache
+            let subset = &mut partials[..3];
+            let expected = BeaconOutput::combine(round, 3, subset).unwrap();
+            assert_eq!(
+                BeaconOutput::combine_cached(round, 3, subset, &mut cache).unwrap(),
+                expected
+            );
+            subset.reverse();
+            assert_eq!(
+                BeaconOutput::combine_cached(round, 3, subset, &mut cache).unwrap(),
+                expected
+            );
+        }
+        assert!(!cache.is_empty() && cache.len() <= 2);
+    }
+
+    #[test]
+    fn holds_at_least_one_signer_set() {
+        let mut cache = LagrangeCache::new(0);
+        cache.coefficients(&[1, 2]).unwrap();
+        cache.coefficients(&[1, 3]).unwrap();
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn invalidates_signer_sets() {
+        let mut cache = LagrangeCache::default();
+        let expected = (0..3)
+            .map(|i| {
+                lagrange_coefficient_at_zero(&[Fr::from(1u64), Fr::from(2u64), Fr::from(4u64)], i)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cache.coefficients(&[1, 2, 4]).unwrap(), &expected[..]);
+        cache.coefficients(&[1, 3]).unwrap();
+        assert_eq!(cache.len(), 2);
+
+        assert!(cache.invalidate(&[1, 2, 4]));
+        assert!(!cache.invalidate(&[1, 2, 4]));
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+
+        for indices in &[&[0, 1][..], &[2, 1], &[1, 1]] {
+            match cache.coefficients(indices) {
+                Err(BLSError::InvalidShareIndex(_)) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_output() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let group_key = PrivateKey::generate(rng);
+        let (_, shares) = deal(&group_key, 1, 1, rng).unwrap();
+        let previous = [0u8; RANDOMNESS_LENGTH];
+        let partial = PartialEvaluation::new(shares[0].key(), 1, 1, &previous, hasher).unwrap();
+        let mut output = BeaconOutput::combine(1, 1, &[partial]).unwrap();
+        output
+            .verify(&group_key.to_public(), &previous, hasher)
+            .unwrap();
+
+        output.randomness[0] ^= 1;
+        output
+            .verify(&group_key.to_public(), &previous, hasher)
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,219 @@
+//! Incremental aggregation of signatures and public keys which arrive one at a time.
+//!
+//! Contributions are keyed by the index of their validator, so that each validator
+//! contributes at most once and a contribution can be subtracted again, e.g. when its
+//! validator equivocates, without tracking the signers separately as `Signature::subtract`
+//! requires.
+use super::{PublicKey, Signature};
+use crate::{BLSError, BTreeMap, BlsResult};
+use algebra::{
+    bls12_377::{G1Projective, G2Projective},
+    ProjectiveCurve, Zero,
+};
+
+/// Aggregates the signatures of validators as they are received
+#[derive(Clone, Debug)]
+pub struct SignatureAggregator {
+    sum: G1Projective,
+    contributions: BTreeMap<usize, Signature>,
+}
+
+impl Default for SignatureAggregator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignatureAggregator {
+    /// Initializes an empty aggregation
+    pub fn new() -> Self {
+        Self {
+            sum: G1Projective::zero(),
+            contributions: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the signature of the validator at `index`. Fails if the validator already
+    /// contributed.
+    pub fn add(&mut self, index: usize, signature: Signature) -> BlsResult<()> {
+        if self.contributions.contains_key(&index) {
+            return Err(BLSError::DuplicateContribution(index));
+        }
+        self.sum += signature.as_ref();
+        self.contributions.insert(index, signature);
+        Ok(())
+    }
+
+    /// Subtracts the signature of the validator at `index` from the aggregate and returns
+    /// it, or returns `None` if the validator did not contribute
+    pub fn remove(&mut self, index: usize) -> Option<Signature> {
+        let signature = self.contributions.remove(&index)?;
+        self.sum -= signature.as_ref();
+        Some(signature)
+    }
+
+    /// Returns true if the validator at `index` contributed
+    pub fn contains(&self, index: usize) -> bool {
+        self.contributions.contains_key(&index)
+    }
+
+    /// The indices of the contributing validators, in increasing order
+    pub fn signers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.contributions.keys().cloned()
+    }
+
+    /// The number of contributions
+    pub fn len(&self) -> usize {
+        self.contributions.len()
+    }
+
+    /// Returns true if nothing was contributed
+    pub fn is_empty(&self) -> bool {
+        self.contributions.is_empty()
+    }
+
+    /// Returns the aggregate of the current contributions. It is normalized, so that it has
+    /// the same representation whatever the order of the additions and removals.
+    pub fn finalize(&self) -> Signature {
+        self.sum.into_affine().into_projective().into()
+    }
+}
+
+/// Aggregates the public keys of validators as their signatures are received
+#[derive(Clone, Debug)]
+pub struct PublicKeyAggregator {
+    sum: G2Projective,
+    contributions: BTreeMap<usize, PublicKey>,
+}
+
+impl Default for PublicKeyAggregator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PublicKeyAggregator {
+    /// Initializes an empty aggregation
+    pub fn new() -> Self {
+        Self {
+            sum: G2Projective::zero(),
+            contributions: BTreeMap::new(),
+        }
+    }
+
+    /// Initializes an aggregation of contributions whose sum was already computed
+    pub(super) fn with_sum(sum: &PublicKey, contributions: BTreeMap<usize, PublicKey>) -> Self {
+        Self {
+            sum: sum.0,
+            contributions,
+        }
+    }
+
+    /// Adds the public key of the validator at `index`. Fails if the validator already
+    /// contributed.
+    pub fn add(&mut self, index: usize, public_key: PublicKey) -> BlsResult<()> {
+        if self.contributions.contains_key(&index) {
+            return Err(BLSError::DuplicateContribution(index));
+        }
+        self.sum += &public_key.0;
+        self.contributions.insert(index, public_key);
+        Ok(())
+    }
+
+    /// Subtracts the public key of the validator at `index` from the aggregate and returns
+    /// it, or returns `None` if the validator did not contribute
+    pub fn remove(&mut self, index: usize) -> Option<PublicKey> {
+        let public_key = self.contributions.remove(&index)?;
+        self.sum -= &public_key.0;
+        Some(public_key)
+    }
+
+    /// Returns true if the validator at `index` contributed
+    pub fn contains(&self, index: usize) -> bool {
+        self.contributions.contains_key(&index)
+    }
+
+    /// The indices of the contributing validators, in increasing order
+    pub fn signers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.contributions.keys().cloned()
+    }
+
+    /// The contributions, in increasing order of their validator's index
+    pub fn contributions(&self) -> impl Iterator<Item = (usize, &PublicKey)> {
+        self.contributions.iter().map(|(index, key)| (*index, key))
+    }
+
+    /// The number of contributions
+    pub fn len(&self) -> usize {
+        self.contributions.len()
+    }
+
+    /// Returns true if nothing was contributed
+    pub fn is_empty(&self) -> bool {
+        self.contributions.is_empty()
+    }
+
+    /// Returns the aggregate of the current contributions. It is normalized, so that it has
+    /// the same representation whatever the order of the additions and removals.
+    pub fn finalize(&self) -> PublicKey {
+        PublicKey(self.sum.into_affine().into_projective())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, PrivateKey};
+    use algebra::One;
+
+    #[test]
+    fn aggregates_incrementally() {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let keys = (0..4)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let signatures = keys
+            .iter()
+            .map(|key| key.sign(b"hello", &[], hasher).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut signature_aggregator = SignatureAggregator::new();
+        let mut key_aggregator = PublicKeyAggregator::new();
+        for index in (0..4).rev() {
+            signature_aggregator
+                .add(index, signatures[index].clone())
+                .unwrap();
+            key_aggregator.add(index, keys[index].to_public()).unwrap();
+        }
+        match signature_aggregator.add(2, signatures[2].clone()) {
+            Err(BLSError::DuplicateContribution(2)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(
+            signature_aggregator.finalize(),
+            Signature::aggregate(&signatures)
+        );
+
+        // validator 1 equivocated
+        assert_eq!(signature_aggregator.remove(1), Some(signatures[1].clone()));
+        assert_eq!(key_aggregator.remove(1), Some(keys[1].to_public()));
+        assert_eq!(signature_aggregator.remove(1), None);
+        assert_eq!(
+            signature_aggregator.signers().collect::<Vec<_>>(),
+            vec![0, 2, 3]
+        );
+        assert_eq!(key_aggregator.len(), 3);
+
+        let signature = signature_aggregator.finalize();
+        let public_key = key_aggregator.finalize();
+        public_key
+            .verify(b"hello", &[], &signature, hasher)
+            .unwrap();
+        assert_eq!(
+            signature,
+            Signature::aggregate(vec![&signatures[0], &signatures[2], &signatures[3]])
+        );
+        assert!(signature.as_ref().z.is_one());
+    }
+}
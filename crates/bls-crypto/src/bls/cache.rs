@@ -1,12 +1,55 @@
-use super::PublicKey;
-use algebra::{bls12_377::G2Projective, CanonicalDeserialize, SerializationError, Zero};
+use super::{sort_canonically, PublicKey, PublicKeyAggregator, ValidationLevel};
+use crate::{BLSError, BlsResult};
+use algebra::{
+    bls12_377::G2Projective, AffineCurve, CanonicalDeserialize, ProjectiveCurve,
+    SerializationError, Zero,
+};
 
+use blake2s_simd::Params;
 use lru::LruCache;
 use std::{
     collections::HashSet,
     hash::{Hash, Hasher},
 };
 
+/// Length of the digests which key the deserialization cache
+pub const KEY_DIGEST_LENGTH: usize = 32;
+
+/// The digest of a serialized public key, used as a cache key. Compressed serialization is
+/// unique for each point, so this is also a digest of the normalized point.
+pub type KeyDigest = [u8; KEY_DIGEST_LENGTH];
+
+/// The occupancy of one of the caches held by a `PublicKeyCache`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheOccupancy {
+    /// The number of entries in the cache
+    pub len: usize,
+    /// The maximum number of entries before the least recently used ones are evicted
+    pub capacity: usize,
+}
+
+impl<K: Hash + Eq, V> From<&LruCache<K, V>> for CacheOccupancy {
+    fn from(cache: &LruCache<K, V>) -> Self {
+        Self {
+            len: cache.len(),
+            capacity: cache.cap(),
+        }
+    }
+}
+
+/// Statistics about a `PublicKeyCache`'s memory use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PublicKeyCacheStats {
+    /// The deserialized public keys
+    pub deserialized: CacheOccupancy,
+    /// The validator sets used by `aggregate_for_bitmap`
+    pub validator_sets: CacheOccupancy,
+    /// The aggregate public keys of bitmaps
+    pub bitmap_aggregates: CacheOccupancy,
+    /// The number of keys in the aggregated validator set
+    pub aggregated_keys: usize,
+}
+
 /// Allows deserializing and aggregation of public keys while holding a cache to improve
 /// performance. Aggregation assumes that the aggregated public key changes slowly.
 pub struct PublicKeyCache {
@@ -14,9 +57,14 @@ pub struct PublicKeyCache {
     keys: HashSet<WrappedPublicKey>,
     /// The aggregated public key of all validators
     pub combined: PublicKey,
-    /// An in-memory mapping of serialized pubkey byte arrays to their deserialized
-    /// group element representation
-    pub de: LruCache<Vec<u8>, PublicKey>,
+    /// An in-memory mapping of the digests of serialized pubkeys to their deserialized
+    /// group element representation. Keying by a fixed-size digest instead of the serialized
+    /// bytes avoids a heap allocation per entry and shortens hashing on lookups.
+    pub de: LruCache<KeyDigest, PublicKey>,
+    /// The validator sets of the most recent epochs, keyed by their epoch id
+    validator_sets: LruCache<u64, Vec<PublicKey>>,
+    /// The aggregate public keys for each (epoch id, packed bitmap) pair
+    bitmap_aggregates: LruCache<(u64, Vec<u8>), PublicKey>,
 }
 
 impl Default for PublicKeyCache {
@@ -32,6 +80,8 @@ impl PublicKeyCache {
             keys: HashSet::new(),
             combined: PublicKey(G2Projective::zero()),
             de: LruCache::new(512),
+            validator_sets: LruCache::new(4),
+            bitmap_aggregates: LruCache::new(256),
         }
     }
 
@@ -40,42 +90,217 @@ impl PublicKeyCache {
         self.keys = HashSet::new();
         self.combined = PublicKey(G2Projective::zero());
         self.de.clear();
+        self.validator_sets.clear();
+        self.bitmap_aggregates.clear();
+    }
+
+    /// Returns the number of entries and the capacity of each of the caches
+    pub fn stats(&self) -> PublicKeyCacheStats {
+        PublicKeyCacheStats {
+            deserialized: (&self.de).into(),
+            validator_sets: (&self.validator_sets).into(),
+            bitmap_aggregates: (&self.bitmap_aggregates).into(),
+            aggregated_keys: self.keys.len(),
+        }
+    }
+
+    /// Sets the validator set of the epoch with the provided id, which is used by
+    /// `aggregate_for_bitmap`. Any aggregates cached for a previous set of the same epoch
+    /// are discarded.
+    pub fn set_validators(&mut self, validators_epoch_id: u64, validators: Vec<PublicKey>) {
+        let stale = self
+            .bitmap_aggregates
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|(epoch_id, _)| *epoch_id == validators_epoch_id)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in stale {
+            self.bitmap_aggregates.pop(&key);
+        }
+        self.validator_sets.put(validators_epoch_id, validators);
+    }
+
+    /// Returns the aggregate public key of the validators of the provided epoch which are
+    /// set in the bitmap. Consensus verifies signatures from the same few bitmaps many times
+    /// per epoch, so the aggregate of each distinct bitmap is cached.
+    ///
+    /// The epoch's validators must have been provided with `set_validators`.
+    pub fn aggregate_for_bitmap(
+        &mut self,
+        validators_epoch_id: u64,
+        bitmap: &[bool],
+    ) -> BlsResult<PublicKey> {
+        let key = (validators_epoch_id, pack_bitmap(bitmap));
+        if let Some(aggregate) = self.bitmap_aggregates.get(&key) {
+            return Ok(aggregate.clone());
+        }
+
+        let validators = self
+            .validator_sets
+            .get(&validators_epoch_id)
+            .ok_or(BLSError::UnknownValidatorSet(validators_epoch_id))?;
+        if validators.len() != bitmap.len() {
+            return Err(BLSError::BitmapLengthMismatch(
+                bitmap.len(),
+                validators.len(),
+            ));
+        }
+
+        let aggregate = PublicKey::aggregate(
+            validators
+                .iter()
+                .zip(bitmap)
+                .filter(|(_, signed)| **signed)
+                .map(|(validator, _)| validator),
+        );
+        self.bitmap_aggregates.put(key, aggregate.clone());
+        Ok(aggregate)
+    }
+
+    /// Starts an incremental aggregation of the public keys of the epoch's validators which
+    /// are set in the bitmap, from their cached aggregate. Validators can then be added and
+    /// removed as their signatures arrive or are withdrawn, without summing the committee
+    /// again.
+    ///
+    /// The epoch's validators must have been provided with `set_validators`.
+    pub fn aggregator_for_bitmap(
+        &mut self,
+        validators_epoch_id: u64,
+        bitmap: &[bool],
+    ) -> BlsResult<PublicKeyAggregator> {
+        let aggregate = self.aggregate_for_bitmap(validators_epoch_id, bitmap)?;
+        // the aggregate may be cached while the validator set was evicted
+        let validators = self
+            .validator_sets
+            .get(&validators_epoch_id)
+            .ok_or(BLSError::UnknownValidatorSet(validators_epoch_id))?;
+        let contributions = validators
+            .iter()
+            .zip(bitmap)
+            .enumerate()
+            .filter(|(_, (_, signed))| **signed)
+            .map(|(index, (validator, _))| (index, validator.clone()))
+            .collect();
+        Ok(PublicKeyAggregator::with_sum(&aggregate, contributions))
+    }
+
+    /// Caches the aggregate of the aggregator's contributions as the aggregate of their
+    /// bitmap in the epoch, so that later calls of `aggregate_for_bitmap` for the same
+    /// signers reuse it, and returns it. Each contribution must be the key of its validator
+    /// in the epoch's validator set.
+    pub fn store_aggregate(
+        &mut self,
+        validators_epoch_id: u64,
+        aggregator: &PublicKeyAggregator,
+    ) -> BlsResult<PublicKey> {
+        let validators = self
+            .validator_sets
+            .get(&validators_epoch_id)
+            .ok_or(BLSError::UnknownValidatorSet(validators_epoch_id))?;
+        let mut bitmap = vec![false; validators.len()];
+        for (index, key) in aggregator.contributions() {
+            if validators.get(index) != Some(key) {
+                return Err(BLSError::ContributionMismatch(index));
+            }
+            bitmap[index] = true;
+        }
+
+        let aggregate = aggregator.finalize();
+        self.bitmap_aggregates.put(
+            (validators_epoch_id, pack_bitmap(&bitmap)),
+            aggregate.clone(),
+        );
+        Ok(aggregate)
     }
 
     /// Returns the PublicKey corresponding to the serialized data from the cache, or deserializes
     /// the element, saves it to the cache for later use and returns it
-    pub fn deserialize(&mut self, data: Vec<u8>) -> Result<PublicKey, SerializationError> {
-        let cached_result = self.de.get(&data);
+    pub fn deserialize(&mut self, data: &[u8]) -> Result<PublicKey, SerializationError> {
+        let digest = key_digest(data);
+        let cached_result = self.de.get(&digest);
         match cached_result {
             // cache hit
             Some(cached_result) => Ok(cached_result.clone()),
             // cache miss
             None => {
                 let generated_result = PublicKey::deserialize(&mut &data[..])?;
-                self.de.put(data, generated_result.clone());
+                self.de.put(digest, generated_result.clone());
                 Ok(generated_result)
             }
         }
     }
 
+    /// Like `deserialize`, checking the public key at the provided validation level on a
+    /// cache miss. Only keys checked at the `Subgroup` level are cached, so that a key which
+    /// skipped some checks is never returned to a caller which requested them.
+    pub fn deserialize_with_validation(
+        &mut self,
+        data: &[u8],
+        level: ValidationLevel,
+    ) -> BlsResult<PublicKey> {
+        let digest = key_digest(data);
+        if let Some(cached_result) = self.de.get(&digest) {
+            return Ok(cached_result.clone());
+        }
+        let generated_result = PublicKey::from_bytes_with_validation(data, level)?;
+        if level == ValidationLevel::Subgroup {
+            self.de.put(digest, generated_result.clone());
+        }
+        Ok(generated_result)
+    }
+
     /// The set of public keys changes slowly, so for speed this method computes the
-    /// difference from the last call and does an incremental update of the combined key
+    /// difference from the last call and does an incremental update of the combined key.
+    ///
+    /// Keys are added and removed in their canonical order (sorted by their compressed
+    /// serialization) and the combined key is normalized, so that the same set of keys
+    /// always produces the same aggregate representation, regardless of input order or
+    /// of the keys aggregated in previous calls.
     pub fn aggregate(&mut self, public_keys: Vec<PublicKey>) -> PublicKey {
+        self.update(public_keys, true)
+    }
+
+    /// Same as `aggregate`, but applies the key differences in whichever order they are
+    /// found and does not normalize the combined key.
+    pub fn aggregate_ordered(&mut self, public_keys: Vec<PublicKey>) -> PublicKey {
+        self.update(public_keys, false)
+    }
+
+    fn update(&mut self, public_keys: Vec<PublicKey>, canonical: bool) -> PublicKey {
         let mut keys: HashSet<WrappedPublicKey> = HashSet::with_capacity(public_keys.len());
         for key in public_keys {
             keys.insert(WrappedPublicKey(key));
         }
 
+        let mut removed = self
+            .keys
+            .difference(&keys)
+            .map(|k| &k.0)
+            .collect::<Vec<_>>();
+        let mut added = keys
+            .difference(&self.keys)
+            .map(|k| &k.0)
+            .collect::<Vec<_>>();
+        if canonical {
+            sort_canonically::<_, PublicKey>(&mut removed);
+            sort_canonically::<_, PublicKey>(&mut added);
+        }
+
         let mut combined = self.combined.0;
 
         // Subtract any keys which are no longer present
-        for key in self.keys.difference(&keys) {
-            combined -= key.0.as_ref();
+        for key in removed {
+            combined -= key.as_ref();
         }
 
         // Add the new keys
-        for key in keys.difference(&self.keys) {
-            combined += key.0.as_ref();
+        for key in added {
+            combined += key.as_ref();
+        }
+
+        if canonical {
+            combined = combined.into_affine().into_projective();
         }
 
         self.keys = keys;
@@ -85,6 +310,30 @@ impl PublicKeyCache {
     }
 }
 
+/// Returns the digest under which the serialized public key is cached
+pub fn key_digest(serialized: &[u8]) -> KeyDigest {
+    let hash = Params::new()
+        .hash_length(KEY_DIGEST_LENGTH)
+        .personal(b"ULpkcach")
+        .hash(serialized);
+    let mut digest = [0u8; KEY_DIGEST_LENGTH];
+    digest.copy_from_slice(hash.as_bytes());
+    digest
+}
+
+// Packs the bitmap to bytes, prefixed with its length so that bitmaps of different lengths
+// do not collide
+pub(crate) fn pack_bitmap(bitmap: &[bool]) -> Vec<u8> {
+    let mut packed = (bitmap.len() as u64).to_le_bytes().to_vec();
+    packed.extend(bitmap.chunks(8).map(|chunk| {
+        chunk
+            .iter()
+            .enumerate()
+            .fold(0u8, |byte, (i, bit)| byte | ((*bit as u8) << i))
+    }));
+    packed
+}
+
 // Helper type with faster equality semantics when used with HashSet
 #[derive(Eq, Clone, Debug)]
 struct WrappedPublicKey(PublicKey);
@@ -133,7 +382,7 @@ mod tests {
 
         let de = serialized
             .iter()
-            .map(|ser| cache.deserialize(ser.clone()).unwrap())
+            .map(|ser| cache.deserialize(ser).unwrap())
             .collect::<Vec<_>>();
         assert_eq!(de, pubkeys);
     }
@@ -149,9 +398,60 @@ mod tests {
 
         assert!(cache.de.is_empty());
 
-        cache.deserialize(serialized.clone()).unwrap();
+        cache.deserialize(&serialized).unwrap();
+
+        assert_eq!(cache.de.get(&key_digest(&serialized)).unwrap(), &pubkey);
+    }
+
+    #[test]
+    fn caches_only_fully_validated_pubkeys() {
+        let mut cache = PublicKeyCache::new();
+
+        let pubkey = rand_pubkey();
+        let mut serialized = vec![];
+        pubkey.serialize(&mut serialized).unwrap();
+
+        let de = cache
+            .deserialize_with_validation(&serialized, ValidationLevel::OnCurve)
+            .unwrap();
+        assert_eq!(de, pubkey);
+        assert!(cache.de.is_empty());
 
-        assert_eq!(cache.de.get(&serialized).unwrap(), &pubkey);
+        cache
+            .deserialize_with_validation(&serialized, ValidationLevel::Subgroup)
+            .unwrap();
+        assert_eq!(cache.de.get(&key_digest(&serialized)).unwrap(), &pubkey);
+    }
+
+    #[test]
+    fn reports_stats() {
+        let mut cache = PublicKeyCache::new();
+        let empty = cache.stats();
+        assert_eq!(empty.deserialized.len, 0);
+        assert_eq!(empty.deserialized.capacity, 512);
+        assert_eq!(empty.aggregated_keys, 0);
+
+        let pubkeys = (0..10).map(|_| rand_pubkey()).collect::<Vec<_>>();
+        for pubkey in &pubkeys {
+            let mut serialized = vec![];
+            pubkey.serialize(&mut serialized).unwrap();
+            // the second call is a cache hit and does not add an entry
+            cache.deserialize(&serialized).unwrap();
+            cache.deserialize(&serialized).unwrap();
+        }
+        cache.aggregate(pubkeys.clone());
+        cache.set_validators(1, pubkeys);
+        cache.aggregate_for_bitmap(1, &[true; 10]).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!(stats.deserialized.len, 10);
+        assert_eq!(stats.validator_sets.len, 1);
+        assert_eq!(stats.validator_sets.capacity, 4);
+        assert_eq!(stats.bitmap_aggregates.len, 1);
+        assert_eq!(stats.aggregated_keys, 10);
+
+        cache.clear_cache();
+        assert_eq!(cache.stats(), empty);
     }
 
     #[test]
@@ -163,4 +463,99 @@ mod tests {
         let apubkey = cache.aggregate(pubkeys.clone());
         assert_eq!(apubkey, PublicKey::aggregate(&pubkeys));
     }
+
+    #[test]
+    fn aggregation_is_canonical() {
+        let pubkeys = (0..10).map(|_| rand_pubkey()).collect::<Vec<_>>();
+        let mut reversed = pubkeys.clone();
+        reversed.reverse();
+
+        let mut cache = PublicKeyCache::new();
+        let apubkey = cache.aggregate(pubkeys.clone());
+
+        // a cache with a different history and input order gives the same bytes
+        let mut other = PublicKeyCache::new();
+        other.aggregate(pubkeys[..3].to_vec());
+        let apubkey_other = other.aggregate(reversed);
+
+        let a = apubkey.as_ref();
+        let b = apubkey_other.as_ref();
+        assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+
+        let mut ordered = PublicKeyCache::new();
+        assert_eq!(ordered.aggregate_ordered(pubkeys), apubkey);
+    }
+
+    #[test]
+    fn aggregation_for_bitmap() {
+        let mut cache = PublicKeyCache::new();
+        let pubkeys = (0..10).map(|_| rand_pubkey()).collect::<Vec<_>>();
+        let bitmap = (0..10).map(|i| i % 3 != 0).collect::<Vec<_>>();
+
+        cache.aggregate_for_bitmap(1, &bitmap).unwrap_err();
+        cache.set_validators(1, pubkeys.clone());
+
+        let expected = PublicKey::aggregate(
+            pubkeys
+                .iter()
+                .zip(&bitmap)
+                .filter(|(_, b)| **b)
+                .map(|(p, _)| p),
+        );
+        assert_eq!(cache.aggregate_for_bitmap(1, &bitmap).unwrap(), expected);
+        // served from the cache
+        assert_eq!(cache.bitmap_aggregates.len(), 1);
+        assert_eq!(cache.aggregate_for_bitmap(1, &bitmap).unwrap(), expected);
+        assert_eq!(cache.bitmap_aggregates.len(), 1);
+
+        cache.aggregate_for_bitmap(1, &bitmap[1..]).unwrap_err();
+
+        // replacing the validator set invalidates the cached aggregates
+        let other_pubkeys = (0..10).map(|_| rand_pubkey()).collect::<Vec<_>>();
+        cache.set_validators(1, other_pubkeys.clone());
+        assert_eq!(
+            cache.aggregate_for_bitmap(1, &[true; 10]).unwrap(),
+            PublicKey::aggregate(&other_pubkeys)
+        );
+        assert_eq!(cache.bitmap_aggregates.len(), 1);
+    }
+
+    #[test]
+    fn incremental_aggregation_for_bitmap() {
+        let mut cache = PublicKeyCache::new();
+        let pubkeys = (0..10).map(|_| rand_pubkey()).collect::<Vec<_>>();
+        let mut bitmap = (0..10).map(|i| i % 3 != 0).collect::<Vec<_>>();
+        cache.set_validators(1, pubkeys.clone());
+
+        let mut aggregator = cache.aggregator_for_bitmap(1, &bitmap).unwrap();
+        assert_eq!(aggregator.len(), 6);
+        // validator 0 signs late and validator 1 equivocates
+        aggregator.add(0, pubkeys[0].clone()).unwrap();
+        aggregator.remove(1).unwrap();
+        bitmap[0] = true;
+        bitmap[1] = false;
+
+        let aggregate = cache.store_aggregate(1, &aggregator).unwrap();
+        assert_eq!(cache.bitmap_aggregates.len(), 2);
+        assert_eq!(cache.aggregate_for_bitmap(1, &bitmap).unwrap(), aggregate);
+        assert_eq!(cache.bitmap_aggregates.len(), 2);
+        assert_eq!(
+            aggregate,
+            PublicKey::aggregate(
+                pubkeys
+                    .iter()
+                    .zip(&bitmap)
+                    .filter(|(_, b)| **b)
+                    .map(|(p, _)| p)
+            )
+        );
+
+        aggregator.remove(0).unwrap();
+        aggregator.add(0, rand_pubkey()).unwrap();
+        match cache.store_aggregate(1, &aggregator) {
+            Err(BLSError::ContributionMismatch(0)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        cache.store_aggregate(2, &aggregator).unwrap_err();
+    }
 }
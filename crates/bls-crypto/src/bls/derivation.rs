@@ -0,0 +1,199 @@
+//! Hierarchical derivation of private keys, in the spirit of EIP-2333.
+//!
+//! A master key is derived from a seed, and each key derives children by index, so that the
+//! operational keys of a validator, e.g. one per epoch or per purpose, can all be
+//! regenerated from a single backed-up seed. Every derivation is hardened: a child key is a
+//! hash of its parent's private key, and neither the child's private key nor its public
+//! key can be computed from the parent's public key.
+use super::{secret::expand_key, PrivateKey};
+use crate::{BLSError, Vec};
+
+use algebra::CanonicalSerialize;
+use blake2s_simd::Params;
+use core::{fmt, str::FromStr};
+use zeroize::Zeroize;
+
+/// The `info` of the master key's derivation with `PrivateKey::from_seed`
+const MASTER_INFO: &[u8] = b"master key";
+
+/// Personalization of the hash which extracts a child's key from its parent
+const CHILD_EXTRACT_PERSONALIZATION: &[u8] = b"ULchldex";
+
+/// A path of child indices from a master key, written `m/12381/3600/0/0`.
+///
+/// Since every derivation is hardened, the `'` suffix of BIP32 paths is accepted and has no
+/// effect: `m/0'` and `m/0` are the same path.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// The indices of the path, from the master key's child to the derived key
+    pub fn indices(&self) -> &[u32] {
+        &self.0
+    }
+
+    /// Returns the path to the child at `index` of this path's key
+    pub fn child(&self, index: u32) -> DerivationPath {
+        let mut indices = self.0.clone();
+        indices.push(index);
+        DerivationPath(indices)
+    }
+}
+
+impl From<Vec<u32>> for DerivationPath {
+    fn from(indices: Vec<u32>) -> Self {
+        DerivationPath(indices)
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = BLSError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let mut components = path.split('/');
+        if components.next() != Some("m") {
+            return Err(BLSError::InvalidDerivationPath(
+                "the path must start with m",
+            ));
+        }
+        components
+            .map(|component| {
+                let index = if component.ends_with('\'') {
+                    &component[..component.len() - 1]
+                } else {
+                    component
+                };
+                if index.is_empty() || !index.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return Err(BLSError::InvalidDerivationPath(
+                        "indices must be decimal integers",
+                    ));
+                }
+                index
+                    .parse()
+                    .map_err(|_| BLSError::InvalidDerivationPath("indices must fit in 32 bits"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(DerivationPath)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("m")?;
+        for index in &self.0 {
+            write!(f, "/{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+impl PrivateKey {
+    /// Derives the master key of a derivation tree from a seed of at least
+    /// `MIN_SEED_LENGTH` bytes
+    pub fn master_from_seed(seed: &[u8]) -> Result<PrivateKey, BLSError> {
+        PrivateKey::from_seed(seed, MASTER_INFO)
+    }
+
+    /// Derives the hardened child of this key at `index`.
+    ///
+    /// The parent's private key keys a hash of the index into a pseudorandom key, which is
+    /// expanded into the child's key like the seed of `from_seed`.
+    pub fn derive_child(&self, index: u32) -> PrivateKey {
+        let mut parent = Vec::with_capacity(self.serialized_size());
+        self.serialize(&mut parent)
+            .expect("serializing to a vector cannot fail");
+        let mut prk = Params::new()
+            .hash_length(32)
+            .key(&parent)
+            .personal(CHILD_EXTRACT_PERSONALIZATION)
+            .hash(&index.to_be_bytes())
+            .as_bytes()
+            .to_vec();
+        parent.zeroize();
+        let child = expand_key(&prk, &[]);
+        prk.zeroize();
+        child
+    }
+
+    /// Derives the key at the end of the path, starting from this key
+    pub fn derive_path(&self, path: &DerivationPath) -> PrivateKey {
+        path.indices()
+            .iter()
+            .fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MIN_SEED_LENGTH;
+
+    #[test]
+    fn derives_children_deterministically() {
+        let master = PrivateKey::master_from_seed(&[3u8; MIN_SEED_LENGTH]).unwrap();
+        assert_eq!(
+            master.as_ref(),
+            PrivateKey::master_from_seed(&[3u8; MIN_SEED_LENGTH])
+                .unwrap()
+                .as_ref()
+        );
+        assert_ne!(master.derive_child(0).as_ref(), master.as_ref());
+        assert_ne!(
+            master.derive_child(0).as_ref(),
+            master.derive_child(1).as_ref()
+        );
+        // the master key is not the key derived with an empty `info`
+        assert_ne!(
+            master.as_ref(),
+            PrivateKey::from_seed(&[3u8; MIN_SEED_LENGTH], &[])
+                .unwrap()
+                .as_ref()
+        );
+
+        let path: DerivationPath = "m/12381/3600/0/0".parse().unwrap();
+        assert_eq!(
+            master.derive_path(&path).as_ref(),
+            master
+                .derive_child(12381)
+                .derive_child(3600)
+                .derive_child(0)
+                .derive_child(0)
+                .as_ref()
+        );
+        assert_eq!(
+            master.derive_path(&path.child(7)).as_ref(),
+            master.derive_path(&path).derive_child(7).as_ref()
+        );
+        assert_eq!(
+            master.derive_path(&DerivationPath::default()).as_ref(),
+            master.as_ref()
+        );
+    }
+
+    #[test]
+    fn parses_paths() {
+        let path: DerivationPath = "m/12381'/3600/0'/4294967295".parse().unwrap();
+        assert_eq!(
+            path,
+            DerivationPath::from(vec![12381, 3600, 0, u32::max_value()])
+        );
+        assert_eq!(path.to_string(), "m/12381/3600/0/4294967295");
+        assert!("m".parse::<DerivationPath>().unwrap().indices().is_empty());
+
+        for invalid in &[
+            "",
+            "12381/0",
+            "m/",
+            "m//0",
+            "m/-1",
+            "m/+1",
+            "m/0''",
+            "m/4294967296",
+        ] {
+            match invalid.parse::<DerivationPath>() {
+                Err(BLSError::InvalidDerivationPath(_)) => {}
+                other => panic!("unexpected result {:?} for {}", other, invalid),
+            }
+        }
+    }
+}
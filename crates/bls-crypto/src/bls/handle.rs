@@ -0,0 +1,223 @@
+use crate::{
+    beacon::{PartialEvaluation, RANDOMNESS_LENGTH},
+    observer::message_digest,
+    slashing::{SlashingProtection, Watermark},
+    BLSError, Domain, HashToCurve, PrivateKey, PublicKey, Signature, SIG_DOMAIN,
+};
+
+use algebra::bls12_377::G1Projective;
+use std::fmt;
+
+/// The sub-protocol which a private key is used with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyUsage {
+    /// Consensus signatures in the `SIG_DOMAIN` and proofs of possession of the key
+    Consensus,
+    /// Evaluations of the randomness beacon, which act as a VRF
+    Vrf,
+    /// Blind signatures, i.e. signatures of hashes chosen by the requester
+    BlindSigning,
+    /// Signatures in an application's domain
+    Application(Domain),
+}
+
+impl fmt::Display for KeyUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyUsage::Consensus => write!(f, "consensus signing"),
+            KeyUsage::Vrf => write!(f, "VRF evaluation"),
+            KeyUsage::BlindSigning => write!(f, "blind signing"),
+            KeyUsage::Application(domain) => write!(f, "signing in {:?}", domain),
+        }
+    }
+}
+
+/// A private key which is bound to the first sub-protocol it is used with, and refuses to be
+/// used with any other.
+///
+/// Signing a hash chosen by someone else, as in blind signing, lets them obtain a signature
+/// of any message which hashes to it in any domain, so a validator key which also signs
+/// blindly could be made to sign consensus messages. Binding the key to one usage rules this
+/// out. The usage should be persisted with the key and restored with `with_usage`.
+#[derive(Clone, Debug)]
+pub struct KeyHandle {
+    key: PrivateKey,
+    usage: Option<KeyUsage>,
+}
+
+impl KeyHandle {
+    /// Wraps a key which has not been used yet
+    pub fn new(key: PrivateKey) -> Self {
+        Self { key, usage: None }
+    }
+
+    /// Wraps a key which has already been used with the provided usage
+    pub fn with_usage(key: PrivateKey, usage: KeyUsage) -> Self {
+        Self {
+            key,
+            usage: Some(usage),
+        }
+    }
+
+    /// Returns the usage the key is bound to, if it has been used
+    pub fn usage(&self) -> Option<KeyUsage> {
+        self.usage
+    }
+
+    /// Returns the public key, which does not bind the key to a usage
+    pub fn to_public(&self) -> PublicKey {
+        self.key.to_public()
+    }
+
+    /// Binds the key to the usage if it is unused and returns it. Fails if the key is bound
+    /// to another usage.
+    pub fn claim(&mut self, usage: KeyUsage) -> Result<&PrivateKey, BLSError> {
+        match self.usage {
+            Some(bound) if bound != usage => {
+                return Err(BLSError::KeyUsageConflict {
+                    bound,
+                    requested: usage,
+                })
+            }
+            _ => self.usage = Some(usage),
+        }
+        Ok(&self.key)
+    }
+
+    /// Signs the message in the `SIG_DOMAIN` with `KeyUsage::Consensus`
+    pub fn sign<H: HashToCurve<Output = G1Projective>>(
+        &mut self,
+        message: &[u8],
+        extra_data: &[u8],
+        hash_to_g1: &H,
+    ) -> Result<Signature, BLSError> {
+        self.claim(KeyUsage::Consensus)?
+            .sign_message(SIG_DOMAIN, message, extra_data, hash_to_g1)
+    }
+
+    /// Signs the consensus message for the height and round like `sign`, after recording it
+    /// in the slashing protection store. Fails with `BLSError::SlashableSignature` without
+    /// signing if it conflicts with an earlier signature of the key. The watermark is kept
+    /// even if hashing the message then fails, as the store cannot tell the difference.
+    pub fn sign_consensus<H, S>(
+        &mut self,
+        height: u64,
+        round: u32,
+        message: &[u8],
+        extra_data: &[u8],
+        hash_to_g1: &H,
+        protection: &mut S,
+    ) -> Result<Signature, BLSError>
+    where
+        H: HashToCurve<Output = G1Projective>,
+        S: SlashingProtection + ?Sized,
+    {
+        let key = self.claim(KeyUsage::Consensus)?;
+        let watermark = Watermark {
+            height,
+            round,
+            message_digest: message_digest(message, extra_data),
+        };
+        protection.check_and_record(&key.to_public(), watermark)?;
+        key.sign_message(SIG_DOMAIN, message, extra_data, hash_to_g1)
+    }
+
+    /// Signs a proof of possession with `KeyUsage::Consensus`
+    pub fn sign_pop<H: HashToCurve<Output = G1Projective>>(
+        &mut self,
+        message: &[u8],
+        hash_to_g1: &H,
+    ) -> Result<Signature, BLSError> {
+        self.claim(KeyUsage::Consensus)?
+            .sign_pop(message, hash_to_g1)
+    }
+
+    /// Signs the message in the application's domain with `KeyUsage::Application`
+    pub fn sign_in_domain<H: HashToCurve<Output = G1Projective>>(
+        &mut self,
+        domain: Domain,
+        message: &[u8],
+        extra_data: &[u8],
+        hash_to_g1: &H,
+    ) -> Result<Signature, BLSError> {
+        self.claim(KeyUsage::Application(domain))?.sign_message(
+            domain.as_bytes(),
+            message,
+            extra_data,
+            hash_to_g1,
+        )
+    }
+
+    /// Evaluates a round of the randomness beacon with `KeyUsage::Vrf`
+    pub fn evaluate_beacon<H: HashToCurve<Output = G1Projective>>(
+        &mut self,
+        index: u64,
+        round: u64,
+        previous: &[u8; RANDOMNESS_LENGTH],
+        hash_to_g1: &H,
+    ) -> Result<PartialEvaluation, BLSError> {
+        let key = self.claim(KeyUsage::Vrf)?;
+        PartialEvaluation::new(key, index, round, previous, hash_to_g1)
+    }
+
+    /// Signs a blinded hash with `KeyUsage::BlindSigning`
+    pub fn sign_blinded(&mut self, blinded: &G1Projective) -> Result<Signature, BLSError> {
+        Ok(self.claim(KeyUsage::BlindSigning)?.sign_hashed(blinded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1;
+    use algebra::{ProjectiveCurve, UniformRand};
+
+    #[test]
+    fn binds_to_first_usage() {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let key = PrivateKey::generate(rng);
+        let mut handle = KeyHandle::new(key.clone());
+        assert_eq!(handle.usage(), None);
+
+        let sig = handle.sign(b"hello", &[], hasher).unwrap();
+        assert_eq!(sig, key.sign(b"hello", &[], hasher).unwrap());
+        handle.sign_pop(b"address", hasher).unwrap();
+        assert_eq!(handle.usage(), Some(KeyUsage::Consensus));
+
+        let blinded = G1Projective::rand(rng);
+        match handle.sign_blinded(&blinded) {
+            Err(BLSError::KeyUsageConflict { bound, requested }) => {
+                assert_eq!(bound, KeyUsage::Consensus);
+                assert_eq!(requested, KeyUsage::BlindSigning);
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+        handle
+            .evaluate_beacon(1, 1, &[0; RANDOMNESS_LENGTH], hasher)
+            .unwrap_err();
+        assert_eq!(handle.to_public(), key.to_public());
+    }
+
+    #[test]
+    fn separates_application_domains() {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let first = Domain::new(b"myapp_v1").unwrap();
+        let second = Domain::new(b"myapp_v2").unwrap();
+
+        let mut handle =
+            KeyHandle::with_usage(PrivateKey::generate(rng), KeyUsage::Application(first));
+        handle.sign_in_domain(first, b"hello", &[], hasher).unwrap();
+        handle
+            .sign_in_domain(second, b"hello", &[], hasher)
+            .unwrap_err();
+        handle.sign(b"hello", &[], hasher).unwrap_err();
+
+        let mut blind = KeyHandle::new(PrivateKey::generate(rng));
+        let blinded = G1Projective::prime_subgroup_generator();
+        blind.sign_blinded(&blinded).unwrap();
+        blind.claim(KeyUsage::BlindSigning).unwrap();
+        blind.claim(KeyUsage::Vrf).unwrap_err();
+    }
+}
@@ -1,13 +1,59 @@
 //! Implements BLS signatures as specified in https://crypto.stanford.edu/~dabo/pubs/papers/BLSmultisig.html.
 
 mod secret;
-pub use secret::PrivateKey;
+pub(crate) use secret::zeroize_scalar;
+pub use secret::{PrivateKey, INTEGRITY_TAG_LENGTH, MIN_SEED_LENGTH};
 
 mod public;
-pub use public::PublicKey;
+pub use public::{PreparedPublicKey, PublicKey};
 
 mod signature;
-pub use signature::Signature;
+#[cfg(feature = "std")]
+pub use signature::NEG_G2_GENERATOR_PREPARED;
+pub(crate) use signature::{is_one, prepare_pair, signature_miller_loop, PAIRING_CHUNK_SIZE};
+pub use signature::{BatchVerifier, Signature};
 
+#[cfg(feature = "std")]
+mod handle;
+#[cfg(feature = "std")]
+pub use handle::{KeyHandle, KeyUsage};
+
+mod aggregator;
+pub use aggregator::{PublicKeyAggregator, SignatureAggregator};
+
+#[cfg(feature = "std")]
 mod cache;
-pub use cache::PublicKeyCache;
+#[cfg(feature = "std")]
+pub(crate) use cache::pack_bitmap;
+#[cfg(feature = "std")]
+pub use cache::{
+    key_digest, CacheOccupancy, KeyDigest, PublicKeyCache, PublicKeyCacheStats, KEY_DIGEST_LENGTH,
+};
+
+pub mod subgroup;
+
+mod derivation;
+pub use derivation::DerivationPath;
+
+mod validation;
+pub(crate) use validation::collect_batch;
+pub use validation::ValidationLevel;
+
+pub mod scheme;
+pub use scheme::{Basic, BlsScheme, Celo, MessageAugmentation, ProofOfPossession};
+
+use crate::Vec;
+use algebra::CanonicalSerialize;
+use core::borrow::Borrow;
+
+/// Sorts the elements by their compressed serialization. This gives a canonical order
+/// which does not depend on the order in which the elements were provided.
+pub(crate) fn sort_canonically<T: Borrow<U>, U: CanonicalSerialize>(elements: &mut [T]) {
+    elements.sort_by_cached_key(|el| {
+        let mut bytes = Vec::new();
+        el.borrow()
+            .serialize(&mut bytes)
+            .expect("serializing to a vector cannot fail");
+        bytes
+    });
+}
@@ -1,17 +1,20 @@
-use crate::{BLSError, BlsResult, HashToCurve, PrivateKey, Signature, POP_DOMAIN, SIG_DOMAIN};
+use crate::{
+    ct::secret_scalar_mul, g2_from_compressed_standard, g2_to_compressed_standard, BLSError,
+    BlsResult, HashToCurve, PrivateKey, Signature, Vec, POP_DOMAIN, SIG_DOMAIN,
+};
 
+use super::{
+    collect_batch, signature::neg_g2_generator_prepared, subgroup::is_valid_g2, ValidationLevel,
+};
 use algebra::{
     bls12_377::{Bls12_377, Fq12, G1Projective, G2Affine, G2Projective},
-    AffineCurve, CanonicalDeserialize, CanonicalSerialize, One, PairingEngine, ProjectiveCurve,
-    SerializationError,
-};
-
-use std::{
-    borrow::Borrow,
     io::{Read, Write},
-    ops::Neg,
+    AffineCurve, CanonicalDeserialize, CanonicalSerialize, ConstantSerializedSize, One,
+    PairingEngine, ProjectiveCurve, SerializationError, Zero,
 };
 
+use core::{borrow::Borrow, fmt};
+
 /// A BLS public key on G2
 #[derive(Clone, Eq, Debug, PartialEq, Hash)]
 pub struct PublicKey(pub(super) G2Projective);
@@ -24,7 +27,10 @@ impl From<G2Projective> for PublicKey {
 
 impl From<&PrivateKey> for PublicKey {
     fn from(pk: &PrivateKey) -> PublicKey {
-        PublicKey::from(G2Projective::prime_subgroup_generator().mul(*pk.as_ref()))
+        PublicKey::from(secret_scalar_mul(
+            &G2Projective::prime_subgroup_generator(),
+            pk.as_ref(),
+        ))
     }
 }
 
@@ -34,6 +40,45 @@ impl AsRef<G2Projective> for PublicKey {
     }
 }
 
+/// A public key whose G2 point is prepared for the Miller loop, which saves preparing it
+/// again in every batch verification the key takes part in
+#[derive(Clone)]
+pub struct PreparedPublicKey {
+    public_key: PublicKey,
+    pub(super) prepared: <Bls12_377 as PairingEngine>::G2Prepared,
+}
+
+impl From<&PublicKey> for PreparedPublicKey {
+    fn from(public_key: &PublicKey) -> PreparedPublicKey {
+        PreparedPublicKey {
+            public_key: public_key.clone(),
+            prepared: public_key.0.into_affine().into(),
+        }
+    }
+}
+
+impl AsRef<PublicKey> for PreparedPublicKey {
+    fn as_ref(&self) -> &PublicKey {
+        &self.public_key
+    }
+}
+
+impl fmt::Debug for PreparedPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PreparedPublicKey")
+            .field(&self.public_key)
+            .finish()
+    }
+}
+
+impl PartialEq for PreparedPublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.public_key == other.public_key
+    }
+}
+
+impl Eq for PreparedPublicKey {}
+
 impl PublicKey {
     /// Sums the provided public keys to produce the aggregate public key.
     pub fn aggregate<P: Borrow<PublicKey>>(public_keys: impl IntoIterator<Item = P>) -> PublicKey {
@@ -44,6 +89,104 @@ impl PublicKey {
             .into()
     }
 
+    /// Removes `other` from the aggregate public key, see `Signature::subtract`.
+    ///
+    /// As with aggregation, the result is only meaningful if the keys have proofs of
+    /// possession, and nothing checks that `other` was part of the aggregate.
+    pub fn subtract(&self, other: &PublicKey) -> PublicKey {
+        (self.0 - other.0).into()
+    }
+
+    /// Deserializes a compressed or uncompressed public key received from an untrusted
+    /// source, checking that it is on the G2 twist and in the prime order subgroup. The
+    /// subgroup check uses the fast endomorphism-based check from the `subgroup` module
+    /// instead of a full multiplication by the group order. A failed check is reported with
+    /// `BLSError::InvalidPoint`, and the point at infinity, which would verify any signature
+    /// by the identity, with `BLSError::IdentityPublicKey`.
+    pub fn from_bytes(bytes: &[u8]) -> BlsResult<PublicKey> {
+        Self::from_bytes_with_validation(bytes, ValidationLevel::Subgroup)
+    }
+
+    /// Like `from_bytes`, checking the public key at the provided validation level. The point
+    /// at infinity is only accepted with `ValidationLevel::None`.
+    pub fn from_bytes_with_validation(
+        bytes: &[u8],
+        level: ValidationLevel,
+    ) -> BlsResult<PublicKey> {
+        let point = level
+            .decode_g2(bytes)
+            .map_err(|reason| BLSError::InvalidPoint { level, reason })?;
+        if level != ValidationLevel::None {
+            check_not_identity(&point)?;
+        }
+        Ok(PublicKey::from(point.into_projective()))
+    }
+
+    /// Deserializes a batch of compressed or uncompressed public keys, checking each one like
+    /// `from_bytes`. The decompressions share their field inversions, and the entries are
+    /// split in chunks which are decoded in parallel with the `parallel` feature. Fails with
+    /// the index of every invalid entry in `BLSError::InvalidBatchEntries`.
+    pub fn batch_from_bytes<B: AsRef<[u8]> + Sync>(entries: &[B]) -> BlsResult<Vec<PublicKey>> {
+        collect_batch(
+            ValidationLevel::Subgroup
+                .batch_decode_g2(entries)
+                .into_iter()
+                .map(|point| match point? {
+                    point if point.is_zero() => Err("the identity"),
+                    point => Ok(PublicKey::from(point.into_projective())),
+                }),
+        )
+    }
+
+    /// Deserializes a compressed or uncompressed public key without any check. Only use this
+    /// for keys from a trusted source, e.g. keys which were checked before being stored.
+    pub fn from_bytes_unchecked(bytes: &[u8]) -> BlsResult<PublicKey> {
+        Self::from_bytes_with_validation(bytes, ValidationLevel::None)
+    }
+
+    /// Decodes a public key in the format of celo-blockchain's Go node, which stores keys as
+    /// the 96 byte compressed points produced by `bls-snark-sys`. The key is validated like
+    /// with `from_bytes`, and any other length is reported with `BLSError::InvalidPoint`.
+    pub fn from_celo_bytes(bytes: &[u8]) -> BlsResult<PublicKey> {
+        if bytes.len() != G2Affine::SERIALIZED_SIZE {
+            return Err(BLSError::InvalidPoint {
+                level: ValidationLevel::Subgroup,
+                reason: "invalid length",
+            });
+        }
+        Self::from_bytes(bytes)
+    }
+
+    /// Deserializes a compressed public key received from an untrusted source, rejecting the
+    /// point at infinity and points which are not on the G2 twist or not in the prime order
+    /// subgroup
+    #[deprecated(note = "use `from_bytes`, or `from_celo_bytes` to only accept compressed keys")]
+    pub fn from_bytes_checked(bytes: &[u8]) -> BlsResult<PublicKey> {
+        Self::from_celo_bytes(bytes)
+    }
+
+    /// Encodes the public key in the format of celo-blockchain's Go node
+    pub fn to_celo_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(G2Affine::SERIALIZED_SIZE);
+        self.serialize(&mut bytes)
+            .expect("serializing to a vector cannot fail");
+        bytes
+    }
+
+    /// Encodes the public key in the standard compressed format of Zcash and EIP-2537 tooling,
+    /// see `SerializationProfile::StandardCompressed`
+    pub fn to_compressed_standard(&self) -> Vec<u8> {
+        g2_to_compressed_standard(&self.as_ref().into_affine())
+    }
+
+    /// Decodes a public key in the standard compressed format, checking that it is in the
+    /// prime order subgroup
+    pub fn from_compressed_standard(bytes: &[u8]) -> BlsResult<PublicKey> {
+        Ok(PublicKey::from(
+            g2_from_compressed_standard(bytes)?.into_projective(),
+        ))
+    }
+
     /// Verifies the provided signature against the message-extra_data pair using the
     /// `hash_to_g1` hasher.
     ///
@@ -71,7 +214,43 @@ impl PublicKey {
         self.verify_sig(POP_DOMAIN, &message, &[], signature, hash_to_g1)
     }
 
-    fn verify_sig<H: HashToCurve<Output = G1Projective>>(
+    /// Verifies a proof of possession produced by `PrivateKey::sign_key_pop`
+    pub fn verify_key_pop<H: HashToCurve<Output = G1Projective>>(
+        &self,
+        pop: &Signature,
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        self.verify_pop(&self.to_celo_bytes(), pop, hash_to_g1)
+    }
+
+    /// Aggregates public keys received from untrusted parties. `aggregate` sums the keys as
+    /// they are, so a party which picks its key after seeing the others can cancel them out
+    /// of the aggregate (a rogue key attack). Here each key must come with its proof of
+    /// possession from `PrivateKey::sign_key_pop`, and must be a non-identity element of the G2
+    /// subgroup. The index of the first rejected key is returned in
+    /// `BLSError::InvalidProofOfPossession`. An empty
+    /// set of keys fails with `BLSError::EmptyAggregation`, as its aggregate is the identity
+    /// which verifies any signature of the identity.
+    pub fn aggregate_with_pop_check<H: HashToCurve<Output = G1Projective>>(
+        keys: &[(PublicKey, Signature)],
+        hash_to_g1: &H,
+    ) -> BlsResult<PublicKey> {
+        if keys.is_empty() {
+            return Err(BLSError::EmptyAggregation);
+        }
+        for (i, (key, pop)) in keys.iter().enumerate() {
+            let point = key.0.into_affine();
+            if check_not_identity(&point).is_err()
+                || !is_valid_g2(&point)
+                || key.verify_key_pop(pop, hash_to_g1).is_err()
+            {
+                return Err(BLSError::InvalidProofOfPossession(i));
+            }
+        }
+        Ok(Self::aggregate(keys.iter().map(|(key, _)| key)))
+    }
+
+    pub(crate) fn verify_sig<H: HashToCurve<Output = G1Projective>>(
         &self,
         domain: &[u8],
         message: &[u8],
@@ -82,7 +261,7 @@ impl PublicKey {
         let pairing = Bls12_377::product_of_pairings(&vec![
             (
                 signature.as_ref().into_affine().into(),
-                G2Affine::prime_subgroup_generator().neg().into(),
+                neg_g2_generator_prepared(),
             ),
             (
                 hash_to_g1
@@ -114,16 +293,300 @@ impl CanonicalSerialize for PublicKey {
     }
 }
 
+/// Deserialized keys are checked to be on the G2 twist and in the prime order subgroup, see
+/// `PublicKey::from_bytes_unchecked` for trusted sources
+/// Checks public keys like `PublicKey::from_bytes`, so that both decoders accept the same keys
 impl CanonicalDeserialize for PublicKey {
     fn deserialize<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
-        Ok(PublicKey::from(
-            G2Affine::deserialize(reader)?.into_projective(),
-        ))
+        let point = ValidationLevel::Subgroup.read_g2(reader, G2Affine::SERIALIZED_SIZE)?;
+        check_not_identity(&point).map_err(|_| SerializationError::InvalidData)?;
+        Ok(PublicKey::from(point.into_projective()))
     }
 
     fn deserialize_uncompressed<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
-        Ok(PublicKey::from(
-            G2Affine::deserialize_uncompressed(reader)?.into_projective(),
-        ))
+        let point = ValidationLevel::Subgroup.read_g2(reader, G2Affine::UNCOMPRESSED_SIZE)?;
+        check_not_identity(&point).map_err(|_| SerializationError::InvalidData)?;
+        Ok(PublicKey::from(point.into_projective()))
+    }
+}
+
+// The identity is on the curve and in the subgroup, but any signature of the identity verifies
+// against it
+fn check_not_identity(point: &G2Affine) -> BlsResult<()> {
+    if point.is_zero() {
+        return Err(BLSError::IdentityPublicKey);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::Fq2, UniformRand, Zero};
+
+    #[test]
+    fn from_bytes_rejects_invalid_keys() {
+        let rng = &mut rand::thread_rng();
+        let pubkey = PrivateKey::generate(rng).to_public();
+        let mut bytes = vec![];
+        pubkey.serialize(&mut bytes).unwrap();
+        assert_eq!(PublicKey::from_bytes(&bytes).unwrap(), pubkey);
+
+        // wrong length
+        PublicKey::from_bytes(&bytes[1..]).unwrap_err();
+
+        // a point on the twist, but outside the subgroup
+        let point = loop {
+            if let Some(point) = G2Affine::get_point_from_x(Fq2::rand(rng), false) {
+                break point;
+            }
+        };
+        let mut bytes = vec![];
+        point.serialize(&mut bytes).unwrap();
+        match PublicKey::from_bytes(&bytes) {
+            Err(BLSError::InvalidPoint { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        // which is only accepted when the subgroup check is skipped
+        PublicKey::from_bytes_with_validation(&bytes, ValidationLevel::Subgroup).unwrap_err();
+        PublicKey::from_bytes_with_validation(&bytes, ValidationLevel::OnCurve).unwrap();
+    }
+
+    #[test]
+    fn rejects_the_identity() {
+        for bytes in &[
+            compressed(&G2Affine::zero()),
+            uncompressed(&G2Affine::zero()),
+        ] {
+            match PublicKey::from_bytes(bytes) {
+                Err(BLSError::IdentityPublicKey) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+            match PublicKey::from_bytes_with_validation(bytes, ValidationLevel::OnCurve) {
+                Err(BLSError::IdentityPublicKey) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+            assert!(PublicKey::from_bytes_unchecked(bytes)
+                .unwrap()
+                .as_ref()
+                .is_zero());
+        }
+        PublicKey::from_celo_bytes(&compressed(&G2Affine::zero())).unwrap_err();
+        PublicKey::deserialize(&mut &compressed(&G2Affine::zero())[..]).unwrap_err();
+        PublicKey::deserialize_uncompressed(&mut &uncompressed(&G2Affine::zero())[..]).unwrap_err();
+
+        let rng = &mut rand::thread_rng();
+        let entries = vec![
+            compressed(&PrivateKey::generate(rng).to_public()),
+            compressed(&G2Affine::zero()),
+        ];
+        match PublicKey::batch_from_bytes(&entries) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => {
+                assert_eq!(invalid, vec![(1, "the identity")])
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deserialization_checks_the_subgroup() {
+        let rng = &mut rand::thread_rng();
+        let pubkey = PrivateKey::generate(rng).to_public();
+        for bytes in &[compressed(&pubkey), uncompressed(&pubkey)] {
+            assert_eq!(PublicKey::deserialize(&mut &bytes[..]).unwrap(), pubkey);
+            assert_eq!(PublicKey::from_bytes(bytes).unwrap(), pubkey);
+        }
+        assert_eq!(
+            PublicKey::deserialize_uncompressed(&mut &uncompressed(&pubkey)[..]).unwrap(),
+            pubkey
+        );
+
+        let point = loop {
+            if let Some(point) = G2Affine::get_point_from_x(Fq2::rand(rng), false) {
+                if !is_valid_g2(&point) {
+                    break point;
+                }
+            }
+        };
+        for bytes in &[compressed(&point), uncompressed(&point)] {
+            match PublicKey::from_bytes(bytes) {
+                Err(BLSError::InvalidPoint {
+                    level: ValidationLevel::Subgroup,
+                    ..
+                }) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+            assert_eq!(
+                PublicKey::from_bytes_unchecked(bytes).unwrap(),
+                PublicKey::from(point.into_projective())
+            );
+        }
+        PublicKey::deserialize(&mut &compressed(&point)[..]).unwrap_err();
+        PublicKey::deserialize_uncompressed(&mut &uncompressed(&point)[..]).unwrap_err();
+    }
+
+    #[test]
+    fn decodes_batches() {
+        let rng = &mut rand::thread_rng();
+        let pubkeys = (0..150)
+            .map(|_| PrivateKey::generate(rng).to_public())
+            .collect::<Vec<_>>();
+        let mut entries = pubkeys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                if i % 2 == 0 {
+                    compressed(key)
+                } else {
+                    uncompressed(key)
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(PublicKey::batch_from_bytes(&entries).unwrap(), pubkeys);
+        assert!(PublicKey::batch_from_bytes::<Vec<u8>>(&[])
+            .unwrap()
+            .is_empty());
+
+        let outside = loop {
+            if let Some(point) = G2Affine::get_point_from_x(Fq2::rand(rng), false) {
+                if !is_valid_g2(&point) {
+                    break point;
+                }
+            }
+        };
+        entries[140] = uncompressed(&outside);
+        match PublicKey::batch_from_bytes(&entries) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => {
+                assert_eq!(invalid, vec![(140, "not in the prime order subgroup")])
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        entries[70].truncate(10);
+        match PublicKey::batch_from_bytes(&entries) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => assert_eq!(
+                invalid,
+                vec![
+                    (70, "invalid length"),
+                    (140, "not in the prime order subgroup")
+                ]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+        entries[3] = compressed(&outside);
+        match PublicKey::batch_from_bytes(&entries) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => assert_eq!(
+                invalid,
+                vec![
+                    (3, "not in the prime order subgroup"),
+                    (70, "invalid length"),
+                    (140, "not in the prime order subgroup")
+                ]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    fn compressed<T: CanonicalSerialize>(value: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        value.serialize(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn uncompressed<T: CanonicalSerialize>(value: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        value.serialize_uncompressed(&mut bytes).unwrap();
+        bytes
+    }
+
+    // Keys in the format the Go node passes to `bls-snark-sys`, taken from its verifier fixtures
+    const CELO_PUBKEYS: [&str; 2] = [
+        "45a3ed64a457fbc0e875b0d6dcc372216f96571eefd7a07d373a4de2b73cbebe6b7d43025a4306d356f5fc189ea720013295a3110785f5f7783e7e22a582b810ffdc5e3b10a61c38d3ee0f70ddc59294dd03d4753c7a3500f3c1456d19571981",
+        "d13b719de39cbf8c84a840484820d3b80836bfa161971f0c32dcd6b23d72adf3d817b9e648082d7e1c0a39fb6393390153ba4ca1ec7fb74a7c4c4f77c2399a214535b303c629b298fa946bbb4c7325ed3a7ac15fe8fdb311287cb06b75ba9481",
+    ];
+
+    #[test]
+    fn celo_bytes_match_go_fixtures() {
+        for fixture in CELO_PUBKEYS.iter() {
+            let bytes = hex::decode(fixture).unwrap();
+            let pubkey = PublicKey::from_celo_bytes(&bytes).unwrap();
+            assert_eq!(pubkey.to_celo_bytes(), bytes);
+            assert_eq!(pubkey, PublicKey::deserialize(&mut &bytes[..]).unwrap());
+        }
+
+        let bytes = hex::decode(CELO_PUBKEYS[0]).unwrap();
+        match PublicKey::from_celo_bytes(&bytes[..95]) {
+            Err(BLSError::InvalidPoint {
+                level: ValidationLevel::Subgroup,
+                reason: "invalid length",
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn checked_decoding_only_accepts_compressed_keys() {
+        let bytes = hex::decode(CELO_PUBKEYS[0]).unwrap();
+        let pubkey = PublicKey::from_bytes_checked(&bytes).unwrap();
+        assert_eq!(pubkey, PublicKey::from_bytes(&bytes).unwrap());
+        PublicKey::from_bytes_checked(&uncompressed(&pubkey)).unwrap_err();
+        PublicKey::from_bytes_checked(&compressed(&G2Affine::zero())).unwrap_err();
+    }
+
+    #[test]
+    fn aggregates_keys_with_valid_pops() {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*crate::hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1;
+        let keys = (0..3)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let mut with_pops = keys
+            .iter()
+            .map(|key| (key.to_public(), key.sign_key_pop(hasher).unwrap()))
+            .collect::<Vec<_>>();
+        for (key, pop) in &with_pops {
+            key.verify_key_pop(pop, hasher).unwrap();
+        }
+        let aggregate = PublicKey::aggregate_with_pop_check(&with_pops, hasher).unwrap();
+        assert_eq!(
+            aggregate,
+            PublicKey::aggregate(keys.iter().map(|key| key.to_public()))
+        );
+
+        // a rogue key cancelling the others out, whose private key the attacker ignores
+        let target = G2Projective::rand(rng);
+        let rogue = PublicKey::from(target - aggregate.0);
+        with_pops.push((rogue, with_pops[0].1.clone()));
+        match PublicKey::aggregate_with_pop_check(&with_pops, hasher) {
+            Err(BLSError::InvalidProofOfPossession(3)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // a proof of possession over another message is not accepted
+        let pop = keys[0].sign_pop(b"address", hasher).unwrap();
+        keys[0]
+            .to_public()
+            .verify_key_pop(&pop, hasher)
+            .unwrap_err();
+        match PublicKey::aggregate_with_pop_check(&[(keys[0].to_public(), pop)], hasher) {
+            Err(BLSError::InvalidProofOfPossession(0)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // the identity key with the identity signature as its proof of possession
+        let identity = (
+            PublicKey::from(G2Projective::zero()),
+            Signature::from(G1Projective::zero()),
+        );
+        identity.0.verify_key_pop(&identity.1, hasher).unwrap();
+        match PublicKey::aggregate_with_pop_check(&[with_pops[0].clone(), identity], hasher) {
+            Err(BLSError::InvalidProofOfPossession(1)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match PublicKey::aggregate_with_pop_check(&[], hasher) {
+            Err(BLSError::EmptyAggregation) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
     }
 }
@@ -0,0 +1,420 @@
+//! The BLS signature schemes of the
+//! [IETF BLS signature draft](https://tools.ietf.org/html/draft-irtf-cfrg-bls-signature-04#section-3).
+//!
+//! The schemes only differ in how they defend aggregate signatures against rogue key attacks:
+//! `Basic` requires the messages of an aggregate to be distinct, `MessageAugmentation` makes
+//! them distinct by signing each message together with the signer's public key, and
+//! `ProofOfPossession` requires every key to come with a proof of possession, which allows
+//! aggregating the keys of signers of the same message. Code written against `BlsScheme` works
+//! with any of them.
+//!
+//! As the draft requires, each scheme hashes its messages in its own domain, so that a
+//! signature of one scheme is never valid in another. The domains are 8 byte Blake2s
+//! personalizations like the other domains of this crate, and the messages are hashed to G1
+//! with the crate's hashers rather than the draft's hash-to-curve suites, so the signatures do
+//! not interoperate with other implementations of the draft. The augmentation is passed as the
+//! extra data of the hasher.
+//!
+//! `Celo` is the proof of possession scheme in the `SIG_DOMAIN`: its signatures are the
+//! signatures of `PrivateKey::sign`, which block seals, epoch blocks and threshold
+//! signatures use.
+use super::{subgroup::is_valid_g2, PrivateKey, PublicKey, Signature};
+use crate::{BLSError, BTreeSet, BlsResult, HashToCurve, Vec, SIG_DOMAIN};
+use algebra::{bls12_377::G1Projective, ProjectiveCurve, Zero};
+use core::borrow::Borrow;
+
+/// Domain of the signatures of the `Basic` scheme
+pub const BASIC_DOMAIN: &[u8] = b"ULsigNUL";
+
+/// Domain of the signatures of the `MessageAugmentation` scheme
+pub const AUGMENTATION_DOMAIN: &[u8] = b"ULsigAUG";
+
+/// Domain of the signatures of the `ProofOfPossession` scheme. The proofs of possession
+/// themselves are signed in the `POP_DOMAIN`.
+pub const POSSESSION_DOMAIN: &[u8] = b"ULsigPOP";
+
+/// A BLS signature scheme
+pub trait BlsScheme {
+    /// The domain in which the messages are hashed to G1
+    const DOMAIN: &'static [u8];
+
+    /// Whether the messages of an aggregate signature must be distinct
+    const DISTINCT_MESSAGES: bool;
+
+    /// The extra data which is hashed with each message signed by the public key
+    fn extra_data(_public_key: &PublicKey) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// The extra data which is hashed with each message signed by the private key, i.e. the
+    /// `extra_data` of its public key. Schemes which override `extra_data` must override this
+    /// too, while the others do not pay for deriving the public key when signing.
+    fn signing_extra_data(_key: &PrivateKey) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Signs the message
+    fn sign<H: HashToCurve<Output = G1Projective>>(
+        key: &PrivateKey,
+        message: &[u8],
+        hash_to_g1: &H,
+    ) -> BlsResult<Signature> {
+        Self::sign_with_extra_data(key, message, &[], hash_to_g1)
+    }
+
+    /// Signs the message along with the caller's extra data, e.g. the extra data of a block
+    /// header, which is hashed after the scheme's own extra data
+    fn sign_with_extra_data<H: HashToCurve<Output = G1Projective>>(
+        key: &PrivateKey,
+        message: &[u8],
+        extra_data: &[u8],
+        hash_to_g1: &H,
+    ) -> BlsResult<Signature> {
+        let extra_data = [&Self::signing_extra_data(key)[..], extra_data].concat();
+        key.sign_message(Self::DOMAIN, message, &extra_data, hash_to_g1)
+    }
+
+    /// Verifies the signature of the message by the public key
+    fn verify<H: HashToCurve<Output = G1Projective>>(
+        public_key: &PublicKey,
+        message: &[u8],
+        signature: &Signature,
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        Self::verify_with_extra_data(public_key, message, &[], signature, hash_to_g1)
+    }
+
+    /// Verifies the signature of the message and the caller's extra data by the public key,
+    /// see `sign_with_extra_data`
+    fn verify_with_extra_data<H: HashToCurve<Output = G1Projective>>(
+        public_key: &PublicKey,
+        message: &[u8],
+        extra_data: &[u8],
+        signature: &Signature,
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        let extra_data = [&Self::extra_data(public_key)[..], extra_data].concat();
+        public_key.verify_sig(Self::DOMAIN, message, &extra_data, signature, hash_to_g1)
+    }
+
+    /// Aggregates the signatures. The aggregate is their sum, so it does not depend on their
+    /// order.
+    fn aggregate<S: Borrow<Signature>>(signatures: impl IntoIterator<Item = S>) -> Signature {
+        Signature::aggregate(signatures)
+    }
+
+    /// Verifies the aggregate signature of the messages, where each message was signed by the
+    /// public key at the same position. Every public key must be a valid non-identity element
+    /// of G2, and with `DISTINCT_MESSAGES` the index of the first repeated message is returned
+    /// in `BLSError::DuplicateMessage`.
+    fn aggregate_verify<H: HashToCurve<Output = G1Projective>, P: Borrow<PublicKey>>(
+        public_keys: &[P],
+        messages: &[&[u8]],
+        signature: &Signature,
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        if public_keys.len() != messages.len() {
+            return Err(BLSError::UnevenNumKeysMessages);
+        }
+        if messages.is_empty() {
+            return Err(BLSError::VerificationFailed);
+        }
+        if Self::DISTINCT_MESSAGES {
+            let mut seen = BTreeSet::new();
+            for (index, message) in messages.iter().enumerate() {
+                if !seen.insert(message) {
+                    return Err(BLSError::DuplicateMessage(index));
+                }
+            }
+        }
+        check_public_keys(public_keys)?;
+
+        let extra_data = public_keys
+            .iter()
+            .map(|public_key| Self::extra_data(public_key.borrow()))
+            .collect::<Vec<_>>();
+        let pairs = messages
+            .iter()
+            .zip(&extra_data)
+            .map(|(message, extra_data)| (*message, &extra_data[..]));
+        signature.batch_verify(public_keys, Self::DOMAIN, pairs, hash_to_g1)
+    }
+
+    /// Verifies the aggregate signature of the message by all the public keys. Unless the
+    /// scheme aggregates the keys, this verifies the message once per key.
+    fn fast_aggregate_verify<H: HashToCurve<Output = G1Projective>, P: Borrow<PublicKey>>(
+        public_keys: &[P],
+        message: &[u8],
+        signature: &Signature,
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        let messages = vec![message; public_keys.len()];
+        Self::aggregate_verify(public_keys, &messages, signature, hash_to_g1)
+    }
+}
+
+/// The basic scheme, whose aggregate signatures must be over distinct messages. Same message
+/// aggregates fail with `BLSError::DuplicateMessage`.
+#[derive(Clone, Copy, Debug)]
+pub enum Basic {}
+
+impl BlsScheme for Basic {
+    const DOMAIN: &'static [u8] = BASIC_DOMAIN;
+    const DISTINCT_MESSAGES: bool = true;
+}
+
+/// The message augmentation scheme, which signs each message together with the signer's
+/// compressed public key. Signing derives the public key from the private key, which costs a
+/// scalar multiplication in G2.
+#[derive(Clone, Copy, Debug)]
+pub enum MessageAugmentation {}
+
+impl BlsScheme for MessageAugmentation {
+    const DOMAIN: &'static [u8] = AUGMENTATION_DOMAIN;
+    const DISTINCT_MESSAGES: bool = false;
+
+    fn extra_data(public_key: &PublicKey) -> Vec<u8> {
+        public_key.to_celo_bytes()
+    }
+
+    fn signing_extra_data(key: &PrivateKey) -> Vec<u8> {
+        Self::extra_data(&key.to_public())
+    }
+}
+
+/// The proof of possession scheme. Its keys must have been checked with
+/// `ProofOfPossession::aggregate_public_keys`, or individually with
+/// `PublicKey::verify_key_pop`, before their signatures are aggregated.
+#[derive(Clone, Copy, Debug)]
+pub enum ProofOfPossession {}
+
+impl ProofOfPossession {
+    /// Signs the proof of possession of the key, see `PrivateKey::sign_key_pop`
+    pub fn prove_possession<H: HashToCurve<Output = G1Projective>>(
+        key: &PrivateKey,
+        hash_to_g1: &H,
+    ) -> BlsResult<Signature> {
+        key.sign_key_pop(hash_to_g1)
+    }
+
+    /// Checks the proof of possession of each key and aggregates them, see
+    /// `PublicKey::aggregate_with_pop_check`
+    pub fn aggregate_public_keys<H: HashToCurve<Output = G1Projective>>(
+        keys: &[(PublicKey, Signature)],
+        hash_to_g1: &H,
+    ) -> BlsResult<PublicKey> {
+        PublicKey::aggregate_with_pop_check(keys, hash_to_g1)
+    }
+}
+
+impl BlsScheme for ProofOfPossession {
+    const DOMAIN: &'static [u8] = POSSESSION_DOMAIN;
+    const DISTINCT_MESSAGES: bool = false;
+
+    /// Verifies the signature against the aggregate of the public keys, with a single pairing
+    /// product
+    fn fast_aggregate_verify<H: HashToCurve<Output = G1Projective>, P: Borrow<PublicKey>>(
+        public_keys: &[P],
+        message: &[u8],
+        signature: &Signature,
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        verify_aggregate_key::<Self, _, _>(public_keys, message, signature, hash_to_g1)
+    }
+}
+
+/// The scheme of Celo's consensus, i.e. the proof of possession scheme in the `SIG_DOMAIN`.
+/// Validators prove the possession of their keys when registering, and the seals of blocks
+/// aggregate their signatures of a same message.
+#[derive(Clone, Copy, Debug)]
+pub enum Celo {}
+
+impl BlsScheme for Celo {
+    const DOMAIN: &'static [u8] = SIG_DOMAIN;
+    const DISTINCT_MESSAGES: bool = false;
+
+    /// Verifies the signature against the aggregate of the public keys, with a single pairing
+    /// product
+    fn fast_aggregate_verify<H: HashToCurve<Output = G1Projective>, P: Borrow<PublicKey>>(
+        public_keys: &[P],
+        message: &[u8],
+        signature: &Signature,
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        verify_aggregate_key::<Self, _, _>(public_keys, message, signature, hash_to_g1)
+    }
+}
+
+/// Verifies the signature of the message against the aggregate of the public keys, for the
+/// schemes whose keys come with proofs of possession
+fn verify_aggregate_key<S, H, P>(
+    public_keys: &[P],
+    message: &[u8],
+    signature: &Signature,
+    hash_to_g1: &H,
+) -> BlsResult<()>
+where
+    S: BlsScheme,
+    H: HashToCurve<Output = G1Projective>,
+    P: Borrow<PublicKey>,
+{
+    if public_keys.is_empty() {
+        return Err(BLSError::VerificationFailed);
+    }
+    check_public_keys(public_keys)?;
+    PublicKey::aggregate(public_keys.iter().map(Borrow::<PublicKey>::borrow)).verify_sig(
+        S::DOMAIN,
+        message,
+        &[],
+        signature,
+        hash_to_g1,
+    )
+}
+
+/// Checks that every public key is a valid non-identity element of G2
+fn check_public_keys<P: Borrow<PublicKey>>(public_keys: &[P]) -> BlsResult<()> {
+    for public_key in public_keys {
+        let point = public_key.borrow().as_ref().into_affine();
+        if point.is_zero() {
+            return Err(BLSError::InvalidPublicKey("point at infinity"));
+        }
+        if !is_valid_g2(&point) {
+            return Err(BLSError::InvalidPublicKey("not in the G2 subgroup"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1;
+
+    fn sign_all<S: BlsScheme>(keys: &[PrivateKey], messages: &[&[u8]]) -> Signature {
+        let hasher = &*DIRECT_HASH_TO_G1;
+        S::aggregate(
+            keys.iter()
+                .zip(messages)
+                .map(|(key, message)| S::sign(key, message, hasher).unwrap()),
+        )
+    }
+
+    fn check_scheme<S: BlsScheme>() {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let keys = (0..3)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let public_keys = keys.iter().map(|key| key.to_public()).collect::<Vec<_>>();
+        let messages: [&[u8]; 3] = [b"one", b"two", b"three"];
+        assert_eq!(
+            S::signing_extra_data(&keys[0]),
+            S::extra_data(&public_keys[0])
+        );
+
+        let signature = S::sign(&keys[0], messages[0], hasher).unwrap();
+        S::verify(&public_keys[0], messages[0], &signature, hasher).unwrap();
+        S::verify(&public_keys[0], messages[1], &signature, hasher).unwrap_err();
+        S::verify(&public_keys[1], messages[0], &signature, hasher).unwrap_err();
+
+        let aggregate = sign_all::<S>(&keys, &messages);
+        S::aggregate_verify(&public_keys, &messages, &aggregate, hasher).unwrap();
+        S::aggregate_verify(&public_keys[1..], &messages[1..], &aggregate, hasher).unwrap_err();
+        match S::aggregate_verify(&public_keys, &messages[1..], &aggregate, hasher) {
+            Err(BLSError::UnevenNumKeysMessages) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn schemes_sign_and_verify() {
+        check_scheme::<Basic>();
+        check_scheme::<MessageAugmentation>();
+        check_scheme::<ProofOfPossession>();
+        check_scheme::<Celo>();
+    }
+
+    #[test]
+    fn same_message_aggregation() {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let keys = (0..3)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let public_keys = keys.iter().map(|key| key.to_public()).collect::<Vec<_>>();
+        let messages: [&[u8]; 3] = [b"block"; 3];
+
+        let aggregate = sign_all::<Basic>(&keys, &messages);
+        match Basic::fast_aggregate_verify(&public_keys, messages[0], &aggregate, hasher) {
+            Err(BLSError::DuplicateMessage(1)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let aggregate = sign_all::<MessageAugmentation>(&keys, &messages);
+        MessageAugmentation::fast_aggregate_verify(&public_keys, messages[0], &aggregate, hasher)
+            .unwrap();
+
+        let aggregate = sign_all::<Celo>(&keys, &messages);
+        Celo::fast_aggregate_verify(&public_keys, messages[0], &aggregate, hasher).unwrap();
+
+        let aggregate = sign_all::<ProofOfPossession>(&keys, &messages);
+        ProofOfPossession::fast_aggregate_verify(&public_keys, messages[0], &aggregate, hasher)
+            .unwrap();
+        ProofOfPossession::fast_aggregate_verify(
+            &public_keys[1..],
+            messages[0],
+            &aggregate,
+            hasher,
+        )
+        .unwrap_err();
+
+        let proven = keys
+            .iter()
+            .map(|key| {
+                let pop = ProofOfPossession::prove_possession(key, hasher).unwrap();
+                (key.to_public(), pop)
+            })
+            .collect::<Vec<_>>();
+        let aggregate_key = ProofOfPossession::aggregate_public_keys(&proven, hasher).unwrap();
+        ProofOfPossession::verify(&aggregate_key, messages[0], &aggregate, hasher).unwrap();
+    }
+
+    #[test]
+    fn schemes_are_separated() {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let key = PrivateKey::generate(rng);
+        let public_key = key.to_public();
+
+        // a signature of one scheme is not valid in another
+        let signatures = [
+            Basic::sign(&key, b"message", hasher).unwrap(),
+            MessageAugmentation::sign(&key, b"message", hasher).unwrap(),
+            ProofOfPossession::sign(&key, b"message", hasher).unwrap(),
+            Celo::sign(&key, b"message", hasher).unwrap(),
+        ];
+        for (i, signature) in signatures.iter().enumerate() {
+            let verifies = [
+                Basic::verify(&public_key, b"message", signature, hasher).is_ok(),
+                MessageAugmentation::verify(&public_key, b"message", signature, hasher).is_ok(),
+                ProofOfPossession::verify(&public_key, b"message", signature, hasher).is_ok(),
+                Celo::verify(&public_key, b"message", signature, hasher).is_ok(),
+            ];
+            for (j, verifies) in verifies.iter().enumerate() {
+                assert_eq!(
+                    *verifies,
+                    i == j,
+                    "signature of scheme {} in scheme {}",
+                    i,
+                    j
+                );
+            }
+        }
+
+        // the Celo scheme produces the signatures of `PrivateKey::sign`
+        let signature = Celo::sign_with_extra_data(&key, b"message", b"extra", hasher).unwrap();
+        assert_eq!(signature, key.sign(b"message", b"extra", hasher).unwrap());
+        Celo::verify_with_extra_data(&public_key, b"message", b"extra", &signature, hasher)
+            .unwrap();
+    }
+}
@@ -1,16 +1,61 @@
-use crate::{BLSError, HashToCurve, PublicKey, Signature, POP_DOMAIN, SIG_DOMAIN};
+#[cfg(feature = "std")]
+use crate::observer;
+use crate::{
+    ct::secret_scalar_mul, BLSError, HashToCurve, PublicKey, Signature, Vec, POP_DOMAIN, SIG_DOMAIN,
+};
 
 use algebra::{
     bls12_377::{Fr, G1Projective},
-    CanonicalDeserialize, CanonicalSerialize, Group, SerializationError, UniformRand,
+    io::{Read, Write},
+    CanonicalDeserialize, CanonicalSerialize, Field, SerializationError, UniformRand, Zero,
 };
+use blake2s_simd::Params;
+use core::fmt;
 use rand::Rng;
-use std::io::{Read, Write};
+use zeroize::Zeroize;
+
+/// Length of the tags computed by `PrivateKey::integrity_tag`
+pub const INTEGRITY_TAG_LENGTH: usize = 32;
+
+/// Personalization of the hash which derives the tag's key from the passphrase
+const TAG_KEY_PERSONALIZATION: &[u8] = b"ULtagkey";
+
+/// Personalization of the keyed hash over the private key
+const TAG_PERSONALIZATION: &[u8] = b"ULkeytag";
+
+/// Personalization of the hash which extracts a key from the seed of `PrivateKey::from_seed`
+const SEED_EXTRACT_PERSONALIZATION: &[u8] = b"ULseedex";
+
+/// Personalization of the hash which expands the extracted key into a scalar
+const SEED_EXPAND_PERSONALIZATION: &[u8] = b"ULseedxp";
+
+/// Minimum length of the seeds of `PrivateKey::from_seed`
+pub const MIN_SEED_LENGTH: usize = 32;
 
 /// A Private Key using a pairing friendly curve's Fr point
-#[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+///
+/// The scalar is wiped from memory when the key is dropped, and it is not printed by `Debug`.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
 pub struct PrivateKey(Fr);
 
+impl Zeroize for PrivateKey {
+    fn zeroize(&mut self) {
+        zeroize_scalar(&mut self.0);
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PrivateKey(<redacted>)")
+    }
+}
+
 impl From<Fr> for PrivateKey {
     fn from(sk: Fr) -> PrivateKey {
         PrivateKey(sk)
@@ -29,6 +74,28 @@ impl PrivateKey {
         PrivateKey(Fr::rand(rng))
     }
 
+    /// Derives a private key from a seed of at least `MIN_SEED_LENGTH` bytes, e.g. one
+    /// recovered from a mnemonic, so that callers do not need their own seed expansion.
+    ///
+    /// The derivation follows HKDF with Blake2s: the seed is hashed into a pseudorandom key,
+    /// which keys the hashes of `info` and a counter. The first of these hashes which is a
+    /// non-zero scalar once truncated to the bit size of the scalar field is the key.
+    /// Different `info` strings derive independent keys from the same seed.
+    pub fn from_seed(seed: &[u8], info: &[u8]) -> Result<PrivateKey, BLSError> {
+        if seed.len() < MIN_SEED_LENGTH {
+            return Err(BLSError::SeedTooShort(seed.len(), MIN_SEED_LENGTH));
+        }
+        let mut prk = Params::new()
+            .hash_length(32)
+            .personal(SEED_EXTRACT_PERSONALIZATION)
+            .hash(seed)
+            .as_bytes()
+            .to_vec();
+        let key = expand_key(&prk, info);
+        prk.zeroize();
+        Ok(key)
+    }
+
     /// Hashes the message/extra_data tuple with the provided `hash_to_g1` function
     /// and then signs it in the SIG_DOMAIN
     pub fn sign<H: HashToCurve<Output = G1Projective>>(
@@ -50,8 +117,18 @@ impl PrivateKey {
         self.sign_message(POP_DOMAIN, &message, &[], hash_to_g1)
     }
 
+    /// Signs a proof of possession over the key's own compressed public key, in the
+    /// POP_DOMAIN. Unlike the proofs of `sign_pop`, which Celo binds to the validator's
+    /// address, the proof needs no context to be checked with `PublicKey::verify_key_pop`.
+    pub fn sign_key_pop<H: HashToCurve<Output = G1Projective>>(
+        &self,
+        hash_to_g1: &H,
+    ) -> Result<Signature, BLSError> {
+        self.sign_pop(&self.to_public().to_celo_bytes(), hash_to_g1)
+    }
+
     /// Hashes to G1 and signs the hash
-    fn sign_message<H: HashToCurve<Output = G1Projective>>(
+    pub(crate) fn sign_message<H: HashToCurve<Output = G1Projective>>(
         &self,
         domain: &[u8],
         message: &[u8],
@@ -59,38 +136,122 @@ impl PrivateKey {
         hash_to_g1: &H,
     ) -> Result<Signature, BLSError> {
         let hash = hash_to_g1.hash(domain, message, extra_data)?;
-        Ok(self.sign_raw(&hash))
+        let signature = self.scalar_sign(&hash);
+        #[cfg(feature = "std")]
+        observer::notify_message(domain, message, extra_data, || self.to_public());
+        Ok(signature)
+    }
+
+    /// Signs a message which has already been hashed to G1. This is a lower level method,
+    /// if you prefer hashing to be done internally, consider using the `sign` method.
+    ///
+    /// No domain separation is applied, so the caller is responsible for hashing the
+    /// message in the appropriate domain.
+    ///
+    /// The hash is multiplied by the key with `ct_scalar_mul`, or with the variable time
+    /// multiplication if the `vartime-signing` feature is enabled.
+    pub fn sign_hashed(&self, hash_point: &G1Projective) -> Signature {
+        let signature = self.scalar_sign(hash_point);
+        #[cfg(feature = "std")]
+        observer::notify_hashed(hash_point, || self.to_public());
+        signature
     }
 
-    fn sign_raw(&self, message: &G1Projective) -> Signature {
-        message.mul(self.as_ref()).into()
+    fn scalar_sign(&self, hash_point: &G1Projective) -> Signature {
+        secret_scalar_mul(hash_point, self.as_ref()).into()
     }
 
     /// Converts the private key to a public key
     pub fn to_public(&self) -> PublicKey {
         PublicKey::from(self)
     }
+
+    /// Computes a tag over the key, keyed with the passphrase. Storing the tag next to a
+    /// backup of the key allows detecting corruption or tampering of the stored key with
+    /// `verify_integrity_tag`. Unlike a fingerprint of the public key, the tag cannot be
+    /// recomputed for a substituted key without the passphrase.
+    pub fn integrity_tag(&self, passphrase: &[u8]) -> [u8; INTEGRITY_TAG_LENGTH] {
+        let mut tag = [0u8; INTEGRITY_TAG_LENGTH];
+        tag.copy_from_slice(self.integrity_hash(passphrase).as_bytes());
+        tag
+    }
+
+    /// Verifies that the tag was computed over this key with the passphrase. The comparison
+    /// takes constant time.
+    pub fn verify_integrity_tag(&self, passphrase: &[u8], tag: &[u8]) -> Result<(), BLSError> {
+        if self.integrity_hash(passphrase) == *tag {
+            Ok(())
+        } else {
+            Err(BLSError::IntegrityTagMismatch)
+        }
+    }
+
+    fn integrity_hash(&self, passphrase: &[u8]) -> blake2s_simd::Hash {
+        // the passphrase may be longer than a Blake2s key, so it is hashed first
+        let key = Params::new()
+            .hash_length(32)
+            .personal(TAG_KEY_PERSONALIZATION)
+            .hash(passphrase);
+        // allocated at its final size, so that no partial copy of the key is left behind
+        let mut bytes = Vec::with_capacity(self.serialized_size());
+        self.serialize(&mut bytes)
+            .expect("serializing to a vector cannot fail");
+        let hash = Params::new()
+            .hash_length(INTEGRITY_TAG_LENGTH)
+            .key(key.as_bytes())
+            .personal(TAG_PERSONALIZATION)
+            .hash(&bytes);
+        bytes.zeroize();
+        hash
+    }
+}
+
+/// Expands the pseudorandom key into a private key: the first keyed hash of `info` and a
+/// counter which is a non-zero scalar once truncated to the bit size of the scalar field
+pub(super) fn expand_key(prk: &[u8], info: &[u8]) -> PrivateKey {
+    for counter in 0..=255u8 {
+        let mut block = Params::new()
+            .hash_length(32)
+            .key(prk)
+            .personal(SEED_EXPAND_PERSONALIZATION)
+            .to_state()
+            .update(info)
+            .update(&[counter])
+            .finalize()
+            .as_bytes()
+            .to_vec();
+        let scalar = Fr::from_random_bytes(&block);
+        block.zeroize();
+        if let Some(scalar) = scalar.filter(|scalar| !scalar.is_zero()) {
+            return PrivateKey(scalar);
+        }
+    }
+    // each attempt succeeds with probability about 1/2
+    panic!("no valid scalar after 256 attempts")
+}
+
+/// Overwrites the limbs of the scalar with zeros
+pub(crate) fn zeroize_scalar(scalar: &mut Fr) {
+    scalar.0.as_mut().zeroize();
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "composite-hash")]
+    use crate::hashers::composite::{CompositeHasher, CRH};
     use crate::{
         hash_to_curve::try_and_increment::TryAndIncrement,
-        hashers::{
-            composite::{CompositeHasher, CRH},
-            DirectHasher, Hasher,
-        },
+        hashers::{DirectHasher, Hasher},
     };
     use algebra::{bls12_377::Parameters, curves::models::bls12::Bls12Parameters};
     use rand::{thread_rng, Rng};
 
     #[test]
     fn test_simple_sig() {
-        let direct_hasher = DirectHasher;
-        let composite_hasher = CompositeHasher::<CRH>::new().unwrap();
-        test_simple_sig_with_hasher(direct_hasher);
-        test_simple_sig_with_hasher(composite_hasher);
+        test_simple_sig_with_hasher(DirectHasher);
+        #[cfg(feature = "composite-hash")]
+        test_simple_sig_with_hasher(CompositeHasher::<CRH>::new().unwrap());
     }
 
     fn test_simple_sig_with_hasher<X: Hasher<Error = BLSError>>(hasher: X) {
@@ -137,4 +298,91 @@ mod tests {
         pk2.verify_pop(&pk_bytes, &sig, &try_and_increment)
             .unwrap_err();
     }
+
+    #[test]
+    fn integrity_tag() {
+        let rng = &mut thread_rng();
+        let sk = PrivateKey::generate(rng);
+        let tag = sk.integrity_tag(b"correct horse");
+        assert_eq!(tag, sk.integrity_tag(b"correct horse"));
+        sk.verify_integrity_tag(b"correct horse", &tag).unwrap();
+
+        // another passphrase or key, or a corrupted tag are rejected
+        sk.verify_integrity_tag(b"battery staple", &tag)
+            .unwrap_err();
+        PrivateKey::generate(rng)
+            .verify_integrity_tag(b"correct horse", &tag)
+            .unwrap_err();
+        let mut corrupted = tag;
+        corrupted[INTEGRITY_TAG_LENGTH - 1] ^= 1;
+        sk.verify_integrity_tag(b"correct horse", &corrupted)
+            .unwrap_err();
+        sk.verify_integrity_tag(b"correct horse", &tag[1..])
+            .unwrap_err();
+
+        // bit-rot of the stored key is detected
+        let mut bytes = vec![];
+        sk.serialize(&mut bytes).unwrap();
+        bytes[0] ^= 1;
+        let corrupted_key = PrivateKey::deserialize(&mut &bytes[..]).unwrap();
+        corrupted_key
+            .verify_integrity_tag(b"correct horse", &tag)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn derives_keys_from_seeds() {
+        let seed = [7u8; MIN_SEED_LENGTH];
+        let key = PrivateKey::from_seed(&seed, b"validator").unwrap();
+        assert_eq!(
+            key.as_ref(),
+            PrivateKey::from_seed(&seed, b"validator").unwrap().as_ref()
+        );
+        assert_ne!(
+            key.as_ref(),
+            PrivateKey::from_seed(&seed, b"attestation")
+                .unwrap()
+                .as_ref()
+        );
+        let mut other_seed = seed;
+        other_seed[0] ^= 1;
+        assert_ne!(
+            key.as_ref(),
+            PrivateKey::from_seed(&other_seed, b"validator")
+                .unwrap()
+                .as_ref()
+        );
+        match PrivateKey::from_seed(&seed[1..], b"validator") {
+            Err(BLSError::SeedTooShort(31, MIN_SEED_LENGTH)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn zeroizes_and_redacts_keys() {
+        let mut sk = PrivateKey::generate(&mut thread_rng());
+        assert_eq!(format!("{:?}", sk), "PrivateKey(<redacted>)");
+        sk.zeroize();
+        assert!(sk.as_ref().is_zero());
+    }
+
+    #[test]
+    fn test_sign_hashed() {
+        let rng = &mut thread_rng();
+        let direct_hasher = DirectHasher;
+        let try_and_increment =
+            TryAndIncrement::<_, <Parameters as Bls12Parameters>::G1Parameters>::new(
+                &direct_hasher,
+            );
+
+        let sk = PrivateKey::generate(rng);
+        let message = b"hello";
+        let hash = try_and_increment
+            .hash(SIG_DOMAIN, &message[..], &[])
+            .unwrap();
+
+        let sig = sk.sign_hashed(&hash);
+        assert_eq!(sig, sk.sign(&message[..], &[], &try_and_increment).unwrap());
+        sig.batch_verify_hashes(&[sk.to_public()], &[hash]).unwrap();
+    }
 }
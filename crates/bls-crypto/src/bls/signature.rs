@@ -1,18 +1,35 @@
-use super::PublicKey;
-use crate::{BLSError, HashToCurve};
+use super::{
+    collect_batch, sort_canonically, subgroup::is_valid_g2, PreparedPublicKey, PublicKey,
+    ValidationLevel,
+};
+#[cfg(feature = "std")]
+use crate::ct::vartime_scalar_mul;
+use crate::{
+    calibration::BatchConfig, g1_from_compressed_standard, g1_to_compressed_standard, BLSError,
+    BTreeMap, BTreeSet, Cow, HashToCurve, Vec, SIG_DOMAIN,
+};
 
+#[cfg(feature = "std")]
+use algebra::bls12_377::Fr;
 use algebra::{
     bls12_377::{Bls12_377, Fq12, G1Affine, G1Projective, G2Affine},
-    AffineCurve, CanonicalDeserialize, CanonicalSerialize, One, PairingEngine, ProjectiveCurve,
-    SerializationError,
-};
-
-use std::{
-    borrow::Borrow,
     io::{Read, Write},
-    ops::Neg,
+    AffineCurve, CanonicalDeserialize, CanonicalSerialize, ConstantSerializedSize, One,
+    PairingEngine, ProjectiveCurve, SerializationError, Zero,
 };
 
+use core::{borrow::Borrow, ops::Neg};
+#[cfg(feature = "threads")]
+use crossbeam_utils::thread;
+#[cfg(feature = "std")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "std")]
+use rand::Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "threads")]
+use std::{panic, sync::mpsc};
+
 /// A BLS signature on G1.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Signature(G1Projective);
@@ -43,23 +60,153 @@ impl CanonicalSerialize for Signature {
     }
 }
 
+/// Deserialized signatures are checked to be on the curve and in the prime order subgroup,
+/// see `Signature::from_bytes_unchecked` for trusted sources
 impl CanonicalDeserialize for Signature {
     fn deserialize<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
-        Ok(Signature::from(
-            G1Affine::deserialize(reader)?.into_projective(),
-        ))
+        let point = ValidationLevel::Subgroup.read_g1(reader, G1Affine::SERIALIZED_SIZE)?;
+        Ok(Signature::from(point.into_projective()))
     }
 
     fn deserialize_uncompressed<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
-        Ok(Signature::from(
-            G1Affine::deserialize_uncompressed(reader)?.into_projective(),
-        ))
+        let point = ValidationLevel::Subgroup.read_g1(reader, G1Affine::UNCOMPRESSED_SIZE)?;
+        Ok(Signature::from(point.into_projective()))
     }
 }
 
 impl Signature {
+    /// Decodes a signature in the format of celo-blockchain's Go node, which stores
+    /// signatures as the 48 byte compressed points produced by `bls-snark-sys`. The signature
+    /// is validated like with `from_bytes`, and any other length is reported with
+    /// `BLSError::InvalidPoint`.
+    pub fn from_celo_bytes(bytes: &[u8]) -> Result<Signature, BLSError> {
+        if bytes.len() != G1Affine::SERIALIZED_SIZE {
+            return Err(BLSError::InvalidPoint {
+                level: ValidationLevel::Subgroup,
+                reason: "invalid length",
+            });
+        }
+        Self::from_bytes(bytes)
+    }
+
+    /// Deserializes a compressed or uncompressed signature, checking that it is on the curve
+    /// and in the prime order subgroup, like `CanonicalDeserialize`. A failed check is
+    /// reported with `BLSError::InvalidPoint`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Signature, BLSError> {
+        Self::from_bytes_with_validation(bytes, ValidationLevel::Subgroup)
+    }
+
+    /// Deserializes a batch of compressed or uncompressed signatures, checking each one like
+    /// `from_bytes`. The entries are split in chunks which are decoded in parallel with the
+    /// `parallel` feature. Fails with the index of every invalid entry in
+    /// `BLSError::InvalidBatchEntries`.
+    pub fn batch_from_bytes<B: AsRef<[u8]> + Sync>(
+        entries: &[B],
+    ) -> Result<Vec<Signature>, BLSError> {
+        Self::batch_from_bytes_with_validation(entries, ValidationLevel::Subgroup)
+    }
+
+    // Decodes a batch of compressed or uncompressed signatures at the provided level
+    fn batch_from_bytes_with_validation<B: AsRef<[u8]> + Sync>(
+        entries: &[B],
+        level: ValidationLevel,
+    ) -> Result<Vec<Signature>, BLSError> {
+        collect_batch(
+            level
+                .batch_decode_g1(entries)
+                .into_iter()
+                .map(|point| Ok(Signature::from(point?.into_projective()))),
+        )
+    }
+
+    /// Deserializes a compressed or uncompressed signature without any check. Only use this
+    /// for signatures from a trusted source, e.g. seals which were verified before being
+    /// stored.
+    pub fn from_bytes_unchecked(bytes: &[u8]) -> Result<Signature, BLSError> {
+        Self::from_bytes_with_validation(bytes, ValidationLevel::None)
+    }
+
+    /// Like `from_bytes`, checking the signature at the provided validation level
+    pub fn from_bytes_with_validation(
+        bytes: &[u8],
+        level: ValidationLevel,
+    ) -> Result<Signature, BLSError> {
+        let point = level
+            .decode_g1(bytes)
+            .map_err(|reason| BLSError::InvalidPoint { level, reason })?;
+        Ok(Signature::from(point.into_projective()))
+    }
+
+    /// Decodes a batch of signatures in the format of celo-blockchain's Go node, e.g. the
+    /// seals of many blocks read from storage. The lengths of all the entries are checked
+    /// before any point is decompressed, so a malformed batch is rejected before doing any
+    /// square roots or subgroup checks. The entries are then decoded like `batch_from_bytes`,
+    /// and the index of every invalid entry is returned in `BLSError::InvalidBatchEntries`.
+    ///
+    /// The base field of BLS12-377 has `p = 1 mod 4` (its 2-adicity is 46), so square roots
+    /// cannot use the single exponentiation available when `p = 3 mod 4` and are computed
+    /// with Tonelli-Shanks for each entry.
+    pub fn batch_from_compressed<B: AsRef<[u8]> + Sync>(
+        entries: &[B],
+    ) -> Result<Vec<Signature>, BLSError> {
+        Self::batch_from_compressed_with_validation(entries, ValidationLevel::Subgroup)
+    }
+
+    /// Like `batch_from_compressed`, checking each signature at the provided validation
+    /// level. Seals read back from a node's own storage were checked when they were first
+    /// received, and can skip the subgroup checks.
+    pub fn batch_from_compressed_with_validation<B: AsRef<[u8]> + Sync>(
+        entries: &[B],
+        level: ValidationLevel,
+    ) -> Result<Vec<Signature>, BLSError> {
+        collect_batch(entries.iter().map(|bytes| {
+            if bytes.as_ref().len() == G1Affine::SERIALIZED_SIZE {
+                Ok(())
+            } else {
+                Err("invalid length")
+            }
+        }))?;
+        Self::batch_from_bytes_with_validation(entries, level)
+    }
+
+    /// Encodes the signature in the format of celo-blockchain's Go node
+    pub fn to_celo_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(G1Affine::SERIALIZED_SIZE);
+        self.serialize(&mut bytes)
+            .expect("serializing to a vector cannot fail");
+        bytes
+    }
+
+    /// Encodes the signature in the standard compressed format of Zcash and EIP-2537 tooling,
+    /// see `SerializationProfile::StandardCompressed`
+    pub fn to_compressed_standard(&self) -> Vec<u8> {
+        g1_to_compressed_standard(&self.as_ref().into_affine())
+    }
+
+    /// Decodes a signature in the standard compressed format, checking that it is in the
+    /// prime order subgroup
+    pub fn from_compressed_standard(bytes: &[u8]) -> Result<Signature, BLSError> {
+        Ok(Signature::from(
+            g1_from_compressed_standard(bytes)?.into_projective(),
+        ))
+    }
+
     /// Sums the provided signatures to produce the aggregate signature.
+    ///
+    /// The signatures are summed in their canonical order (sorted by their compressed
+    /// serialization), so that the same set of signatures always produces the same aggregate
+    /// representation, regardless of the order in which they were provided.
     pub fn aggregate<S: Borrow<Signature>>(signatures: impl IntoIterator<Item = S>) -> Signature {
+        let mut signatures = signatures.into_iter().collect::<Vec<_>>();
+        sort_canonically::<_, Signature>(&mut signatures);
+        Self::aggregate_ordered(signatures)
+    }
+
+    /// Sums the provided signatures in the order they were provided to produce the
+    /// aggregate signature.
+    pub fn aggregate_ordered<S: Borrow<Signature>>(
+        signatures: impl IntoIterator<Item = S>,
+    ) -> Signature {
         signatures
             .into_iter()
             .map(|s| s.borrow().0)
@@ -67,31 +214,235 @@ impl Signature {
             .into()
     }
 
+    /// Removes `other` from the aggregate signature, e.g. the signature of a validator which
+    /// equivocated, without aggregating the remaining signatures again.
+    ///
+    /// The group operation cannot tell whether `other` was part of the aggregate: subtracting
+    /// a signature which was never added, or subtracting one twice, silently produces an
+    /// aggregate which no longer matches its signers. Callers must track the signers of the
+    /// aggregate themselves, e.g. with the bitmap, and remove the same public key from the
+    /// aggregate public key with `PublicKey::subtract`.
+    pub fn subtract(&self, other: &Signature) -> Signature {
+        (self.0 - other.0).into()
+    }
+
     /// Verifies the signature against a vector of pubkey & message tuples, for the provided
     /// messages domain.
     ///
-    /// For each message, an optional extra_data field can be provided (empty otherwise).
+    /// For each message, an optional extra_data field can be provided (empty otherwise). The
+    /// messages can be any iterator over borrowed pairs, whose lengths are all checked before
+    /// any message is hashed. Callers which reuse a buffer for each message should use
+    /// `BatchVerifier::add_transient`.
     ///
     /// The provided hash_to_g1 implementation will be used to hash each message-extra_data pair
     /// to G1.
     ///
     /// The verification equation can be found in pg.11 from
     /// https://eprint.iacr.org/2018/483.pdf: "Batch verification"
-    pub fn batch_verify<H: HashToCurve<Output = G1Projective>, P: Borrow<PublicKey>>(
+    pub fn batch_verify<'m, H, P, M>(
+        &self,
+        pubkeys: &[P],
+        domain: &[u8],
+        messages: impl IntoIterator<Item = M>,
+        hash_to_g1: &H,
+    ) -> Result<(), BLSError>
+    where
+        H: HashToCurve<Output = G1Projective>,
+        P: Borrow<PublicKey>,
+        M: Borrow<(&'m [u8], &'m [u8])>,
+    {
+        let messages = messages
+            .into_iter()
+            .map(|message| *message.borrow())
+            .collect::<Vec<_>>();
+        if messages.len() != pubkeys.len() {
+            return Err(BLSError::UnevenNumKeysMessages);
+        }
+        // all the lengths are checked before any message is hashed
+        let mut verifier = BatchVerifier::new(domain, hash_to_g1);
+        for (index, (message, extra_data)) in messages.iter().enumerate() {
+            verifier.check_length(index, message, extra_data)?;
+        }
+        for (pubkey, (message, extra_data)) in pubkeys.iter().zip(messages) {
+            verifier.add(pubkey.borrow(), message, extra_data)?;
+        }
+
+        verifier.verify(self)
+    }
+
+    /// Same as `batch_verify`, but the messages are hashed on a separate thread while the
+    /// Miller loops of the already hashed messages are computed on the calling thread. The
+    /// two stages are connected by a bounded channel, so at most 64 hashes wait to be
+    /// paired.
+    ///
+    /// This improves throughput on machines with 2 to 4 cores, where hashing and pairing
+    /// on the same thread pool makes the two workloads compete for the same threads. Unlike
+    /// `batch_verify`, repeated messages are hashed every time.
+    ///
+    /// Requires the `threads` feature.
+    #[cfg(feature = "threads")]
+    pub fn batch_verify_pipelined<H, P>(
         &self,
         pubkeys: &[P],
         domain: &[u8],
         messages: &[(&[u8], &[u8])],
         hash_to_g1: &H,
-    ) -> Result<(), BLSError> {
+    ) -> Result<(), BLSError>
+    where
+        H: HashToCurve<Output = G1Projective> + Sync,
+        P: Borrow<PublicKey>,
+    {
         if pubkeys.len() != messages.len() {
             return Err(BLSError::UnevenNumKeysMessages);
-        };
+        }
+        check_message_lengths(messages, hash_to_g1)?;
+
+        let (sender, receiver) = mpsc::sync_channel(PIPELINE_DEPTH);
+        let product = thread::scope(|s| {
+            s.spawn(move |_| {
+                for (message, extra_data) in messages {
+                    let hash = hash_to_g1.hash(domain, message, extra_data);
+                    let failed = hash.is_err();
+                    // sending fails if the pairing stage stopped early
+                    if sender.send(hash).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+
+            let mut product = signature_miller_loop(&self.0);
+            let mut pairs = Vec::with_capacity(PIPELINE_CHUNK_SIZE);
+            for (pubkey, hash) in pubkeys.iter().zip(receiver.iter()) {
+                pairs.push(prepare_pair(&hash?, pubkey.borrow()));
+                if pairs.len() == PIPELINE_CHUNK_SIZE {
+                    product *= &Bls12_377::miller_loop(&pairs);
+                    pairs.clear();
+                }
+            }
+            product *= &Bls12_377::miller_loop(&pairs);
+            Ok(product)
+        })
+        .unwrap_or_else(|err| panic::resume_unwind(err))?;
+
+        if is_one(&product) {
+            Ok(())
+        } else {
+            Err(BLSError::VerificationFailed)
+        }
+    }
+
+    /// Same as `batch_verify`, but the messages are hashed to G1 and the Miller loops are
+    /// computed across rayon's thread pool, for batches spanning hundreds of epochs.
+    ///
+    /// Each Miller loop prepares `chunk_size` pairs and runs as one task, so smaller chunks
+    /// spread the pairings over more threads while larger ones amortize more of each loop.
+    /// `BatchConfig::pairing_chunk_size` from `calibration::calibrate` is a good start. Unlike
+    /// `batch_verify`, repeated messages are hashed every time.
+    #[cfg(feature = "parallel")]
+    pub fn batch_verify_parallel<H, P>(
+        &self,
+        pubkeys: &[P],
+        domain: &[u8],
+        messages: &[(&[u8], &[u8])],
+        hash_to_g1: &H,
+        chunk_size: usize,
+    ) -> Result<(), BLSError>
+    where
+        H: HashToCurve<Output = G1Projective> + Sync,
+        P: Borrow<PublicKey> + Sync,
+    {
+        if pubkeys.len() != messages.len() {
+            return Err(BLSError::UnevenNumKeysMessages);
+        }
+        check_message_lengths(messages, hash_to_g1)?;
+
         let message_hashes = messages
-            .iter()
+            .par_iter()
             .map(|(message, extra_data)| hash_to_g1.hash(domain, message, extra_data))
-            .collect::<Result<Vec<G1Projective>, _>>()?;
+            .collect::<Result<Vec<_>, _>>()?;
+        self.batch_verify_hashes_parallel(pubkeys, &message_hashes, chunk_size)
+    }
 
+    /// Same as `batch_verify_hashes`, but the Miller loops of the chunks of `chunk_size`
+    /// pairs are computed across rayon's thread pool, see `batch_verify_parallel`
+    #[cfg(feature = "parallel")]
+    pub fn batch_verify_hashes_parallel<P: Borrow<PublicKey> + Sync>(
+        &self,
+        pubkeys: &[P],
+        message_hashes: &[G1Projective],
+        chunk_size: usize,
+    ) -> Result<(), BLSError> {
+        if pubkeys.len() != message_hashes.len() {
+            return Err(BLSError::UnevenNumKeysMessages);
+        };
+        let chunk_size = chunk_size.max(1);
+        let mut product = message_hashes
+            .par_chunks(chunk_size)
+            .zip(pubkeys.par_chunks(chunk_size))
+            .map(|(message_hashes, pubkeys)| {
+                let els = message_hashes
+                    .iter()
+                    .zip(pubkeys)
+                    .map(|(hash, pubkey)| prepare_pair(hash, pubkey.borrow()))
+                    .collect::<Vec<_>>();
+                Bls12_377::miller_loop(&els)
+            })
+            .reduce(Fq12::one, |a, b| a * &b);
+        product *= &signature_miller_loop(&self.0);
+
+        if is_one(&product) {
+            Ok(())
+        } else {
+            Err(BLSError::VerificationFailed)
+        }
+    }
+
+    /// Verifies the aggregate signature over distinct messages, where each message was signed
+    /// by the public key at the same position. This follows `AggregateVerify` from the
+    /// [IETF BLS signature draft](https://tools.ietf.org/html/draft-irtf-cfrg-bls-signature-04#section-3.1.1):
+    /// the messages must be distinct, which is enforced instead of assumed, every public key
+    /// must be a valid non-identity element of G2, and the signature must be in G1.
+    ///
+    /// Messages are hashed with `SIG_DOMAIN` and no extra data. Use `batch_verify` for batches
+    /// which may repeat a message, e.g. the same block signed by several committees.
+    pub fn aggregate_verify<H: HashToCurve<Output = G1Projective>, P: Borrow<PublicKey>>(
+        &self,
+        pubkeys: &[P],
+        messages: &[&[u8]],
+        hash_to_g1: &H,
+    ) -> Result<(), BLSError> {
+        if pubkeys.len() != messages.len() {
+            return Err(BLSError::UnevenNumKeysMessages);
+        }
+        if messages.is_empty() {
+            return Err(BLSError::VerificationFailed);
+        }
+        let mut seen = BTreeSet::new();
+        for (index, message) in messages.iter().enumerate() {
+            if !seen.insert(message) {
+                return Err(BLSError::DuplicateMessage(index));
+            }
+        }
+        for pubkey in pubkeys {
+            let point = pubkey.borrow().as_ref().into_affine();
+            if point.is_zero() {
+                return Err(BLSError::InvalidPublicKey("point at infinity"));
+            }
+            if !is_valid_g2(&point) {
+                return Err(BLSError::InvalidPublicKey("not in the G2 subgroup"));
+            }
+        }
+        if ValidationLevel::Subgroup
+            .validate_g1(&self.0.into_affine())
+            .is_err()
+        {
+            return Err(BLSError::InvalidSignature("not in the G1 subgroup"));
+        }
+
+        let message_hashes = messages
+            .iter()
+            .map(|message| hash_to_g1.hash(SIG_DOMAIN, message, &[]))
+            .collect::<Result<Vec<_>, _>>()?;
         self.batch_verify_hashes(pubkeys, &message_hashes)
     }
 
@@ -99,6 +450,9 @@ impl Signature {
     /// This is a lower level method, if you prefer hashing to be done internally,
     /// consider using the `batch_verify` method.
     ///
+    /// The Miller loops are computed over bounded chunks of the batch, so that the memory
+    /// used for the prepared points does not grow with the batch size.
+    ///
     /// The verification equation can be found in pg.11 from
     /// https://eprint.iacr.org/2018/483.pdf: "Batch verification"
     pub fn batch_verify_hashes<P: Borrow<PublicKey>>(
@@ -109,49 +463,487 @@ impl Signature {
         if pubkeys.len() != message_hashes.len() {
             return Err(BLSError::UnevenNumKeysMessages);
         };
-        // `.into()` is needed to prepared the points
-        let mut els = Vec::with_capacity(message_hashes.len() + 1);
-        els.push((
-            self.as_ref().into_affine().into(),
-            G2Affine::prime_subgroup_generator().neg().into(),
-        ));
-        message_hashes
-            .iter()
-            .zip(pubkeys)
-            .for_each(|(hash, pubkey)| {
-                els.push((
-                    hash.into_affine().into(),
-                    pubkey.borrow().as_ref().into_affine().into(),
-                ));
-            });
+        if pairing_product_is_one(&self.0, pubkeys, message_hashes, PAIRING_CHUNK_SIZE) {
+            Ok(())
+        } else {
+            Err(BLSError::VerificationFailed)
+        }
+    }
 
-        let pairing = Bls12_377::product_of_pairings(&els);
-        if pairing == Fq12::one() {
+    /// Same as `batch_verify_hashes`, but prepares the number of pairs of each Miller loop
+    /// chosen by the config, e.g. one returned by `calibration::calibrate` on this host
+    pub fn batch_verify_hashes_with_config<P: Borrow<PublicKey>>(
+        &self,
+        pubkeys: &[P],
+        message_hashes: &[G1Projective],
+        config: &BatchConfig,
+    ) -> Result<(), BLSError> {
+        if pubkeys.len() != message_hashes.len() {
+            return Err(BLSError::UnevenNumKeysMessages);
+        };
+        let chunk_size = config.pairing_chunk_size.max(1);
+        if pairing_product_is_one(&self.0, pubkeys, message_hashes, chunk_size) {
             Ok(())
         } else {
             Err(BLSError::VerificationFailed)
         }
     }
+
+    /// Same as `batch_verify_hashes`, but with public keys which were already prepared for
+    /// the Miller loop, e.g. the keys of the current validator set
+    pub fn batch_verify_hashes_prepared<P: Borrow<PreparedPublicKey>>(
+        &self,
+        pubkeys: &[P],
+        message_hashes: &[G1Projective],
+    ) -> Result<(), BLSError> {
+        if pubkeys.len() != message_hashes.len() {
+            return Err(BLSError::UnevenNumKeysMessages);
+        };
+        let mut product = signature_miller_loop(&self.0);
+        let chunks = message_hashes
+            .chunks(PAIRING_CHUNK_SIZE)
+            .zip(pubkeys.chunks(PAIRING_CHUNK_SIZE));
+        for (message_hashes, pubkeys) in chunks {
+            // the Miller loop takes the pairs by reference, so the prepared keys are copied
+            let els = message_hashes
+                .iter()
+                .zip(pubkeys)
+                .map(|(hash, pubkey)| (hash.into_affine().into(), pubkey.borrow().prepared.clone()))
+                .collect::<Vec<_>>();
+            product *= &Bls12_377::miller_loop(&els);
+        }
+
+        if is_one(&product) {
+            Ok(())
+        } else {
+            Err(BLSError::VerificationFailed)
+        }
+    }
+
+    /// Verifies a batch which is split in consecutive chunks of `chunk_size` messages, where
+    /// `signatures[i]` is the aggregate signature over the messages of the `i`th chunk (the
+    /// last chunk may be shorter). The chunks are verified in order and verification stops at
+    /// the first invalid one, whose index is returned in `BLSError::ChunkVerificationFailed`.
+    ///
+    /// This costs one final exponentiation per chunk, instead of one for the whole batch as
+    /// with `batch_verify_hashes`, in exchange for blaming the invalid chunk. A `chunk_size`
+    /// of 0 is treated as 1.
+    pub fn batch_verify_hashes_chunked<P: Borrow<PublicKey>>(
+        signatures: &[Signature],
+        pubkeys: &[P],
+        message_hashes: &[G1Projective],
+        chunk_size: usize,
+    ) -> Result<(), BLSError> {
+        let chunk_size = chunk_size.max(1);
+        if pubkeys.len() != message_hashes.len() {
+            return Err(BLSError::UnevenNumKeysMessages);
+        };
+        let num_chunks = (message_hashes.len() + chunk_size - 1) / chunk_size;
+        if signatures.len() != num_chunks {
+            return Err(BLSError::ChunkCountMismatch(signatures.len(), num_chunks));
+        }
+
+        let chunks = pubkeys
+            .chunks(chunk_size)
+            .zip(message_hashes.chunks(chunk_size));
+        for (index, (signature, (pubkeys, message_hashes))) in
+            signatures.iter().zip(chunks).enumerate()
+        {
+            if !pairing_product_is_one(&signature.0, pubkeys, message_hashes, PAIRING_CHUNK_SIZE) {
+                return Err(BLSError::ChunkVerificationFailed(index));
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies independent signatures, each by its own public key over its own message and
+    /// extra data, e.g. a batch of gossip messages, with a single multi-pairing instead of two
+    /// pairings per signature.
+    ///
+    /// Summing the signatures as they are would let a signer cancel an invalid signature out
+    /// with another one. Each entry is instead multiplied by a random 128 bit coefficient
+    /// `r_i`, and the batch is valid if `e(sum r_i * sig_i, -g2) * prod e(r_i * H(m_i), pk_i)`
+    /// is one, which an invalid batch only passes with probability `2^-128`. This only holds
+    /// in the prime order subgroups: a component of small order could be cancelled by a
+    /// coefficient which it divides, so the keys and signatures are checked to be in G2 and G1
+    /// first, and the batch fails with `BLSError::InvalidBatchEntries` otherwise.
+    ///
+    /// Fails with `BLSError::VerificationFailed` if any signature is invalid, use
+    /// `verify_batch_independent_with_bisection` to find which ones. An empty batch is valid.
+    ///
+    /// The coefficients are drawn from the thread's RNG, so this requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn verify_batch_independent<H: HashToCurve<Output = G1Projective>>(
+        items: &[(PublicKey, &[u8], &[u8], Signature)],
+        domain: &[u8],
+        hash_to_g1: &H,
+    ) -> Result<(), BLSError> {
+        let (hashes, signatures) = randomize_independent(items, domain, hash_to_g1)?;
+        if independent_range_is_valid(items, &hashes, &signatures) {
+            Ok(())
+        } else {
+            Err(BLSError::VerificationFailed)
+        }
+    }
+
+    /// Like `verify_batch_independent`, but if the batch is invalid, its halves are verified
+    /// recursively to find the invalid signatures, whose indices are returned in
+    /// `BLSError::InvalidSignatures`. Each of the `k` invalid signatures costs about
+    /// `2 * log2(n)` more multi-pairings, so this is only cheaper than verifying the
+    /// signatures one by one when few of them are invalid.
+    #[cfg(feature = "std")]
+    pub fn verify_batch_independent_with_bisection<H: HashToCurve<Output = G1Projective>>(
+        items: &[(PublicKey, &[u8], &[u8], Signature)],
+        domain: &[u8],
+        hash_to_g1: &H,
+    ) -> Result<(), BLSError> {
+        let (hashes, signatures) = randomize_independent(items, domain, hash_to_g1)?;
+        let mut invalid = vec![];
+        bisect_independent(items, &hashes, &signatures, 0, &mut invalid);
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(BLSError::InvalidSignatures(invalid))
+        }
+    }
+}
+
+// Checks that the keys and signatures of independent signatures are in the prime order
+// subgroups, hashes their messages, and returns the hashes and the signatures multiplied by
+// the same random coefficient for each entry
+#[cfg(feature = "std")]
+fn randomize_independent<H: HashToCurve<Output = G1Projective>>(
+    items: &[(PublicKey, &[u8], &[u8], Signature)],
+    domain: &[u8],
+    hash_to_g1: &H,
+) -> Result<(Vec<G1Projective>, Vec<G1Projective>), BLSError> {
+    collect_batch(items.iter().map(|(pubkey, _, _, signature)| {
+        if ValidationLevel::Subgroup
+            .validate_g2(&pubkey.as_ref().into_affine())
+            .is_err()
+        {
+            Err("public key is not in G2")
+        } else if ValidationLevel::Subgroup
+            .validate_g1(&signature.0.into_affine())
+            .is_err()
+        {
+            Err("signature is not in G1")
+        } else {
+            Ok(())
+        }
+    }))?;
+
+    let messages = items
+        .iter()
+        .map(|(_, message, extra_data, _)| (*message, *extra_data))
+        .collect::<Vec<_>>();
+    check_message_lengths(&messages, hash_to_g1)?;
+
+    let rng = &mut rand::thread_rng();
+    let mut hashes = Vec::with_capacity(items.len());
+    let mut signatures = Vec::with_capacity(items.len());
+    for (_, message, extra_data, signature) in items {
+        let coefficient = Fr::from(rng.gen::<u128>());
+        let hash = hash_to_g1.hash(domain, message, extra_data)?;
+        hashes.push(vartime_scalar_mul(&hash, &coefficient));
+        signatures.push(vartime_scalar_mul(&signature.0, &coefficient));
+    }
+    Ok((hashes, signatures))
+}
+
+// Verifies the randomized entries of a range of the batch at once
+#[cfg(feature = "std")]
+fn independent_range_is_valid(
+    items: &[(PublicKey, &[u8], &[u8], Signature)],
+    hashes: &[G1Projective],
+    signatures: &[G1Projective],
+) -> bool {
+    let signature = signatures.iter().sum::<G1Projective>();
+    let pubkeys = items.iter().map(|(pubkey, ..)| pubkey).collect::<Vec<_>>();
+    pairing_product_is_one(&signature, &pubkeys, hashes, PAIRING_CHUNK_SIZE)
+}
+
+// Pushes the indices of the invalid entries of the range starting at `offset` to `invalid`
+#[cfg(feature = "std")]
+fn bisect_independent(
+    items: &[(PublicKey, &[u8], &[u8], Signature)],
+    hashes: &[G1Projective],
+    signatures: &[G1Projective],
+    offset: usize,
+    invalid: &mut Vec<usize>,
+) {
+    if items.is_empty() || independent_range_is_valid(items, hashes, signatures) {
+        return;
+    }
+    if items.len() == 1 {
+        invalid.push(offset);
+        return;
+    }
+    let mid = items.len() / 2;
+    bisect_independent(
+        &items[..mid],
+        &hashes[..mid],
+        &signatures[..mid],
+        offset,
+        invalid,
+    );
+    bisect_independent(
+        &items[mid..],
+        &hashes[mid..],
+        &signatures[mid..],
+        offset + mid,
+        invalid,
+    );
+}
+
+// Maximum number of pairs which are prepared at once. Each chunk's Miller loop is multiplied
+// into an accumulator and the final exponentiation is done once, so memory stays bounded
+// for batches with thousands of entries.
+pub(crate) const PAIRING_CHUNK_SIZE: usize = 256;
+
+// Maximum number of hashes which wait for the pairing stage of `batch_verify_pipelined`
+#[cfg(feature = "threads")]
+const PIPELINE_DEPTH: usize = 64;
+
+// Number of pairs of each Miller loop of the pipelined verification. It is small so that the
+// pairing stage starts while the hashing stage is still running.
+#[cfg(feature = "threads")]
+const PIPELINE_CHUNK_SIZE: usize = 16;
+
+pub(crate) type PreparedPair = (
+    <Bls12_377 as PairingEngine>::G1Prepared,
+    <Bls12_377 as PairingEngine>::G2Prepared,
+);
+
+// `.into()` is needed to prepared the points
+pub(crate) fn prepare_pair(hash: &G1Projective, pubkey: &PublicKey) -> PreparedPair {
+    (
+        hash.into_affine().into(),
+        pubkey.as_ref().into_affine().into(),
+    )
+}
+
+/// The negation of the G2 generator, prepared for the Miller loop. Every verification pairs
+/// the signature with it, so it is prepared once per process instead of once per call. It can
+/// be used to compute the pairings of custom verification equations.
+#[cfg(feature = "std")]
+pub static NEG_G2_GENERATOR_PREPARED: Lazy<<Bls12_377 as PairingEngine>::G2Prepared> =
+    Lazy::new(|| G2Affine::prime_subgroup_generator().neg().into());
+
+// Returns the prepared negation of the G2 generator. Copying `NEG_G2_GENERATOR_PREPARED` is
+// much cheaper than preparing it again, which is only done without `std`.
+pub(crate) fn neg_g2_generator_prepared() -> <Bls12_377 as PairingEngine>::G2Prepared {
+    #[cfg(feature = "std")]
+    let prepared = NEG_G2_GENERATOR_PREPARED.clone();
+    #[cfg(not(feature = "std"))]
+    let prepared = G2Affine::prime_subgroup_generator().neg().into();
+    prepared
+}
+
+// Returns the Miller loop of e(signature, -g2)
+pub(crate) fn signature_miller_loop(signature: &G1Projective) -> Fq12 {
+    Bls12_377::miller_loop(&[(signature.into_affine().into(), neg_g2_generator_prepared())])
+}
+
+// Fails with the index of the first message which is longer than the hasher accepts
+fn check_message_lengths<H: HashToCurve>(
+    messages: &[(&[u8], &[u8])],
+    hash_to_g1: &H,
+) -> Result<(), BLSError> {
+    if let Some(max) = hash_to_g1.max_input_length() {
+        for (index, (message, extra_data)) in messages.iter().enumerate() {
+            let length = message.len() + extra_data.len();
+            if length > max {
+                return Err(BLSError::MessageTooLong { index, length, max });
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn is_one(product: &Fq12) -> bool {
+    Bls12_377::final_exponentiation(product).map_or(false, |pairing| pairing == Fq12::one())
+}
+
+// Returns true if e(signature, -g2) * prod e(hash_i, pubkey_i) == 1, preparing `chunk_size`
+// pairs for each Miller loop
+fn pairing_product_is_one<P: Borrow<PublicKey>>(
+    signature: &G1Projective,
+    pubkeys: &[P],
+    message_hashes: &[G1Projective],
+    chunk_size: usize,
+) -> bool {
+    let mut product = signature_miller_loop(signature);
+    let chunks = message_hashes
+        .chunks(chunk_size)
+        .zip(pubkeys.chunks(chunk_size));
+    for (message_hashes, pubkeys) in chunks {
+        let els = message_hashes
+            .iter()
+            .zip(pubkeys)
+            .map(|(hash, pubkey)| prepare_pair(hash, pubkey.borrow()))
+            .collect::<Vec<_>>();
+        product *= &Bls12_377::miller_loop(&els);
+    }
+
+    is_one(&product)
+}
+
+/// Collects the public keys and messages of a batch verification one at a time.
+///
+/// Each message is hashed as soon as it is added, and messages which were already added are
+/// not hashed again. `add` borrows the message for the lifetime of the verifier, while
+/// `add_transient` only borrows it for the duration of the call, copying each distinct
+/// message once. The latter suits callers which read the messages from storage into a
+/// reused buffer.
+pub struct BatchVerifier<'a, H> {
+    domain: &'a [u8],
+    hash_to_g1: &'a H,
+    pubkeys: Vec<PublicKey>,
+    message_hashes: Vec<G1Projective>,
+    // message -> extra data -> hash
+    hashes: BTreeMap<Cow<'a, [u8]>, BTreeMap<Cow<'a, [u8]>, G1Projective>>,
+}
+
+impl<'a, H: HashToCurve<Output = G1Projective>> BatchVerifier<'a, H> {
+    /// Creates an empty batch for the provided messages domain
+    pub fn new(domain: &'a [u8], hash_to_g1: &'a H) -> Self {
+        Self {
+            domain,
+            hash_to_g1,
+            pubkeys: Vec::new(),
+            message_hashes: Vec::new(),
+            hashes: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a public key and the message it signed to the batch, borrowing the message until
+    /// the batch is verified. Fails without hashing if the message is longer than the hasher
+    /// accepts.
+    pub fn add(
+        &mut self,
+        pubkey: &PublicKey,
+        message: &'a [u8],
+        extra_data: &'a [u8],
+    ) -> Result<(), BLSError> {
+        self.add_entry(pubkey, Cow::Borrowed(message), Cow::Borrowed(extra_data))
+    }
+
+    /// Same as `add`, but the message is only borrowed for the duration of the call. It is
+    /// copied if it was not added before.
+    pub fn add_transient(
+        &mut self,
+        pubkey: &PublicKey,
+        message: &[u8],
+        extra_data: &[u8],
+    ) -> Result<(), BLSError> {
+        // checked before copying the message
+        self.check_length(self.len(), message, extra_data)?;
+        if let Some(hash) = self.cached_hash(message, extra_data) {
+            self.push(pubkey, hash);
+            return Ok(());
+        }
+        self.add_entry(
+            pubkey,
+            Cow::Owned(message.to_vec()),
+            Cow::Owned(extra_data.to_vec()),
+        )
+    }
+
+    fn add_entry(
+        &mut self,
+        pubkey: &PublicKey,
+        message: Cow<'a, [u8]>,
+        extra_data: Cow<'a, [u8]>,
+    ) -> Result<(), BLSError> {
+        self.check_length(self.len(), &message, &extra_data)?;
+        // Batches often contain the same message several times (e.g. the same block sealed
+        // at multiple rounds), so each distinct message is only hashed once
+        let hash = match self.cached_hash(&message, &extra_data) {
+            Some(hash) => hash,
+            None => {
+                let hash = self.hash_to_g1.hash(self.domain, &message, &extra_data)?;
+                self.hashes
+                    .entry(message)
+                    .or_default()
+                    .insert(extra_data, hash);
+                hash
+            }
+        };
+        self.push(pubkey, hash);
+        Ok(())
+    }
+
+    fn cached_hash(&self, message: &[u8], extra_data: &[u8]) -> Option<G1Projective> {
+        self.hashes
+            .get(message)
+            .and_then(|hashes| hashes.get(extra_data))
+            .copied()
+    }
+
+    fn push(&mut self, pubkey: &PublicKey, hash: G1Projective) {
+        self.pubkeys.push(pubkey.clone());
+        self.message_hashes.push(hash);
+    }
+
+    /// Fails if the message at the index of the batch is longer than the hasher accepts
+    fn check_length(
+        &self,
+        index: usize,
+        message: &[u8],
+        extra_data: &[u8],
+    ) -> Result<(), BLSError> {
+        if let Some(max) = self.hash_to_g1.max_input_length() {
+            let length = message.len() + extra_data.len();
+            if length > max {
+                return Err(BLSError::MessageTooLong { index, length, max });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of messages in the batch
+    pub fn len(&self) -> usize {
+        self.message_hashes.len()
+    }
+
+    /// Returns true if no messages were added
+    pub fn is_empty(&self) -> bool {
+        self.message_hashes.is_empty()
+    }
+
+    /// Verifies the aggregate signature over all the messages of the batch
+    pub fn verify(&self, signature: &Signature) -> Result<(), BLSError> {
+        signature.batch_verify_hashes(&self.pubkeys, &self.message_hashes)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "composite-hash")]
+    use crate::{
+        hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1,
+        hashers::composite::COMPOSITE_HASHER, PublicKeyCache,
+    };
     use crate::{
-        hash_to_curve::try_and_increment::{TryAndIncrement, COMPOSITE_HASH_TO_G1},
-        hashers::{composite::COMPOSITE_HASHER, DirectHasher, Hasher},
-        test_helpers::{keygen_batch, sign_batch, sum},
-        PrivateKey, PublicKeyCache, SIG_DOMAIN,
+        hash_to_curve::try_and_increment::{TryAndIncrement, DIRECT_HASH_TO_G1},
+        hashers::{DirectHasher, Hasher},
+        testing::{keygen_batch, sign_batch, sum},
+        PrivateKey, SIG_DOMAIN,
     };
+    #[cfg(feature = "composite-hash")]
+    use algebra::{CanonicalDeserialize, CanonicalSerialize};
 
     use algebra::{
-        bls12_377::{Bls12_377, G1Projective, G2Projective, Parameters},
+        bls12_377::{Bls12_377, Fq, Fq2, G1Projective, G2Projective, Parameters},
         curves::bls12::Bls12Parameters,
-        CanonicalDeserialize, CanonicalSerialize, UniformRand, Zero,
+        UniformRand, Zero,
     };
     use rand::{thread_rng, Rng};
 
     #[test]
+    #[cfg(feature = "composite-hash")]
     fn test_aggregated_sig() {
         let message = b"hello";
         let rng = &mut thread_rng();
@@ -201,6 +993,7 @@ mod tests {
     #[test]
     fn test_batch_verify() {
         test_batch_verify_with_hasher(&DirectHasher, false);
+        #[cfg(feature = "composite-hash")]
         test_batch_verify_with_hasher(&*COMPOSITE_HASHER, true);
     }
 
@@ -279,6 +1072,46 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(feature = "composite-hash")]
+    fn batch_verify_rejects_long_messages_before_hashing() {
+        let try_and_increment = &CountingHash {
+            inner: &*COMPOSITE_HASH_TO_G1,
+            hashed: Default::default(),
+        };
+        let max = try_and_increment.max_input_length().unwrap();
+        let pubkeys = (0..3)
+            .map(|_| PublicKey::from(G2Projective::rand(&mut thread_rng())))
+            .collect::<Vec<_>>();
+        let short = vec![0u8; 10];
+        let long = vec![0u8; max];
+        let messages = vec![
+            (&short[..], &short[..]),
+            (&long[..], &[][..]),
+            (&long[..], &short[..]),
+        ];
+
+        let asig = Signature::from(G1Projective::rand(&mut thread_rng()));
+        match asig.batch_verify(&pubkeys, SIG_DOMAIN, &messages, try_and_increment) {
+            Err(BLSError::MessageTooLong { index, length, .. }) => {
+                assert_eq!(index, 2);
+                assert_eq!(length, max + 10);
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+        // the valid messages before the long one were not hashed either
+        assert_eq!(try_and_increment.hashed.get(), 0);
+
+        // and neither are long messages added to a verifier
+        let mut verifier = BatchVerifier::new(SIG_DOMAIN, try_and_increment);
+        match verifier.add_transient(&pubkeys[0], &long, &short) {
+            Err(BLSError::MessageTooLong { index: 0, .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(verifier.is_empty());
+        assert_eq!(try_and_increment.hashed.get(), 0);
+    }
+
     #[test]
     fn batch_verify_hashes() {
         // generate 5 (aggregate sigs, message hash pairs)
@@ -312,9 +1145,475 @@ mod tests {
         let res = asig.batch_verify_hashes(&aggregate_pubkeys, &messages);
 
         assert!(res.is_ok());
+
+        let prepared = aggregate_pubkeys
+            .iter()
+            .map(PreparedPublicKey::from)
+            .collect::<Vec<_>>();
+        asig.batch_verify_hashes_prepared(&prepared, &messages)
+            .unwrap();
+        asig.batch_verify_hashes_prepared(&prepared[1..], &messages[1..])
+            .unwrap_err();
+    }
+
+    #[test]
+    fn prepared_neg_generator_verifies_custom_equations() {
+        let rng = &mut thread_rng();
+        let key = PrivateKey::generate(rng);
+        let hash = G1Projective::rand(rng);
+        let signature = key.sign_hashed(&hash);
+
+        // same as preparing the generator in place
+        let fresh: <Bls12_377 as PairingEngine>::G2Prepared =
+            G2Affine::prime_subgroup_generator().neg().into();
+        let point = signature.as_ref().into_affine();
+        assert_eq!(
+            signature_miller_loop(signature.as_ref()),
+            Bls12_377::miller_loop(&[(point.into(), fresh)])
+        );
+
+        // e(σ, -g2) * e(H(m), pk) == 1
+        let product = Bls12_377::product_of_pairings(&[
+            (point.into(), NEG_G2_GENERATOR_PREPARED.clone()),
+            (
+                hash.into_affine().into(),
+                key.to_public().as_ref().into_affine().into(),
+            ),
+        ]);
+        assert!(product.is_one());
+    }
+
+    #[test]
+    fn batch_verify_hashes_in_chunks() {
+        let rng = &mut thread_rng();
+        let num_messages = PAIRING_CHUNK_SIZE + 3;
+        let (secret_keys, public_keys) = keygen_batch::<Bls12_377>(num_messages, 1);
+        let pubkeys = public_keys
+            .iter()
+            .map(|pks| PublicKey::from(pks[0]))
+            .collect::<Vec<_>>();
+        let messages = (0..num_messages)
+            .map(|_| G1Projective::rand(rng))
+            .collect::<Vec<_>>();
+        let sigs = sign_batch::<Bls12_377>(&secret_keys, &messages);
+
+        // spans more than one chunk of Miller loops
+        let asig = Signature::from(sum(&sigs));
+        asig.batch_verify_hashes(&pubkeys, &messages).unwrap();
+        Signature::from(sum(&sigs[1..]))
+            .batch_verify_hashes(&pubkeys, &messages)
+            .unwrap_err();
+
+        // one aggregate signature per chunk of 100 messages, the second one is invalid
+        let mut chunk_sigs = sigs
+            .chunks(100)
+            .map(|sigs| Signature::from(sum(sigs)))
+            .collect::<Vec<_>>();
+        Signature::batch_verify_hashes_chunked(&chunk_sigs, &pubkeys, &messages, 100).unwrap();
+        chunk_sigs[1] = Signature::from(sum(&sigs[100..199]));
+        match Signature::batch_verify_hashes_chunked(&chunk_sigs, &pubkeys, &messages, 100) {
+            Err(BLSError::ChunkVerificationFailed(1)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match Signature::batch_verify_hashes_chunked(&chunk_sigs[1..], &pubkeys, &messages, 100) {
+            Err(BLSError::ChunkCountMismatch(2, 3)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // empty chunks are treated as chunks of a single message
+        let single_sigs = sigs[..3]
+            .iter()
+            .map(|sig| Signature::from(*sig))
+            .collect::<Vec<_>>();
+        Signature::batch_verify_hashes_chunked(&single_sigs, &pubkeys[..3], &messages[..3], 0)
+            .unwrap();
+    }
+
+    #[test]
+    fn aggregate_is_order_independent() {
+        let rng = &mut thread_rng();
+        let sigs = (0..10)
+            .map(|_| Signature::from(G1Projective::rand(rng)))
+            .collect::<Vec<_>>();
+        let mut reversed = sigs.clone();
+        reversed.reverse();
+
+        let asig = Signature::aggregate(&sigs);
+        let asig_reversed = Signature::aggregate(&reversed);
+        // the projective coordinates match exactly, not just the underlying points
+        assert_eq!(asig.0.x, asig_reversed.0.x);
+        assert_eq!(asig.0.y, asig_reversed.0.y);
+        assert_eq!(asig.0.z, asig_reversed.0.z);
+
+        assert_eq!(asig, Signature::aggregate_ordered(&sigs));
+    }
+
+    #[test]
+    fn subtract_removes_a_signer() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let message = b"hello";
+        let keys = (0..3)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let sigs = keys
+            .iter()
+            .map(|key| key.sign(&message[..], &[], hasher).unwrap())
+            .collect::<Vec<_>>();
+        let pubkeys = keys.iter().map(|key| key.to_public()).collect::<Vec<_>>();
+
+        let asig = Signature::aggregate(&sigs).subtract(&sigs[1]);
+        let apk = PublicKey::aggregate(&pubkeys).subtract(&pubkeys[1]);
+        assert_eq!(asig, Signature::aggregate(vec![&sigs[0], &sigs[2]]));
+        apk.verify(&message[..], &[], &asig, hasher).unwrap();
+
+        // subtracting a signature which was not aggregated is not detected
+        let removed_twice = asig.subtract(&sigs[1]);
+        apk.verify(&message[..], &[], &removed_twice, hasher)
+            .unwrap_err();
+    }
+
+    // Counts the messages hashed by the inner hasher
+    #[cfg(feature = "composite-hash")]
+    struct CountingHash<'a, H> {
+        inner: &'a H,
+        hashed: core::cell::Cell<usize>,
+    }
+
+    #[cfg(feature = "composite-hash")]
+    impl<'a, H: HashToCurve<Output = G1Projective>> HashToCurve for CountingHash<'a, H> {
+        type Output = G1Projective;
+
+        fn hash(
+            &self,
+            domain: &[u8],
+            message: &[u8],
+            extra_data: &[u8],
+        ) -> Result<G1Projective, BLSError> {
+            self.hashed.set(self.hashed.get() + 1);
+            self.inner.hash(domain, message, extra_data)
+        }
+
+        fn max_input_length(&self) -> Option<usize> {
+            self.inner.max_input_length()
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "composite-hash")]
+    fn batch_verify_hashes_duplicate_messages_once() {
+        let rng = &mut thread_rng();
+        let hasher = CountingHash {
+            inner: &*COMPOSITE_HASH_TO_G1,
+            hashed: Default::default(),
+        };
+        let msgs: [(&[u8], &[u8]); 5] = [
+            (b"block", b"round 0"),
+            (b"block", b"round 1"),
+            (b"block", b"round 0"),
+            (b"other", b""),
+            (b"block", b"round 0"),
+        ];
+
+        let keys = (0..msgs.len())
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let pubkeys = keys.iter().map(|k| k.to_public()).collect::<Vec<_>>();
+        let sigs = keys
+            .iter()
+            .zip(&msgs)
+            .map(|(key, (message, extra_data))| {
+                key.sign(message, extra_data, hasher.inner).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let asig = Signature::aggregate(&sigs);
+
+        asig.batch_verify(&pubkeys, SIG_DOMAIN, &msgs, &hasher)
+            .unwrap();
+        assert_eq!(hasher.hashed.get(), 3);
+
+        // same result as hashing each message separately
+        let hashes = msgs
+            .iter()
+            .map(|(message, extra_data)| {
+                hasher.inner.hash(SIG_DOMAIN, message, extra_data).unwrap()
+            })
+            .collect::<Vec<_>>();
+        asig.batch_verify_hashes(&pubkeys, &hashes).unwrap();
+
+        // keys swapped between distinct messages are still rejected
+        let mut swapped = pubkeys.clone();
+        swapped.swap(0, 1);
+        asig.batch_verify(&swapped, SIG_DOMAIN, &msgs, &hasher)
+            .unwrap_err();
     }
 
     #[test]
+    fn batch_verify_streams_messages() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let keys = (0..4)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let pubkeys = keys.iter().map(|k| k.to_public()).collect::<Vec<_>>();
+        let stored = (0..keys.len() as u8)
+            .map(|i| vec![i; 32])
+            .collect::<Vec<_>>();
+        let sigs = keys
+            .iter()
+            .zip(&stored)
+            .map(|(key, message)| key.sign(message, &[], hasher).unwrap())
+            .collect::<Vec<_>>();
+        let asig = Signature::aggregate(&sigs);
+
+        // pairs built on the fly, without collecting them
+        let messages = stored.iter().map(|m| (&m[..], &[][..]));
+        asig.batch_verify(&pubkeys, SIG_DOMAIN, messages, hasher)
+            .unwrap();
+        let messages = stored.iter().take(3).map(|m| (&m[..], &[][..]));
+        match asig.batch_verify(&pubkeys, SIG_DOMAIN, messages, hasher) {
+            Err(BLSError::UnevenNumKeysMessages) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // messages read into a reused buffer
+        let mut verifier = BatchVerifier::new(SIG_DOMAIN, hasher);
+        let mut buffer = vec![0u8; 32];
+        for (pubkey, message) in pubkeys.iter().zip(&stored) {
+            buffer.copy_from_slice(message);
+            verifier.add_transient(pubkey, &buffer, &[]).unwrap();
+        }
+        assert_eq!(verifier.len(), keys.len());
+        verifier.verify(&asig).unwrap();
+        verifier.verify(&sigs[0]).unwrap_err();
+
+        // and messages which outlive the verifier, which are borrowed
+        let mut verifier = BatchVerifier::new(SIG_DOMAIN, hasher);
+        for (pubkey, message) in pubkeys.iter().zip(&stored) {
+            verifier.add(pubkey, message, &[]).unwrap();
+        }
+        verifier.verify(&asig).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn pipelined_batch_verify() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        // spans several Miller loop chunks and fills the channel
+        let num_messages = PIPELINE_DEPTH + 3;
+        let keys = (0..num_messages)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let pubkeys = keys.iter().map(|k| k.to_public()).collect::<Vec<_>>();
+        let stored = (0..num_messages)
+            .map(|i| vec![i as u8; 32])
+            .collect::<Vec<_>>();
+        let messages = stored.iter().map(|m| (&m[..], &[][..])).collect::<Vec<_>>();
+        let asig = Signature::aggregate(
+            keys.iter()
+                .zip(&stored)
+                .map(|(key, message)| key.sign(message, &[], hasher).unwrap()),
+        );
+
+        asig.batch_verify_pipelined(&pubkeys, SIG_DOMAIN, &messages, hasher)
+            .unwrap();
+        asig.batch_verify(&pubkeys, SIG_DOMAIN, &messages, hasher)
+            .unwrap();
+
+        let mut swapped = pubkeys.clone();
+        swapped.swap(0, num_messages - 1);
+        match asig.batch_verify_pipelined(&swapped, SIG_DOMAIN, &messages, hasher) {
+            Err(BLSError::VerificationFailed) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match asig.batch_verify_pipelined(&pubkeys[1..], SIG_DOMAIN, &messages, hasher) {
+            Err(BLSError::UnevenNumKeysMessages) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_batch_verify() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let num_messages = 37;
+        let keys = (0..num_messages)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let pubkeys = keys.iter().map(|k| k.to_public()).collect::<Vec<_>>();
+        let stored = (0..num_messages)
+            .map(|i| vec![i as u8; 32])
+            .collect::<Vec<_>>();
+        let messages = stored.iter().map(|m| (&m[..], &[][..])).collect::<Vec<_>>();
+        let asig = Signature::aggregate(
+            keys.iter()
+                .zip(&stored)
+                .map(|(key, message)| key.sign(message, &[], hasher).unwrap()),
+        );
+
+        // chunks which divide the batch, leave a remainder, or span it
+        for chunk_size in &[0, 1, 5, 37, 100] {
+            asig.batch_verify_parallel(&pubkeys, SIG_DOMAIN, &messages, hasher, *chunk_size)
+                .unwrap();
+        }
+
+        let mut swapped = pubkeys.clone();
+        swapped.swap(0, num_messages - 1);
+        match asig.batch_verify_parallel(&swapped, SIG_DOMAIN, &messages, hasher, 8) {
+            Err(BLSError::VerificationFailed) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match asig.batch_verify_parallel(&pubkeys[1..], SIG_DOMAIN, &messages, hasher, 8) {
+            Err(BLSError::UnevenNumKeysMessages) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn verifies_independent_signatures() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let keys = (0..8)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let messages = (0..8u8).map(|i| vec![i; 5]).collect::<Vec<_>>();
+        let mut items = keys
+            .iter()
+            .zip(&messages)
+            .map(|(key, message)| {
+                let signature = key.sign(message, &[], hasher).unwrap();
+                (key.to_public(), &message[..], &[][..], signature)
+            })
+            .collect::<Vec<_>>();
+        Signature::verify_batch_independent(&items, SIG_DOMAIN, hasher).unwrap();
+        Signature::verify_batch_independent_with_bisection(&items, SIG_DOMAIN, hasher).unwrap();
+        Signature::verify_batch_independent(&[], SIG_DOMAIN, hasher).unwrap();
+
+        // two invalid signatures which cancel out in the plain aggregate
+        let delta = G1Projective::rand(rng);
+        items[2].3 = Signature(items[2].3 .0 + delta);
+        items[5].3 = Signature(items[5].3 .0 - delta);
+        let aggregate = Signature::aggregate(items.iter().map(|item| &item.3));
+        aggregate
+            .batch_verify(
+                &items.iter().map(|item| &item.0).collect::<Vec<_>>(),
+                SIG_DOMAIN,
+                items.iter().map(|item| (item.1, item.2)),
+                hasher,
+            )
+            .unwrap();
+
+        match Signature::verify_batch_independent(&items, SIG_DOMAIN, hasher) {
+            Err(BLSError::VerificationFailed) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match Signature::verify_batch_independent_with_bisection(&items, SIG_DOMAIN, hasher) {
+            Err(BLSError::InvalidSignatures(invalid)) => assert_eq!(invalid, vec![2, 5]),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // points outside the prime order subgroups are rejected before the combination
+        let outside_g1 = loop {
+            if let Some(point) = G1Affine::get_point_from_x(Fq::rand(rng), false) {
+                if !point.is_in_correct_subgroup_assuming_on_curve() {
+                    break point;
+                }
+            }
+        };
+        items[3].3 = Signature(items[3].3 .0 + outside_g1.into_projective());
+        match Signature::verify_batch_independent(&items, SIG_DOMAIN, hasher) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => {
+                assert_eq!(invalid, vec![(3, "signature is not in G1")])
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        let outside_g2 = loop {
+            if let Some(point) = G2Affine::get_point_from_x(Fq2::rand(rng), false) {
+                if !is_valid_g2(&point) {
+                    break point;
+                }
+            }
+        };
+        items[1].0 = PublicKey::from(outside_g2.into_projective());
+        match Signature::verify_batch_independent_with_bisection(&items, SIG_DOMAIN, hasher) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => assert_eq!(
+                invalid,
+                vec![
+                    (1, "public key is not in G2"),
+                    (3, "signature is not in G1")
+                ]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn aggregate_verify_requires_distinct_messages() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let keys = (0..3)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let pubkeys = keys.iter().map(|k| k.to_public()).collect::<Vec<_>>();
+        let sign = |messages: &[&[u8]]| {
+            let sigs = keys
+                .iter()
+                .zip(messages)
+                .map(|(key, message)| key.sign(message, &[], hasher).unwrap())
+                .collect::<Vec<_>>();
+            Signature::aggregate(&sigs)
+        };
+
+        let messages: [&[u8]; 3] = [b"a", b"b", b"c"];
+        let asig = sign(&messages);
+        asig.aggregate_verify(&pubkeys, &messages, hasher).unwrap();
+        let reordered: [&[u8]; 3] = [b"a", b"c", b"b"];
+        asig.aggregate_verify(&pubkeys, &reordered, hasher)
+            .unwrap_err();
+
+        // a valid aggregate over a repeated message is still rejected
+        let repeated: [&[u8]; 3] = [b"a", b"b", b"a"];
+        let asig = sign(&repeated);
+        asig.batch_verify(
+            &pubkeys,
+            SIG_DOMAIN,
+            repeated.iter().map(|m| (*m, &[][..])),
+            hasher,
+        )
+        .unwrap();
+        match asig.aggregate_verify(&pubkeys, &repeated, hasher) {
+            Err(BLSError::DuplicateMessage(2)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let identity = [pubkeys[0].clone(), PublicKey::from(G2Projective::zero())];
+        match asig.aggregate_verify(&identity, &messages[..2], hasher) {
+            Err(BLSError::InvalidPublicKey(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        Signature::from(G1Projective::zero())
+            .aggregate_verify::<_, PublicKey>(&[], &[], hasher)
+            .unwrap_err();
+
+        // a signature outside of the G1 subgroup
+        let outside_g1 = loop {
+            if let Some(point) = G1Affine::get_point_from_x(Fq::rand(rng), false) {
+                if !point.is_in_correct_subgroup_assuming_on_curve() {
+                    break point;
+                }
+            }
+        };
+        let asig = Signature(sign(&messages).0 + outside_g1.into_projective());
+        match asig.aggregate_verify(&pubkeys, &messages, hasher) {
+            Err(BLSError::InvalidSignature("not in the G1 subgroup")) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "composite-hash")]
     fn test_signature_serialization() {
         let try_and_increment = &*COMPOSITE_HASH_TO_G1;
         let rng = &mut thread_rng();
@@ -329,4 +1628,152 @@ mod tests {
             assert_eq!(sig, de);
         }
     }
+
+    // Compressed G1 points in the format the Go node exchanges with `bls-snark-sys`, taken
+    // from the hash to curve fixtures
+    const CELO_SIGNATURES: [&str; 2] = [
+        "a7e17c99126acf78536e64fffe88e1032d834b483584fe5757b1deafa493c97a132572c7825ca4f617f6bcef93b93980",
+        "21e328cfedb263f8c815131cc42f0357ab0ba903d855a11de6e7bcd7e61375a818d1b093bcf9fce224536714efad5c80",
+    ];
+
+    #[test]
+    fn celo_bytes_match_go_fixtures() {
+        for fixture in CELO_SIGNATURES.iter() {
+            let bytes = hex::decode(fixture).unwrap();
+            let signature = Signature::from_celo_bytes(&bytes).unwrap();
+            assert_eq!(signature.to_celo_bytes(), bytes);
+        }
+
+        let bytes = hex::decode(CELO_SIGNATURES[0]).unwrap();
+        for bytes in &[bytes[1..].to_vec(), [bytes.clone(), vec![0]].concat()] {
+            match Signature::from_celo_bytes(bytes) {
+                Err(BLSError::InvalidPoint {
+                    level: ValidationLevel::Subgroup,
+                    reason: "invalid length",
+                }) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn deserialization_checks_the_subgroup() {
+        let rng = &mut thread_rng();
+        let point = loop {
+            if let Some(point) = G1Affine::get_point_from_x(Fq::rand(rng), false) {
+                if !point.is_in_correct_subgroup_assuming_on_curve() {
+                    break point;
+                }
+            }
+        };
+        let mut bytes = vec![];
+        point.serialize(&mut bytes).unwrap();
+        match Signature::from_bytes(&bytes) {
+            Err(BLSError::InvalidPoint {
+                level: ValidationLevel::Subgroup,
+                ..
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        Signature::deserialize(&mut &bytes[..]).unwrap_err();
+        Signature::from_bytes_with_validation(&bytes, ValidationLevel::OnCurve).unwrap();
+        // an uncompressed point whose y coordinate was changed
+        let mut off_curve = vec![];
+        point.serialize_uncompressed(&mut off_curve).unwrap();
+        off_curve[G1Affine::SERIALIZED_SIZE] ^= 1;
+        match Signature::from_bytes_with_validation(&off_curve, ValidationLevel::OnCurve) {
+            Err(BLSError::InvalidPoint {
+                level: ValidationLevel::OnCurve,
+                ..
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(
+            Signature::from_bytes_unchecked(&bytes).unwrap(),
+            Signature::from(point.into_projective())
+        );
+
+        let bytes = hex::decode(CELO_SIGNATURES[0]).unwrap();
+        assert_eq!(
+            Signature::from_bytes(&bytes).unwrap(),
+            Signature::from_celo_bytes(&bytes).unwrap()
+        );
+
+        let mut entries = CELO_SIGNATURES
+            .iter()
+            .map(|fixture| hex::decode(fixture).unwrap())
+            .collect::<Vec<_>>();
+        let signatures = Signature::batch_from_bytes(&entries).unwrap();
+        assert_eq!(signatures.len(), 2);
+        let mut outside = vec![];
+        point.serialize(&mut outside).unwrap();
+        entries.push(outside);
+        entries.push(vec![0; 5]);
+        // every invalid entry is reported
+        match Signature::batch_from_bytes(&entries) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => assert_eq!(
+                invalid,
+                vec![
+                    (2, "not in the prime order subgroup"),
+                    (3, "invalid length")
+                ]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+        entries.remove(2);
+        match Signature::batch_from_bytes(&entries) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => {
+                assert_eq!(invalid, vec![(2, "invalid length")])
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_batches_of_compressed_signatures() {
+        let mut entries = CELO_SIGNATURES
+            .iter()
+            .map(|fixture| hex::decode(fixture).unwrap())
+            .collect::<Vec<_>>();
+        let signatures = Signature::batch_from_compressed(&entries).unwrap();
+        for (signature, bytes) in signatures.iter().zip(&entries) {
+            assert_eq!(&signature.to_celo_bytes(), bytes);
+        }
+        assert_eq!(
+            Signature::batch_from_compressed_with_validation(&entries, ValidationLevel::None)
+                .unwrap(),
+            signatures
+        );
+        assert!(Signature::batch_from_compressed::<Vec<u8>>(&[])
+            .unwrap()
+            .is_empty());
+
+        // not a point of the curve, with the infinity flag cleared
+        let mut invalid = entries.clone();
+        invalid[1] = vec![0xff; G1Affine::SERIALIZED_SIZE];
+        invalid[1][G1Affine::SERIALIZED_SIZE - 1] &= 0x3f;
+        match Signature::batch_from_compressed(&invalid) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => {
+                assert_eq!(invalid, vec![(1, "invalid x coordinate")])
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // lengths are reported first, even after an invalid point
+        invalid.push(vec![0; 3]);
+        match Signature::batch_from_compressed(&invalid) {
+            Err(BLSError::InvalidBatchEntries(entries)) => {
+                assert_eq!(entries, vec![(invalid.len() - 1, "invalid length")])
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        entries[0].pop();
+        match Signature::batch_from_compressed(&entries) {
+            Err(BLSError::InvalidBatchEntries(invalid)) => {
+                assert_eq!(invalid, vec![(0, "invalid length")])
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
 }
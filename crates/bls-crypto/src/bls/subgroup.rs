@@ -0,0 +1,251 @@
+//! Fast validity checks for G1 and G2 points, for use when importing untrusted signatures
+//! and public keys.
+//!
+//! Checking subgroup membership by multiplying by the group order costs a full 253-bit
+//! scalar multiplication per point. Instead, we use the untwist-Frobenius-twist
+//! endomorphism `psi`, which acts on the prime order subgroup of BLS12 curves as
+//! multiplication by the curve parameter `x`: a point on the twist is in G2 if and only if
+//! `psi(P) == [x]P` (Scott, https://eprint.iacr.org/2021/1130). As `x` is only 64 bits, this
+//! is several times faster. Likewise, the endomorphism `phi(x, y) = (beta * x, y)` of the
+//! curve, where `beta` is a cube root of unity, acts on G1 as multiplication by `-x^2`, and a
+//! point on the curve is in G1 if and only if `phi(P) == [-x^2]P`.
+#[cfg(test)]
+use algebra::fields::Fp12Parameters;
+use algebra::{
+    biginteger::BigInteger384,
+    bls12_377::{Fq, Fq2, G1Affine, G2Affine, Parameters},
+    curves::models::bls12::Bls12Parameters,
+    field_new,
+    fields::Fp6Parameters,
+    AffineCurve, BitIterator, Field, ProjectiveCurve, Zero,
+};
+
+type Fp6Params = <Parameters as Bls12Parameters>::Fp6Params;
+#[cfg(test)]
+type Fp12Params = <Parameters as Bls12Parameters>::Fp12Params;
+
+/// The coefficients `(c_x, c_y)` of `psi(x, y) = (c_x * x^p, c_y * y^p)`.
+///
+/// BLS12-377's twist is a D-type twist by the non-residue `xi = u`, so they are
+/// `xi^((p-1)/3)`, which is the first Frobenius coefficient of `Fq6`, and `xi^((p-1)/2)`,
+/// which is in `Fq` and is written in Montgomery form. They are constants so that builds
+/// without `std` do not compute them again for each call of `psi`.
+const PSI_COEFFICIENTS: (Fq2, Fq2) = (
+    Fp6Params::FROBENIUS_COEFF_FP6_C1[1],
+    field_new!(
+        Fq2,
+        field_new!(
+            Fq,
+            BigInteger384([
+                0x982c13d9d084771f,
+                0xfd49de0c6da34a32,
+                0x61a530d183ab0e53,
+                0xdf8fe44106dd9879,
+                0x40f29b58d88472bc,
+                0x158723199046d5d,
+            ])
+        ),
+        field_new!(Fq, BigInteger384([0, 0, 0, 0, 0, 0]))
+    ),
+);
+
+/// The coefficient `beta` of `phi(x, y) = (beta * x, y)`, the cube root of unity for which
+/// `phi` acts on G1 as multiplication by `-x^2` rather than by `x^2 - 1`, in Montgomery form
+const PHI_COEFFICIENT: Fq = field_new!(
+    Fq,
+    BigInteger384([
+        0x2c766f925a7b8727,
+        0x03d7f6b0253d58b5,
+        0x838ec0deec122131,
+        0xbd5eb3e9f658bb10,
+        0x6942bd126ed3e52e,
+        0x01673786dd04ed6a,
+    ])
+);
+
+/// Finds the coefficient of `phi` among the cube roots of unity, by checking the relation on
+/// the generator of G1
+#[cfg(test)]
+fn phi_coefficient() -> Fq {
+    // xi^((p^2-1)/3), which is in Fq
+    let omega = Fp6Params::FROBENIUS_COEFF_FP6_C1[2];
+    assert!(omega.c1.is_zero());
+    let omega = omega.c0;
+
+    let generator = G1Affine::prime_subgroup_generator();
+    let expected = (-mul_by_x(&mul_by_x(&generator.into_projective()))).into_affine();
+    *[omega, omega.square()]
+        .iter()
+        .find(|beta| phi_with(&generator, beta) == expected)
+        .expect("phi must act as multiplication by -x^2 on G1")
+}
+
+fn phi_with(point: &G1Affine, beta: &Fq) -> G1Affine {
+    if point.is_zero() {
+        return *point;
+    }
+    G1Affine::new(point.x * beta, point.y, false)
+}
+
+/// Finds the coefficients of `psi` among the powers of `xi` and their inverses, by checking
+/// the relation on the generator of G2
+#[cfg(test)]
+fn psi_coefficients() -> (Fq2, Fq2) {
+    // xi^((p-1)/3)
+    let w = Fp6Params::FROBENIUS_COEFF_FP6_C1[1];
+    // xi^((p-1)/6)^3 = xi^((p-1)/2)
+    let v = Fp12Params::FROBENIUS_COEFF_FP12_C1[1];
+    let v = v * &v * &v;
+
+    let generator = G2Affine::prime_subgroup_generator();
+    let expected = mul_by_x(&generator.into_projective()).into_affine();
+    let candidates = [
+        (w, v),
+        (w.inverse().unwrap(), v.inverse().unwrap()),
+        (w, v.inverse().unwrap()),
+        (w.inverse().unwrap(), v),
+    ];
+    *candidates
+        .iter()
+        .find(|(c_x, c_y)| psi_with(&generator, c_x, c_y) == expected)
+        .expect("psi must act as multiplication by x on G2")
+}
+
+fn psi_with(point: &G2Affine, c_x: &Fq2, c_y: &Fq2) -> G2Affine {
+    if point.is_zero() {
+        return *point;
+    }
+    let mut x = point.x;
+    x.frobenius_map(1);
+    let mut y = point.y;
+    y.frobenius_map(1);
+    G2Affine::new(x * c_x, y * c_y, false)
+}
+
+/// Multiplies the point by the BLS12 curve parameter `x`
+fn mul_by_x<C: ProjectiveCurve>(point: &C) -> C {
+    let mut res = C::zero();
+    for bit in BitIterator::new(Parameters::X) {
+        res.double_in_place();
+        if bit {
+            res += point;
+        }
+    }
+    if Parameters::X_IS_NEGATIVE {
+        -res
+    } else {
+        res
+    }
+}
+
+/// The untwist-Frobenius-twist endomorphism on the G2 twist
+pub fn psi(point: &G2Affine) -> G2Affine {
+    let (c_x, c_y) = &PSI_COEFFICIENTS;
+    psi_with(point, c_x, c_y)
+}
+
+/// The endomorphism `phi` on the G1 curve
+pub fn phi(point: &G1Affine) -> G1Affine {
+    phi_with(point, &PHI_COEFFICIENT)
+}
+
+/// Returns true if the point, which must be on the curve, is in the prime order subgroup G1.
+/// Equivalent to `is_in_correct_subgroup_assuming_on_curve`, but much faster.
+pub fn is_in_g1_subgroup(point: &G1Affine) -> bool {
+    let projective = point.into_projective();
+    phi(point).into_projective() == -mul_by_x(&mul_by_x(&projective))
+}
+
+/// Returns true if the point satisfies the equation of the G2 twist
+pub fn is_on_twist(point: &G2Affine) -> bool {
+    point.is_on_curve()
+}
+
+/// Returns true if the point, which must be on the twist, is in the prime order subgroup.
+/// Equivalent to `is_in_correct_subgroup_assuming_on_curve`, but much faster.
+pub fn is_in_g2_subgroup(point: &G2Affine) -> bool {
+    psi(point).into_projective() == mul_by_x(&point.into_projective())
+}
+
+/// Returns true if the point is on the twist and in the prime order subgroup
+pub fn is_valid_g2(point: &G2Affine) -> bool {
+    is_on_twist(point) && is_in_g2_subgroup(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{
+        bls12_377::{G1Projective, G2Projective},
+        UniformRand,
+    };
+
+    #[test]
+    fn psi_coefficients_are_the_constants() {
+        assert_eq!(psi_coefficients(), PSI_COEFFICIENTS);
+    }
+
+    #[test]
+    fn phi_coefficient_is_the_constant() {
+        assert_eq!(phi_coefficient(), PHI_COEFFICIENT);
+    }
+
+    #[test]
+    fn checks_g1_points() {
+        let rng = &mut rand::thread_rng();
+        assert!(is_in_g1_subgroup(&G1Affine::prime_subgroup_generator()));
+        assert!(is_in_g1_subgroup(&G1Affine::zero()));
+        for _ in 0..10 {
+            assert!(is_in_g1_subgroup(&G1Projective::rand(rng).into_affine()));
+        }
+        let mut found = 0;
+        while found < 10 {
+            // points on the curve which have not been multiplied by the cofactor
+            if let Some(point) = G1Affine::get_point_from_x(Fq::rand(rng), false) {
+                assert_eq!(
+                    is_in_g1_subgroup(&point),
+                    point.is_in_correct_subgroup_assuming_on_curve()
+                );
+                assert!(!is_in_g1_subgroup(&point));
+                found += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn accepts_subgroup_points() {
+        let rng = &mut rand::thread_rng();
+        assert!(is_valid_g2(&G2Affine::prime_subgroup_generator()));
+        assert!(is_valid_g2(&G2Affine::zero()));
+        for _ in 0..10 {
+            let point = G2Projective::rand(rng).into_affine();
+            assert!(is_valid_g2(&point));
+        }
+    }
+
+    #[test]
+    fn rejects_points_outside_subgroup() {
+        let rng = &mut rand::thread_rng();
+        let mut found = 0;
+        while found < 10 {
+            // points on the twist which have not been multiplied by the cofactor
+            if let Some(point) = G2Affine::get_point_from_x(Fq2::rand(rng), false) {
+                assert!(is_on_twist(&point));
+                assert_eq!(
+                    is_in_g2_subgroup(&point),
+                    point.is_in_correct_subgroup_assuming_on_curve()
+                );
+                assert!(!is_in_g2_subgroup(&point));
+                found += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_points_off_the_twist() {
+        let mut point = G2Affine::prime_subgroup_generator();
+        point.y.double_in_place();
+        assert!(!is_on_twist(&point));
+        assert!(!is_valid_g2(&point));
+    }
+}
@@ -0,0 +1,392 @@
+//! Validation levels for curve points read from untrusted sources.
+//!
+//! Decoding a point can check that it is on the curve, and that it is in the prime order
+//! subgroup. The subgroup check is by far the most expensive part of decoding, and points
+//! from a trusted source, e.g. keys which were validated before being stored, do not need
+//! it. Every decoding function which takes a `ValidationLevel` makes this tradeoff explicit
+//! at the call site, instead of leaving it to the choice of function.
+use super::subgroup::{is_in_g1_subgroup, is_in_g2_subgroup};
+use crate::{compression::PointCompression, BLSError, BlsResult, Vec};
+use algebra::{
+    bls12_377::{G1Affine, G2Affine, Parameters},
+    curves::models::{
+        bls12::Bls12Parameters, short_weierstrass_jacobian::GroupAffine, SWModelParameters,
+    },
+    io::Read,
+    CanonicalDeserialize, CanonicalDeserializeWithFlags, ConstantSerializedSize, SWFlags,
+    SerializationError, Zero,
+};
+use core::{convert::TryFrom, fmt};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Number of entries of a batch decoded together, and by one thread with the `parallel`
+/// feature
+const BATCH_CHUNK_SIZE: usize = 64;
+
+type G2Parameters = <Parameters as Bls12Parameters>::G2Parameters;
+
+/// The checks done on a curve point when it is decoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValidationLevel {
+    /// No checks, the point is used as it is decoded. Only use this for points from a
+    /// trusted source. Compressed points are still on the curve, as their y coordinate is
+    /// recovered from the curve equation, but uncompressed ones may not be.
+    None,
+    /// The point must be on the curve, but may be outside the prime order subgroup
+    OnCurve,
+    /// The point must be on the curve and in the prime order subgroup
+    Subgroup,
+}
+
+impl Default for ValidationLevel {
+    fn default() -> Self {
+        ValidationLevel::Subgroup
+    }
+}
+
+/// The integer representation used over FFI: 0 for `None`, 1 for `OnCurve` and 2 for
+/// `Subgroup`
+impl TryFrom<i32> for ValidationLevel {
+    type Error = BLSError;
+
+    fn try_from(level: i32) -> BlsResult<Self> {
+        match level {
+            0 => Ok(ValidationLevel::None),
+            1 => Ok(ValidationLevel::OnCurve),
+            2 => Ok(ValidationLevel::Subgroup),
+            _ => Err(BLSError::UnknownValidationLevel(level)),
+        }
+    }
+}
+
+impl From<ValidationLevel> for i32 {
+    fn from(level: ValidationLevel) -> i32 {
+        match level {
+            ValidationLevel::None => 0,
+            ValidationLevel::OnCurve => 1,
+            ValidationLevel::Subgroup => 2,
+        }
+    }
+}
+
+impl fmt::Display for ValidationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ValidationLevel::None => "none",
+            ValidationLevel::OnCurve => "on curve",
+            ValidationLevel::Subgroup => "subgroup",
+        };
+        f.write_str(name)
+    }
+}
+
+impl ValidationLevel {
+    /// Checks a G1 point at this level. The subgroup check uses the endomorphism-based
+    /// check of the `subgroup` module.
+    pub fn validate_g1(self, point: &G1Affine) -> BlsResult<()> {
+        self.check(point, is_in_g1_subgroup)
+            .map_err(|reason| BLSError::InvalidPoint {
+                level: self,
+                reason,
+            })
+    }
+
+    /// Checks a G2 point at this level. The subgroup check uses the endomorphism-based
+    /// check of the `subgroup` module.
+    pub fn validate_g2(self, point: &G2Affine) -> BlsResult<()> {
+        self.check(point, is_in_g2_subgroup)
+            .map_err(|reason| BLSError::InvalidPoint {
+                level: self,
+                reason,
+            })
+    }
+
+    /// Decodes a compressed or uncompressed G1 point, told apart by their length, and
+    /// checks it at this level
+    pub(crate) fn decode_g1(self, bytes: &[u8]) -> Result<G1Affine, &'static str> {
+        self.decode(bytes, is_in_g1_subgroup)
+    }
+
+    /// Decodes a compressed or uncompressed G2 point, told apart by their length, and
+    /// checks it at this level
+    pub(crate) fn decode_g2(self, bytes: &[u8]) -> Result<G2Affine, &'static str> {
+        self.decode(bytes, is_in_g2_subgroup)
+    }
+
+    /// Decodes a batch of compressed or uncompressed G1 points and checks them at this level.
+    /// Returns the point of each entry, or the reason it is invalid, see `collect_batch`.
+    pub(crate) fn batch_decode_g1<B: AsRef<[u8]> + Sync>(
+        self,
+        entries: &[B],
+    ) -> Vec<Result<G1Affine, &'static str>> {
+        batch_decode(entries, |chunk| {
+            chunk
+                .iter()
+                .map(|bytes| self.decode_g1(bytes.as_ref()))
+                .collect()
+        })
+    }
+
+    /// Decodes a batch of compressed or uncompressed G2 points and checks them at this level.
+    /// The square roots which recover the y coordinates of the compressed points of each
+    /// chunk share their field inversion, see `PointCompression::batch_points_from_x`.
+    /// Returns the point of each entry, or the reason it is invalid, see `collect_batch`.
+    pub(crate) fn batch_decode_g2<B: AsRef<[u8]> + Sync>(
+        self,
+        entries: &[B],
+    ) -> Vec<Result<G2Affine, &'static str>> {
+        batch_decode(entries, |chunk| {
+            let encoded = chunk
+                .iter()
+                .map(|bytes| parse::<G2Parameters>(bytes.as_ref()))
+                .collect::<Vec<_>>();
+            let compressed = encoded
+                .iter()
+                .filter_map(|encoded| match encoded {
+                    Ok(Encoded::Compressed(x, y_bit)) => Some((*x, *y_bit)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let mut decompressed = PointCompression::batch_points_from_x(&compressed).into_iter();
+            encoded
+                .into_iter()
+                .map(|encoded| {
+                    let point = match encoded? {
+                        Encoded::Point(point) => point,
+                        Encoded::Compressed(..) => {
+                            decompressed.next().flatten().ok_or("not on the curve")?
+                        }
+                    };
+                    self.check(&point, is_in_g2_subgroup)?;
+                    Ok(point)
+                })
+                .collect()
+        })
+    }
+
+    /// Reads a G1 point of `len` bytes, the length of its compressed or uncompressed
+    /// encoding, and checks it at this level
+    pub(crate) fn read_g1<R: Read>(
+        self,
+        reader: &mut R,
+        len: usize,
+    ) -> Result<G1Affine, SerializationError> {
+        self.decode_g1(&read_bytes(reader, len)?)
+            .map_err(|_| SerializationError::InvalidData)
+    }
+
+    /// Reads a G2 point of `len` bytes, the length of its compressed or uncompressed
+    /// encoding, and checks it at this level
+    pub(crate) fn read_g2<R: Read>(
+        self,
+        reader: &mut R,
+        len: usize,
+    ) -> Result<G2Affine, SerializationError> {
+        self.decode_g2(&read_bytes(reader, len)?)
+            .map_err(|_| SerializationError::InvalidData)
+    }
+
+    fn check<P: SWModelParameters>(
+        self,
+        point: &GroupAffine<P>,
+        in_subgroup: fn(&GroupAffine<P>) -> bool,
+    ) -> Result<(), &'static str> {
+        if self == ValidationLevel::None {
+            return Ok(());
+        }
+        if !point.is_on_curve() {
+            return Err("not on the curve");
+        }
+        if self == ValidationLevel::Subgroup && !in_subgroup(point) {
+            return Err("not in the prime order subgroup");
+        }
+        Ok(())
+    }
+
+    fn decode<P: SWModelParameters>(
+        self,
+        bytes: &[u8],
+        in_subgroup: fn(&GroupAffine<P>) -> bool,
+    ) -> Result<GroupAffine<P>, &'static str> {
+        let point = match parse(bytes)? {
+            Encoded::Point(point) => point,
+            Encoded::Compressed(x, y_bit) => {
+                PointCompression::point_from_x(x, y_bit).ok_or("not on the curve")?
+            }
+        };
+        self.check(&point, in_subgroup)?;
+        Ok(point)
+    }
+}
+
+// A point as it is encoded, before the y coordinate of a compressed point is recovered
+enum Encoded<P: SWModelParameters> {
+    Point(GroupAffine<P>),
+    Compressed(P::BaseField, bool),
+}
+
+// Parses a compressed or uncompressed point, told apart by their length
+fn parse<P: SWModelParameters>(bytes: &[u8]) -> Result<Encoded<P>, &'static str> {
+    let reader = &mut &bytes[..];
+    if bytes.len() == GroupAffine::<P>::SERIALIZED_SIZE {
+        let (x, flags): (P::BaseField, SWFlags) =
+            CanonicalDeserializeWithFlags::deserialize_with_flags(reader)
+                .map_err(|_| "invalid x coordinate")?;
+        if flags.is_infinity() {
+            Ok(Encoded::Point(GroupAffine::zero()))
+        } else {
+            Ok(Encoded::Compressed(x, flags.is_positive().unwrap_or(true)))
+        }
+    } else if bytes.len() == GroupAffine::<P>::UNCOMPRESSED_SIZE {
+        let x = P::BaseField::deserialize(reader).map_err(|_| "invalid x coordinate")?;
+        let (y, flags): (P::BaseField, SWFlags) =
+            CanonicalDeserializeWithFlags::deserialize_with_flags(reader)
+                .map_err(|_| "invalid y coordinate")?;
+        if flags.is_infinity() {
+            Ok(Encoded::Point(GroupAffine::zero()))
+        } else {
+            Ok(Encoded::Point(GroupAffine::new(x, y, false)))
+        }
+    } else {
+        Err("invalid length")
+    }
+}
+
+/// Collects the results of the entries of a batch. Fails with the index of every invalid
+/// entry and the reason it is invalid in `BLSError::InvalidBatchEntries`.
+pub(crate) fn collect_batch<P>(
+    results: impl IntoIterator<Item = Result<P, &'static str>>,
+) -> BlsResult<Vec<P>> {
+    let mut values = Vec::new();
+    let mut invalid = Vec::new();
+    for (index, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(value) => values.push(value),
+            Err(reason) => invalid.push((index, reason)),
+        }
+    }
+    if !invalid.is_empty() {
+        return Err(BLSError::InvalidBatchEntries(invalid));
+    }
+    Ok(values)
+}
+
+// Decodes the entries chunk by chunk, in parallel with the `parallel` feature
+fn batch_decode<B, P, F>(entries: &[B], decode_chunk: F) -> Vec<Result<P, &'static str>>
+where
+    B: Sync,
+    P: Send,
+    F: Fn(&[B]) -> Vec<Result<P, &'static str>> + Sync,
+{
+    #[cfg(feature = "parallel")]
+    let chunks = entries.par_chunks(BATCH_CHUNK_SIZE);
+    #[cfg(not(feature = "parallel"))]
+    let chunks = entries.chunks(BATCH_CHUNK_SIZE);
+    chunks.flat_map(|chunk| decode_chunk(chunk)).collect()
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>, SerializationError> {
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{
+        bls12_377::{Fq, Fq2, G2Projective},
+        CanonicalSerialize, ProjectiveCurve, UniformRand,
+    };
+
+    // a point on the G2 twist which was not multiplied by the cofactor
+    fn g2_outside_subgroup() -> G2Affine {
+        let rng = &mut rand::thread_rng();
+        loop {
+            if let Some(point) = G2Affine::get_point_from_x(Fq2::rand(rng), false) {
+                if !is_in_g2_subgroup(&point) {
+                    return point;
+                }
+            }
+        }
+    }
+
+    fn compressed<T: CanonicalSerialize>(point: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        point.serialize(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn uncompressed<T: CanonicalSerialize>(point: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        point.serialize_uncompressed(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn converts_levels_to_and_from_ints() {
+        for level in &[
+            ValidationLevel::None,
+            ValidationLevel::OnCurve,
+            ValidationLevel::Subgroup,
+        ] {
+            assert_eq!(
+                ValidationLevel::try_from(i32::from(*level)).unwrap(),
+                *level
+            );
+        }
+        match ValidationLevel::try_from(3) {
+            Err(BLSError::UnknownValidationLevel(3)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(ValidationLevel::default(), ValidationLevel::Subgroup);
+    }
+
+    #[test]
+    fn decodes_both_encodings() {
+        let rng = &mut rand::thread_rng();
+        let point = G2Projective::rand(rng).into_affine();
+        for bytes in &[compressed(&point), uncompressed(&point)] {
+            assert_eq!(ValidationLevel::Subgroup.decode_g2(bytes).unwrap(), point);
+        }
+        let zero = G2Affine::zero();
+        assert_eq!(
+            ValidationLevel::Subgroup
+                .decode_g2(&compressed(&zero))
+                .unwrap(),
+            zero
+        );
+
+        let point = G1Affine::prime_subgroup_generator();
+        for bytes in &[compressed(&point), uncompressed(&point)] {
+            assert_eq!(ValidationLevel::Subgroup.decode_g1(bytes).unwrap(), point);
+        }
+        ValidationLevel::None
+            .decode_g1(&compressed(&point)[1..])
+            .unwrap_err();
+    }
+
+    #[test]
+    fn checks_at_each_level() {
+        let outside = g2_outside_subgroup();
+        let bytes = compressed(&outside);
+        assert_eq!(ValidationLevel::None.decode_g2(&bytes).unwrap(), outside);
+        assert_eq!(ValidationLevel::OnCurve.decode_g2(&bytes).unwrap(), outside);
+        ValidationLevel::Subgroup.decode_g2(&bytes).unwrap_err();
+        match ValidationLevel::Subgroup.validate_g2(&outside) {
+            Err(BLSError::InvalidPoint {
+                level: ValidationLevel::Subgroup,
+                ..
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // uncompressed coordinates which are not on the curve
+        let off_curve = G1Affine::new(Fq::from(1u64), Fq::from(1u64), false);
+        let bytes = uncompressed(&off_curve);
+        assert_eq!(ValidationLevel::None.decode_g1(&bytes).unwrap(), off_curve);
+        ValidationLevel::OnCurve.decode_g1(&bytes).unwrap_err();
+        ValidationLevel::Subgroup.decode_g1(&bytes).unwrap_err();
+    }
+}
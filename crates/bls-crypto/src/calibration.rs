@@ -0,0 +1,269 @@
+//! Calibration of batch sizes for the host.
+//!
+//! The defaults of `BatchConfig` were measured on servers. On mobile and embedded hosts a
+//! pairing can be an order of magnitude slower, so batches which verify in a few
+//! milliseconds on a server may stall the caller. `calibrate` times a few Miller loops,
+//! final exponentiations and hashes on the host and derives a config from them, which can be
+//! stored and reused instead of calibrating on every start.
+//!
+//! The timing probes require the `threads` feature, since they also time the pipelined
+//! verification and read the system clock, which `wasm32-unknown-unknown` does not provide.
+use crate::bls::PAIRING_CHUNK_SIZE;
+#[cfg(feature = "threads")]
+use crate::{
+    bls::{is_one, prepare_pair, signature_miller_loop},
+    hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1,
+    HashToCurve, PublicKey, Signature, SIG_DOMAIN,
+};
+
+#[cfg(feature = "threads")]
+use algebra::{
+    bls12_377::{Bls12_377, G1Projective, G2Projective},
+    PairingEngine, UniformRand,
+};
+use core::time::Duration;
+#[cfg(feature = "threads")]
+use std::time::Instant;
+
+/// The latency targeted by `calibrate`
+pub const DEFAULT_TARGET_LATENCY: Duration = Duration::from_millis(100);
+
+/// The number of pairs of each Miller loop which are timed by the calibration
+#[cfg(feature = "threads")]
+const CHUNK_SIZE_CANDIDATES: [usize; 4] = [8, 32, 128, PAIRING_CHUNK_SIZE];
+
+/// A Miller loop size is picked if its cost per pair is within this many percent of the
+/// cheapest one, since smaller chunks use less memory
+#[cfg(feature = "threads")]
+const CHUNK_SIZE_TOLERANCE_PERCENT: u32 = 10;
+
+/// The number of messages verified to compare the sequential and pipelined verification
+const PIPELINE_PROBE_SIZE: usize = 32;
+
+/// The number of hashes which are timed
+#[cfg(feature = "threads")]
+const HASH_PROBES: u32 = 8;
+
+/// Batch sizes and thresholds used by batch verification and aggregation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchConfig {
+    /// The number of pairs prepared for each Miller loop, see
+    /// `Signature::batch_verify_hashes_with_config`
+    pub pairing_chunk_size: usize,
+    /// The largest number of signatures which should be batch verified, or aggregated for
+    /// verification with `Signature::batch_verify_hashes_chunked`, at once
+    pub max_batch_size: usize,
+    /// The batch size from which `Signature::batch_verify_pipelined` is faster than
+    /// `Signature::batch_verify`, if it is on this host
+    pub pipeline_threshold: Option<usize>,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            pairing_chunk_size: PAIRING_CHUNK_SIZE,
+            max_batch_size: 1024,
+            pipeline_threshold: Some(PIPELINE_PROBE_SIZE),
+        }
+    }
+}
+
+impl BatchConfig {
+    /// Returns true if a batch of `batch_size` signatures should use the pipelined
+    /// verification
+    pub fn should_pipeline(&self, batch_size: usize) -> bool {
+        self.pipeline_threshold
+            .map_or(false, |threshold| batch_size >= threshold)
+    }
+
+    /// Returns the number of batches of at most `max_batch_size` signatures which
+    /// `num_signatures` signatures should be split in
+    pub fn num_batches(&self, num_signatures: usize) -> usize {
+        let max = self.max_batch_size.max(1);
+        (num_signatures + max - 1) / max
+    }
+}
+
+/// The timings measured by the calibration
+#[cfg(feature = "threads")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Measurements {
+    /// The cost of preparing a pair and its share of a Miller loop, with the chosen chunk size
+    pub per_pair: Duration,
+    /// The cost of the final exponentiation, paid once per batch
+    pub final_exponentiation: Duration,
+    /// The cost of hashing a message to G1 with the direct hasher
+    pub hash: Duration,
+    /// The time of the sequential verification of the probe batch
+    pub sequential: Duration,
+    /// The time of the pipelined verification of the probe batch
+    pub pipelined: Duration,
+}
+
+/// Calibrates the batch sizes for `DEFAULT_TARGET_LATENCY`
+#[cfg(feature = "threads")]
+pub fn calibrate() -> BatchConfig {
+    calibrate_for(DEFAULT_TARGET_LATENCY).0
+}
+
+/// Runs the timing probes on the host and returns the config for batches which verify
+/// within `target_latency`, along with the measurements it was derived from. On a server
+/// this takes a few hundred milliseconds.
+#[cfg(feature = "threads")]
+pub fn calibrate_for(target_latency: Duration) -> (BatchConfig, Measurements) {
+    let rng = &mut rand::thread_rng();
+    let point = G1Projective::rand(rng);
+    let pubkey = PublicKey::from(G2Projective::rand(rng));
+
+    // the cost of the Miller loop does not depend on the points, so the same pair is reused
+    let per_pair_costs = CHUNK_SIZE_CANDIDATES
+        .iter()
+        .map(|&chunk_size| {
+            let elapsed = time(|| {
+                let pairs = (0..chunk_size)
+                    .map(|_| prepare_pair(&point, &pubkey))
+                    .collect::<Vec<_>>();
+                Bls12_377::miller_loop(&pairs)
+            });
+            (chunk_size, elapsed / chunk_size as u32)
+        })
+        .collect::<Vec<_>>();
+    let cheapest = per_pair_costs
+        .iter()
+        .map(|(_, cost)| *cost)
+        .min()
+        .expect("there are candidates");
+    let tolerance = cheapest * (100 + CHUNK_SIZE_TOLERANCE_PERCENT) / 100;
+    let (pairing_chunk_size, per_pair) = *per_pair_costs
+        .iter()
+        .find(|(_, cost)| *cost <= tolerance)
+        .expect("the cheapest candidate is within the tolerance");
+
+    let product = signature_miller_loop(&point);
+    let final_exponentiation = time(|| is_one(&product));
+
+    let hash = time(|| {
+        for i in 0..HASH_PROBES {
+            let _ = DIRECT_HASH_TO_G1.hash(SIG_DOMAIN, &i.to_le_bytes(), &[]);
+        }
+    }) / HASH_PROBES;
+
+    let (sequential, pipelined) = time_pipelining(rng);
+
+    let per_signature = (per_pair + hash).max(Duration::from_nanos(1));
+    let budget = target_latency
+        .checked_sub(final_exponentiation)
+        .unwrap_or_default();
+    let max_batch_size = ((budget.as_nanos() / per_signature.as_nanos()) as usize).max(1);
+    // pipelining must win clearly, since its benefit depends on the other load of the host
+    let pipeline_threshold = if pipelined * 10 < sequential * 9 {
+        Some(PIPELINE_PROBE_SIZE)
+    } else {
+        None
+    };
+
+    let config = BatchConfig {
+        pairing_chunk_size,
+        max_batch_size,
+        pipeline_threshold,
+    };
+    let measurements = Measurements {
+        per_pair,
+        final_exponentiation,
+        hash,
+        sequential,
+        pipelined,
+    };
+    (config, measurements)
+}
+
+// Verifies a probe batch sequentially and pipelined, and returns both timings. Whether the
+// signature is valid does not change the work done.
+#[cfg(feature = "threads")]
+fn time_pipelining<R: rand::Rng>(rng: &mut R) -> (Duration, Duration) {
+    let pubkeys = vec![PublicKey::from(G2Projective::rand(rng)); PIPELINE_PROBE_SIZE];
+    let bodies = (0..PIPELINE_PROBE_SIZE as u64)
+        .map(|i| i.to_le_bytes())
+        .collect::<Vec<_>>();
+    let messages = bodies
+        .iter()
+        .map(|body| (&body[..], &[][..]))
+        .collect::<Vec<_>>();
+    let signature = Signature::from(G1Projective::rand(rng));
+
+    let sequential =
+        time(|| signature.batch_verify(&pubkeys, SIG_DOMAIN, &messages, &*DIRECT_HASH_TO_G1));
+    let pipelined = time(|| {
+        signature.batch_verify_pipelined(&pubkeys, SIG_DOMAIN, &messages, &*DIRECT_HASH_TO_G1)
+    });
+    (sequential, pipelined)
+}
+
+#[cfg(feature = "threads")]
+fn time<T>(f: impl FnOnce() -> T) -> Duration {
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    drop(result);
+    elapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrivateKey;
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn calibration_is_consistent() {
+        let (config, measurements) = calibrate_for(Duration::from_secs(1));
+        assert!(CHUNK_SIZE_CANDIDATES.contains(&config.pairing_chunk_size));
+        assert!(config.max_batch_size >= 1);
+        assert!(measurements.per_pair > Duration::from_nanos(0));
+
+        // a tighter target cannot allow larger batches
+        let (tight, _) = calibrate_for(Duration::from_micros(10));
+        assert_eq!(tight.max_batch_size, 1);
+    }
+
+    #[test]
+    fn configured_verification() {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..5)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let hashes = (0..5).map(|_| G1Projective::rand(rng)).collect::<Vec<_>>();
+        let signature = Signature::aggregate(
+            keys.iter()
+                .zip(&hashes)
+                .map(|(key, hash)| key.sign_hashed(hash)),
+        );
+        let pubkeys = keys.iter().map(|key| key.to_public()).collect::<Vec<_>>();
+
+        let config = BatchConfig {
+            pairing_chunk_size: 2,
+            ..BatchConfig::default()
+        };
+        signature
+            .batch_verify_hashes_with_config(&pubkeys, &hashes, &config)
+            .unwrap();
+        signature
+            .batch_verify_hashes_with_config(&pubkeys[1..], &hashes[1..], &config)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn splits_batches() {
+        let config = BatchConfig {
+            max_batch_size: 10,
+            pipeline_threshold: None,
+            ..BatchConfig::default()
+        };
+        assert_eq!(config.num_batches(0), 0);
+        assert_eq!(config.num_batches(10), 1);
+        assert_eq!(config.num_batches(11), 2);
+        assert!(!config.should_pipeline(1000));
+        assert!(BatchConfig::default().should_pipeline(PIPELINE_PROBE_SIZE));
+    }
+}
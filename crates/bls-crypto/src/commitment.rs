@@ -0,0 +1,312 @@
+//! Commitments to validator sets, for verifiers which do not store the validators' keys.
+//!
+//! The commitment is a Merkle root over the validators' compressed public keys, bound to the
+//! number of validators. The tree's leaves are padded with empty leaves up to the next power
+//! of two. A signer proves its membership with the authentication path of its leaf, so a
+//! verifier which only holds the 32 byte commitment can aggregate the signers' keys and check
+//! a seal natively.
+use crate::{BLSError, BlsResult, HashToCurve, PublicKey, Signature};
+
+use algebra::{bls12_377::G1Projective, CanonicalSerialize};
+
+/// Length of the commitment and of the tree's node hashes
+pub const COMMITMENT_LENGTH: usize = 32;
+
+/// A commitment to a validator set, or a node of its Merkle tree
+pub type Commitment = [u8; COMMITMENT_LENGTH];
+
+const LEAF_PERSONALIZATION: &[u8] = b"ULvsleaf";
+const NODE_PERSONALIZATION: &[u8] = b"ULvsnode";
+const ROOT_PERSONALIZATION: &[u8] = b"ULvsroot";
+
+/// The Merkle tree over a validator set's public keys
+#[derive(Clone, Debug)]
+pub struct ValidatorSetCommitment {
+    // the leaves first, the root last
+    levels: Vec<Vec<Commitment>>,
+    public_keys: Vec<PublicKey>,
+}
+
+/// Proves that a public key is the leaf of a validator set commitment
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleProof {
+    /// The validator's public key
+    pub public_key: PublicKey,
+    /// The sibling hashes from the leaf up to, excluding, the root
+    pub siblings: Vec<Commitment>,
+}
+
+impl ValidatorSetCommitment {
+    /// Builds the tree over the validators' public keys, in the order of the validator set
+    pub fn new(public_keys: &[PublicKey]) -> BlsResult<Self> {
+        let depth = depth(public_keys.len());
+        let mut leaves = public_keys
+            .iter()
+            .map(hash_leaf)
+            .collect::<BlsResult<Vec<_>>>()?;
+        leaves.resize(1 << depth, [0u8; COMMITMENT_LENGTH]);
+
+        let mut levels = vec![leaves];
+        for _ in 0..depth {
+            let level = levels
+                .last()
+                .expect("there is always at least one level")
+                .chunks(2)
+                .map(|pair| hash_node(&pair[0], &pair[1]))
+                .collect();
+            levels.push(level);
+        }
+
+        Ok(Self {
+            levels,
+            public_keys: public_keys.to_vec(),
+        })
+    }
+
+    /// Returns the commitment, which binds the tree's root to the number of validators
+    pub fn commitment(&self) -> Commitment {
+        hash_root(
+            self.public_keys.len(),
+            &self.levels[self.levels.len() - 1][0],
+        )
+    }
+
+    /// Returns the proof of membership of the validator at the provided index, or `None` if
+    /// the index is out of bounds
+    pub fn prove(&self, index: usize) -> Option<MerkleProof> {
+        let public_key = self.public_keys.get(index)?;
+        let siblings = self.levels[..self.levels.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(height, level)| level[(index >> height) ^ 1])
+            .collect();
+        Some(MerkleProof {
+            public_key: public_key.clone(),
+            siblings,
+        })
+    }
+}
+
+impl MerkleProof {
+    /// Verifies that the proof's key is the validator at `index` in a set of `num_validators`
+    /// validators with the provided commitment
+    pub fn verify(
+        &self,
+        commitment: &Commitment,
+        index: usize,
+        num_validators: usize,
+    ) -> BlsResult<()> {
+        if index >= num_validators || self.siblings.len() != depth(num_validators) {
+            return Err(BLSError::InvalidMerkleProof(index));
+        }
+        let root = self.siblings.iter().enumerate().try_fold(
+            hash_leaf(&self.public_key)?,
+            |node, (height, sibling)| {
+                let node = if (index >> height) & 1 == 0 {
+                    hash_node(&node, sibling)
+                } else {
+                    hash_node(sibling, &node)
+                };
+                Ok::<_, BLSError>(node)
+            },
+        )?;
+        if &hash_root(num_validators, &root) != commitment {
+            return Err(BLSError::InvalidMerkleProof(index));
+        }
+        Ok(())
+    }
+}
+
+/// Verifies a signature by the validators which are set in the bitmap, whose keys are proven
+/// against the validator set commitment. `merkle_proofs` holds one proof per signer, in the
+/// order of the bitmap. The bitmap's length is the number of validators in the set.
+///
+/// This only checks that the signers' aggregate signature is valid, and that there is at least
+/// one signer. Callers must still check that the number of signers reaches the required
+/// quorum.
+pub fn verify_against_commitment<H: HashToCurve<Output = G1Projective>>(
+    commitment: &Commitment,
+    merkle_proofs: &[MerkleProof],
+    bitmap: &[bool],
+    message: &[u8],
+    extra_data: &[u8],
+    signature: &Signature,
+    hash_to_g1: &H,
+) -> BlsResult<()> {
+    let signers = bitmap
+        .iter()
+        .enumerate()
+        .filter(|(_, signed)| **signed)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    if signers.len() != merkle_proofs.len() {
+        return Err(BLSError::SignerCountMismatch(
+            merkle_proofs.len(),
+            signers.len(),
+        ));
+    }
+    // an empty set of signers would verify the identity as its aggregate signature
+    if signers.is_empty() {
+        return Err(BLSError::NoQuorum {
+            signers: 0,
+            quorum: 1,
+        });
+    }
+
+    for (index, proof) in signers.into_iter().zip(merkle_proofs) {
+        proof.verify(commitment, index, bitmap.len())?;
+    }
+
+    let aggregate = PublicKey::aggregate(merkle_proofs.iter().map(|proof| &proof.public_key));
+    aggregate.verify(message, extra_data, signature, hash_to_g1)
+}
+
+// The depth of the tree over `num_validators` leaves
+fn depth(num_validators: usize) -> usize {
+    num_validators.next_power_of_two().trailing_zeros() as usize
+}
+
+fn hash_leaf(public_key: &PublicKey) -> BlsResult<Commitment> {
+    let mut bytes = vec![];
+    public_key.serialize(&mut bytes)?;
+    Ok(hash(LEAF_PERSONALIZATION, &[&bytes[..]]))
+}
+
+fn hash_node(left: &Commitment, right: &Commitment) -> Commitment {
+    hash(NODE_PERSONALIZATION, &[&left[..], &right[..]])
+}
+
+fn hash_root(num_validators: usize, root: &Commitment) -> Commitment {
+    hash(
+        ROOT_PERSONALIZATION,
+        &[&(num_validators as u64).to_le_bytes()[..], &root[..]],
+    )
+}
+
+fn hash(personalization: &[u8], inputs: &[&[u8]]) -> Commitment {
+    let mut state = blake2s_simd::Params::new()
+        .hash_length(COMMITMENT_LENGTH)
+        .personal(personalization)
+        .to_state();
+    for input in inputs {
+        state.update(input);
+    }
+    let mut out = [0u8; COMMITMENT_LENGTH];
+    out.copy_from_slice(state.finalize().as_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, PrivateKey};
+    use algebra::Zero;
+
+    fn setup(num_validators: usize, bitmap: &[bool]) -> (Commitment, Vec<MerkleProof>, Signature) {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..num_validators)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let public_keys = keys.iter().map(|k| k.to_public()).collect::<Vec<_>>();
+        let tree = ValidatorSetCommitment::new(&public_keys).unwrap();
+
+        let mut proofs = vec![];
+        let mut signatures = vec![];
+        for (index, signed) in bitmap.iter().enumerate() {
+            if *signed {
+                proofs.push(tree.prove(index).unwrap());
+                signatures.push(keys[index].sign(b"seal", &[], &*DIRECT_HASH_TO_G1).unwrap());
+            }
+        }
+        (tree.commitment(), proofs, Signature::aggregate(&signatures))
+    }
+
+    #[test]
+    fn verifies_signers_against_commitment() {
+        let hasher = &*DIRECT_HASH_TO_G1;
+        for num_validators in 1..=6 {
+            let bitmap = (0..num_validators).map(|i| i % 3 != 1).collect::<Vec<_>>();
+            let (commitment, proofs, signature) = setup(num_validators, &bitmap);
+            verify_against_commitment(
+                &commitment,
+                &proofs,
+                &bitmap,
+                b"seal",
+                &[],
+                &signature,
+                hasher,
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_positions_and_sizes() {
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let bitmap = [true, false, true, false, true];
+        let (commitment, proofs, signature) = setup(5, &bitmap);
+
+        // a proof used for another position
+        match verify_against_commitment(
+            &commitment,
+            &proofs,
+            &[false, true, true, false, true],
+            b"seal",
+            &[],
+            &signature,
+            hasher,
+        ) {
+            Err(BLSError::InvalidMerkleProof(1)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // the commitment binds the number of validators
+        proofs[0].verify(&commitment, 0, 6).unwrap_err();
+        proofs[0].verify(&commitment, 0, 5).unwrap();
+
+        // a missing proof
+        verify_against_commitment(
+            &commitment,
+            &proofs[1..],
+            &bitmap,
+            b"seal",
+            &[],
+            &signature,
+            hasher,
+        )
+        .unwrap_err();
+
+        // a proof for a key outside of the set
+        let mut forged = proofs.clone();
+        forged[0].public_key = PrivateKey::generate(&mut rand::thread_rng()).to_public();
+        match verify_against_commitment(
+            &commitment,
+            &forged,
+            &bitmap,
+            b"seal",
+            &[],
+            &signature,
+            hasher,
+        ) {
+            Err(BLSError::InvalidMerkleProof(0)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // no signers and the identity signature, which would verify any message
+        match verify_against_commitment(
+            &commitment,
+            &[],
+            &[false; 5],
+            b"forged",
+            &[],
+            &Signature::from(G1Projective::zero()),
+            hasher,
+        ) {
+            Err(BLSError::NoQuorum {
+                signers: 0,
+                quorum: 1,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
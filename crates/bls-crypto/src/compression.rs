@@ -0,0 +1,185 @@
+//! The convention which selects the sign of the y coordinate of compressed points.
+//!
+//! A compressed point holds its x coordinate and a bit telling which of the two matching y
+//! coordinates it has: the bit is set if y is greater than -y. Elements of a prime field are
+//! compared as integers, so the bit of y is set if y > (p-1)/2. Elements of Fp2 are compared
+//! lexicographically with c1 first, so the bit is set if c1 > (p-1)/2, or if c1 is zero and
+//! c0 > (p-1)/2. This is the order of Zexe's fields, which its point serialization uses.
+//!
+//! `PointCompression` is the single place which implements the convention. The serialization
+//! profiles, the validated decoding, the epoch encoding and the `YToBitGadget` of
+//! `bls-gadgets`, which can only compare individual prime field elements, all go through it.
+use crate::Vec;
+use algebra::{
+    curves::models::{short_weierstrass_jacobian::GroupAffine, SWModelParameters},
+    fields::{batch_inversion, Fp2, Fp2Parameters},
+    Field, One, PrimeField, Zero,
+};
+
+/// The point compression convention, see the module's documentation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PointCompression;
+
+impl PointCompression {
+    /// Returns the bit of the y coordinate in any field, i.e. whether y is greater than -y
+    pub fn y_bit<F: Field>(y: &F) -> bool {
+        *y > -*y
+    }
+
+    /// Returns the bit of the point's y coordinate. The point at infinity has no y
+    /// coordinate, and its bit is unset.
+    pub fn point_bit<P: SWModelParameters>(point: &GroupAffine<P>) -> bool {
+        !point.is_zero() && Self::y_bit(&point.y)
+    }
+
+    /// Same as `y_bit`, by comparing the element with (p-1)/2
+    pub fn fp_y_bit<F: PrimeField>(y: &F) -> bool {
+        y.into_repr() > F::modulus_minus_one_div_two()
+    }
+
+    /// Same as `y_bit`, by comparing the coefficients with (p-1)/2. c0 decides only if c1 is
+    /// zero, as then -y has the same c1 as y.
+    pub fn fp2_y_bit<P: Fp2Parameters>(y: &Fp2<P>) -> bool {
+        if y.c1.is_zero() {
+            Self::fp_y_bit(&y.c0)
+        } else {
+            Self::fp_y_bit(&y.c1)
+        }
+    }
+
+    /// Returns the point with the x coordinate whose y coordinate has the bit, or `None` if x
+    /// is not the coordinate of a point on the curve
+    pub fn point_from_x<P: SWModelParameters>(
+        x: P::BaseField,
+        y_bit: bool,
+    ) -> Option<GroupAffine<P>> {
+        GroupAffine::get_point_from_x(x, y_bit)
+    }
+
+    /// Same as `point_from_x` for a batch of points over Fp2, e.g. G2 points. The square root
+    /// of y^2 in Fp2 ends with a division by a square root in Fp, and the divisions of the
+    /// whole batch share a single inversion with Montgomery's trick.
+    pub fn batch_points_from_x<P, Q>(entries: &[(Fp2<Q>, bool)]) -> Vec<Option<GroupAffine<P>>>
+    where
+        P: SWModelParameters<BaseField = Fp2<Q>>,
+        Q: Fp2Parameters,
+    {
+        let half = Q::Fp::one().double().inverse().expect("2 is invertible");
+        let roots = entries
+            .iter()
+            .map(|(x, _)| {
+                let y2 = x.square() * x + &P::mul_by_a(x) + &P::COEFF_B;
+                undivided_sqrt(&y2, &half)
+            })
+            .collect::<Vec<_>>();
+        let mut divisors = roots
+            .iter()
+            .map(|root| root.map_or_else(Q::Fp::one, |(_, divisor)| divisor))
+            .collect::<Vec<_>>();
+        batch_inversion(&mut divisors);
+
+        entries
+            .iter()
+            .zip(roots)
+            .zip(divisors)
+            .map(|(((x, y_bit), root), inverse)| {
+                let (y, _) = root?;
+                let y = Fp2::new(y.c0, y.c1 * &inverse);
+                let y = if Self::y_bit(&y) == *y_bit { y } else { -y };
+                Some(GroupAffine::new(*x, y, false))
+            })
+            .collect()
+    }
+}
+
+// The square root of `a` with the complex method, as `((c0, c1), d)` where the root is
+// `c0 + c1 / d * u`, or `None` if `a` is not a square. If `a = a0 + a1 * u` with `a1` nonzero,
+// `c0` is the square root of `(a0 ± sqrt(norm(a))) / 2` and `c1 = a1 / 2`, and `d = c0`.
+fn undivided_sqrt<P: Fp2Parameters>(a: &Fp2<P>, half: &P::Fp) -> Option<(Fp2<P>, P::Fp)> {
+    if a.c1.is_zero() {
+        return a.sqrt().map(|root| (root, P::Fp::one()));
+    }
+    let norm = a.c0.square() - &P::mul_fp_by_nonresidue(&a.c1.square());
+    let sqrt_norm = norm.sqrt()?;
+    let c0 = ((a.c0 + &sqrt_norm) * half)
+        .sqrt()
+        .or_else(|| ((a.c0 - &sqrt_norm) * half).sqrt())?;
+    Some((Fp2::new(c0, a.c1 * half), c0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{
+        bls12_377::{Fq, Fq2, G1Affine, G1Projective, G2Affine, G2Projective},
+        CanonicalDeserializeWithFlags, CanonicalSerialize, One, ProjectiveCurve, SWFlags,
+        UniformRand,
+    };
+
+    // the bit which Zexe serialized in the compressed encoding
+    fn serialized_bit<P: SWModelParameters>(point: &GroupAffine<P>) -> bool {
+        let mut bytes = vec![];
+        point.serialize(&mut bytes).unwrap();
+        let (_, flags): (P::BaseField, SWFlags) =
+            CanonicalDeserializeWithFlags::deserialize_with_flags(&mut &bytes[..]).unwrap();
+        flags.is_positive().unwrap()
+    }
+
+    #[test]
+    fn conventions_agree_on_random_points() {
+        let rng = &mut rand::thread_rng();
+        for _ in 0..100 {
+            let point = G1Projective::rand(rng).into_affine();
+            let bit = PointCompression::point_bit(&point);
+            assert_eq!(bit, PointCompression::fp_y_bit(&point.y));
+            assert_eq!(bit, serialized_bit(&point));
+            assert_eq!(PointCompression::point_from_x(point.x, bit), Some(point));
+            assert_eq!(PointCompression::point_bit(&-point), !bit);
+
+            let point = G2Projective::rand(rng).into_affine();
+            let bit = PointCompression::point_bit(&point);
+            assert_eq!(bit, PointCompression::fp2_y_bit(&point.y));
+            assert_eq!(bit, serialized_bit(&point));
+            assert_eq!(PointCompression::point_from_x(point.x, bit), Some(point));
+            assert_eq!(PointCompression::point_bit(&-point), !bit);
+        }
+        assert!(!PointCompression::point_bit(&G1Affine::zero()));
+        assert!(!PointCompression::point_bit(&G2Affine::zero()));
+    }
+
+    #[test]
+    fn decompresses_batches() {
+        let rng = &mut rand::thread_rng();
+        let mut entries = vec![];
+        let mut expected = vec![];
+        while entries.len() < 20 {
+            let x = Fq2::rand(rng);
+            let y_bit = entries.len() % 3 == 0;
+            entries.push((x, y_bit));
+            expected.push(PointCompression::point_from_x(x, y_bit));
+        }
+        let points: Vec<Option<G2Affine>> = PointCompression::batch_points_from_x(&entries);
+        assert_eq!(points, expected);
+        assert!(points.iter().any(Option::is_some));
+        assert!(points.iter().any(Option::is_none));
+        let empty: Vec<Option<G2Affine>> = PointCompression::batch_points_from_x(&[]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn conventions_agree_on_edge_elements() {
+        let half = Fq::from_repr(Fq::modulus_minus_one_div_two()).unwrap();
+        let rng = &mut rand::thread_rng();
+        let mut elements = vec![Fq::zero(), Fq::one(), half, half + &Fq::one(), -Fq::one()];
+        elements.extend((0..10).map(|_| Fq::rand(rng)));
+        for c0 in &elements {
+            assert_eq!(PointCompression::y_bit(c0), PointCompression::fp_y_bit(c0));
+            for c1 in &elements {
+                let y = Fq2::new(*c0, *c1);
+                assert_eq!(PointCompression::y_bit(&y), PointCompression::fp2_y_bit(&y));
+            }
+        }
+        // zero is its own negation
+        assert!(!PointCompression::fp2_y_bit(&Fq2::zero()));
+    }
+}
@@ -0,0 +1,86 @@
+//! Scalar multiplication by secret scalars.
+//!
+//! Signing and deriving public keys multiply a point by the private key. The generic
+//! `ProjectiveCurve::mul` skips the scalar's leading zeros and only adds for its set bits,
+//! so its running time depends on the key. `ct_scalar_mul` instead runs a Montgomery ladder
+//! over every bit of the scalar's representation, so the same sequence of group operations
+//! is executed for every scalar.
+//!
+//! The ladder selects its operands by indexing with the scalar's bits instead of branching
+//! on them. The field arithmetic and the point additions of the underlying curve library are
+//! not audited to be constant time themselves, e.g. additions special-case the identity.
+//!
+//! The `vartime-signing` feature makes signing use `vartime_scalar_mul`, for benchmarks
+//! which compare the two. It must not be enabled in production.
+use algebra::{BitIterator, PrimeField, ProjectiveCurve};
+use zeroize::Zeroize;
+
+/// Multiplies the point by the scalar with a Montgomery ladder, which doubles and adds once
+/// per bit of the scalar's representation regardless of its value. Works for both G1 and G2.
+pub fn ct_scalar_mul<G: ProjectiveCurve>(point: &G, scalar: &G::ScalarField) -> G {
+    // r1 - r0 == point is an invariant of the ladder
+    let mut ladder = [G::zero(), *point];
+    // borrowed by the iterator, so that the only copy of the scalar's bits can be wiped
+    let mut repr = scalar.into_repr();
+    for bit in BitIterator::new(&repr) {
+        let bit = bit as usize;
+        // for a set bit, r0 = r0 + r1 and r1 = 2 * r1, otherwise r1 = r0 + r1 and r0 = 2 * r0
+        let sum = ladder[0] + &ladder[1];
+        let doubled = ladder[bit].double();
+        ladder = [[doubled, sum][bit], [sum, doubled][bit]];
+    }
+    repr.as_mut().zeroize();
+    ladder[0]
+}
+
+/// Multiplies the point by the scalar with the curve library's variable time algorithm. Only
+/// use this with public scalars.
+pub fn vartime_scalar_mul<G: ProjectiveCurve>(point: &G, scalar: &G::ScalarField) -> G {
+    ProjectiveCurve::mul(*point, *scalar)
+}
+
+/// The scalar multiplication used with private keys: `ct_scalar_mul`, unless the
+/// `vartime-signing` feature is enabled
+pub(crate) fn secret_scalar_mul<G: ProjectiveCurve>(point: &G, scalar: &G::ScalarField) -> G {
+    if cfg!(feature = "vartime-signing") {
+        vartime_scalar_mul(point, scalar)
+    } else {
+        ct_scalar_mul(point, scalar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{
+        bls12_377::{Fr, G1Projective, G2Projective},
+        Field, One, UniformRand, Zero,
+    };
+
+    fn differential<G: ProjectiveCurve<ScalarField = Fr>>() {
+        let rng = &mut rand::thread_rng();
+        let point = G::rand(rng);
+        let edge_cases = [Fr::zero(), Fr::one(), Fr::one().double(), -Fr::one()];
+        for scalar in edge_cases
+            .iter()
+            .cloned()
+            .chain((0..20).map(|_| Fr::rand(rng)))
+        {
+            assert_eq!(
+                ct_scalar_mul(&point, &scalar),
+                vartime_scalar_mul(&point, &scalar)
+            );
+        }
+        assert!(ct_scalar_mul(&G::zero(), &Fr::rand(rng)).is_zero());
+    }
+
+    #[test]
+    fn matches_vartime_on_g1() {
+        differential::<G1Projective>();
+    }
+
+    #[test]
+    fn matches_vartime_on_g2() {
+        differential::<G2Projective>();
+    }
+}
@@ -0,0 +1,135 @@
+//! Validated domain separators.
+//!
+//! The hashers use the domain as the 8 byte Blake2s personalization, which pads shorter
+//! domains with zeros and rejects longer ones, while the SNARK hashes with a fixed 8 byte
+//! personalization. `Domain` only accepts exactly 8 bytes, so that a domain is encoded the
+//! same way everywhere, and rejects the domains which are used by this library so that
+//! application messages cannot be confused with signatures, proofs of possession or SNARK
+//! outputs.
+use crate::{
+    beacon::BEACON_DOMAIN,
+    bls::scheme::{AUGMENTATION_DOMAIN, BASIC_DOMAIN, POSSESSION_DOMAIN},
+    BLSError, OUT_DOMAIN, POP_DOMAIN, SIG_DOMAIN,
+};
+use std::{convert::TryFrom, fmt};
+
+/// The length of a domain separator in bytes
+pub const DOMAIN_LENGTH: usize = 8;
+
+/// Domain of the operator key's signatures over the attestations of epoch SNARK proofs.
+/// Operators often reuse their validator key, whose signatures over epoch blocks must not
+/// double as attestations.
+pub const ATTESTATION_DOMAIN: &[u8] = b"ULattest";
+
+/// Domain of the release key's signatures over the manifests of circuit artifacts. A release
+/// key that also signs consensus messages under `SIG_DOMAIN` can therefore not be tricked
+/// into signing a manifest.
+pub const MANIFEST_DOMAIN: &[u8] = b"ULmanfst";
+
+/// The domains which are used by this library
+pub const BUILT_IN_DOMAINS: [&[u8]; 9] = [
+    SIG_DOMAIN,
+    POP_DOMAIN,
+    OUT_DOMAIN,
+    BEACON_DOMAIN,
+    BASIC_DOMAIN,
+    AUGMENTATION_DOMAIN,
+    POSSESSION_DOMAIN,
+    ATTESTATION_DOMAIN,
+    MANIFEST_DOMAIN,
+];
+
+/// A domain separator of exactly `DOMAIN_LENGTH` bytes
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Domain([u8; DOMAIN_LENGTH]);
+
+impl Domain {
+    /// The domain of signatures, `SIG_DOMAIN`
+    pub const SIG: Domain = Domain(*b"ULforxof");
+    /// The domain of proofs of possession, `POP_DOMAIN`
+    pub const POP: Domain = Domain(*b"ULforpop");
+    /// The domain of the SNARK's public inputs, `OUT_DOMAIN`
+    pub const OUT: Domain = Domain(*b"ULforout");
+
+    /// Creates an application domain. Fails if the domain is not exactly `DOMAIN_LENGTH`
+    /// bytes long or if it is one of the `BUILT_IN_DOMAINS`.
+    pub fn new(bytes: &[u8]) -> Result<Self, BLSError> {
+        let domain = <[u8; DOMAIN_LENGTH]>::try_from(bytes)
+            .map_err(|_| BLSError::InvalidDomainLength(bytes.len()))?;
+        if BUILT_IN_DOMAINS.contains(&bytes) {
+            return Err(BLSError::ReservedDomain);
+        }
+        Ok(Domain(domain))
+    }
+
+    /// Creates a domain without checking it against the `BUILT_IN_DOMAINS`, e.g. to verify
+    /// signatures of the built-in domains through an API which takes a `Domain`
+    pub const fn unchecked(bytes: [u8; DOMAIN_LENGTH]) -> Self {
+        Domain(bytes)
+    }
+
+    /// Returns the domain's bytes, to be passed to the signing and hashing functions
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Domain {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl TryFrom<&[u8]> for Domain {
+    type Error = BLSError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, BLSError> {
+        Domain::new(bytes)
+    }
+}
+
+impl fmt::Debug for Domain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Domain({})", String::from_utf8_lossy(&self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_length() {
+        let domain = Domain::new(b"myappsig").unwrap();
+        assert_eq!(domain.as_bytes(), b"myappsig");
+
+        for bytes in &[&b""[..], b"myapp", b"myapp\0\0\0\0"] {
+            match Domain::new(bytes) {
+                Err(BLSError::InvalidDomainLength(len)) => assert_eq!(len, bytes.len()),
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_built_in_domains() {
+        for bytes in BUILT_IN_DOMAINS.iter() {
+            match Domain::new(bytes) {
+                Err(BLSError::ReservedDomain) => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+        assert_eq!(Domain::SIG.as_bytes(), SIG_DOMAIN);
+        assert_eq!(Domain::POP.as_bytes(), POP_DOMAIN);
+        assert_eq!(Domain::OUT.as_bytes(), OUT_DOMAIN);
+        assert_eq!(Domain::unchecked(*b"ULforxof"), Domain::SIG);
+
+        // a custom domain signer must not be able to sign attestations or manifests
+        for bytes in &[b"ULattest", b"ULmanfst"] {
+            match Domain::new(*bytes) {
+                Err(BLSError::ReservedDomain) => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! Versioned binary envelopes for stored keys, signatures and other artifacts.
+//!
+//! An envelope wraps the encoding of an artifact with its kind and the version of the
+//! encoding, so that artifacts stored with an older encoding can be recognized and migrated
+//! when the encoding changes. All integers are little-endian:
+//!
+//! | bytes  | field                                    |
+//! |--------|------------------------------------------|
+//! | 0-7    | `ENVELOPE_MAGIC`                         |
+//! | 8      | the kind of the artifact                 |
+//! | 9-10   | the version of the payload's encoding    |
+//! | 11-18  | the length of the payload                |
+//! | 19-    | the payload                              |
+//!
+//! The kinds of this crate's artifacts are `PRIVATE_KEY_KIND`, `PUBLIC_KEY_KIND` and
+//! `SIGNATURE_KIND`, whose version 1 payload is their compressed `CanonicalSerialize`
+//! encoding, and `QUORUM_CERTIFICATE_KIND`, see `quorum::QuorumCertificate`. Kinds from 16
+//! onwards are left to downstream crates.
+//!
+//! With the `serde` feature, keys and signatures are serialized in their envelope: as bytes
+//! for binary formats and as a hex string for human-readable ones. Other `Versioned` types
+//! can do the same with `#[serde(with = "bls_crypto::envelope")]`.
+use crate::{BLSError, BlsResult, PrivateKey, PublicKey, Signature};
+
+use algebra::{CanonicalDeserialize, CanonicalSerialize};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+/// Magic bytes at the start of every envelope
+pub const ENVELOPE_MAGIC: &[u8; 8] = b"ULenvelp";
+
+/// Length of the envelope's header, before the payload
+pub const ENVELOPE_HEADER_LENGTH: usize = 19;
+
+/// The kind of private key envelopes
+pub const PRIVATE_KEY_KIND: u8 = 1;
+
+/// The kind of public key envelopes
+pub const PUBLIC_KEY_KIND: u8 = 2;
+
+/// The kind of signature envelopes
+pub const SIGNATURE_KIND: u8 = 3;
+
+/// The kind of quorum certificate envelopes
+pub const QUORUM_CERTIFICATE_KIND: u8 = 4;
+
+/// An artifact which is stored in a versioned envelope
+pub trait Versioned: Sized {
+    /// The kind of the artifact, which is checked when opening an envelope
+    const KIND: u8;
+
+    /// The version of the encoding which `encode_payload` produces
+    const VERSION: u16;
+
+    /// Encodes the artifact with the current version of its encoding
+    fn encode_payload(&self) -> BlsResult<Vec<u8>>;
+
+    /// Decodes a payload of the provided version, migrating it if it is older than the
+    /// current one. Versions which are not known must be rejected with
+    /// `BLSError::UnsupportedVersion`.
+    fn decode_payload(version: u16, payload: &[u8]) -> BlsResult<Self>;
+
+    /// Writes the artifact in an envelope
+    fn write_envelope<W: Write>(&self, writer: W) -> BlsResult<()> {
+        Envelope::new(Self::KIND, Self::VERSION, self.encode_payload()?).write(writer)
+    }
+
+    /// Reads an artifact from an envelope
+    fn read_envelope<R: Read>(reader: R) -> BlsResult<Self> {
+        Envelope::read(reader)?.open()
+    }
+
+    /// Returns the artifact in an envelope
+    fn to_envelope(&self) -> BlsResult<Vec<u8>> {
+        let mut bytes = vec![];
+        self.write_envelope(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decodes an artifact from an envelope, which must span all of the bytes
+    fn from_envelope(bytes: &[u8]) -> BlsResult<Self> {
+        Envelope::from_bytes(bytes)?.open()
+    }
+}
+
+/// A decoded envelope, whose payload has not been decoded yet
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Envelope {
+    /// The kind of the artifact
+    pub kind: u8,
+    /// The version of the payload's encoding
+    pub version: u16,
+    /// The encoded artifact
+    pub payload: Vec<u8>,
+}
+
+impl Envelope {
+    /// Wraps an encoded artifact
+    pub fn new(kind: u8, version: u16, payload: Vec<u8>) -> Self {
+        Envelope {
+            kind,
+            version,
+            payload,
+        }
+    }
+
+    /// Writes the envelope
+    pub fn write<W: Write>(&self, mut writer: W) -> BlsResult<()> {
+        writer.write_all(ENVELOPE_MAGIC)?;
+        writer.write_u8(self.kind)?;
+        writer.write_u16::<LittleEndian>(self.version)?;
+        writer.write_u64::<LittleEndian>(self.payload.len() as u64)?;
+        writer.write_all(&self.payload)?;
+        Ok(())
+    }
+
+    /// Reads an envelope, consuming exactly its bytes from the reader
+    pub fn read<R: Read>(mut reader: R) -> BlsResult<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != ENVELOPE_MAGIC {
+            return Err(BLSError::InvalidEncoding("not an envelope"));
+        }
+        let kind = reader.read_u8()?;
+        let version = reader.read_u16::<LittleEndian>()?;
+        let length = reader.read_u64::<LittleEndian>()?;
+
+        // the length is not trusted to preallocate the payload
+        let mut payload = vec![];
+        reader.take(length).read_to_end(&mut payload)?;
+        if payload.len() as u64 != length {
+            return Err(BLSError::InvalidEncoding("truncated envelope"));
+        }
+        Ok(Envelope::new(kind, version, payload))
+    }
+
+    /// Decodes an envelope which spans all of the bytes
+    pub fn from_bytes(bytes: &[u8]) -> BlsResult<Self> {
+        let reader = &mut &bytes[..];
+        let envelope = Self::read(&mut *reader)?;
+        if !reader.is_empty() {
+            return Err(BLSError::InvalidEncoding(
+                "trailing bytes after the envelope",
+            ));
+        }
+        Ok(envelope)
+    }
+
+    /// Returns the encoded envelope
+    pub fn to_bytes(&self) -> BlsResult<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(ENVELOPE_HEADER_LENGTH + self.payload.len());
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decodes the artifact in the envelope, after checking its kind
+    pub fn open<T: Versioned>(&self) -> BlsResult<T> {
+        if self.kind != T::KIND {
+            return Err(BLSError::UnexpectedKind {
+                expected: T::KIND,
+                got: self.kind,
+            });
+        }
+        T::decode_payload(self.version, &self.payload)
+    }
+}
+
+// Decodes a version 1 payload of this crate's artifacts, which must span all of the bytes
+fn decode_canonical<T: Versioned + CanonicalDeserialize>(
+    version: u16,
+    payload: &[u8],
+) -> BlsResult<T> {
+    if version != 1 {
+        return Err(BLSError::UnsupportedVersion {
+            kind: T::KIND,
+            version,
+        });
+    }
+    let reader = &mut &payload[..];
+    let value = T::deserialize(&mut *reader)?;
+    if !reader.is_empty() {
+        return Err(BLSError::InvalidEncoding(
+            "trailing bytes after the payload",
+        ));
+    }
+    Ok(value)
+}
+
+fn encode_canonical<T: CanonicalSerialize>(value: &T) -> BlsResult<Vec<u8>> {
+    let mut bytes = vec![];
+    value.serialize(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl Versioned for PrivateKey {
+    const KIND: u8 = PRIVATE_KEY_KIND;
+    const VERSION: u16 = 1;
+
+    fn encode_payload(&self) -> BlsResult<Vec<u8>> {
+        encode_canonical(self)
+    }
+
+    fn decode_payload(version: u16, payload: &[u8]) -> BlsResult<Self> {
+        decode_canonical(version, payload)
+    }
+}
+
+impl Versioned for PublicKey {
+    const KIND: u8 = PUBLIC_KEY_KIND;
+    const VERSION: u16 = 1;
+
+    fn encode_payload(&self) -> BlsResult<Vec<u8>> {
+        encode_canonical(self)
+    }
+
+    fn decode_payload(version: u16, payload: &[u8]) -> BlsResult<Self> {
+        decode_canonical(version, payload)
+    }
+}
+
+impl Versioned for Signature {
+    const KIND: u8 = SIGNATURE_KIND;
+    const VERSION: u16 = 1;
+
+    fn encode_payload(&self) -> BlsResult<Vec<u8>> {
+        encode_canonical(self)
+    }
+
+    fn decode_payload(version: u16, payload: &[u8]) -> BlsResult<Self> {
+        decode_canonical(version, payload)
+    }
+}
+
+/// Serializes the value in its envelope, for `#[serde(with = "bls_crypto::envelope")]`
+#[cfg(feature = "serde")]
+pub fn serialize<T: Versioned, S: serde::Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let bytes = value.to_envelope().map_err(serde::ser::Error::custom)?;
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&hex::encode(&bytes))
+    } else {
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+/// Deserializes a value from its envelope, for `#[serde(with = "bls_crypto::envelope")]`
+#[cfg(feature = "serde")]
+pub fn deserialize<'de, T: Versioned, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    let bytes = if deserializer.is_human_readable() {
+        deserializer.deserialize_str(EnvelopeVisitor)?
+    } else {
+        deserializer.deserialize_bytes(EnvelopeVisitor)?
+    };
+    T::from_envelope(&bytes).map_err(serde::de::Error::custom)
+}
+
+// Accepts the bytes of an envelope, or their hex encoding
+#[cfg(feature = "serde")]
+struct EnvelopeVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for EnvelopeVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an envelope, as bytes or a hex string")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Vec<u8>, E> {
+        hex::decode(value).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, value: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(value.to_vec())
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut bytes = vec![];
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(feature = "serde")]
+macro_rules! impl_serde {
+    ($type:ty) => {
+        impl serde::Serialize for $type {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize(self, serializer)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $type {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize(deserializer)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "serde")]
+impl_serde!(PrivateKey);
+#[cfg(feature = "serde")]
+impl_serde!(PublicKey);
+#[cfg(feature = "serde")]
+impl_serde!(Signature);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G1Projective, UniformRand};
+    use rand::thread_rng;
+
+    #[test]
+    fn roundtrips_in_envelopes() {
+        let rng = &mut thread_rng();
+        let key = PrivateKey::generate(rng);
+        let public_key = key.to_public();
+        let signature = Signature::from(G1Projective::rand(rng));
+
+        let bytes = key.to_envelope().unwrap();
+        assert_eq!(&bytes[..8], ENVELOPE_MAGIC);
+        assert_eq!(bytes[8], PRIVATE_KEY_KIND);
+        assert_eq!(
+            PrivateKey::from_envelope(&bytes).unwrap().as_ref(),
+            key.as_ref()
+        );
+
+        let bytes = public_key.to_envelope().unwrap();
+        assert_eq!(PublicKey::from_envelope(&bytes).unwrap(), public_key);
+
+        let bytes = signature.to_envelope().unwrap();
+        assert_eq!(Signature::from_envelope(&bytes).unwrap(), signature);
+
+        // envelopes can be read one after the other from a stream
+        let mut stream = vec![];
+        public_key.write_envelope(&mut stream).unwrap();
+        signature.write_envelope(&mut stream).unwrap();
+        let reader = &mut &stream[..];
+        assert_eq!(PublicKey::read_envelope(&mut *reader).unwrap(), public_key);
+        assert_eq!(Signature::read_envelope(&mut *reader).unwrap(), signature);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_envelopes() {
+        let rng = &mut thread_rng();
+        let bytes = PrivateKey::generate(rng).to_public().to_envelope().unwrap();
+
+        match Signature::from_envelope(&bytes) {
+            Err(BLSError::UnexpectedKind {
+                expected: SIGNATURE_KIND,
+                got: PUBLIC_KEY_KIND,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut newer = bytes.clone();
+        newer[9] = 2;
+        match PublicKey::from_envelope(&newer) {
+            Err(BLSError::UnsupportedVersion {
+                kind: PUBLIC_KEY_KIND,
+                version: 2,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut magic = bytes.clone();
+        magic[0] ^= 1;
+        PublicKey::from_envelope(&magic).unwrap_err();
+        PublicKey::from_envelope(&bytes[..bytes.len() - 1]).unwrap_err();
+        PublicKey::from_envelope(&[&bytes[..], &[0]].concat()).unwrap_err();
+    }
+
+    #[test]
+    fn migrates_older_versions() {
+        // a hypothetical artifact whose version 1 stored a u8, and version 2 a u16
+        #[derive(Debug, PartialEq)]
+        struct Counter(u16);
+
+        impl Versioned for Counter {
+            const KIND: u8 = 16;
+            const VERSION: u16 = 2;
+
+            fn encode_payload(&self) -> BlsResult<Vec<u8>> {
+                Ok(self.0.to_le_bytes().to_vec())
+            }
+
+            fn decode_payload(version: u16, payload: &[u8]) -> BlsResult<Self> {
+                match (version, payload) {
+                    (1, [value]) => Ok(Counter(u16::from(*value))),
+                    (2, [low, high]) => Ok(Counter(u16::from_le_bytes([*low, *high]))),
+                    (1, _) | (2, _) => Err(BLSError::InvalidEncoding("unexpected length")),
+                    _ => Err(BLSError::UnsupportedVersion {
+                        kind: Self::KIND,
+                        version,
+                    }),
+                }
+            }
+        }
+
+        let old = Envelope::new(16, 1, vec![7]).to_bytes().unwrap();
+        assert_eq!(Counter::from_envelope(&old).unwrap(), Counter(7));
+        let new = Counter(300).to_envelope().unwrap();
+        assert_eq!(Counter::from_envelope(&new).unwrap(), Counter(300));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serializes_with_serde() {
+        let rng = &mut thread_rng();
+        let key = PrivateKey::generate(rng);
+        let public_key = key.to_public();
+
+        let json = serde_json::to_string(&public_key).unwrap();
+        assert_eq!(
+            json,
+            format!("\"{}\"", hex::encode(public_key.to_envelope().unwrap()))
+        );
+        assert_eq!(
+            serde_json::from_str::<PublicKey>(&json).unwrap(),
+            public_key
+        );
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!(
+            serde_json::from_str::<PrivateKey>(&json).unwrap().as_ref(),
+            key.as_ref()
+        );
+        serde_json::from_str::<Signature>(&json).unwrap_err();
+    }
+}
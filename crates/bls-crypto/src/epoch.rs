@@ -0,0 +1,473 @@
+//! Epoch blocks and the verification of the transitions between them.
+//!
+//! The last block of each epoch commits to the validators of the next epoch, and is sealed by
+//! the validators of the previous one. A light client following the epochs keeps the last
+//! `EpochBlock` it verified, and checks each new one with `EpochBlock::verify_transition`,
+//! which returns the validators added and removed by the transition.
+//!
+//! The signed message is the canonical encoding of the block, which is also the one the epoch
+//! SNARK hashes: the index, the maximum number of non-signers, the entropies of blocks encoded
+//! with `EncodingVersion::V2` and the public keys of the block, as bits packed with
+//! `bits_to_bytes`. The `epoch-snark` crate encodes its blocks with `encode_block`.
+use crate::{quorum, BLSError, BlsResult, HashToCurve, PublicKey, Signature};
+use algebra::{
+    bls12_377::{FqParameters, G1Projective},
+    BigInteger, FpParameters, PrimeField, ProjectiveCurve,
+};
+
+#[cfg(feature = "composite-hash")]
+use crate::hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1;
+
+/// The length in bytes of the epoch entropy and parent entropy fields
+pub const ENTROPY_BYTES: usize = 16;
+
+/// The format of the epoch block message which is signed by the validators
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodingVersion {
+    /// The index, maximum non signers and public keys of the epoch
+    V1,
+    /// Additionally commits to the epoch's entropy and to the entropy of its parent epoch,
+    /// which are encoded after the maximum non signers
+    V2,
+}
+
+impl Default for EncodingVersion {
+    fn default() -> Self {
+        EncodingVersion::V1
+    }
+}
+
+impl EncodingVersion {
+    /// Returns true if the epoch entropy and parent entropy are part of the encoding
+    pub fn has_entropy(self) -> bool {
+        self == EncodingVersion::V2
+    }
+}
+
+/// The last block of an epoch, which commits to the validators of the epoch
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpochBlock {
+    /// The index of the epoch
+    pub index: u16,
+    /// The consensus round at which the block was sealed. It is not part of the signed
+    /// message.
+    pub round: u32,
+    /// The validators of the epoch, which seal the block of the next epoch
+    pub validators: Vec<PublicKey>,
+    /// The minimum number of validators of the epoch which must seal the block of the next
+    /// epoch
+    pub threshold: u32,
+    /// The encoding of the block which is signed by the validators
+    pub version: EncodingVersion,
+    /// The entropy of the epoch and the entropy of its parent, set for
+    /// `EncodingVersion::V2`
+    pub entropy: Option<([u8; ENTROPY_BYTES], [u8; ENTROPY_BYTES])>,
+}
+
+/// The validators replaced by a transition, by their slot in the validator set
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidatorSetDiff {
+    /// The validators of the next epoch which were not in their slot in the previous epoch
+    pub added: Vec<(usize, PublicKey)>,
+    /// The validators of the previous epoch which are not in their slot in the next epoch
+    pub removed: Vec<(usize, PublicKey)>,
+}
+
+impl ValidatorSetDiff {
+    /// Returns true if the transition keeps the validator set
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl EpochBlock {
+    /// Creates a new epoch block, encoded with `EncodingVersion::V1`
+    pub fn new(index: u16, round: u32, validators: Vec<PublicKey>, threshold: u32) -> Self {
+        Self {
+            index,
+            round,
+            validators,
+            threshold,
+            version: EncodingVersion::V1,
+            entropy: None,
+        }
+    }
+
+    /// Sets the epoch's entropy and the entropy of its parent, and switches the block's
+    /// encoding to `EncodingVersion::V2`
+    pub fn with_entropy(
+        mut self,
+        epoch_entropy: [u8; ENTROPY_BYTES],
+        parent_entropy: [u8; ENTROPY_BYTES],
+    ) -> Self {
+        self.version = EncodingVersion::V2;
+        self.entropy = Some((epoch_entropy, parent_entropy));
+        self
+    }
+
+    /// Creates a new epoch block whose threshold is Istanbul's quorum of its validators, see
+    /// `quorum::quorum_size`
+    pub fn with_istanbul_quorum(index: u16, round: u32, validators: Vec<PublicKey>) -> Self {
+        let threshold = quorum::quorum_size(validators.len()) as u32;
+        Self::new(index, round, validators, threshold)
+    }
+
+    /// The maximum number of validators which may not seal the next epoch's block, as
+    /// encoded in the block
+    pub fn maximum_non_signers(&self) -> BlsResult<u32> {
+        (self.validators.len() as u32)
+            .checked_sub(self.threshold)
+            .ok_or(BLSError::InvalidThreshold(
+                self.threshold,
+                self.validators.len(),
+            ))
+    }
+
+    /// Encodes the block to the bits hashed by the epoch SNARK, see `encode_block`. Blocks
+    /// with `EncodingVersion::V2` must have their entropies set.
+    pub fn encode_to_bits(&self) -> BlsResult<Vec<bool>> {
+        let entropy = match (self.version.has_entropy(), &self.entropy) {
+            (false, _) => None,
+            (true, Some((epoch_entropy, parent_entropy))) => {
+                Some((&epoch_entropy[..], &parent_entropy[..]))
+            }
+            (true, None) => return Err(BLSError::InvalidEncoding("the entropies are missing")),
+        };
+        encode_block(
+            self.index,
+            self.maximum_non_signers()?,
+            entropy,
+            &self.validators,
+        )
+    }
+
+    /// Encodes the block to the message signed by the validators of the previous epoch
+    pub fn encode_to_bytes(&self) -> BlsResult<Vec<u8>> {
+        Ok(bits_to_bytes(&self.encode_to_bits()?))
+    }
+
+    /// Returns the validators which `next` replaces in each slot of this block's validator
+    /// set. The slots past the end of the shorter set are added or removed.
+    pub fn diff(&self, next: &EpochBlock) -> ValidatorSetDiff {
+        let mut diff = ValidatorSetDiff::default();
+        let slots = self.validators.len().max(next.validators.len());
+        for slot in 0..slots {
+            let previous = self.validators.get(slot);
+            let current = next.validators.get(slot);
+            if previous == current {
+                continue;
+            }
+            if let Some(previous) = previous {
+                diff.removed.push((slot, previous.clone()));
+            }
+            if let Some(current) = current {
+                diff.added.push((slot, current.clone()));
+            }
+        }
+        diff
+    }
+
+    /// Verifies that `next` follows `prev`, and that it is sealed by the aggregate signature
+    /// of the validators of `prev` set in the bitmap, which must be at least the threshold of
+    /// `prev`. The message is hashed with the composite hasher, as in the epoch SNARK.
+    /// Returns the validators added and removed by the transition.
+    #[cfg(feature = "composite-hash")]
+    pub fn verify_transition(
+        prev: &EpochBlock,
+        next: &EpochBlock,
+        aggregate_signature: &Signature,
+        bitmap: &[bool],
+    ) -> BlsResult<ValidatorSetDiff> {
+        Self::verify_transition_with(
+            prev,
+            next,
+            aggregate_signature,
+            bitmap,
+            &*COMPOSITE_HASH_TO_G1,
+        )
+    }
+
+    /// Same as `verify_transition`, hashing the message with `hash_to_g1`
+    pub fn verify_transition_with<H: HashToCurve<Output = G1Projective>>(
+        prev: &EpochBlock,
+        next: &EpochBlock,
+        aggregate_signature: &Signature,
+        bitmap: &[bool],
+        hash_to_g1: &H,
+    ) -> BlsResult<ValidatorSetDiff> {
+        if prev.index.checked_add(1) != Some(next.index) {
+            return Err(BLSError::NonConsecutiveEpochs(prev.index, next.index));
+        }
+        if bitmap.len() != prev.validators.len() {
+            return Err(BLSError::BitmapLengthMismatch(
+                bitmap.len(),
+                prev.validators.len(),
+            ));
+        }
+        // an empty set of signers would verify the identity as its aggregate signature
+        let signers = bitmap.iter().filter(|signed| **signed).count();
+        let quorum = (prev.threshold as usize).max(1);
+        if signers < quorum {
+            return Err(BLSError::NoQuorum { signers, quorum });
+        }
+
+        let message = next.encode_to_bytes()?;
+        let signers = prev
+            .validators
+            .iter()
+            .zip(bitmap)
+            .filter(|(_, signed)| **signed)
+            .map(|(key, _)| key);
+        PublicKey::aggregate(signers).verify(&message, &[], aggregate_signature, hash_to_g1)?;
+
+        Ok(prev.diff(next))
+    }
+}
+
+/// Encodes an epoch block to the bits hashed by the epoch SNARK: the index and the maximum
+/// number of non-signers with `encode_u16` and `encode_u32`, the epoch entropy and the parent
+/// entropy with `encode_entropy` if the block is encoded with `EncodingVersion::V2`, and the
+/// public keys with `encode_public_key`
+pub fn encode_block(
+    index: u16,
+    maximum_non_signers: u32,
+    entropy: Option<(&[u8], &[u8])>,
+    public_keys: &[PublicKey],
+) -> BlsResult<Vec<bool>> {
+    let mut bits = encode_u16(index);
+    bits.extend_from_slice(&encode_u32(maximum_non_signers));
+    if let Some((epoch_entropy, parent_entropy)) = entropy {
+        bits.extend_from_slice(&encode_entropy(epoch_entropy)?);
+        bits.extend_from_slice(&encode_entropy(parent_entropy)?);
+    }
+    for public_key in public_keys {
+        bits.extend_from_slice(&encode_public_key(public_key));
+    }
+    Ok(bits)
+}
+
+/// Encodes the number to bits, the bits of each of its little endian bytes in ascending
+/// order
+pub fn encode_u16(num: u16) -> Vec<bool> {
+    (0..16).map(|i| (num >> i) & 1 == 1).collect()
+}
+
+/// Encodes the number to bits, the bits of each of its little endian bytes in ascending
+/// order
+pub fn encode_u32(num: u32) -> Vec<bool> {
+    (0..32).map(|i| (num >> i) & 1 == 1).collect()
+}
+
+/// Encodes the entropy to bits, the bits of each of its bytes in ascending order. The entropy
+/// must have `ENTROPY_BYTES` bytes.
+pub fn encode_entropy(entropy: &[u8]) -> BlsResult<Vec<bool>> {
+    if entropy.len() != ENTROPY_BYTES {
+        return Err(BLSError::InvalidEntropyLength(entropy.len(), ENTROPY_BYTES));
+    }
+    Ok(entropy
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1 == 1))
+        .collect())
+}
+
+/// Encodes the public key to the bits of both coordinates of its x, each in descending order,
+/// followed by the bit of its y, see `PointCompression::fp2_y_bit`. The key must not be the
+/// point at infinity, which is true for BLS public keys.
+pub fn encode_public_key(public_key: &PublicKey) -> Vec<bool> {
+    let affine = public_key.as_ref().into_affine();
+    let num_bits = FqParameters::MODULUS_BITS as usize;
+    let mut bits = Vec::with_capacity(2 * num_bits + 1);
+    for coordinate in &[affine.x.c0, affine.x.c1] {
+        let repr = coordinate.into_repr();
+        bits.extend((0..num_bits).rev().map(|i| repr.get_bit(i)));
+    }
+    bits.push(crate::PointCompression::fp2_y_bit(&affine.y));
+    bits
+}
+
+/// Packs the bits to bytes as the epoch SNARK does: the bits are reversed, and then packed
+/// in bytes from their least significant bit
+pub fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
+    let reversed = bits.iter().rev().collect::<Vec<_>>();
+    reversed
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, bit)| byte | ((**bit as u8) << i))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, PrivateKey};
+
+    fn keys(num: usize) -> Vec<PrivateKey> {
+        let rng = &mut rand::thread_rng();
+        (0..num).map(|_| PrivateKey::generate(rng)).collect()
+    }
+
+    fn block(index: u16, keys: &[PrivateKey]) -> EpochBlock {
+        let validators = keys.iter().map(|key| key.to_public()).collect();
+        EpochBlock::with_istanbul_quorum(index, 0, validators)
+    }
+
+    /// Seals the block with the keys set in the bitmap
+    fn seal<H: HashToCurve<Output = G1Projective>>(
+        keys: &[PrivateKey],
+        bitmap: &[bool],
+        block: &EpochBlock,
+        hash_to_g1: &H,
+    ) -> Signature {
+        let message = block.encode_to_bytes().unwrap();
+        let signatures = keys
+            .iter()
+            .zip(bitmap)
+            .filter(|(_, signed)| **signed)
+            .map(|(key, _)| key.sign(&message, &[], hash_to_g1).unwrap())
+            .collect::<Vec<_>>();
+        Signature::aggregate(&signatures)
+    }
+
+    #[test]
+    fn verifies_transitions() {
+        let hash_to_g1 = &*DIRECT_HASH_TO_G1;
+        let prev_keys = keys(4);
+        let mut next_keys = prev_keys.clone();
+        next_keys[2] = keys(1).remove(0);
+        let prev = block(1, &prev_keys);
+        let next = block(2, &next_keys);
+
+        let bitmap = [true, false, true, true];
+        let asig = seal(&prev_keys, &bitmap, &next, hash_to_g1);
+        let diff =
+            EpochBlock::verify_transition_with(&prev, &next, &asig, &bitmap, hash_to_g1).unwrap();
+        assert_eq!(diff.removed, vec![(2, prev.validators[2].clone())]);
+        assert_eq!(diff.added, vec![(2, next.validators[2].clone())]);
+
+        // the round is not part of the signed message
+        let resealed = EpochBlock {
+            round: 3,
+            ..next.clone()
+        };
+        assert!(
+            EpochBlock::verify_transition_with(&prev, &resealed, &asig, &bitmap, hash_to_g1)
+                .is_ok()
+        );
+
+        // a signature by the next epoch's validators does not seal the block
+        let asig = seal(&next_keys, &bitmap, &next, hash_to_g1);
+        assert!(
+            EpochBlock::verify_transition_with(&prev, &next, &asig, &bitmap, hash_to_g1).is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_transitions_below_the_threshold() {
+        let hash_to_g1 = &*DIRECT_HASH_TO_G1;
+        let prev_keys = keys(4);
+        let prev = block(1, &prev_keys);
+        let next = block(2, &keys(4));
+        assert_eq!(prev.threshold, 3);
+
+        let bitmap = [true, false, false, true];
+        let asig = seal(&prev_keys, &bitmap, &next, hash_to_g1);
+        match EpochBlock::verify_transition_with(&prev, &next, &asig, &bitmap, hash_to_g1) {
+            Err(BLSError::NoQuorum {
+                signers: 2,
+                quorum: 3,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // without signers, even a threshold of zero is not met
+        let prev = EpochBlock {
+            threshold: 0,
+            ..prev
+        };
+        let bitmap = [false; 4];
+        let asig = Signature::aggregate(&[] as &[Signature]);
+        assert!(
+            EpochBlock::verify_transition_with(&prev, &next, &asig, &bitmap, hash_to_g1).is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_non_consecutive_epochs() {
+        let hash_to_g1 = &*DIRECT_HASH_TO_G1;
+        let prev_keys = keys(3);
+        let prev = block(1, &prev_keys);
+        let next = block(3, &keys(3));
+        let bitmap = [true; 3];
+        let asig = seal(&prev_keys, &bitmap, &next, hash_to_g1);
+        match EpochBlock::verify_transition_with(&prev, &next, &asig, &bitmap, hash_to_g1) {
+            Err(BLSError::NonConsecutiveEpochs(1, 3)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn diffs_validator_sets_of_different_sizes() {
+        let prev = block(1, &keys(2));
+        let mut next = prev.clone();
+        next.validators.push(keys(1)[0].to_public());
+        let diff = prev.diff(&next);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.added, vec![(2, next.validators[2].clone())]);
+        assert!(prev.diff(&prev).is_empty());
+    }
+
+    #[test]
+    fn encodes_the_entropy_of_v2_blocks() {
+        let v1 = block(2, &keys(3));
+        let v2 = v1
+            .clone()
+            .with_entropy([1; ENTROPY_BYTES], [2; ENTROPY_BYTES]);
+        let v1_bits = v1.encode_to_bits().unwrap();
+        let v2_bits = v2.encode_to_bits().unwrap();
+        assert_eq!(v2_bits.len(), v1_bits.len() + 2 * ENTROPY_BYTES * 8);
+        assert_eq!(&v2_bits[..48], &v1_bits[..48]);
+        assert_eq!(
+            &v2_bits[48..56],
+            &[true, false, false, false, false, false, false, false]
+        );
+        assert_eq!(&v2_bits[48 + 2 * ENTROPY_BYTES * 8..], &v1_bits[48..]);
+
+        let mut missing = v1;
+        missing.version = EncodingVersion::V2;
+        assert!(missing.encode_to_bits().is_err());
+        match encode_entropy(&[0; ENTROPY_BYTES + 1]) {
+            Err(BLSError::InvalidEntropyLength(17, 16)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn verifies_v2_transitions() {
+        let hash_to_g1 = &*DIRECT_HASH_TO_G1;
+        let prev_keys = keys(3);
+        let prev = block(1, &prev_keys);
+        let next = block(2, &keys(3)).with_entropy([3; ENTROPY_BYTES], [4; ENTROPY_BYTES]);
+        let bitmap = [true; 3];
+        let asig = seal(&prev_keys, &bitmap, &next, hash_to_g1);
+        assert!(
+            EpochBlock::verify_transition_with(&prev, &next, &asig, &bitmap, hash_to_g1).is_ok()
+        );
+
+        // the entropy is part of the signed message
+        let other = next.with_entropy([3; ENTROPY_BYTES], [5; ENTROPY_BYTES]);
+        assert!(
+            EpochBlock::verify_transition_with(&prev, &other, &asig, &bitmap, hash_to_g1).is_err()
+        );
+    }
+
+    #[test]
+    fn encodes_integers_as_the_circuit() {
+        assert_eq!(bits_to_bytes(&encode_u16(0x0102)), vec![0x80, 0x40]);
+        let threshold_too_large = EpochBlock::new(1, 0, vec![], 1);
+        assert!(threshold_too_large.encode_to_bytes().is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! Consistency checks of block seals against the individual signatures they aggregate.
+//!
+//! After an incident, e.g. a seal which does not verify or a validator claiming it was left
+//! out of a block, the individual committed seals collected by the nodes can be compared
+//! with the aggregated seal of the header. `check_seal` verifies each collected signature,
+//! recomputes the aggregate over the signers of the bitmap, and reports which contributions
+//! the seal is missing or has in excess.
+use crate::{
+    quorum::is_quorum, BLSError, BlsResult, HashToCurve, PublicKey, QuorumCertificate, Signature,
+    SIG_DOMAIN,
+};
+use algebra::{bls12_377::G1Projective, Zero};
+
+/// The result of comparing a seal with the individual signatures of its block
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SealReport {
+    /// The validators set in the bitmap
+    pub signers: Vec<usize>,
+    /// The signers of the bitmap whose individual signature was not collected
+    pub uncollected: Vec<usize>,
+    /// The validators whose collected signature does not verify against their key
+    pub invalid: Vec<usize>,
+    /// The signers of the bitmap whose collected signature is not part of the seal
+    pub missing: Vec<usize>,
+    /// The validators which are not set in the bitmap, but whose collected signature is
+    /// part of the seal
+    pub extra: Vec<usize>,
+    /// True if the seal is the aggregate of the collected signatures of the bitmap's signers
+    pub matches_aggregate: bool,
+    /// True if the seal verifies against the aggregate public key of the bitmap's signers
+    pub seal_verifies: bool,
+    /// True if the bitmap's signers form a quorum
+    pub quorum: bool,
+}
+
+impl SealReport {
+    /// Returns true if nothing inconsistent was found: every signer's signature was
+    /// collected and is valid, the seal is their aggregate and verifies, and they form a
+    /// quorum
+    pub fn is_consistent(&self) -> bool {
+        self.uncollected.is_empty()
+            && self.invalid.is_empty()
+            && self.missing.is_empty()
+            && self.extra.is_empty()
+            && self.matches_aggregate
+            && self.seal_verifies
+            && self.quorum
+    }
+}
+
+/// Compares the seal of a block, i.e. its quorum certificate, with the individual signatures
+/// collected for it.
+///
+/// `signatures` holds the signature collected from each validator, if any, in the order of
+/// `validators` and of the certificate's bitmap. Invalid collected signatures are reported, and are still
+/// considered as contributions to the seal. When the seal differs from the aggregate of
+/// the collected signatures of the bitmap's signers, a difference of a single contribution
+/// is attributed to the validator in `missing` or `extra`. Larger differences are only
+/// reported by `matches_aggregate`.
+///
+/// Fails if the lengths of the inputs differ, or if the message cannot be hashed.
+pub fn check_seal<H: HashToCurve<Output = G1Projective>>(
+    validators: &[PublicKey],
+    certificate: &QuorumCertificate,
+    signatures: &[Option<Signature>],
+    extra_data: &[u8],
+    hash_to_g1: &H,
+) -> BlsResult<SealReport> {
+    let bitmap = &certificate.bitmap;
+    let seal = &certificate.aggregate_signature;
+    let quorum = is_quorum(bitmap, validators.len())?;
+    if signatures.len() != validators.len() {
+        return Err(BLSError::SignatureCountMismatch(
+            signatures.len(),
+            validators.len(),
+        ));
+    }
+    let hash = hash_to_g1.hash(SIG_DOMAIN, &certificate.message, extra_data)?;
+
+    let mut report = SealReport {
+        quorum,
+        ..Default::default()
+    };
+    let mut aggregate = G1Projective::zero();
+    for (index, (signed, signature)) in bitmap.iter().zip(signatures).enumerate() {
+        if *signed {
+            report.signers.push(index);
+        }
+        match signature {
+            Some(signature) => {
+                if signature
+                    .batch_verify_hashes(&[&validators[index]], &[hash])
+                    .is_err()
+                {
+                    report.invalid.push(index);
+                }
+                if *signed {
+                    aggregate += signature.as_ref();
+                }
+            }
+            None if *signed => report.uncollected.push(index),
+            None => {}
+        }
+    }
+
+    let difference = *seal.as_ref() - &aggregate;
+    report.matches_aggregate = report.uncollected.is_empty() && difference.is_zero();
+    if !difference.is_zero() {
+        attribute_difference(&mut report, bitmap, signatures, &difference);
+    }
+
+    let signers = report.signers.iter().map(|index| &validators[*index]);
+    report.seal_verifies = seal
+        .batch_verify_hashes(&[PublicKey::aggregate(signers)], &[hash])
+        .is_ok();
+    Ok(report)
+}
+
+// Finds the single collected signature whose addition to or removal from the aggregate
+// explains the difference with the seal
+fn attribute_difference(
+    report: &mut SealReport,
+    bitmap: &[bool],
+    signatures: &[Option<Signature>],
+    difference: &G1Projective,
+) {
+    for (index, (signed, signature)) in bitmap.iter().zip(signatures).enumerate() {
+        let signature = match signature {
+            Some(signature) => signature.as_ref(),
+            None => continue,
+        };
+        if *signed && -*signature == *difference {
+            report.missing.push(index);
+            return;
+        }
+        if !*signed && signature == difference {
+            report.extra.push(index);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, PrivateKey};
+    use algebra::UniformRand;
+
+    struct Block {
+        validators: Vec<PublicKey>,
+        signatures: Vec<Option<Signature>>,
+    }
+
+    const MESSAGE: &[u8] = b"block";
+
+    fn block(num_validators: usize) -> Block {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let keys = (0..num_validators)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        Block {
+            validators: keys.iter().map(|key| key.to_public()).collect(),
+            signatures: keys
+                .iter()
+                .map(|key| Some(key.sign(MESSAGE, &[], hasher).unwrap()))
+                .collect(),
+        }
+    }
+
+    fn seal(block: &Block, signers: &[usize]) -> Signature {
+        Signature::aggregate(
+            signers
+                .iter()
+                .map(|index| block.signatures[*index].as_ref().unwrap()),
+        )
+    }
+
+    fn certificate(bitmap: &[bool], seal: &Signature) -> QuorumCertificate {
+        QuorumCertificate {
+            epoch_id: 0,
+            message: MESSAGE.to_vec(),
+            bitmap: bitmap.to_vec(),
+            aggregate_signature: seal.clone(),
+        }
+    }
+
+    fn check(block: &Block, bitmap: &[bool], seal: &Signature) -> SealReport {
+        check_seal(
+            &block.validators,
+            &certificate(bitmap, seal),
+            &block.signatures,
+            &[],
+            &*DIRECT_HASH_TO_G1,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_consistent_seals() {
+        let block = block(4);
+        let bitmap = [true, true, false, true];
+        let report = check(&block, &bitmap, &seal(&block, &[0, 1, 3]));
+        assert!(report.is_consistent());
+        assert_eq!(report.signers, vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn reports_missing_and_extra_contributions() {
+        let block = block(4);
+        let bitmap = [true, true, false, true];
+
+        // validator 1 is in the bitmap, but left out of the seal
+        let report = check(&block, &bitmap, &seal(&block, &[0, 3]));
+        assert_eq!(report.missing, vec![1]);
+        assert!(report.extra.is_empty());
+        assert!(!report.matches_aggregate);
+        assert!(!report.seal_verifies);
+
+        // validator 2 is not in the bitmap, but its signature was added to the seal
+        let report = check(&block, &bitmap, &seal(&block, &[0, 1, 2, 3]));
+        assert_eq!(report.extra, vec![2]);
+        assert!(report.missing.is_empty());
+        assert!(!report.is_consistent());
+    }
+
+    #[test]
+    fn reports_invalid_and_uncollected_signatures() {
+        let mut block = block(4);
+        let bitmap = [true, true, true, false];
+        let seal = seal(&block, &[0, 1, 2]);
+        block.signatures[0] = Some(Signature::from(G1Projective::rand(&mut rand::thread_rng())));
+        block.signatures[2] = None;
+
+        let report = check(&block, &bitmap, &seal);
+        assert_eq!(report.invalid, vec![0]);
+        assert_eq!(report.uncollected, vec![2]);
+        assert!(!report.matches_aggregate);
+        // the seal itself is still valid
+        assert!(report.seal_verifies);
+        assert!(report.quorum);
+
+        match check_seal(
+            &block.validators,
+            &certificate(&bitmap, &seal),
+            &block.signatures[1..],
+            &[],
+            &*DIRECT_HASH_TO_G1,
+        ) {
+            Err(BLSError::SignatureCountMismatch(3, 4)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
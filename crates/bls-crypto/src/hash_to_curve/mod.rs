@@ -24,6 +24,7 @@
 /// Doing this manually requires importing the curves and instantiating the hashers as follows:
 ///
 /// ```rust
+/// # #[cfg(feature = "composite-hash")] {
 /// use algebra::bls12_377::g1::Parameters;
 /// use bls_crypto::{
 ///     OUT_DOMAIN,
@@ -40,9 +41,31 @@
 /// // You can also use the underlying struct's method to get the counter
 /// let (hash, counter) = hasher.hash_with_attempt(OUT_DOMAIN, &b"some_data"[..], &b"extra"[..]).expect("should not fail");
 /// assert_eq!(counter, 3);
+/// # }
 /// ```
 pub mod try_and_increment;
 
+/// Monitoring of the distribution of the attempts of try-and-increment
+#[cfg(feature = "std")]
+pub mod monitor;
+
+/// Implementation of the simplified SWU map of
+/// [draft-irtf-cfrg-hash-to-curve](https://datatracker.ietf.org/doc/draft-irtf-cfrg-hash-to-curve/)
+/// for BLS12-377's G1, an alternative to Try-and-Increment which does not loop over a
+/// counter.
+///
+/// # Examples
+///
+/// The SSWU hashers are used like the Try-and-Increment ones
+///
+/// ```rust
+/// use bls_crypto::{OUT_DOMAIN, hash_to_curve::{HashToCurve, sswu::DIRECT_SSWU_HASH_TO_G1}};
+///
+/// let hasher = &*DIRECT_SSWU_HASH_TO_G1;
+/// let hash = hasher.hash(OUT_DOMAIN, &b"some_data"[..], &b"extra"[..]).expect("should not fail");
+/// ```
+pub mod sswu;
+
 use crate::BLSError;
 
 /// Trait for hashing arbitrary data to a group element on an elliptic curve
@@ -58,4 +81,10 @@ pub trait HashToCurve {
         message: &[u8],
         extra_data: &[u8],
     ) -> Result<Self::Output, BLSError>;
+
+    /// The maximum combined length in bytes of a message and its extra data, or `None` if
+    /// their length is unbounded
+    fn max_input_length(&self) -> Option<usize> {
+        None
+    }
 }
@@ -0,0 +1,392 @@
+//! Monitoring of the attempts taken by try-and-increment.
+//!
+//! Each attempt of try-and-increment succeeds independently with the same probability, so the
+//! number of failed attempts before a hash succeeds follows a geometric distribution. A bug
+//! in the hasher or the decoding of the candidates, or inputs which are not uniformly hashed,
+//! show up as a deviation from it long before they produce invalid signatures. An
+//! `AttemptMonitor` attached to a hasher with `TryAndIncrement::with_monitor` keeps the
+//! attempt counts of the latest hashes, periodically runs a chi-squared goodness of fit test
+//! against the geometric distribution, and reports an `AttemptWarning` when it fails.
+//!
+//! Only the hashes which start from the first attempt are recorded, i.e. not those of
+//! `hash_from_attempt` with a hint, whose attempt counts are shifted.
+use std::{collections::VecDeque, fmt, sync::Mutex};
+
+/// The minimum expected number of samples of each bin of the chi-squared test. Rarer attempt
+/// counts are merged in the last bin.
+const MIN_EXPECTED_PER_BIN: f64 = 5.0;
+
+/// The configuration of an `AttemptMonitor`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorConfig {
+    /// The number of latest hashes which are tested
+    pub window: usize,
+    /// The number of hashes recorded between two tests
+    pub check_interval: usize,
+    /// The number of hashes in the window before the first test
+    pub min_samples: usize,
+    /// The expected success probability of each attempt. If `None`, it is estimated from the
+    /// window, and only the shape of the distribution is tested.
+    pub success_probability: Option<f64>,
+    /// The number of standard deviations of the normal approximation of the chi-squared
+    /// statistic above which the test fails. The default 3.72 has a false alarm probability
+    /// of about 1e-4 per test.
+    pub critical_z: f64,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            window: 10_000,
+            check_interval: 1_000,
+            min_samples: 1_000,
+            success_probability: None,
+            critical_z: 3.72,
+        }
+    }
+}
+
+/// A sign that the attempts of try-and-increment are not distributed as expected
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttemptWarning {
+    /// The attempt counts of the window fail the goodness of fit test
+    DistributionMismatch {
+        /// The number of hashes in the window
+        samples: usize,
+        /// The success probability which the window was tested against
+        success_probability: f64,
+        /// The chi-squared statistic of the window
+        statistic: f64,
+        /// The value of the statistic above which the test fails
+        critical_value: f64,
+        /// The degrees of freedom of the test
+        degrees_of_freedom: usize,
+    },
+    /// A hash failed after all of its attempts, which is practically impossible for a
+    /// correct hasher
+    Exhausted,
+}
+
+impl fmt::Display for AttemptWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttemptWarning::DistributionMismatch {
+                samples,
+                success_probability,
+                statistic,
+                critical_value,
+                degrees_of_freedom,
+            } => write!(
+                f,
+                "the attempts of the last {} hashes are not geometric with success probability \
+                 {:.3}: chi-squared statistic {:.1} > {:.1} with {} degrees of freedom",
+                samples, success_probability, statistic, critical_value, degrees_of_freedom
+            ),
+            AttemptWarning::Exhausted => write!(f, "a hash ran out of attempts"),
+        }
+    }
+}
+
+/// The counts of the hashes recorded by an `AttemptMonitor` since it was created
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttemptStats {
+    /// The number of successful hashes
+    pub hashes: u64,
+    /// The number of hashes which ran out of attempts
+    pub exhausted: u64,
+    /// The number of successful hashes by their number of failed attempts
+    pub histogram: Vec<u64>,
+    /// The number of warnings which were reported
+    pub warnings: u64,
+}
+
+impl AttemptStats {
+    /// The mean number of attempts of the successful hashes, including the successful one
+    pub fn mean_attempts(&self) -> f64 {
+        let attempts = self
+            .histogram
+            .iter()
+            .enumerate()
+            .map(|(failures, count)| (failures as u64 + 1) * count)
+            .sum::<u64>();
+        attempts as f64 / self.hashes.max(1) as f64
+    }
+}
+
+#[derive(Default)]
+struct State {
+    window: VecDeque<usize>,
+    since_check: usize,
+    stats: AttemptStats,
+}
+
+/// Records the attempts of the hashes of a try-and-increment hasher and reports the
+/// deviations of their distribution, see the module documentation
+pub struct AttemptMonitor {
+    config: MonitorConfig,
+    state: Mutex<State>,
+    on_warning: Box<dyn Fn(&AttemptWarning) + Send + Sync>,
+}
+
+impl fmt::Debug for AttemptMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttemptMonitor")
+            .field("config", &self.config)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl AttemptMonitor {
+    /// Creates a monitor which calls `on_warning` with each warning, on the hashing thread
+    pub fn new<F: Fn(&AttemptWarning) + Send + Sync + 'static>(
+        config: MonitorConfig,
+        on_warning: F,
+    ) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+            on_warning: Box::new(on_warning),
+        }
+    }
+
+    /// The configuration of the monitor
+    pub fn config(&self) -> &MonitorConfig {
+        &self.config
+    }
+
+    /// Returns the counts of the hashes recorded so far
+    pub fn stats(&self) -> AttemptStats {
+        self.lock().stats.clone()
+    }
+
+    /// Records a hash which succeeded after `failures` failed attempts, and tests the window
+    /// if it is due
+    pub fn record(&self, failures: usize) {
+        let warning = {
+            let mut state = self.lock();
+            if state.stats.histogram.len() <= failures {
+                state.stats.histogram.resize(failures + 1, 0);
+            }
+            state.stats.histogram[failures] += 1;
+            state.stats.hashes += 1;
+            state.window.push_back(failures);
+            if state.window.len() > self.config.window {
+                state.window.pop_front();
+            }
+
+            state.since_check += 1;
+            let due = state.since_check >= self.config.check_interval
+                && state.window.len() >= self.config.min_samples;
+            if !due {
+                return;
+            }
+            state.since_check = 0;
+            let warning = goodness_of_fit(
+                &state.window,
+                self.config.success_probability,
+                self.config.critical_z,
+            );
+            if warning.is_some() {
+                state.stats.warnings += 1;
+            }
+            warning
+        };
+        // the callback runs without the lock, so that it may read the stats
+        if let Some(warning) = warning {
+            (self.on_warning)(&warning);
+        }
+    }
+
+    /// Records a hash which ran out of attempts
+    pub fn record_exhausted(&self) {
+        {
+            let mut state = self.lock();
+            state.stats.exhausted += 1;
+            state.stats.warnings += 1;
+        }
+        (self.on_warning)(&AttemptWarning::Exhausted);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("the monitor lock is not poisoned")
+    }
+}
+
+/// Tests the failure counts against the geometric distribution with the success probability,
+/// or the one estimated from them, and returns the warning if the test fails
+fn goodness_of_fit(
+    failures: &VecDeque<usize>,
+    success_probability: Option<f64>,
+    critical_z: f64,
+) -> Option<AttemptWarning> {
+    let samples = failures.len();
+    let n = samples as f64;
+    let (p, fitted) = match success_probability {
+        Some(p) => (p, false),
+        // maximum likelihood estimate, from the mean number of attempts
+        None => (
+            n / failures.iter().map(|f| *f as f64 + 1.0).sum::<f64>(),
+            true,
+        ),
+    };
+    let q = 1.0 - p;
+
+    // Bin k holds the hashes with k failures, and the last bin all the rarer ones
+    let mut expected = vec![];
+    let mut tail = n;
+    while tail * q >= MIN_EXPECTED_PER_BIN && tail * p >= MIN_EXPECTED_PER_BIN {
+        expected.push(tail * p);
+        tail *= q;
+    }
+    expected.push(tail);
+    let last = expected.len() - 1;
+    let mut observed = vec![0usize; expected.len()];
+    for f in failures {
+        observed[(*f).min(last)] += 1;
+    }
+
+    let statistic = observed
+        .iter()
+        .zip(&expected)
+        .map(|(o, e)| {
+            let diff = *o as f64 - e;
+            // an observation in a bin which cannot happen, e.g. a failure when every attempt
+            // is expected to succeed
+            if *e == 0.0 {
+                return if *o == 0 { 0.0 } else { f64::INFINITY };
+            }
+            diff * diff / e
+        })
+        .sum::<f64>();
+    let degrees_of_freedom = expected.len().saturating_sub(if fitted { 2 } else { 1 });
+    if degrees_of_freedom == 0 {
+        // a single bin fits any window, unless the estimate itself is impossible: a correct
+        // hasher fails some attempts
+        if fitted && q == 0.0 && samples >= MIN_EXPECTED_PER_BIN as usize {
+            return Some(AttemptWarning::DistributionMismatch {
+                samples,
+                success_probability: p,
+                statistic: f64::INFINITY,
+                critical_value: 0.0,
+                degrees_of_freedom,
+            });
+        }
+        return None;
+    }
+
+    let critical_value = chi_squared_quantile(degrees_of_freedom as f64, critical_z);
+    if statistic > critical_value {
+        Some(AttemptWarning::DistributionMismatch {
+            samples,
+            success_probability: p,
+            statistic,
+            critical_value,
+            degrees_of_freedom,
+        })
+    } else {
+        None
+    }
+}
+
+/// The Wilson-Hilferty approximation of the quantile of the chi-squared distribution with
+/// `k` degrees of freedom at `z` standard deviations of the normal distribution
+fn chi_squared_quantile(k: f64, z: f64) -> f64 {
+    let a = 2.0 / (9.0 * k);
+    k * (1.0 - a + z * a.sqrt()).powi(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn geometric<R: Rng>(rng: &mut R, p: f64) -> usize {
+        let mut failures = 0;
+        while !rng.gen_bool(p) {
+            failures += 1;
+        }
+        failures
+    }
+
+    fn counting_monitor(config: MonitorConfig) -> (AttemptMonitor, Arc<AtomicUsize>) {
+        let warnings = Arc::new(AtomicUsize::new(0));
+        let counter = warnings.clone();
+        let monitor = AttemptMonitor::new(config, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        (monitor, warnings)
+    }
+
+    #[test]
+    fn geometric_attempts_pass() {
+        let rng = &mut XorShiftRng::from_seed([7; 16]);
+        for &success_probability in &[None, Some(0.5)] {
+            let (monitor, warnings) = counting_monitor(MonitorConfig {
+                success_probability,
+                ..MonitorConfig::default()
+            });
+            for _ in 0..20_000 {
+                monitor.record(geometric(rng, 0.5));
+            }
+            assert_eq!(warnings.load(Ordering::SeqCst), 0);
+
+            let stats = monitor.stats();
+            assert_eq!(stats.hashes, 20_000);
+            assert_eq!(stats.histogram.iter().sum::<u64>(), 20_000);
+            assert!((stats.mean_attempts() - 2.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn biased_attempts_are_reported() {
+        let rng = &mut XorShiftRng::from_seed([7; 16]);
+
+        // the right mean, but no long runs of failures
+        let (monitor, warnings) = counting_monitor(MonitorConfig::default());
+        for i in 0..1_000 {
+            monitor.record(i % 3);
+        }
+        assert_eq!(warnings.load(Ordering::SeqCst), 1);
+
+        // geometric, but with another success probability than expected
+        let (monitor, warnings) = counting_monitor(MonitorConfig {
+            success_probability: Some(0.5),
+            ..MonitorConfig::default()
+        });
+        for _ in 0..1_000 {
+            monitor.record(geometric(rng, 0.4));
+        }
+        assert_eq!(warnings.load(Ordering::SeqCst), 1);
+        assert_eq!(monitor.stats().warnings, 1);
+
+        // every attempt succeeds
+        let (monitor, warnings) = counting_monitor(MonitorConfig::default());
+        for _ in 0..1_000 {
+            monitor.record(0);
+        }
+        assert_eq!(warnings.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn exhausted_hashes_are_reported() {
+        let (monitor, warnings) = counting_monitor(MonitorConfig::default());
+        monitor.record_exhausted();
+        assert_eq!(warnings.load(Ordering::SeqCst), 1);
+        assert_eq!(monitor.stats().exhausted, 1);
+    }
+
+    #[test]
+    fn quantile_approximation() {
+        // tabulated 99.99% quantiles of the chi-squared distribution
+        for &(k, quantile) in &[(5.0, 25.74), (10.0, 35.56), (20.0, 52.39)] {
+            let approximation = chi_squared_quantile(k, 3.719);
+            assert!((approximation - quantile).abs() / quantile < 0.03, "{}", k);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Hashing to BLS12-377's G1 with the simplified SWU map of
+//! [draft-irtf-cfrg-hash-to-curve](https://datatracker.ietf.org/doc/draft-irtf-cfrg-hash-to-curve/)
+//! (RFC 9380).
+//!
+//! The message is hashed to two field elements which are mapped to points with the
+//! simplified SWU map, added and multiplied by the cofactor. Unlike Try-and-Increment, the
+//! same operations are executed for every message.
+//!
+//! G1's equation `y^2 = x^3 + 1` has `A = 0`, which the simplified SWU map does not support,
+//! so the map is applied on the 2-isogenous curve `E': y^2 = x^3 - 15x + 22` and its result
+//! is moved to G1 with the isogeny
+//!
+//! ```text
+//! x = (x' - 3 / (x' - 2)) / 4
+//! y = y' (1 + 3 / (x' - 2)^2) / 8
+//! ```
+//!
+//! whose kernel is `(2, 0)`. `Z = -11` is the constant selected by the draft's procedure for
+//! `E'`.
+//!
+//! The draft does not specify a suite for BLS12-377, so hashes are not interoperable with
+//! other implementations. The only other deviation from the draft is `hash_to_field`: the
+//! two field elements are read from a 128 byte output of this crate's `Hasher` instead of
+//! `expand_message_xmd`, so that the composite hasher can be used and the hash can be
+//! verified in a SNARK with `bls-gadgets`.
+//!
+//! The map selects its values by indexing instead of branching on them, but the field
+//! arithmetic of the underlying curve library (e.g. inversions and square roots) is not
+//! constant time.
+use super::HashToCurve;
+#[cfg(all(feature = "composite-hash", feature = "std"))]
+use crate::hashers::composite::{CompositeHasher, COMPOSITE_HASHER, CRH};
+#[cfg(feature = "std")]
+use crate::hashers::DirectHasher;
+use crate::hashers::Hasher;
+use crate::BLSError;
+
+use algebra::{
+    bls12_377::{Fq, G1Affine, G1Projective},
+    AffineCurve, BigInteger, Field, One, PrimeField, ProjectiveCurve, SquareRootField, Zero,
+};
+#[cfg(feature = "std")]
+use once_cell::sync::Lazy;
+
+/// The number of bytes of the hash which are reduced to each field element, i.e. the
+/// draft's `L = ceil((ceil(log2(p)) + k) / 8)` for a security level `k` of 128 bits
+pub const FIELD_ELEMENT_BYTES: usize = 64;
+
+/// The coefficient `A` of the isogenous curve `E'`, negated
+pub const ISO_MINUS_A: u64 = 15;
+
+/// The coefficient `B` of the isogenous curve `E'`
+pub const ISO_B: u64 = 22;
+
+/// The constant `Z` of the map, negated
+pub const MINUS_Z: u64 = 11;
+
+/// Composite (Bowe-Hopwood CRH, Blake2x XOF) SSWU hasher for BLS 12-377.
+#[cfg(all(feature = "composite-hash", feature = "std"))]
+pub static COMPOSITE_SSWU_HASH_TO_G1: Lazy<Sswu<CompositeHasher<CRH>>> =
+    Lazy::new(|| Sswu::new(&*COMPOSITE_HASHER));
+
+/// Direct (Blake2s CRH, Blake2x XOF) SSWU hasher for BLS 12-377.
+#[cfg(feature = "std")]
+pub static DIRECT_SSWU_HASH_TO_G1: Lazy<Sswu<DirectHasher>> =
+    Lazy::new(|| Sswu::new(&DirectHasher));
+
+/// Hashes to BLS12-377's G1 with the simplified SWU map, see the module's documentation
+#[derive(Clone)]
+pub struct Sswu<'a, H> {
+    hasher: &'a H,
+}
+
+impl<'a, H> Sswu<'a, H>
+where
+    H: Hasher<Error = BLSError>,
+{
+    /// Instantiates a new SSWU hasher with the provided hashing method
+    pub fn new(h: &'a H) -> Self {
+        Sswu { hasher: h }
+    }
+
+    /// Hashes the message and extra data to the two field elements which are mapped to the
+    /// curve. Each element is read big endian from `FIELD_ELEMENT_BYTES` bytes of the hash.
+    pub fn hash_to_field(
+        &self,
+        domain: &[u8],
+        message: &[u8],
+        extra_data: &[u8],
+    ) -> Result<[Fq; 2], BLSError> {
+        let input = [extra_data, message].concat();
+        let hash = self.hasher.hash(domain, &input, 2 * FIELD_ELEMENT_BYTES)?;
+        let (u0, u1) = hash.split_at(FIELD_ELEMENT_BYTES);
+        Ok([field_element_from_bytes(u0), field_element_from_bytes(u1)])
+    }
+}
+
+impl<'a, H> HashToCurve for Sswu<'a, H>
+where
+    H: Hasher<Error = BLSError>,
+{
+    type Output = G1Projective;
+
+    fn hash(
+        &self,
+        domain: &[u8],
+        message: &[u8],
+        extra_data: &[u8],
+    ) -> Result<Self::Output, BLSError> {
+        let [u0, u1] = self.hash_to_field(domain, message, extra_data)?;
+        let q0 = map_to_curve(&u0);
+        let q1 = map_to_curve(&u1);
+        Ok((q0 + &q1).into_affine().scale_by_cofactor())
+    }
+
+    fn max_input_length(&self) -> Option<usize> {
+        self.hasher.max_input_length()
+    }
+}
+
+/// Reduces the big endian integer in `bytes` modulo the field's characteristic
+pub fn field_element_from_bytes(bytes: &[u8]) -> Fq {
+    let base = Fq::from(256u64);
+    bytes.iter().fold(Fq::zero(), |acc, byte| {
+        acc * &base + &Fq::from(*byte as u64)
+    })
+}
+
+/// Maps the field element to G1, without clearing the cofactor
+pub fn map_to_curve(u: &Fq) -> G1Projective {
+    let (x, y) = map_to_iso_curve(u);
+    iso_map(&x, &y)
+}
+
+/// The simplified SWU map to the isogenous curve `E'`, following the straight-line
+/// description of the draft. Returns the affine coordinates of the point.
+pub fn map_to_iso_curve(u: &Fq) -> (Fq, Fq) {
+    let a = -Fq::from(ISO_MINUS_A);
+    let b = Fq::from(ISO_B);
+    let z = -Fq::from(MINUS_Z);
+
+    // tv1 = inv0(Z^2 u^4 + Z u^2)
+    let zu2 = z * &u.square();
+    let tv1 = inv0(&(zu2.square() + &zu2));
+    // x1 = (-B / A) (1 + tv1), or B / (Z A) if tv1 is 0
+    let exceptional = tv1.is_zero() as usize;
+    let x1 = [-b * &inv0(&a) * &(Fq::one() + &tv1), b * &inv0(&(z * &a))][exceptional];
+    let gx1 = iso_curve_equation(&x1);
+    let x2 = zu2 * &x1;
+    let gx2 = iso_curve_equation(&x2);
+
+    // gx1 * gx2 = Z^3 u^6 gx1^2, so gx2 is a square if gx1 is not
+    let is_square = !gx1.legendre().is_qnr() as usize;
+    let x = [x2, x1][is_square];
+    let gx = [gx2, gx1][is_square];
+    let y = gx.sqrt().expect("one of gx1 and gx2 is a square");
+
+    // the sign of y is the sign of u
+    let flip = (sgn0(u) != sgn0(&y)) as usize;
+    (x, [y, -y][flip])
+}
+
+/// The isogeny from `E'` to G1's curve. The kernel `(2, 0)` is mapped to the identity.
+pub fn iso_map(x: &Fq, y: &Fq) -> G1Projective {
+    let two = Fq::from(2u64);
+    let three = Fq::from(3u64);
+    let d = inv0(&(*x - &two));
+    let in_kernel = d.is_zero() as usize;
+
+    let x = (*x - &(three * &d)) * &inv0(&Fq::from(4u64));
+    let y = *y * &(Fq::one() + &(three * &d.square())) * &inv0(&Fq::from(8u64));
+    // the coordinates are replaced by the generator's so that the point is valid
+    let generator = G1Affine::prime_subgroup_generator();
+    let point = G1Affine::new(
+        [x, generator.x][in_kernel],
+        [y, generator.y][in_kernel],
+        false,
+    )
+    .into_projective();
+    [point, G1Projective::zero()][in_kernel]
+}
+
+/// `y^2` for the given `x` on `E'`
+fn iso_curve_equation(x: &Fq) -> Fq {
+    let a = -Fq::from(ISO_MINUS_A);
+    let b = Fq::from(ISO_B);
+    x.square() * x + &(a * x) + &b
+}
+
+/// The inverse of the element, or 0 for 0
+fn inv0(x: &Fq) -> Fq {
+    x.inverse().unwrap_or_else(Fq::zero)
+}
+
+/// The draft's sign of the element, i.e. its parity
+fn sgn0(x: &Fq) -> bool {
+    x.into_repr().is_odd()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::Parameters, curves::models::bls12::Bls12Parameters};
+    use rand::RngCore;
+    use std::str::FromStr;
+
+    use crate::hash_to_curve::try_and_increment::TryAndIncrement;
+
+    fn point(x: &str, y: &str) -> G1Projective {
+        G1Affine::new(Fq::from_str(x).unwrap(), Fq::from_str(y).unwrap(), false).into_projective()
+    }
+
+    #[test]
+    fn maps_to_the_curve() {
+        // computed with an independent implementation of the map and isogeny
+        let expected = [
+            (
+                Fq::zero(),
+                point(
+                    "42648836908090737524375480496122326660464882757804381529492821880167601031244290421825227389679655210994029192807",
+                    "35144723379762937457178037852050910925446026358235025042316100519932032474134187195561223923930207259556160788904",
+                ),
+            ),
+            (
+                Fq::one(),
+                point(
+                    "164209460120093202525974991465125846716801938556796151675779479835599436055187394974778936164229810151646884160710",
+                    "248147570073866406917373859971518251652346058276844344660150440270648084470955849149623680188515216543635615952678",
+                ),
+            ),
+            (
+                -Fq::one(),
+                point(
+                    "164209460120093202525974991465125846716801938556796151675779479835599436055187394974778936164229810151646884160710",
+                    "10516855939102687093278873723375281884047454478070315879733822396072383877384973625345207951058143580804705505499",
+                ),
+            ),
+            (
+                Fq::from(5u64),
+                point(
+                    "238120353747879905419798667328097419778994446378586868774824702553253061211827167461665870038788378856504914295703",
+                    "115384845553034943617553839574236305326855934104476172360918914373202916240543743646673075728355468394510963935071",
+                ),
+            ),
+        ];
+        for (u, expected) in expected.iter() {
+            let mapped = map_to_curve(u);
+            assert!(mapped.into_affine().is_on_curve());
+            assert_eq!(&mapped, expected);
+        }
+    }
+
+    #[test]
+    fn maps_the_kernel_to_the_identity() {
+        assert!(iso_map(&Fq::from(2u64), &Fq::zero()).is_zero());
+    }
+
+    #[test]
+    fn reads_big_endian_bytes() {
+        assert_eq!(field_element_from_bytes(&[1, 0]), Fq::from(256u64));
+        // 2^512 is reduced
+        let mut bytes = [0u8; FIELD_ELEMENT_BYTES + 1];
+        bytes[0] = 1;
+        assert_eq!(
+            field_element_from_bytes(&bytes),
+            Fq::from(256u64).pow(&[FIELD_ELEMENT_BYTES as u64])
+        );
+    }
+
+    #[test]
+    fn hashes_to_the_subgroup() {
+        let hasher = &*DIRECT_SSWU_HASH_TO_G1;
+        let try_and_increment =
+            TryAndIncrement::<_, <Parameters as Bls12Parameters>::G1Parameters>::new(&DirectHasher);
+        let mut rng = rand::thread_rng();
+        for length in &[0, 10, 25, 50, 100, 200, 300] {
+            let mut input = vec![0; *length];
+            rng.fill_bytes(&mut input);
+            let hash = hasher.hash(&b"domain"[..], &input, &b"extra"[..]).unwrap();
+            let affine = hash.into_affine();
+            assert!(affine.is_on_curve());
+            assert!(affine.is_in_correct_subgroup_assuming_on_curve());
+            assert!(!hash.is_zero());
+
+            // deterministic, and separated by the domain and the extra data
+            assert_eq!(
+                hasher.hash(&b"domain"[..], &input, &b"extra"[..]).unwrap(),
+                hash
+            );
+            assert_ne!(
+                hasher.hash(&b"other"[..], &input, &b"extra"[..]).unwrap(),
+                hash
+            );
+            assert_ne!(hasher.hash(&b"domain"[..], &input, &[]).unwrap(), hash);
+            assert_ne!(
+                try_and_increment
+                    .hash(&b"domain"[..], &input, &b"extra"[..])
+                    .unwrap(),
+                hash
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "composite-hash")]
+    fn hashes_with_the_composite_hasher() {
+        let hash = COMPOSITE_SSWU_HASH_TO_G1
+            .hash(&b"domain"[..], &b"message"[..], &[])
+            .unwrap();
+        assert!(hash
+            .into_affine()
+            .is_in_correct_subgroup_assuming_on_curve());
+        assert_ne!(
+            DIRECT_SSWU_HASH_TO_G1
+                .hash(&b"domain"[..], &b"message"[..], &[])
+                .unwrap(),
+            hash
+        );
+    }
+}
@@ -1,14 +1,18 @@
 use bench_utils::{end_timer, start_timer};
-use byteorder::WriteBytesExt;
+use core::marker::PhantomData;
 use log::trace;
-use std::marker::PhantomData;
 
+#[cfg(feature = "std")]
+use super::monitor::AttemptMonitor;
 use super::HashToCurve;
-use crate::hashers::{
-    composite::{CompositeHasher, COMPOSITE_HASHER, CRH},
-    DirectHasher, Hasher,
+#[cfg(all(feature = "composite-hash", feature = "std"))]
+use crate::hashers::composite::{
+    CompactCRH, CompositeHasher, COMPACT_COMPOSITE_HASHER, COMPOSITE_HASHER, CRH,
 };
-use crate::BLSError;
+#[cfg(feature = "std")]
+use crate::hashers::DirectHasher;
+use crate::hashers::Hasher;
+use crate::{BLSError, Vec};
 
 use algebra::{
     bls12_377::Parameters,
@@ -17,26 +21,51 @@ use algebra::{
     AffineCurve, ConstantSerializedSize, Zero,
 };
 
+#[cfg(feature = "std")]
 use once_cell::sync::Lazy;
+#[cfg(feature = "std")]
+use std::sync::Arc;
 
 const NUM_TRIES: u8 = 255;
 
 /// Composite (Bowe-Hopwood CRH, Blake2x XOF) Try-and-Increment hasher for BLS 12-377.
+#[cfg(all(feature = "composite-hash", feature = "std"))]
 pub static COMPOSITE_HASH_TO_G1: Lazy<
     TryAndIncrement<CompositeHasher<CRH>, <Parameters as Bls12Parameters>::G1Parameters>,
 > = Lazy::new(|| TryAndIncrement::new(&*COMPOSITE_HASHER));
 
+/// Same as `COMPOSITE_HASH_TO_G1`, with the small-memory `COMPACT_COMPOSITE_HASHER`
+#[cfg(all(feature = "composite-hash", feature = "std"))]
+pub static COMPACT_COMPOSITE_HASH_TO_G1: Lazy<
+    TryAndIncrement<CompositeHasher<CompactCRH>, <Parameters as Bls12Parameters>::G1Parameters>,
+> = Lazy::new(|| TryAndIncrement::new(&*COMPACT_COMPOSITE_HASHER));
+
 /// Direct (Blake2s CRH, Blake2x XOF) Try-and-Increment hasher for BLS 12-377.
 /// Equivalent to Blake2xs.
+#[cfg(feature = "std")]
 pub static DIRECT_HASH_TO_G1: Lazy<
     TryAndIncrement<DirectHasher, <Parameters as Bls12Parameters>::G1Parameters>,
 > = Lazy::new(|| TryAndIncrement::new(&DirectHasher));
 
+/// How try-and-increment derives the candidate bytes of each attempt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashMode {
+    /// Attempt `i` hashes `i || extra_data || message`. This is the mode deployed by Celo.
+    Counter,
+    /// `extra_data || message` is compressed once with the CRH, and attempt `i` reads the
+    /// `i`-th window of a single XOF output over it. Any attempt can be computed directly by
+    /// seeking into the XOF, without a counter in the hashed input.
+    Seek,
+}
+
 /// A try-and-increment method for hashing to G1 and G2. See page 521 in
 /// https://link.springer.com/content/pdf/10.1007/3-540-45682-1_30.pdf.
 #[derive(Clone)]
 pub struct TryAndIncrement<'a, H, P> {
     hasher: &'a H,
+    mode: HashMode,
+    #[cfg(feature = "std")]
+    monitor: Option<Arc<AttemptMonitor>>,
     curve_params: PhantomData<P>,
 }
 
@@ -50,9 +79,43 @@ where
     pub fn new(h: &'a H) -> Self {
         TryAndIncrement {
             hasher: h,
+            mode: HashMode::Counter,
+            #[cfg(feature = "std")]
+            monitor: None,
             curve_params: PhantomData,
         }
     }
+
+    /// Sets the mode in which the candidate bytes of each attempt are derived. The modes
+    /// produce different hashes, so signers and verifiers must agree on it.
+    pub fn with_mode(mut self, mode: HashMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// The mode in which the candidate bytes of each attempt are derived
+    pub fn mode(&self) -> HashMode {
+        self.mode
+    }
+
+    /// Records the attempts of each hash in the monitor, which may be shared with other
+    /// hashers to the same group, see the `monitor` module
+    #[cfg(feature = "std")]
+    pub fn with_monitor(mut self, monitor: Arc<AttemptMonitor>) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// The number of XOF bytes from which the candidate of each attempt is taken
+    pub fn attempt_length() -> usize {
+        hash_length(GroupAffine::<P>::SERIALIZED_SIZE)
+    }
+
+    /// The total output length of the XOF in `HashMode::Seek`, which covers every attempt.
+    /// Attempt `i` reads `attempt_length()` bytes at offset `i * attempt_length()`.
+    pub fn seek_xof_length() -> usize {
+        NUM_TRIES as usize * Self::attempt_length()
+    }
 }
 
 impl<'a, H, P> HashToCurve for TryAndIncrement<'a, H, P>
@@ -71,6 +134,17 @@ where
         self.hash_with_attempt(domain, message, extra_data)
             .map(|res| res.0)
     }
+
+    fn max_input_length(&self) -> Option<usize> {
+        match self.mode {
+            // one byte of the hasher's input is taken by the counter
+            HashMode::Counter => self
+                .hasher
+                .max_input_length()
+                .map(|len| len.saturating_sub(1)),
+            HashMode::Seek => self.hasher.max_input_length(),
+        }
+    }
 }
 
 impl<'a, H, P> TryAndIncrement<'a, H, P>
@@ -85,53 +159,146 @@ where
         message: &[u8],
         extra_data: &[u8],
     ) -> Result<(GroupProjective<P>, usize), BLSError> {
-        let num_bytes = GroupAffine::<P>::SERIALIZED_SIZE;
-        let hash_loop_time = start_timer!(|| "try_and_increment::hash_loop");
-        let hash_bytes = hash_length(num_bytes);
-
-        let mut counter = [0; 1];
-        for c in 0..NUM_TRIES {
-            (&mut counter[..]).write_u8(c as u8)?;
-
-            // concatenate the message with the counter
-            let msg = &[&counter, extra_data, &message].concat();
-
-            // produce a hash with sufficient length
-            let candidate_hash = self.hasher.hash(domain, msg, hash_bytes)?;
-
-            // handle the Celo deployed bit extraction logic
-            #[cfg(feature = "compat")]
-            let candidate_hash = {
-                use algebra::serialize::{Flags, SWFlags};
+        self.hash_from_attempt(domain, message, extra_data, 0)
+    }
 
-                let mut candidate_hash = candidate_hash[..num_bytes].to_vec();
-                let positive_flag = candidate_hash[num_bytes - 1] & 2 != 0;
-                if positive_flag {
-                    candidate_hash[num_bytes - 1] |= SWFlags::PositiveY.u8_bitmask();
-                } else {
-                    candidate_hash[num_bytes - 1] &= !SWFlags::PositiveY.u8_bitmask();
-                }
-                candidate_hash
-            };
+    /// Same as `hash_with_attempt`, but starts from the counter `first_attempt` instead of 0,
+    /// skipping the hashes of the earlier counters.
+    ///
+    /// This is only an optimization for callers which computed the counter themselves with
+    /// `hash_with_attempt`, e.g. from hashing the same message earlier. The earlier counters
+    /// are not checked, so a larger counter produces another point than `hash`: a counter
+    /// received from an untrusted party must not be used to verify signatures.
+    pub fn hash_from_attempt(
+        &self,
+        domain: &[u8],
+        message: &[u8],
+        extra_data: &[u8],
+        first_attempt: usize,
+    ) -> Result<(GroupProjective<P>, usize), BLSError> {
+        let hash_loop_time = start_timer!(|| "try_and_increment::hash_loop");
+        let seek_input = self.seek_input(domain, message, extra_data)?;
 
-            if let Some(p) = GroupAffine::<P>::from_random_bytes(&candidate_hash[..num_bytes]) {
+        for c in first_attempt..NUM_TRIES as usize {
+            let candidate =
+                self.try_attempt(domain, message, extra_data, seek_input.as_deref(), c)?;
+            if let Some(p) = candidate {
+                #[cfg(feature = "std")]
                 trace!(
                     "succeeded hashing \"{}\" to curve in {} tries",
                     hex::encode(message),
                     c
                 );
+                #[cfg(not(feature = "std"))]
+                trace!("succeeded hashing to curve in {} tries", c);
                 end_timer!(hash_loop_time);
-
-                let scaled = p.scale_by_cofactor();
-                if scaled.is_zero() {
-                    continue;
+                #[cfg(feature = "std")]
+                if let (Some(monitor), 0) = (&self.monitor, first_attempt) {
+                    monitor.record(c);
                 }
-
-                return Ok((scaled, c as usize));
+                return Ok((p, c));
             }
         }
+        #[cfg(feature = "std")]
+        if let (Some(monitor), 0) = (&self.monitor, first_attempt) {
+            monitor.record_exhausted();
+        }
         Err(BLSError::HashToCurveError)
     }
+
+    /// Recomputes only the given attempt of `hash_with_attempt`, e.g. to verify a hash for
+    /// which the successful attempt was transmitted along with it. In `HashMode::Seek` this
+    /// costs a single window of the XOF.
+    ///
+    /// The earlier attempts are not checked, so this returns a point for any attempt which
+    /// decodes to one, not only for the first one. Callers which need the point of `hash` must
+    /// either trust the attempt or check the earlier attempts fail with this same method.
+    pub fn hash_at_attempt(
+        &self,
+        domain: &[u8],
+        message: &[u8],
+        extra_data: &[u8],
+        attempt: usize,
+    ) -> Result<GroupProjective<P>, BLSError> {
+        if attempt >= NUM_TRIES as usize {
+            return Err(BLSError::HashToCurveError);
+        }
+        let seek_input = self.seek_input(domain, message, extra_data)?;
+        self.try_attempt(domain, message, extra_data, seek_input.as_deref(), attempt)?
+            .ok_or(BLSError::HashToCurveError)
+    }
+
+    // The CRH output which every attempt reads from in `HashMode::Seek`
+    fn seek_input(
+        &self,
+        domain: &[u8],
+        message: &[u8],
+        extra_data: &[u8],
+    ) -> Result<Option<Vec<u8>>, BLSError> {
+        match self.mode {
+            HashMode::Counter => Ok(None),
+            HashMode::Seek => self
+                .hasher
+                .crh(
+                    domain,
+                    &[extra_data, message].concat(),
+                    Self::seek_xof_length(),
+                )
+                .map(Some),
+        }
+    }
+
+    // Derives the candidate bytes of the attempt and decodes them to a point of the subgroup
+    fn try_attempt(
+        &self,
+        domain: &[u8],
+        message: &[u8],
+        extra_data: &[u8],
+        seek_input: Option<&[u8]>,
+        attempt: usize,
+    ) -> Result<Option<GroupProjective<P>>, BLSError> {
+        let num_bytes = GroupAffine::<P>::SERIALIZED_SIZE;
+        let hash_bytes = Self::attempt_length();
+
+        // produce a hash with sufficient length
+        let candidate_hash = match seek_input {
+            Some(seek_input) => self.hasher.xof_at(
+                domain,
+                seek_input,
+                Self::seek_xof_length(),
+                attempt * hash_bytes,
+                hash_bytes,
+            )?,
+            None => {
+                let counter = [attempt as u8];
+
+                // concatenate the message with the counter
+                let msg = &[&counter, extra_data, &message].concat();
+                self.hasher.hash(domain, msg, hash_bytes)?
+            }
+        };
+
+        // handle the Celo deployed bit extraction logic
+        #[cfg(feature = "compat")]
+        let candidate_hash = {
+            use algebra::serialize::{Flags, SWFlags};
+
+            let mut candidate_hash = candidate_hash[..num_bytes].to_vec();
+            let positive_flag = candidate_hash[num_bytes - 1] & 2 != 0;
+            if positive_flag {
+                candidate_hash[num_bytes - 1] |= SWFlags::PositiveY.u8_bitmask();
+            } else {
+                candidate_hash[num_bytes - 1] &= !SWFlags::PositiveY.u8_bitmask();
+            }
+            candidate_hash
+        };
+
+        Ok(
+            GroupAffine::<P>::from_random_bytes(&candidate_hash[..num_bytes])
+                .map(|p| p.scale_by_cofactor())
+                .filter(|scaled| !scaled.is_zero()),
+        )
+    }
 }
 
 /// Given `n` bytes, it returns the value rounded to the nearest multiple of 256 bits (in bytes)
@@ -139,9 +306,8 @@ where
 ///      2. given 96 = 768 bits, it will return 96 bytes (no rounding needed since 768 is already a
 ///         multiple of 256)
 fn hash_length(n: usize) -> usize {
-    let bits = (n * 8) as f64 / 256.0;
-    let rounded_bits = bits.ceil() * 256.0;
-    rounded_bits as usize / 8
+    // `f64::ceil` needs `std`
+    (n + 31) / 32 * 32
 }
 
 #[cfg(test)]
@@ -163,6 +329,7 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "composite-hash")]
     fn hash_to_curve_composite_g1() {
         let h = CompositeHasher::<CRH>::new().unwrap();
         hash_to_curve_test::<<Parameters as Bls12Parameters>::G1Parameters, _>(h)
@@ -175,11 +342,130 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "composite-hash")]
     fn hash_to_curve_composite_g2() {
         let h = CompositeHasher::<CRH>::new().unwrap();
         hash_to_curve_test::<<Parameters as Bls12Parameters>::G2Parameters, _>(h)
     }
 
+    #[test]
+    fn hash_from_attempt_skips_counters() {
+        let h = DirectHasher;
+        let hasher = TryAndIncrement::<_, <Parameters as Bls12Parameters>::G1Parameters>::new(&h);
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let (domain, msg, extra_data) = generate_test_data(&mut rng);
+            let (hash, attempt) = hasher
+                .hash_with_attempt(&domain, &msg, &extra_data)
+                .unwrap();
+            for hint in 0..=attempt {
+                assert_eq!(
+                    hasher
+                        .hash_from_attempt(&domain, &msg, &extra_data, hint)
+                        .unwrap(),
+                    (hash, attempt)
+                );
+            }
+            let (_, later) = hasher
+                .hash_from_attempt(&domain, &msg, &extra_data, attempt + 1)
+                .unwrap();
+            assert!(later > attempt);
+        }
+    }
+
+    #[test]
+    fn monitor_records_the_attempts() {
+        use crate::hash_to_curve::monitor::{AttemptMonitor, MonitorConfig};
+
+        let h = DirectHasher;
+        let monitor = Arc::new(AttemptMonitor::new(MonitorConfig::default(), |warning| {
+            panic!("unexpected warning {}", warning)
+        }));
+        let hasher = TryAndIncrement::<_, <Parameters as Bls12Parameters>::G1Parameters>::new(&h)
+            .with_monitor(monitor.clone());
+        let mut rng = rand::thread_rng();
+        let mut histogram = vec![];
+        for _ in 0..20 {
+            let (domain, msg, extra_data) = generate_test_data(&mut rng);
+            let (_, attempt) = hasher
+                .hash_with_attempt(&domain, &msg, &extra_data)
+                .unwrap();
+            if histogram.len() <= attempt {
+                histogram.resize(attempt + 1, 0);
+            }
+            histogram[attempt] += 1;
+            // hashes with a hint are not recorded
+            hasher
+                .hash_from_attempt(&domain, &msg, &extra_data, attempt + 1)
+                .unwrap();
+        }
+
+        let stats = monitor.stats();
+        assert_eq!(stats.hashes, 20);
+        assert_eq!(stats.histogram, histogram);
+        assert_eq!(stats.warnings, 0);
+    }
+
+    #[test]
+    fn hash_at_attempt_recomputes_the_hash() {
+        let h = DirectHasher;
+        let counter = TryAndIncrement::<_, <Parameters as Bls12Parameters>::G1Parameters>::new(&h);
+        let seek = counter.clone().with_mode(HashMode::Seek);
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let (domain, msg, extra_data) = generate_test_data(&mut rng);
+            for hasher in &[&counter, &seek] {
+                let (hash, attempt) = hasher
+                    .hash_with_attempt(&domain, &msg, &extra_data)
+                    .unwrap();
+                assert_eq!(
+                    hasher
+                        .hash_at_attempt(&domain, &msg, &extra_data, attempt)
+                        .unwrap(),
+                    hash
+                );
+                for earlier in 0..attempt {
+                    hasher
+                        .hash_at_attempt(&domain, &msg, &extra_data, earlier)
+                        .unwrap_err();
+                }
+            }
+            // the modes hash to different points
+            assert_ne!(
+                counter.hash(&domain, &msg, &extra_data).unwrap(),
+                seek.hash(&domain, &msg, &extra_data).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    // the compat bit fix alters the window before decoding
+    #[cfg(not(feature = "compat"))]
+    fn seek_mode_reads_windows_of_one_xof() {
+        type G1 = <Parameters as Bls12Parameters>::G1Parameters;
+        let h = DirectHasher;
+        let hasher = TryAndIncrement::<_, G1>::new(&h).with_mode(HashMode::Seek);
+        let (domain, msg, extra_data) = generate_test_data(&mut rand::thread_rng());
+        let (hash, attempt) = hasher
+            .hash_with_attempt(&domain, &msg, &extra_data)
+            .unwrap();
+
+        let length = TryAndIncrement::<DirectHasher, G1>::attempt_length();
+        let total = TryAndIncrement::<DirectHasher, G1>::seek_xof_length();
+        let crh = h
+            .crh(&domain, &[&extra_data[..], &msg[..]].concat(), total)
+            .unwrap();
+        let xof = h.xof(&domain, &crh, total).unwrap();
+        let window = &xof[attempt * length..(attempt + 1) * length];
+        let num_bytes = GroupAffine::<G1>::SERIALIZED_SIZE;
+        assert_eq!(
+            GroupAffine::<G1>::from_random_bytes(&window[..num_bytes])
+                .unwrap()
+                .scale_by_cofactor(),
+            hash
+        );
+    }
+
     fn hash_to_curve_test<P: SWModelParameters, X: Hasher<Error = BLSError>>(h: X) {
         let hasher = TryAndIncrement::<X, P>::new(&h);
         let mut rng = rand::thread_rng();
@@ -226,7 +512,7 @@ mod test {
     }
 }
 
-#[cfg(all(test, feature = "compat"))]
+#[cfg(all(test, feature = "compat", feature = "composite-hash"))]
 mod compat_tests {
     #![allow(clippy::op_ref)]
 
@@ -363,7 +649,7 @@ mod compat_tests {
     }
 }
 
-#[cfg(all(test, not(feature = "compat")))]
+#[cfg(all(test, not(feature = "compat"), feature = "composite-hash"))]
 mod non_compat_tests {
     use super::*;
     use crate::hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1;
@@ -1,29 +1,72 @@
 //! Utilities for hashing using a fixed-length CRH. Consider using the re-exported
 //! COMPOSITE_HASHER which is already instantiated with the Bowe Hopwood Pedersen CRH and
 //! Blake2x as the XOF
-use crate::{hashers::DirectHasher, BLSError, Hasher};
+//!
+//! The CRH's generators take about 10MB. `COMPACT_COMPOSITE_HASHER` computes the same hashes
+//! with `CompactCRH`, which keeps the first generator of each window, about 100KB, and derives
+//! the others while hashing. It is slower, and meant for memory constrained verifiers, e.g.
+//! on mobile or in wasm.
+//!
+//! The windows of the CRH are independent, so its input can be streamed with
+//! `StreamingHasher`: each block of `STREAMING_BLOCK_LENGTH` bytes fills whole windows, which
+//! are evaluated as soon as the block arrives.
+use crate::{
+    hashers::{DirectHasher, StreamingHasher},
+    BLSError, Hasher, Vec,
+};
 
-use algebra::{ed_on_bw6_761::EdwardsProjective as Edwards, CanonicalSerialize, ProjectiveCurve};
+use algebra::{
+    ed_on_bw6_761::EdwardsProjective as Edwards, CanonicalSerialize, ProjectiveCurve, UniformRand,
+    Zero,
+};
 
 use blake2s_simd::Params;
-use crypto_primitives::crh::{
-    bowe_hopwood::BoweHopwoodPedersenCRH, pedersen::PedersenWindow, FixedLengthCRH,
+use crypto_primitives::{
+    crh::{bowe_hopwood::BoweHopwoodPedersenCRH, pedersen::PedersenWindow, FixedLengthCRH},
+    Error as CRHError,
 };
+#[cfg(feature = "std")]
 use once_cell::sync::Lazy;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaChaRng;
 
+/// The number of input bits encoded by each generator of the Bowe Hopwood CRH
+const CHUNK_SIZE: usize = 3;
+
+/// The number of input bits hashed by each window of `CRH`. The gadget evaluates the CRH one
+/// block at a time, and pads the last block with zeros.
+pub const CIRCUIT_BLOCK_BITS: usize = 93 * CHUNK_SIZE;
+
+/// The number of windows of `CRH`, i.e. the maximum number of blocks of the gadget's input
+pub const CIRCUIT_MAX_BLOCKS: usize = 560;
+
+/// The maximum number of bytes hashed by `CRH`, natively or in the circuit
+pub const MAX_CIRCUIT_INPUT_LENGTH: usize = CIRCUIT_BLOCK_BITS * CIRCUIT_MAX_BLOCKS / 8;
+
+/// The number of windows filled by `STREAMING_BLOCK_LENGTH` bytes
+const STREAMING_BLOCK_WINDOWS: usize = 8;
+
+/// The smallest number of input bytes which fills whole windows of the CRH
+pub const STREAMING_BLOCK_LENGTH: usize = CIRCUIT_BLOCK_BITS * STREAMING_BLOCK_WINDOWS / 8;
+
+/// Returns whether the try-and-increment input of the message, i.e. the counter byte followed
+/// by `extra_data` and `message`, fits in the input of the hash to group gadget. Seals of
+/// messages for which this is false can be created natively but never proven in the SNARK.
+pub fn fits_in_circuit(message: &[u8], extra_data: &[u8]) -> bool {
+    1 + extra_data.len() + message.len() <= MAX_CIRCUIT_INPUT_LENGTH
+}
+
 // Fix to get around leaking a private type in a public interface
 mod window {
-    use super::PedersenWindow;
+    use super::{PedersenWindow, CHUNK_SIZE, CIRCUIT_BLOCK_BITS, CIRCUIT_MAX_BLOCKS};
 
     /// The window which will be used with the Fixed Length CRH
     #[derive(Clone)]
     pub struct Window;
 
     impl PedersenWindow for Window {
-        const WINDOW_SIZE: usize = 93;
-        const NUM_WINDOWS: usize = 560;
+        const WINDOW_SIZE: usize = CIRCUIT_BLOCK_BITS / CHUNK_SIZE;
+        const NUM_WINDOWS: usize = CIRCUIT_MAX_BLOCKS;
     }
 }
 
@@ -33,9 +76,153 @@ pub type CRH = BoweHopwoodPedersenCRH<Edwards, window::Window>;
 
 /// Lazily evaluated composite hasher instantiated over the
 /// Bowe-Hopwood-Pedersen CRH.
+#[cfg(feature = "std")]
 pub static COMPOSITE_HASHER: Lazy<CompositeHasher<CRH>> =
     Lazy::new(|| CompositeHasher::<CRH>::new().unwrap());
 
+/// Lazily evaluated composite hasher instantiated over `CompactCRH`, which computes the same
+/// hashes as `COMPOSITE_HASHER` with a fraction of its memory
+#[cfg(feature = "std")]
+pub static COMPACT_COMPOSITE_HASHER: Lazy<CompositeHasher<CompactCRH>> =
+    Lazy::new(|| CompositeHasher::<CompactCRH>::new().unwrap());
+
+/// A Bowe Hopwood CRH whose windows can be evaluated separately, which is needed to stream
+/// its input
+pub trait WindowedCRH: FixedLengthCRH<Output = Edwards> {
+    /// Returns the sum of the windows starting at `first_window` over the bits, which must
+    /// be a whole number of chunks. Fails if the bits do not fit in the remaining windows.
+    fn evaluate_windows(
+        parameters: &Self::Parameters,
+        first_window: usize,
+        bits: &[bool],
+    ) -> Result<Edwards, CRHError>;
+}
+
+/// Returns the bits of the bytes, each read from its least significant bit, padded with
+/// zeros to a whole number of chunks as the CRH does
+fn to_chunk_bits(input: &[u8]) -> Vec<bool> {
+    let mut bits = input
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1 == 1))
+        .collect::<Vec<_>>();
+    let num_chunks = (bits.len() + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    bits.resize(num_chunks * CHUNK_SIZE, false);
+    bits
+}
+
+/// Encodes a chunk of 3 bits with its generator: (1 + c0 + 2 * c1) * (1 - 2 * c2) times the
+/// generator
+fn encode_chunk(generator: &Edwards, chunk: &[bool]) -> Edwards {
+    let mut encoded = *generator;
+    if chunk[0] {
+        encoded += generator;
+    }
+    if chunk[1] {
+        encoded += generator.double();
+    }
+    if chunk[2] {
+        encoded = -encoded;
+    }
+    encoded
+}
+
+fn check_windows(first_window: usize, bits: &[bool], num_windows: usize) -> Result<(), CRHError> {
+    let window_bits = window::Window::WINDOW_SIZE * CHUNK_SIZE;
+    let needed = (bits.len() + window_bits - 1) / window_bits;
+    if first_window + needed > num_windows {
+        return Err(format!(
+            "input of {} bits at window {} is too long for the CRH",
+            bits.len(),
+            first_window
+        )
+        .into());
+    }
+    Ok(())
+}
+
+impl WindowedCRH for CRH {
+    fn evaluate_windows(
+        parameters: &Self::Parameters,
+        first_window: usize,
+        bits: &[bool],
+    ) -> Result<Edwards, CRHError> {
+        check_windows(first_window, bits, parameters.generators.len())?;
+        let window_bits = window::Window::WINDOW_SIZE * CHUNK_SIZE;
+        Ok(bits
+            .chunks(window_bits)
+            .zip(&parameters.generators[first_window..])
+            .flat_map(|(window, generators)| window.chunks(CHUNK_SIZE).zip(generators))
+            .map(|(chunk, generator)| encode_chunk(generator, chunk))
+            .sum())
+    }
+}
+
+/// The generators of `CompactCRH`, i.e. the first generator of each window of `CRH`
+#[derive(Clone, Debug, Default)]
+pub struct CompactParameters {
+    bases: Vec<Edwards>,
+}
+
+/// The Bowe Hopwood Pedersen CRH of `CRH`, which only keeps the first generator of each
+/// window. The other generators of a window are 16 times the previous one, and are derived
+/// while hashing, at the cost of 4 doublings per chunk of 3 input bits.
+#[derive(Clone, Debug)]
+pub struct CompactCRH;
+
+impl FixedLengthCRH for CompactCRH {
+    const INPUT_SIZE_BITS: usize = CRH::INPUT_SIZE_BITS;
+    type Output = Edwards;
+    type Parameters = CompactParameters;
+
+    fn setup<R: Rng>(rng: &mut R) -> Result<Self::Parameters, CRHError> {
+        // `CRH::setup` draws the first generator of each window in the same way
+        let bases = (0..window::Window::NUM_WINDOWS)
+            .map(|_| Edwards::rand(rng))
+            .collect();
+        Ok(CompactParameters { bases })
+    }
+
+    fn evaluate(parameters: &Self::Parameters, input: &[u8]) -> Result<Edwards, CRHError> {
+        Self::evaluate_windows(parameters, 0, &to_chunk_bits(input))
+    }
+}
+
+impl WindowedCRH for CompactCRH {
+    fn evaluate_windows(
+        parameters: &Self::Parameters,
+        first_window: usize,
+        bits: &[bool],
+    ) -> Result<Edwards, CRHError> {
+        check_windows(first_window, bits, parameters.bases.len())?;
+        let window_bits = window::Window::WINDOW_SIZE * CHUNK_SIZE;
+        let mut result = Edwards::zero();
+        for (window, base) in bits
+            .chunks(window_bits)
+            .zip(&parameters.bases[first_window..])
+        {
+            let mut generator = *base;
+            for chunk in window.chunks(CHUNK_SIZE) {
+                result += encode_chunk(&generator, chunk);
+                for _ in 0..4 {
+                    generator.double_in_place();
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// The state of a streaming CRH computation of `CompositeHasher`
+#[derive(Clone, Debug)]
+pub struct CompositeState {
+    /// The sum of the windows evaluated so far
+    sum: Edwards,
+    /// The number of windows evaluated so far
+    windows: usize,
+    /// The input which does not fill a block yet, shorter than `STREAMING_BLOCK_LENGTH`
+    pending: Vec<u8>,
+}
+
 /// Uses the Bowe-Hopwood-Pedersen hash (instantiated with a prng) as a CRH and Blake2x as the XOF.
 /// The CRH does _not_ use the domain or the output bytes.
 #[derive(Clone, Debug)]
@@ -72,17 +259,60 @@ impl<H: FixedLengthCRH> CompositeHasher<H> {
     }
 }
 
+impl<H: FixedLengthCRH<Output = Edwards>> CompositeHasher<H> {
+    fn serialize_crh_output(h: Edwards) -> Result<Vec<u8>, BLSError> {
+        let mut res = vec![];
+        h.into_affine().x.serialize(&mut res)?;
+        Ok(res)
+    }
+}
+
+/// The CRH is evaluated over each block as soon as it is complete, so that the state only
+/// buffers less than `STREAMING_BLOCK_LENGTH` bytes.
+impl<H: WindowedCRH> StreamingHasher for CompositeHasher<H> {
+    type State = CompositeState;
+
+    fn init(&self, _: &[u8], _: usize) -> Result<CompositeState, Self::Error> {
+        Ok(CompositeState {
+            sum: Edwards::zero(),
+            windows: 0,
+            pending: Vec::with_capacity(STREAMING_BLOCK_LENGTH),
+        })
+    }
+
+    fn update(&self, state: &mut CompositeState, mut data: &[u8]) -> Result<(), Self::Error> {
+        while !data.is_empty() {
+            let take = (STREAMING_BLOCK_LENGTH - state.pending.len()).min(data.len());
+            state.pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if state.pending.len() == STREAMING_BLOCK_LENGTH {
+                let bits = to_chunk_bits(&state.pending);
+                state.sum += H::evaluate_windows(&self.parameters, state.windows, &bits)?;
+                state.windows += STREAMING_BLOCK_WINDOWS;
+                state.pending.clear();
+            }
+        }
+        Ok(())
+    }
+
+    fn finalize(&self, state: CompositeState) -> Result<Vec<u8>, Self::Error> {
+        let bits = to_chunk_bits(&state.pending);
+        let sum = state.sum + H::evaluate_windows(&self.parameters, state.windows, &bits)?;
+        Self::serialize_crh_output(sum)
+    }
+}
+
 impl<H: FixedLengthCRH<Output = Edwards>> Hasher for CompositeHasher<H> {
     type Error = BLSError;
 
     // TODO: Should we improve the trait design somehow? Seems like there's a bad abstraction
     // here if we do not use the 2 params
     fn crh(&self, _: &[u8], message: &[u8], _: usize) -> Result<Vec<u8>, Self::Error> {
-        let h = H::evaluate(&self.parameters, message)?.into_affine();
-        let mut res = vec![];
-        h.x.serialize(&mut res)?;
+        Self::serialize_crh_output(H::evaluate(&self.parameters, message)?)
+    }
 
-        Ok(res)
+    fn max_input_length(&self) -> Option<usize> {
+        Some(H::INPUT_SIZE_BITS / 8)
     }
 
     fn xof(
@@ -93,12 +323,28 @@ impl<H: FixedLengthCRH<Output = Edwards>> Hasher for CompositeHasher<H> {
     ) -> Result<Vec<u8>, Self::Error> {
         DirectHasher.xof(domain, hashed_message, xof_digest_length)
     }
+
+    fn xof_at(
+        &self,
+        domain: &[u8],
+        hashed_message: &[u8],
+        xof_digest_length: usize,
+        offset: usize,
+        output_size_in_bytes: usize,
+    ) -> Result<Vec<u8>, Self::Error> {
+        DirectHasher.xof_at(
+            domain,
+            hashed_message,
+            xof_digest_length,
+            offset,
+            output_size_in_bytes,
+        )
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::hashers::Hasher;
     use rand::{Rng, SeedableRng};
     use rand_xorshift::XorShiftRng;
 
@@ -125,6 +371,59 @@ mod test {
         assert_eq!(hex::encode(result), "066e4894d9e5074a8aaf37d342703e48f83aa967952b79bf99cb9db98270907c1d92043890256cf7b19a0cb5b8155300")
     }
 
+    #[test]
+    fn test_crh_streaming() {
+        let mut rng = XorShiftRng::from_seed([
+            0x5d, 0xbe, 0x62, 0x59, 0x8d, 0x31, 0x3d, 0x76, 0x32, 0x37, 0xdb, 0x17, 0xe5, 0xbc,
+            0x06, 0x54,
+        ]);
+        let max_length = MAX_CIRCUIT_INPUT_LENGTH;
+        for &length in &[
+            0,
+            100,
+            STREAMING_BLOCK_LENGTH,
+            3 * STREAMING_BLOCK_LENGTH + 5,
+            max_length,
+        ] {
+            let msg = (0..length).map(|_| rng.gen()).collect::<Vec<u8>>();
+            for &chunk_size in &[7, STREAMING_BLOCK_LENGTH, 1000] {
+                let mut state = COMPOSITE_HASHER.init(&[], 96).unwrap();
+                let mut compact_state = COMPACT_COMPOSITE_HASHER.init(&[], 96).unwrap();
+                for chunk in msg.chunks(chunk_size) {
+                    COMPOSITE_HASHER.update(&mut state, chunk).unwrap();
+                    COMPACT_COMPOSITE_HASHER
+                        .update(&mut compact_state, chunk)
+                        .unwrap();
+                }
+                // only the input of the incomplete block is buffered
+                assert!(state.pending.len() < STREAMING_BLOCK_LENGTH);
+                let expected = COMPOSITE_HASHER.crh(&[], &msg, 96).unwrap();
+                assert_eq!(COMPOSITE_HASHER.finalize(state).unwrap(), expected);
+                assert_eq!(
+                    COMPACT_COMPOSITE_HASHER.finalize(compact_state).unwrap(),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_rejects_long_inputs() {
+        let hasher = &*COMPOSITE_HASHER;
+        let mut state = hasher.init(&[], 96).unwrap();
+        hasher
+            .update(&mut state, &vec![0; MAX_CIRCUIT_INPUT_LENGTH])
+            .unwrap();
+        let mut longer = state.clone();
+        hasher.update(&mut longer, &[0]).unwrap();
+        hasher.finalize(longer).unwrap_err();
+
+        // input past the last window fails as soon as a block is complete
+        hasher
+            .update(&mut state, &vec![0; STREAMING_BLOCK_LENGTH])
+            .unwrap_err();
+    }
+
     #[test]
     fn test_xof_random_768() {
         let hasher = &*COMPOSITE_HASHER;
@@ -188,6 +487,60 @@ mod test {
         assert_eq!(hex::encode(result), "9108330a206e984c63b034fa59ee6f774628a881c38f2ef3e1f02d135b41958a124fabc66e547a2030f5c8142d610b1d272a67577f2c75addfd54cc96d08cff7f014fbd3147a58a8ecc2a892a04426adee811f2b7f056d58557cd7a42751dde8")
     }
 
+    #[test]
+    fn compact_crh_matches_crh() {
+        let mut rng = XorShiftRng::from_seed([
+            0x5d, 0xbe, 0x62, 0x59, 0x8d, 0x31, 0x3d, 0x76, 0x32, 0x37, 0xdb, 0x17, 0xe5, 0xbc,
+            0x06, 0x54,
+        ]);
+        let parameters = CompositeHasher::<CRH>::setup_crh().unwrap();
+        let compact = CompositeHasher::<CompactCRH>::setup_crh().unwrap();
+        for (generators, base) in parameters.generators.iter().zip(&compact.bases) {
+            assert_eq!(generators[0], *base);
+        }
+        assert_eq!(parameters.generators.len(), compact.bases.len());
+
+        let max_length = COMPACT_COMPOSITE_HASHER.max_input_length().unwrap();
+        assert_eq!(Some(max_length), COMPOSITE_HASHER.max_input_length());
+        for &length in &[0, 1, 2, 3, 32, 35, 97, 1000, max_length] {
+            let msg = (0..length).map(|_| rng.gen()).collect::<Vec<u8>>();
+            assert_eq!(
+                COMPACT_COMPOSITE_HASHER.crh(&[], &msg, 96).unwrap(),
+                COMPOSITE_HASHER.crh(&[], &msg, 96).unwrap(),
+            );
+            assert_eq!(
+                COMPACT_COMPOSITE_HASHER
+                    .hash(b"ULforxof", &msg, 96)
+                    .unwrap(),
+                COMPOSITE_HASHER.hash(b"ULforxof", &msg, 96).unwrap(),
+            );
+        }
+        COMPACT_COMPOSITE_HASHER
+            .crh(&[], &vec![0; 1_000_000], 96)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn circuit_limits_match_crh() {
+        assert_eq!(MAX_CIRCUIT_INPUT_LENGTH, CRH::INPUT_SIZE_BITS / 8);
+        assert_eq!(
+            Some(MAX_CIRCUIT_INPUT_LENGTH),
+            COMPOSITE_HASHER.max_input_length()
+        );
+
+        let max_message = MAX_CIRCUIT_INPUT_LENGTH - 1;
+        assert!(fits_in_circuit(&[], &[]));
+        assert!(fits_in_circuit(&vec![0; max_message], &[]));
+        assert!(fits_in_circuit(&vec![0; max_message - 32], &[0; 32]));
+        assert!(!fits_in_circuit(&vec![0; max_message + 1], &[]));
+        assert!(!fits_in_circuit(&vec![0; max_message - 31], &[0; 32]));
+
+        // the longest input which fits is accepted by the CRH, and one more byte is refused
+        let hasher = &*COMPOSITE_HASHER;
+        hasher.crh(&[], &vec![0; max_message + 1], 96).unwrap();
+        hasher.crh(&[], &vec![0; max_message + 2], 96).unwrap_err();
+    }
+
     #[test]
     #[should_panic]
     fn test_invalid_message() {
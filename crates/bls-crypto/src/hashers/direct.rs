@@ -1,6 +1,9 @@
-use crate::{hashers::Hasher, BLSError};
-use blake2s_simd::Params;
-use byteorder::{LittleEndian, WriteBytesExt};
+use crate::{
+    hashers::{Hasher, StreamingHasher},
+    BLSError, Vec,
+};
+use blake2s_simd::{Params, State};
+use byteorder::{ByteOrder, LittleEndian};
 
 /// Uses Blake2s as a CRH and Blake2x as the XOF.
 pub struct DirectHasher;
@@ -10,13 +13,34 @@ fn xof_digest_length_to_node_offset(
     xof_digest_length: usize,
 ) -> Result<u64, BLSError> {
     let mut xof_digest_length_bytes: [u8; 2] = [0; 2];
-    (&mut xof_digest_length_bytes[..]).write_u16::<LittleEndian>(xof_digest_length as u16)?;
+    LittleEndian::write_u16(&mut xof_digest_length_bytes, xof_digest_length as u16);
     let offset = node_offset as u64
         | ((xof_digest_length_bytes[0] as u64) << 32)
         | ((xof_digest_length_bytes[1] as u64) << 40);
     Ok(offset)
 }
 
+impl StreamingHasher for DirectHasher {
+    type State = State;
+
+    fn init(&self, domain: &[u8], xof_digest_length: usize) -> Result<State, Self::Error> {
+        Ok(Params::new()
+            .hash_length(32)
+            .node_offset(xof_digest_length_to_node_offset(0, xof_digest_length)?)
+            .personal(domain)
+            .to_state())
+    }
+
+    fn update(&self, state: &mut State, data: &[u8]) -> Result<(), Self::Error> {
+        state.update(data);
+        Ok(())
+    }
+
+    fn finalize(&self, state: State) -> Result<Vec<u8>, Self::Error> {
+        Ok(state.finalize().as_ref().to_vec())
+    }
+}
+
 impl Hasher for DirectHasher {
     type Error = BLSError;
 
@@ -26,16 +50,9 @@ impl Hasher for DirectHasher {
         message: &[u8],
         xof_digest_length: usize,
     ) -> Result<Vec<u8>, Self::Error> {
-        let hash_result = Params::new()
-            .hash_length(32)
-            .node_offset(xof_digest_length_to_node_offset(0, xof_digest_length)?)
-            .personal(domain)
-            .to_state()
-            .update(message)
-            .finalize()
-            .as_ref()
-            .to_vec();
-        Ok(hash_result.to_vec())
+        let mut state = self.init(domain, xof_digest_length)?;
+        self.update(&mut state, message)?;
+        self.finalize(state)
     }
 
     fn xof(
@@ -43,14 +60,45 @@ impl Hasher for DirectHasher {
         domain: &[u8],
         hashed_message: &[u8],
         xof_digest_length: usize,
+    ) -> Result<Vec<u8>, Self::Error> {
+        self.xof_at(
+            domain,
+            hashed_message,
+            xof_digest_length,
+            0,
+            xof_digest_length,
+        )
+    }
+
+    fn xof_at(
+        &self,
+        domain: &[u8],
+        hashed_message: &[u8],
+        xof_digest_length: usize,
+        offset: usize,
+        output_size_in_bytes: usize,
     ) -> Result<Vec<u8>, Self::Error> {
         if domain.len() > 8 {
             return Err(BLSError::DomainTooLarge(domain.len()));
         }
+        let end = offset
+            .checked_add(output_size_in_bytes)
+            .filter(|end| *end <= xof_digest_length)
+            .ok_or(BLSError::XofOutOfRange {
+                offset,
+                length: output_size_in_bytes,
+                xof_digest_length,
+            })?;
+        if output_size_in_bytes == 0 {
+            return Ok(vec![]);
+        }
         let num_hashes = (xof_digest_length + 32 - 1) / 32;
 
+        // only the blocks overlapping the requested range are computed
+        let first = offset / 32;
+        let last = (end - 1) / 32;
         let mut result = vec![];
-        for i in 0..num_hashes {
+        for i in first..=last {
             let hash_length = if i == num_hashes - 1 && (xof_digest_length % 32 != 0) {
                 xof_digest_length % 32
             } else {
@@ -75,7 +123,8 @@ impl Hasher for DirectHasher {
             result.append(&mut hash_result);
         }
 
-        Ok(result)
+        let start = offset - first * 32;
+        Ok(result[start..start + output_size_in_bytes].to_vec())
     }
 }
 
@@ -112,6 +161,23 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_crh_streaming() {
+        let mut rng = XorShiftRng::from_seed([
+            0x5d, 0xbe, 0x62, 0x59, 0x8d, 0x31, 0x3d, 0x76, 0x32, 0x37, 0xdb, 0x17, 0xe5, 0xbc,
+            0x06, 0x54,
+        ]);
+        let msg = (0..1000).map(|_| rng.gen()).collect::<Vec<u8>>();
+
+        let mut state = DirectHasher.init(b"ULforxof", 96).unwrap();
+        for chunk in msg.chunks(77) {
+            DirectHasher.update(&mut state, chunk).unwrap();
+        }
+        let streamed = DirectHasher.finalize(state).unwrap();
+
+        assert_eq!(streamed, DirectHasher.crh(b"ULforxof", &msg, 96).unwrap());
+    }
+
     #[test]
     fn test_xof_random_96() {
         let hasher = DirectHasher;
@@ -130,6 +196,36 @@ mod test {
             "5801c1a4b06a9329109326c0fbccb028c5d7f0fb03ff5345f681f65f8b81dbb1c8c48d4cd4f5a4f1698dfc53a87db8865895a484f9c5d0d120709333418e6d2ac4787d996b564bbf5d6d506f1e280e4695599e42cd9e668c0ed9444a7b58a781");
     }
 
+    #[test]
+    fn test_xof_at_matches_xof() {
+        let hasher = DirectHasher;
+        let message = hasher.crh(&[], b"message", 100).unwrap();
+        let xof_result = hasher.xof(b"ULforxof", &message, 100).unwrap();
+        for (offset, length) in &[
+            (0, 100),
+            (0, 1),
+            (31, 2),
+            (32, 32),
+            (40, 50),
+            (96, 4),
+            (100, 0),
+        ] {
+            let seeked = hasher
+                .xof_at(b"ULforxof", &message, 100, *offset, *length)
+                .unwrap();
+            assert_eq!(seeked, &xof_result[*offset..*offset + *length]);
+        }
+
+        match hasher.xof_at(b"ULforxof", &message, 100, 90, 11) {
+            Err(BLSError::XofOutOfRange {
+                offset: 90,
+                length: 11,
+                xof_digest_length: 100,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
     #[test]
     fn test_hash_random() {
         let hasher = DirectHasher;
@@ -1,9 +1,15 @@
+#[cfg(feature = "composite-hash")]
 pub mod composite;
-pub use composite::COMPOSITE_HASHER;
+#[cfg(feature = "composite-hash")]
+pub use composite::{fits_in_circuit, MAX_CIRCUIT_INPUT_LENGTH};
+#[cfg(all(feature = "composite-hash", feature = "std"))]
+pub use composite::{COMPACT_COMPOSITE_HASHER, COMPOSITE_HASHER};
 
 mod direct;
 pub use direct::DirectHasher;
 
+use crate::Vec;
+
 /// A hasher groups together collision resistant (CRH) and extendable output (XOF) functions in order
 /// to produce a hash of the input.
 pub trait Hasher {
@@ -29,6 +35,24 @@ pub trait Hasher {
         output_size_in_bytes: usize,
     ) -> Result<Vec<u8>, Self::Error>;
 
+    /// Returns `output_size_in_bytes` bytes at `offset` of the output of `xof` with a total
+    /// output length of `xof_digest_length`, i.e. `xof(..)[offset..offset + output_size_in_bytes]`,
+    /// without computing the rest of the output.
+    fn xof_at(
+        &self,
+        domain: &[u8],
+        hashed_message: &[u8],
+        xof_digest_length: usize,
+        offset: usize,
+        output_size_in_bytes: usize,
+    ) -> Result<Vec<u8>, Self::Error>;
+
+    /// The maximum number of bytes which the CRH accepts as input, or `None` if the input
+    /// length is unbounded
+    fn max_input_length(&self) -> Option<usize> {
+        None
+    }
+
     /// Runs the CRH over the domain on the input, and then runs it again over the XOF
     fn hash(
         &self,
@@ -40,3 +64,22 @@ pub trait Hasher {
         self.xof(domain, &prepared_message, output_size_in_bytes)
     }
 }
+
+/// A hasher whose CRH can be computed over an input which arrives in pieces, without holding
+/// all of it in memory
+pub trait StreamingHasher: Hasher {
+    /// The state of a streaming CRH computation
+    type State;
+
+    /// Starts a streaming CRH computation with the specified domain. Feed the input with
+    /// `update` and get the result with `finalize`, which is equal to calling `crh` on the
+    /// concatenation of all the updates.
+    fn init(&self, domain: &[u8], xof_digest_length: usize) -> Result<Self::State, Self::Error>;
+
+    /// Feeds more input to a streaming CRH computation. Fails if the input becomes longer
+    /// than `max_input_length`.
+    fn update(&self, state: &mut Self::State, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Completes a streaming CRH computation and returns its output
+    fn finalize(&self, state: Self::State) -> Result<Vec<u8>, Self::Error>;
+}
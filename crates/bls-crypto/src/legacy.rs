@@ -0,0 +1,266 @@
+//! The legacy byte formats of keys and signatures, and the migration to the canonical ones.
+//!
+//! The legacy format is the `CeloLegacy` profile, i.e. the compressed `CanonicalSerialize`
+//! encoding, which the Celo blockchain stores on chain and its nodes on disk. The canonical
+//! format is the compressed `ExplicitFlags` profile, `CANONICAL_PROFILE`, which is the default
+//! `SerializationProfile`. Data in the legacy format stays readable and writable with the
+//! explicitly named `LegacyBytes` methods.
+//!
+//! A canonical point is one byte longer than a legacy one, so `DualFormat` decodes keys and
+//! signatures in either format. An `AcceptanceWindow` schedules the migration of data which is
+//! checked by several parties, e.g. on chain: canonical encodings are accepted and written from
+//! a block or epoch onwards, and legacy ones are still accepted for a while after it, so that
+//! there is no flag day. Private keys have the same length in both formats, and must always be
+//! decoded from a known format.
+use crate::{
+    BLSError, BlsResult, PrivateKey, ProfileSerialize, PublicKey, SerializationProfile, Signature,
+};
+use algebra::{
+    bls12_377::{G1Affine, G2Affine},
+    ConstantSerializedSize,
+};
+
+/// The profile of the canonical format
+pub const CANONICAL_PROFILE: SerializationProfile =
+    SerializationProfile::ExplicitFlags { compressed: true };
+
+/// The formats of keys and signatures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteFormat {
+    /// The `CeloLegacy` profile
+    Legacy,
+    /// The `CANONICAL_PROFILE`
+    Canonical,
+}
+
+impl ByteFormat {
+    /// Returns the profile of the format
+    pub fn profile(self) -> SerializationProfile {
+        match self {
+            ByteFormat::Legacy => SerializationProfile::CeloLegacy,
+            ByteFormat::Canonical => CANONICAL_PROFILE,
+        }
+    }
+}
+
+/// Encoding in the legacy format
+pub trait LegacyBytes: ProfileSerialize {
+    /// Serializes the element in the legacy format
+    fn to_legacy_bytes(&self) -> BlsResult<Vec<u8>> {
+        self.serialize_with_profile(SerializationProfile::CeloLegacy)
+    }
+
+    /// Deserializes an element in the legacy format
+    fn from_legacy_bytes(bytes: &[u8]) -> BlsResult<Self> {
+        Self::deserialize_with_profile(bytes, SerializationProfile::CeloLegacy)
+    }
+}
+
+impl LegacyBytes for PrivateKey {}
+impl LegacyBytes for PublicKey {}
+impl LegacyBytes for Signature {}
+
+/// Elements whose format can be told from the length of their encoding
+pub trait DualFormat: LegacyBytes {
+    /// The length of the legacy encoding
+    const LEGACY_LENGTH: usize;
+
+    /// Returns the format of the encoding. Anything but the length of the legacy encoding is
+    /// assumed to be canonical, and checked when decoding.
+    fn format_of(bytes: &[u8]) -> ByteFormat {
+        if bytes.len() == Self::LEGACY_LENGTH {
+            ByteFormat::Legacy
+        } else {
+            ByteFormat::Canonical
+        }
+    }
+
+    /// Deserializes an element in either format, and returns it with its format
+    fn from_either_bytes(bytes: &[u8]) -> BlsResult<(Self, ByteFormat)> {
+        let format = Self::format_of(bytes);
+        Ok((
+            Self::deserialize_with_profile(bytes, format.profile())?,
+            format,
+        ))
+    }
+}
+
+impl DualFormat for PublicKey {
+    const LEGACY_LENGTH: usize = G2Affine::SERIALIZED_SIZE;
+}
+
+impl DualFormat for Signature {
+    const LEGACY_LENGTH: usize = G1Affine::SERIALIZED_SIZE;
+}
+
+/// Converts a legacy encoding to the canonical format
+pub fn legacy_to_canonical<T: DualFormat>(bytes: &[u8]) -> BlsResult<Vec<u8>> {
+    T::from_legacy_bytes(bytes)?.serialize_with_profile(CANONICAL_PROFILE)
+}
+
+/// Converts a canonical encoding to the legacy format
+pub fn canonical_to_legacy<T: DualFormat>(bytes: &[u8]) -> BlsResult<Vec<u8>> {
+    T::deserialize_with_profile(bytes, CANONICAL_PROFILE)?.to_legacy_bytes()
+}
+
+/// The schedule of a migration to the canonical format, over positions such as block
+/// numbers or epochs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AcceptanceWindow {
+    /// The first position at which canonical encodings are accepted and written
+    pub canonical_from: u64,
+    /// The number of positions from `canonical_from` during which legacy encodings are still
+    /// accepted. Zero is a flag day.
+    pub overlap: u64,
+}
+
+impl AcceptanceWindow {
+    /// Returns whether encodings in the format are accepted at the position
+    pub fn accepts(&self, format: ByteFormat, position: u64) -> bool {
+        match format {
+            ByteFormat::Legacy => position < self.canonical_from.saturating_add(self.overlap),
+            ByteFormat::Canonical => position >= self.canonical_from,
+        }
+    }
+
+    /// Returns the format in which elements are written at the position
+    pub fn write_format(&self, position: u64) -> ByteFormat {
+        if position >= self.canonical_from {
+            ByteFormat::Canonical
+        } else {
+            ByteFormat::Legacy
+        }
+    }
+
+    /// Serializes the element in the format which is written at the position
+    pub fn encode<T: DualFormat>(&self, element: &T, position: u64) -> BlsResult<Vec<u8>> {
+        element.serialize_with_profile(self.write_format(position).profile())
+    }
+
+    /// Deserializes an element in either format, failing with `BLSError::FormatNotAccepted`
+    /// if its format is not accepted at the position
+    pub fn decode<T: DualFormat>(&self, bytes: &[u8], position: u64) -> BlsResult<T> {
+        let format = T::format_of(bytes);
+        if !self.accepts(format, position) {
+            return Err(BLSError::FormatNotAccepted { format, position });
+        }
+        T::deserialize_with_profile(bytes, format.profile())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G1Projective, CanonicalSerialize, UniformRand};
+
+    #[test]
+    fn legacy_bytes_are_the_current_encoding() {
+        let rng = &mut rand::thread_rng();
+        let private_key = PrivateKey::generate(rng);
+        let public_key = private_key.to_public();
+        let signature = Signature::from(G1Projective::rand(rng));
+
+        let mut bytes = vec![];
+        public_key.serialize(&mut bytes).unwrap();
+        assert_eq!(public_key.to_legacy_bytes().unwrap(), bytes);
+        assert_eq!(
+            public_key.to_legacy_bytes().unwrap(),
+            public_key.to_celo_bytes()
+        );
+        assert_eq!(PublicKey::from_legacy_bytes(&bytes).unwrap(), public_key);
+        assert_eq!(
+            signature.to_legacy_bytes().unwrap(),
+            signature.to_celo_bytes()
+        );
+
+        let mut bytes = vec![];
+        private_key.serialize(&mut bytes).unwrap();
+        assert_eq!(private_key.to_legacy_bytes().unwrap(), bytes);
+        let decoded = PrivateKey::from_legacy_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_public(), public_key);
+
+        assert_eq!(SerializationProfile::default(), CANONICAL_PROFILE);
+    }
+
+    #[test]
+    fn converts_and_decodes_either_format() {
+        let rng = &mut rand::thread_rng();
+        let public_key = PrivateKey::generate(rng).to_public();
+        let signature = Signature::from(G1Projective::rand(rng));
+
+        let legacy = public_key.to_legacy_bytes().unwrap();
+        let canonical = legacy_to_canonical::<PublicKey>(&legacy).unwrap();
+        assert_eq!(canonical.len(), legacy.len() + 1);
+        assert_eq!(
+            canonical_to_legacy::<PublicKey>(&canonical).unwrap(),
+            legacy
+        );
+        assert_eq!(
+            PublicKey::from_either_bytes(&legacy).unwrap(),
+            (public_key.clone(), ByteFormat::Legacy)
+        );
+        assert_eq!(
+            PublicKey::from_either_bytes(&canonical).unwrap(),
+            (public_key, ByteFormat::Canonical)
+        );
+
+        let legacy = signature.to_legacy_bytes().unwrap();
+        let canonical = legacy_to_canonical::<Signature>(&legacy).unwrap();
+        assert_eq!(
+            Signature::from_either_bytes(&canonical).unwrap(),
+            (signature.clone(), ByteFormat::Canonical)
+        );
+        assert_eq!(
+            canonical_to_legacy::<Signature>(&canonical).unwrap(),
+            legacy
+        );
+        // other lengths are neither
+        Signature::from_either_bytes(&canonical[..40]).unwrap_err();
+        legacy_to_canonical::<Signature>(&canonical).unwrap_err();
+    }
+
+    #[test]
+    fn accepts_formats_within_the_window() {
+        let rng = &mut rand::thread_rng();
+        let signature = Signature::from(G1Projective::rand(rng));
+        let window = AcceptanceWindow {
+            canonical_from: 100,
+            overlap: 10,
+        };
+
+        let legacy = window.encode(&signature, 99).unwrap();
+        let canonical = window.encode(&signature, 100).unwrap();
+        assert_eq!(legacy, signature.to_legacy_bytes().unwrap());
+        assert_eq!(canonical.len(), legacy.len() + 1);
+
+        for &(position, legacy_accepted, canonical_accepted) in &[
+            (0, true, false),
+            (99, true, false),
+            (100, true, true),
+            (109, true, true),
+            (110, false, true),
+        ] {
+            for &(bytes, accepted) in
+                &[(&legacy, legacy_accepted), (&canonical, canonical_accepted)]
+            {
+                match window.decode::<Signature>(bytes, position) {
+                    Ok(decoded) => {
+                        assert!(accepted);
+                        assert_eq!(decoded, signature);
+                    }
+                    Err(BLSError::FormatNotAccepted { .. }) => assert!(!accepted),
+                    Err(err) => panic!("unexpected error {}", err),
+                }
+            }
+        }
+
+        // without overlap, the formats switch on a flag day
+        let flag_day = AcceptanceWindow {
+            canonical_from: 100,
+            overlap: 0,
+        };
+        assert!(flag_day.accepts(ByteFormat::Legacy, 99));
+        assert!(!flag_day.accepts(ByteFormat::Legacy, 100));
+        assert!(flag_day.accepts(ByteFormat::Canonical, 100));
+    }
+}
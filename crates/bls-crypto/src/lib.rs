@@ -6,7 +6,8 @@
 //! - signing and verifying BLS signatures
 //! - aggregating BLS signatures and public keys
 //! - batch verification of `n` BLS signatures with `n+1` pairings instead of `2n`
-//! - SNARK-friendly hashing utilizing a Pedersen CRH via the `composite` hasher module
+//! - SNARK-friendly hashing utilizing a Pedersen CRH via the `composite` hasher module, enabled
+//!   with the default `composite-hash` feature
 //!
 //! # Example
 //!
@@ -50,9 +51,44 @@
 //! Currently the supported curves are BLS12-377 with signatures on G1 and public keys on G2.
 //! In a future iteration, this will be abstracted to support any curve which implements
 //! algebra's `PairingEngine` trait. We will also support public keys on G1 and signatures on G2.
+//!
+//! # Without the standard library
+//!
+//! Disabling the default `std` feature builds the crate with `#![no_std]` and `alloc`, e.g. for
+//! embedded signers. It then only has the keys, signatures and their aggregation, the hashers,
+//! hashing to the curve and the point encodings. The lazily evaluated hashers such as
+//! `DIRECT_HASH_TO_G1` need `std`, so hashers are instantiated explicitly, e.g. with
+//! `TryAndIncrement::new(&DirectHasher)`. `BLSError` then does not implement
+//! `std::error::Error`.
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "forbid-unsafe", forbid(unsafe_code))]
+
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+
+// the allocated types which are not in the prelude without `std`
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+#[cfg(feature = "std")]
+pub(crate) use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
 
 pub mod bls;
-pub use bls::{PrivateKey, PublicKey, PublicKeyCache, Signature};
+pub use bls::{
+    BatchVerifier, DerivationPath, PreparedPublicKey, PrivateKey, PublicKey, PublicKeyAggregator,
+    Signature, SignatureAggregator, ValidationLevel,
+};
+#[cfg(feature = "std")]
+pub use bls::{KeyHandle, KeyUsage, PublicKeyCache, PublicKeyCacheStats};
 
 /// Traits and implementations for hashing arbitrary data to an elliptic curve's group element
 pub mod hash_to_curve;
@@ -60,16 +96,115 @@ pub use hash_to_curve::HashToCurve;
 
 /// Hash function implementations using a CRH followed by a XOF.
 pub mod hashers;
-pub use hashers::Hasher;
+pub use hashers::{Hasher, StreamingHasher};
+
+#[cfg(feature = "std")]
+pub mod registration;
+
+/// Validated domain separators for application messages
+#[cfg(feature = "std")]
+pub mod domain;
+#[cfg(feature = "std")]
+pub use domain::{Domain, ATTESTATION_DOMAIN, MANIFEST_DOMAIN};
+
+/// Constant-time scalar multiplication used with private keys
+pub mod ct;
+
+/// The convention selecting the sign of the y coordinate of compressed points
+pub mod compression;
+pub use compression::PointCompression;
+
+/// Selectable encodings of keys and signatures for interoperability with other tooling
+pub mod profile;
+pub use profile::{
+    g1_from_compressed_standard, g1_to_compressed_standard, g2_from_compressed_standard,
+    g2_to_compressed_standard, ProfileSerialize, SerializationProfile,
+};
+
+/// The legacy byte formats of keys and signatures, and the migration to the canonical ones
+#[cfg(feature = "std")]
+pub mod legacy;
+#[cfg(feature = "std")]
+pub use legacy::{AcceptanceWindow, ByteFormat, DualFormat, LegacyBytes};
+
+/// Merkle commitments to validator sets and verification of signatures against them
+#[cfg(feature = "std")]
+pub mod commitment;
+
+/// Threshold randomness beacon built on BLS signatures
+#[cfg(feature = "std")]
+pub mod beacon;
+
+/// Calibration of batch sizes from timing probes on the host
+pub mod calibration;
+pub use calibration::BatchConfig;
+
+/// Detection of the field arithmetic backend selected with the `asm` feature
+#[cfg(feature = "std")]
+pub mod backend;
+
+/// Machine-readable benchmark baselines and regression checks
+#[cfg(feature = "bench-baseline")]
+pub mod baseline;
+
+/// Shamir sharding of private keys for offline backups
+#[cfg(feature = "std")]
+pub mod sharding;
+
+/// Istanbul's quorum rules, seal verification and quorum certificates
+#[cfg(feature = "std")]
+pub mod quorum;
+#[cfg(feature = "std")]
+pub use quorum::QuorumCertificate;
+
+/// Epoch blocks and the verification of the transitions between them, for light clients
+#[cfg(feature = "std")]
+pub mod epoch;
+#[cfg(feature = "std")]
+pub use epoch::{EpochBlock, ValidatorSetDiff};
+
+/// Threshold signing with Feldman-verifiable shares of a private key
+#[cfg(feature = "std")]
+pub mod threshold;
+
+/// Forensic consistency checks of block seals against the signatures they aggregate
+#[cfg(feature = "std")]
+pub mod forensics;
+#[cfg(feature = "std")]
+pub use forensics::{check_seal, SealReport};
 
-#[cfg(any(test, feature = "test-helpers"))]
-pub mod test_helpers;
+/// Hooks notified of every signature, for external audit logs
+#[cfg(feature = "std")]
+pub mod observer;
+#[cfg(feature = "std")]
+pub use observer::{clear_sign_observer, set_sign_observer, SignEvent, SignObserver};
+
+/// Watermarks of consensus signatures which prevent signing conflicting messages
+#[cfg(feature = "std")]
+pub mod slashing;
+#[cfg(feature = "std")]
+pub use slashing::{
+    FileSlashingProtection, MemorySlashingProtection, SlashingProtection, Watermark,
+};
+
+/// Versioned binary envelopes for stored keys, signatures and other artifacts
+#[cfg(feature = "std")]
+pub mod envelope;
+#[cfg(feature = "std")]
+pub use envelope::{Envelope, Versioned};
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+/// Alias of the `testing` module, kept for existing users
+#[cfg(any(test, feature = "testing"))]
+#[doc(hidden)]
+pub use testing as test_helpers;
 
 use log::error;
-use thiserror::Error;
 
 /// Convenience result alias
-pub type BlsResult<T> = std::result::Result<T, BLSError>;
+pub type BlsResult<T> = core::result::Result<T, BLSError>;
 
 /// Domain separator for signing messages
 pub const SIG_DOMAIN: &[u8] = b"ULforxof";
@@ -80,34 +215,367 @@ pub const POP_DOMAIN: &[u8] = b"ULforpop";
 /// Domain separator for public inputs to the snark
 pub const OUT_DOMAIN: &[u8] = b"ULforout";
 
-#[derive(Debug, Error)]
-/// Error type
+#[derive(Debug)]
+/// Error type. Without the `std` feature, it does not implement `std::error::Error` and
+/// has no variants holding the types of `std` modules.
 pub enum BLSError {
     /// Error
-    #[error("signature verification failed")]
     VerificationFailed,
 
     /// An IO error
-    #[error("io error {0}")]
-    IoError(#[from] std::io::Error),
+    #[cfg(feature = "std")]
+    IoError(std::io::Error),
 
     /// Error while hashing
-    #[error("error in hasher {0}")]
-    HashingError(#[from] Box<dyn std::error::Error>),
+    HashingError(Box<dyn algebra::Error>),
 
     /// Personalization string cannot be larger than 8 bytes
-    #[error("domain length is too large: {0}")]
     DomainTooLarge(usize),
 
     /// Hashing to curve failed
-    #[error("Could not hash to curve")]
     HashToCurveError,
 
     /// There must be the same number of keys and messages
-    #[error("there must be the same number of keys and messages")]
     UnevenNumKeysMessages,
 
     /// Serialization error in Zexe
-    #[error(transparent)]
-    SerializationError(#[from] algebra::SerializationError),
+    SerializationError(algebra::SerializationError),
+
+    /// The validator registration payload is malformed
+    InvalidRegistration(&'static str),
+
+    /// The ECDSA public key does not correspond to the validator's address
+    EcdsaBindingMismatch,
+
+    /// No validator set is known for the epoch
+    UnknownValidatorSet(u64),
+
+    /// A message of a batch is longer than the hasher accepts
+    MessageTooLong {
+        /// The index of the message in the batch
+        index: usize,
+        /// The combined length of the message and its extra data
+        length: usize,
+        /// The maximum length accepted by the hasher
+        max: usize,
+    },
+
+    /// The bitmap's length does not match the number of validators
+    BitmapLengthMismatch(usize, usize),
+
+    /// The public key is not a valid G2 subgroup element
+    InvalidPublicKey(&'static str),
+
+    /// The public key is the point at infinity
+    IdentityPublicKey,
+
+    /// A partial evaluation has a zero or duplicate share index
+    InvalidShareIndex(u64),
+
+    /// Fewer partial evaluations than the threshold were provided
+    NotEnoughPartials(usize, usize),
+
+    /// A Merkle proof does not prove the key of the validator at the index
+    InvalidMerkleProof(usize),
+
+    /// There must be one Merkle proof per signer
+    SignerCountMismatch(usize, usize),
+
+    /// The bytes are not a valid encoding in the selected serialization profile
+    InvalidEncoding(&'static str),
+
+    /// A key share is malformed or does not belong to the same key as the others
+    InvalidShare(&'static str),
+
+    /// Fewer key shares than the threshold were provided
+    NotEnoughShares(usize, usize),
+
+    /// The messages of an aggregate verification must be distinct
+    DuplicateMessage(usize),
+
+    /// An entropy of an epoch block does not have `epoch::ENTROPY_BYTES` bytes
+    InvalidEntropyLength(usize, usize),
+
+    /// A domain separator must be exactly `DOMAIN_LENGTH` bytes long
+    InvalidDomainLength(usize),
+
+    /// The domain is used by this library and cannot be used by applications
+    ReservedDomain,
+
+    /// The key is bound to another usage than the requested one
+    #[cfg(feature = "std")]
+    KeyUsageConflict {
+        /// The usage the key is bound to
+        bound: bls::KeyUsage,
+        /// The requested usage
+        requested: bls::KeyUsage,
+    },
+
+    /// The private key does not match its integrity tag
+    IntegrityTagMismatch,
+
+    /// The CPU does not support the instructions this build was compiled for
+    UnsupportedCpu(&'static str),
+
+    /// Entries of a batch could not be decoded or failed their checks, with their indices and
+    /// the reasons
+    InvalidBatchEntries(Vec<(usize, &'static str)>),
+
+    /// There must be one aggregate signature per chunk of a batch
+    ChunkCountMismatch(usize, usize),
+
+    /// A chunk of a batch failed verification
+    ChunkVerificationFailed(usize),
+
+    /// A public key of an aggregation has no valid proof of possession
+    InvalidProofOfPossession(usize),
+
+    /// An aggregation was given no public keys
+    EmptyAggregation,
+
+    /// The signers of a seal do not form a quorum of the validators
+    NoQuorum {
+        /// The number of signers
+        signers: usize,
+        /// The number of signers required by the quorum rules
+        quorum: usize,
+    },
+
+    /// A seek into the output of an XOF reads past its end
+    XofOutOfRange {
+        /// The offset of the read
+        offset: usize,
+        /// The number of bytes read
+        length: usize,
+        /// The total output length of the XOF
+        xof_digest_length: usize,
+    },
+
+    /// An envelope holds another kind of artifact than the one requested
+    UnexpectedKind {
+        /// The kind of the requested artifact
+        expected: u8,
+        /// The kind of the artifact in the envelope
+        got: u8,
+    },
+
+    /// An envelope's payload is encoded with an unknown version
+    UnsupportedVersion {
+        /// The kind of the artifact
+        kind: u8,
+        /// The version of the payload's encoding
+        version: u16,
+    },
+
+    /// The integer does not represent a `ValidationLevel`
+    UnknownValidationLevel(i32),
+
+    /// The integer does not represent a `SerializationProfile`
+    UnknownSerializationProfile(i32),
+
+    /// A curve point failed the checks of its validation level
+    InvalidPoint {
+        /// The level at which the point was checked
+        level: ValidationLevel,
+        /// The check which failed
+        reason: &'static str,
+    },
+
+    /// The signature is not a valid G1 point
+    InvalidSignature(&'static str),
+
+    /// The seed of a key derivation is too short
+    SeedTooShort(usize, usize),
+
+    /// The derivation path is malformed
+    InvalidDerivationPath(&'static str),
+
+    /// The number of collected signatures does not match the number of validators
+    SignatureCountMismatch(usize, usize),
+
+    /// A validator contributed twice to an incremental aggregate
+    DuplicateContribution(usize),
+
+    /// A contribution to an aggregate is not the key of its validator in the validator set
+    ContributionMismatch(usize),
+
+    /// Signing the consensus message would conflict with an earlier signature of the key
+    SlashableSignature {
+        /// The height of the message
+        height: u64,
+        /// The round of the message
+        round: u32,
+    },
+
+    /// Some signatures of a batch of independent signatures are invalid
+    InvalidSignatures(Vec<usize>),
+
+    /// The encoding's format is not accepted at the position of its migration window
+    #[cfg(feature = "std")]
+    FormatNotAccepted {
+        /// The format of the encoding
+        format: legacy::ByteFormat,
+        /// The position at which the encoding was decoded
+        position: u64,
+    },
+
+    /// The threshold of an epoch is larger than its number of validators
+    InvalidThreshold(u32, usize),
+
+    /// A transition's epoch does not follow the previous epoch
+    NonConsecutiveEpochs(u16, u16),
+}
+
+impl core::fmt::Display for BLSError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use BLSError::*;
+        match self {
+            VerificationFailed => write!(f, "signature verification failed"),
+            #[cfg(feature = "std")]
+            IoError(a0) => write!(f, "io error {}", a0),
+            HashingError(a0) => write!(f, "error in hasher {}", a0),
+            DomainTooLarge(a0) => write!(f, "domain length is too large: {}", a0),
+            HashToCurveError => write!(f, "Could not hash to curve"),
+            UnevenNumKeysMessages => {
+                write!(f, "there must be the same number of keys and messages")
+            }
+            SerializationError(err) => write!(f, "{}", err),
+            InvalidRegistration(a0) => write!(f, "invalid registration payload: {}", a0),
+            EcdsaBindingMismatch => {
+                write!(f, "ECDSA public key does not match the validator address")
+            }
+            UnknownValidatorSet(a0) => write!(f, "unknown validator set for epoch {}", a0),
+            MessageTooLong { index, length, max } => write!(
+                f,
+                "message {} has length {}, maximum is {}",
+                index, length, max
+            ),
+            BitmapLengthMismatch(a0, a1) => write!(
+                f,
+                "bitmap has length {}, but there are {} validators",
+                a0, a1
+            ),
+            InvalidPublicKey(a0) => write!(f, "invalid public key: {}", a0),
+            IdentityPublicKey => write!(f, "public key is the point at infinity"),
+            InvalidShareIndex(a0) => write!(f, "invalid or duplicate share index {}", a0),
+            NotEnoughPartials(a0, a1) => {
+                write!(f, "got {} partial evaluations, threshold is {}", a0, a1)
+            }
+            InvalidMerkleProof(a0) => write!(f, "invalid merkle proof for validator {}", a0),
+            SignerCountMismatch(a0, a1) => {
+                write!(f, "got {} merkle proofs, but there are {} signers", a0, a1)
+            }
+            InvalidEncoding(a0) => write!(f, "invalid encoding: {}", a0),
+            InvalidShare(a0) => write!(f, "invalid key share: {}", a0),
+            NotEnoughShares(a0, a1) => write!(f, "got {} key shares, threshold is {}", a0, a1),
+            DuplicateMessage(a0) => {
+                write!(f, "message {} is a duplicate of an earlier message", a0)
+            }
+            InvalidEntropyLength(a0, a1) => write!(f, "entropy has length {}, expected {}", a0, a1),
+            InvalidDomainLength(a0) => write!(f, "domain has length {}, expected 8", a0),
+            ReservedDomain => write!(f, "domain is reserved"),
+            #[cfg(feature = "std")]
+            KeyUsageConflict { bound, requested } => write!(
+                f,
+                "key is bound to {}, cannot be used for {}",
+                bound, requested
+            ),
+            IntegrityTagMismatch => write!(f, "the key does not match its integrity tag"),
+            UnsupportedCpu(a0) => write!(f, "unsupported cpu: {}", a0),
+            InvalidBatchEntries(a0) => write!(f, "invalid entries of the batch: {:?}", a0),
+            ChunkCountMismatch(a0, a1) => {
+                write!(f, "got {} signatures, but the batch has {} chunks", a0, a1)
+            }
+            ChunkVerificationFailed(a0) => write!(f, "chunk {} failed verification", a0),
+            InvalidProofOfPossession(a0) => {
+                write!(f, "public key {} has no valid proof of possession", a0)
+            }
+            EmptyAggregation => write!(f, "cannot aggregate an empty set of public keys"),
+            NoQuorum { signers, quorum } => {
+                write!(f, "got {} signers, quorum is {}", signers, quorum)
+            }
+            XofOutOfRange {
+                offset,
+                length,
+                xof_digest_length,
+            } => write!(
+                f,
+                "cannot read {} bytes at offset {} of a {} bytes XOF",
+                length, offset, xof_digest_length
+            ),
+            UnexpectedKind { expected, got } => write!(
+                f,
+                "envelope holds an artifact of kind {}, expected {}",
+                got, expected
+            ),
+            UnsupportedVersion { kind, version } => write!(
+                f,
+                "unsupported version {} of artifacts of kind {}",
+                version, kind
+            ),
+            UnknownValidationLevel(a0) => write!(f, "unknown validation level {}", a0),
+            UnknownSerializationProfile(a0) => write!(f, "unknown serialization profile {}", a0),
+            InvalidPoint { level, reason } => {
+                write!(f, "invalid point at validation level {}: {}", level, reason)
+            }
+            InvalidSignature(a0) => write!(f, "invalid signature: {}", a0),
+            SeedTooShort(a0, a1) => write!(f, "seed has length {}, minimum is {}", a0, a1),
+            InvalidDerivationPath(a0) => write!(f, "invalid derivation path: {}", a0),
+            SignatureCountMismatch(a0, a1) => {
+                write!(f, "got {} signatures, but there are {} validators", a0, a1)
+            }
+            DuplicateContribution(a0) => {
+                write!(f, "validator {} already contributed to the aggregate", a0)
+            }
+            ContributionMismatch(a0) => {
+                write!(f, "contribution {} is not the key of the validator", a0)
+            }
+            SlashableSignature { height, round } => write!(
+                f,
+                "refusing to sign at height {}, round {}: conflicts with an earlier signature",
+                height, round
+            ),
+            InvalidSignatures(a0) => write!(f, "signatures {:?} failed verification", a0),
+            #[cfg(feature = "std")]
+            FormatNotAccepted { format, position } => write!(
+                f,
+                "{:?} encodings are not accepted at position {}",
+                format, position
+            ),
+            InvalidThreshold(a0, a1) => {
+                write!(f, "threshold {} is larger than the {} validators", a0, a1)
+            }
+            NonConsecutiveEpochs(a0, a1) => write!(f, "epoch {} does not follow epoch {}", a1, a0),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BLSError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BLSError::IoError(err) => Some(err),
+            BLSError::SerializationError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for BLSError {
+    fn from(err: std::io::Error) -> Self {
+        BLSError::IoError(err)
+    }
+}
+
+impl From<Box<dyn algebra::Error>> for BLSError {
+    fn from(err: Box<dyn algebra::Error>) -> Self {
+        BLSError::HashingError(err)
+    }
+}
+
+impl From<algebra::SerializationError> for BLSError {
+    fn from(err: algebra::SerializationError) -> Self {
+        BLSError::SerializationError(err)
+    }
 }
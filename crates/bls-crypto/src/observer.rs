@@ -0,0 +1,166 @@
+//! Hooks notified of every signature produced by a private key.
+//!
+//! Validator operators can register a `SignObserver` with `set_sign_observer` to keep an
+//! external log of the signatures of their keys, e.g. to refuse signing two different blocks
+//! at the same height, without patching the crate. The observer is called after each
+//! successful signature of `PrivateKey`, `KeyHandle` and the FFI, on the signing thread, so it
+//! should return quickly. Nothing is computed for the notification while no observer is set.
+use crate::PublicKey;
+use algebra::{bls12_377::G1Projective, CanonicalSerialize, ProjectiveCurve};
+use blake2s_simd::Params;
+use once_cell::sync::Lazy;
+use std::{
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+/// Length of the digests of `message_digest`
+pub const MESSAGE_DIGEST_LENGTH: usize = 32;
+
+/// Personalization of the digest of the signed messages
+const DIGEST_PERSONALIZATION: &[u8] = b"ULsigobs";
+
+/// A signature produced by a private key
+#[derive(Clone, Debug)]
+pub struct SignEvent<'a> {
+    /// The digest of the signed message and extra data, see `message_digest`. For
+    /// signatures of already hashed messages, the digest of the compressed hash.
+    pub message_digest: [u8; MESSAGE_DIGEST_LENGTH],
+    /// The domain the message was signed in, or `None` for signatures of already hashed
+    /// messages, whose domain is unknown
+    pub domain: Option<&'a [u8]>,
+    /// The public key of the signer
+    pub public_key: &'a PublicKey,
+    /// The time at which the signature was produced
+    pub timestamp: SystemTime,
+}
+
+/// Receives the events of the signatures produced by private keys
+pub trait SignObserver: Send + Sync {
+    /// Called after each successful signature
+    fn on_sign(&self, event: &SignEvent);
+}
+
+impl<F: Fn(&SignEvent) + Send + Sync> SignObserver for F {
+    fn on_sign(&self, event: &SignEvent) {
+        self(event)
+    }
+}
+
+static OBSERVER: Lazy<RwLock<Option<Arc<dyn SignObserver>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Registers the observer of all signatures, replacing the previous one
+pub fn set_sign_observer(observer: Arc<dyn SignObserver>) {
+    *OBSERVER.write().expect("the observer lock is not poisoned") = Some(observer);
+}
+
+/// Removes the observer of signatures, if any
+pub fn clear_sign_observer() {
+    *OBSERVER.write().expect("the observer lock is not poisoned") = None;
+}
+
+/// Returns the digest of a message and its extra data, as reported in `SignEvent`s. The
+/// message's length is hashed first, so that the boundary between the two is unambiguous.
+pub fn message_digest(message: &[u8], extra_data: &[u8]) -> [u8; MESSAGE_DIGEST_LENGTH] {
+    let hash = Params::new()
+        .hash_length(MESSAGE_DIGEST_LENGTH)
+        .personal(DIGEST_PERSONALIZATION)
+        .to_state()
+        .update(&(message.len() as u64).to_le_bytes())
+        .update(message)
+        .update(extra_data)
+        .finalize();
+    let mut digest = [0u8; MESSAGE_DIGEST_LENGTH];
+    digest.copy_from_slice(hash.as_bytes());
+    digest
+}
+
+/// Notifies the observer of the signature of a message
+pub(crate) fn notify_message<F: FnOnce() -> PublicKey>(
+    domain: &[u8],
+    message: &[u8],
+    extra_data: &[u8],
+    public_key: F,
+) {
+    notify(
+        || message_digest(message, extra_data),
+        Some(domain),
+        public_key,
+    )
+}
+
+/// Notifies the observer of the signature of an already hashed message
+pub(crate) fn notify_hashed<F: FnOnce() -> PublicKey>(hash: &G1Projective, public_key: F) {
+    let digest = || {
+        let mut bytes = vec![];
+        hash.into_affine()
+            .serialize(&mut bytes)
+            .expect("serializing to a vector cannot fail");
+        message_digest(&bytes, &[])
+    };
+    notify(digest, None, public_key)
+}
+
+fn notify<D, F>(digest: D, domain: Option<&[u8]>, public_key: F)
+where
+    D: FnOnce() -> [u8; MESSAGE_DIGEST_LENGTH],
+    F: FnOnce() -> PublicKey,
+{
+    // released before calling the observer, which may replace itself
+    let observer = match &*OBSERVER.read().expect("the observer lock is not poisoned") {
+        Some(observer) => observer.clone(),
+        None => return,
+    };
+    let public_key = public_key();
+    observer.on_sign(&SignEvent {
+        message_digest: digest(),
+        domain,
+        public_key: &public_key,
+        timestamp: SystemTime::now(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, PrivateKey, SIG_DOMAIN};
+    use std::sync::Mutex;
+
+    #[test]
+    fn observes_signatures() {
+        // other tests sign concurrently, so only the events of this key are recorded
+        let key = PrivateKey::generate(&mut rand::thread_rng());
+        let public_key = key.to_public();
+        let events = Arc::new(Mutex::new(vec![]));
+        let recorded = events.clone();
+        set_sign_observer(Arc::new(move |event: &SignEvent| {
+            if *event.public_key == public_key {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((event.message_digest, event.domain.map(|d| d.to_vec())));
+            }
+        }));
+
+        key.sign(b"block", b"extra", &*DIRECT_HASH_TO_G1).unwrap();
+        key.sign_hashed(&G1Projective::prime_subgroup_generator());
+        clear_sign_observer();
+        key.sign(b"block", b"extra", &*DIRECT_HASH_TO_G1).unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0],
+            (
+                message_digest(b"block", b"extra"),
+                Some(SIG_DOMAIN.to_vec())
+            )
+        );
+        assert_eq!(events[1].1, None);
+        // the boundary between the message and extra data is part of the digest
+        assert_ne!(
+            message_digest(b"block", b"extra"),
+            message_digest(b"blockextra", b"")
+        );
+    }
+}
@@ -0,0 +1,878 @@
+//! Serialization profiles for keys and signatures.
+//!
+//! The same elements are encoded differently by this crate, by arkworks tooling and by EVM
+//! contracts. The profile is selected per call, so that integrators do not need to convert
+//! between the encodings by hand:
+//!
+//! - `CeloLegacy`: the encoding used by this crate and by the Celo blockchain. Points are
+//!   compressed, with the little-endian `x` coordinate followed by the flags in the two most
+//!   significant bits of the last byte: bit 6 is set for the point at infinity, bit 7 is set
+//!   if `y` is the lexicographically largest of `y` and `-y`. Scalars are 32 bytes little-endian.
+//! - `ArkworksCanonical`: the compressed encoding of arkworks' `CanonicalSerialize`. It only
+//!   differs from `CeloLegacy` in the sign bit, which arkworks sets if `y` is the smallest of
+//!   `y` and `-y`.
+//! - `EvmBigEndian`: the uncompressed encoding of the EIP-2539 precompiles. Each base field
+//!   element is 64 bytes big-endian, zero-padded on the left, and points are `x || y` with
+//!   `Fq2` elements written as `c0 || c1`. The point at infinity is all zeros. Scalars are 32
+//!   bytes big-endian.
+//! - `ExplicitFlags`: a flags byte followed by the big-endian `x` coordinate, 48 bytes for G1
+//!   and 96 bytes for G2 with `Fq2` elements written as `c0 || c1`, and by `y` if the point is
+//!   uncompressed. The flags do not share a byte with `x`, so the encoding does not depend on
+//!   the spare bits of the field and is straightforward to implement without zexe. Scalars are
+//!   32 bytes big-endian.
+//! - `StandardCompressed`: the compressed encoding of Zcash's BLS12-381 serialization, which
+//!   the EIP-2537 tooling and most other BLS libraries also use: the big-endian `x`
+//!   coordinate, 48 bytes for G1 and 96 bytes for G2 with `Fq2` elements written as
+//!   `c1 || c0`, with the flags in the three most significant bits of the first byte: bit 7
+//!   is always set as the point is compressed, bit 6 is set for the point at infinity, whose
+//!   other bits are then all zeros, and bit 5 is set if `y` is the lexicographically largest
+//!   of `y` and `-y`. Scalars are 32 bytes big-endian.
+//!
+//! The flags byte of `ExplicitFlags` is laid out as follows:
+//!
+//! | bits | meaning                                                                 |
+//! |------|-------------------------------------------------------------------------|
+//! | 0    | set if the point is compressed                                          |
+//! | 1    | set for the point at infinity, whose coordinates are then all zeros     |
+//! | 2    | set if `y` is the lexicographically largest of `y` and `-y`, compressed |
+//! | 3    | reserved, must be unset                                                 |
+//! | 4-7  | the version of the encoding, `EXPLICIT_FLAGS_VERSION`                   |
+//!
+//! Decoders select the layout from the version, so that the encoding can evolve without
+//! ambiguity, and reject versions they do not know.
+//!
+//! Points decoded with the `EvmBigEndian`, `ExplicitFlags` and `StandardCompressed` profiles
+//! are checked to be on the curve and in the prime order subgroup. The other compressed
+//! profiles perform the same checks as `CanonicalDeserialize`. The `StandardCompressed`
+//! decoders report points which fail these checks with `BLSError::InvalidPoint`, and
+//! malformed bytes with `BLSError::InvalidEncoding`.
+use crate::{
+    bls::subgroup::{is_in_g1_subgroup, is_valid_g2},
+    compression::PointCompression,
+    BLSError, BlsResult, PrivateKey, PublicKey, Signature, ValidationLevel, Vec,
+};
+
+use algebra::{
+    bls12_377::{Fq, Fq2, Fr, G1Affine, G2Affine},
+    curves::models::{short_weierstrass_jacobian::GroupAffine, SWModelParameters},
+    AffineCurve, CanonicalDeserialize, CanonicalSerialize, ConstantSerializedSize, ProjectiveCurve,
+    Zero,
+};
+use core::convert::TryFrom;
+
+/// Length of a base field element in the `EvmBigEndian` profile
+pub const EVM_FIELD_LENGTH: usize = 64;
+
+/// The version of the `ExplicitFlags` encoding, in the high nibble of its flags byte
+pub const EXPLICIT_FLAGS_VERSION: u8 = 1;
+
+const INFINITY_FLAG: u8 = 1 << 6;
+const SIGN_FLAG: u8 = 1 << 7;
+
+const EXPLICIT_COMPRESSED_FLAG: u8 = 1;
+const EXPLICIT_INFINITY_FLAG: u8 = 1 << 1;
+const EXPLICIT_SIGN_FLAG: u8 = 1 << 2;
+const EXPLICIT_RESERVED_FLAG: u8 = 1 << 3;
+
+const STANDARD_COMPRESSED_FLAG: u8 = 1 << 7;
+const STANDARD_INFINITY_FLAG: u8 = 1 << 6;
+const STANDARD_SIGN_FLAG: u8 = 1 << 5;
+const STANDARD_FLAGS: u8 = STANDARD_COMPRESSED_FLAG | STANDARD_INFINITY_FLAG | STANDARD_SIGN_FLAG;
+
+/// The encodings which keys and signatures can be serialized with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SerializationProfile {
+    /// The compressed little-endian encoding of this crate and the Celo blockchain
+    CeloLegacy,
+    /// The compressed little-endian encoding of arkworks
+    ArkworksCanonical,
+    /// The uncompressed big-endian encoding of the EIP-2539 precompiles
+    EvmBigEndian,
+    /// The big-endian encoding with a separate flags byte. Elements in either form are
+    /// accepted when deserializing.
+    ExplicitFlags {
+        /// Whether points are serialized compressed
+        compressed: bool,
+    },
+    /// The compressed big-endian encoding of Zcash and EIP-2537 tooling
+    StandardCompressed,
+}
+
+impl SerializationProfile {
+    fn big_endian_scalars(self) -> bool {
+        match self {
+            SerializationProfile::EvmBigEndian
+            | SerializationProfile::ExplicitFlags { .. }
+            | SerializationProfile::StandardCompressed => true,
+            _ => false,
+        }
+    }
+}
+
+/// The default is the canonical format of the `legacy` module, the compressed
+/// `ExplicitFlags` profile. The `CeloLegacy` encoding must be requested explicitly.
+impl Default for SerializationProfile {
+    fn default() -> Self {
+        crate::legacy::CANONICAL_PROFILE
+    }
+}
+
+/// The integer representation used over FFI: 0 for `CeloLegacy`, 1 for `ArkworksCanonical`,
+/// 2 for `EvmBigEndian`, 3 and 4 for the compressed and uncompressed `ExplicitFlags`, and 5
+/// for `StandardCompressed`
+impl TryFrom<i32> for SerializationProfile {
+    type Error = BLSError;
+
+    fn try_from(profile: i32) -> BlsResult<Self> {
+        match profile {
+            0 => Ok(SerializationProfile::CeloLegacy),
+            1 => Ok(SerializationProfile::ArkworksCanonical),
+            2 => Ok(SerializationProfile::EvmBigEndian),
+            3 => Ok(SerializationProfile::ExplicitFlags { compressed: true }),
+            4 => Ok(SerializationProfile::ExplicitFlags { compressed: false }),
+            5 => Ok(SerializationProfile::StandardCompressed),
+            _ => Err(BLSError::UnknownSerializationProfile(profile)),
+        }
+    }
+}
+
+impl From<SerializationProfile> for i32 {
+    fn from(profile: SerializationProfile) -> i32 {
+        match profile {
+            SerializationProfile::CeloLegacy => 0,
+            SerializationProfile::ArkworksCanonical => 1,
+            SerializationProfile::EvmBigEndian => 2,
+            SerializationProfile::ExplicitFlags { compressed: true } => 3,
+            SerializationProfile::ExplicitFlags { compressed: false } => 4,
+            SerializationProfile::StandardCompressed => 5,
+        }
+    }
+}
+
+/// Serialization of an element with a selectable profile
+pub trait ProfileSerialize: Sized {
+    /// Serializes the element with the provided profile
+    fn serialize_with_profile(&self, profile: SerializationProfile) -> BlsResult<Vec<u8>>;
+
+    /// Deserializes an element which was serialized with the provided profile. The input
+    /// must have the exact length of the encoding.
+    fn deserialize_with_profile(bytes: &[u8], profile: SerializationProfile) -> BlsResult<Self>;
+}
+
+impl ProfileSerialize for PrivateKey {
+    fn serialize_with_profile(&self, profile: SerializationProfile) -> BlsResult<Vec<u8>> {
+        let mut bytes = vec![];
+        self.serialize(&mut bytes)?;
+        if profile.big_endian_scalars() {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn deserialize_with_profile(bytes: &[u8], profile: SerializationProfile) -> BlsResult<Self> {
+        check_length(bytes, Fr::SERIALIZED_SIZE)?;
+        let mut bytes = bytes.to_vec();
+        if profile.big_endian_scalars() {
+            bytes.reverse();
+        }
+        Ok(PrivateKey::deserialize(&mut &bytes[..])?)
+    }
+}
+
+impl ProfileSerialize for Signature {
+    fn serialize_with_profile(&self, profile: SerializationProfile) -> BlsResult<Vec<u8>> {
+        match profile {
+            SerializationProfile::EvmBigEndian => {
+                let point = self.as_ref().into_affine();
+                let mut bytes = Vec::with_capacity(2 * EVM_FIELD_LENGTH);
+                if !point.is_zero() {
+                    write_fq(&point.x, EVM_FIELD_LENGTH, &mut bytes)?;
+                    write_fq(&point.y, EVM_FIELD_LENGTH, &mut bytes)?;
+                } else {
+                    bytes.resize(2 * EVM_FIELD_LENGTH, 0);
+                }
+                Ok(bytes)
+            }
+            SerializationProfile::ExplicitFlags { compressed } => serialize_flagged(
+                &self.as_ref().into_affine(),
+                compressed,
+                Fq::SERIALIZED_SIZE,
+                |x, out| write_fq(x, Fq::SERIALIZED_SIZE, out),
+            ),
+            SerializationProfile::StandardCompressed => {
+                Ok(g1_to_compressed_standard(&self.as_ref().into_affine()))
+            }
+            _ => serialize_compressed(self, profile),
+        }
+    }
+
+    fn deserialize_with_profile(bytes: &[u8], profile: SerializationProfile) -> BlsResult<Self> {
+        match profile {
+            SerializationProfile::EvmBigEndian => {
+                check_length(bytes, 2 * EVM_FIELD_LENGTH)?;
+                if bytes.iter().all(|b| *b == 0) {
+                    return Ok(Signature::from(G1Affine::zero().into_projective()));
+                }
+                let (x, y) = bytes.split_at(EVM_FIELD_LENGTH);
+                let point = G1Affine::new(read_fq(x)?, read_fq(y)?, false);
+                if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+                    return Err(BLSError::InvalidEncoding("not a G1 subgroup element"));
+                }
+                Ok(Signature::from(point.into_projective()))
+            }
+            SerializationProfile::ExplicitFlags { .. } => {
+                let point = deserialize_flagged(bytes, Fq::SERIALIZED_SIZE, read_fq)?;
+                if !point.is_in_correct_subgroup_assuming_on_curve() {
+                    return Err(BLSError::InvalidEncoding("not a G1 subgroup element"));
+                }
+                Ok(Signature::from(point.into_projective()))
+            }
+            SerializationProfile::StandardCompressed => Ok(Signature::from(
+                g1_from_compressed_standard(bytes)?.into_projective(),
+            )),
+            _ => deserialize_compressed(bytes, G1Affine::SERIALIZED_SIZE, profile),
+        }
+    }
+}
+
+impl ProfileSerialize for PublicKey {
+    fn serialize_with_profile(&self, profile: SerializationProfile) -> BlsResult<Vec<u8>> {
+        match profile {
+            SerializationProfile::EvmBigEndian => {
+                let point = self.as_ref().into_affine();
+                let mut bytes = Vec::with_capacity(4 * EVM_FIELD_LENGTH);
+                if !point.is_zero() {
+                    write_fq2(&point.x, EVM_FIELD_LENGTH, &mut bytes)?;
+                    write_fq2(&point.y, EVM_FIELD_LENGTH, &mut bytes)?;
+                } else {
+                    bytes.resize(4 * EVM_FIELD_LENGTH, 0);
+                }
+                Ok(bytes)
+            }
+            SerializationProfile::ExplicitFlags { compressed } => serialize_flagged(
+                &self.as_ref().into_affine(),
+                compressed,
+                Fq2::SERIALIZED_SIZE,
+                |x, out| write_fq2(x, Fq::SERIALIZED_SIZE, out),
+            ),
+            SerializationProfile::StandardCompressed => {
+                Ok(g2_to_compressed_standard(&self.as_ref().into_affine()))
+            }
+            _ => serialize_compressed(self, profile),
+        }
+    }
+
+    fn deserialize_with_profile(bytes: &[u8], profile: SerializationProfile) -> BlsResult<Self> {
+        match profile {
+            SerializationProfile::EvmBigEndian => {
+                check_length(bytes, 4 * EVM_FIELD_LENGTH)?;
+                if bytes.iter().all(|b| *b == 0) {
+                    return Ok(PublicKey::from(G2Affine::zero().into_projective()));
+                }
+                let (x, y) = bytes.split_at(2 * EVM_FIELD_LENGTH);
+                let point = G2Affine::new(read_fq2(x)?, read_fq2(y)?, false);
+                if !is_valid_g2(&point) {
+                    return Err(BLSError::InvalidEncoding("not a G2 subgroup element"));
+                }
+                Ok(PublicKey::from(point.into_projective()))
+            }
+            SerializationProfile::ExplicitFlags { .. } => {
+                let point = deserialize_flagged(bytes, Fq2::SERIALIZED_SIZE, read_fq2)?;
+                if !is_valid_g2(&point) {
+                    return Err(BLSError::InvalidEncoding("not a G2 subgroup element"));
+                }
+                Ok(PublicKey::from(point.into_projective()))
+            }
+            SerializationProfile::StandardCompressed => Ok(PublicKey::from(
+                g2_from_compressed_standard(bytes)?.into_projective(),
+            )),
+            _ => deserialize_compressed(bytes, G2Affine::SERIALIZED_SIZE, profile),
+        }
+    }
+}
+
+/// Encodes a G1 point with the `StandardCompressed` profile
+pub fn g1_to_compressed_standard(point: &G1Affine) -> Vec<u8> {
+    serialize_standard(point, Fq::SERIALIZED_SIZE, write_fq_standard)
+}
+
+/// Decodes a G1 point encoded with the `StandardCompressed` profile, checking that it is in
+/// the prime order subgroup
+pub fn g1_from_compressed_standard(bytes: &[u8]) -> BlsResult<G1Affine> {
+    let point = deserialize_standard(bytes, Fq::SERIALIZED_SIZE, read_fq)?;
+    if !is_in_g1_subgroup(&point) {
+        return Err(invalid_standard_point("not in the prime order subgroup"));
+    }
+    Ok(point)
+}
+
+/// Encodes a G2 point with the `StandardCompressed` profile
+pub fn g2_to_compressed_standard(point: &G2Affine) -> Vec<u8> {
+    serialize_standard(point, Fq2::SERIALIZED_SIZE, write_fq2_standard)
+}
+
+/// Decodes a G2 point encoded with the `StandardCompressed` profile, checking that it is in
+/// the prime order subgroup
+pub fn g2_from_compressed_standard(bytes: &[u8]) -> BlsResult<G2Affine> {
+    let point = deserialize_standard(bytes, Fq2::SERIALIZED_SIZE, read_fq2_standard)?;
+    if !is_valid_g2(&point) {
+        return Err(invalid_standard_point("not in the prime order subgroup"));
+    }
+    Ok(point)
+}
+
+// The `StandardCompressed` decoders check the points at the `Subgroup` level
+fn invalid_standard_point(reason: &'static str) -> BLSError {
+    BLSError::InvalidPoint {
+        level: ValidationLevel::Subgroup,
+        reason,
+    }
+}
+
+fn check_length(bytes: &[u8], expected: usize) -> BlsResult<()> {
+    if bytes.len() != expected {
+        return Err(BLSError::InvalidEncoding("unexpected length"));
+    }
+    Ok(())
+}
+
+// Arkworks sets the sign bit for the opposite `y` than zexe, unless the point is infinity
+fn flip_sign(bytes: &mut [u8]) {
+    let last = bytes.last_mut().expect("compressed points are not empty");
+    if *last & INFINITY_FLAG == 0 {
+        *last ^= SIGN_FLAG;
+    }
+}
+
+fn serialize_compressed<T: CanonicalSerialize>(
+    element: &T,
+    profile: SerializationProfile,
+) -> BlsResult<Vec<u8>> {
+    let mut bytes = vec![];
+    element.serialize(&mut bytes)?;
+    if profile == SerializationProfile::ArkworksCanonical {
+        flip_sign(&mut bytes);
+    }
+    Ok(bytes)
+}
+
+fn deserialize_compressed<T: CanonicalDeserialize>(
+    bytes: &[u8],
+    length: usize,
+    profile: SerializationProfile,
+) -> BlsResult<T> {
+    check_length(bytes, length)?;
+    let mut bytes = bytes.to_vec();
+    if profile == SerializationProfile::ArkworksCanonical {
+        flip_sign(&mut bytes);
+    }
+    Ok(T::deserialize(&mut &bytes[..])?)
+}
+
+// Serializes a point with the `ExplicitFlags` profile, where `write` appends a big-endian
+// coordinate of `coordinate_length` bytes
+fn serialize_flagged<P: SWModelParameters>(
+    point: &GroupAffine<P>,
+    compressed: bool,
+    coordinate_length: usize,
+    write: fn(&P::BaseField, &mut Vec<u8>) -> BlsResult<()>,
+) -> BlsResult<Vec<u8>> {
+    let mut flags = EXPLICIT_FLAGS_VERSION << 4;
+    if compressed {
+        flags |= EXPLICIT_COMPRESSED_FLAG;
+    }
+    let num_coordinates = if compressed { 1 } else { 2 };
+    let mut bytes = Vec::with_capacity(1 + num_coordinates * coordinate_length);
+    if point.is_zero() {
+        bytes.push(flags | EXPLICIT_INFINITY_FLAG);
+        bytes.resize(1 + num_coordinates * coordinate_length, 0);
+        return Ok(bytes);
+    }
+    if compressed && PointCompression::point_bit(point) {
+        flags |= EXPLICIT_SIGN_FLAG;
+    }
+    bytes.push(flags);
+    write(&point.x, &mut bytes)?;
+    if !compressed {
+        write(&point.y, &mut bytes)?;
+    }
+    Ok(bytes)
+}
+
+// Deserializes a point on the curve with the `ExplicitFlags` profile. The caller must check
+// that it is in the prime order subgroup.
+fn deserialize_flagged<P: SWModelParameters>(
+    bytes: &[u8],
+    coordinate_length: usize,
+    read: fn(&[u8]) -> BlsResult<P::BaseField>,
+) -> BlsResult<GroupAffine<P>> {
+    let (flags, coordinates) = bytes
+        .split_first()
+        .ok_or(BLSError::InvalidEncoding("unexpected length"))?;
+    if flags >> 4 != EXPLICIT_FLAGS_VERSION {
+        return Err(BLSError::InvalidEncoding("unsupported encoding version"));
+    }
+    if flags & EXPLICIT_RESERVED_FLAG != 0 {
+        return Err(BLSError::InvalidEncoding("reserved flag is set"));
+    }
+    let compressed = flags & EXPLICIT_COMPRESSED_FLAG != 0;
+    let sign = flags & EXPLICIT_SIGN_FLAG != 0;
+    let num_coordinates = if compressed { 1 } else { 2 };
+    check_length(coordinates, num_coordinates * coordinate_length)?;
+
+    if flags & EXPLICIT_INFINITY_FLAG != 0 {
+        if sign || coordinates.iter().any(|b| *b != 0) {
+            return Err(BLSError::InvalidEncoding("non-canonical point at infinity"));
+        }
+        return Ok(GroupAffine::zero());
+    }
+
+    let (x, y) = coordinates.split_at(coordinate_length);
+    let x = read(x)?;
+    if compressed {
+        PointCompression::point_from_x(x, sign)
+            .ok_or(BLSError::InvalidEncoding("x is not on the curve"))
+    } else {
+        if sign {
+            return Err(BLSError::InvalidEncoding(
+                "sign flag is set on an uncompressed point",
+            ));
+        }
+        let point = GroupAffine::new(x, read(y)?, false);
+        if !point.is_on_curve() {
+            return Err(BLSError::InvalidEncoding("not on the curve"));
+        }
+        Ok(point)
+    }
+}
+
+// Serializes a point with the `StandardCompressed` profile, where `write` appends the
+// big-endian `x` coordinate of `coordinate_length` bytes. The spare bits of the field leave
+// the three most significant bits of the first byte unset.
+fn serialize_standard<P: SWModelParameters>(
+    point: &GroupAffine<P>,
+    coordinate_length: usize,
+    write: fn(&P::BaseField, &mut Vec<u8>),
+) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(coordinate_length);
+    if point.is_zero() {
+        bytes.resize(coordinate_length, 0);
+        bytes[0] = STANDARD_COMPRESSED_FLAG | STANDARD_INFINITY_FLAG;
+        return bytes;
+    }
+    write(&point.x, &mut bytes);
+    bytes[0] |= STANDARD_COMPRESSED_FLAG;
+    if PointCompression::point_bit(point) {
+        bytes[0] |= STANDARD_SIGN_FLAG;
+    }
+    bytes
+}
+
+// Deserializes a point on the curve with the `StandardCompressed` profile. The caller must
+// check that it is in the prime order subgroup.
+fn deserialize_standard<P: SWModelParameters>(
+    bytes: &[u8],
+    coordinate_length: usize,
+    read: fn(&[u8]) -> BlsResult<P::BaseField>,
+) -> BlsResult<GroupAffine<P>> {
+    check_length(bytes, coordinate_length)?;
+    let flags = bytes[0] & STANDARD_FLAGS;
+    if flags & STANDARD_COMPRESSED_FLAG == 0 {
+        return Err(BLSError::InvalidEncoding("compression flag is not set"));
+    }
+    let sign = flags & STANDARD_SIGN_FLAG != 0;
+    let mut x = bytes.to_vec();
+    x[0] &= !STANDARD_FLAGS;
+
+    if flags & STANDARD_INFINITY_FLAG != 0 {
+        if sign || x.iter().any(|b| *b != 0) {
+            return Err(BLSError::InvalidEncoding("non-canonical point at infinity"));
+        }
+        return Ok(GroupAffine::zero());
+    }
+    PointCompression::point_from_x(read(&x)?, sign)
+        .ok_or_else(|| invalid_standard_point("not on the curve"))
+}
+
+fn write_fq_standard(element: &Fq, out: &mut Vec<u8>) {
+    write_fq(element, Fq::SERIALIZED_SIZE, out).expect("serializing to a vector cannot fail")
+}
+
+fn write_fq2_standard(element: &Fq2, out: &mut Vec<u8>) {
+    write_fq_standard(&element.c1, out);
+    write_fq_standard(&element.c0, out);
+}
+
+fn read_fq2_standard(bytes: &[u8]) -> BlsResult<Fq2> {
+    let (c1, c0) = bytes.split_at(bytes.len() / 2);
+    Ok(Fq2::new(read_fq(c0)?, read_fq(c1)?))
+}
+
+// Appends the big-endian element, zero-padded on the left to `length` bytes
+fn write_fq(element: &Fq, length: usize, out: &mut Vec<u8>) -> BlsResult<()> {
+    let mut bytes = vec![];
+    element.serialize(&mut bytes)?;
+    out.resize(out.len() + length - bytes.len(), 0);
+    out.extend(bytes.iter().rev());
+    Ok(())
+}
+
+fn write_fq2(element: &Fq2, length: usize, out: &mut Vec<u8>) -> BlsResult<()> {
+    write_fq(&element.c0, length, out)?;
+    write_fq(&element.c1, length, out)
+}
+
+// Reads a big-endian element which is zero-padded on the left
+fn read_fq(bytes: &[u8]) -> BlsResult<Fq> {
+    let (padding, bytes) = bytes.split_at(bytes.len() - Fq::SERIALIZED_SIZE);
+    if padding.iter().any(|b| *b != 0) {
+        return Err(BLSError::InvalidEncoding("non-zero padding"));
+    }
+    let mut bytes = bytes.to_vec();
+    bytes.reverse();
+    Ok(Fq::deserialize(&mut &bytes[..])?)
+}
+
+fn read_fq2(bytes: &[u8]) -> BlsResult<Fq2> {
+    let (c0, c1) = bytes.split_at(bytes.len() / 2);
+    Ok(Fq2::new(read_fq(c0)?, read_fq(c1)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G1Projective, UniformRand};
+    use rand::thread_rng;
+
+    const PROFILES: [SerializationProfile; 6] = [
+        SerializationProfile::CeloLegacy,
+        SerializationProfile::ArkworksCanonical,
+        SerializationProfile::EvmBigEndian,
+        SerializationProfile::ExplicitFlags { compressed: true },
+        SerializationProfile::ExplicitFlags { compressed: false },
+        SerializationProfile::StandardCompressed,
+    ];
+
+    #[test]
+    fn roundtrips_with_all_profiles() {
+        let rng = &mut thread_rng();
+        for _ in 0..10 {
+            let key = PrivateKey::generate(rng);
+            let public_key = key.to_public();
+            let signature = Signature::from(G1Projective::rand(rng));
+            for profile in PROFILES.iter().cloned() {
+                let bytes = key.serialize_with_profile(profile).unwrap();
+                let decoded = PrivateKey::deserialize_with_profile(&bytes, profile).unwrap();
+                assert_eq!(decoded.as_ref(), key.as_ref());
+
+                let bytes = public_key.serialize_with_profile(profile).unwrap();
+                assert_eq!(
+                    PublicKey::deserialize_with_profile(&bytes, profile).unwrap(),
+                    public_key
+                );
+
+                let bytes = signature.serialize_with_profile(profile).unwrap();
+                assert_eq!(
+                    Signature::deserialize_with_profile(&bytes, profile).unwrap(),
+                    signature
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn profiles_differ_as_documented() {
+        let rng = &mut thread_rng();
+        let key = PrivateKey::generate(rng);
+        let public_key = key.to_public();
+
+        let mut legacy = vec![];
+        public_key.serialize(&mut legacy).unwrap();
+        assert_eq!(
+            public_key
+                .serialize_with_profile(SerializationProfile::CeloLegacy)
+                .unwrap(),
+            legacy
+        );
+
+        let arkworks = public_key
+            .serialize_with_profile(SerializationProfile::ArkworksCanonical)
+            .unwrap();
+        assert_eq!(arkworks[..legacy.len() - 1], legacy[..legacy.len() - 1]);
+        assert_eq!(
+            arkworks[legacy.len() - 1],
+            legacy[legacy.len() - 1] ^ SIGN_FLAG
+        );
+
+        let evm = public_key
+            .serialize_with_profile(SerializationProfile::EvmBigEndian)
+            .unwrap();
+        assert_eq!(evm.len(), 256);
+        let x_c0 = &evm[EVM_FIELD_LENGTH - Fq::SERIALIZED_SIZE..EVM_FIELD_LENGTH];
+        let mut x_c0_le = x_c0.to_vec();
+        x_c0_le.reverse();
+        assert_eq!(x_c0_le[..], legacy[..Fq::SERIALIZED_SIZE]);
+
+        let mut key_bytes = key
+            .serialize_with_profile(SerializationProfile::EvmBigEndian)
+            .unwrap();
+        key_bytes.reverse();
+        assert_eq!(
+            key_bytes,
+            key.serialize_with_profile(SerializationProfile::CeloLegacy)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_evm_encodings() {
+        let rng = &mut thread_rng();
+        let signature = Signature::from(G1Projective::rand(rng));
+        let bytes = signature
+            .serialize_with_profile(SerializationProfile::EvmBigEndian)
+            .unwrap();
+
+        // not on the curve
+        let mut tampered = bytes.clone();
+        tampered[2 * EVM_FIELD_LENGTH - 1] ^= 1;
+        Signature::deserialize_with_profile(&tampered, SerializationProfile::EvmBigEndian)
+            .unwrap_err();
+
+        // non-zero padding
+        let mut tampered = bytes.clone();
+        tampered[0] = 1;
+        Signature::deserialize_with_profile(&tampered, SerializationProfile::EvmBigEndian)
+            .unwrap_err();
+
+        // wrong length
+        Signature::deserialize_with_profile(&bytes[1..], SerializationProfile::EvmBigEndian)
+            .unwrap_err();
+
+        // infinity is all zeros
+        let infinity = Signature::from(G1Projective::zero());
+        let bytes = infinity
+            .serialize_with_profile(SerializationProfile::EvmBigEndian)
+            .unwrap();
+        assert!(bytes.iter().all(|b| *b == 0));
+        assert_eq!(
+            Signature::deserialize_with_profile(&bytes, SerializationProfile::EvmBigEndian)
+                .unwrap(),
+            infinity
+        );
+    }
+
+    #[test]
+    fn explicit_flags_layout() {
+        let rng = &mut thread_rng();
+        let compressed = SerializationProfile::ExplicitFlags { compressed: true };
+        let uncompressed = SerializationProfile::ExplicitFlags { compressed: false };
+        let signature = Signature::from(G1Projective::rand(rng));
+        let public_key = PrivateKey::generate(rng).to_public();
+
+        let bytes = signature.serialize_with_profile(compressed).unwrap();
+        assert_eq!(bytes.len(), 1 + 48);
+        assert_eq!(bytes[0] & !EXPLICIT_SIGN_FLAG, 0x11);
+        let full = signature.serialize_with_profile(uncompressed).unwrap();
+        assert_eq!(full.len(), 1 + 96);
+        assert_eq!(full[0], 0x10);
+        assert_eq!(full[1..49], bytes[1..]);
+        // either form is accepted
+        assert_eq!(
+            Signature::deserialize_with_profile(&full, compressed).unwrap(),
+            signature
+        );
+
+        // x is big-endian
+        let mut legacy = vec![];
+        signature.serialize(&mut legacy).unwrap();
+        let last = legacy.len() - 1;
+        legacy[last] &= !(INFINITY_FLAG | SIGN_FLAG);
+        legacy.reverse();
+        assert_eq!(bytes[1..], legacy[..]);
+
+        let bytes = public_key.serialize_with_profile(compressed).unwrap();
+        assert_eq!(bytes.len(), 1 + 96);
+
+        let infinity = Signature::from(G1Projective::zero())
+            .serialize_with_profile(compressed)
+            .unwrap();
+        assert_eq!(infinity[0], 0x13);
+        assert!(infinity[1..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn rejects_invalid_explicit_flags() {
+        let rng = &mut thread_rng();
+        let profile = SerializationProfile::ExplicitFlags { compressed: true };
+        let signature = Signature::from(G1Projective::rand(rng));
+        let bytes = signature.serialize_with_profile(profile).unwrap();
+        let decode = |bytes: &[u8]| Signature::deserialize_with_profile(bytes, profile);
+
+        // the other sign decodes to the negated point
+        let mut negated = bytes.clone();
+        negated[0] ^= EXPLICIT_SIGN_FLAG;
+        assert_eq!(
+            decode(&negated).unwrap(),
+            Signature::from(-*signature.as_ref())
+        );
+
+        let mut version = bytes.clone();
+        version[0] = (version[0] & 0x0f) | ((EXPLICIT_FLAGS_VERSION + 1) << 4);
+        decode(&version).unwrap_err();
+
+        let mut reserved = bytes.clone();
+        reserved[0] |= EXPLICIT_RESERVED_FLAG;
+        decode(&reserved).unwrap_err();
+
+        // the compression flag determines the length
+        let mut uncompressed = bytes.clone();
+        uncompressed[0] &= !EXPLICIT_COMPRESSED_FLAG;
+        decode(&uncompressed).unwrap_err();
+
+        let mut infinity = bytes.clone();
+        infinity[0] |= EXPLICIT_INFINITY_FLAG;
+        decode(&infinity).unwrap_err();
+
+        // x is larger than the modulus
+        let mut large = bytes;
+        for byte in &mut large[1..] {
+            *byte = 0xff;
+        }
+        decode(&large).unwrap_err();
+        decode(&[]).unwrap_err();
+    }
+
+    #[test]
+    fn profiles_roundtrip_over_ffi() {
+        for profile in PROFILES.iter().cloned() {
+            assert_eq!(
+                SerializationProfile::try_from(i32::from(profile)).unwrap(),
+                profile
+            );
+        }
+        SerializationProfile::try_from(6).unwrap_err();
+        SerializationProfile::try_from(-1).unwrap_err();
+    }
+
+    #[test]
+    fn standard_compressed_layout() {
+        let rng = &mut thread_rng();
+        let profile = SerializationProfile::StandardCompressed;
+        let signature = Signature::from(G1Projective::rand(rng));
+        let public_key = PrivateKey::generate(rng).to_public();
+
+        // x is big-endian, with the flags in the top bits of the first byte
+        let bytes = signature.serialize_with_profile(profile).unwrap();
+        assert_eq!(bytes.len(), 48);
+        let explicit = signature
+            .serialize_with_profile(SerializationProfile::ExplicitFlags { compressed: true })
+            .unwrap();
+        assert_eq!(bytes[0] & !STANDARD_FLAGS, explicit[1]);
+        assert_eq!(bytes[1..], explicit[2..]);
+        assert_eq!(
+            bytes[0] & STANDARD_COMPRESSED_FLAG,
+            STANDARD_COMPRESSED_FLAG
+        );
+        assert_eq!(
+            bytes[0] & STANDARD_SIGN_FLAG != 0,
+            explicit[0] & EXPLICIT_SIGN_FLAG != 0
+        );
+        assert_eq!(signature.to_compressed_standard(), bytes);
+        assert_eq!(
+            Signature::from_compressed_standard(&bytes).unwrap(),
+            signature
+        );
+
+        // the coefficients of x are written c1 first
+        let bytes = public_key.serialize_with_profile(profile).unwrap();
+        assert_eq!(bytes.len(), 96);
+        let explicit = public_key
+            .serialize_with_profile(SerializationProfile::ExplicitFlags { compressed: true })
+            .unwrap();
+        assert_eq!(bytes[0] & !STANDARD_FLAGS, explicit[49]);
+        assert_eq!(bytes[1..48], explicit[50..]);
+        assert_eq!(bytes[48..], explicit[1..49]);
+        assert_eq!(public_key.to_compressed_standard(), bytes);
+        assert_eq!(
+            PublicKey::from_compressed_standard(&bytes).unwrap(),
+            public_key
+        );
+
+        let infinity = g1_to_compressed_standard(&G1Affine::zero());
+        assert_eq!(infinity[0], 0xc0);
+        assert!(infinity[1..].iter().all(|b| *b == 0));
+        assert!(g1_from_compressed_standard(&infinity).unwrap().is_zero());
+        let infinity = g2_to_compressed_standard(&G2Affine::zero());
+        assert_eq!(infinity.len(), 96);
+        assert!(g2_from_compressed_standard(&infinity).unwrap().is_zero());
+    }
+
+    #[test]
+    fn rejects_invalid_standard_encodings() {
+        let rng = &mut thread_rng();
+        let signature = Signature::from(G1Projective::rand(rng));
+        let bytes = signature.to_compressed_standard();
+
+        // the other sign decodes to the negated point
+        let mut negated = bytes.clone();
+        negated[0] ^= STANDARD_SIGN_FLAG;
+        assert_eq!(
+            Signature::from_compressed_standard(&negated).unwrap(),
+            Signature::from(-*signature.as_ref())
+        );
+
+        let mut uncompressed = bytes.clone();
+        uncompressed[0] &= !STANDARD_COMPRESSED_FLAG;
+        g1_from_compressed_standard(&uncompressed).unwrap_err();
+
+        let mut infinity = bytes.clone();
+        infinity[0] |= STANDARD_INFINITY_FLAG;
+        g1_from_compressed_standard(&infinity).unwrap_err();
+
+        let mut infinity = g1_to_compressed_standard(&G1Affine::zero());
+        infinity[0] |= STANDARD_SIGN_FLAG;
+        g1_from_compressed_standard(&infinity).unwrap_err();
+
+        // x is larger than the modulus
+        let mut large = bytes.clone();
+        for byte in &mut large[..] {
+            *byte |= 0x1f;
+        }
+        for byte in &mut large[1..] {
+            *byte = 0xff;
+        }
+        g1_from_compressed_standard(&large).unwrap_err();
+        g1_from_compressed_standard(&bytes[1..]).unwrap_err();
+        g2_from_compressed_standard(&bytes).unwrap_err();
+
+        // valid encodings of points which are off the curve or outside the subgroup
+        let off_curve = loop {
+            let x = Fq::rand(rng);
+            if G1Affine::get_point_from_x(x, false).is_none() {
+                let mut bytes = vec![];
+                write_fq_standard(&x, &mut bytes);
+                bytes[0] |= STANDARD_COMPRESSED_FLAG;
+                break bytes;
+            }
+        };
+        let outside = loop {
+            if let Some(point) = G1Affine::get_point_from_x(Fq::rand(rng), false) {
+                if !point.is_in_correct_subgroup_assuming_on_curve() {
+                    break g1_to_compressed_standard(&point);
+                }
+            }
+        };
+        for (bytes, expected) in &[
+            (off_curve, "not on the curve"),
+            (outside, "not in the prime order subgroup"),
+        ] {
+            match g1_from_compressed_standard(bytes) {
+                Err(BLSError::InvalidPoint {
+                    level: ValidationLevel::Subgroup,
+                    reason,
+                }) => assert_eq!(reason, *expected),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,424 @@
+//! Istanbul's quorum rules.
+//!
+//! A block is sealed by a quorum of `ceil(2n / 3)` of the `n` validators of its epoch, so at
+//! most `n - ceil(2n / 3) = floor(n / 3)` of them may not sign it. These are the only
+//! implementations of the rules in the workspace: the epoch encoding, the native seal
+//! verification and the FFI all call them, so that they cannot disagree by one on a
+//! boundary.
+//!
+//! A `QuorumCertificate` holds the seal of a message: the bitmap of the validators of an
+//! epoch which signed it and their aggregate signature. It is the unit which batch
+//! verification, the ingestion of epochs and the forensics of seals exchange, and it is
+//! stored in an envelope of kind `QUORUM_CERTIFICATE_KIND`.
+use crate::{
+    bls::pack_bitmap,
+    envelope::{Versioned, QUORUM_CERTIFICATE_KIND},
+    BLSError, BlsResult, HashToCurve, PublicKey, PublicKeyCache, Signature, SIG_DOMAIN,
+};
+use algebra::{bls12_377::G1Projective, CanonicalDeserialize, CanonicalSerialize};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::{borrow::Borrow, convert::TryFrom, io::Read};
+
+/// The number of signers which form a quorum of `num_validators`, i.e. `ceil(2n / 3)`
+pub fn quorum_size(num_validators: usize) -> usize {
+    num_validators - max_non_signers(num_validators)
+}
+
+/// The maximum number of validators which may not sign, i.e. `floor(n / 3)`
+pub fn max_non_signers(num_validators: usize) -> usize {
+    num_validators / 3
+}
+
+/// Returns true if the validators set in the bitmap form a quorum of `num_validators`. An
+/// empty validator set has no quorum.
+pub fn is_quorum(bitmap: &[bool], num_validators: usize) -> BlsResult<bool> {
+    if bitmap.len() != num_validators {
+        return Err(BLSError::BitmapLengthMismatch(bitmap.len(), num_validators));
+    }
+    let signers = bitmap.iter().filter(|signed| **signed).count();
+    Ok(num_validators > 0 && signers >= quorum_size(num_validators))
+}
+
+/// Verifies the seal of a block: the validators set in the bitmap must form a quorum, and
+/// the signature must be their aggregate signature of the message.
+pub fn verify_seal<H: HashToCurve<Output = G1Projective>>(
+    validators: &[PublicKey],
+    bitmap: &[bool],
+    message: &[u8],
+    extra_data: &[u8],
+    signature: &Signature,
+    hash_to_g1: &H,
+) -> BlsResult<()> {
+    if !is_quorum(bitmap, validators.len())? {
+        return Err(BLSError::NoQuorum {
+            signers: bitmap.iter().filter(|signed| **signed).count(),
+            quorum: quorum_size(validators.len()),
+        });
+    }
+    let signers = validators
+        .iter()
+        .zip(bitmap)
+        .filter(|(_, signed)| **signed)
+        .map(|(key, _)| key);
+    PublicKey::aggregate(signers).verify(message, extra_data, signature, hash_to_g1)
+}
+
+/// The seal of a message by the validators of an epoch
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuorumCertificate {
+    /// The id of the epoch whose validators signed the message, as used by `PublicKeyCache`
+    pub epoch_id: u64,
+    /// The signed message
+    pub message: Vec<u8>,
+    /// The validators of the epoch which signed the message, in the order of the validator
+    /// set
+    pub bitmap: Vec<bool>,
+    /// The aggregate signature of the message by the validators set in the bitmap
+    pub aggregate_signature: Signature,
+}
+
+impl QuorumCertificate {
+    /// Returns the indices of the validators set in the bitmap
+    pub fn signers(&self) -> Vec<usize> {
+        self.bitmap
+            .iter()
+            .enumerate()
+            .filter(|(_, signed)| **signed)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Verifies the certificate against the validators of its epoch, see `verify_seal`
+    pub fn verify<H: HashToCurve<Output = G1Projective>>(
+        &self,
+        validators: &[PublicKey],
+        extra_data: &[u8],
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        verify_seal(
+            validators,
+            &self.bitmap,
+            &self.message,
+            extra_data,
+            &self.aggregate_signature,
+            hash_to_g1,
+        )
+    }
+
+    /// Verifies the certificate against the validators of its epoch which were provided to
+    /// the cache with `set_validators`, reusing the cached aggregate of its bitmap
+    pub fn verify_cached<H: HashToCurve<Output = G1Projective>>(
+        &self,
+        cache: &mut PublicKeyCache,
+        extra_data: &[u8],
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        self.check_quorum()?;
+        cache
+            .aggregate_for_bitmap(self.epoch_id, &self.bitmap)?
+            .verify(
+                &self.message,
+                extra_data,
+                &self.aggregate_signature,
+                hash_to_g1,
+            )
+    }
+
+    /// Verifies several certificates at once, each against the validators of its epoch in
+    /// `validators`. The certificates are weighted with random coefficients, see
+    /// `Signature::verify_batch_independent`, so that the signatures of two certificates cannot
+    /// be swapped, and their messages may repeat.
+    pub fn batch_verify<H, V>(
+        certificates: &[QuorumCertificate],
+        validators: &[V],
+        extra_data: &[u8],
+        hash_to_g1: &H,
+    ) -> BlsResult<()>
+    where
+        H: HashToCurve<Output = G1Projective>,
+        V: Borrow<[PublicKey]>,
+    {
+        if certificates.len() != validators.len() {
+            return Err(BLSError::UnevenNumKeysMessages);
+        }
+        let mut items = Vec::with_capacity(certificates.len());
+        for (certificate, validators) in certificates.iter().zip(validators) {
+            let validators = validators.borrow();
+            if !is_quorum(&certificate.bitmap, validators.len())? {
+                return Err(certificate.no_quorum());
+            }
+            let signers = certificate.signers();
+            items.push((
+                PublicKey::aggregate(signers.iter().map(|index| &validators[*index])),
+                &certificate.message[..],
+                extra_data,
+                certificate.aggregate_signature.clone(),
+            ));
+        }
+        Signature::verify_batch_independent(&items, SIG_DOMAIN, hash_to_g1)
+    }
+
+    fn check_quorum(&self) -> BlsResult<()> {
+        if !is_quorum(&self.bitmap, self.bitmap.len())? {
+            return Err(self.no_quorum());
+        }
+        Ok(())
+    }
+
+    fn no_quorum(&self) -> BLSError {
+        BLSError::NoQuorum {
+            signers: self.signers().len(),
+            quorum: quorum_size(self.bitmap.len()),
+        }
+    }
+}
+
+/// Version 1: the epoch id as a `u64`, the length of the bitmap as a `u64` followed by its
+/// bits packed in bytes, least significant bit first, the length of the message as a `u32`
+/// followed by the message, and the compressed aggregate signature
+impl Versioned for QuorumCertificate {
+    const KIND: u8 = QUORUM_CERTIFICATE_KIND;
+    const VERSION: u16 = 1;
+
+    fn encode_payload(&self) -> BlsResult<Vec<u8>> {
+        let mut bytes = vec![];
+        bytes.write_u64::<LittleEndian>(self.epoch_id)?;
+        bytes.extend(pack_bitmap(&self.bitmap));
+        let message_len = u32::try_from(self.message.len())
+            .map_err(|_| BLSError::InvalidEncoding("message is too long"))?;
+        bytes.write_u32::<LittleEndian>(message_len)?;
+        bytes.extend_from_slice(&self.message);
+        self.aggregate_signature.serialize(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn decode_payload(version: u16, payload: &[u8]) -> BlsResult<Self> {
+        if version != Self::VERSION {
+            return Err(BLSError::UnsupportedVersion {
+                kind: Self::KIND,
+                version,
+            });
+        }
+        let reader = &mut &payload[..];
+        let epoch_id = reader.read_u64::<LittleEndian>()?;
+        let bitmap_len = reader.read_u64::<LittleEndian>()?;
+        // checked before allocating, as the length is untrusted
+        let bitmap_too_long = || BLSError::InvalidEncoding("bitmap is longer than the payload");
+        let packed_len = bitmap_len.checked_add(7).ok_or_else(bitmap_too_long)? / 8;
+        if packed_len > reader.len() as u64 {
+            return Err(bitmap_too_long());
+        }
+        let bitmap_len = usize::try_from(bitmap_len).map_err(|_| bitmap_too_long())?;
+        let mut packed = vec![0u8; packed_len as usize];
+        reader.read_exact(&mut packed)?;
+        let bitmap = (0..bitmap_len)
+            .map(|i| packed[i / 8] & (1 << (i % 8)) != 0)
+            .collect();
+        let message_len = reader.read_u32::<LittleEndian>()? as usize;
+        if message_len > reader.len() {
+            return Err(BLSError::InvalidEncoding(
+                "message is longer than the payload",
+            ));
+        }
+        let mut message = vec![0u8; message_len];
+        reader.read_exact(&mut message)?;
+        let aggregate_signature = Signature::deserialize(&mut *reader)?;
+        if !reader.is_empty() {
+            return Err(BLSError::InvalidEncoding(
+                "trailing bytes after the payload",
+            ));
+        }
+        Ok(QuorumCertificate {
+            epoch_id,
+            message,
+            bitmap,
+            aggregate_signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, PrivateKey};
+    use algebra::UniformRand;
+
+    #[test]
+    fn matches_istanbul() {
+        // (validators, quorum, max non signers)
+        let expected = [
+            (1, 1, 0),
+            (2, 2, 0),
+            (3, 2, 1),
+            (4, 3, 1),
+            (5, 4, 1),
+            (6, 4, 2),
+            (7, 5, 2),
+            (100, 67, 33),
+            (150, 100, 50),
+        ];
+        for (n, quorum, non_signers) in expected.iter() {
+            assert_eq!(quorum_size(*n), *quorum, "quorum of {}", n);
+            assert_eq!(max_non_signers(*n), *non_signers, "non signers of {}", n);
+        }
+        // ceil(2n / 3), computed without overflow
+        for n in 0..1000 {
+            assert_eq!(quorum_size(n), (2 * n + 2) / 3);
+        }
+        assert_eq!(max_non_signers(usize::max_value()), usize::max_value() / 3);
+    }
+
+    #[test]
+    fn checks_bitmaps() {
+        assert!(is_quorum(&[true, true, false], 3).unwrap());
+        assert!(!is_quorum(&[true, false, false], 3).unwrap());
+        assert!(is_quorum(&[true, true, true, false], 4).unwrap());
+        assert!(!is_quorum(&[true, true, false, false], 4).unwrap());
+        assert!(!is_quorum(&[], 0).unwrap());
+        match is_quorum(&[true; 3], 4) {
+            Err(BLSError::BitmapLengthMismatch(3, 4)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn verifies_seals() {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let keys = (0..4)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let validators = keys.iter().map(|key| key.to_public()).collect::<Vec<_>>();
+        let message = b"block";
+        let sign = |bitmap: &[bool]| {
+            let signatures = keys
+                .iter()
+                .zip(bitmap)
+                .filter(|(_, signed)| **signed)
+                .map(|(key, _)| key.sign(message, &[], hasher).unwrap())
+                .collect::<Vec<_>>();
+            Signature::aggregate(&signatures)
+        };
+
+        let bitmap = [true, false, true, true];
+        verify_seal(&validators, &bitmap, message, &[], &sign(&bitmap), hasher).unwrap();
+        // the signature must be from the validators of the bitmap
+        let other = [true, true, false, true];
+        verify_seal(&validators, &other, message, &[], &sign(&bitmap), hasher).unwrap_err();
+
+        let bitmap = [true, false, false, true];
+        match verify_seal(&validators, &bitmap, message, &[], &sign(&bitmap), hasher) {
+            Err(BLSError::NoQuorum {
+                signers: 2,
+                quorum: 3,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn verifies_certificates() {
+        let rng = &mut rand::thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let keys = (0..4)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let validators = keys.iter().map(|key| key.to_public()).collect::<Vec<_>>();
+        let certify = |epoch_id, message: &[u8], bitmap: Vec<bool>| {
+            let signatures = keys
+                .iter()
+                .zip(&bitmap)
+                .filter(|(_, signed)| **signed)
+                .map(|(key, _)| key.sign(message, &[], hasher).unwrap())
+                .collect::<Vec<_>>();
+            QuorumCertificate {
+                epoch_id,
+                message: message.to_vec(),
+                bitmap,
+                aggregate_signature: Signature::aggregate(&signatures),
+            }
+        };
+
+        let first = certify(7, b"first", vec![true, false, true, true]);
+        let second = certify(7, b"second", vec![true, true, true, false]);
+        assert_eq!(first.signers(), vec![0, 2, 3]);
+        first.verify(&validators, &[], hasher).unwrap();
+        QuorumCertificate::batch_verify(
+            &[first.clone(), second.clone()],
+            &[&validators[..], &validators[..]],
+            &[],
+            hasher,
+        )
+        .unwrap();
+
+        let mut cache = PublicKeyCache::new();
+        cache.set_validators(7, validators.clone());
+        first.verify_cached(&mut cache, &[], hasher).unwrap();
+
+        // a certificate of another message fails the batch
+        let mut forged = second.clone();
+        forged.message = b"forged".to_vec();
+        QuorumCertificate::batch_verify(
+            &[first.clone(), forged.clone()],
+            &[&validators[..], &validators[..]],
+            &[],
+            hasher,
+        )
+        .unwrap_err();
+        forged.verify_cached(&mut cache, &[], hasher).unwrap_err();
+
+        // swapping the signatures of two certificates keeps their sum, but fails the batch
+        let (mut first_swapped, mut second_swapped) = (first.clone(), second.clone());
+        first_swapped.aggregate_signature = second.aggregate_signature.clone();
+        second_swapped.aggregate_signature = first.aggregate_signature.clone();
+        QuorumCertificate::batch_verify(
+            &[first_swapped, second_swapped],
+            &[&validators[..], &validators[..]],
+            &[],
+            hasher,
+        )
+        .unwrap_err();
+
+        let no_quorum = certify(7, b"third", vec![true, false, false, true]);
+        match no_quorum.verify_cached(&mut cache, &[], hasher) {
+            Err(BLSError::NoQuorum {
+                signers: 2,
+                quorum: 3,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match QuorumCertificate::batch_verify(&[no_quorum], &[&validators[..]], &[], hasher) {
+            Err(BLSError::NoQuorum { .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn roundtrips_certificates_in_envelopes() {
+        let rng = &mut rand::thread_rng();
+        let certificate = QuorumCertificate {
+            epoch_id: 42,
+            message: b"block".to_vec(),
+            bitmap: (0..11).map(|i| i % 3 != 0).collect(),
+            aggregate_signature: Signature::from(G1Projective::rand(rng)),
+        };
+        let bytes = certificate.to_envelope().unwrap();
+        assert_eq!(
+            QuorumCertificate::from_envelope(&bytes).unwrap(),
+            certificate
+        );
+
+        // lengths which exceed the payload are rejected before allocating
+        let mut payload = certificate.encode_payload().unwrap();
+        for bitmap_len in &[u64::max_value(), u64::max_value() - 7, 1 << 40] {
+            payload[8..16].copy_from_slice(&bitmap_len.to_le_bytes());
+            match QuorumCertificate::decode_payload(1, &payload) {
+                Err(BLSError::InvalidEncoding("bitmap is longer than the payload")) => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+        let payload = certificate.encode_payload().unwrap();
+        QuorumCertificate::decode_payload(1, &payload[..payload.len() - 1]).unwrap_err();
+        QuorumCertificate::decode_payload(2, &payload).unwrap_err();
+    }
+}
@@ -0,0 +1,309 @@
+//! Validator registration payloads, as submitted to Celo's `Validators` contract.
+//!
+//! A validator registers by calling `registerValidator(bytes,bytes,bytes)` with:
+//! 1. its 64 byte uncompressed ECDSA public key, which must hash to the validator's address
+//! 1. its compressed BLS public key
+//! 1. a BLS proof of possession over the validator's address
+use crate::{BLSError, BlsResult, HashToCurve, PrivateKey, PublicKey, Signature};
+
+use algebra::{bls12_377::G1Projective, CanonicalDeserialize, CanonicalSerialize};
+use std::convert::TryFrom;
+use tiny_keccak::{Hasher as KeccakHasher, Keccak};
+
+/// The signature of the contract method which registers a validator
+pub const REGISTER_VALIDATOR_SIGNATURE: &str = "registerValidator(bytes,bytes,bytes)";
+
+/// Length of an uncompressed secp256k1 public key without its prefix byte
+pub const ECDSA_PUBLIC_KEY_LENGTH: usize = 64;
+
+/// Length of a compressed BLS public key
+pub const BLS_PUBLIC_KEY_LENGTH: usize = 96;
+
+/// Length of a compressed BLS proof of possession
+pub const BLS_POP_LENGTH: usize = 48;
+
+/// Length of an account address
+pub const ADDRESS_LENGTH: usize = 20;
+
+const WORD: usize = 32;
+
+/// The full payload which is submitted when registering a validator
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegistrationPayload {
+    /// The address of the validator's account
+    pub address: [u8; ADDRESS_LENGTH],
+    /// The validator's uncompressed ECDSA public key, without its prefix byte
+    pub ecdsa_public_key: Vec<u8>,
+    /// The validator's BLS public key
+    pub bls_public_key: PublicKey,
+    /// The proof of possession of the BLS key, signed over the address
+    pub bls_pop: Signature,
+}
+
+impl RegistrationPayload {
+    /// Creates the payload for the validator at `address`, signing the proof of possession
+    /// with the provided private key
+    pub fn new<H: HashToCurve<Output = G1Projective>>(
+        private_key: &PrivateKey,
+        address: [u8; ADDRESS_LENGTH],
+        ecdsa_public_key: &[u8],
+        hash_to_g1: &H,
+    ) -> BlsResult<Self> {
+        let payload = Self {
+            address,
+            ecdsa_public_key: ecdsa_public_key.to_vec(),
+            bls_public_key: private_key.to_public(),
+            bls_pop: private_key.sign_pop(&address, hash_to_g1)?,
+        };
+        payload.verify_ecdsa_binding()?;
+        Ok(payload)
+    }
+
+    /// Verifies that the ECDSA public key belongs to the address and that the proof of
+    /// possession is valid for the BLS public key
+    pub fn verify<H: HashToCurve<Output = G1Projective>>(&self, hash_to_g1: &H) -> BlsResult<()> {
+        self.verify_ecdsa_binding()?;
+        self.bls_public_key
+            .verify_pop(&self.address, &self.bls_pop, hash_to_g1)
+    }
+
+    fn verify_ecdsa_binding(&self) -> BlsResult<()> {
+        if self.ecdsa_public_key.len() != ECDSA_PUBLIC_KEY_LENGTH {
+            return Err(BLSError::InvalidRegistration(
+                "ECDSA public key must be 64 bytes",
+            ));
+        }
+        if address_from_ecdsa_public_key(&self.ecdsa_public_key) != self.address {
+            return Err(BLSError::EcdsaBindingMismatch);
+        }
+        Ok(())
+    }
+
+    /// ABI-encodes the payload as calldata for `registerValidator(bytes,bytes,bytes)`
+    pub fn encode(&self) -> BlsResult<Vec<u8>> {
+        let mut bls_public_key = Vec::with_capacity(BLS_PUBLIC_KEY_LENGTH);
+        self.bls_public_key.serialize(&mut bls_public_key)?;
+        let mut bls_pop = Vec::with_capacity(BLS_POP_LENGTH);
+        self.bls_pop.serialize(&mut bls_pop)?;
+
+        let mut calldata = selector().to_vec();
+        calldata.extend_from_slice(&encode_bytes_arguments(&[
+            &self.ecdsa_public_key,
+            &bls_public_key,
+            &bls_pop,
+        ]));
+        Ok(calldata)
+    }
+
+    /// Decodes `registerValidator(bytes,bytes,bytes)` calldata which was sent by `address`.
+    /// The decoded payload is not verified, call `verify` for that.
+    pub fn decode(address: [u8; ADDRESS_LENGTH], calldata: &[u8]) -> BlsResult<Self> {
+        if calldata.len() < 4 || calldata[..4] != selector() {
+            return Err(BLSError::InvalidRegistration("unknown method selector"));
+        }
+        let args = decode_bytes_arguments(&calldata[4..], 3)?;
+
+        let ecdsa_public_key = args[0];
+        if ecdsa_public_key.len() != ECDSA_PUBLIC_KEY_LENGTH {
+            return Err(BLSError::InvalidRegistration(
+                "ECDSA public key must be 64 bytes",
+            ));
+        }
+        if args[1].len() != BLS_PUBLIC_KEY_LENGTH {
+            return Err(BLSError::InvalidRegistration(
+                "BLS public key must be 96 bytes",
+            ));
+        }
+        if args[2].len() != BLS_POP_LENGTH {
+            return Err(BLSError::InvalidRegistration(
+                "BLS proof of possession must be 48 bytes",
+            ));
+        }
+
+        Ok(Self {
+            address,
+            ecdsa_public_key: ecdsa_public_key.to_vec(),
+            bls_public_key: PublicKey::deserialize(&mut &args[1][..])?,
+            bls_pop: Signature::deserialize(&mut &args[2][..])?,
+        })
+    }
+}
+
+/// Derives the account address from an uncompressed ECDSA public key (without its prefix
+/// byte) by taking the last 20 bytes of its Keccak-256 hash
+pub fn address_from_ecdsa_public_key(ecdsa_public_key: &[u8]) -> [u8; ADDRESS_LENGTH] {
+    let hash = keccak256(ecdsa_public_key);
+    let mut address = [0u8; ADDRESS_LENGTH];
+    address.copy_from_slice(&hash[WORD - ADDRESS_LENGTH..]);
+    address
+}
+
+fn keccak256(data: &[u8]) -> [u8; WORD] {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut out = [0u8; WORD];
+    hasher.finalize(&mut out);
+    out
+}
+
+fn selector() -> [u8; 4] {
+    let hash = keccak256(REGISTER_VALIDATOR_SIGNATURE.as_bytes());
+    let mut selector = [0u8; 4];
+    selector.copy_from_slice(&hash[..4]);
+    selector
+}
+
+fn encode_word(value: usize) -> [u8; WORD] {
+    let mut word = [0u8; WORD];
+    word[WORD - 8..].copy_from_slice(&(value as u64).to_be_bytes());
+    word
+}
+
+/// Returns the `len` bytes of the calldata starting at `offset`, which are attacker
+/// controlled and may overflow
+fn calldata_range(data: &[u8], offset: usize, len: usize) -> BlsResult<&[u8]> {
+    let end = offset
+        .checked_add(len)
+        .ok_or(BLSError::InvalidRegistration("word out of range"))?;
+    data.get(offset..end)
+        .ok_or(BLSError::InvalidRegistration("calldata too short"))
+}
+
+fn decode_word(data: &[u8], offset: usize) -> BlsResult<usize> {
+    let word = calldata_range(data, offset, WORD)?;
+    // Anything which does not fit in a u64 is larger than any valid calldata
+    if word[..WORD - 8].iter().any(|b| *b != 0) {
+        return Err(BLSError::InvalidRegistration("word out of range"));
+    }
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&word[WORD - 8..]);
+    usize::try_from(u64::from_be_bytes(value))
+        .map_err(|_| BLSError::InvalidRegistration("word out of range"))
+}
+
+/// ABI-encodes a list of dynamic `bytes` arguments: the head contains the offset of each
+/// argument, followed by each argument's length and its data padded to a multiple of 32 bytes
+fn encode_bytes_arguments(args: &[&[u8]]) -> Vec<u8> {
+    let mut head = Vec::with_capacity(args.len() * WORD);
+    let mut tail = Vec::new();
+    for arg in args {
+        head.extend_from_slice(&encode_word(args.len() * WORD + tail.len()));
+        tail.extend_from_slice(&encode_word(arg.len()));
+        tail.extend_from_slice(arg);
+        let padding = (WORD - arg.len() % WORD) % WORD;
+        tail.extend(std::iter::repeat(0).take(padding));
+    }
+    head.extend_from_slice(&tail);
+    head
+}
+
+fn decode_bytes_arguments(data: &[u8], num_args: usize) -> BlsResult<Vec<&[u8]>> {
+    (0..num_args)
+        .map(|i| {
+            let offset = decode_word(data, i * WORD)?;
+            let len = decode_word(data, offset)?;
+            let start = offset
+                .checked_add(WORD)
+                .ok_or(BLSError::InvalidRegistration("word out of range"))?;
+            calldata_range(data, start, len)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1;
+    use rand::{thread_rng, Rng};
+
+    fn random_payload() -> RegistrationPayload {
+        let rng = &mut thread_rng();
+        let ecdsa_public_key = (0..ECDSA_PUBLIC_KEY_LENGTH)
+            .map(|_| rng.gen())
+            .collect::<Vec<u8>>();
+        let address = address_from_ecdsa_public_key(&ecdsa_public_key);
+        let private_key = PrivateKey::generate(rng);
+        RegistrationPayload::new(
+            &private_key,
+            address,
+            &ecdsa_public_key,
+            &*DIRECT_HASH_TO_G1,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let payload = random_payload();
+        payload.verify(&*DIRECT_HASH_TO_G1).unwrap();
+
+        let calldata = payload.encode().unwrap();
+        // selector + 3 offsets + 3 lengths + 64 + 96 + 64 (48 padded) bytes of data
+        assert_eq!(calldata.len(), 4 + 6 * 32 + 64 + 96 + 64);
+
+        let decoded = RegistrationPayload::decode(payload.address, &calldata).unwrap();
+        assert_eq!(decoded, payload);
+        decoded.verify(&*DIRECT_HASH_TO_G1).unwrap();
+    }
+
+    #[test]
+    fn rejects_wrong_address() {
+        let mut payload = random_payload();
+        payload.address[0] ^= 1;
+        match payload.verify(&*DIRECT_HASH_TO_G1) {
+            Err(BLSError::EcdsaBindingMismatch) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn rejects_pop_for_other_key() {
+        let mut payload = random_payload();
+        payload.bls_public_key = PrivateKey::generate(&mut thread_rng()).to_public();
+        payload.verify(&*DIRECT_HASH_TO_G1).unwrap_err();
+    }
+
+    #[test]
+    fn rejects_malformed_calldata() {
+        let payload = random_payload();
+        let calldata = payload.encode().unwrap();
+
+        RegistrationPayload::decode(payload.address, &calldata[..calldata.len() - WORD])
+            .unwrap_err();
+
+        let mut wrong_selector = calldata.clone();
+        wrong_selector[0] ^= 1;
+        RegistrationPayload::decode(payload.address, &wrong_selector).unwrap_err();
+    }
+
+    #[test]
+    fn rejects_overflowing_offsets_and_lengths() {
+        let payload = random_payload();
+        let calldata = payload.encode().unwrap();
+        let near_max = [
+            usize::MAX,
+            usize::MAX - WORD + 1,
+            usize::MAX - calldata.len(),
+        ];
+
+        for &value in &near_max {
+            // the offset of the first argument
+            let mut overflowing_offset = calldata.clone();
+            overflowing_offset[4..4 + WORD].copy_from_slice(&encode_word(value));
+            match RegistrationPayload::decode(payload.address, &overflowing_offset) {
+                Err(BLSError::InvalidRegistration(_)) => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+
+            // the length of the first argument, which follows the 3 offsets
+            let mut overflowing_length = calldata.clone();
+            let length_offset = 4 + 3 * WORD;
+            overflowing_length[length_offset..length_offset + WORD]
+                .copy_from_slice(&encode_word(value));
+            match RegistrationPayload::decode(payload.address, &overflowing_length) {
+                Err(BLSError::InvalidRegistration(_)) => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Offline backup of private keys as `k`-of-`n` Shamir shares.
+//!
+//! This is meant for cold storage of a validator's BLS key: the key is split into `n` shares
+//! which are stored separately, and any `k` of them restore it. Unlike the threshold
+//! signing of the `beacon` module, the key is reconstructed in full, so restoring must only
+//! happen on a trusted, air-gapped machine.
+//!
+//! Each share is encoded as a [Bech32](https://github.com/bitcoin/bips/blob/master/bip-0173.mediawiki)
+//! string with the `blsshare` prefix, whose checksum detects transcription errors. A share
+//! carries the threshold, its index and a fingerprint of the public key, so that shares of
+//! different keys cannot be mixed up unnoticed.
+use crate::{
+    beacon::lagrange_coefficient_at_zero, bls::zeroize_scalar, BLSError, BlsResult, PrivateKey,
+};
+
+use algebra::{bls12_377::Fr, CanonicalDeserialize, CanonicalSerialize, UniformRand, Zero};
+use rand::Rng;
+use std::{collections::HashSet, fmt};
+use zeroize::Zeroize;
+
+/// The human readable prefix of encoded shares
+pub const SHARE_PREFIX: &str = "blsshare";
+
+/// Length of the public key fingerprint in each share
+pub const KEY_ID_LENGTH: usize = 4;
+
+const SHARE_VERSION: u8 = 0;
+const KEY_ID_PERSONALIZATION: &[u8] = b"ULkeyid_";
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// A share of a private key
+///
+/// Like `PrivateKey`, the share's value is wiped from memory when it is dropped, and it is not
+/// printed by `Debug`.
+#[derive(Clone, PartialEq)]
+pub struct KeyShare {
+    /// The number of shares needed to restore the key
+    pub threshold: u8,
+    /// The index of the share, starting from 1
+    pub index: u8,
+    /// The fingerprint of the key's public key
+    pub key_id: [u8; KEY_ID_LENGTH],
+    value: Fr,
+}
+
+impl Zeroize for KeyShare {
+    fn zeroize(&mut self) {
+        zeroize_scalar(&mut self.value);
+    }
+}
+
+impl Drop for KeyShare {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl fmt::Debug for KeyShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyShare")
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .field("key_id", &self.key_id)
+            .field("value", &format_args!("<redacted>"))
+            .finish()
+    }
+}
+
+/// Splits the private key into `num_shares` shares, any `threshold` of which restore it
+pub fn split<R: Rng>(
+    key: &PrivateKey,
+    threshold: u8,
+    num_shares: u8,
+    rng: &mut R,
+) -> BlsResult<Vec<KeyShare>> {
+    if threshold == 0 || threshold > num_shares {
+        return Err(BLSError::InvalidShare(
+            "threshold must be between 1 and the number of shares",
+        ));
+    }
+    let key_id = key_id(key)?;
+    let mut coefficients = vec![*key.as_ref()];
+    coefficients.extend((1..threshold).map(|_| Fr::rand(rng)));
+
+    let shares = (1..=num_shares)
+        .map(|index| {
+            let x = Fr::from(index as u64);
+            let value = coefficients
+                .iter()
+                .rev()
+                .fold(Fr::zero(), |acc, c| acc * &x + c);
+            KeyShare {
+                threshold,
+                index,
+                key_id,
+                value,
+            }
+        })
+        .collect();
+    // the coefficients determine the key
+    coefficients.iter_mut().for_each(zeroize_scalar);
+    Ok(shares)
+}
+
+/// Restores the private key from at least `threshold` of its shares. Extra shares are
+/// ignored. Fails if the shares belong to different keys or splits, or if the restored key
+/// does not match the shares' fingerprint.
+pub fn restore(shares: &[KeyShare]) -> BlsResult<PrivateKey> {
+    let first = shares.first().ok_or(BLSError::NotEnoughShares(0, 1))?;
+    let mut indices = HashSet::with_capacity(shares.len());
+    for share in shares {
+        if share.threshold != first.threshold || share.key_id != first.key_id {
+            return Err(BLSError::InvalidShare("shares belong to different keys"));
+        }
+        if share.index == 0 || !indices.insert(share.index) {
+            return Err(BLSError::InvalidShareIndex(share.index as u64));
+        }
+    }
+    let threshold = first.threshold as usize;
+    if shares.len() < threshold {
+        return Err(BLSError::NotEnoughShares(shares.len(), threshold));
+    }
+
+    let shares = &shares[..threshold];
+    let indices = shares
+        .iter()
+        .map(|share| Fr::from(share.index as u64))
+        .collect::<Vec<_>>();
+    let key = shares
+        .iter()
+        .enumerate()
+        .map(|(i, share)| share.value * &lagrange_coefficient_at_zero(&indices, i))
+        .fold(Fr::zero(), |acc, term| acc + &term);
+    let key = PrivateKey::from(key);
+
+    if key_id(&key)? != first.key_id {
+        return Err(BLSError::InvalidShare(
+            "restored key does not match the fingerprint",
+        ));
+    }
+    Ok(key)
+}
+
+impl KeyShare {
+    /// Encodes the share as a checksummed Bech32 string
+    pub fn encode(&self) -> BlsResult<String> {
+        let mut bytes = vec![SHARE_VERSION, self.threshold, self.index];
+        bytes.extend_from_slice(&self.key_id);
+        self.value.serialize(&mut bytes)?;
+
+        let mut data = convert_bits(&bytes, 8, 5, true).expect("padding is allowed");
+        bytes.zeroize();
+        let checksum = checksum(SHARE_PREFIX, &data);
+        let mut encoded = format!("{}1", SHARE_PREFIX);
+        encoded.extend(
+            data.iter()
+                .chain(&checksum)
+                .map(|value| CHARSET[*value as usize] as char),
+        );
+        data.zeroize();
+        Ok(encoded)
+    }
+
+    /// Decodes a share from its Bech32 string. Upper case strings are accepted.
+    pub fn decode(encoded: &str) -> BlsResult<Self> {
+        let encoded = if encoded.chars().all(|c| !c.is_ascii_lowercase()) {
+            encoded.to_ascii_lowercase()
+        } else {
+            encoded.to_owned()
+        };
+        let separator = encoded
+            .rfind('1')
+            .ok_or(BLSError::InvalidShare("missing separator"))?;
+        let (prefix, payload) = (&encoded[..separator], &encoded[separator + 1..]);
+        if prefix != SHARE_PREFIX {
+            return Err(BLSError::InvalidShare("unexpected prefix"));
+        }
+        let values = payload
+            .bytes()
+            .map(|c| CHARSET.iter().position(|v| *v == c).map(|v| v as u8))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(BLSError::InvalidShare("invalid character"))?;
+        if values.len() < 6 || polymod(&[&expand_prefix(prefix)[..], &values[..]].concat()) != 1 {
+            return Err(BLSError::InvalidShare("invalid checksum"));
+        }
+
+        let mut bytes = convert_bits(&values[..values.len() - 6], 5, 8, false)
+            .ok_or(BLSError::InvalidShare("invalid padding"))?;
+        if bytes.len() != 3 + KEY_ID_LENGTH + 32 || bytes[0] != SHARE_VERSION {
+            return Err(BLSError::InvalidShare("unsupported share format"));
+        }
+        let mut key_id = [0u8; KEY_ID_LENGTH];
+        key_id.copy_from_slice(&bytes[3..3 + KEY_ID_LENGTH]);
+        let (threshold, index) = (bytes[1], bytes[2]);
+        let value = Fr::deserialize(&mut &bytes[3 + KEY_ID_LENGTH..]);
+        bytes.zeroize();
+
+        Ok(Self {
+            threshold,
+            index,
+            key_id,
+            value: value?,
+        })
+    }
+}
+
+fn key_id(key: &PrivateKey) -> BlsResult<[u8; KEY_ID_LENGTH]> {
+    let mut bytes = vec![];
+    key.to_public().serialize(&mut bytes)?;
+    let hash = blake2s_simd::Params::new()
+        .hash_length(KEY_ID_LENGTH)
+        .personal(KEY_ID_PERSONALIZATION)
+        .hash(&bytes);
+    let mut id = [0u8; KEY_ID_LENGTH];
+    id.copy_from_slice(hash.as_bytes());
+    Ok(id)
+}
+
+// The Bech32 checksum, as specified in BIP-173
+fn polymod(values: &[u8]) -> u32 {
+    const GENERATORS: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    values.iter().fold(1u32, |chk, value| {
+        let top = chk >> 25;
+        let chk = ((chk & 0x01ff_ffff) << 5) ^ *value as u32;
+        GENERATORS
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (top >> i) & 1 == 1)
+            .fold(chk, |chk, (_, generator)| chk ^ generator)
+    })
+}
+
+fn expand_prefix(prefix: &str) -> Vec<u8> {
+    let bytes = prefix.as_bytes();
+    bytes
+        .iter()
+        .map(|b| b >> 5)
+        .chain(std::iter::once(0))
+        .chain(bytes.iter().map(|b| b & 31))
+        .collect()
+}
+
+fn checksum(prefix: &str, data: &[u8]) -> [u8; 6] {
+    let values = [&expand_prefix(prefix)[..], data, &[0; 6]].concat();
+    let polymod = polymod(&values) ^ 1;
+    let mut checksum = [0u8; 6];
+    for (i, value) in checksum.iter_mut().enumerate() {
+        *value = ((polymod >> (5 * (5 - i))) & 31) as u8;
+    }
+    checksum
+}
+
+// Regroups the bits of the input from `from`-bit to `to`-bit values
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut out = vec![];
+    let max = (1 << to) - 1;
+    for value in data {
+        acc = (acc << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            out.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max) != 0 {
+        return None;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{seq::SliceRandom, thread_rng};
+
+    #[test]
+    fn restores_from_any_threshold_subset() {
+        let rng = &mut thread_rng();
+        let key = PrivateKey::generate(rng);
+        let mut shares = split(&key, 3, 5, rng).unwrap();
+        for _ in 0..5 {
+            shares.shuffle(rng);
+            let restored = restore(&shares[..3]).unwrap();
+            assert_eq!(restored.as_ref(), key.as_ref());
+        }
+
+        match restore(&shares[..2]) {
+            Err(BLSError::NotEnoughShares(2, 3)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        split(&key, 4, 3, rng).unwrap_err();
+    }
+
+    #[test]
+    fn rejects_mixed_and_corrupted_shares() {
+        let rng = &mut thread_rng();
+        let shares = split(&PrivateKey::generate(rng), 2, 3, rng).unwrap();
+        let others = split(&PrivateKey::generate(rng), 2, 3, rng).unwrap();
+        restore(&[shares[0].clone(), others[1].clone()]).unwrap_err();
+
+        let mut corrupted = shares[1].clone();
+        corrupted.value += &Fr::from(1u64);
+        restore(&[shares[0].clone(), corrupted]).unwrap_err();
+    }
+
+    #[test]
+    fn zeroizes_and_redacts_shares() {
+        let rng = &mut thread_rng();
+        let mut share = split(&PrivateKey::generate(rng), 2, 3, rng)
+            .unwrap()
+            .remove(0);
+        let debug = format!("{:?}", share);
+        assert!(debug.contains("value: <redacted>"));
+        assert!(!debug.contains(&format!("{:?}", share.value)));
+        share.zeroize();
+        assert!(share.value.is_zero());
+    }
+
+    #[test]
+    fn encoding_roundtrips_and_detects_typos() {
+        let rng = &mut thread_rng();
+        let key = PrivateKey::generate(rng);
+        let shares = split(&key, 2, 3, rng).unwrap();
+        let encoded = shares
+            .iter()
+            .map(|share| share.encode().unwrap())
+            .collect::<Vec<_>>();
+        for (share, encoded) in shares.iter().zip(&encoded) {
+            assert!(encoded.starts_with("blsshare1"));
+            assert_eq!(&KeyShare::decode(encoded).unwrap(), share);
+            assert_eq!(
+                &KeyShare::decode(&encoded.to_ascii_uppercase()).unwrap(),
+                share
+            );
+        }
+
+        // a single substituted character is always detected
+        let mut typo = encoded[0].clone().into_bytes();
+        let last = typo.len() - 10;
+        typo[last] = if typo[last] == b'q' { b'p' } else { b'q' };
+        KeyShare::decode(&String::from_utf8(typo).unwrap()).unwrap_err();
+
+        let decoded = encoded
+            .iter()
+            .skip(1)
+            .map(|e| KeyShare::decode(e).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(restore(&decoded).unwrap().as_ref(), key.as_ref());
+    }
+
+    #[test]
+    fn checksum_matches_bip173_vector() {
+        // "a12uel5l" is a valid Bech32 string with an empty payload
+        let values = [&expand_prefix("a")[..], &checksum("a", &[])].concat();
+        assert_eq!(polymod(&values), 1);
+        let encoded = checksum("a", &[])
+            .iter()
+            .map(|v| CHARSET[*v as usize] as char)
+            .collect::<String>();
+        assert_eq!(encoded, "2uel5l");
+    }
+}
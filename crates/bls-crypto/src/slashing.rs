@@ -0,0 +1,263 @@
+//! Double-signing prevention for consensus signatures, in the spirit of the slashing
+//! protection databases of Eth2 clients.
+//!
+//! A `SlashingProtection` store holds a watermark for each key: the highest height and round
+//! it signed a consensus message at, and the digest of that message. `KeyHandle::
+//! sign_consensus` consults the store before signing, and refuses to sign below the
+//! watermark, or to sign another message at the watermark. `MemorySlashingProtection` keeps
+//! the watermarks for the lifetime of the process, and `FileSlashingProtection` persists them
+//! to a file, so that they survive restarts. Other backends, e.g. a database shared by
+//! several signers, implement the trait.
+use crate::{observer::MESSAGE_DIGEST_LENGTH, BLSError, BlsResult, PublicKey};
+use algebra::CanonicalSerialize;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+/// The position of the last consensus message signed by a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watermark {
+    /// The height of the message
+    pub height: u64,
+    /// The round of the message at its height
+    pub round: u32,
+    /// The digest of the message and its extra data, see `observer::message_digest`
+    pub message_digest: [u8; MESSAGE_DIGEST_LENGTH],
+}
+
+/// A store of the watermarks of signing keys
+pub trait SlashingProtection {
+    /// Returns the watermark of the key, or `None` if it never signed
+    fn watermark(&self, public_key: &PublicKey) -> BlsResult<Option<Watermark>>;
+
+    /// Sets the watermark of the key. The signature is released once this returns, so a
+    /// persistent store must have durably written the watermark.
+    fn record(&mut self, public_key: &PublicKey, watermark: Watermark) -> BlsResult<()>;
+
+    /// Checks that the key may sign at the watermark and records it. Signing the same message
+    /// again at the current watermark is allowed, e.g. when a node restarts before
+    /// broadcasting its signature. Fails with `BLSError::SlashableSignature` otherwise.
+    fn check_and_record(&mut self, public_key: &PublicKey, watermark: Watermark) -> BlsResult<()> {
+        if let Some(previous) = self.watermark(public_key)? {
+            let position = (watermark.height, watermark.round);
+            let previous_position = (previous.height, previous.round);
+            if position == previous_position && watermark.message_digest == previous.message_digest
+            {
+                return Ok(());
+            }
+            if position <= previous_position {
+                return Err(BLSError::SlashableSignature {
+                    height: watermark.height,
+                    round: watermark.round,
+                });
+            }
+        }
+        self.record(public_key, watermark)
+    }
+}
+
+/// Watermarks held in memory, which are lost when the process exits
+#[derive(Clone, Debug, Default)]
+pub struct MemorySlashingProtection {
+    /// The watermarks, keyed by the compressed public keys
+    watermarks: HashMap<Vec<u8>, Watermark>,
+}
+
+impl MemorySlashingProtection {
+    /// Initializes an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SlashingProtection for MemorySlashingProtection {
+    fn watermark(&self, public_key: &PublicKey) -> BlsResult<Option<Watermark>> {
+        Ok(self.watermarks.get(&key_bytes(public_key)?).cloned())
+    }
+
+    fn record(&mut self, public_key: &PublicKey, watermark: Watermark) -> BlsResult<()> {
+        self.watermarks.insert(key_bytes(public_key)?, watermark);
+        Ok(())
+    }
+}
+
+/// Watermarks persisted to a file, with one line per key holding the hex encoded compressed
+/// key, the height, the round and the hex encoded message digest. The file is rewritten to a
+/// temporary file which replaces it on each update, so that a crash never leaves it partially
+/// written.
+#[derive(Clone, Debug)]
+pub struct FileSlashingProtection {
+    path: PathBuf,
+    memory: MemorySlashingProtection,
+}
+
+impl FileSlashingProtection {
+    /// Opens the store at the path, which is created on the first update if it does not
+    /// exist
+    pub fn open<P: AsRef<Path>>(path: P) -> BlsResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut memory = MemorySlashingProtection::new();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, watermark) =
+                parse_line(line).ok_or(BLSError::InvalidEncoding("malformed watermark"))?;
+            memory.watermarks.insert(key, watermark);
+        }
+        Ok(Self { path, memory })
+    }
+
+    fn persist(&self) -> BlsResult<()> {
+        let mut contents = String::new();
+        for (key, watermark) in &self.memory.watermarks {
+            contents.push_str(&format!(
+                "{} {} {} {}\n",
+                hex::encode(key),
+                watermark.height,
+                watermark.round,
+                hex::encode(&watermark.message_digest)
+            ));
+        }
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        // the rename is only durable once the directory entry is on disk
+        #[cfg(unix)]
+        {
+            let parent = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            File::open(parent)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+impl SlashingProtection for FileSlashingProtection {
+    fn watermark(&self, public_key: &PublicKey) -> BlsResult<Option<Watermark>> {
+        self.memory.watermark(public_key)
+    }
+
+    fn record(&mut self, public_key: &PublicKey, watermark: Watermark) -> BlsResult<()> {
+        let previous = self.memory.clone();
+        self.memory.record(public_key, watermark)?;
+        // the watermark only counts as recorded once it is on disk
+        if let Err(err) = self.persist() {
+            self.memory = previous;
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+fn key_bytes(public_key: &PublicKey) -> BlsResult<Vec<u8>> {
+    let mut bytes = vec![];
+    public_key.serialize(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn parse_line(line: &str) -> Option<(Vec<u8>, Watermark)> {
+    let mut fields = line.split_whitespace();
+    let key = hex::decode(fields.next()?).ok()?;
+    let height = fields.next()?.parse().ok()?;
+    let round = fields.next()?.parse().ok()?;
+    let digest = hex::decode(fields.next()?).ok()?;
+    if fields.next().is_some() || digest.len() != MESSAGE_DIGEST_LENGTH {
+        return None;
+    }
+    let mut message_digest = [0u8; MESSAGE_DIGEST_LENGTH];
+    message_digest.copy_from_slice(&digest);
+    Some((
+        key,
+        Watermark {
+            height,
+            round,
+            message_digest,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, KeyHandle, PrivateKey};
+
+    fn sign<S: SlashingProtection>(
+        handle: &mut KeyHandle,
+        protection: &mut S,
+        height: u64,
+        round: u32,
+        message: &[u8],
+    ) -> BlsResult<()> {
+        handle
+            .sign_consensus(height, round, message, &[], &*DIRECT_HASH_TO_G1, protection)
+            .map(|_| ())
+    }
+
+    fn assert_slashable(result: BlsResult<()>) {
+        match result {
+            Err(BLSError::SlashableSignature { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn refuses_conflicting_signatures() {
+        let rng = &mut rand::thread_rng();
+        let mut handle = KeyHandle::new(PrivateKey::generate(rng));
+        let mut other = KeyHandle::new(PrivateKey::generate(rng));
+        let protection = &mut MemorySlashingProtection::new();
+
+        sign(&mut handle, protection, 10, 0, b"block a").unwrap();
+        // the same message can be signed again
+        sign(&mut handle, protection, 10, 0, b"block a").unwrap();
+        assert_slashable(sign(&mut handle, protection, 10, 0, b"block b"));
+        assert_slashable(sign(&mut handle, protection, 9, 3, b"block c"));
+        // a later round or height moves the watermark
+        sign(&mut handle, protection, 10, 1, b"block b").unwrap();
+        sign(&mut handle, protection, 11, 0, b"block d").unwrap();
+        assert_slashable(sign(&mut handle, protection, 10, 2, b"block e"));
+        // each key has its own watermark
+        sign(&mut other, protection, 10, 0, b"block b").unwrap();
+    }
+
+    #[test]
+    fn persists_watermarks() {
+        let path = std::env::temp_dir().join(format!(
+            "bls-slashing-protection-{}-{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let mut handle = KeyHandle::new(PrivateKey::generate(&mut rand::thread_rng()));
+
+        let mut protection = FileSlashingProtection::open(&path).unwrap();
+        sign(&mut handle, &mut protection, 5, 2, b"block a").unwrap();
+        drop(protection);
+
+        let mut protection = FileSlashingProtection::open(&path).unwrap();
+        assert_eq!(
+            protection.watermark(&handle.to_public()).unwrap(),
+            Some(Watermark {
+                height: 5,
+                round: 2,
+                message_digest: crate::observer::message_digest(b"block a", &[]),
+            })
+        );
+        assert_slashable(sign(&mut handle, &mut protection, 5, 2, b"block b"));
+        sign(&mut handle, &mut protection, 6, 0, b"block b").unwrap();
+
+        fs::write(&path, "not a watermark\n").unwrap();
+        FileSlashingProtection::open(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+//! Helpers for generating keys and signatures in tests.
+//!
+//! These operate directly on the curve's scalars and group elements instead of the
+//! `PrivateKey`, `PublicKey` and `Signature` wrappers, so that they can be used with any
+//! `PairingEngine` and with messages which have already been hashed to G1. Randomness is
+//! drawn from the thread-local RNG, so the outputs differ between runs.
+//!
+//! Enabled with the `testing` feature. Downstream crates should use this module instead of
+//! copying these helpers, so that all test suites generate keys and signatures the same way.
+//!
+//! The module also builds BLS12-377 points which are on the curve but outside of the prime
+//! order subgroups, for testing that untrusted keys and signatures are rejected.
+use algebra::{
+    bls12_377::{g1, Fq, Fq2, Fr, G1Affine, G2Affine, G2Projective},
+    curves::models::SWModelParameters,
+    AffineCurve, BitIterator, Field, PairingEngine, ProjectiveCurve, UniformRand, Zero,
+};
+
+/// The RNG used by all helpers in this module
+pub fn rng() -> rand::rngs::ThreadRng {
+    rand::thread_rng()
+}
+
+/// Generates a random secret key and its public key, `secret_key * G2` where `G2` is the
+/// generator of the prime order subgroup
+pub fn keygen<E: PairingEngine>() -> (E::Fr, E::G2Projective) {
+    let rng = &mut rng();
+    let generator = E::G2Projective::prime_subgroup_generator();
+
+    let secret_key = E::Fr::rand(rng);
+    let pubkey = generator.mul(secret_key);
+    (secret_key, pubkey)
+}
+
+/// Generates `num` keypairs with `keygen`, returning the secret keys and the public keys
+/// as two vectors of the same order
+pub fn keygen_mul<E: PairingEngine>(num: usize) -> (Vec<E::Fr>, Vec<E::G2Projective>) {
+    let mut secret_keys = Vec::new();
+    let mut public_keys = Vec::new();
+    for _ in 0..num {
+        let (secret_key, public_key) = keygen::<E>();
+        secret_keys.push(secret_key);
+        public_keys.push(public_key);
+    }
+    (secret_keys, public_keys)
+}
+
+/// Generates `num_batches` sets of `num_per_batch` keypairs with `keygen_mul`, e.g. the
+/// validator sets of consecutive epochs. The `i`th vector of secret keys corresponds to the
+/// `i`th vector of public keys.
+#[allow(clippy::type_complexity)]
+pub fn keygen_batch<E: PairingEngine>(
+    num_batches: usize,
+    num_per_batch: usize,
+) -> (Vec<Vec<E::Fr>>, Vec<Vec<E::G2Projective>>) {
+    let mut secret_keys = Vec::new();
+    let mut public_keys = Vec::new();
+    (0..num_batches).for_each(|_| {
+        let (secret_keys_i, public_keys_i) = keygen_mul::<E>(num_per_batch);
+        secret_keys.push(secret_keys_i);
+        public_keys.push(public_keys_i);
+    });
+    (secret_keys, public_keys)
+}
+
+/// Sums the elements in the provided slice, e.g. to get an aggregate public key or
+/// signature. Returns zero for an empty slice.
+pub fn sum<P: ProjectiveCurve>(elements: &[P]) -> P {
+    elements.iter().fold(P::zero(), |acc, key| acc + key)
+}
+
+/// Signs each message hash with the corresponding set of secret keys, and returns the
+/// aggregate signature of each set. The `i`th set of `secret_keys` signs the `i`th message;
+/// any sets or messages without a counterpart are ignored.
+pub fn sign_batch<E: PairingEngine>(
+    secret_keys: &[Vec<E::Fr>],
+    messages: &[E::G1Projective],
+) -> Vec<E::G1Projective> {
+    secret_keys
+        .iter()
+        .zip(messages)
+        .map(|(secret_keys, message)| {
+            let (_, asig) = sign::<E>(*message, &secret_keys);
+            asig
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Signs a message which has already been hashed to G1 with each of the secret keys.
+/// Returns the individual signatures and their aggregate signature.
+pub fn sign<E: PairingEngine>(
+    message_hash: E::G1Projective,
+    secret_keys: &[E::Fr],
+) -> (Vec<E::G1Projective>, E::G1Projective) {
+    let sigs = secret_keys
+        .iter()
+        .map(|key| message_hash.mul(*key))
+        .collect::<Vec<_>>();
+    let asig = sigs
+        .iter()
+        .fold(E::G1Projective::zero(), |acc, sig| acc + sig);
+    (sigs, asig)
+}
+
+/// The prime factors of the cofactor of BLS12-377's G1 curve, which is
+/// `2^92 * 3 * 7^2 * 13^2 * 499^2`. A point of each of these orders exists on the curve.
+pub const G1_COFACTOR_PRIMES: [u64; 5] = [2, 3, 7, 13, 499];
+
+/// Returns a random point on the G1 curve which is not in the prime order subgroup, i.e. a
+/// point whose cofactor was not cleared
+pub fn g1_non_subgroup_point() -> G1Affine {
+    let rng = &mut rng();
+    loop {
+        if let Some(point) = G1Affine::get_point_from_x(Fq::rand(rng), false) {
+            if !point.is_in_correct_subgroup_assuming_on_curve() {
+                return point;
+            }
+        }
+    }
+}
+
+/// Returns a random non-zero point on the G1 curve whose order divides the cofactor, so that
+/// it has no component in the prime order subgroup
+pub fn g1_torsion_point() -> G1Affine {
+    loop {
+        let point = mul_by_limbs(
+            &g1_non_subgroup_point().into_projective(),
+            Fr::characteristic(),
+        );
+        if !point.is_zero() {
+            return point.into_affine();
+        }
+    }
+}
+
+/// Returns a point on the G1 curve of the provided small prime order
+///
+/// # Panics
+///
+/// If `order` is not one of `G1_COFACTOR_PRIMES`
+pub fn g1_point_of_order(order: u64) -> G1Affine {
+    assert!(
+        G1_COFACTOR_PRIMES.contains(&order),
+        "{} is not a prime factor of the G1 cofactor",
+        order
+    );
+    let cofactor = g1::Parameters::COFACTOR;
+    let cofactor = cofactor[0] as u128 | (cofactor[1] as u128) << 64;
+    let multiple = cofactor / order as u128;
+    let limbs = [multiple as u64, (multiple >> 64) as u64];
+    loop {
+        // a torsion point has order dividing the cofactor, so multiplying it by the
+        // cofactor divided by a prime leaves either zero or a point of that order
+        let point = mul_by_limbs(&g1_torsion_point().into_projective(), &limbs);
+        if !point.is_zero() {
+            return point.into_affine();
+        }
+    }
+}
+
+/// Returns a random point on the G2 twist which is not in the prime order subgroup, i.e. a
+/// point whose cofactor was not cleared
+pub fn g2_non_subgroup_point() -> G2Affine {
+    let rng = &mut rng();
+    loop {
+        if let Some(point) = G2Affine::get_point_from_x(Fq2::rand(rng), false) {
+            if !point.is_in_correct_subgroup_assuming_on_curve() {
+                return point;
+            }
+        }
+    }
+}
+
+/// Returns a random non-zero point on the G2 twist whose order divides the cofactor, so that
+/// it has no component in the prime order subgroup
+pub fn g2_torsion_point() -> G2Affine {
+    loop {
+        let point: G2Projective = mul_by_limbs(
+            &g2_non_subgroup_point().into_projective(),
+            Fr::characteristic(),
+        );
+        if !point.is_zero() {
+            return point.into_affine();
+        }
+    }
+}
+
+// Multiplies the point by the integer with the provided little-endian limbs
+fn mul_by_limbs<P: ProjectiveCurve>(point: &P, limbs: &[u64]) -> P {
+    let mut res = P::zero();
+    for bit in BitIterator::new(limbs) {
+        res.double_in_place();
+        if bit {
+            res += point;
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PublicKey, Signature};
+    use algebra::{CanonicalDeserialize, CanonicalSerialize};
+
+    #[test]
+    fn builds_points_of_small_order() {
+        for order in &G1_COFACTOR_PRIMES {
+            let point = g1_point_of_order(*order).into_projective();
+            assert!(!point.is_zero());
+            assert!(mul_by_limbs(&point, &[*order]).is_zero());
+            assert!(!point
+                .into_affine()
+                .is_in_correct_subgroup_assuming_on_curve());
+        }
+    }
+
+    #[test]
+    fn torsion_points_are_killed_by_the_cofactor() {
+        let point = g1_torsion_point();
+        assert!(point.is_on_curve());
+        assert!(point.scale_by_cofactor().is_zero());
+        assert!(!point.is_zero());
+
+        let point = g2_torsion_point();
+        assert!(point.is_on_curve());
+        assert!(point.scale_by_cofactor().is_zero());
+        assert!(!point.is_in_correct_subgroup_assuming_on_curve());
+    }
+
+    #[test]
+    fn wrong_subgroup_points_are_rejected() {
+        let mut bytes = vec![];
+        g2_torsion_point().serialize(&mut bytes).unwrap();
+        PublicKey::from_bytes(&bytes).unwrap_err();
+
+        let mut bytes = vec![];
+        g1_point_of_order(13).serialize(&mut bytes).unwrap();
+        Signature::deserialize(&mut &bytes[..]).unwrap_err();
+        let mut bytes = vec![];
+        g1_non_subgroup_point().serialize(&mut bytes).unwrap();
+        Signature::deserialize(&mut &bytes[..]).unwrap_err();
+    }
+}
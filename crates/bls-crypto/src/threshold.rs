@@ -0,0 +1,339 @@
+//! `t`-of-`n` threshold signing.
+//!
+//! A validator's private key is shared with a Shamir sharing among `n` signers, along with
+//! Feldman commitments to the coefficients of the sharing polynomial, so that each signer can
+//! check its share and anyone can derive the public key of each share. Each signer signs
+//! with its share, producing a partial signature which can be verified against the public key
+//! of the share. Any `t` valid partial signatures are combined with Lagrange interpolation
+//! into the signature under the validator's key, which verifies with `PublicKey::verify`
+//! like any other signature.
+//!
+//! Unlike with the `sharding` module, the key is never reconstructed. The dealer however
+//! knows the key, so it must discard it once the shares are distributed.
+use crate::{
+    beacon::{lagrange_coefficient_at_zero, LagrangeCache},
+    bls::{BlsScheme, Celo},
+    BLSError, BlsResult, HashToCurve, PrivateKey, PublicKey, Signature,
+};
+
+use algebra::{
+    bls12_377::{Fr, G1Projective, G2Projective},
+    Group, UniformRand, Zero,
+};
+use rand::Rng;
+use std::collections::HashSet;
+
+/// A signer's share of a private key
+#[derive(Clone, Debug)]
+pub struct SecretShare {
+    /// The index of the share, starting from 1
+    pub index: u64,
+    key: PrivateKey,
+}
+
+/// The public key of a signer's share
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublicKeyShare {
+    /// The index of the share, starting from 1
+    pub index: u64,
+    pub public_key: PublicKey,
+}
+
+/// A signature produced with a share of a private key
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialSignature {
+    /// The index of the share which produced the signature
+    pub index: u64,
+    pub signature: Signature,
+}
+
+/// Feldman commitments to the coefficients of a sharing polynomial, i.e. the coefficients
+/// multiplied by the G2 generator. The first commitment is the public key of the shared key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShareCommitments(Vec<G2Projective>);
+
+/// Shares the private key among `num_shares` signers, any `threshold` of which can sign
+/// with it. Returns the commitments to the sharing and the shares at indices
+/// `1..=num_shares`.
+pub fn deal<R: Rng>(
+    key: &PrivateKey,
+    threshold: usize,
+    num_shares: usize,
+    rng: &mut R,
+) -> BlsResult<(ShareCommitments, Vec<SecretShare>)> {
+    if threshold == 0 || threshold > num_shares {
+        return Err(BLSError::InvalidShare(
+            "threshold must be between 1 and the number of shares",
+        ));
+    }
+    let mut coefficients = vec![*key.as_ref()];
+    coefficients.extend((1..threshold).map(|_| Fr::rand(rng)));
+
+    let shares = (1..=num_shares as u64)
+        .map(|index| {
+            let x = Fr::from(index);
+            let value = coefficients
+                .iter()
+                .rev()
+                .fold(Fr::zero(), |acc, c| acc * &x + c);
+            SecretShare {
+                index,
+                key: PrivateKey::from(value),
+            }
+        })
+        .collect();
+    let commitments = coefficients
+        .iter()
+        .map(|c| *PrivateKey::from(*c).to_public().as_ref())
+        .collect();
+    Ok((ShareCommitments(commitments), shares))
+}
+
+impl ShareCommitments {
+    /// The number of shares needed to sign
+    pub fn threshold(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The public key of the shared key
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from(self.0[0])
+    }
+
+    /// Derives the public key of the share at the index, by evaluating the sharing
+    /// polynomial on the commitments
+    pub fn public_key_share(&self, index: u64) -> BlsResult<PublicKeyShare> {
+        if index == 0 {
+            return Err(BLSError::InvalidShareIndex(index));
+        }
+        let x = Fr::from(index);
+        let public_key = self
+            .0
+            .iter()
+            .rev()
+            .fold(G2Projective::zero(), |acc, c| acc.mul(&x) + c);
+        Ok(PublicKeyShare {
+            index,
+            public_key: PublicKey::from(public_key),
+        })
+    }
+
+    /// Checks a share received from the dealer against the commitments
+    pub fn verify_share(&self, share: &SecretShare) -> BlsResult<()> {
+        if self.public_key_share(share.index)? != share.to_public() {
+            return Err(BLSError::InvalidShare(
+                "share does not match the commitments",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl SecretShare {
+    /// Signs the message with the share, with the `Celo` scheme like `PrivateKey::sign`
+    pub fn sign<H: HashToCurve<Output = G1Projective>>(
+        &self,
+        message: &[u8],
+        extra_data: &[u8],
+        hash_to_g1: &H,
+    ) -> BlsResult<PartialSignature> {
+        Ok(PartialSignature {
+            index: self.index,
+            signature: Celo::sign_with_extra_data(&self.key, message, extra_data, hash_to_g1)?,
+        })
+    }
+
+    /// The private key of the share, for the tests of the `beacon` which runs on the shares
+    #[cfg(test)]
+    pub(crate) fn key(&self) -> &PrivateKey {
+        &self.key
+    }
+
+    /// The public key of the share
+    pub fn to_public(&self) -> PublicKeyShare {
+        PublicKeyShare {
+            index: self.index,
+            public_key: self.key.to_public(),
+        }
+    }
+}
+
+impl PartialSignature {
+    /// Verifies the partial signature against the public key of the share which produced it
+    pub fn verify<H: HashToCurve<Output = G1Projective>>(
+        &self,
+        share: &PublicKeyShare,
+        message: &[u8],
+        extra_data: &[u8],
+        hash_to_g1: &H,
+    ) -> BlsResult<()> {
+        if self.index != share.index {
+            return Err(BLSError::InvalidShareIndex(self.index));
+        }
+        Celo::verify_with_extra_data(
+            &share.public_key,
+            message,
+            extra_data,
+            &self.signature,
+            hash_to_g1,
+        )
+    }
+}
+
+/// Combines `threshold` partial signatures into the signature under the shared key. The
+/// partial signatures must have been verified individually beforehand, extra partial
+/// signatures are ignored.
+pub fn combine(threshold: usize, partials: &[PartialSignature]) -> BlsResult<Signature> {
+    let partials = select_partials(threshold, partials)?;
+    let indices = partials
+        .iter()
+        .map(|partial| Fr::from(partial.index))
+        .collect::<Vec<_>>();
+    let signature = partials
+        .iter()
+        .enumerate()
+        .map(|(i, partial)| {
+            partial
+                .signature
+                .as_ref()
+                .mul(&lagrange_coefficient_at_zero(&indices, i))
+        })
+        .sum::<G1Projective>();
+    Ok(signature.into())
+}
+
+/// Same as `combine`, but takes the Lagrange coefficients of the signer set from the cache,
+/// which can be shared with `BeaconOutput::combine_cached` when the beacon runs on the same
+/// shares, so that combining the partial signatures of a signer set which was combined before
+/// only costs `threshold` scalar multiplications.
+pub fn combine_cached(
+    threshold: usize,
+    partials: &[PartialSignature],
+    cache: &mut LagrangeCache,
+) -> BlsResult<Signature> {
+    let mut partials = select_partials(threshold, partials)?.to_vec();
+    partials.sort_by_key(|partial| partial.index);
+    let indices = partials
+        .iter()
+        .map(|partial| partial.index)
+        .collect::<Vec<_>>();
+    let signature = partials
+        .iter()
+        .zip(cache.coefficients(&indices)?)
+        .map(|(partial, coefficient)| partial.signature.as_ref().mul(coefficient))
+        .sum::<G1Projective>();
+    Ok(signature.into())
+}
+
+// Checks the indices of the partial signatures and returns the first `threshold` of them
+fn select_partials(
+    threshold: usize,
+    partials: &[PartialSignature],
+) -> BlsResult<&[PartialSignature]> {
+    let mut indices = HashSet::with_capacity(partials.len());
+    for partial in partials {
+        if partial.index == 0 || !indices.insert(partial.index) {
+            return Err(BLSError::InvalidShareIndex(partial.index));
+        }
+    }
+    if threshold == 0 || partials.len() < threshold {
+        return Err(BLSError::NotEnoughPartials(partials.len(), threshold));
+    }
+    Ok(&partials[..threshold])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1;
+    use rand::{seq::SliceRandom, thread_rng};
+
+    #[test]
+    fn combines_to_a_standard_signature() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let key = PrivateKey::generate(rng);
+        let (commitments, shares) = deal(&key, 3, 5, rng).unwrap();
+        assert_eq!(commitments.threshold(), 3);
+        assert_eq!(commitments.public_key(), key.to_public());
+
+        let message = b"block";
+        let mut partials = shares
+            .iter()
+            .map(|share| {
+                commitments.verify_share(share).unwrap();
+                let public = commitments.public_key_share(share.index).unwrap();
+                assert_eq!(public, share.to_public());
+                let partial = share.sign(message, &[], hasher).unwrap();
+                partial.verify(&public, message, &[], hasher).unwrap();
+                partial
+            })
+            .collect::<Vec<_>>();
+
+        let signature = combine(3, &partials).unwrap();
+        key.to_public()
+            .verify(message, &[], &signature, hasher)
+            .unwrap();
+        assert_eq!(signature, key.sign(message, &[], hasher).unwrap());
+
+        // any subset of signers produces the same signature
+        partials.shuffle(rng);
+        assert_eq!(combine(3, &partials[..3]).unwrap(), signature);
+        let mut cache = LagrangeCache::default();
+        assert_eq!(
+            combine_cached(3, &partials[..3], &mut cache).unwrap(),
+            signature
+        );
+        partials[..3].reverse();
+        assert_eq!(
+            combine_cached(3, &partials[..3], &mut cache).unwrap(),
+            signature
+        );
+        assert_eq!(cache.len(), 1);
+        // but fewer signers than the threshold produce an invalid one
+        let partial = combine(2, &partials[..2]).unwrap();
+        key.to_public()
+            .verify(message, &[], &partial, hasher)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn rejects_invalid_shares() {
+        let rng = &mut thread_rng();
+        let hasher = &*DIRECT_HASH_TO_G1;
+        let key = PrivateKey::generate(rng);
+        let (commitments, mut shares) = deal(&key, 2, 3, rng).unwrap();
+
+        // a share which does not match the commitments
+        shares[0].key = PrivateKey::generate(rng);
+        match commitments.verify_share(&shares[0]) {
+            Err(BLSError::InvalidShare(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // a partial signature checked against another share
+        let partial = shares[1].sign(b"block", &[], hasher).unwrap();
+        let other = commitments.public_key_share(3).unwrap();
+        match partial.verify(&other, b"block", &[], hasher) {
+            Err(BLSError::InvalidShareIndex(2)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let other = PublicKeyShare { index: 2, ..other };
+        partial.verify(&other, b"block", &[], hasher).unwrap_err();
+
+        match combine(2, &[partial.clone()]) {
+            Err(BLSError::NotEnoughPartials(1, 2)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match combine(2, &[partial.clone(), partial]) {
+            Err(BLSError::InvalidShareIndex(2)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        for (threshold, num_shares) in &[(0, 3), (4, 3)] {
+            match deal(&key, *threshold, *num_shares, rng) {
+                Err(BLSError::InvalidShare(_)) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+}
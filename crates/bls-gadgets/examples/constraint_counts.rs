@@ -0,0 +1,212 @@
+//! Prints the number of constraints and variables of each gadget, so that changes in the
+//! cost of the circuits show up as numbers.
+//!
+//! Usage: `cargo run --release --example constraint_counts [num validators] [message length]`
+use algebra::{
+    bls12_377::{Bls12_377, G1Projective, G2Projective, Parameters},
+    bw6_761::Fr as BW6_761Fr,
+    Field, UniformRand,
+};
+use bls_crypto::{hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1, SIG_DOMAIN};
+use bls_gadgets::{BlsVerifyGadget, HashToGroupGadget, QuorumCertificateGadget, YToBitGadget};
+use r1cs_core::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use r1cs_std::{
+    alloc::AllocGadget,
+    bits::uint8::UInt8,
+    bls12_377::{G1Gadget, G2Gadget, PairingGadget as Bls12_377PairingGadget},
+    boolean::Boolean,
+    fields::fp::FpGadget,
+};
+use rand::RngCore;
+use std::{env, marker::PhantomData};
+
+/// Counts the constraints and the variables allocated by a gadget, computing the witness
+struct Counter<F> {
+    num_constraints: usize,
+    num_inputs: usize,
+    num_aux: usize,
+    field: PhantomData<F>,
+}
+
+impl<F> Counter<F> {
+    fn new() -> Self {
+        Self {
+            num_constraints: 0,
+            num_inputs: 0,
+            num_aux: 0,
+            field: PhantomData,
+        }
+    }
+}
+
+impl<F: Field> ConstraintSystem<F> for Counter<F> {
+    type Root = Self;
+
+    fn alloc<FN, A, AR>(&mut self, _: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        f()?;
+        self.num_aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.num_aux - 1)))
+    }
+
+    fn alloc_input<FN, A, AR>(&mut self, _: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        f()?;
+        self.num_inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(self.num_inputs - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, _: LA, _: LB, _: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LB: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LC: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+    {
+        self.num_constraints += 1;
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+
+    fn num_constraints(&self) -> usize {
+        self.num_constraints
+    }
+}
+
+/// Runs the gadget on a fresh counter and prints the counts of what it allocated, excluding
+/// the allocation of its arguments by `setup`
+fn report<F, S, T, G>(name: &str, setup: S, gadget: G)
+where
+    F: Field,
+    S: FnOnce(&mut Counter<F>) -> T,
+    G: FnOnce(&mut Counter<F>, T),
+{
+    let mut cs = Counter::<F>::new();
+    let arguments = setup(&mut cs);
+    let (constraints, inputs, aux) = (cs.num_constraints, cs.num_inputs, cs.num_aux);
+    gadget(&mut cs, arguments);
+    println!(
+        "{:<40} {:>10} constraints {:>10} witness variables {:>6} public inputs",
+        name,
+        cs.num_constraints - constraints,
+        cs.num_aux - aux,
+        cs.num_inputs - inputs,
+    );
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let num_validators: usize = args.next().map_or(100, |arg| arg.parse().expect("NaN"));
+    let message_length: usize = args.next().map_or(64, |arg| arg.parse().expect("NaN"));
+    let rng = &mut rand::thread_rng();
+
+    let g1 = G1Projective::rand(rng);
+    let g2 = G2Projective::rand(rng);
+    let alloc_g1 =
+        |cs: &mut Counter<BW6_761Fr>| G1Gadget::alloc(cs.ns(|| "point"), || Ok(g1)).unwrap();
+    let alloc_g2 =
+        |cs: &mut Counter<BW6_761Fr>| G2Gadget::alloc(cs.ns(|| "point"), || Ok(g2)).unwrap();
+
+    report("YToBit G1", alloc_g1, |cs, point| {
+        YToBitGadget::<Parameters>::y_to_bit_g1(cs.ns(|| "y to bit"), &point).unwrap();
+    });
+    report("YToBit G1 (cheap)", alloc_g1, |cs, point| {
+        YToBitGadget::<Parameters>::y_to_bit_g1_cheap(cs.ns(|| "y to bit"), &point).unwrap();
+    });
+    report("YToBit G2", alloc_g2, |cs, point| {
+        YToBitGadget::<Parameters>::y_to_bit_g2(cs.ns(|| "y to bit"), &point).unwrap();
+    });
+    report("YToBit G2 (cheap)", alloc_g2, |cs, point| {
+        YToBitGadget::<Parameters>::y_to_bit_g2_cheap(cs.ns(|| "y to bit"), &point).unwrap();
+    });
+
+    let mut message = vec![0; message_length];
+    rng.fill_bytes(&mut message);
+    let (_, attempt) = COMPOSITE_HASH_TO_G1
+        .hash_with_attempt(SIG_DOMAIN, &message, &[])
+        .unwrap();
+    let alloc_message = |cs: &mut Counter<BW6_761Fr>| {
+        let counter = UInt8::alloc(cs.ns(|| "counter"), || Ok(attempt as u8)).unwrap();
+        let message = message
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| UInt8::alloc(cs.ns(|| format!("byte {}", i)), || Ok(*byte)).unwrap())
+            .collect::<Vec<_>>();
+        (counter, message)
+    };
+    for &generate_constraints_for_hash in &[false, true] {
+        let name = format!(
+            "hash to group ({} bytes, {} XOF)",
+            message_length,
+            if generate_constraints_for_hash {
+                "with"
+            } else {
+                "without"
+            }
+        );
+        report(&name, alloc_message, |cs, (counter, message)| {
+            HashToGroupGadget::<Parameters>::enforce_hash_to_group(
+                &mut cs.ns(|| "hash to group"),
+                counter,
+                &message,
+                generate_constraints_for_hash,
+            )
+            .unwrap();
+        });
+    }
+
+    let name = format!("BLS verify ({} validators)", num_validators);
+    let setup = |cs: &mut Counter<BW6_761Fr>| {
+        let pub_keys = (0..num_validators)
+            .map(|i| G2Gadget::alloc(cs.ns(|| format!("key {}", i)), || Ok(g2)).unwrap())
+            .collect::<Vec<_>>();
+        let bitmap = (0..num_validators)
+            .map(|i| Boolean::alloc(cs.ns(|| format!("bit {}", i)), || Ok(true)).unwrap())
+            .collect::<Vec<_>>();
+        let message_hash = G1Gadget::alloc(cs.ns(|| "message hash"), || Ok(g1)).unwrap();
+        let certificate = QuorumCertificateGadget {
+            bitmap,
+            message_hash,
+        };
+        let signature = G1Gadget::alloc(cs.ns(|| "signature"), || Ok(g1)).unwrap();
+        let maximum_non_signers = FpGadget::alloc(cs.ns(|| "maximum non signers"), || {
+            Ok(BW6_761Fr::from((num_validators / 3) as u64))
+        })
+        .unwrap();
+        (pub_keys, certificate, signature, maximum_non_signers)
+    };
+    report(
+        &name,
+        setup,
+        |cs, (pub_keys, certificate, signature, maximum_non_signers)| {
+            BlsVerifyGadget::<Bls12_377, BW6_761Fr, Bls12_377PairingGadget>::verify(
+                cs.ns(|| "verify"),
+                &pub_keys,
+                &certificate,
+                &signature,
+                &maximum_non_signers,
+            )
+            .unwrap();
+        },
+    );
+}
@@ -1,4 +1,4 @@
-use crate::utils::is_setup;
+use crate::{utils::is_setup, RangeCheck};
 use algebra::PrimeField;
 use r1cs_core::{ConstraintSystem, LinearCombination, SynthesisError};
 use r1cs_std::{
@@ -7,8 +7,12 @@ use r1cs_std::{
     Assignment,
 };
 
+/// The number of bits `max_occurrences` must fit in
+pub const MAX_OCCURRENCES_BITS: usize = 32;
+
 /// Enforces that there are no more than `max_occurrences` of `value` (0 or 1)
-/// present in the provided bitmap
+/// present in the provided bitmap. `max_occurrences` must be smaller than
+/// `2^MAX_OCCURRENCES_BITS`.
 pub fn enforce_maximum_occurrences_in_bitmap<F: PrimeField, CS: ConstraintSystem<F>>(
     cs: &mut CS,
     bitmap: &[Boolean],
@@ -51,12 +55,14 @@ pub fn enforce_maximum_occurrences_in_bitmap<F: PrimeField, CS: ConstraintSystem
         Ok(F::from(occurrences))
     })?;
 
-    // Enforce `occurences <= max_occurences`
-    occurrences.enforce_cmp(
+    // Enforce `occurences <= max_occurences` by checking that the difference fits in
+    // `MAX_OCCURRENCES_BITS` bits. A negative difference wraps around the modulus and
+    // does not.
+    let difference = max_occurrences.sub(&mut cs.ns(|| "max minus occurrences"), &occurrences)?;
+    RangeCheck::new(
         &mut cs.ns(|| "enforce maximum number of occurrences"),
-        &max_occurrences,
-        std::cmp::Ordering::Less,
-        true,
+        &difference,
+        MAX_OCCURRENCES_BITS,
     )?;
 
     // Enforce that we have correctly counted the number of occurrences
@@ -73,6 +79,7 @@ pub fn enforce_maximum_occurrences_in_bitmap<F: PrimeField, CS: ConstraintSystem
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::test_helpers::assert_tampering_detected;
     use algebra::{
         bls12_377::{Fq, Fr},
         Bls12_377,
@@ -160,6 +167,31 @@ mod tests {
         cs
     }
 
+    #[test]
+    fn detects_tampering() {
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let bitmap = [true, false, true, true, false]
+            .iter()
+            .enumerate()
+            .map(|(i, b)| Boolean::alloc(cs.ns(|| i.to_string()), || Ok(*b)).unwrap())
+            .collect::<Vec<_>>();
+        let max_occurrences =
+            FpGadget::<Fq>::alloc(cs.ns(|| "max occurences"), || Ok(Fq::from(3u64))).unwrap();
+        enforce_maximum_occurrences_in_bitmap(&mut cs, &bitmap, &max_occurrences, false).unwrap();
+
+        assert_tampering_detected(
+            &mut cs,
+            &[
+                "0/boolean",
+                "1/boolean",
+                "2/boolean",
+                "3/boolean",
+                "4/boolean",
+                "num occurrences/alloc",
+            ],
+        );
+    }
+
     mod zeros {
         use super::*;
 
@@ -1,8 +1,8 @@
-use crate::enforce_maximum_occurrences_in_bitmap;
+use crate::{enforce_equal_with_label, enforce_maximum_occurrences_in_bitmap};
 use algebra::{PairingEngine, PrimeField, ProjectiveCurve};
 use r1cs_core::{ConstraintSystem, SynthesisError};
 use r1cs_std::{
-    alloc::AllocGadget, boolean::Boolean, eq::EqGadget, fields::fp::FpGadget, fields::FieldGadget,
+    alloc::AllocGadget, boolean::Boolean, fields::fp::FpGadget, fields::FieldGadget,
     groups::GroupGadget, pairing::PairingGadget, select::CondSelectGadget,
 };
 use std::marker::PhantomData;
@@ -21,14 +21,29 @@ pub struct BlsVerifyGadget<E, F, P> {
     pairing_gadget_type: PhantomData<P>,
 }
 
+/// The allocated counterpart of `bls_crypto::QuorumCertificate`.
+///
+/// The certificate's epoch id and message are not allocated: the message is represented by
+/// its hash, which binds the certificate to its epoch when the message encodes it, as epoch
+/// blocks do. Neither is its aggregate signature, which is passed to the verification
+/// instead: certificates verified in a batch are checked against the sum of their
+/// signatures, which is allocated once for the whole batch.
+#[derive(Clone, Debug)]
+pub struct QuorumCertificateGadget<G1> {
+    /// The validators which signed the message
+    pub bitmap: Vec<Boolean>,
+    /// The hash of the signed message
+    pub message_hash: G1,
+}
+
 impl<E, F, P> BlsVerifyGadget<E, F, P>
 where
     E: PairingEngine,
     F: PrimeField,
     P: PairingGadget<E, F>,
 {
-    /// Enforces verification of a BLS Signature against a list of public keys and a bitmap indicating
-    /// which of these pubkeys signed.
+    /// Enforces verification of a BLS Signature against a list of public keys and the bitmap
+    /// of a quorum certificate indicating which of these pubkeys signed its message.
     ///
     /// A maximum number of non_signers is also provided to
     /// indicate our threshold
@@ -38,28 +53,28 @@ where
     pub fn verify<CS: ConstraintSystem<F>>(
         mut cs: CS,
         pub_keys: &[P::G2Gadget],
-        signed_bitmap: &[Boolean],
-        message_hash: &P::G1Gadget,
+        certificate: &QuorumCertificateGadget<P::G1Gadget>,
         signature: &P::G1Gadget,
         maximum_non_signers: &FpGadget<F>,
     ) -> Result<(), SynthesisError> {
         let span = span!(Level::TRACE, "BlsVerifyGadget_verify");
         let _enter = span.enter();
-        // Get the message hash and the aggregated public key based on the bitmap
-        // and allowed number of non-signers
-        let (message_hash, aggregated_pk) = Self::enforce_bitmap(
+        // Get the aggregated public key based on the bitmap and allowed number of
+        // non-signers
+        let aggregated_pk = Self::enforce_bitmap(
             cs.ns(|| "verify partial"),
             pub_keys,
-            signed_bitmap,
-            message_hash,
+            certificate,
             maximum_non_signers,
         )?;
 
         let prepared_aggregated_pk =
             P::prepare_g2(cs.ns(|| "prepare aggregate pk in epoch"), &aggregated_pk)?;
 
-        let prepared_message_hash =
-            P::prepare_g1(cs.ns(|| "prepare message hash in epoch"), &message_hash)?;
+        let prepared_message_hash = P::prepare_g1(
+            cs.ns(|| "prepare message hash in epoch"),
+            &certificate.message_hash,
+        )?;
 
         // Prepare the signature and get the generator
         let (prepared_signature, prepared_g2_neg_generator) =
@@ -111,13 +126,17 @@ where
         )
     }
 
-    /// Batch verification against prepared messages
+    /// Batch verification against prepared messages. Fails with `Unsatisfiable` if there is
+    /// not one public key per message.
     pub fn batch_verify_prepared<CS: ConstraintSystem<F>>(
         mut cs: CS,
         prepared_aggregated_pub_keys: &[P::G2PreparedGadget],
         prepared_message_hashes: &[P::G1PreparedGadget],
         aggregated_signature: &P::G1Gadget,
     ) -> Result<(), SynthesisError> {
+        if prepared_aggregated_pub_keys.len() != prepared_message_hashes.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
         // Prepare the signature and get the generator
         let (prepared_signature, prepared_g2_neg_generator) =
             Self::prepare_signature_neg_generator(&mut cs, aggregated_signature)?;
@@ -135,6 +154,52 @@ where
         Ok(())
     }
 
+    /// Enforces verification of the sum of the aggregate signatures of several quorum
+    /// certificates of distinct messages, each signed by the pubkeys of its own validator set
+    /// which have a 1 in its bitmap, e.g. the certificates of several epochs. Each bitmap may
+    /// contain no more than `maximum_non_signers` 0s.
+    ///
+    /// This is `verify` for distinct messages: the pubkeys of each certificate are aggregated
+    /// according to its bitmap, and the aggregates are checked with `batch_verify`, like the
+    /// native `QuorumCertificate::batch_verify`. Fails with `Unsatisfiable` if there is not
+    /// one validator set per certificate.
+    ///
+    /// # Panics
+    /// If a bitmap's length differs from the number of pubkeys of its validator set
+    pub fn batch_verify_certificates<CS: ConstraintSystem<F>>(
+        mut cs: CS,
+        pub_keys: &[Vec<P::G2Gadget>],
+        certificates: &[QuorumCertificateGadget<P::G1Gadget>],
+        aggregated_signature: &P::G1Gadget,
+        maximum_non_signers: &FpGadget<F>,
+    ) -> Result<(), SynthesisError> {
+        debug!("batch verifying quorum certificates");
+        if pub_keys.len() != certificates.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let mut aggregated_pub_keys = Vec::with_capacity(pub_keys.len());
+        for (i, (pub_keys, certificate)) in pub_keys.iter().zip(certificates).enumerate() {
+            aggregated_pub_keys.push(Self::enforce_bitmap(
+                cs.ns(|| format!("message {}", i)),
+                pub_keys,
+                certificate,
+                maximum_non_signers,
+            )?);
+        }
+        let message_hashes = certificates
+            .iter()
+            .map(|certificate| certificate.message_hash.clone())
+            .collect::<Vec<_>>();
+
+        Self::batch_verify(
+            cs.ns(|| "batch verify"),
+            &aggregated_pub_keys,
+            &message_hashes,
+            aggregated_signature,
+        )
+    }
+
     /// Returns a gadget which checks that an aggregate pubkey is correctly calculated
     /// by the sum of the pub keys which had a 1 in the bitmap
     ///
@@ -205,24 +270,23 @@ where
         Ok(aggregated_pk)
     }
 
-    /// Enforces that the provided bitmap contains no more than `maximum_non_signers`
-    /// 0s. Also returns a gadget of the prepared message hash and a gadget for the aggregate public key
+    /// Enforces that the bitmap of the certificate contains no more than
+    /// `maximum_non_signers` 0s. Also returns a gadget for the aggregate public key of its
+    /// signers
     ///
     /// # Panics
-    /// If signed_bitmap length != pub_keys length (due to internal call to `enforced_aggregated_pubkeys`)
+    /// If the bitmap's length != pub_keys length (due to internal call to `enforced_aggregated_pubkeys`)
     pub fn enforce_bitmap<CS: ConstraintSystem<F>>(
         mut cs: CS,
         pub_keys: &[P::G2Gadget],
-        signed_bitmap: &[Boolean],
-        message_hash: &P::G1Gadget,
+        certificate: &QuorumCertificateGadget<P::G1Gadget>,
         maximum_non_signers: &FpGadget<F>,
-    ) -> Result<(P::G1Gadget, P::G2Gadget), SynthesisError> {
+    ) -> Result<P::G2Gadget, SynthesisError> {
         trace!("enforcing bitmap");
+        let signed_bitmap = &certificate.bitmap;
         enforce_maximum_occurrences_in_bitmap(&mut cs, signed_bitmap, maximum_non_signers, false)?;
 
-        let aggregated_pk = Self::enforce_aggregated_pubkeys(&mut cs, pub_keys, signed_bitmap)?;
-
-        Ok((message_hash.clone(), aggregated_pk))
+        Self::enforce_aggregated_pubkeys(&mut cs, pub_keys, signed_bitmap)
     }
 
     /// Verifying BLS signatures requires preparing a G1 Signature and
@@ -260,7 +324,12 @@ where
         trace!("enforcing BLS equation");
         let bls_equation = P::product_of_pairings(cs.ns(|| "verify BLS signature"), g1, g2)?;
         let gt_one = &P::GTGadget::one(&mut cs.ns(|| "GT one"))?;
-        bls_equation.enforce_equal(&mut cs.ns(|| "BLS equation is one"), gt_one)?;
+        enforce_equal_with_label(
+            cs.ns(|| "BLS equation is one"),
+            || "product of pairings is one in GT",
+            &bls_equation,
+            gt_one,
+        )?;
         Ok(())
     }
 }
@@ -269,7 +338,7 @@ where
 mod verify_one_message {
     use super::*;
     use crate::utils::test_helpers::alloc_vec;
-    use bls_crypto::test_helpers::*;
+    use bls_crypto::testing::*;
 
     use algebra::{
         bls12_377::{Bls12_377, Fr as Bls12_377Fr, G1Projective, G2Projective},
@@ -313,11 +382,14 @@ mod verify_one_message {
         let max_occurrences =
             &FpGadget::<F>::alloc(cs.ns(|| "num non signers"), || Ok(F::from(num_non_signers)))
                 .unwrap();
+        let certificate = QuorumCertificateGadget {
+            bitmap,
+            message_hash: message_hash_var,
+        };
         BlsVerifyGadget::<E, F, P>::verify(
             cs.ns(|| "verify sig"),
             &pub_keys,
-            &bitmap,
-            &message_hash_var,
+            &certificate,
             &signature_var,
             &max_occurrences,
         )
@@ -367,6 +439,124 @@ mod verify_one_message {
         assert!(cs.is_satisfied());
     }
 
+    // converts the arguments to constraints and checks them against `batch_verify_certificates`
+    fn cs_batch_verify_certificates(
+        message_hashes: &[G1Projective],
+        pub_keys: &[Vec<G2Projective>],
+        signature: G1Projective,
+        bitmaps: &[Vec<bool>],
+        num_non_signers: u64,
+    ) -> TestConstraintSystem<BW6_761Fr> {
+        let mut cs = TestConstraintSystem::<BW6_761Fr>::new();
+        let message_hashes = alloc_vec(&mut cs.ns(|| "messages"), message_hashes);
+        let pub_keys = pub_keys
+            .iter()
+            .enumerate()
+            .map(|(i, pub_keys)| alloc_vec(&mut cs.ns(|| format!("pub_keys_{}", i)), pub_keys))
+            .collect::<Vec<_>>();
+        let certificates = bitmaps
+            .iter()
+            .zip(message_hashes)
+            .map(|(bitmap, message_hash)| QuorumCertificateGadget {
+                bitmap: bitmap.iter().map(|b| Boolean::constant(*b)).collect(),
+                message_hash,
+            })
+            .collect::<Vec<_>>();
+        let signature = G1Gadget::alloc(&mut cs.ns(|| "signature"), || Ok(signature)).unwrap();
+        let max_occurrences = &FpGadget::<BW6_761Fr>::alloc(cs.ns(|| "num non signers"), || {
+            Ok(BW6_761Fr::from(num_non_signers))
+        })
+        .unwrap();
+
+        BlsVerifyGadget::<Bls12_377, BW6_761Fr, Bls12_377PairingGadget>::batch_verify_certificates(
+            cs.ns(|| "verify sig"),
+            &pub_keys,
+            &certificates,
+            &signature,
+            max_occurrences,
+        )
+        .unwrap();
+
+        cs
+    }
+
+    #[test]
+    fn batch_verify_certificates_ok() {
+        let (batch_size, num_keys) = (3, 4);
+        let rng = &mut rng();
+        let messages = (0..batch_size)
+            .map(|_| G1Projective::rand(rng))
+            .collect::<Vec<_>>();
+        let (secret_keys, public_keys) = keygen_batch::<Bls12_377>(batch_size, num_keys);
+        // the last validator of the first set and the first validator of the second set do
+        // not sign
+        let bitmaps = vec![
+            vec![true, true, true, false],
+            vec![false, true, true, true],
+            vec![true; num_keys],
+        ];
+        let signers = secret_keys
+            .iter()
+            .zip(&bitmaps)
+            .map(|(keys, bitmap)| {
+                keys.iter()
+                    .zip(bitmap)
+                    .filter(|(_, signed)| **signed)
+                    .map(|(key, _)| *key)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let asig = sum(&sign_batch::<Bls12_377>(&signers, &messages));
+
+        let cs = cs_batch_verify_certificates(&messages, &public_keys, asig, &bitmaps, 1);
+        assert!(cs.is_satisfied());
+
+        // too many non signers
+        let cs = cs_batch_verify_certificates(&messages, &public_keys, asig, &bitmaps, 0);
+        assert!(!cs.is_satisfied());
+
+        // the bitmap does not match the signers
+        let mut wrong_bitmaps = bitmaps.clone();
+        wrong_bitmaps[0][3] = true;
+        let cs = cs_batch_verify_certificates(&messages, &public_keys, asig, &wrong_bitmaps, 1);
+        assert!(!cs.is_satisfied());
+
+        // the messages are bound to their validator sets
+        let mut swapped = messages.clone();
+        swapped.swap(0, 1);
+        let cs = cs_batch_verify_certificates(&swapped, &public_keys, asig, &bitmaps, 1);
+        assert!(!cs.is_satisfied());
+    }
+
+    #[test]
+    fn batch_verify_certificates_rejects_uneven_inputs() {
+        let mut cs = TestConstraintSystem::<BW6_761Fr>::new();
+        let message = G1Gadget::alloc(&mut cs.ns(|| "message"), || {
+            Ok(G1Projective::prime_subgroup_generator())
+        })
+        .unwrap();
+        let max_occurrences =
+            &FpGadget::<BW6_761Fr>::alloc(cs.ns(|| "num non signers"), || Ok(BW6_761Fr::zero()))
+                .unwrap();
+
+        let certificate = QuorumCertificateGadget {
+            bitmap: vec![],
+            message_hash: message.clone(),
+        };
+        let result =
+            BlsVerifyGadget::<Bls12_377, BW6_761Fr, Bls12_377PairingGadget>::batch_verify_certificates(
+                cs.ns(|| "verify sig"),
+                &[],
+                &[certificate],
+                &message,
+                max_occurrences,
+            );
+        match result {
+            Err(SynthesisError::Unsatisfiable) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
     #[test]
     // Verifies signatures over BLS12_377 with Sw6 field (384 bits).
     fn one_signature_ok() {
@@ -385,7 +575,7 @@ mod verify_one_message {
             0,
         );
         assert!(cs.is_satisfied());
-        assert_eq!(cs.num_constraints(), 21184);
+        assert_eq!(cs.num_constraints(), 16989);
 
         // random sig fails
         let cs = cs_verify::<Bls12_377, BW6_761Fr, Bls12_377PairingGadget>(
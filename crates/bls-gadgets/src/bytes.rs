@@ -0,0 +1,139 @@
+//! Packing of serialized bits into the bytes which are fed to the hash gadgets.
+//!
+//! The serialization gadgets output bits in descending order, while the hashers consume
+//! little-endian bytes, exactly like the native `utils::bits_to_bytes`: the bits are reversed
+//! and every 8 of them form a byte, least significant bit first, with the last byte padded
+//! with zeros. All conversions between the two go through this module, so that the in-circuit
+//! encoding cannot diverge from the native one.
+use crate::enforce_equal_with_label;
+use algebra::Field;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::{boolean::Boolean, uint8::UInt8};
+
+/// Packs the bits, in descending order, to little-endian bytes. This is the in-circuit
+/// equivalent of `utils::bits_to_bytes` and adds no constraints.
+pub fn bits_to_bytes(bits: &[Boolean]) -> Vec<UInt8> {
+    let mut bits = bits.to_vec();
+    bits.reverse();
+    bits.chunks(8)
+        .map(|chunk| {
+            let mut chunk = chunk.to_vec();
+            chunk.resize(8, Boolean::constant(false));
+            UInt8::from_bits_le(&chunk)
+        })
+        .collect()
+}
+
+/// Enforces that the bytes are the packing of the bits, e.g. that a byte string witness is
+/// the serialization of in-circuit points, before the bytes are fed to a hash gadget.
+///
+/// The label must not contain `/`, as it is used as a namespace.
+pub fn enforce_bytes_match_bits<F: Field, CS: ConstraintSystem<F>>(
+    mut cs: CS,
+    label: &str,
+    bits: &[Boolean],
+    bytes: &[UInt8],
+) -> Result<(), SynthesisError> {
+    let packed = bits_to_bytes(bits);
+    if packed.len() != bytes.len() {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    for (i, (packed, byte)) in packed.iter().zip(bytes).enumerate() {
+        enforce_equal_with_label(cs.ns(|| format!("byte {}", i)), || label, packed, byte)?;
+    }
+    Ok(())
+}
+
+/// Allocates the byte string witness and enforces that it is the packing of the bits. The
+/// bytes are left unassigned during the setup.
+pub fn alloc_bytes_matching_bits<F: Field, CS: ConstraintSystem<F>>(
+    mut cs: CS,
+    label: &str,
+    bits: &[Boolean],
+    bytes: Option<&[u8]>,
+) -> Result<Vec<UInt8>, SynthesisError> {
+    let num_bytes = (bits.len() + 7) / 8;
+    let values = match bytes {
+        Some(bytes) if bytes.len() != num_bytes => return Err(SynthesisError::Unsatisfiable),
+        Some(bytes) => bytes.iter().map(|b| Some(*b)).collect::<Vec<_>>(),
+        None => vec![None; num_bytes],
+    };
+    let bytes = UInt8::alloc_vec(cs.ns(|| "bytes"), &values)?;
+    enforce_bytes_match_bits(cs.ns(|| "match bits"), label, bits, &bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{bits_to_bytes as native_bits_to_bytes, bytes_to_bits};
+    use algebra::bls12_377::Fq;
+    use r1cs_std::{alloc::AllocGadget, test_constraint_system::TestConstraintSystem};
+
+    fn alloc_bits(cs: &mut TestConstraintSystem<Fq>, bits: &[bool]) -> Vec<Boolean> {
+        bits.iter()
+            .enumerate()
+            .map(|(i, b)| Boolean::alloc(cs.ns(|| format!("bit {}", i)), || Ok(*b)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn packing_matches_native() {
+        let bytes = [0x01, 0x80, 0x5a, 0xff, 0x07];
+        // also checks a length which is not a multiple of 8
+        for num_bits in &[40, 35] {
+            let bits = bytes_to_bits(&bytes, *num_bits);
+            let mut cs = TestConstraintSystem::<Fq>::new();
+            let bits_var = alloc_bits(&mut cs, &bits);
+            let packed = bits_to_bytes(&bits_var)
+                .iter()
+                .map(|b| b.get_value().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(packed, native_bits_to_bytes(&bits));
+        }
+    }
+
+    #[test]
+    fn enforces_witness_matches_bits() {
+        let bytes = [0x12, 0x34, 0xab];
+        let bits = bytes_to_bits(&bytes, 24);
+
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let bits_var = alloc_bits(&mut cs, &bits);
+        let witness = alloc_bytes_matching_bits(
+            cs.ns(|| "witness"),
+            "serialized",
+            &bits_var,
+            Some(&bytes[..]),
+        )
+        .unwrap();
+        assert_eq!(witness.len(), 3);
+        assert!(cs.is_satisfied());
+
+        // bytes in the wrong order, as if the packing was big-endian
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let bits_var = alloc_bits(&mut cs, &bits);
+        alloc_bytes_matching_bits(
+            cs.ns(|| "witness"),
+            "serialized",
+            &bits_var,
+            Some(&[0xab, 0x34, 0x12][..]),
+        )
+        .unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs
+            .which_is_unsatisfied()
+            .unwrap()
+            .contains("equal: serialized"));
+
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let bits_var = alloc_bits(&mut cs, &bits);
+        alloc_bytes_matching_bits(
+            cs.ns(|| "witness"),
+            "serialized",
+            &bits_var,
+            Some(&bytes[1..]),
+        )
+        .unwrap_err();
+    }
+}
@@ -0,0 +1,224 @@
+//! Differential testing of the hash to G1 gadget against the native hasher.
+//!
+//! Random `(domain, message, extra_data)` triples are hashed with `COMPOSITE_HASH_TO_G1`
+//! and by evaluating `HashToGroupGadget` with constraints for the XOF, and the first triple
+//! for which the results differ (or the constraints are not satisfied) is reported with its
+//! full inputs. This takes a while, so it is only built with the `hash-differential`
+//! feature and should be run before releases:
+//!
+//! ```text
+//! HASH_DIFFERENTIAL_CASES=5000 cargo test --release -p bls-gadgets --features hash-differential differential
+//! ```
+//!
+//! Setting `HASH_DIFFERENTIAL_SEED` reproduces a previous run.
+use crate::HashToGroupGadget;
+use algebra::bls12_377::{Fq, G1Projective, Parameters};
+use bls_crypto::{hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1, SIG_DOMAIN};
+use r1cs_std::{
+    alloc::AllocGadget, groups::GroupGadget, test_constraint_system::TestConstraintSystem,
+    uint8::UInt8,
+};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use std::fmt;
+
+/// The maximum length of the random messages
+pub const MAX_MESSAGE_LENGTH: usize = 128;
+
+/// The maximum length of the random extra data
+pub const MAX_EXTRA_DATA_LENGTH: usize = 32;
+
+/// The inputs of a hash to G1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Case {
+    /// The domain, i.e. the XOF's personalization
+    pub domain: [u8; 8],
+    /// The message
+    pub message: Vec<u8>,
+    /// The extra data, which is hashed before the message
+    pub extra_data: Vec<u8>,
+}
+
+impl Case {
+    /// Samples a case. A quarter of the cases use `SIG_DOMAIN`, the others a random domain.
+    pub fn random<R: Rng>(rng: &mut R) -> Self {
+        let mut domain = [0u8; 8];
+        if rng.gen_range(0, 4) == 0 {
+            domain.copy_from_slice(SIG_DOMAIN);
+        } else {
+            rng.fill(&mut domain);
+        }
+        let message = random_bytes(rng, MAX_MESSAGE_LENGTH);
+        let extra_data = random_bytes(rng, MAX_EXTRA_DATA_LENGTH);
+        Self {
+            domain,
+            message,
+            extra_data,
+        }
+    }
+}
+
+fn random_bytes<R: Rng>(rng: &mut R, max_length: usize) -> Vec<u8> {
+    let length = rng.gen_range(0, max_length + 1);
+    (0..length).map(|_| rng.gen()).collect()
+}
+
+/// How the gadget diverged from the native hasher
+#[derive(Clone, Debug)]
+pub enum Mismatch {
+    /// The native hasher failed, so there is nothing to compare against
+    Native(String),
+    /// The gadget could not be evaluated
+    Synthesis(String),
+    /// The constraints are not satisfied by the witness, with the first unsatisfied one
+    Unsatisfied(String),
+    /// The gadget computed another point
+    Output {
+        native: G1Projective,
+        gadget: Option<G1Projective>,
+    },
+}
+
+/// The first case for which the gadget diverged from the native hasher
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    /// The seed of the run
+    pub seed: u64,
+    /// The index of the case in the run
+    pub index: usize,
+    /// The inputs
+    pub case: Case,
+    /// The native hasher's counter for the case, if it succeeded
+    pub attempt: Option<usize>,
+    /// What differed
+    pub mismatch: Mismatch,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "hash to G1 diverged at case {} of seed {}",
+            self.index, self.seed
+        )?;
+        writeln!(f, "  domain:     {}", hex::encode(&self.case.domain))?;
+        writeln!(f, "  message:    {}", hex::encode(&self.case.message))?;
+        writeln!(f, "  extra data: {}", hex::encode(&self.case.extra_data))?;
+        writeln!(f, "  attempt:    {:?}", self.attempt)?;
+        write!(f, "  mismatch:   {:?}", self.mismatch)
+    }
+}
+
+/// Hashes the case natively and with the gadget. Returns the native hasher's counter and
+/// the mismatch, if any.
+pub fn check_case(case: &Case) -> (Option<usize>, Result<(), Mismatch>) {
+    let (native, attempt) =
+        match COMPOSITE_HASH_TO_G1.hash_with_attempt(&case.domain, &case.message, &case.extra_data)
+        {
+            Ok(res) => res,
+            Err(e) => return (None, Err(Mismatch::Native(e.to_string()))),
+        };
+    let result = evaluate_gadget(case, attempt)
+        .map_err(|e| Mismatch::Synthesis(e.to_string()))
+        .and_then(|(gadget, unsatisfied)| {
+            if let Some(constraint) = unsatisfied {
+                Err(Mismatch::Unsatisfied(constraint))
+            } else if gadget != Some(native) {
+                Err(Mismatch::Output { native, gadget })
+            } else {
+                Ok(())
+            }
+        });
+    (Some(attempt), result)
+}
+
+/// Returns the gadget's output and the first unsatisfied constraint, if any
+fn evaluate_gadget(
+    case: &Case,
+    attempt: usize,
+) -> Result<(Option<G1Projective>, Option<String>), r1cs_core::SynthesisError> {
+    let mut cs = TestConstraintSystem::<Fq>::new();
+    let counter = UInt8::alloc(cs.ns(|| "counter"), || Ok(attempt as u8))?;
+    // the native hasher prepends the extra data to the message
+    let input = case
+        .extra_data
+        .iter()
+        .chain(&case.message)
+        .enumerate()
+        .map(|(i, byte)| UInt8::alloc(cs.ns(|| format!("input {}", i)), || Ok(*byte)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (hash, _, _) = HashToGroupGadget::<Parameters>::enforce_hash_to_group_with_domain(
+        &mut cs.ns(|| "hash to group"),
+        counter,
+        &input,
+        case.domain,
+        true,
+    )?;
+    let unsatisfied = cs.which_is_unsatisfied().map(String::from);
+    Ok((hash.get_value(), unsatisfied))
+}
+
+/// Checks `num_cases` random cases sampled from `seed` and returns the first divergence
+pub fn run(num_cases: usize, seed: u64) -> Result<(), Box<Divergence>> {
+    let rng = &mut XorShiftRng::seed_from_u64(seed);
+    for index in 0..num_cases {
+        let case = Case::random(rng);
+        if let (attempt, Err(mismatch)) = check_case(&case) {
+            return Err(Box::new(Divergence {
+                seed,
+                index,
+                case,
+                attempt,
+                mismatch,
+            }));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+        env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    #[test]
+    fn differential_native_and_gadget() {
+        let num_cases = env_or("HASH_DIFFERENTIAL_CASES", 1000);
+        let seed = env_or("HASH_DIFFERENTIAL_SEED", rand::thread_rng().gen());
+        println!("checking {} cases with seed {}", num_cases, seed);
+        if let Err(divergence) = run(num_cases, seed) {
+            panic!("{}", divergence);
+        }
+    }
+
+    #[test]
+    fn reports_full_inputs() {
+        let case = Case {
+            domain: *b"ULforxof",
+            message: vec![1, 2, 3],
+            extra_data: vec![0xab],
+        };
+        let (attempt, result) = check_case(&case);
+        result.unwrap();
+
+        let divergence = Divergence {
+            seed: 7,
+            index: 3,
+            case,
+            attempt,
+            mismatch: Mismatch::Unsatisfied("hash to group/xof result 0".to_string()),
+        };
+        let report = divergence.to_string();
+        assert!(report.contains("case 3 of seed 7"));
+        assert!(report.contains(&hex::encode(b"ULforxof")));
+        assert!(report.contains("message:    010203"));
+        assert!(report.contains("extra data: ab"));
+    }
+}
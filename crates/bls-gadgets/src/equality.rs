@@ -0,0 +1,105 @@
+use algebra::Field;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::{boolean::Boolean, eq::EqGadget};
+use std::fmt::Display;
+
+/// Enforces that the two gadgets (field elements, points, booleans, etc.) are equal. The
+/// constraints are namespaced under the label, so that `which_is_unsatisfied` reports which
+/// equality failed, e.g. `"... /equal: aggregate pubkey/..."`. The label is only built when
+/// the constraint system records namespaces, so it does not slow down proving.
+///
+/// The label must not contain `/`, as it is used as a namespace.
+pub fn enforce_equal_with_label<F, T, L, S, CS>(
+    mut cs: CS,
+    label: L,
+    a: &T,
+    b: &T,
+) -> Result<(), SynthesisError>
+where
+    F: Field,
+    T: EqGadget<F>,
+    L: FnOnce() -> S,
+    S: Display,
+    CS: ConstraintSystem<F>,
+{
+    a.enforce_equal(cs.ns(|| format!("equal: {}", label())), b)
+}
+
+/// Enforces that the two gadgets are equal if `condition` is true. The constraints are
+/// namespaced under the label, which is built lazily like in `enforce_equal_with_label`.
+///
+/// The label must not contain `/`, as it is used as a namespace.
+pub fn conditional_enforce_equal_with_label<F, T, L, S, CS>(
+    mut cs: CS,
+    label: L,
+    a: &T,
+    b: &T,
+    condition: &Boolean,
+) -> Result<(), SynthesisError>
+where
+    F: Field,
+    T: EqGadget<F>,
+    L: FnOnce() -> S,
+    S: Display,
+    CS: ConstraintSystem<F>,
+{
+    a.conditional_enforce_equal(
+        cs.ns(|| format!("conditionally equal: {}", label())),
+        b,
+        condition,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{
+        bls12_377::{Fq, G1Projective},
+        UniformRand,
+    };
+    use r1cs_std::{
+        alloc::AllocGadget, bls12_377::G1Gadget, fields::fp::FpGadget,
+        test_constraint_system::TestConstraintSystem,
+    };
+
+    #[test]
+    fn labels_unsatisfied_points() {
+        let rng = &mut rand::thread_rng();
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let a = G1Gadget::alloc(cs.ns(|| "a"), || Ok(G1Projective::rand(rng))).unwrap();
+        let b = G1Gadget::alloc(cs.ns(|| "b"), || Ok(G1Projective::rand(rng))).unwrap();
+        enforce_equal_with_label(cs.ns(|| "check"), || "a is b", &a, &a).unwrap();
+        assert!(cs.is_satisfied());
+
+        enforce_equal_with_label(cs.ns(|| "check 2"), || "a is b", &a, &b).unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs
+            .which_is_unsatisfied()
+            .unwrap()
+            .starts_with("check 2/equal: a is b/"));
+    }
+
+    #[test]
+    fn labels_unsatisfied_field_elements() {
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let a = FpGadget::alloc(cs.ns(|| "a"), || Ok(Fq::from(1u64))).unwrap();
+        let b = FpGadget::alloc(cs.ns(|| "b"), || Ok(Fq::from(2u64))).unwrap();
+
+        // not enforced when the condition is false
+        conditional_enforce_equal_with_label(
+            cs.ns(|| "conditional"),
+            || "a is b",
+            &a,
+            &b,
+            &Boolean::constant(false),
+        )
+        .unwrap();
+        assert!(cs.is_satisfied());
+
+        enforce_equal_with_label(cs.ns(|| "check"), || "a is b", &a, &b).unwrap();
+        assert!(cs
+            .which_is_unsatisfied()
+            .unwrap()
+            .starts_with("check/equal: a is b/"));
+    }
+}
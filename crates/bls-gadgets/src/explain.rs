@@ -0,0 +1,368 @@
+//! A constraint system for tests which explains why it is not satisfied.
+//!
+//! `TestConstraintSystem::which_is_unsatisfied` only returns the path of the first failing
+//! constraint. `ExplainingConstraintSystem` has the same interface, and its
+//! `explain_unsatisfied` also returns the values of the constraint's linear combinations and
+//! the path and value of each of their variables, so that a failing gadget test shows which
+//! witness is wrong without adding debug prints to the gadget.
+use algebra::Field;
+use r1cs_core::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// An enforced constraint `a * b = c`, with the path of its namespace
+struct Constraint<F: Field> {
+    a: LinearCombination<F>,
+    b: LinearCombination<F>,
+    c: LinearCombination<F>,
+    path: String,
+}
+
+/// A term of a linear combination, with the path and the value of its variable
+#[derive(Clone, Debug, PartialEq)]
+pub struct Term<F> {
+    pub coefficient: F,
+    pub variable: String,
+    pub value: F,
+}
+
+/// A constraint `a * b = c` which is not satisfied by the assignment
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnsatisfiedConstraint<F> {
+    /// The path of the constraint, as returned by `which_is_unsatisfied`
+    pub path: String,
+    /// The values of the linear combinations `a`, `b` and `c`
+    pub values: [F; 3],
+    /// The terms of the linear combinations `a`, `b` and `c`
+    pub terms: [Vec<Term<F>>; 3],
+}
+
+impl<F: Field> fmt::Display for UnsatisfiedConstraint<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "constraint {} is not satisfied: a * b != c", self.path)?;
+        for ((name, value), terms) in ["a", "b", "c"].iter().zip(&self.values).zip(&self.terms) {
+            writeln!(f, "  {} = {}", name, value)?;
+            for term in terms {
+                if term.coefficient.is_one() {
+                    writeln!(f, "    {} = {}", term.variable, term.value)?;
+                } else {
+                    writeln!(
+                        f,
+                        "    {} * {} = {} * {}",
+                        term.coefficient, term.variable, term.coefficient, term.value
+                    )?;
+                }
+            }
+        }
+        write!(f, "  a * b = {}", self.values[0] * &self.values[1])
+    }
+}
+
+/// A constraint system which computes the witness, checks it, and explains the first
+/// constraint which it does not satisfy. Like `TestConstraintSystem`, variables and
+/// constraints are named after the path of their namespace, and the paths must be unique.
+pub struct ExplainingConstraintSystem<F: Field> {
+    namespace: Vec<String>,
+    inputs: Vec<(F, String)>,
+    aux: Vec<(F, String)>,
+    constraints: Vec<Constraint<F>>,
+    named_variables: HashMap<String, Index>,
+    paths: HashSet<String>,
+}
+
+impl<F: Field> Default for ExplainingConstraintSystem<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: Field> ExplainingConstraintSystem<F> {
+    /// Creates a constraint system with the input variable `ONE`
+    pub fn new() -> Self {
+        let mut named_variables = HashMap::new();
+        named_variables.insert("ONE".to_owned(), Index::Input(0));
+        Self {
+            namespace: vec![],
+            inputs: vec![(F::one(), "ONE".to_owned())],
+            aux: vec![],
+            constraints: vec![],
+            named_variables,
+            paths: std::iter::once("ONE".to_owned()).collect(),
+        }
+    }
+
+    /// Returns true if the assignment satisfies every constraint
+    pub fn is_satisfied(&self) -> bool {
+        self.which_is_unsatisfied().is_none()
+    }
+
+    /// Returns the path of the first constraint which the assignment does not satisfy
+    pub fn which_is_unsatisfied(&self) -> Option<&str> {
+        self.constraints
+            .iter()
+            .find(|constraint| !self.satisfies(constraint))
+            .map(|constraint| constraint.path.as_str())
+    }
+
+    /// Returns the first constraint which the assignment does not satisfy, with the values
+    /// of its linear combinations and variables
+    pub fn explain_unsatisfied(&self) -> Option<UnsatisfiedConstraint<F>> {
+        let constraint = self
+            .constraints
+            .iter()
+            .find(|constraint| !self.satisfies(constraint))?;
+        let lcs = [&constraint.a, &constraint.b, &constraint.c];
+        let terms = |lc: &LinearCombination<F>| -> Vec<Term<F>> {
+            lc.as_ref()
+                .iter()
+                .map(|(variable, coefficient)| {
+                    let (value, name) = self.variable(variable.get_unchecked());
+                    Term {
+                        coefficient: *coefficient,
+                        variable: name.to_owned(),
+                        value: *value,
+                    }
+                })
+                .collect()
+        };
+        Some(UnsatisfiedConstraint {
+            path: constraint.path.clone(),
+            values: [self.eval(lcs[0]), self.eval(lcs[1]), self.eval(lcs[2])],
+            terms: [terms(lcs[0]), terms(lcs[1]), terms(lcs[2])],
+        })
+    }
+
+    /// Panics with the explanation of the first unsatisfied constraint, if any
+    pub fn assert_satisfied(&self) {
+        if let Some(explanation) = self.explain_unsatisfied() {
+            panic!("{}", explanation);
+        }
+    }
+
+    /// Returns the value of the variable at the path
+    ///
+    /// # Panics
+    ///
+    /// If there is no variable at the path
+    pub fn get(&self, path: &str) -> F {
+        self.variable(self.index(path)).0
+    }
+
+    /// Sets the value of the variable at the path, e.g. to check that tampering with it is
+    /// detected
+    ///
+    /// # Panics
+    ///
+    /// If there is no variable at the path
+    pub fn set(&mut self, path: &str, value: F) {
+        match self.index(path) {
+            Index::Input(i) => self.inputs[i].0 = value,
+            Index::Aux(i) => self.aux[i].0 = value,
+        }
+    }
+
+    /// The number of public inputs, including `ONE`
+    pub fn num_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    fn index(&self, path: &str) -> Index {
+        *self
+            .named_variables
+            .get(path)
+            .unwrap_or_else(|| panic!("no variable exists at path {}", path))
+    }
+
+    fn variable(&self, index: Index) -> &(F, String) {
+        match index {
+            Index::Input(i) => &self.inputs[i],
+            Index::Aux(i) => &self.aux[i],
+        }
+    }
+
+    fn eval(&self, lc: &LinearCombination<F>) -> F {
+        lc.as_ref()
+            .iter()
+            .fold(F::zero(), |sum, (variable, coefficient)| {
+                sum + &(self.variable(variable.get_unchecked()).0 * coefficient)
+            })
+    }
+
+    fn satisfies(&self, constraint: &Constraint<F>) -> bool {
+        self.eval(&constraint.a) * &self.eval(&constraint.b) == self.eval(&constraint.c)
+    }
+
+    /// Returns the path of the name in the current namespace, which must not be used yet
+    fn path(&mut self, name: String) -> String {
+        let path = self
+            .namespace
+            .iter()
+            .chain(std::iter::once(&name))
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("/");
+        assert!(
+            self.paths.insert(path.clone()),
+            "path {} already exists",
+            path
+        );
+        path
+    }
+}
+
+impl<F: Field> ConstraintSystem<F> for ExplainingConstraintSystem<F> {
+    type Root = Self;
+
+    fn alloc<FN, A, AR>(&mut self, annotation: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let path = self.path(annotation().into());
+        let index = Index::Aux(self.aux.len());
+        self.aux.push((f()?, path.clone()));
+        self.named_variables.insert(path, index);
+        Ok(Variable::new_unchecked(index))
+    }
+
+    fn alloc_input<FN, A, AR>(&mut self, annotation: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let path = self.path(annotation().into());
+        let index = Index::Input(self.inputs.len());
+        self.inputs.push((f()?, path.clone()));
+        self.named_variables.insert(path, index);
+        Ok(Variable::new_unchecked(index))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LB: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LC: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+    {
+        let path = self.path(annotation().into());
+        self.constraints.push(Constraint {
+            a: a(LinearCombination::zero()),
+            b: b(LinearCombination::zero()),
+            c: c(LinearCombination::zero()),
+            path,
+        });
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.namespace.push(name_fn().into());
+    }
+
+    fn pop_namespace(&mut self) {
+        assert!(self.namespace.pop().is_some());
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+
+    fn num_constraints(&self) -> usize {
+        self.constraints.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::bls12_377::Fq;
+    use r1cs_std::test_constraint_system::TestConstraintSystem;
+
+    /// Enforces `a * b = c`, and `a + b = 5` as another constraint
+    fn product<CS: ConstraintSystem<Fq>>(cs: &mut CS, a: u64, b: u64, c: u64) {
+        let a = cs.alloc(|| "a", || Ok(Fq::from(a))).unwrap();
+        let b = cs.alloc(|| "b", || Ok(Fq::from(b))).unwrap();
+        let c = cs.alloc(|| "c", || Ok(Fq::from(c))).unwrap();
+        cs.enforce(
+            || "sum",
+            |lc| lc + a + b,
+            |lc| lc + CS::one(),
+            |lc| lc + (Fq::from(5u64), CS::one()),
+        );
+        cs.enforce(|| "product", |lc| lc + a, |lc| lc + b, |lc| lc + c);
+    }
+
+    #[test]
+    fn matches_test_constraint_system() {
+        for &c in &[6, 7] {
+            let mut explaining = ExplainingConstraintSystem::<Fq>::new();
+            let mut test = TestConstraintSystem::<Fq>::new();
+            product(&mut explaining.ns(|| "gadget"), 2, 3, c);
+            product(&mut test.ns(|| "gadget"), 2, 3, c);
+
+            assert_eq!(explaining.num_constraints(), test.num_constraints());
+            assert_eq!(explaining.is_satisfied(), test.is_satisfied());
+            assert_eq!(
+                explaining.which_is_unsatisfied(),
+                test.which_is_unsatisfied()
+            );
+            assert_eq!(explaining.get("gadget/c"), test.get("gadget/c"));
+        }
+    }
+
+    #[test]
+    fn explains_the_failing_constraint() {
+        let mut cs = ExplainingConstraintSystem::<Fq>::new();
+        product(&mut cs.ns(|| "gadget"), 2, 3, 6);
+        cs.assert_satisfied();
+        assert!(cs.explain_unsatisfied().is_none());
+
+        cs.set("gadget/c", Fq::from(7u64));
+        let explanation = cs.explain_unsatisfied().unwrap();
+        assert_eq!(explanation.path, "gadget/product");
+        assert_eq!(
+            explanation.values,
+            [Fq::from(2u64), Fq::from(3u64), Fq::from(7u64)]
+        );
+        let term = |variable: &str, value: u64| Term {
+            coefficient: Fq::from(1u64),
+            variable: variable.to_owned(),
+            value: Fq::from(value),
+        };
+        assert_eq!(
+            explanation.terms,
+            [
+                vec![term("gadget/a", 2)],
+                vec![term("gadget/b", 3)],
+                vec![term("gadget/c", 7)],
+            ]
+        );
+
+        let message = explanation.to_string();
+        assert!(message.contains("gadget/product"), "{}", message);
+        assert!(message.contains("gadget/c"), "{}", message);
+    }
+
+    #[test]
+    #[should_panic(expected = "gadget/product is not satisfied")]
+    fn panics_with_the_explanation() {
+        let mut cs = ExplainingConstraintSystem::<Fq>::new();
+        product(&mut cs.ns(|| "gadget"), 2, 3, 7);
+        cs.assert_satisfied();
+    }
+
+    #[test]
+    #[should_panic(expected = "already exists")]
+    fn paths_are_unique() {
+        let mut cs = ExplainingConstraintSystem::<Fq>::new();
+        cs.alloc(|| "x", || Ok(Fq::from(1u64))).unwrap();
+        cs.alloc(|| "x", || Ok(Fq::from(1u64))).unwrap();
+    }
+}
@@ -3,11 +3,12 @@ use crate::{
     YToBitGadget,
 };
 use bls_crypto::{
+    hash_to_curve::try_and_increment::TryAndIncrement,
     hashers::{
         composite::{CompositeHasher, CRH},
         DirectHasher, Hasher,
     },
-    SIG_DOMAIN,
+    PointCompression, SIG_DOMAIN,
 };
 
 // Imported for the BLS12-377 API
@@ -17,10 +18,8 @@ use algebra::{
 };
 use algebra::{
     curves::{
-        bls12::G1Projective,
-        models::bls12::Bls12Parameters,
-        short_weierstrass_jacobian::{GroupAffine, GroupProjective},
-        SWModelParameters,
+        bls12::G1Projective, models::bls12::Bls12Parameters,
+        short_weierstrass_jacobian::GroupProjective, SWModelParameters,
     },
     AffineCurve, BigInteger, BitIterator, One, PrimeField, ProjectiveCurve,
 };
@@ -60,7 +59,7 @@ const X_BITS: usize = 377;
 /// • “Node depth” is set to 0 (leaves)
 /// • “Inner hash byte length” is set to 32 for BLAKE2Xs and 64 for BLAKE2Xb
 fn blake2xs_params(
-    hash_length: u16,
+    xof_digest_length: u16,
     offset: u32,
     personalization: [u8; 8],
 ) -> Blake2sWithParameterBlock {
@@ -71,7 +70,7 @@ fn blake2xs_params(
         depth: 0,
         leaf_length: 32,
         node_offset: offset,
-        xof_digest_length: hash_length / 8, // need to convert to bytes
+        xof_digest_length,
         node_depth: 0,
         inner_length: 32,
         salt: [0; 8],
@@ -110,6 +109,27 @@ impl HashToGroupGadget<Bls12_377_Parameters> {
         counter: UInt8,
         message: &[UInt8],
         generate_constraints_for_hash: bool,
+    ) -> Result<(G1Gadget<Bls12_377_Parameters>, Vec<Boolean>, Vec<Boolean>), SynthesisError> {
+        let mut domain = [0; 8];
+        domain.copy_from_slice(SIG_DOMAIN);
+        Self::enforce_hash_to_group_with_domain(
+            cs,
+            counter,
+            message,
+            domain,
+            generate_constraints_for_hash,
+        )
+    }
+
+    /// Same as `enforce_hash_to_group`, but personalizes the XOF with `domain` instead of
+    /// `SIG_DOMAIN`. The native hash's `extra_data` corresponds to a prefix of the message.
+    #[allow(clippy::type_complexity)]
+    pub fn enforce_hash_to_group_with_domain<CS: ConstraintSystem<Bls12_377_Fq>>(
+        cs: &mut CS,
+        counter: UInt8,
+        message: &[UInt8],
+        domain: [u8; 8],
+        generate_constraints_for_hash: bool,
     ) -> Result<(G1Gadget<Bls12_377_Parameters>, Vec<Boolean>, Vec<Boolean>), SynthesisError> {
         let span = span!(Level::TRACE, "enforce_hash_to_group",);
         let _enter = span.enter();
@@ -121,15 +141,13 @@ impl HashToGroupGadget<Bls12_377_Parameters> {
         let crh_bits = Self::pedersen_hash(cs, &input)?;
 
         // Hash to bits
-        let mut personalization = [0; 8];
-        personalization.copy_from_slice(SIG_DOMAIN);
         // We want 378 random bits for hashing to curve, so we get 512 from the hash and will
         // discard any unneeded ones. We do not generate constraints.
         let xof_bits = hash_to_bits(
             cs.ns(|| "hash to bits"),
             &crh_bits,
             512,
-            personalization,
+            domain,
             generate_constraints_for_hash,
         )?;
 
@@ -139,8 +157,49 @@ impl HashToGroupGadget<Bls12_377_Parameters> {
         Ok((hash, crh_bits, xof_bits))
     }
 
+    /// Returns the G1 constrained hash of the message at the given attempt, in
+    /// `HashMode::Seek` of the [non-gadget implementation][hash_to_group].
+    ///
+    /// The message is compressed without a counter, and the attempt only selects the window
+    /// of the XOF output which is decoded. The attempt is a constant of the circuit, so this
+    /// suits verifying a hash whose successful attempt is known, e.g. transmitted along with
+    /// the message. As with `enforce_hash_to_group`, the earlier attempts are not checked.
+    ///
+    /// [hash_to_group]: ../bls_crypto/hash_to_curve/try_and_increment/index.html
+    #[allow(clippy::type_complexity)]
+    pub fn enforce_hash_to_group_at_attempt<CS: ConstraintSystem<Bls12_377_Fq>>(
+        cs: &mut CS,
+        attempt: u8,
+        message: &[UInt8],
+        domain: [u8; 8],
+        generate_constraints_for_hash: bool,
+    ) -> Result<(G1Gadget<Bls12_377_Parameters>, Vec<Boolean>, Vec<Boolean>), SynthesisError> {
+        let span = span!(Level::TRACE, "enforce_hash_to_group_at_attempt", attempt);
+        let _enter = span.enter();
+
+        let crh_bits = Self::pedersen_hash(cs, message)?;
+
+        type G1Parameters = <Bls12_377_Parameters as Bls12Parameters>::G1Parameters;
+        let attempt_length = TryAndIncrement::<DirectHasher, G1Parameters>::attempt_length();
+        let xof_digest_length = TryAndIncrement::<DirectHasher, G1Parameters>::seek_xof_length();
+        let xof_bits = hash_to_bits_at(
+            cs.ns(|| "hash to bits"),
+            &crh_bits,
+            (attempt_length * 8) as u16,
+            xof_digest_length as u16,
+            attempt as usize * attempt_length,
+            domain,
+            generate_constraints_for_hash,
+        )?;
+
+        let hash = Self::hash_to_group(cs.ns(|| "hash to group"), &xof_bits)?;
+
+        debug!("message has been hashed to G1 at attempt {}", attempt);
+        Ok((hash, crh_bits, xof_bits))
+    }
+
     /// Compress the input by passing it through a Pedersen hash
-    fn pedersen_hash<CS: ConstraintSystem<Bls12_377_Fq>>(
+    pub(crate) fn pedersen_hash<CS: ConstraintSystem<Bls12_377_Fq>>(
         cs: &mut CS,
         input: &[UInt8],
     ) -> Result<Vec<Boolean>, SynthesisError> {
@@ -183,9 +242,37 @@ impl HashToGroupGadget<Bls12_377_Parameters> {
 ///
 /// If the provided hash_length is not a multiple of 256.
 pub fn hash_to_bits<F: PrimeField, CS: ConstraintSystem<F>>(
+    cs: CS,
+    message: &[Boolean],
+    hash_length: u16,
+    personalization: [u8; 8],
+    generate_constraints_for_hash: bool,
+) -> Result<Vec<Boolean>, SynthesisError> {
+    hash_to_bits_at(
+        cs,
+        message,
+        hash_length,
+        hash_length / 8,
+        0,
+        personalization,
+        generate_constraints_for_hash,
+    )
+}
+
+/// Same as `hash_to_bits`, but returns the `hash_length` bits at byte `offset` of an XOF
+/// output of `xof_digest_length` bytes, i.e. the bits of `Hasher::xof_at`. Only the Blake2s
+/// blocks of the requested bits are computed.
+///
+/// # Panics
+///
+/// If the requested bits are past the end of the XOF output, or with constraints, if the
+/// provided hash_length is not a multiple of 256 or the offset is not a multiple of 32.
+pub fn hash_to_bits_at<F: PrimeField, CS: ConstraintSystem<F>>(
     mut cs: CS,
     message: &[Boolean],
     hash_length: u16,
+    xof_digest_length: u16,
+    offset: usize,
     personalization: [u8; 8],
     generate_constraints_for_hash: bool,
 ) -> Result<Vec<Boolean>, SynthesisError> {
@@ -193,25 +280,32 @@ pub fn hash_to_bits<F: PrimeField, CS: ConstraintSystem<F>>(
         Level::TRACE,
         "hash_to_bits",
         hash_length,
+        offset,
         generate_constraints_for_hash
     );
     let _enter = span.enter();
+    assert!(
+        offset + hash_length as usize / 8 <= xof_digest_length as usize,
+        "xof offset out of range"
+    );
     let xof_bits = if generate_constraints_for_hash {
         trace!("generating hash with constraints");
         // Reverse the message to LE
         let mut message = message.to_vec();
         message.reverse();
         // Blake2s outputs 256 bit hashes so the desired output hash length
-        // must be a multiple of that.
+        // and the offset must be a multiple of that.
         assert_eq!(hash_length % 256, 0, "invalid hash length size");
-        let iterations = hash_length / 256;
+        assert_eq!(offset % 32, 0, "invalid xof offset");
+        let first = (offset / 32) as u32;
+        let iterations = u32::from(hash_length / 256);
         let mut xof_bits = Vec::new();
         // Run Blake on the message N times, each time offset by `i`
         // to get a `hash_length` hash. The hash is in LE.
-        for i in 0..iterations {
+        for i in first..first + iterations {
             trace!(blake_iteration = i);
             // calculate the hash (Vec<Boolean>)
-            let blake2s_parameters = blake2xs_params(hash_length, i.into(), personalization);
+            let blake2s_parameters = blake2xs_params(xof_digest_length, i, personalization);
             let xof_result = blake2s_gadget_with_parameters(
                 cs.ns(|| format!("xof result {}", i)),
                 &message,
@@ -229,15 +323,23 @@ pub fn hash_to_bits<F: PrimeField, CS: ConstraintSystem<F>>(
     } else {
         trace!("generating hash without constraints");
         let bits = if is_setup(&message) {
-            vec![false; 512]
+            vec![false; hash_length as usize]
         } else {
             let message = message
                 .iter()
                 .map(|m| m.get_value().get())
                 .collect::<Result<Vec<_>, _>>()?;
             let message = bits_to_bytes(&message);
-            let hash_result = DirectHasher.xof(&personalization, &message, 64).unwrap();
-            let mut bits = bytes_to_bits(&hash_result, 512);
+            let hash_result = DirectHasher
+                .xof_at(
+                    &personalization,
+                    &message,
+                    xof_digest_length as usize,
+                    offset,
+                    hash_length as usize / 8,
+                )
+                .unwrap();
+            let mut bits = bytes_to_bits(&hash_result, hash_length as usize);
             bits.reverse();
             bits
         };
@@ -287,7 +389,7 @@ impl<P: Bls12Parameters> HashToGroupGadget<P> {
 
                 // Converts the point read from the xof bits to a G1 element
                 // with point decompression
-                let p = GroupAffine::<P::G1Parameters>::get_point_from_x(x, greatest)
+                let p = PointCompression::point_from_x::<P::G1Parameters>(x, greatest)
                     .ok_or(SynthesisError::AssignmentMissing)?;
 
                 Ok(p.into_projective())
@@ -336,7 +438,7 @@ impl<P: Bls12Parameters> HashToGroupGadget<P> {
         Ok(scaled_point)
     }
 
-    fn scale_by_cofactor_g1<CS: r1cs_core::ConstraintSystem<P::Fp>>(
+    pub(crate) fn scale_by_cofactor_g1<CS: r1cs_core::ConstraintSystem<P::Fp>>(
         mut cs: CS,
         p: &G1Gadget<P>,
     ) -> Result<G1Gadget<P>, SynthesisError>
@@ -366,11 +468,12 @@ impl<P: Bls12Parameters> HashToGroupGadget<P> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::utils::test_helpers::assert_tampering_detected;
 
     use algebra::bls12_377;
     use r1cs_std::{groups::GroupGadget, test_constraint_system::TestConstraintSystem};
 
-    use bls_crypto::hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1;
+    use bls_crypto::hash_to_curve::try_and_increment::{HashMode, COMPOSITE_HASH_TO_G1};
     use r1cs_std::bits::uint8::UInt8;
     use rand::{thread_rng, RngCore};
 
@@ -417,4 +520,97 @@ mod test {
         assert!(cs.is_satisfied());
         assert_eq!(expected_hash, hash.get_value().unwrap());
     }
+
+    #[test]
+    fn hash_to_group_at_attempt() {
+        let try_and_increment = COMPOSITE_HASH_TO_G1.clone().with_mode(HashMode::Seek);
+        let mut rng = thread_rng();
+        let mut input = vec![0; 50];
+        rng.fill_bytes(&mut input);
+        let (expected_hash, attempt) = try_and_increment
+            .hash_with_attempt(SIG_DOMAIN, &input, &[])
+            .unwrap();
+
+        let mut cs = TestConstraintSystem::<bls12_377::Fq>::new();
+        let input = input
+            .iter()
+            .enumerate()
+            .map(|(i, num)| {
+                UInt8::alloc(&mut cs.ns(|| format!("input {}", i)), || Ok(num)).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut domain = [0; 8];
+        domain.copy_from_slice(SIG_DOMAIN);
+
+        let hash = HashToGroupGadget::<bls12_377::Parameters>::enforce_hash_to_group_at_attempt(
+            &mut cs.ns(|| "hash to group"),
+            attempt as u8,
+            &input,
+            domain,
+            false,
+        )
+        .unwrap()
+        .0;
+
+        assert!(cs.is_satisfied());
+        assert_eq!(expected_hash, hash.get_value().unwrap());
+    }
+
+    #[test]
+    fn hash_to_bits_at_matches_native() {
+        let message = bytes_to_bits(&[1, 2, 3, 4], 32);
+        let mut personalization = [0; 8];
+        personalization.copy_from_slice(SIG_DOMAIN);
+
+        let mut cs = TestConstraintSystem::<bls12_377::Fq>::new();
+        let message = constrain_bool(&mut cs.ns(|| "message"), &message).unwrap();
+        let constrained = hash_to_bits_at(
+            cs.ns(|| "constrained"),
+            &message,
+            512,
+            256,
+            128,
+            personalization,
+            true,
+        )
+        .unwrap();
+        let native = hash_to_bits_at(
+            cs.ns(|| "native"),
+            &message,
+            512,
+            256,
+            128,
+            personalization,
+            false,
+        )
+        .unwrap();
+        let full = hash_to_bits(cs.ns(|| "full"), &message, 2048, personalization, false).unwrap();
+
+        assert!(cs.is_satisfied());
+        let values = |bits: &[Boolean]| {
+            bits.iter()
+                .map(|b| b.get_value().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values(&constrained), values(&native));
+        // the bits are those of the fifth and sixth blocks of the whole output
+        assert_eq!(values(&constrained), values(&full[1024..1536]));
+    }
+
+    #[test]
+    fn hash_to_bits_detects_tampering() {
+        let message = bytes_to_bits(&[1, 2, 3, 4], 32);
+
+        let mut cs = TestConstraintSystem::<bls12_377::Fq>::new();
+        let message = constrain_bool(&mut cs.ns(|| "message"), &message).unwrap();
+        let mut personalization = [0; 8];
+        personalization.copy_from_slice(SIG_DOMAIN);
+        hash_to_bits(cs.ns(|| "hash"), &message, 256, personalization, true).unwrap();
+
+        let paths = (0..message.len())
+            .map(|i| format!("message/{}/boolean", i))
+            .collect::<Vec<_>>();
+        let paths = paths.iter().map(|p| p.as_str()).collect::<Vec<_>>();
+        assert_tampering_detected(&mut cs, &paths);
+    }
 }
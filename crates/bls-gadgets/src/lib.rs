@@ -2,18 +2,38 @@
 //!
 //! This module provides gadgets for constructing R1CS involving BLS Signatures
 //! over the BLS12-377 curve.
+#![cfg_attr(feature = "forbid-unsafe", forbid(unsafe_code))]
 
 mod bls;
-pub use bls::BlsVerifyGadget;
+pub use bls::{BlsVerifyGadget, QuorumCertificateGadget};
 
 mod bitmap;
 pub(crate) use bitmap::enforce_maximum_occurrences_in_bitmap;
 
+mod range;
+pub use range::{enforce_less_than_constant, RangeCheck};
+
 mod y_to_bit;
 pub use y_to_bit::YToBitGadget;
 
 mod hash_to_group;
-pub use hash_to_group::{hash_to_bits, HashToGroupGadget};
+pub use hash_to_group::{hash_to_bits, hash_to_bits_at, HashToGroupGadget};
+
+mod sswu;
+pub use sswu::SswuGadget;
+
+mod bytes;
+pub use bytes::{alloc_bytes_matching_bits, bits_to_bytes, enforce_bytes_match_bits};
+
+mod equality;
+pub use equality::{conditional_enforce_equal_with_label, enforce_equal_with_label};
+
+/// Differential tests of the hash to G1 gadget against the native hasher
+#[cfg(feature = "hash-differential")]
+pub mod differential;
+
+#[cfg(any(test, feature = "test-helpers"))]
+mod explain;
 
 /// Utility functions which do not involve generating constraints
 pub mod utils;
@@ -0,0 +1,380 @@
+use algebra::{BigInteger, FpParameters, PrimeField};
+use r1cs_core::{ConstraintSystem, LinearCombination, SynthesisError};
+use r1cs_std::{
+    alloc::AllocGadget,
+    boolean::Boolean,
+    fields::{fp::FpGadget, FieldGadget},
+    Assignment,
+};
+
+/// The little-endian decomposition of a field element into a fixed number of bits.
+///
+/// Decomposing a value into `num_bits` bits enforces `value < 2^num_bits`. Comparisons against
+/// constants are then performed on the bits, so a value which is checked against several
+/// bounds, or whose bits are also needed for encoding, is only decomposed once.
+#[derive(Clone, Debug)]
+pub struct RangeCheck {
+    bits: Vec<Boolean>,
+}
+
+impl RangeCheck {
+    /// Decomposes `value` into `num_bits` little-endian bits, enforcing `value < 2^num_bits`
+    ///
+    /// # Panics
+    /// If `num_bits` is larger than the field's capacity, since the decomposition would not
+    /// be unique
+    pub fn new<F: PrimeField, CS: ConstraintSystem<F>>(
+        cs: &mut CS,
+        value: &FpGadget<F>,
+        num_bits: usize,
+    ) -> Result<Self, SynthesisError> {
+        assert!(
+            num_bits <= F::Params::CAPACITY as usize,
+            "cannot decompose into {} bits",
+            num_bits
+        );
+        Self::decompose(cs, value, num_bits)
+    }
+
+    /// Decomposes `value` into the `MODULUS_BITS` little-endian bits of its canonical
+    /// representation. A decomposition into that many bits is not unique, as values smaller
+    /// than `2^MODULUS_BITS - p` can also be decomposed as `value + p`, so the bits are
+    /// enforced to represent a value smaller than the modulus. This is needed when a bit of
+    /// the value is used, e.g. its parity.
+    pub fn new_canonical<F: PrimeField, CS: ConstraintSystem<F>>(
+        cs: &mut CS,
+        value: &FpGadget<F>,
+    ) -> Result<Self, SynthesisError> {
+        let num_bits = F::Params::MODULUS_BITS as usize;
+        let range = Self::decompose(&mut cs.ns(|| "decompose"), value, num_bits)?;
+        range.enforce_less_than_constant(&mut cs.ns(|| "canonical"), &F::Params::MODULUS)?;
+        Ok(range)
+    }
+
+    fn decompose<F: PrimeField, CS: ConstraintSystem<F>>(
+        cs: &mut CS,
+        value: &FpGadget<F>,
+        num_bits: usize,
+    ) -> Result<Self, SynthesisError> {
+        let mut bits = Vec::with_capacity(num_bits);
+        let mut packed = LinearCombination::zero();
+        let mut coeff = F::one();
+        for i in 0..num_bits {
+            let bit = Boolean::alloc(cs.ns(|| format!("bit {}", i)), || {
+                Ok(value.get_value().get()?.into_repr().get_bit(i))
+            })?;
+            packed = packed + bit.lc(CS::one(), coeff);
+            coeff.double_in_place();
+            bits.push(bit);
+        }
+
+        cs.enforce(
+            || "enforce bits pack to value",
+            |lc| lc + (F::one(), CS::one()),
+            |_| packed,
+            |lc| value.get_variable() + lc,
+        );
+
+        Ok(Self { bits })
+    }
+
+    /// The little-endian bits of the value
+    pub fn bits(&self) -> &[Boolean] {
+        &self.bits
+    }
+
+    /// Returns the little-endian bits of the value
+    pub fn into_bits(self) -> Vec<Boolean> {
+        self.bits
+    }
+
+    /// Enforces that the value is smaller than or equal to `constant`. Costs one constraint
+    /// per bit after the most significant set bit of `constant`.
+    pub fn enforce_at_most_constant<F: PrimeField, CS: ConstraintSystem<F>>(
+        &self,
+        cs: &mut CS,
+        constant: &F::BigInt,
+    ) -> Result<(), SynthesisError> {
+        let num_bits = self.bits.len();
+        // every value of the decomposition is in range
+        if constant.num_bits() as usize > num_bits {
+            return Ok(());
+        }
+        // the running equality is only needed while there are unset bits left to check
+        let lowest_unset = match (0..num_bits).find(|i| !constant.get_bit(*i)) {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+
+        // Going from the most significant bit, `equal` is true while the value's bits are
+        // equal to the constant's. Where the constant's bit is unset, the value's bit must
+        // be unset too unless a higher bit was already smaller.
+        let mut equal = Boolean::constant(true);
+        for i in (lowest_unset..num_bits).rev() {
+            let bit = &self.bits[i];
+            if constant.get_bit(i) {
+                equal = Boolean::and(cs.ns(|| format!("equal {}", i)), &equal, bit)?;
+            } else {
+                cs.enforce(
+                    || format!("enforce bit {}", i),
+                    |_| equal.lc(CS::one(), F::one()),
+                    |_| bit.lc(CS::one(), F::one()),
+                    |lc| lc,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Same as `enforce_at_most_constant`, but costs one constraint per set bit and per run
+    /// of unset bits of `constant` instead of one per bit. The constraints differ from those
+    /// of `enforce_at_most_constant`, which circuits with existing parameters keep using.
+    pub fn enforce_at_most_constant_batched<F: PrimeField, CS: ConstraintSystem<F>>(
+        &self,
+        cs: &mut CS,
+        constant: &F::BigInt,
+    ) -> Result<(), SynthesisError> {
+        let num_bits = self.bits.len();
+        if constant.num_bits() as usize > num_bits {
+            return Ok(());
+        }
+        let lowest_unset = match (0..num_bits).find(|i| !constant.get_bit(*i)) {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+
+        // `equal` only changes at the constant's set bits, so the value's bits along a run of
+        // unset bits are all enforced to be unset by a single constraint
+        let mut equal = Boolean::constant(true);
+        let mut run = vec![];
+        for i in (lowest_unset..num_bits).rev() {
+            let bit = &self.bits[i];
+            if constant.get_bit(i) {
+                if !run.is_empty() {
+                    enforce_unset_if(cs, i + 1, &equal, &run);
+                    run.clear();
+                }
+                equal = Boolean::and(cs.ns(|| format!("equal {}", i)), &equal, bit)?;
+            } else {
+                run.push(bit);
+            }
+        }
+        // the last run ends at the lowest unset bit
+        enforce_unset_if(cs, lowest_unset, &equal, &run);
+
+        Ok(())
+    }
+
+    /// Enforces that the value is smaller than `constant`
+    pub fn enforce_less_than_constant<F: PrimeField, CS: ConstraintSystem<F>>(
+        &self,
+        cs: &mut CS,
+        constant: &F::BigInt,
+    ) -> Result<(), SynthesisError> {
+        let mut max = *constant;
+        // no value is smaller than zero
+        if max.sub_noborrow(&F::BigInt::from(1)) {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        self.enforce_at_most_constant(cs, &max)
+    }
+}
+
+// Enforces that the bits, the lowest of which is at `index`, are unset if `equal` is set. The
+// sum of the bits is zero only if they all are, as it cannot wrap around the modulus.
+fn enforce_unset_if<F: PrimeField, CS: ConstraintSystem<F>>(
+    cs: &mut CS,
+    index: usize,
+    equal: &Boolean,
+    bits: &[&Boolean],
+) {
+    cs.enforce(
+        || format!("enforce bits from {}", index),
+        |_| equal.lc(CS::one(), F::one()),
+        |_| {
+            bits.iter().fold(LinearCombination::zero(), |lc, bit| {
+                lc + bit.lc(CS::one(), F::one())
+            })
+        },
+        |lc| lc,
+    );
+}
+
+/// Enforces that `value < constant`, where `value` is decomposed into `num_bits` bits.
+/// The decomposition is returned so that it can be reused for other checks.
+pub fn enforce_less_than_constant<F: PrimeField, CS: ConstraintSystem<F>>(
+    cs: &mut CS,
+    value: &FpGadget<F>,
+    constant: &F::BigInt,
+    num_bits: usize,
+) -> Result<RangeCheck, SynthesisError> {
+    let range = RangeCheck::new(&mut cs.ns(|| "decompose"), value, num_bits)?;
+    range.enforce_less_than_constant(&mut cs.ns(|| "compare"), constant)?;
+    Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_helpers::assert_tampering_detected;
+    use algebra::{bls12_377::Fq, UniformRand};
+    use r1cs_std::test_constraint_system::TestConstraintSystem;
+
+    type BigInt = <Fq as PrimeField>::BigInt;
+
+    fn cs_less_than(value: Fq, constant: u64, num_bits: usize) -> TestConstraintSystem<Fq> {
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let value = FpGadget::alloc(cs.ns(|| "value"), || Ok(value)).unwrap();
+        enforce_less_than_constant(&mut cs, &value, &BigInt::from(constant), num_bits).unwrap();
+        cs
+    }
+
+    #[test]
+    fn decomposes_little_endian() {
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let value = FpGadget::alloc(cs.ns(|| "value"), || Ok(Fq::from(0b1011u64))).unwrap();
+        let range = RangeCheck::new(&mut cs, &value, 6).unwrap();
+        let bits = range
+            .bits()
+            .iter()
+            .map(|b| b.get_value().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(bits, vec![true, true, false, true, false, false]);
+        assert_eq!(cs.num_constraints(), 7);
+        assert!(cs.is_satisfied());
+    }
+
+    #[test]
+    fn less_than_constant() {
+        for value in 0..20u64 {
+            let cs = cs_less_than(Fq::from(value), 13, 5);
+            assert_eq!(cs.is_satisfied(), value < 13, "{} < 13", value);
+        }
+    }
+
+    #[test]
+    fn less_than_power_of_two_is_decomposition() {
+        assert!(cs_less_than(Fq::from(65535u64), 1 << 16, 16).is_satisfied());
+        assert!(!cs_less_than(Fq::from(65536u64), 1 << 16, 16).is_satisfied());
+        // only the decomposition's constraints are needed
+        assert_eq!(
+            cs_less_than(Fq::from(1u64), 1 << 16, 16).num_constraints(),
+            17
+        );
+    }
+
+    #[test]
+    fn nothing_is_less_than_zero() {
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let value = FpGadget::alloc(cs.ns(|| "value"), || Ok(Fq::from(0u64))).unwrap();
+        assert!(enforce_less_than_constant(&mut cs, &value, &BigInt::from(0), 8).is_err());
+    }
+
+    #[test]
+    fn rejects_values_wrapping_around_the_modulus() {
+        // -1 cannot be decomposed into a small number of bits
+        assert!(!cs_less_than(-Fq::from(1u64), 13, 5).is_satisfied());
+    }
+
+    #[test]
+    fn at_most_modulus_minus_one_div_two() {
+        let half = Fq::modulus_minus_one_div_two();
+        let num_bits = <Fq as PrimeField>::Params::CAPACITY as usize;
+        let check = |value: Fq| {
+            let mut cs = TestConstraintSystem::<Fq>::new();
+            let value = FpGadget::alloc(cs.ns(|| "value"), || Ok(value)).unwrap();
+            let range = RangeCheck::new(&mut cs.ns(|| "decompose"), &value, num_bits).unwrap();
+            range
+                .enforce_at_most_constant(&mut cs.ns(|| "compare"), &half)
+                .unwrap();
+            cs.is_satisfied()
+        };
+
+        let half_fq = Fq::from_repr(half).unwrap();
+        assert!(check(half_fq));
+        assert!(check(half_fq - &Fq::from(1u64)));
+        assert!(!check(half_fq + &Fq::from(1u64)));
+        assert!(!check(-Fq::from(1u64)));
+
+        let rng = &mut rand::thread_rng();
+        for _ in 0..20 {
+            let value = Fq::rand(rng);
+            assert_eq!(check(value), value <= half_fq);
+        }
+    }
+
+    #[test]
+    fn batched_comparison_matches() {
+        let check = |value: u64, constant: u64, batched: bool| {
+            let mut cs = TestConstraintSystem::<Fq>::new();
+            let value = FpGadget::alloc(cs.ns(|| "value"), || Ok(Fq::from(value))).unwrap();
+            let range = RangeCheck::new(&mut cs.ns(|| "decompose"), &value, 8).unwrap();
+            let constant = BigInt::from(constant);
+            let compare = &mut cs.ns(|| "compare");
+            let result = if batched {
+                range.enforce_at_most_constant_batched(compare, &constant)
+            } else {
+                range.enforce_at_most_constant(compare, &constant)
+            };
+            result.unwrap();
+            (cs.is_satisfied(), cs.num_constraints())
+        };
+
+        for &constant in &[
+            0b1000_0000u64,
+            0b1011_0010,
+            0b0110_0111,
+            0b1111_1110,
+            0,
+            255,
+        ] {
+            for value in 0..256u64 {
+                let (satisfied, _) = check(value, constant, true);
+                assert_eq!(satisfied, value <= constant, "{} <= {}", value, constant);
+                assert_eq!(satisfied, check(value, constant, false).0);
+            }
+        }
+        // 9 constraints for the decomposition, then one per set bit after the most
+        // significant one and one per run of unset bits, instead of one per bit
+        assert_eq!(check(0, 0b1011_0010, false).1, 9 + 7);
+        assert_eq!(check(0, 0b1011_0010, true).1, 9 + 3 + 3);
+    }
+
+    #[test]
+    fn canonical_decomposition_is_unique() {
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let value = FpGadget::alloc(cs.ns(|| "value"), || Ok(Fq::from(3u64))).unwrap();
+        let range = RangeCheck::new_canonical(&mut cs, &value).unwrap();
+        assert_eq!(range.bits().len(), 377);
+        assert!(cs.is_satisfied());
+
+        // 3 + p packs to the same value, but is not smaller than the modulus
+        let mut shifted = <Fq as PrimeField>::Params::MODULUS;
+        shifted.add_nocarry(&BigInt::from(3));
+        for i in 0..377 {
+            cs.set(
+                &format!("decompose/bit {}/boolean", i),
+                Fq::from(shifted.get_bit(i) as u64),
+            );
+        }
+        assert_eq!(
+            cs.which_is_unsatisfied()
+                .map(|path| path.starts_with("canonical")),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let mut cs = cs_less_than(Fq::from(10u64), 13, 5);
+        assert_tampering_detected(
+            &mut cs,
+            &[
+                "value/alloc",
+                "decompose/bit 0/boolean",
+                "decompose/bit 3/boolean",
+            ],
+        );
+    }
+}
@@ -0,0 +1,397 @@
+#![allow(clippy::op_ref)] // clippy throws a false positive around field ops
+use crate::{hash_to_bits, utils::is_setup, HashToGroupGadget, RangeCheck, YToBitGadget};
+use algebra::{
+    bls12_377::{Fq, Parameters},
+    Field, One, SquareRootField, Zero,
+};
+use bls_crypto::{
+    hash_to_curve::sswu::{
+        field_element_from_bytes, map_to_iso_curve, FIELD_ELEMENT_BYTES, ISO_B, ISO_MINUS_A,
+        MINUS_Z,
+    },
+    SIG_DOMAIN,
+};
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::{
+    alloc::AllocGadget,
+    boolean::Boolean,
+    eq::EqGadget,
+    fields::{fp::FpGadget, FieldGadget},
+    groups::{bls12::G1Gadget, GroupGadget},
+    select::CondSelectGadget,
+    uint8::UInt8,
+    Assignment,
+};
+use tracing::{debug, span, trace, Level};
+
+type FqGadget = FpGadget<Fq>;
+
+/// The length in bits of the XOF output from which the two field elements are read
+const XOF_BITS: usize = 2 * 8 * FIELD_ELEMENT_BYTES;
+
+/// Gadget which enforces correct calculation of hashing to BLS12-377's G1 with the
+/// simplified SWU map. For more information on the method, refer to the [non-gadget
+/// implementation][sswu].
+///
+/// The message is compressed with the same Pedersen hash as in `HashToGroupGadget` and the
+/// field elements are read from the XOF's output, so the gadget matches the native composite
+/// SSWU hasher.
+///
+/// The constraints cannot be satisfied in the exceptional cases of the incomplete formulas
+/// used: a field element mapped to a point with `y = 0` or to the kernel of the isogeny, and
+/// the two field elements mapped to points with the same `x` coordinate. These only happen
+/// with negligible probability for a hashed message.
+///
+/// [sswu]: ../bls_crypto/hash_to_curve/sswu/index.html
+pub struct SswuGadget;
+
+impl SswuGadget {
+    /// Returns the G1 constrained hash of the message.
+    ///
+    /// As for `HashToGroupGadget::enforce_hash_to_group`, constraints are only generated for
+    /// the CRH -> XOF conversion if `generate_constraints_for_hash` is set, and the CRH bits
+    /// and the XOF bits are also returned so that the conversion can be verified in a
+    /// separate proof.
+    #[allow(clippy::type_complexity)]
+    pub fn enforce_hash_to_group<CS: ConstraintSystem<Fq>>(
+        cs: &mut CS,
+        message: &[UInt8],
+        generate_constraints_for_hash: bool,
+    ) -> Result<(G1Gadget<Parameters>, Vec<Boolean>, Vec<Boolean>), SynthesisError> {
+        let mut domain = [0; 8];
+        domain.copy_from_slice(SIG_DOMAIN);
+        Self::enforce_hash_to_group_with_domain(cs, message, domain, generate_constraints_for_hash)
+    }
+
+    /// Same as `enforce_hash_to_group`, but personalizes the XOF with `domain` instead of
+    /// `SIG_DOMAIN`. The native hash's `extra_data` corresponds to a prefix of the message.
+    #[allow(clippy::type_complexity)]
+    pub fn enforce_hash_to_group_with_domain<CS: ConstraintSystem<Fq>>(
+        cs: &mut CS,
+        message: &[UInt8],
+        domain: [u8; 8],
+        generate_constraints_for_hash: bool,
+    ) -> Result<(G1Gadget<Parameters>, Vec<Boolean>, Vec<Boolean>), SynthesisError> {
+        let span = span!(Level::TRACE, "enforce_hash_to_group_sswu",);
+        let _enter = span.enter();
+
+        let crh_bits = HashToGroupGadget::<Parameters>::pedersen_hash(cs, message)?;
+        let xof_bits = hash_to_bits(
+            cs.ns(|| "hash to bits"),
+            &crh_bits,
+            XOF_BITS as u16,
+            domain,
+            generate_constraints_for_hash,
+        )?;
+
+        let mut points = vec![];
+        for (i, bits) in xof_bits.chunks(XOF_BITS / 2).enumerate() {
+            trace!(field_element = i);
+            let u = Self::field_element_from_bits(cs.ns(|| format!("u {}", i)), bits)?;
+            let (x, y) = Self::map_to_iso_curve(cs.ns(|| format!("map {}", i)), &u)?;
+            points.push(Self::iso_map(cs.ns(|| format!("isogeny {}", i)), &x, &y)?);
+        }
+        let sum = points[0].add(cs.ns(|| "add points"), &points[1])?;
+
+        trace!("scaling by G1 cofactor");
+        let hash = HashToGroupGadget::<Parameters>::scale_by_cofactor_g1(
+            cs.ns(|| "scale by cofactor"),
+            &sum,
+        )?;
+
+        debug!("message has been hashed to G1 with SSWU");
+        Ok((hash, crh_bits, xof_bits))
+    }
+
+    /// Reads the field element from XOF bits, where bit `8j + k` is bit `k` of byte `j` and
+    /// the bytes are read big endian, as in `sswu::field_element_from_bytes`
+    fn field_element_from_bits<CS: ConstraintSystem<Fq>>(
+        mut cs: CS,
+        bits: &[Boolean],
+    ) -> Result<FqGadget, SynthesisError> {
+        let u = FqGadget::alloc(cs.ns(|| "alloc u"), || {
+            if is_setup(bits) {
+                return Err(SynthesisError::AssignmentMissing);
+            }
+            let bytes = bits
+                .chunks(8)
+                .map(|byte| {
+                    byte.iter().enumerate().try_fold(0u8, |acc, (k, bit)| {
+                        Ok(acc | ((bit.get_value().get()? as u8) << k))
+                    })
+                })
+                .collect::<Result<Vec<u8>, SynthesisError>>()?;
+            Ok(field_element_from_bytes(&bytes))
+        })?;
+
+        cs.enforce(
+            || "u is read from the bits",
+            |lc| lc + (Fq::one(), CS::one()),
+            |lc| u.get_variable() + lc,
+            |mut lc| {
+                // the last byte is the least significant
+                let mut coeff = Fq::one();
+                for byte in bits.chunks(8).rev() {
+                    for bit in byte {
+                        lc = lc + bit.lc(CS::one(), coeff);
+                        coeff.double_in_place();
+                    }
+                }
+                lc
+            },
+        );
+
+        Ok(u)
+    }
+
+    /// Enforces the simplified SWU map of `u` to the isogenous curve, returning the
+    /// coordinates of the point
+    fn map_to_iso_curve<CS: ConstraintSystem<Fq>>(
+        mut cs: CS,
+        u: &FqGadget,
+    ) -> Result<(FqGadget, FqGadget), SynthesisError> {
+        let a = -Fq::from(ISO_MINUS_A);
+        let b = Fq::from(ISO_B);
+        let z = -Fq::from(MINUS_Z);
+
+        let zu2 = u
+            .square(cs.ns(|| "u^2"))?
+            .mul_by_constant(cs.ns(|| "Z u^2"), &z)?;
+        let denominator = zu2
+            .square(cs.ns(|| "Z^2 u^4"))?
+            .add(cs.ns(|| "denominator"), &zu2)?;
+
+        // tv1 = inv0(denominator)
+        let exceptional = YToBitGadget::<Parameters>::is_eq_zero(
+            &mut cs.ns(|| "denominator is zero"),
+            &denominator,
+        )?;
+        let tv1 = FqGadget::alloc(cs.ns(|| "tv1"), || {
+            Ok(denominator
+                .get_value()
+                .get()?
+                .inverse()
+                .unwrap_or_else(Fq::zero))
+        })?;
+        cs.enforce(
+            || "tv1 inverts the denominator",
+            |lc| denominator.get_variable() + lc,
+            |lc| tv1.get_variable() + lc,
+            |lc| lc + (Fq::one(), CS::one()) + exceptional.lc(CS::one(), -Fq::one()),
+        );
+        cs.enforce(
+            || "tv1 is zero if the denominator is",
+            |lc| tv1.get_variable() + lc,
+            |_| exceptional.lc(CS::one(), Fq::one()),
+            |lc| lc,
+        );
+
+        // x1 = (-B / A) (1 + tv1), or B / (Z A) if tv1 is 0
+        let regular_x1 = tv1
+            .add_constant(cs.ns(|| "1 + tv1"), &Fq::one())?
+            .mul_by_constant(cs.ns(|| "regular x1"), &(-b * &a.inverse().unwrap()))?;
+        let exceptional_x1 =
+            FqGadget::alloc_constant(cs.ns(|| "exceptional x1"), b * &(z * &a).inverse().unwrap())?;
+        let x1 = FqGadget::conditionally_select(
+            cs.ns(|| "x1"),
+            &exceptional,
+            &exceptional_x1,
+            &regular_x1,
+        )?;
+        let gx1 = Self::iso_curve_equation(cs.ns(|| "gx1"), &x1)?;
+        let x2 = zu2.mul(cs.ns(|| "x2"), &x1)?;
+        let gx2 = Self::iso_curve_equation(cs.ns(|| "gx2"), &x2)?;
+
+        // x2 must only be selected if gx1 is not a square, i.e. if Z gx1 is a nonzero square
+        let is_square = Boolean::alloc(cs.ns(|| "gx1 is square"), || {
+            Ok(!gx1.get_value().get()?.legendre().is_qnr())
+        })?;
+        let one = FqGadget::one(cs.ns(|| "one"))?;
+        let z_gadget = FqGadget::alloc_constant(cs.ns(|| "Z"), z)?;
+        let factor =
+            FqGadget::conditionally_select(cs.ns(|| "factor"), &is_square, &one, &z_gadget)?;
+        let scaled_gx1 = gx1.mul(cs.ns(|| "scaled gx1"), &factor)?;
+        let root = FqGadget::alloc(cs.ns(|| "root of scaled gx1"), || {
+            scaled_gx1.get_value().get()?.sqrt().get()
+        })?;
+        root.square_equals(cs.ns(|| "scaled gx1 is a square"), &scaled_gx1)?;
+        let gx1_inv = FqGadget::alloc(cs.ns(|| "gx1 inverse"), || {
+            if is_square.get_value().get()? {
+                Ok(Fq::zero())
+            } else {
+                gx1.get_value().get()?.inverse().get()
+            }
+        })?;
+        cs.enforce(
+            || "gx1 is nonzero if not a square",
+            |lc| gx1.get_variable() + lc,
+            |lc| gx1_inv.get_variable() + lc,
+            |lc| lc + (Fq::one(), CS::one()) + is_square.lc(CS::one(), -Fq::one()),
+        );
+
+        let x = FqGadget::conditionally_select(cs.ns(|| "x"), &is_square, &x1, &x2)?;
+        let gx = FqGadget::conditionally_select(cs.ns(|| "gx"), &is_square, &gx1, &gx2)?;
+
+        // y is the square root of gx with the sign of u
+        let y = FqGadget::alloc(cs.ns(|| "y"), || {
+            Ok(map_to_iso_curve(&u.get_value().get()?).1)
+        })?;
+        y.square_equals(cs.ns(|| "y is a square root of gx"), &gx)?;
+        // The decompositions must be canonical: the bits of y + p also pack to y, and have the
+        // opposite parity, which would let the prover pick either root
+        let u_bits = RangeCheck::new_canonical(&mut cs.ns(|| "u bits"), u)?;
+        let y_bits = RangeCheck::new_canonical(&mut cs.ns(|| "y bits"), &y)?;
+        // the bits are little endian, so the first ones are the parities
+        u_bits.bits()[0].enforce_equal(cs.ns(|| "sign"), &y_bits.bits()[0])?;
+
+        Ok((x, y))
+    }
+
+    /// Enforces the isogeny from the isogenous curve to G1's curve
+    fn iso_map<CS: ConstraintSystem<Fq>>(
+        mut cs: CS,
+        x: &FqGadget,
+        y: &FqGadget,
+    ) -> Result<G1Gadget<Parameters>, SynthesisError> {
+        let three = Fq::from(3u64);
+
+        // d = 1 / (x - 2), which does not exist for the isogeny's kernel
+        let d = x
+            .add_constant(cs.ns(|| "x - 2"), &-Fq::from(2u64))?
+            .inverse(cs.ns(|| "d"))?;
+
+        // x' = (x - 3 d) / 4
+        let three_d = d.mul_by_constant(cs.ns(|| "3 d"), &three)?;
+        let mapped_x = x
+            .sub(cs.ns(|| "x - 3 d"), &three_d)?
+            .mul_by_constant(cs.ns(|| "mapped x"), &Fq::from(4u64).inverse().unwrap())?;
+
+        // y' = y (1 + 3 d^2) / 8
+        let factor = d
+            .square(cs.ns(|| "d^2"))?
+            .mul_by_constant(cs.ns(|| "3 d^2"), &three)?
+            .add_constant(cs.ns(|| "1 + 3 d^2"), &Fq::one())?
+            .mul_by_constant(cs.ns(|| "y factor"), &Fq::from(8u64).inverse().unwrap())?;
+        let mapped_y = y.mul(cs.ns(|| "mapped y"), &factor)?;
+
+        Ok(G1Gadget::<Parameters>::new(
+            mapped_x,
+            mapped_y,
+            Boolean::constant(false),
+        ))
+    }
+
+    /// Enforces `x^3 + A x + B` for the isogenous curve
+    fn iso_curve_equation<CS: ConstraintSystem<Fq>>(
+        mut cs: CS,
+        x: &FqGadget,
+    ) -> Result<FqGadget, SynthesisError> {
+        let a = -Fq::from(ISO_MINUS_A);
+        let b = Fq::from(ISO_B);
+        // (x^2 + A) x + B
+        x.square(cs.ns(|| "x^2"))?
+            .add_constant(cs.ns(|| "x^2 + A"), &a)?
+            .mul(cs.ns(|| "x^3 + A x"), x)?
+            .add_constant(cs.ns(|| "x^3 + A x + B"), &b)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_helpers::assert_tampering_detected;
+
+    use algebra::bls12_377;
+    use bls_crypto::{hash_to_curve::sswu::COMPOSITE_SSWU_HASH_TO_G1, HashToCurve};
+    use r1cs_std::test_constraint_system::TestConstraintSystem;
+    use rand::{thread_rng, RngCore};
+
+    fn hash_to_group(input: &[u8]) -> TestConstraintSystem<bls12_377::Fq> {
+        let expected_hash = COMPOSITE_SSWU_HASH_TO_G1
+            .hash(SIG_DOMAIN, input, &[])
+            .unwrap();
+
+        let mut cs = TestConstraintSystem::<bls12_377::Fq>::new();
+        let input = input
+            .iter()
+            .enumerate()
+            .map(|(i, num)| {
+                UInt8::alloc(&mut cs.ns(|| format!("input {}", i)), || Ok(num)).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let hash = SswuGadget::enforce_hash_to_group(&mut cs.ns(|| "hash to group"), &input, false)
+            .unwrap()
+            .0;
+
+        assert!(cs.is_satisfied());
+        assert_eq!(expected_hash, hash.get_value().unwrap());
+        cs
+    }
+
+    #[test]
+    fn test_hash_to_group() {
+        let mut rng = thread_rng();
+        for length in &[10, 25, 50, 100, 200, 300] {
+            let mut input = vec![0; *length];
+            rng.fill_bytes(&mut input);
+            hash_to_group(&input);
+        }
+    }
+
+    #[test]
+    fn rejects_the_negated_root() {
+        use algebra::{BigInteger, FpParameters, PrimeField};
+
+        // Negating y keeps it a root of gx. Its canonical bits have the other parity, but
+        // when -y + p fits in the decomposition its bits have the parity of y, so only the
+        // canonicity of the decomposition rejects the negated root.
+        let mut rng = thread_rng();
+        let mut tried = 0;
+        loop {
+            tried += 1;
+            assert!(
+                tried < 200,
+                "no root with a non-canonical negation was found"
+            );
+            let mut input = vec![0; 32];
+            rng.fill_bytes(&mut input);
+            let mut cs = hash_to_group(&input);
+
+            let negated = -cs.get("hash to group/map 0/y/alloc");
+            let mut bits = negated.into_repr();
+            bits.add_nocarry(&<Fq as PrimeField>::Params::MODULUS);
+            if bits.num_bits() > <Fq as PrimeField>::Params::MODULUS_BITS {
+                continue;
+            }
+
+            cs.set("hash to group/map 0/y/alloc", negated);
+            for i in 0..<Fq as PrimeField>::Params::MODULUS_BITS as usize {
+                cs.set(
+                    &format!("hash to group/map 0/y bits/decompose/bit {}/boolean", i),
+                    Fq::from(bits.get_bit(i) as u64),
+                );
+            }
+            let unsatisfied = cs.which_is_unsatisfied().unwrap();
+            assert!(
+                unsatisfied.starts_with("hash to group/map 0/y bits/canonical"),
+                "{}",
+                unsatisfied
+            );
+            break;
+        }
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let mut cs = hash_to_group(b"message");
+        assert_tampering_detected(
+            &mut cs,
+            &[
+                "hash to group/map 0/gx1 is square/boolean",
+                "hash to group/map 1/gx1 is square/boolean",
+                "hash to group/map 0/denominator is zero/alloc bit/boolean",
+            ],
+        );
+    }
+}
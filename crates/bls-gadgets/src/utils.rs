@@ -63,9 +63,10 @@ pub(crate) fn constrain_bool<F: Field, CS: ConstraintSystem<F>>(
 
 #[cfg(any(test, feature = "test-helpers"))]
 pub mod test_helpers {
+    pub use crate::explain::{ExplainingConstraintSystem, Term, UnsatisfiedConstraint};
     use algebra::{Field, Group};
     use r1cs_core::ConstraintSystem;
-    use r1cs_std::groups::GroupGadget;
+    use r1cs_std::{groups::GroupGadget, test_constraint_system::TestConstraintSystem};
 
     /// Allocates an array of group elements to a group gadget
     pub fn alloc_vec<F: Field, G: Group, GG: GroupGadget<G, F>, CS: ConstraintSystem<F>>(
@@ -78,4 +79,50 @@ pub mod test_helpers {
             .map(|(i, element)| GG::alloc(&mut cs.ns(|| format!("{}", i)), || Ok(element)).unwrap())
             .collect::<Vec<_>>()
     }
+
+    /// Tampers with the variables at the provided paths one at a time, and panics if the
+    /// constraint system remains satisfied after any of them is modified. Variables holding
+    /// 0 or 1 are flipped, while any other value is incremented by one. Each variable is
+    /// restored before moving on to the next one.
+    ///
+    /// Paths follow the namespacing of the constraint system, e.g. a `Boolean` allocated
+    /// under `cs.ns(|| "bit")` lives at `"bit/boolean"`.
+    ///
+    /// # Panics
+    ///
+    /// - If the constraint system is not satisfied before tampering
+    /// - If no variable exists at any of the provided paths
+    pub fn assert_tampering_detected<F: Field>(cs: &mut TestConstraintSystem<F>, paths: &[&str]) {
+        assert!(
+            cs.is_satisfied(),
+            "constraint system must be satisfied before tampering: {:?}",
+            cs.which_is_unsatisfied()
+        );
+
+        let undetected = paths
+            .iter()
+            .filter(|path| {
+                let original = cs.get(path);
+                let tampered = if original.is_zero() {
+                    F::one()
+                } else if original.is_one() {
+                    F::zero()
+                } else {
+                    original + &F::one()
+                };
+
+                cs.set(path, tampered);
+                let detected = !cs.is_satisfied();
+                cs.set(path, original);
+
+                !detected
+            })
+            .collect::<Vec<_>>();
+
+        assert!(
+            undetected.is_empty(),
+            "tampering with {:?} was not detected by the constraints",
+            undetected
+        );
+    }
 }
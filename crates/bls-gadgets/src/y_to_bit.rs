@@ -1,11 +1,14 @@
 #![allow(clippy::op_ref)] // clippy throws a false positive around field ops
-use algebra::{curves::bls12::Bls12Parameters, Field, One, PrimeField, Zero};
+use crate::RangeCheck;
+use algebra::{curves::bls12::Bls12Parameters, Field, FpParameters, One, PrimeField, Zero};
+use bls_crypto::PointCompression;
 use r1cs_core::{ConstraintSystem, SynthesisError};
 use r1cs_std::{
     alloc::AllocGadget,
     boolean::Boolean,
     fields::{fp::FpGadget, FieldGadget},
     groups::curves::short_weierstrass::bls12::{G1Gadget, G2Gadget},
+    select::CondSelectGadget,
     Assignment,
 };
 use std::{marker::PhantomData, ops::Neg};
@@ -16,7 +19,13 @@ use std::{marker::PhantomData, ops::Neg};
 /// [half+1, p-1]), by subtracting half (resulting in a number in the [1, half]
 /// range). Then we check that the cast element is <= half, which enforces that
 /// originally they were > half. For points in G2, we also check the
-/// lexicographical ordering.
+/// lexicographical ordering. The bits match those of `PointCompression`.
+///
+/// The `_cheap` methods compute the same bits with the same soundness in fewer constraints:
+/// G2 normalizes only the coefficient of y which decides the bit, and the comparison with
+/// $half$ costs one constraint per run of unset bits of $half$ instead of one per bit. Their
+/// constraints differ, so they are only used by circuits whose parameters are generated with
+/// them.
 pub struct YToBitGadget<P: Bls12Parameters> {
     parameters_type: PhantomData<P>,
 }
@@ -26,27 +35,46 @@ impl<P: Bls12Parameters> YToBitGadget<P> {
         mut cs: CS,
         pk: &G1Gadget<P>,
     ) -> Result<Boolean, SynthesisError> {
-        let y_bit = Self::normalize(&mut cs.ns(|| "g1 normalize"), &pk.y)?;
+        let y_bit = Self::normalize(&mut cs.ns(|| "g1 normalize"), &pk.y, false)?;
         Ok(y_bit)
     }
 
+    /// Same as `y_to_bit_g1`, in fewer constraints
+    pub fn y_to_bit_g1_cheap<CS: ConstraintSystem<P::Fp>>(
+        mut cs: CS,
+        pk: &G1Gadget<P>,
+    ) -> Result<Boolean, SynthesisError> {
+        Self::normalize(&mut cs.ns(|| "g1 normalize"), &pk.y, true)
+    }
+
+    /// Same as `y_to_bit_g2`, in fewer constraints
+    pub fn y_to_bit_g2_cheap<CS: ConstraintSystem<P::Fp>>(
+        mut cs: CS,
+        pk: &G2Gadget<P>,
+    ) -> Result<Boolean, SynthesisError> {
+        // c0 only decides the bit if c1 is zero
+        let c1_is_zero = Self::is_eq_zero(&mut cs.ns(|| "c1 == 0"), &pk.y.c1)?;
+        let deciding = FpGadget::conditionally_select(
+            cs.ns(|| "select deciding coefficient"),
+            &c1_is_zero,
+            &pk.y.c0,
+            &pk.y.c1,
+        )?;
+        Self::normalize(&mut cs.ns(|| "normalize"), &deciding, true)
+    }
+
     pub fn y_to_bit_g2<CS: ConstraintSystem<P::Fp>>(
         mut cs: CS,
         pk: &G2Gadget<P>,
     ) -> Result<Boolean, SynthesisError> {
         // Apply the point compression logic for getting the y bit's value.
         let y_bit = Boolean::alloc(cs.ns(|| "alloc y bit"), || {
-            let half = P::Fp::from_repr(P::Fp::modulus_minus_one_div_two()).get()?;
-            let c1 = pk.y.c1.get_value().get()?;
-            let c0 = pk.y.c0.get_value().get()?;
-
-            let bit = c1 > half || (c1 == P::Fp::zero() && c0 > half);
-            Ok(bit)
+            Ok(PointCompression::fp2_y_bit(&pk.y.get_value().get()?))
         })?;
 
         // Get the y_c1 and y_c0 bits
-        let y_c0_bit = Self::normalize(&mut cs.ns(|| "normalize c0"), &pk.y.c0)?;
-        let y_c1_bit = Self::normalize(&mut cs.ns(|| "normalize c1"), &pk.y.c1)?;
+        let y_c0_bit = Self::normalize(&mut cs.ns(|| "normalize c0"), &pk.y.c0, false)?;
+        let y_c1_bit = Self::normalize(&mut cs.ns(|| "normalize c1"), &pk.y.c1, false)?;
 
         // (1-a)*(b*c) == o - a
         // a is c1
@@ -110,14 +138,18 @@ impl<P: Bls12Parameters> YToBitGadget<P> {
         Ok(bit)
     }
 
-    // Returns 1 if el > half, else 0.
+    // Returns 1 if el > half, else 0. The comparison with half is batched for the `_cheap`
+    // methods.
     fn normalize<CS: ConstraintSystem<P::Fp>>(
         cs: &mut CS,
         el: &FpGadget<P::Fp>,
+        batched: bool,
     ) -> Result<Boolean, SynthesisError> {
         let half = P::Fp::from_repr(P::Fp::modulus_minus_one_div_two()).get()?;
 
-        let bit = Boolean::alloc(cs.ns(|| "alloc y bit"), || Ok(el.get_value().get()? > half))?;
+        let bit = Boolean::alloc(cs.ns(|| "alloc y bit"), || {
+            Ok(PointCompression::fp_y_bit(&el.get_value().get()?))
+        })?;
 
         let adjusted = FpGadget::alloc(cs.ns(|| "alloc y"), || {
             let el = el.get_value().get()?;
@@ -136,10 +168,18 @@ impl<P: Bls12Parameters> YToBitGadget<P> {
         );
 
         // Enforce `adjusted <= half`
-        FpGadget::enforce_smaller_or_equal_than_mod_minus_one_div_two(
-            cs.ns(|| "enforce smaller than or equal to modulus minus one div two"),
+        let range = RangeCheck::new(
+            &mut cs.ns(|| "adjusted to bits"),
             &adjusted,
+            <P::Fp as PrimeField>::Params::CAPACITY as usize,
         )?;
+        let cs = &mut cs.ns(|| "enforce smaller than or equal to modulus minus one div two");
+        let half_repr = P::Fp::modulus_minus_one_div_two();
+        if batched {
+            range.enforce_at_most_constant_batched(cs, &half_repr)?;
+        } else {
+            range.enforce_at_most_constant(cs, &half_repr)?;
+        }
 
         Ok(bit)
     }
@@ -148,13 +188,14 @@ impl<P: Bls12Parameters> YToBitGadget<P> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::utils::test_helpers::assert_tampering_detected;
 
     use algebra::{
         bls12_377::{G1Projective, G2Affine, G2Projective, Parameters},
         bw6_761::Fr as BW6_761Fr,
         curves::bls12::Bls12Parameters,
         fields::Fp2,
-        AffineCurve, BigInteger, PrimeField, UniformRand, Zero,
+        AffineCurve, BigInteger, PrimeField, ProjectiveCurve, UniformRand, Zero,
     };
     use r1cs_std::{
         alloc::AllocGadget,
@@ -187,7 +228,7 @@ mod test {
                 y_bit.get_value().get().unwrap()
             );
 
-            assert_eq!(cs.num_constraints(), 1621);
+            assert_eq!(cs.num_constraints(), 758);
             if !cs.is_satisfied() {
                 println!("{}", cs.which_is_unsatisfied().unwrap());
             }
@@ -222,7 +263,7 @@ mod test {
                 assert_eq!(false, y_bit.get_value().unwrap());
             }
 
-            assert_eq!(cs.num_constraints(), 3248);
+            assert_eq!(cs.num_constraints(), 1522);
             if !cs.is_satisfied() {
                 println!("{}", cs.which_is_unsatisfied().unwrap());
             }
@@ -230,6 +271,103 @@ mod test {
         }
     }
 
+    #[test]
+    fn y_to_bit_cheap() {
+        let rng = &mut rand::thread_rng();
+
+        for _ in 0..10 {
+            let element = G1Projective::rand(rng);
+            let mut cs = TestConstraintSystem::<BW6_761Fr>::new();
+            let allocated =
+                G1Gadget::<Parameters>::alloc(&mut cs.ns(|| "alloc"), || Ok(element)).unwrap();
+            let y_bit =
+                YToBitGadget::<Parameters>::y_to_bit_g1_cheap(cs.ns(|| "y to bit"), &allocated)
+                    .unwrap();
+            assert_eq!(
+                y_bit.get_value(),
+                Some(PointCompression::point_bit(&element.into_affine()))
+            );
+            assert_eq!(cs.num_constraints(), 589);
+            assert!(cs.is_satisfied());
+
+            let element = G2Projective::rand(rng);
+            let mut cs = TestConstraintSystem::<BW6_761Fr>::new();
+            let allocated =
+                G2Gadget::<Parameters>::alloc(&mut cs.ns(|| "alloc"), || Ok(element)).unwrap();
+            let y_bit =
+                YToBitGadget::<Parameters>::y_to_bit_g2_cheap(cs.ns(|| "y to bit"), &allocated)
+                    .unwrap();
+            assert_eq!(
+                y_bit.get_value(),
+                Some(PointCompression::point_bit(&element.into_affine()))
+            );
+            assert_eq!(cs.num_constraints(), 597);
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn y_to_bit_g2_cheap_detects_tampering() {
+        let rng = &mut rand::thread_rng();
+        let element = G2Projective::rand(rng);
+
+        let mut cs = TestConstraintSystem::<BW6_761Fr>::new();
+        let allocated =
+            G2Gadget::<Parameters>::alloc(&mut cs.ns(|| "alloc"), || Ok(element)).unwrap();
+        YToBitGadget::<Parameters>::y_to_bit_g2_cheap(cs.ns(|| "y to bit"), &allocated).unwrap();
+
+        assert_tampering_detected(
+            &mut cs,
+            &[
+                "y to bit/c1 == 0/alloc bit/boolean",
+                "y to bit/normalize/alloc y bit/boolean",
+                "y to bit/normalize/alloc y/alloc",
+            ],
+        );
+    }
+
+    #[test]
+    fn y_to_bit_g1_detects_tampering() {
+        let rng = &mut rand::thread_rng();
+        let element = G1Projective::rand(rng);
+
+        let mut cs = TestConstraintSystem::<BW6_761Fr>::new();
+        let allocated =
+            G1Gadget::<Parameters>::alloc(&mut cs.ns(|| "alloc"), || Ok(element)).unwrap();
+        YToBitGadget::<Parameters>::y_to_bit_g1(cs.ns(|| "y to bit"), &allocated).unwrap();
+
+        assert_tampering_detected(
+            &mut cs,
+            &[
+                "y to bit/g1 normalize/alloc y bit/boolean",
+                "y to bit/g1 normalize/alloc y/alloc",
+            ],
+        );
+    }
+
+    #[test]
+    fn y_to_bit_g2_detects_tampering() {
+        let rng = &mut rand::thread_rng();
+        let element = G2Projective::rand(rng);
+
+        let mut cs = TestConstraintSystem::<BW6_761Fr>::new();
+        let allocated =
+            G2Gadget::<Parameters>::alloc(&mut cs.ns(|| "alloc"), || Ok(element)).unwrap();
+        YToBitGadget::<Parameters>::y_to_bit_g2(cs.ns(|| "y to bit"), &allocated).unwrap();
+
+        assert_tampering_detected(
+            &mut cs,
+            &[
+                "y to bit/alloc y bit/boolean",
+                "y to bit/normalize c0/alloc y bit/boolean",
+                "y to bit/normalize c0/alloc y/alloc",
+                "y to bit/normalize c1/alloc y bit/boolean",
+                "y to bit/normalize c1/alloc y/alloc",
+                "y to bit/c1 == 0/alloc bit/boolean",
+            ],
+        );
+    }
+
     fn test_y_to_bit_g2_edge(edge: <<Parameters as Bls12Parameters>::Fp as PrimeField>::BigInt) {
         let half = Fp::from_repr(Fp::modulus_minus_one_div_two()).unwrap();
         let zero = <Parameters as Bls12Parameters>::Fp::zero();
@@ -259,7 +397,13 @@ mod test {
                 assert_eq!(false, y_bit.get_value().unwrap());
             }
 
-            assert_eq!(cs.num_constraints(), 3248);
+            assert_eq!(cs.num_constraints(), 1522);
+            let cheap_bit = YToBitGadget::<Parameters>::y_to_bit_g2_cheap(
+                cs.ns(|| "cheap y to bit"),
+                &allocated,
+            )
+            .unwrap();
+            assert_eq!(cheap_bit.get_value(), y_bit.get_value());
             // we're not checking this, because we couldn't find a matching point on BLS12-377,
             // and so we can't generate proper points on the curve
             /*
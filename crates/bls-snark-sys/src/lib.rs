@@ -1,8 +1,15 @@
 //! FFI Bindings for BLS Signatures and SNARKs over the BLS12-377 Curve
+//!
+//! All the raw pointer accesses of the bindings are in the private `raw` module, whose
+//! `unsafe fn`s dereference the pointers received from callers. The other modules only call
+//! them in `unsafe` blocks, and declare as `unsafe extern` the functions which take ownership
+//! of handles. With the `forbid-unsafe-core` feature, bls-crypto and bls-gadgets are built
+//! with `#![forbid(unsafe_code)]`, which callers can check with `core_forbids_unsafe`.
 
 use bls_crypto::bls;
 
 type PublicKey = bls::PublicKey;
+type PreparedPublicKey = bls::PreparedPublicKey;
 type Signature = bls::Signature;
 type PrivateKey = bls::PrivateKey;
 type PublicKeyCache = bls::PublicKeyCache;
@@ -10,19 +17,33 @@ type PublicKeyCache = bls::PublicKeyCache;
 use bls_crypto::hash_to_curve::try_and_increment::{COMPOSITE_HASH_TO_G1, DIRECT_HASH_TO_G1};
 use core::fmt::Display;
 use once_cell::sync::Lazy;
+use std::panic::{self, AssertUnwindSafe};
 
 pub(crate) mod cache;
+pub mod observer;
+pub mod quorum;
+mod raw;
 pub mod serialization;
 pub mod signatures;
 pub mod snark;
+pub mod streaming;
 pub mod utils;
+pub mod verifier;
 
+/// Runs `f`, logging its error if it fails. Panics, e.g. those raised for null or misaligned
+/// pointers, are caught so that they do not unwind into the caller, and also return `false`.
 pub fn convert_result_to_bool<T, E: Display, F: Fn() -> Result<T, E>>(f: F) -> bool {
-    if let Err(e) = f() {
-        log::error!("SNARK library error: {}", e);
-        return false;
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(_)) => true,
+        Ok(Err(e)) => {
+            log::error!("SNARK library error: {}", e);
+            false
+        }
+        Err(_) => {
+            log::error!("SNARK library error: invalid arguments");
+            false
+        }
     }
-    true
 }
 
 #[no_mangle]
@@ -31,3 +52,10 @@ pub extern "C" fn init() {
     Lazy::force(&COMPOSITE_HASH_TO_G1);
     Lazy::force(&DIRECT_HASH_TO_G1);
 }
+
+#[no_mangle]
+/// Returns true if the library was built with the `forbid-unsafe-core` feature, i.e. if
+/// bls-crypto and bls-gadgets were compiled with `#![forbid(unsafe_code)]`.
+pub extern "C" fn core_forbids_unsafe() -> bool {
+    cfg!(feature = "forbid-unsafe-core")
+}
@@ -0,0 +1,114 @@
+//! Registration of a C callback notified of every signature, see `bls_crypto::observer`
+use crate::PublicKey;
+use bls_crypto::{clear_sign_observer, set_sign_observer, SignEvent};
+use std::{ffi::c_void, os::raw::c_int, ptr, sync::Arc, time::UNIX_EPOCH};
+
+/// Called after each signature with the 32 bytes digest of the signed message and extra
+/// data, the domain and its length (null and 0 for signatures of already hashed messages),
+/// the signer's public key, the time of the signature in milliseconds since the Unix epoch,
+/// and the context which was registered with the callback.
+///
+/// The pointers are only valid during the call. The callback is called on the signing
+/// thread, possibly from several threads at once.
+pub type SignCallback = extern "C" fn(
+    message_digest: *const u8,
+    domain: *const u8,
+    domain_len: c_int,
+    public_key: *const PublicKey,
+    timestamp_ms: u64,
+    context: *mut c_void,
+);
+
+#[no_mangle]
+/// Registers the callback notified of every signature produced by the library, replacing
+/// the previous one, or removes it if the callback is null. The context is passed back to
+/// the callback as is, and must stay valid until the callback is replaced or removed.
+pub extern "C" fn set_sign_callback(callback: Option<SignCallback>, context: *mut c_void) -> bool {
+    let callback = match callback {
+        Some(callback) => callback,
+        None => {
+            clear_sign_observer();
+            return true;
+        }
+    };
+    // stored as an integer, so that the observer can be shared between threads
+    let context = context as usize;
+    set_sign_observer(Arc::new(move |event: &SignEvent| {
+        let (domain, domain_len) = match event.domain {
+            Some(domain) => (domain.as_ptr(), domain.len() as c_int),
+            None => (ptr::null(), 0),
+        };
+        let timestamp_ms = event
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+        callback(
+            event.message_digest.as_ptr(),
+            domain,
+            domain_len,
+            event.public_key as *const PublicKey,
+            timestamp_ms,
+            context as *mut c_void,
+        )
+    }));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw, signatures::sign_message, PrivateKey, Signature};
+    use bls_crypto::observer::{message_digest, MESSAGE_DIGEST_LENGTH};
+    use std::sync::Mutex;
+
+    const MESSAGE: &[u8] = b"observed message";
+
+    extern "C" fn record(
+        digest: *const u8,
+        _domain: *const u8,
+        domain_len: c_int,
+        _public_key: *const PublicKey,
+        timestamp_ms: u64,
+        context: *mut c_void,
+    ) {
+        let digest = unsafe { raw::slice(digest, MESSAGE_DIGEST_LENGTH) };
+        // other tests sign concurrently, so only the signatures of the message are recorded
+        if digest == &message_digest(MESSAGE, &[])[..] {
+            let events = unsafe { raw::reference(context as *const Mutex<Vec<(c_int, u64)>>) };
+            events.lock().unwrap().push((domain_len, timestamp_ms));
+        }
+    }
+
+    #[test]
+    fn calls_the_sign_callback() {
+        let events = Mutex::new(Vec::<(c_int, u64)>::new());
+        let key = PrivateKey::generate(&mut rand::thread_rng());
+        let sign = || {
+            let mut signature = ptr::null_mut();
+            assert!(sign_message(
+                &key as *const PrivateKey,
+                MESSAGE.as_ptr(),
+                MESSAGE.len() as c_int,
+                ptr::null(),
+                0,
+                false,
+                &mut signature as *mut *mut Signature,
+            ));
+            unsafe { raw::take(signature) };
+        };
+
+        assert!(set_sign_callback(
+            Some(record),
+            &events as *const _ as *mut c_void
+        ));
+        sign();
+        assert!(set_sign_callback(None, ptr::null_mut()));
+        sign();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, bls_crypto::SIG_DOMAIN.len() as c_int);
+        assert!(events[0].1 > 0);
+    }
+}
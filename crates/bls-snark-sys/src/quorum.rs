@@ -0,0 +1,281 @@
+//! Istanbul's quorum rules and quorum certificates, see `bls_crypto::quorum`
+use crate::{
+    convert_result_to_bool, raw, PublicKey, Signature, COMPOSITE_HASH_TO_G1, DIRECT_HASH_TO_G1,
+};
+use bls_crypto::{envelope::Versioned, quorum, BLSError, QuorumCertificate};
+use std::os::raw::{c_int, c_uint};
+
+#[no_mangle]
+/// Writes the maximum number of the `in_num_validators` validators which may not sign a
+/// block, i.e. `floor(n / 3)`.
+pub extern "C" fn max_non_signers(
+    in_num_validators: c_uint,
+    out_max_non_signers: *mut c_uint,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let max_non_signers = quorum::max_non_signers(in_num_validators as usize);
+        unsafe { raw::write(out_max_non_signers, max_non_signers as c_uint) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Writes whether the validators set in the bitmap form a quorum of the
+/// `in_num_validators` validators. Fails if the bitmap's length is not the number of
+/// validators.
+pub extern "C" fn is_quorum(
+    in_bitmap: *const bool,
+    in_bitmap_len: c_int,
+    in_num_validators: c_uint,
+    out_is_quorum: *mut bool,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let bitmap = unsafe { raw::array(in_bitmap, in_bitmap_len) };
+        let is_quorum = quorum::is_quorum(bitmap, in_num_validators as usize)?;
+        unsafe { raw::write(out_is_quorum, is_quorum) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Creates the certificate of the seal of a message by the validators of an epoch. The
+/// handle must be freed with `destroy_quorum_certificate`.
+pub extern "C" fn new_quorum_certificate(
+    in_epoch_id: u64,
+    in_message: *const u8,
+    in_message_len: c_int,
+    in_bitmap: *const bool,
+    in_bitmap_len: c_int,
+    in_signature: *const Signature,
+    out_certificate: *mut *mut QuorumCertificate,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let certificate = QuorumCertificate {
+            epoch_id: in_epoch_id,
+            message: unsafe { raw::bytes(in_message, in_message_len) }.to_vec(),
+            bitmap: unsafe { raw::array(in_bitmap, in_bitmap_len) }.to_vec(),
+            aggregate_signature: unsafe { raw::reference(in_signature) }.clone(),
+        };
+        unsafe { raw::write_boxed(out_certificate, certificate) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Deserializes a certificate from its envelope, see
+/// `bls_crypto::envelope::QUORUM_CERTIFICATE_KIND`. The handle must be freed with
+/// `destroy_quorum_certificate`.
+pub extern "C" fn deserialize_quorum_certificate(
+    in_bytes: *const u8,
+    in_bytes_len: c_int,
+    out_certificate: *mut *mut QuorumCertificate,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let bytes = unsafe { raw::bytes(in_bytes, in_bytes_len) };
+        unsafe { raw::write_boxed(out_certificate, QuorumCertificate::from_envelope(bytes)?) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Serializes a certificate to its envelope. The bytes must be freed with `free_vec`.
+pub extern "C" fn serialize_quorum_certificate(
+    in_certificate: *const QuorumCertificate,
+    out_bytes: *mut *mut u8,
+    out_len: *mut c_int,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let bytes = unsafe { raw::reference(in_certificate) }.to_envelope()?;
+        unsafe { raw::write_bytes(out_bytes, out_len, bytes) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Writes the id of the epoch whose validators signed the certificate's message
+pub extern "C" fn quorum_certificate_epoch_id(
+    in_certificate: *const QuorumCertificate,
+    out_epoch_id: *mut u64,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        unsafe { raw::write(out_epoch_id, raw::reference(in_certificate).epoch_id) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Writes a copy of the certificate's message. The bytes must be freed with `free_vec`.
+pub extern "C" fn quorum_certificate_message(
+    in_certificate: *const QuorumCertificate,
+    out_message: *mut *mut u8,
+    out_message_len: *mut c_int,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let message = unsafe { raw::reference(in_certificate) }.message.clone();
+        unsafe { raw::write_bytes(out_message, out_message_len, message) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Writes the certificate's bitmap, one byte per validator which is 1 if the validator
+/// signed the message and 0 otherwise. The bytes must be freed with `free_vec`.
+pub extern "C" fn quorum_certificate_bitmap(
+    in_certificate: *const QuorumCertificate,
+    out_bitmap: *mut *mut u8,
+    out_bitmap_len: *mut c_int,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let bitmap = unsafe { raw::reference(in_certificate) }
+            .bitmap
+            .iter()
+            .map(|signed| *signed as u8)
+            .collect();
+        unsafe { raw::write_bytes(out_bitmap, out_bitmap_len, bitmap) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Writes a copy of the certificate's aggregate signature. The handle must be freed with
+/// `destroy_signature`.
+pub extern "C" fn quorum_certificate_signature(
+    in_certificate: *const QuorumCertificate,
+    out_signature: *mut *mut Signature,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let signature = unsafe { raw::reference(in_certificate) }
+            .aggregate_signature
+            .clone();
+        unsafe { raw::write_boxed(out_signature, signature) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Verifies the certificate against the validators of its epoch, see
+/// `QuorumCertificate::verify`. `out_verified` is false if the signers are not a quorum.
+pub extern "C" fn verify_quorum_certificate(
+    in_certificate: *const QuorumCertificate,
+    in_public_keys: *const *const PublicKey,
+    in_public_keys_len: c_int,
+    in_extra_data: *const u8,
+    in_extra_data_len: c_int,
+    should_use_composite: bool,
+    out_verified: *mut bool,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let certificate = unsafe { raw::reference(in_certificate) };
+        let public_keys = unsafe { raw::cloned(in_public_keys, in_public_keys_len) };
+        let extra_data = unsafe { raw::bytes(in_extra_data, in_extra_data_len) };
+        let verified = if should_use_composite {
+            certificate.verify(&public_keys, extra_data, &*COMPOSITE_HASH_TO_G1)
+        } else {
+            certificate.verify(&public_keys, extra_data, &*DIRECT_HASH_TO_G1)
+        };
+        unsafe { raw::write(out_verified, verified.is_ok()) };
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// This function must only be called on a valid QuorumCertificate instance pointer.
+#[no_mangle]
+pub unsafe extern "C" fn destroy_quorum_certificate(certificate: *mut QuorumCertificate) -> bool {
+    unsafe { raw::destroy(certificate) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrivateKey;
+
+    #[test]
+    fn computes_quorums() {
+        let mut non_signers = 0;
+        assert!(max_non_signers(100, &mut non_signers));
+        assert_eq!(non_signers, 33);
+
+        let mut result = false;
+        let bitmap = [true, true, false, false];
+        assert!(is_quorum(bitmap.as_ptr(), 4, 4, &mut result));
+        assert!(!result);
+        assert!(is_quorum(bitmap.as_ptr(), 3, 3, &mut result));
+        assert!(result);
+        // mismatched lengths
+        assert!(!is_quorum(bitmap.as_ptr(), 4, 5, &mut result));
+        // negative lengths
+        assert!(!is_quorum(bitmap.as_ptr(), -1, 4, &mut result));
+    }
+
+    #[test]
+    fn exposes_quorum_certificates() {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..4)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let public_keys = keys.iter().map(|k| k.to_public()).collect::<Vec<_>>();
+        let key_ptrs = public_keys
+            .iter()
+            .map(|k| k as *const PublicKey)
+            .collect::<Vec<_>>();
+        let (message, extra_data) = (b"block".to_vec(), b"extra".to_vec());
+        let bitmap = [true, true, true, false];
+        let signature = Signature::aggregate(
+            keys[..3]
+                .iter()
+                .map(|k| k.sign(&message, &extra_data, &*DIRECT_HASH_TO_G1).unwrap()),
+        );
+
+        let mut certificate = std::ptr::null_mut();
+        assert!(new_quorum_certificate(
+            7,
+            message.as_ptr(),
+            message.len() as c_int,
+            bitmap.as_ptr(),
+            bitmap.len() as c_int,
+            &signature,
+            &mut certificate,
+        ));
+        let mut verified = false;
+        assert!(verify_quorum_certificate(
+            certificate,
+            key_ptrs.as_ptr(),
+            key_ptrs.len() as c_int,
+            extra_data.as_ptr(),
+            extra_data.len() as c_int,
+            false,
+            &mut verified,
+        ));
+        assert!(verified);
+
+        let (mut bytes, mut len) = (std::ptr::null_mut(), 0);
+        assert!(serialize_quorum_certificate(
+            certificate,
+            &mut bytes,
+            &mut len
+        ));
+        let mut decoded = std::ptr::null_mut();
+        assert!(deserialize_quorum_certificate(bytes, len, &mut decoded));
+        assert!(unsafe { raw::free_bytes(bytes, len) });
+        let mut epoch_id = 0;
+        assert!(quorum_certificate_epoch_id(decoded, &mut epoch_id));
+        assert_eq!(epoch_id, 7);
+        assert!(quorum_certificate_bitmap(decoded, &mut bytes, &mut len));
+        assert_eq!(unsafe { raw::take_bytes(bytes, len) }, vec![1, 1, 1, 0]);
+        assert!(quorum_certificate_message(decoded, &mut bytes, &mut len));
+        assert_eq!(unsafe { raw::take_bytes(bytes, len) }, message);
+        let mut decoded_signature = std::ptr::null_mut();
+        assert!(quorum_certificate_signature(
+            decoded,
+            &mut decoded_signature
+        ));
+        assert_eq!(unsafe { raw::take(decoded_signature) }, signature);
+
+        unsafe {
+            assert!(destroy_quorum_certificate(certificate));
+            assert!(destroy_quorum_certificate(decoded));
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! All the raw pointer accesses of the bindings.
+//!
+//! The exported functions receive raw pointers from their callers, and this module is the
+//! only place where they are dereferenced. Its wrappers are `unsafe fn`s whose `# Safety`
+//! sections state what the pointers must satisfy, and the exported functions call them in
+//! `unsafe` blocks which rely on the contract of the C API: pointers are aligned and valid
+//! for the duration of the call, lengths do not exceed their buffers, and the handles passed
+//! to destructors were allocated by this library. The returned references must not outlive
+//! the call.
+//!
+//! The parts of the contract which can be checked are: null and misaligned pointers and
+//! negative lengths make the wrappers panic instead of dereferencing them, and
+//! `convert_result_to_bool` turns the panic into a `false` return value.
+#![allow(unsafe_code)]
+
+use std::{mem, os::raw::c_int, slice};
+use zeroize::Zeroize;
+
+/// Returns true if `ptr` is aligned for `T`. Null pointers are aligned.
+pub(crate) fn is_aligned<T>(ptr: *const T) -> bool {
+    ptr as usize % mem::align_of::<T>() == 0
+}
+
+fn check<T>(ptr: *const T) {
+    assert!(!ptr.is_null(), "null pointer");
+    assert!(is_aligned(ptr), "misaligned pointer");
+}
+
+/// Converts a length received from a caller, panicking if it is negative
+fn length(len: c_int) -> usize {
+    assert!(len >= 0, "negative length");
+    len as usize
+}
+
+/// Returns the `len` elements starting at `ptr`. An empty slice is returned for a zero
+/// length without reading the pointer, which may then be null.
+///
+/// # Safety
+///
+/// If `len` is not zero, `ptr` must point to `len` initialized elements, which are not
+/// mutated while the slice is alive.
+pub(crate) unsafe fn slice<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    if len == 0 {
+        return &[];
+    }
+    check(ptr);
+    slice::from_raw_parts(ptr, len)
+}
+
+/// Returns the `len` bytes starting at `ptr`, for buffers whose length is a C int. Panics if
+/// `len` is negative.
+///
+/// # Safety
+///
+/// See `slice`.
+pub(crate) unsafe fn bytes<'a>(ptr: *const u8, len: c_int) -> &'a [u8] {
+    array(ptr, len)
+}
+
+/// Returns the `len` elements starting at `ptr`, for arrays whose length is a C int. Panics
+/// if `len` is negative.
+///
+/// # Safety
+///
+/// See `slice`.
+pub(crate) unsafe fn array<'a, T>(ptr: *const T, len: c_int) -> &'a [T] {
+    slice(ptr, length(len))
+}
+
+/// Returns the value `ptr` points to
+///
+/// # Safety
+///
+/// `ptr` must be null or point to an initialized value, which is not mutated while the
+/// reference is alive.
+pub(crate) unsafe fn reference<'a, T>(ptr: *const T) -> &'a T {
+    check(ptr);
+    &*ptr
+}
+
+/// Returns the value `ptr` points to, or `None` if it is null
+///
+/// # Safety
+///
+/// See `reference`.
+pub(crate) unsafe fn optional<'a, T>(ptr: *const T) -> Option<&'a T> {
+    assert!(is_aligned(ptr), "misaligned pointer");
+    ptr.as_ref()
+}
+
+/// Returns the value `ptr` points to, for mutation
+///
+/// # Safety
+///
+/// `ptr` must be null or point to an initialized value, which is not accessed through any
+/// other pointer while the reference is alive.
+pub(crate) unsafe fn mutable<'a, T>(ptr: *mut T) -> &'a mut T {
+    check(ptr);
+    &mut *ptr
+}
+
+/// Clones the values pointed to by the `len` pointers starting at `ptrs`. Panics if `len` is
+/// negative.
+///
+/// # Safety
+///
+/// `ptrs` must satisfy the requirements of `slice`, and each of the pointers those of
+/// `reference`.
+pub(crate) unsafe fn cloned<T: Clone>(ptrs: *const *const T, len: c_int) -> Vec<T> {
+    slice(ptrs, length(len))
+        .iter()
+        .map(|ptr| reference(*ptr).clone())
+        .collect()
+}
+
+/// Writes `value` to the out parameter `out`. The previous value is not dropped.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+pub(crate) unsafe fn write<T>(out: *mut T, value: T) {
+    check(out);
+    out.write(value)
+}
+
+/// Moves `value` to the heap and writes the handle to `out`. The handle must be freed with
+/// `destroy`.
+///
+/// # Safety
+///
+/// See `write`.
+pub(crate) unsafe fn write_boxed<T>(out: *mut *mut T, value: T) {
+    // checked before boxing, so that the value is not leaked
+    check(out);
+    write(out, Box::into_raw(Box::new(value)))
+}
+
+/// Hands the bytes over to the caller, writing the pointer to `out_bytes` and the length to
+/// `out_len`. The bytes must be freed with `free_bytes`.
+///
+/// # Safety
+///
+/// `out_bytes` and `out_len` must satisfy the requirements of `write`.
+pub(crate) unsafe fn write_bytes(out_bytes: *mut *mut u8, out_len: *mut c_int, mut bytes: Vec<u8>) {
+    // `free_bytes` reconstructs the vector with a capacity equal to its length
+    bytes.shrink_to_fit();
+    write(out_len, bytes.len() as c_int);
+    write(out_bytes, bytes.as_mut_ptr());
+    std::mem::forget(bytes);
+}
+
+/// Takes back ownership of a handle produced by `write_boxed`
+///
+/// # Safety
+///
+/// `ptr` must be a handle produced by `write_boxed` for a `T`, which has not been taken or
+/// destroyed yet.
+pub(crate) unsafe fn take<T>(ptr: *mut T) -> T {
+    *Box::from_raw(ptr)
+}
+
+/// Frees a handle produced by `write_boxed`. Returns false if the handle is null or
+/// misaligned.
+///
+/// # Safety
+///
+/// `ptr` must be null, misaligned, or satisfy the requirements of `take`.
+pub(crate) unsafe fn destroy<T>(ptr: *mut T) -> bool {
+    if ptr.is_null() || !is_aligned(ptr) {
+        return false;
+    }
+    drop(take(ptr));
+    true
+}
+
+/// Takes back ownership of bytes handed over with `write_bytes`. Panics if `len` is
+/// negative.
+///
+/// # Safety
+///
+/// `ptr` and `len` must be the pointer and length written by `write_bytes`, whose bytes
+/// have not been taken or freed yet.
+pub(crate) unsafe fn take_bytes(ptr: *mut u8, len: c_int) -> Vec<u8> {
+    let len = length(len);
+    Vec::from_raw_parts(ptr, len, len)
+}
+
+/// Frees bytes handed over with `write_bytes`. Returns false if the pointer is null or the
+/// length negative. The bytes are wiped first, as they may hold a serialized private key.
+///
+/// # Safety
+///
+/// `ptr` must be null, or satisfy the requirements of `take_bytes` with `len`.
+pub(crate) unsafe fn free_bytes(ptr: *mut u8, len: c_int) -> bool {
+    if ptr.is_null() || len < 0 {
+        return false;
+    }
+    take_bytes(ptr, len).zeroize();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_slices_ignore_the_pointer() {
+        let empty: &[u64] = unsafe { slice(std::ptr::null(), 0) };
+        assert!(empty.is_empty());
+        assert!(unsafe { bytes(std::ptr::null(), 0) }.is_empty());
+    }
+
+    #[test]
+    fn rejects_null_and_misaligned_pointers() {
+        let values = [1u64, 2];
+        let misaligned = (values.as_ptr() as usize + 1) as *const u64;
+        assert!(!is_aligned(misaligned));
+        assert!(std::panic::catch_unwind(|| unsafe { reference(misaligned) }).is_err());
+        assert!(std::panic::catch_unwind(|| unsafe { slice(misaligned, 1) }).is_err());
+        assert!(
+            std::panic::catch_unwind(|| unsafe { reference::<u64>(std::ptr::null()) }).is_err()
+        );
+        assert!(
+            std::panic::catch_unwind(|| unsafe { write::<u64>(std::ptr::null_mut(), 1) }).is_err()
+        );
+        assert_eq!(unsafe { optional::<u64>(std::ptr::null()) }, None);
+        assert!(!unsafe { destroy(misaligned as *mut u64) });
+    }
+
+    #[test]
+    fn rejects_negative_lengths() {
+        let values = [1u8, 2];
+        let ptrs = [&values[0] as *const u8];
+        assert!(std::panic::catch_unwind(|| unsafe { bytes(values.as_ptr(), -1) }).is_err());
+        assert!(std::panic::catch_unwind(|| unsafe { cloned(ptrs.as_ptr(), -1) }).is_err());
+        assert!(
+            std::panic::catch_unwind(|| unsafe { take_bytes(values.as_ptr() as *mut u8, -1) })
+                .is_err()
+        );
+        assert!(!unsafe { free_bytes(values.as_ptr() as *mut u8, -1) });
+    }
+
+    #[test]
+    fn handles_roundtrip() {
+        let mut handle: *mut Vec<u8> = std::ptr::null_mut();
+        unsafe {
+            write_boxed(&mut handle as *mut *mut Vec<u8>, vec![1, 2, 3]);
+            assert_eq!(reference(handle), &vec![1, 2, 3]);
+            mutable(handle).push(4);
+            assert_eq!(optional(handle as *const Vec<u8>), Some(&vec![1, 2, 3, 4]));
+            assert!(destroy(handle));
+            assert!(!destroy::<Vec<u8>>(std::ptr::null_mut()));
+        }
+
+        let mut ptr: *mut u8 = std::ptr::null_mut();
+        let mut len: c_int = 0;
+        unsafe {
+            write_bytes(&mut ptr, &mut len, vec![5, 6]);
+            assert_eq!(take_bytes(ptr, len), vec![5, 6]);
+        }
+    }
+}
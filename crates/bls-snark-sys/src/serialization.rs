@@ -1,11 +1,11 @@
-use super::{convert_result_to_bool, PrivateKey, PublicKey, Signature};
+use super::{convert_result_to_bool, raw, PreparedPublicKey, PrivateKey, PublicKey, Signature};
 use crate::cache::PUBLIC_KEY_CACHE;
 use algebra::{
     bls12_377::{Fq, Fq2, G1Affine, G2Affine},
     AffineCurve, CanonicalDeserialize, CanonicalSerialize, FromBytes,
 };
-use bls_crypto::BLSError;
-use std::{os::raw::c_int, slice};
+use bls_crypto::{BLSError, ProfileSerialize, SerializationProfile, ValidationLevel};
+use std::{convert::TryFrom, os::raw::c_int};
 
 // Serialization & deserialization
 
@@ -32,12 +32,19 @@ pub extern "C" fn serialize_private_key(
 }
 
 #[no_mangle]
+/// Deserializes a compressed or uncompressed public key, checking that it is on the curve and
+/// in the prime order subgroup
 pub extern "C" fn deserialize_public_key(
     in_public_key_bytes: *const u8,
     in_public_key_bytes_len: c_int,
     out_public_key: *mut *mut PublicKey,
 ) -> bool {
-    deserialize(in_public_key_bytes, in_public_key_bytes_len, out_public_key)
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let bytes = unsafe { raw::bytes(in_public_key_bytes, in_public_key_bytes_len) };
+        unsafe { raw::write_boxed(out_public_key, PublicKey::from_bytes(bytes)?) };
+
+        Ok(())
+    })
 }
 
 #[no_mangle]
@@ -47,13 +54,55 @@ pub extern "C" fn deserialize_public_key_cached(
     out_public_key: *mut *mut PublicKey,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let mut cache = PUBLIC_KEY_CACHE.lock().expect("mutex poisoned");
-        let bytes =
-            unsafe { slice::from_raw_parts(in_public_key_bytes, in_public_key_bytes_len as usize) };
-        let key = cache.deserialize(bytes.to_vec())?;
-        unsafe {
-            *out_public_key = Box::into_raw(Box::new(key));
-        }
+        let bytes = unsafe { raw::bytes(in_public_key_bytes, in_public_key_bytes_len) };
+        let key = PUBLIC_KEY_CACHE
+            .lock()
+            .expect("mutex poisoned")
+            .deserialize(bytes)?;
+        unsafe { raw::write_boxed(out_public_key, key) };
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Deserializes a compressed or uncompressed public key, checking it at the validation level
+/// `in_validation_level`: 0 for no checks, 1 for the curve equation and 2 for the curve
+/// equation and subgroup membership. `deserialize_public_key` always does all the checks.
+pub extern "C" fn deserialize_public_key_with_validation(
+    in_public_key_bytes: *const u8,
+    in_public_key_bytes_len: c_int,
+    in_validation_level: c_int,
+    out_public_key: *mut *mut PublicKey,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let level = ValidationLevel::try_from(in_validation_level)?;
+        let bytes = unsafe { raw::bytes(in_public_key_bytes, in_public_key_bytes_len) };
+        let key = PublicKey::from_bytes_with_validation(bytes, level)?;
+        unsafe { raw::write_boxed(out_public_key, key) };
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Like `deserialize_public_key_cached`, checking the public key at the validation level
+/// `in_validation_level` (see `deserialize_public_key_with_validation`) when it is not
+/// cached. Only keys checked at level 2 are added to the cache.
+pub extern "C" fn deserialize_public_key_cached_with_validation(
+    in_public_key_bytes: *const u8,
+    in_public_key_bytes_len: c_int,
+    in_validation_level: c_int,
+    out_public_key: *mut *mut PublicKey,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let level = ValidationLevel::try_from(in_validation_level)?;
+        let bytes = unsafe { raw::bytes(in_public_key_bytes, in_public_key_bytes_len) };
+        let key = PUBLIC_KEY_CACHE
+            .lock()
+            .expect("mutex poisoned")
+            .deserialize_with_validation(bytes, level)?;
+        unsafe { raw::write_boxed(out_public_key, key) };
 
         Ok(())
     })
@@ -69,12 +118,38 @@ pub extern "C" fn serialize_public_key(
 }
 
 #[no_mangle]
+/// Deserializes a compressed or uncompressed signature, checking that it is on the curve and
+/// in the prime order subgroup
 pub extern "C" fn deserialize_signature(
     in_signature_bytes: *const u8,
     in_signature_bytes_len: c_int,
     out_signature: *mut *mut Signature,
 ) -> bool {
-    deserialize(in_signature_bytes, in_signature_bytes_len, out_signature)
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let bytes = unsafe { raw::bytes(in_signature_bytes, in_signature_bytes_len) };
+        unsafe { raw::write_boxed(out_signature, Signature::from_bytes(bytes)?) };
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Deserializes a compressed or uncompressed signature, checking it at the validation level
+/// `in_validation_level` (see `deserialize_public_key_with_validation`)
+pub extern "C" fn deserialize_signature_with_validation(
+    in_signature_bytes: *const u8,
+    in_signature_bytes_len: c_int,
+    in_validation_level: c_int,
+    out_signature: *mut *mut Signature,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let level = ValidationLevel::try_from(in_validation_level)?;
+        let bytes = unsafe { raw::bytes(in_signature_bytes, in_signature_bytes_len) };
+        let signature = Signature::from_bytes_with_validation(bytes, level)?;
+        unsafe { raw::write_boxed(out_signature, signature) };
+
+        Ok(())
+    })
 }
 
 #[no_mangle]
@@ -86,17 +161,107 @@ pub extern "C" fn serialize_signature(
     serialize(in_signature, out_bytes, out_len)
 }
 
+#[no_mangle]
+/// Deserializes a public key encoded with the serialization profile `in_profile`: 0 for this
+/// crate's encoding, as read by `deserialize_public_key`, 1 for arkworks' compressed encoding,
+/// 2 for the uncompressed EIP-2539 encoding, 3 and 4 for the compressed and uncompressed
+/// explicit flags encoding, and 5 for the standard compressed encoding of Zcash and EIP-2537
+/// tooling. The key is checked to be in the prime order subgroup.
+pub extern "C" fn deserialize_public_key_with_profile(
+    in_public_key_bytes: *const u8,
+    in_public_key_bytes_len: c_int,
+    in_profile: c_int,
+    out_public_key: *mut *mut PublicKey,
+) -> bool {
+    deserialize_with_profile(
+        in_public_key_bytes,
+        in_public_key_bytes_len,
+        in_profile,
+        out_public_key,
+    )
+}
+
+#[no_mangle]
+/// Serializes a public key with the serialization profile `in_profile` (see
+/// `deserialize_public_key_with_profile`)
+pub extern "C" fn serialize_public_key_with_profile(
+    in_public_key: *const PublicKey,
+    in_profile: c_int,
+    out_bytes: *mut *mut u8,
+    out_len: *mut c_int,
+) -> bool {
+    serialize_with_profile(in_public_key, in_profile, out_bytes, out_len)
+}
+
+#[no_mangle]
+/// Deserializes a signature encoded with the serialization profile `in_profile` (see
+/// `deserialize_public_key_with_profile`). The signature is checked to be in the prime order
+/// subgroup.
+pub extern "C" fn deserialize_signature_with_profile(
+    in_signature_bytes: *const u8,
+    in_signature_bytes_len: c_int,
+    in_profile: c_int,
+    out_signature: *mut *mut Signature,
+) -> bool {
+    deserialize_with_profile(
+        in_signature_bytes,
+        in_signature_bytes_len,
+        in_profile,
+        out_signature,
+    )
+}
+
+#[no_mangle]
+/// Serializes a signature with the serialization profile `in_profile` (see
+/// `deserialize_public_key_with_profile`)
+pub extern "C" fn serialize_signature_with_profile(
+    in_signature: *const Signature,
+    in_profile: c_int,
+    out_bytes: *mut *mut u8,
+    out_len: *mut c_int,
+) -> bool {
+    serialize_with_profile(in_signature, in_profile, out_bytes, out_len)
+}
+
+fn deserialize_with_profile<T: ProfileSerialize>(
+    in_bytes: *const u8,
+    in_bytes_len: c_int,
+    in_profile: c_int,
+    out: *mut *mut T,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let profile = SerializationProfile::try_from(in_profile)?;
+        let bytes = unsafe { raw::bytes(in_bytes, in_bytes_len) };
+        unsafe { raw::write_boxed(out, T::deserialize_with_profile(bytes, profile)?) };
+
+        Ok(())
+    })
+}
+
+fn serialize_with_profile<T: ProfileSerialize>(
+    in_obj: *const T,
+    in_profile: c_int,
+    out_bytes: *mut *mut u8,
+    out_len: *mut c_int,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let profile = SerializationProfile::try_from(in_profile)?;
+        let bytes = unsafe { raw::reference(in_obj) }.serialize_with_profile(profile)?;
+        unsafe { raw::write_bytes(out_bytes, out_len, bytes) };
+
+        Ok(())
+    })
+}
+
 fn deserialize<T: CanonicalDeserialize>(
     in_bytes: *const u8,
     in_bytes_len: c_int,
     out: *mut *mut T,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let bytes = unsafe { slice::from_raw_parts(in_bytes, in_bytes_len as usize) };
+        let bytes = unsafe { raw::bytes(in_bytes, in_bytes_len) };
         let key = T::deserialize(&mut &bytes[..])?;
-        unsafe {
-            *out = Box::into_raw(Box::new(key));
-        }
+        unsafe { raw::write_boxed(out, key) };
 
         Ok(())
     })
@@ -108,15 +273,11 @@ fn serialize<T: CanonicalSerialize>(
     out_len: *mut c_int,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let obj = unsafe { &*in_obj };
-        let mut obj_bytes = vec![];
+        let obj = unsafe { raw::reference(in_obj) };
+        // allocated at its final size, so that growing it leaves no copies of private keys
+        let mut obj_bytes = Vec::with_capacity(obj.serialized_size());
         obj.serialize(&mut obj_bytes)?;
-        obj_bytes.shrink_to_fit();
-        unsafe {
-            *out_bytes = obj_bytes.as_mut_ptr();
-            *out_len = obj_bytes.len() as c_int;
-        }
-        std::mem::forget(obj_bytes);
+        unsafe { raw::write_bytes(out_bytes, out_len, obj_bytes) };
 
         Ok(())
     })
@@ -132,19 +293,14 @@ pub extern "C" fn compress_signature(
     out_len: *mut c_int,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let signature = unsafe { slice::from_raw_parts(in_signature, in_signature_len as usize) };
+        let signature = unsafe { raw::bytes(in_signature, in_signature_len) };
         let x = Fq::read(&signature[0..48]).unwrap();
         let y = Fq::read(&signature[48..96]).unwrap();
         let affine = G1Affine::new(x, y, false);
         let sig = Signature::from(affine.into_projective());
         let mut obj_bytes = vec![];
         sig.serialize(&mut obj_bytes)?;
-        obj_bytes.shrink_to_fit();
-        unsafe {
-            *out_signature = obj_bytes.as_mut_ptr();
-            *out_len = obj_bytes.len() as c_int;
-        }
-        std::mem::forget(obj_bytes);
+        unsafe { raw::write_bytes(out_signature, out_len, obj_bytes) };
         Ok(())
     })
 }
@@ -157,7 +313,7 @@ pub extern "C" fn compress_pubkey(
     out_len: *mut c_int,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let pubkey = unsafe { slice::from_raw_parts(in_pubkey, in_pubkey_len as usize) };
+        let pubkey = unsafe { raw::bytes(in_pubkey, in_pubkey_len) };
         let x = Fq2::read(&pubkey[0..96]).unwrap();
         let y = Fq2::read(&pubkey[96..192]).unwrap();
         let affine = G2Affine::new(x, y, false);
@@ -165,13 +321,7 @@ pub extern "C" fn compress_pubkey(
 
         let mut obj_bytes = vec![];
         pk.serialize(&mut obj_bytes)?;
-        obj_bytes.shrink_to_fit();
-
-        unsafe {
-            *out_pubkey = obj_bytes.as_mut_ptr();
-            *out_len = obj_bytes.len() as c_int;
-        }
-        std::mem::forget(obj_bytes);
+        unsafe { raw::write_bytes(out_pubkey, out_len, obj_bytes) };
         Ok(())
     })
 }
@@ -183,11 +333,7 @@ pub extern "C" fn compress_pubkey(
 /// This function must only be called on a valid PrivateKey instance pointer.
 #[no_mangle]
 pub unsafe extern "C" fn destroy_private_key(private_key: *mut PrivateKey) -> bool {
-    if private_key.is_null() {
-        return false;
-    }
-    Box::from_raw(private_key);
-    true
+    unsafe { raw::destroy(private_key) }
 }
 
 /// # Safety
@@ -195,11 +341,7 @@ pub unsafe extern "C" fn destroy_private_key(private_key: *mut PrivateKey) -> bo
 /// This function must only be called on a valid vector pointer.
 #[no_mangle]
 pub unsafe extern "C" fn free_vec(bytes: *mut u8, len: c_int) -> bool {
-    if bytes.is_null() {
-        return false;
-    }
-    Vec::from_raw_parts(bytes, len as usize, len as usize);
-    true
+    unsafe { raw::free_bytes(bytes, len) }
 }
 
 /// # Safety
@@ -207,11 +349,17 @@ pub unsafe extern "C" fn free_vec(bytes: *mut u8, len: c_int) -> bool {
 /// This function must only be called on a valid PublicKey instance pointer.
 #[no_mangle]
 pub unsafe extern "C" fn destroy_public_key(public_key: *mut PublicKey) -> bool {
-    if public_key.is_null() {
-        return false;
-    }
-    Box::from_raw(public_key);
-    true
+    unsafe { raw::destroy(public_key) }
+}
+
+/// # Safety
+///
+/// This function must only be called on a valid PreparedPublicKey instance pointer.
+#[no_mangle]
+pub unsafe extern "C" fn destroy_prepared_public_key(
+    prepared_public_key: *mut PreparedPublicKey,
+) -> bool {
+    unsafe { raw::destroy(prepared_public_key) }
 }
 
 /// # Safety
@@ -219,9 +367,76 @@ pub unsafe extern "C" fn destroy_public_key(public_key: *mut PublicKey) -> bool
 /// This function must only be called on a valid Signature instance pointer.
 #[no_mangle]
 pub unsafe extern "C" fn destroy_signature(signature: *mut Signature) -> bool {
-    if signature.is_null() {
-        return false;
+    unsafe { raw::destroy(signature) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G2Projective, UniformRand};
+
+    fn deserialize_ffi_public_key(bytes: &[u8]) -> Option<PublicKey> {
+        let mut key = std::ptr::null_mut();
+        if deserialize_public_key(bytes.as_ptr(), bytes.len() as c_int, &mut key) {
+            Some(unsafe { raw::take(key) })
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn deserialization_checks_the_subgroup() {
+        let rng = &mut rand::thread_rng();
+        let key = PublicKey::from(G2Projective::rand(rng));
+        let mut bytes = vec![];
+        key.serialize(&mut bytes).unwrap();
+        assert_eq!(deserialize_ffi_public_key(&bytes), Some(key));
+
+        let outside = loop {
+            if let Some(point) = G2Affine::get_point_from_x(Fq2::rand(rng), false) {
+                if PublicKey::from_bytes(&compressed(&point)).is_err() {
+                    break point;
+                }
+            }
+        };
+        assert_eq!(deserialize_ffi_public_key(&compressed(&outside)), None);
+    }
+
+    fn compressed(point: &G2Affine) -> Vec<u8> {
+        let mut bytes = vec![];
+        point.serialize(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn serializes_with_the_standard_profile() {
+        let rng = &mut rand::thread_rng();
+        let key = PublicKey::from(G2Projective::rand(rng));
+        let standard = i32::from(SerializationProfile::StandardCompressed);
+
+        let mut bytes = std::ptr::null_mut();
+        let mut len = 0;
+        assert!(serialize_public_key_with_profile(
+            &key, standard, &mut bytes, &mut len
+        ));
+        let encoded = unsafe { raw::take_bytes(bytes, len) };
+        assert_eq!(encoded, key.to_compressed_standard());
+
+        let mut decoded = std::ptr::null_mut();
+        assert!(deserialize_public_key_with_profile(
+            encoded.as_ptr(),
+            encoded.len() as c_int,
+            standard,
+            &mut decoded
+        ));
+        assert_eq!(unsafe { raw::take(decoded) }, key);
+
+        // unknown profiles are rejected
+        assert!(!deserialize_public_key_with_profile(
+            encoded.as_ptr(),
+            encoded.len() as c_int,
+            6,
+            &mut decoded
+        ));
     }
-    Box::from_raw(signature);
-    true
 }
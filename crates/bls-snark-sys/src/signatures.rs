@@ -1,23 +1,59 @@
 use crate::{
     cache::PUBLIC_KEY_CACHE,
-    convert_result_to_bool,
-    utils::{Message, MessageFFI},
-    PrivateKey, PublicKey, Signature, COMPOSITE_HASH_TO_G1, DIRECT_HASH_TO_G1,
+    convert_result_to_bool, raw,
+    utils::{Message, MessageFFI, MessageFFIError, MessageV2, MessageV2FFI, Pop, PopFFI},
+    PreparedPublicKey, PrivateKey, PublicKey, Signature, COMPOSITE_HASH_TO_G1, DIRECT_HASH_TO_G1,
+};
+use algebra::{
+    bls12_377::{G1Projective, Parameters},
+    curves::models::bls12::Bls12Parameters,
+    ProjectiveCurve, ToBytes,
+};
+use bls_crypto::{
+    bls::{BlsScheme, Celo},
+    hash_to_curve::try_and_increment::TryAndIncrement,
+    hashers::Hasher,
+    BLSError, HashToCurve, POP_DOMAIN,
+};
+use std::{
+    borrow::Cow,
+    convert::TryFrom,
+    fmt::{self, Display},
+    os::raw::c_int,
 };
-use algebra::{ProjectiveCurve, ToBytes};
-use bls_crypto::{BLSError, HashToCurve, POP_DOMAIN, SIG_DOMAIN};
-use std::{os::raw::c_int, slice};
 
 /// # Safety
 ///
 /// out_private_key must initialized to memory that can contain a pointer.
 #[no_mangle]
 pub unsafe extern "C" fn generate_private_key(out_private_key: *mut *mut PrivateKey) -> bool {
-    let mut rng = rand::thread_rng();
-    let key = PrivateKey::generate(&mut rng);
-    *out_private_key = Box::into_raw(Box::new(key));
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let mut rng = rand::thread_rng();
+        let key = PrivateKey::generate(&mut rng);
+        unsafe { raw::write_boxed(out_private_key, key) };
 
-    true
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Derives a private key from a seed of at least 32 bytes and a context string, see
+/// `PrivateKey::from_seed`. The same seed and context always derive the same key.
+pub extern "C" fn private_key_from_seed(
+    in_seed: *const u8,
+    in_seed_len: c_int,
+    in_info: *const u8,
+    in_info_len: c_int,
+    out_private_key: *mut *mut PrivateKey,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let seed = unsafe { raw::bytes(in_seed, in_seed_len) };
+        let info = unsafe { raw::bytes(in_info, in_info_len) };
+        let key = PrivateKey::from_seed(seed, info)?;
+        unsafe { raw::write_boxed(out_private_key, key) };
+
+        Ok(())
+    })
 }
 
 #[no_mangle]
@@ -26,11 +62,9 @@ pub extern "C" fn private_key_to_public_key(
     out_public_key: *mut *mut PublicKey,
 ) -> bool {
     convert_result_to_bool::<_, std::io::Error, _>(|| {
-        let private_key = unsafe { &*in_private_key };
+        let private_key = unsafe { raw::reference(in_private_key) };
         let public_key = private_key.to_public();
-        unsafe {
-            *out_public_key = Box::into_raw(Box::new(public_key));
-        }
+        unsafe { raw::write_boxed(out_public_key, public_key) };
 
         Ok(())
     })
@@ -47,24 +81,23 @@ pub extern "C" fn sign_message(
     out_signature: *mut *mut Signature,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let private_key = unsafe { &*in_private_key };
-        let message = unsafe { slice::from_raw_parts(in_message, in_message_len as usize) };
-        let extra_data =
-            unsafe { slice::from_raw_parts(in_extra_data, in_extra_data_len as usize) };
+        let private_key = unsafe { raw::reference(in_private_key) };
+        let message = unsafe { raw::bytes(in_message, in_message_len) };
+        let extra_data = unsafe { raw::bytes(in_extra_data, in_extra_data_len) };
         let signature = if should_use_composite {
-            private_key.sign(message, extra_data, &*COMPOSITE_HASH_TO_G1)?
+            Celo::sign_with_extra_data(private_key, message, extra_data, &*COMPOSITE_HASH_TO_G1)?
         } else {
-            private_key.sign(message, extra_data, &*DIRECT_HASH_TO_G1)?
+            Celo::sign_with_extra_data(private_key, message, extra_data, &*DIRECT_HASH_TO_G1)?
         };
-        unsafe {
-            *out_signature = Box::into_raw(Box::new(signature));
-        }
+        unsafe { raw::write_boxed(out_signature, signature) };
 
         Ok(())
     })
 }
 
 #[no_mangle]
+/// Signs a proof of possession of the private key over the message, which for validator
+/// registration is the validator's address
 pub extern "C" fn sign_pop(
     in_private_key: *const PrivateKey,
     in_message: *const u8,
@@ -72,12 +105,26 @@ pub extern "C" fn sign_pop(
     out_signature: *mut *mut Signature,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let private_key = unsafe { &*in_private_key };
-        let message = unsafe { slice::from_raw_parts(in_message, in_message_len as usize) };
+        let private_key = unsafe { raw::reference(in_private_key) };
+        let message = unsafe { raw::bytes(in_message, in_message_len) };
         let signature = private_key.sign_pop(message, &*DIRECT_HASH_TO_G1)?;
-        unsafe {
-            *out_signature = Box::into_raw(Box::new(signature));
-        }
+        unsafe { raw::write_boxed(out_signature, signature) };
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Signs a proof of possession of the private key over its own compressed public key, which
+/// `aggregate_public_keys_with_pop_check` requires
+pub extern "C" fn sign_key_pop(
+    in_private_key: *const PrivateKey,
+    out_signature: *mut *mut Signature,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let private_key = unsafe { raw::reference(in_private_key) };
+        let signature = private_key.sign_key_pop(&*DIRECT_HASH_TO_G1)?;
+        unsafe { raw::write_boxed(out_signature, signature) };
 
         Ok(())
     })
@@ -92,17 +139,12 @@ pub extern "C" fn hash_direct(
     use_pop: bool,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let message = unsafe { slice::from_raw_parts(in_message, in_message_len as usize) };
-        let domain = if use_pop { POP_DOMAIN } else { SIG_DOMAIN };
+        let message = unsafe { raw::bytes(in_message, in_message_len) };
+        let domain = if use_pop { POP_DOMAIN } else { Celo::DOMAIN };
         let hash = DIRECT_HASH_TO_G1.hash(domain, message, &[])?;
         let mut obj_bytes = vec![];
         hash.into_affine().write(&mut obj_bytes)?;
-        obj_bytes.shrink_to_fit();
-        unsafe {
-            *out_hash = obj_bytes.as_mut_ptr();
-            *out_len = obj_bytes.len() as c_int;
-        }
-        std::mem::forget(obj_bytes);
+        unsafe { raw::write_bytes(out_hash, out_len, obj_bytes) };
         Ok(())
     })
 }
@@ -117,18 +159,12 @@ pub extern "C" fn hash_composite(
     out_len: *mut c_int,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let message = unsafe { slice::from_raw_parts(in_message, in_message_len as usize) };
-        let extra_data =
-            unsafe { slice::from_raw_parts(in_extra_data, in_extra_data_len as usize) };
-        let hash = COMPOSITE_HASH_TO_G1.hash(SIG_DOMAIN, message, extra_data)?;
+        let message = unsafe { raw::bytes(in_message, in_message_len) };
+        let extra_data = unsafe { raw::bytes(in_extra_data, in_extra_data_len) };
+        let hash = COMPOSITE_HASH_TO_G1.hash(Celo::DOMAIN, message, extra_data)?;
         let mut obj_bytes = vec![];
         hash.write(&mut obj_bytes)?;
-        obj_bytes.shrink_to_fit();
-        unsafe {
-            *out_hash = obj_bytes.as_mut_ptr();
-            *out_len = obj_bytes.len() as c_int;
-        }
-        std::mem::forget(obj_bytes);
+        unsafe { raw::write_bytes(out_hash, out_len, obj_bytes) };
         Ok(())
     })
 }
@@ -145,26 +181,116 @@ pub extern "C" fn verify_signature(
     out_verified: *mut bool,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let public_key = unsafe { &*in_public_key };
-        let message = unsafe { slice::from_raw_parts(in_message, in_message_len as usize) };
-        let extra_data =
-            unsafe { slice::from_raw_parts(in_extra_data, in_extra_data_len as usize) };
-        let signature = unsafe { &*in_signature };
+        let public_key = unsafe { raw::reference(in_public_key) };
+        let message = unsafe { raw::bytes(in_message, in_message_len) };
+        let extra_data = unsafe { raw::bytes(in_extra_data, in_extra_data_len) };
+        let signature = unsafe { raw::reference(in_signature) };
         let verified = if should_use_composite {
-            public_key
-                .verify(message, extra_data, signature, &*COMPOSITE_HASH_TO_G1)
-                .is_ok()
+            Celo::verify_with_extra_data(
+                public_key,
+                message,
+                extra_data,
+                signature,
+                &*COMPOSITE_HASH_TO_G1,
+            )
+            .is_ok()
         } else {
-            public_key
-                .verify(message, extra_data, signature, &*DIRECT_HASH_TO_G1)
-                .is_ok()
+            Celo::verify_with_extra_data(
+                public_key,
+                message,
+                extra_data,
+                signature,
+                &*DIRECT_HASH_TO_G1,
+            )
+            .is_ok()
         };
-        unsafe { *out_verified = verified };
+        unsafe { raw::write(out_verified, verified) };
 
         Ok(())
     })
 }
 
+/// Maximum number of messages accepted by `batch_verify_signature`
+pub const MAX_BATCH_SIZE: usize = 4096;
+
+/// Fixed cost charged for each message of a batch, accounting for its hash to G1
+/// and its Miller loop
+pub const BATCH_ENTRY_COST: usize = 1024;
+
+/// Maximum total cost accepted by `batch_verify_signature`. Each message costs
+/// `BATCH_ENTRY_COST` plus the number of bytes in its data and extra data.
+pub const MAX_BATCH_COST: usize = 8 * 1024 * 1024;
+
+/// Error code: the batch was within the provided limits
+pub const BATCH_OK: c_int = 0;
+/// Error code: the batch contained more messages than allowed
+pub const BATCH_ERROR_TOO_MANY_MESSAGES: c_int = 1;
+/// Error code: the total cost of the batch exceeded the allowed cost
+pub const BATCH_ERROR_COST_EXCEEDED: c_int = 2;
+/// Error code: a message did not have the layout version expected by the library
+pub const BATCH_ERROR_INVALID_MESSAGE: c_int = 3;
+
+/// Error raised when a batch exceeds the limits of the bounded batch verification
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchLimitError {
+    /// The number of messages and the maximum batch size
+    TooManyMessages(usize, usize),
+    /// The cost of the batch and the maximum cost
+    CostExceeded(usize, usize),
+    /// The index of a message with an invalid layout and the reason
+    InvalidMessage(usize, MessageFFIError),
+}
+
+impl BatchLimitError {
+    /// Returns the error code which is passed to the FFI consumer
+    pub fn code(&self) -> c_int {
+        match self {
+            BatchLimitError::TooManyMessages(..) => BATCH_ERROR_TOO_MANY_MESSAGES,
+            BatchLimitError::CostExceeded(..) => BATCH_ERROR_COST_EXCEEDED,
+            BatchLimitError::InvalidMessage(..) => BATCH_ERROR_INVALID_MESSAGE,
+        }
+    }
+}
+
+impl Display for BatchLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchLimitError::TooManyMessages(len, max) => {
+                write!(f, "batch has {} messages, maximum is {}", len, max)
+            }
+            BatchLimitError::CostExceeded(cost, max) => {
+                write!(f, "batch has cost {}, maximum is {}", cost, max)
+            }
+            BatchLimitError::InvalidMessage(index, err) => {
+                write!(f, "invalid message {}: {}", index, err)
+            }
+        }
+    }
+}
+
+/// Returns the cost of verifying the provided messages. Only the buffer lengths are read,
+/// so this is cheap to compute before touching the underlying data.
+pub fn batch_cost(messages: &[MessageFFI]) -> usize {
+    messages.iter().fold(0usize, |cost, m| {
+        cost.saturating_add(entry_cost(m.data.len, m.extra.len))
+    })
+}
+
+/// Same as `batch_cost`, for `MessageV2FFI`
+pub fn batch_cost_v2(messages: &[MessageV2FFI]) -> usize {
+    messages.iter().fold(0usize, |cost, m| {
+        cost.saturating_add(entry_cost(m.data.len, m.extra.len))
+    })
+}
+
+// Lengths beyond the address space saturate the cost, so that such messages exceed any limit
+fn entry_cost(data_len: u64, extra_len: u64) -> usize {
+    let len = |len: u64| usize::try_from(len).unwrap_or(usize::MAX);
+    BATCH_ENTRY_COST
+        .saturating_add(len(data_len))
+        .saturating_add(len(extra_len))
+}
+
 #[no_mangle]
 /// Receives a list of messages composed of:
 /// 1. the data
@@ -174,6 +300,9 @@ pub extern "C" fn verify_signature(
 /// It will create the aggregate signature from all messages and execute batch
 /// verification against each (data, publickey) pair. Internally calls `Signature::batch_verify`
 ///
+/// Batches larger than `MAX_BATCH_SIZE` or costlier than `MAX_BATCH_COST` are rejected.
+/// Use `batch_verify_signature_bounded` to provide different limits and get an error code.
+///
 /// The verification equation can be found in pg.11 from
 /// https://eprint.iacr.org/2018/483.pdf: "Batch verification"
 pub extern "C" fn batch_verify_signature(
@@ -182,12 +311,74 @@ pub extern "C" fn batch_verify_signature(
     should_use_composite: bool,
     verified: *mut bool,
 ) -> bool {
-    convert_result_to_bool::<_, BLSError, _>(|| {
+    let mut error_code = BATCH_OK;
+    batch_verify_signature_bounded(
+        messages_ptr,
+        messages_len,
+        should_use_composite,
+        MAX_BATCH_SIZE,
+        MAX_BATCH_COST,
+        verified,
+        &mut error_code as *mut c_int,
+    )
+}
+
+#[no_mangle]
+/// Same as `batch_verify_signature`, but rejects batches with more than `max_batch_size`
+/// messages or with a cost larger than `max_cost` before doing any hashing or pairings.
+///
+/// `out_error_code` is set to `BATCH_OK` if the batch was within limits, or to one of the
+/// `BATCH_ERROR_*` codes otherwise, in which case the function also returns `false`.
+/// Messages whose `version` is not `MESSAGE_FFI_VERSION` are rejected with
+/// `BATCH_ERROR_INVALID_MESSAGE`, as is a `messages_ptr` which is null or not aligned for
+/// `MessageFFI`.
+pub extern "C" fn batch_verify_signature_bounded(
+    messages_ptr: *const MessageFFI,
+    messages_len: usize,
+    should_use_composite: bool,
+    max_batch_size: usize,
+    max_cost: usize,
+    verified: *mut bool,
+    out_error_code: *mut c_int,
+) -> bool {
+    convert_result_to_bool::<_, BatchLimitError, _>(|| {
+        unsafe { raw::write(out_error_code, BATCH_OK) };
+        if messages_len > max_batch_size {
+            let err = BatchLimitError::TooManyMessages(messages_len, max_batch_size);
+            unsafe { raw::write(out_error_code, err.code()) };
+            return Err(err);
+        }
+
         // Get the pointers slice
-        let messages: &[MessageFFI] = unsafe { slice::from_raw_parts(messages_ptr, messages_len) };
+        if messages_len > 0 && messages_ptr.is_null() {
+            let err = BatchLimitError::InvalidMessage(0, MessageFFIError::NullPointer);
+            unsafe { raw::write(out_error_code, err.code()) };
+            return Err(err);
+        }
+        if !raw::is_aligned(messages_ptr) {
+            let err = BatchLimitError::InvalidMessage(0, MessageFFIError::Misaligned);
+            unsafe { raw::write(out_error_code, err.code()) };
+            return Err(err);
+        }
+        let messages: &[MessageFFI] = unsafe { raw::slice(messages_ptr, messages_len) };
+
+        let cost = batch_cost(messages);
+        if cost > max_cost {
+            let err = BatchLimitError::CostExceeded(cost, max_cost);
+            unsafe { raw::write(out_error_code, err.code()) };
+            return Err(err);
+        }
 
         // Get the data from the underlying pointers in the right format
-        let messages = messages.iter().map(Message::from).collect::<Vec<_>>();
+        let messages = messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| Message::try_from(m).map_err(|e| BatchLimitError::InvalidMessage(i, e)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                unsafe { raw::write(out_error_code, err.code()) };
+                err
+            })?;
 
         let asig = Signature::aggregate(messages.iter().map(|m| m.sig));
 
@@ -198,19 +389,126 @@ pub extern "C" fn batch_verify_signature(
             .collect::<Vec<_>>();
 
         let is_verified = if should_use_composite {
-            asig.batch_verify(&pubkeys, SIG_DOMAIN, &messages, &*COMPOSITE_HASH_TO_G1)
+            asig.batch_verify(&pubkeys, Celo::DOMAIN, &messages, &*COMPOSITE_HASH_TO_G1)
                 .is_ok()
         } else {
-            asig.batch_verify(&pubkeys, SIG_DOMAIN, &messages, &*DIRECT_HASH_TO_G1)
+            asig.batch_verify(&pubkeys, Celo::DOMAIN, &messages, &*DIRECT_HASH_TO_G1)
                 .is_ok()
         };
 
-        unsafe { *verified = is_verified };
+        unsafe { raw::write(verified, is_verified) };
         Ok(())
     })
 }
 
 #[no_mangle]
+/// Same as `batch_verify_signature_bounded`, but for `MessageV2FFI` messages, which may be
+/// signed in different domains and carry a prepared public key.
+///
+/// Messages whose `version` is not `MESSAGE_V2_FFI_VERSION` or whose domain is neither empty
+/// nor 8 bytes long are rejected with `BATCH_ERROR_INVALID_MESSAGE`.
+pub extern "C" fn batch_verify_signature_v2(
+    messages_ptr: *const MessageV2FFI,
+    messages_len: usize,
+    should_use_composite: bool,
+    max_batch_size: usize,
+    max_cost: usize,
+    verified: *mut bool,
+    out_error_code: *mut c_int,
+) -> bool {
+    convert_result_to_bool::<_, BatchLimitError, _>(|| {
+        unsafe { raw::write(out_error_code, BATCH_OK) };
+        if messages_len > max_batch_size {
+            let err = BatchLimitError::TooManyMessages(messages_len, max_batch_size);
+            unsafe { raw::write(out_error_code, err.code()) };
+            return Err(err);
+        }
+
+        if messages_len > 0 && messages_ptr.is_null() {
+            let err = BatchLimitError::InvalidMessage(0, MessageFFIError::NullPointer);
+            unsafe { raw::write(out_error_code, err.code()) };
+            return Err(err);
+        }
+        if !raw::is_aligned(messages_ptr) {
+            let err = BatchLimitError::InvalidMessage(0, MessageFFIError::Misaligned);
+            unsafe { raw::write(out_error_code, err.code()) };
+            return Err(err);
+        }
+        let messages: &[MessageV2FFI] = unsafe { raw::slice(messages_ptr, messages_len) };
+
+        let cost = batch_cost_v2(messages);
+        if cost > max_cost {
+            let err = BatchLimitError::CostExceeded(cost, max_cost);
+            unsafe { raw::write(out_error_code, err.code()) };
+            return Err(err);
+        }
+
+        let messages = messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| MessageV2::try_from(m).map_err(|e| BatchLimitError::InvalidMessage(i, e)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                unsafe { raw::write(out_error_code, err.code()) };
+                err
+            })?;
+
+        let is_verified = if should_use_composite {
+            verify_messages_v2(&messages, &*COMPOSITE_HASH_TO_G1)
+        } else {
+            verify_messages_v2(&messages, &*DIRECT_HASH_TO_G1)
+        };
+
+        unsafe { raw::write(verified, is_verified) };
+        Ok(())
+    })
+}
+
+/// Batch verifies the messages. Each message is hashed from the first try-and-increment
+/// counter, so that only the canonical hash is accepted: the `hash_hint`s come from the
+/// caller and are ignored.
+fn verify_messages_v2<H: Hasher<Error = BLSError>>(
+    messages: &[MessageV2],
+    hash_to_g1: &TryAndIncrement<H, <Parameters as Bls12Parameters>::G1Parameters>,
+) -> bool {
+    let asig = Signature::aggregate(messages.iter().map(|m| m.sig));
+    let pubkeys = messages
+        .iter()
+        .map(|m| match m.prepared_public_key {
+            Some(prepared) => Cow::Borrowed(prepared),
+            None => Cow::Owned(PreparedPublicKey::from(m.public_key)),
+        })
+        .collect::<Vec<_>>();
+
+    let verify = || -> Result<(), BLSError> {
+        let hashes = messages
+            .iter()
+            .map(|m| hash_to_g1.hash(m.domain.as_bytes(), m.data, m.extra))
+            .collect::<Result<Vec<G1Projective>, _>>()?;
+        asig.batch_verify_hashes_prepared(&pubkeys, &hashes)
+    };
+    verify().is_ok()
+}
+
+#[no_mangle]
+/// Prepares the public key for the Miller loop, for passing it as the
+/// `prepared_public_key` of the `MessageV2FFI`s it verifies. The handle must be freed with
+/// `destroy_prepared_public_key`.
+pub extern "C" fn prepare_public_key(
+    in_public_key: *const PublicKey,
+    out_prepared_public_key: *mut *mut PreparedPublicKey,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let public_key = unsafe { raw::reference(in_public_key) };
+        let prepared = PreparedPublicKey::from(public_key);
+        unsafe { raw::write_boxed(out_prepared_public_key, prepared) };
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Verifies a proof of possession produced by `sign_pop`
 pub extern "C" fn verify_pop(
     in_public_key: *const PublicKey,
     in_message: *const u8,
@@ -219,13 +517,68 @@ pub extern "C" fn verify_pop(
     out_verified: *mut bool,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let public_key = unsafe { &*in_public_key };
-        let message = unsafe { slice::from_raw_parts(in_message, in_message_len as usize) };
-        let signature = unsafe { &*in_signature };
+        let public_key = unsafe { raw::reference(in_public_key) };
+        let message = unsafe { raw::bytes(in_message, in_message_len) };
+        let signature = unsafe { raw::reference(in_signature) };
         let verified = public_key
             .verify_pop(message, signature, &*DIRECT_HASH_TO_G1)
             .is_ok();
-        unsafe { *out_verified = verified };
+        unsafe { raw::write(out_verified, verified) };
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Verifies a proof of possession produced by `sign_key_pop`
+pub extern "C" fn verify_key_pop(
+    in_public_key: *const PublicKey,
+    in_signature: *const Signature,
+    out_verified: *mut bool,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let public_key = unsafe { raw::reference(in_public_key) };
+        let signature = unsafe { raw::reference(in_signature) };
+        let verified = public_key
+            .verify_key_pop(signature, &*DIRECT_HASH_TO_G1)
+            .is_ok();
+        unsafe { raw::write(out_verified, verified) };
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Verifies a list of proofs of possession, e.g. those of the validators registering in an
+/// epoch. `out_verified` is set to true only if every proof is valid for its public key and
+/// message. The proofs are verified separately, since a batch verification of proofs of
+/// possession would not prove possession of each key.
+///
+/// Returns `false` if there are no proofs, if `pops_ptr` is null or misaligned, or if a
+/// proof's `version` is not `POP_FFI_VERSION` or one of its pointers is null.
+pub extern "C" fn verify_pops(
+    pops_ptr: *const PopFFI,
+    pops_len: usize,
+    out_verified: *mut bool,
+) -> bool {
+    if pops_ptr.is_null() || pops_len == 0 {
+        return false;
+    }
+    convert_result_to_bool::<_, MessageFFIError, _>(|| {
+        if !raw::is_aligned(pops_ptr) {
+            return Err(MessageFFIError::Misaligned);
+        }
+        let pops: &[PopFFI] = unsafe { raw::slice(pops_ptr, pops_len) };
+        let pops = pops
+            .iter()
+            .map(Pop::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let verified = pops.iter().all(|p| {
+            p.public_key
+                .verify_pop(p.message, p.pop, &*DIRECT_HASH_TO_G1)
+                .is_ok()
+        });
+        unsafe { raw::write(out_verified, verified) };
 
         Ok(())
     })
@@ -238,20 +591,37 @@ pub extern "C" fn aggregate_public_keys(
     out_public_key: *mut *mut PublicKey,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let public_keys_ptrs =
-            unsafe { slice::from_raw_parts(in_public_keys, in_public_keys_len as usize) };
-        let public_keys = public_keys_ptrs
-            .to_vec()
-            .into_iter()
-            .map(|pk| unsafe { &*pk }.clone())
-            .collect::<Vec<PublicKey>>();
-
-        let mut cache = PUBLIC_KEY_CACHE.lock().expect("mutex poisoned");
-        let aggregated_public_key = cache.aggregate(public_keys);
-
-        unsafe {
-            *out_public_key = Box::into_raw(Box::new(aggregated_public_key));
-        }
+        let public_keys = unsafe { raw::cloned(in_public_keys, in_public_keys_len) };
+
+        // the lock is released before writing to the out parameter
+        let aggregated_public_key = PUBLIC_KEY_CACHE
+            .lock()
+            .expect("mutex poisoned")
+            .aggregate(public_keys);
+
+        unsafe { raw::write_boxed(out_public_key, aggregated_public_key) };
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Aggregates public keys received from untrusted parties. The `i`-th proof of possession,
+/// produced by `sign_key_pop`, must be valid for the `i`-th key, otherwise `false` is
+/// returned and no key is written.
+pub extern "C" fn aggregate_public_keys_with_pop_check(
+    in_public_keys: *const *const PublicKey,
+    in_pops: *const *const Signature,
+    in_public_keys_len: c_int,
+    out_public_key: *mut *mut PublicKey,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let public_keys = unsafe { raw::cloned(in_public_keys, in_public_keys_len) };
+        let pops = unsafe { raw::cloned(in_pops, in_public_keys_len) };
+        let keys = public_keys.into_iter().zip(pops).collect::<Vec<_>>();
+        let aggregated_public_key =
+            PublicKey::aggregate_with_pop_check(&keys, &*DIRECT_HASH_TO_G1)?;
+        unsafe { raw::write_boxed(out_public_key, aggregated_public_key) };
 
         Ok(())
     })
@@ -265,24 +635,17 @@ pub extern "C" fn aggregate_public_keys_subtract(
     out_public_key: *mut *mut PublicKey,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let aggregated_public_key = unsafe { &*in_aggregated_public_key };
-        let public_keys_ptrs =
-            unsafe { slice::from_raw_parts(in_public_keys, in_public_keys_len as usize) };
-        let public_keys = public_keys_ptrs
-            .to_vec()
-            .into_iter()
-            .map(|pk| unsafe { &*pk }.clone())
-            .collect::<Vec<PublicKey>>();
-
-        let mut cache = PUBLIC_KEY_CACHE.lock().expect("mutex poisoned");
-        let aggregated_public_key_to_subtract = cache.aggregate(public_keys);
-        let prepared_aggregated_public_key = PublicKey::from(
-            *aggregated_public_key.as_ref() - *aggregated_public_key_to_subtract.as_ref(),
-        );
+        let aggregated_public_key = unsafe { raw::reference(in_aggregated_public_key) };
+        let public_keys = unsafe { raw::cloned(in_public_keys, in_public_keys_len) };
 
-        unsafe {
-            *out_public_key = Box::into_raw(Box::new(prepared_aggregated_public_key));
-        }
+        let aggregated_public_key_to_subtract = PUBLIC_KEY_CACHE
+            .lock()
+            .expect("mutex poisoned")
+            .aggregate(public_keys);
+        let prepared_aggregated_public_key =
+            aggregated_public_key.subtract(&aggregated_public_key_to_subtract);
+
+        unsafe { raw::write_boxed(out_public_key, prepared_aggregated_public_key) };
 
         Ok(())
     })
@@ -295,18 +658,396 @@ pub extern "C" fn aggregate_signatures(
     out_signature: *mut *mut Signature,
 ) -> bool {
     convert_result_to_bool::<_, BLSError, _>(|| {
-        let signatures_ptrs =
-            unsafe { slice::from_raw_parts(in_signatures, in_signatures_len as usize) };
-        let signatures = signatures_ptrs
-            .to_vec()
-            .into_iter()
-            .map(|sig| unsafe { &*sig }.clone())
-            .collect::<Vec<Signature>>();
+        let signatures = unsafe { raw::cloned(in_signatures, in_signatures_len) };
         let aggregated_signature = Signature::aggregate(&signatures[..]);
-        unsafe {
-            *out_signature = Box::into_raw(Box::new(aggregated_signature));
-        }
+        unsafe { raw::write_boxed(out_signature, aggregated_signature) };
 
         Ok(())
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{
+        bls12_377::{G1Projective, G2Projective},
+        UniformRand,
+    };
+    use bls_crypto::Domain;
+    use std::ptr;
+
+    fn random_messages(num: usize, data_len: usize) -> (Vec<u8>, Vec<PublicKey>, Vec<Signature>) {
+        let rng = &mut rand::thread_rng();
+        let data = vec![1u8; data_len];
+        let pubkeys = (0..num)
+            .map(|_| PublicKey::from(G2Projective::rand(rng)))
+            .collect::<Vec<_>>();
+        let sigs = (0..num)
+            .map(|_| Signature::from(G1Projective::rand(rng)))
+            .collect::<Vec<_>>();
+        (data, pubkeys, sigs)
+    }
+
+    fn to_ffi<'a>(
+        data: &'a [u8],
+        pubkeys: &'a [PublicKey],
+        sigs: &'a [Signature],
+    ) -> Vec<MessageFFI> {
+        pubkeys
+            .iter()
+            .zip(sigs)
+            .map(|(public_key, sig)| {
+                MessageFFI::from(&Message {
+                    data,
+                    extra: data,
+                    public_key,
+                    sig,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batch_cost_counts_bytes() {
+        let (data, pubkeys, sigs) = random_messages(3, 10);
+        let msgs = to_ffi(&data, &pubkeys, &sigs);
+        assert_eq!(batch_cost(&msgs), 3 * (BATCH_ENTRY_COST + 20));
+    }
+
+    #[test]
+    fn rejects_too_many_messages() {
+        let (data, pubkeys, sigs) = random_messages(3, 10);
+        let msgs = to_ffi(&data, &pubkeys, &sigs);
+        let mut verified = true;
+        let mut error_code = BATCH_OK;
+        let success = batch_verify_signature_bounded(
+            &msgs[0] as *const MessageFFI,
+            msgs.len(),
+            false,
+            2,
+            MAX_BATCH_COST,
+            &mut verified as *mut bool,
+            &mut error_code as *mut c_int,
+        );
+        assert!(!success);
+        assert_eq!(error_code, BATCH_ERROR_TOO_MANY_MESSAGES);
+    }
+
+    #[test]
+    fn rejects_costly_batch() {
+        let (data, pubkeys, sigs) = random_messages(2, 100);
+        let msgs = to_ffi(&data, &pubkeys, &sigs);
+        let mut verified = true;
+        let mut error_code = BATCH_OK;
+        let success = batch_verify_signature_bounded(
+            &msgs[0] as *const MessageFFI,
+            msgs.len(),
+            false,
+            MAX_BATCH_SIZE,
+            2 * BATCH_ENTRY_COST,
+            &mut verified as *mut bool,
+            &mut error_code as *mut c_int,
+        );
+        assert!(!success);
+        assert_eq!(error_code, BATCH_ERROR_COST_EXCEEDED);
+    }
+
+    #[test]
+    fn rejects_unsupported_message_version() {
+        let (data, pubkeys, sigs) = random_messages(2, 10);
+        let mut msgs = to_ffi(&data, &pubkeys, &sigs);
+        msgs[1].version = 0;
+        let mut verified = true;
+        let mut error_code = BATCH_OK;
+        let success = batch_verify_signature_bounded(
+            &msgs[0] as *const MessageFFI,
+            msgs.len(),
+            false,
+            MAX_BATCH_SIZE,
+            MAX_BATCH_COST,
+            &mut verified as *mut bool,
+            &mut error_code as *mut c_int,
+        );
+        assert!(!success);
+        assert_eq!(error_code, BATCH_ERROR_INVALID_MESSAGE);
+    }
+
+    #[test]
+    fn rejects_null_messages() {
+        let mut verified = true;
+        let mut error_code = BATCH_OK;
+        assert!(!batch_verify_signature_bounded(
+            ptr::null(),
+            2,
+            false,
+            MAX_BATCH_SIZE,
+            MAX_BATCH_COST,
+            &mut verified as *mut bool,
+            &mut error_code as *mut c_int,
+        ));
+        assert_eq!(error_code, BATCH_ERROR_INVALID_MESSAGE);
+
+        error_code = BATCH_OK;
+        assert!(!batch_verify_signature_v2(
+            ptr::null(),
+            2,
+            false,
+            MAX_BATCH_SIZE,
+            MAX_BATCH_COST,
+            &mut verified as *mut bool,
+            &mut error_code as *mut c_int,
+        ));
+        assert_eq!(error_code, BATCH_ERROR_INVALID_MESSAGE);
+    }
+
+    #[test]
+    fn runs_verification_within_limits() {
+        let (data, pubkeys, sigs) = random_messages(2, 10);
+        let msgs = to_ffi(&data, &pubkeys, &sigs);
+        let mut verified = true;
+        let mut error_code = BATCH_ERROR_COST_EXCEEDED;
+        let success = batch_verify_signature_bounded(
+            &msgs[0] as *const MessageFFI,
+            msgs.len(),
+            false,
+            MAX_BATCH_SIZE,
+            MAX_BATCH_COST,
+            &mut verified as *mut bool,
+            &mut error_code as *mut c_int,
+        );
+        assert!(success);
+        assert_eq!(error_code, BATCH_OK);
+        // random signatures do not verify
+        assert!(!verified);
+    }
+
+    fn verify_v2(msgs: &[MessageV2]) -> bool {
+        let msgs = msgs.iter().map(MessageV2FFI::from).collect::<Vec<_>>();
+        let mut verified = false;
+        let mut error_code = BATCH_OK;
+        assert!(batch_verify_signature_v2(
+            msgs.as_ptr(),
+            msgs.len(),
+            false,
+            MAX_BATCH_SIZE,
+            MAX_BATCH_COST,
+            &mut verified as *mut bool,
+            &mut error_code as *mut c_int,
+        ));
+        assert_eq!(error_code, BATCH_OK);
+        verified
+    }
+
+    #[test]
+    fn verifies_v2_messages_ignoring_hints() {
+        let rng = &mut rand::thread_rng();
+        let domains = [Domain::SIG, Domain::new(b"myapp_v1").unwrap()];
+        let keys = (0..4)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let pubkeys = keys.iter().map(PrivateKey::to_public).collect::<Vec<_>>();
+        let prepared = pubkeys
+            .iter()
+            .map(PreparedPublicKey::from)
+            .collect::<Vec<_>>();
+        let data = (0..4u8).map(|i| vec![i; 10]).collect::<Vec<_>>();
+
+        let mut sigs = vec![];
+        let mut hints = vec![];
+        for (i, key) in keys.iter().enumerate() {
+            let domain = domains[i % 2];
+            let (hash, attempt) = DIRECT_HASH_TO_G1
+                .hash_with_attempt(domain.as_bytes(), &data[i], &[])
+                .unwrap();
+            sigs.push(key.sign_hashed(&hash));
+            hints.push(attempt as u32);
+        }
+
+        let mut msgs = (0..4)
+            .map(|i| MessageV2 {
+                data: &data[i],
+                extra: &[],
+                domain: domains[i % 2],
+                public_key: &pubkeys[i],
+                prepared_public_key: if i % 2 == 0 { Some(&prepared[i]) } else { None },
+                sig: &sigs[i],
+                hash_hint: hints[i],
+            })
+            .collect::<Vec<_>>();
+        assert!(verify_v2(&msgs));
+
+        // the hints are ignored, whether they are right or wrong
+        msgs[1].hash_hint += 1;
+        assert!(verify_v2(&msgs));
+
+        // a signature of a later counter's point is not the canonical hash's
+        let (later, later_attempt) = DIRECT_HASH_TO_G1
+            .hash_from_attempt(domains[0].as_bytes(), &data[0], &[], hints[0] as usize + 1)
+            .unwrap();
+        let non_canonical = keys[0].sign_hashed(&later);
+        let mut forged = msgs.clone();
+        forged[0].sig = &non_canonical;
+        forged[0].hash_hint = later_attempt as u32;
+        assert!(!verify_v2(&forged));
+
+        // the domain is part of the signed message
+        msgs[1].domain = Domain::SIG;
+        assert!(!verify_v2(&msgs));
+    }
+
+    fn ffi_pop(private_key: &PrivateKey, address: &[u8]) -> Signature {
+        let mut pop = std::ptr::null_mut();
+        assert!(sign_pop(
+            private_key as *const PrivateKey,
+            address.as_ptr(),
+            address.len() as c_int,
+            &mut pop as *mut *mut Signature,
+        ));
+        unsafe { raw::take(pop) }
+    }
+
+    #[test]
+    fn aggregates_keys_with_pops() {
+        let rng = &mut rand::thread_rng();
+        let private_keys = (0..3)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let public_keys = private_keys
+            .iter()
+            .map(|sk| sk.to_public())
+            .collect::<Vec<_>>();
+        let pops = private_keys
+            .iter()
+            .map(|sk| {
+                let mut pop = std::ptr::null_mut();
+                assert!(sign_key_pop(
+                    sk as *const PrivateKey,
+                    &mut pop as *mut *mut Signature
+                ));
+                unsafe { raw::take(pop) }
+            })
+            .collect::<Vec<_>>();
+
+        let mut verified = false;
+        assert!(verify_key_pop(
+            &public_keys[0] as *const PublicKey,
+            &pops[0] as *const Signature,
+            &mut verified as *mut bool,
+        ));
+        assert!(verified);
+
+        let aggregate = |pops: &[Signature]| {
+            let key_ptrs = public_keys
+                .iter()
+                .map(|pk| pk as *const PublicKey)
+                .collect::<Vec<_>>();
+            let pop_ptrs = pops
+                .iter()
+                .map(|pop| pop as *const Signature)
+                .collect::<Vec<_>>();
+            let mut aggregated = std::ptr::null_mut();
+            if aggregate_public_keys_with_pop_check(
+                key_ptrs.as_ptr(),
+                pop_ptrs.as_ptr(),
+                key_ptrs.len() as c_int,
+                &mut aggregated as *mut *mut PublicKey,
+            ) {
+                Some(unsafe { raw::take(aggregated) })
+            } else {
+                None
+            }
+        };
+        assert_eq!(
+            aggregate(&pops).unwrap(),
+            PublicKey::aggregate(&public_keys)
+        );
+        // the proofs must be in the order of the keys
+        assert!(aggregate(&[pops[1].clone(), pops[0].clone(), pops[2].clone()]).is_none());
+    }
+
+    #[test]
+    fn signs_and_verifies_pops() {
+        let rng = &mut rand::thread_rng();
+        let private_keys = (0..3)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let public_keys = private_keys
+            .iter()
+            .map(|sk| sk.to_public())
+            .collect::<Vec<_>>();
+        let addresses = (0..3u8).map(|i| [i; 20]).collect::<Vec<_>>();
+        let sigs = private_keys
+            .iter()
+            .zip(&addresses)
+            .map(|(sk, address)| ffi_pop(sk, address))
+            .collect::<Vec<_>>();
+
+        let mut verified = false;
+        assert!(verify_pop(
+            &public_keys[0] as *const PublicKey,
+            addresses[0].as_ptr(),
+            addresses[0].len() as c_int,
+            &sigs[0] as *const Signature,
+            &mut verified as *mut bool,
+        ));
+        assert!(verified);
+
+        let pops = |messages: &[[u8; 20]]| {
+            public_keys
+                .iter()
+                .zip(messages)
+                .zip(&sigs)
+                .map(|((public_key, message), pop)| {
+                    PopFFI::from(&Pop {
+                        message,
+                        public_key,
+                        pop,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let valid = pops(&addresses);
+        let mut verified = false;
+        assert!(verify_pops(
+            valid.as_ptr(),
+            valid.len(),
+            &mut verified as *mut bool
+        ));
+        assert!(verified);
+
+        // a proof replayed for another address
+        let swapped = pops(&[addresses[1], addresses[0], addresses[2]]);
+        assert!(verify_pops(
+            swapped.as_ptr(),
+            swapped.len(),
+            &mut verified as *mut bool
+        ));
+        assert!(!verified);
+
+        let mut invalid = pops(&addresses);
+        invalid[2].version = 0;
+        assert!(!verify_pops(
+            invalid.as_ptr(),
+            invalid.len(),
+            &mut verified as *mut bool
+        ));
+
+        let mut invalid = pops(&addresses);
+        invalid[1].public_key = std::ptr::null();
+        assert!(!verify_pops(
+            invalid.as_ptr(),
+            invalid.len(),
+            &mut verified as *mut bool
+        ));
+
+        // no proofs do not prove anything
+        verified = true;
+        assert!(!verify_pops(
+            std::ptr::null(),
+            3,
+            &mut verified as *mut bool
+        ));
+        assert!(!verify_pops(valid.as_ptr(), 0, &mut verified as *mut bool));
+        assert!(verified);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::convert_result_to_bool;
+use crate::{convert_result_to_bool, raw};
 use algebra::{
     bls12_377::G2Affine, AffineCurve, CanonicalDeserialize, CanonicalSerialize, ProjectiveCurve,
 };
@@ -7,7 +7,6 @@ use epoch_snark::{EncodingError, EpochBlock};
 use std::{
     convert::TryFrom,
     os::raw::{c_int, c_uint, c_ushort},
-    slice,
 };
 
 /// Each pubkey is a BLS G2Projective element
@@ -24,31 +23,20 @@ pub extern "C" fn encode_epoch_block_to_bytes(
     out_len: *mut c_int,
 ) -> bool {
     convert_result_to_bool::<_, EncodingError, _>(|| {
-        let added_public_keys_ptrs = unsafe {
-            slice::from_raw_parts(in_added_public_keys, in_added_public_keys_len as usize)
-        };
-        let added_public_keys = added_public_keys_ptrs
-            .to_vec()
-            .into_iter()
-            .map(|pk| unsafe { &*pk }.clone())
-            .collect::<Vec<PublicKey>>();
+        let added_public_keys =
+            unsafe { raw::cloned(in_added_public_keys, in_added_public_keys_len) };
 
         let epoch_block = EpochBlock::new(
             in_epoch_index as u16,
             in_maximum_non_signers as u32,
             added_public_keys,
         );
-        let mut encoded = if in_should_encode_aggregated_pk {
+        let encoded = if in_should_encode_aggregated_pk {
             epoch_block.encode_to_bytes_with_aggregated_pk()?
         } else {
             epoch_block.encode_to_bytes()?
         };
-        encoded.shrink_to_fit();
-        unsafe {
-            *out_bytes = encoded.as_mut_ptr();
-            *out_len = encoded.len() as c_int;
-        }
-        std::mem::forget(encoded);
+        unsafe { raw::write_bytes(out_bytes, out_len, encoded) };
         Ok(())
     })
 }
@@ -71,12 +59,8 @@ impl TryFrom<&EpochBlockFFI> for EpochBlock {
     type Error = EncodingError;
 
     fn try_from(src: &EpochBlockFFI) -> Result<EpochBlock, Self::Error> {
-        let pubkeys = unsafe { read_pubkeys(src.pubkeys, src.pubkeys_num as usize)? };
-        Ok(EpochBlock {
-            index: src.index,
-            maximum_non_signers: src.maximum_non_signers,
-            new_public_keys: pubkeys,
-        })
+        let pubkeys = read_pubkeys(src.pubkeys, src.pubkeys_num as usize)?;
+        Ok(EpochBlock::new(src.index, src.maximum_non_signers, pubkeys))
     }
 }
 
@@ -90,18 +74,14 @@ pub unsafe fn read_slice<C: CanonicalDeserialize>(
     ptr: *const u8,
     len: usize,
 ) -> Result<C, EncodingError> {
-    let mut data = slice::from_raw_parts(ptr, len);
+    let mut data = unsafe { raw::slice(ptr, len) };
     Ok(C::deserialize(&mut data)?)
 }
 
-/// Reads `num` * `PUBKEY_BYTES` bytes starting from the pointer's location
-///
-/// # Safety
-///
-/// This WILL read invalid data if you give it a larger `num` argument
-/// than expected. Use with caution.
-unsafe fn read_serialized_pubkeys<'a>(ptr: *const u8, num: usize) -> &'a [u8] {
-    slice::from_raw_parts(ptr, num * PUBKEY_BYTES)
+/// Reads `num` * `PUBKEY_BYTES` bytes starting from the pointer's location. This WILL read
+/// invalid data if you give it a larger `num` argument than expected.
+fn read_serialized_pubkeys<'a>(ptr: *const u8, num: usize) -> &'a [u8] {
+    unsafe { raw::slice(ptr, num * PUBKEY_BYTES) }
 }
 
 /// Serializes the inner G2 elements of the pubkeys to a vector
@@ -114,12 +94,10 @@ pub fn serialize_pubkeys(pubkeys: &[PublicKey]) -> Result<Vec<u8>, EncodingError
 }
 
 /// Reads `num` PublicKey elements starting from the memory that the pointer points to.
-///
-/// # Safety
 /// This WILL NOT fail if the `num` variable is larger than the expected elements, and will
-/// simply return an array of `PublicKeys` whose internals will be whatever data was in the memory.
-/// Use with caution.
-unsafe fn read_pubkeys(ptr: *const u8, num: usize) -> Result<Vec<PublicKey>, EncodingError> {
+/// simply return an array of `PublicKeys` whose internals will be whatever data was in the
+/// memory.
+fn read_pubkeys(ptr: *const u8, num: usize) -> Result<Vec<PublicKey>, EncodingError> {
     let mut data = read_serialized_pubkeys(ptr, num);
     let mut pubkeys = Vec::new();
     for _ in 0..num {
@@ -145,11 +123,7 @@ mod tests {
     fn ffi_block_conversion() {
         let num_keys = 10;
         let pubkeys = rand_pubkeys(num_keys);
-        let block = EpochBlock {
-            index: 1,
-            maximum_non_signers: 19,
-            new_public_keys: pubkeys,
-        };
+        let block = EpochBlock::new(1, 19, pubkeys);
         let src = block;
         let serialized_pubkeys = serialize_pubkeys(&src.new_public_keys).unwrap();
         let ffi_block = EpochBlockFFI {
@@ -212,7 +186,7 @@ mod tests {
         let pubkeys = rand_pubkeys(num_keys);
         let serialized = serialize_pubkeys(&pubkeys).unwrap();
         let ptr = &serialized[0] as *const u8;
-        let deserialized_from_ptr = unsafe { read_pubkeys(ptr, num_keys).unwrap() };
+        let deserialized_from_ptr = read_pubkeys(ptr, num_keys).unwrap();
         assert_eq!(deserialized_from_ptr, pubkeys);
     }
 
@@ -222,9 +196,8 @@ mod tests {
         let pubkeys = rand_pubkeys(num_keys);
         let serialized = serialize_pubkeys(&pubkeys).unwrap();
         let ptr = &serialized[0] as *const u8;
-        // We read a bunch of junk data, hence why this MUST
-        // be unsafe :)
-        unsafe { read_pubkeys(ptr, 99).unwrap_err() };
+        // We read a bunch of junk data
+        read_pubkeys(ptr, 99).unwrap_err();
     }
 
     fn rand_pubkeys(num_keys: usize) -> Vec<PublicKey> {
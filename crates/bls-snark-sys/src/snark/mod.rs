@@ -1,6 +1,8 @@
 pub mod epoch_block;
 use epoch_block::{read_slice, EpochBlockFFI};
 
+pub mod prover;
+
 #[cfg(test)]
 mod test_helpers;
 
@@ -0,0 +1,309 @@
+//! Generation of epoch SNARK proofs, with progress reports to a C callback
+use super::epoch_block::EpochBlockFFI;
+use crate::{convert_result_to_bool, raw, Signature};
+use algebra::CanonicalSerialize;
+use bls_crypto::envelope::Versioned;
+use epoch_snark::{
+    BLSCurve, CPCurve, EncodingError, EpochBlock, EpochTransition, Progress, ProvingStage,
+};
+use std::{
+    convert::TryFrom,
+    error::Error,
+    ffi::c_void,
+    os::raw::{c_int, c_uint},
+};
+
+/// The proving parameters of the epoch SNARK
+pub type Parameters = epoch_snark::Parameters<CPCurve, BLSCurve>;
+
+/// Called as the proof is generated with the stage, see `ProvingStage` (0 while searching the
+/// hash attempts of the epochs, 1 while proving the HashToBits helper, 2 while proving the
+/// epochs), the units of work of the stage which are done and their total, and the context
+/// which was passed to `prove`. Returning false cancels the proof.
+///
+/// The callback is called from the proving threads, but never concurrently. Once `done`
+/// reaches `total`, the last part of the stage runs for a while without reports, and the
+/// proof can only be cancelled after it.
+pub type ProgressCallback =
+    extern "C" fn(stage: c_int, done: u64, total: u64, context: *mut c_void) -> bool;
+
+/// Data structure received from consumers of the FFI interface describing
+/// an epoch transition.
+#[repr(C)]
+pub struct EpochTransitionFFI {
+    /// The new epoch block
+    pub block: EpochBlockFFI,
+    /// The aggregate signature of the block by the validators of the previous epoch
+    pub aggregate_signature: *const Signature,
+    /// Pointer to the bitmap of the validators of the previous epoch which signed
+    pub bitmap: *const bool,
+    /// The number of entries of the bitmap, i.e. the validators of the previous epoch
+    pub bitmap_len: usize,
+}
+
+impl TryFrom<&EpochTransitionFFI> for EpochTransition {
+    type Error = EncodingError;
+
+    fn try_from(src: &EpochTransitionFFI) -> Result<EpochTransition, Self::Error> {
+        Ok(EpochTransition {
+            block: EpochBlock::try_from(&src.block)?,
+            aggregate_signature: unsafe { raw::reference(src.aggregate_signature) }.clone(),
+            bitmap: unsafe { raw::slice(src.bitmap, src.bitmap_len) }.to_vec(),
+        })
+    }
+}
+
+#[no_mangle]
+/// Deserializes the proving parameters from their envelope, see
+/// `epoch_snark::PARAMETERS_KIND`. The handle must be freed with `destroy_parameters`.
+pub extern "C" fn deserialize_parameters(
+    in_parameters_bytes: *const u8,
+    in_parameters_bytes_len: usize,
+    out_parameters: *mut *mut Parameters,
+) -> bool {
+    convert_result_to_bool::<_, Box<dyn Error>, _>(|| {
+        let bytes = unsafe { raw::slice(in_parameters_bytes, in_parameters_bytes_len) };
+        let parameters = Parameters::from_envelope(bytes)?;
+        unsafe { raw::write_boxed(out_parameters, parameters) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Serializes the verifying key of the epochs circuit, as expected by `verify`
+pub extern "C" fn serialize_verifying_key(
+    in_parameters: *const Parameters,
+    out_bytes: *mut *mut u8,
+    out_len: *mut c_int,
+) -> bool {
+    convert_result_to_bool::<_, Box<dyn Error>, _>(|| {
+        let parameters = unsafe { raw::reference(in_parameters) };
+        let mut bytes = vec![];
+        parameters.epochs.vk.serialize(&mut bytes)?;
+        unsafe { raw::write_bytes(out_bytes, out_len, bytes) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Generates a proof that the transitions follow the first epoch, for a circuit proving up to
+/// `in_max_transitions` transitions of epochs with `in_num_validators` validators, and
+/// writes the compressed proof, as expected by `verify`, to `out_proof`. The bytes must be
+/// freed with `free_vec`.
+///
+/// `in_progress` is called as the proof is generated if it is not null, see
+/// `ProgressCallback`. Returns false if the proof could not be generated or was cancelled,
+/// or if a length is negative.
+pub extern "C" fn prove(
+    in_parameters: *const Parameters,
+    in_num_validators: c_uint,
+    in_first_epoch: EpochBlockFFI,
+    in_transitions: *const EpochTransitionFFI,
+    in_transitions_len: c_int,
+    in_max_transitions: c_int,
+    in_progress: Option<ProgressCallback>,
+    in_context: *mut c_void,
+    out_proof: *mut *mut u8,
+    out_proof_len: *mut c_int,
+) -> bool {
+    if in_transitions_len < 0 || in_max_transitions < 0 {
+        return false;
+    }
+    // stored as an integer, so that the callback can be called from the proving threads
+    let context = in_context as usize;
+    convert_result_to_bool::<_, Box<dyn Error>, _>(|| {
+        let parameters = unsafe { raw::reference(in_parameters) };
+        let first_epoch = EpochBlock::try_from(&in_first_epoch)?;
+        let transitions = unsafe { raw::array(in_transitions, in_transitions_len) }
+            .iter()
+            .map(EpochTransition::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let proof = epoch_snark::prove_with_progress(
+            parameters,
+            in_num_validators as u32,
+            &first_epoch,
+            &transitions,
+            in_max_transitions as usize,
+            |progress: Progress| match in_progress {
+                Some(callback) => callback(
+                    stage_code(progress.stage),
+                    progress.done as u64,
+                    progress.total as u64,
+                    context as *mut c_void,
+                ),
+                None => true,
+            },
+        )?;
+
+        let mut bytes = vec![];
+        proof.serialize(&mut bytes)?;
+        unsafe { raw::write_bytes(out_proof, out_proof_len, bytes) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Frees the proving parameters
+pub extern "C" fn destroy_parameters(parameters: *mut Parameters) -> bool {
+    unsafe { raw::destroy(parameters) }
+}
+
+fn stage_code(stage: ProvingStage) -> c_int {
+    stage as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snark::{epoch_block::serialize_pubkeys, verify};
+    use bls_crypto::{
+        hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1, PrivateKey, PublicKey,
+    };
+    use std::{ptr, sync::Mutex};
+
+    extern "C" fn record(stage: c_int, done: u64, total: u64, context: *mut c_void) -> bool {
+        let reports = unsafe { raw::reference(context as *const Mutex<Vec<(c_int, u64, u64)>>) };
+        reports.lock().unwrap().push((stage, done, total));
+        true
+    }
+
+    extern "C" fn cancel(_stage: c_int, _done: u64, _total: u64, _context: *mut c_void) -> bool {
+        false
+    }
+
+    #[test]
+    fn stage_codes_are_stable() {
+        assert_eq!(stage_code(ProvingStage::HashWitness), 0);
+        assert_eq!(stage_code(ProvingStage::HashToBits), 1);
+        assert_eq!(stage_code(ProvingStage::Epochs), 2);
+    }
+
+    #[test]
+    fn rejects_negative_lengths() {
+        let mut proof = ptr::null_mut();
+        let mut proof_len = 0;
+        for (transitions_len, max_transitions) in &[(-1, 1), (0, -1)] {
+            assert!(!prove(
+                ptr::null(),
+                4,
+                EpochBlockFFI {
+                    index: 0,
+                    pubkeys: ptr::null(),
+                    pubkeys_num: 0,
+                    maximum_non_signers: 0,
+                },
+                ptr::null(),
+                *transitions_len,
+                *max_transitions,
+                None,
+                ptr::null_mut(),
+                &mut proof,
+                &mut proof_len,
+            ));
+        }
+        assert!(proof.is_null());
+    }
+
+    #[test]
+    #[ignore] // Generating the parameters and the proof takes too long for CI
+    fn proves_with_progress_and_verifies() {
+        let rng = &mut rand::thread_rng();
+        let num_validators = 4;
+        let parameters = epoch_snark::trusted_setup(num_validators, 1, 1, rng, false).unwrap();
+        let envelope = parameters.to_envelope().unwrap();
+
+        let mut handle = ptr::null_mut();
+        assert!(deserialize_parameters(
+            envelope.as_ptr(),
+            envelope.len(),
+            &mut handle
+        ));
+
+        // the first epoch's validators sign the second epoch
+        let signers = (0..num_validators)
+            .map(|_| PrivateKey::generate(rng))
+            .collect::<Vec<_>>();
+        let first_keys = signers.iter().map(|k| k.to_public()).collect::<Vec<_>>();
+        let last_keys = (0..num_validators)
+            .map(|_| PrivateKey::generate(rng).to_public())
+            .collect::<Vec<PublicKey>>();
+        let first_epoch = EpochBlock::new(0, 1, first_keys.clone());
+        let last_epoch = EpochBlock::new(1, 1, last_keys.clone());
+        let message = last_epoch.encode_to_bytes().unwrap();
+        let signature = Signature::aggregate(
+            signers
+                .iter()
+                .map(|key| key.sign(&message, &[], &*COMPOSITE_HASH_TO_G1).unwrap()),
+        );
+
+        let first_pubkeys = serialize_pubkeys(&first_keys).unwrap();
+        let last_pubkeys = serialize_pubkeys(&last_keys).unwrap();
+        let epoch_ffi = |index, pubkeys: &[u8]| EpochBlockFFI {
+            index,
+            pubkeys: pubkeys.as_ptr(),
+            pubkeys_num: num_validators,
+            maximum_non_signers: 1,
+        };
+        let bitmap = vec![true; num_validators];
+        let transitions = [EpochTransitionFFI {
+            block: epoch_ffi(1, &last_pubkeys),
+            aggregate_signature: &signature,
+            bitmap: bitmap.as_ptr(),
+            bitmap_len: bitmap.len(),
+        }];
+
+        // cancelling fails the proof
+        let mut proof = ptr::null_mut();
+        let mut proof_len = 0;
+        assert!(!prove(
+            handle,
+            num_validators as c_uint,
+            epoch_ffi(0, &first_pubkeys),
+            transitions.as_ptr(),
+            1,
+            1,
+            Some(cancel),
+            ptr::null_mut(),
+            &mut proof,
+            &mut proof_len,
+        ));
+
+        let reports = Mutex::new(vec![]);
+        assert!(prove(
+            handle,
+            num_validators as c_uint,
+            epoch_ffi(0, &first_pubkeys),
+            transitions.as_ptr(),
+            1,
+            1,
+            Some(record),
+            &reports as *const _ as *mut c_void,
+            &mut proof,
+            &mut proof_len,
+        ));
+        let reports = reports.into_inner().unwrap();
+        // without HashToBits parameters, only the epochs circuit is proven
+        assert!(reports.iter().all(|(stage, _, _)| *stage == 2));
+        let (_, done, total) = reports[reports.len() - 1];
+        assert_eq!(done, total);
+
+        let mut vk = ptr::null_mut();
+        let mut vk_len = 0;
+        assert!(serialize_verifying_key(handle, &mut vk, &mut vk_len));
+        assert!(unsafe {
+            verify(
+                vk,
+                vk_len as u32,
+                proof,
+                proof_len as u32,
+                epoch_ffi(0, &first_pubkeys),
+                epoch_ffi(1, &last_pubkeys),
+            )
+        });
+
+        unsafe { raw::free_bytes(proof, proof_len) };
+        unsafe { raw::free_bytes(vk, vk_len) };
+        assert!(destroy_parameters(handle));
+    }
+}
@@ -0,0 +1,202 @@
+//! Streaming hashing, for inputs which are too large to be passed in a single call
+use crate::{convert_result_to_bool, raw};
+use bls_crypto::{
+    bls::{BlsScheme, Celo},
+    hashers::{
+        composite::{CompositeHasher, CRH},
+        DirectHasher, Hasher, StreamingHasher, COMPOSITE_HASHER,
+    },
+    BLSError, POP_DOMAIN,
+};
+use std::{convert::TryFrom, os::raw::c_int};
+
+enum StreamingState {
+    Direct(<DirectHasher as StreamingHasher>::State),
+    Composite(<CompositeHasher<CRH> as StreamingHasher>::State),
+}
+
+/// An in-progress hash computation. The input is fed to the hasher's CRH as it arrives,
+/// and the XOF is run over the CRH's output when the computation is finalized.
+pub struct StreamingHash {
+    domain: &'static [u8],
+    output_len: usize,
+    // `None` once the computation has been finalized
+    state: Option<StreamingState>,
+}
+
+#[no_mangle]
+/// Starts a streaming hash computation producing `output_len` bytes. Data is fed with
+/// `streaming_hash_update` and the result is produced by `streaming_hash_finalize`.
+pub extern "C" fn streaming_hash_init(
+    use_composite: bool,
+    use_pop: bool,
+    output_len: c_int,
+    out_state: *mut *mut StreamingHash,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let domain = if use_pop { POP_DOMAIN } else { Celo::DOMAIN };
+        let output_len = usize::try_from(output_len)
+            .map_err(|_| BLSError::HashingError("negative output length".into()))?;
+        let state = if use_composite {
+            StreamingState::Composite(COMPOSITE_HASHER.init(domain, output_len)?)
+        } else {
+            StreamingState::Direct(DirectHasher.init(domain, output_len)?)
+        };
+        let hash = StreamingHash {
+            domain,
+            output_len,
+            state: Some(state),
+        };
+        unsafe { raw::write_boxed(out_state, hash) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Feeds `in_data` to the streaming hash computation. Fails if the input exceeds the
+/// hasher's maximum input length.
+pub extern "C" fn streaming_hash_update(
+    in_state: *mut StreamingHash,
+    in_data: *const u8,
+    in_data_len: c_int,
+) -> bool {
+    if in_state.is_null() || in_data_len < 0 {
+        return false;
+    }
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let hash = unsafe { raw::mutable(in_state) };
+        let data = unsafe { raw::bytes(in_data, in_data_len) };
+        match hash.state.as_mut().ok_or_else(already_finalized)? {
+            StreamingState::Direct(state) => DirectHasher.update(state, data),
+            StreamingState::Composite(state) => COMPOSITE_HASHER.update(state, data),
+        }
+    })
+}
+
+/// # Safety
+///
+/// `in_state` must have been produced by `streaming_hash_init` and not been finalized or
+/// destroyed. It is consumed by this call and must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn streaming_hash_finalize(
+    in_state: *mut StreamingHash,
+    out_hash: *mut *mut u8,
+    out_len: *mut c_int,
+) -> bool {
+    if in_state.is_null() {
+        return false;
+    }
+    let success = convert_result_to_bool::<_, BLSError, _>(|| {
+        let hash = unsafe { raw::mutable(in_state) };
+        let domain = hash.domain;
+        let output_len = hash.output_len;
+        let obj_bytes = match hash.state.take().ok_or_else(already_finalized)? {
+            StreamingState::Direct(state) => {
+                let crh = DirectHasher.finalize(state)?;
+                DirectHasher.xof(domain, &crh, output_len)?
+            }
+            StreamingState::Composite(state) => {
+                let crh = COMPOSITE_HASHER.finalize(state)?;
+                COMPOSITE_HASHER.xof(domain, &crh, output_len)?
+            }
+        };
+        unsafe { raw::write_bytes(out_hash, out_len, obj_bytes) };
+        Ok(())
+    });
+    unsafe { raw::destroy(in_state) };
+    success
+}
+
+fn already_finalized() -> BLSError {
+    BLSError::HashingError("streaming hash was already finalized".into())
+}
+
+/// # Safety
+///
+/// This function must only be called on a valid StreamingHash instance pointer which has
+/// not been finalized.
+#[no_mangle]
+pub unsafe extern "C" fn destroy_streaming_hash(state: *mut StreamingHash) -> bool {
+    unsafe { raw::destroy(state) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn streaming_hash(use_composite: bool, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut state: *mut StreamingHash = std::ptr::null_mut();
+        assert!(streaming_hash_init(
+            use_composite,
+            false,
+            64,
+            &mut state as *mut *mut StreamingHash
+        ));
+        for chunk in chunks {
+            assert!(streaming_hash_update(
+                state,
+                chunk.as_ptr(),
+                chunk.len() as c_int
+            ));
+        }
+
+        let mut out_hash: *mut u8 = std::ptr::null_mut();
+        let mut out_len: c_int = 0;
+        assert!(unsafe {
+            streaming_hash_finalize(
+                state,
+                &mut out_hash as *mut *mut u8,
+                &mut out_len as *mut c_int,
+            )
+        });
+        unsafe { raw::take_bytes(out_hash, out_len) }
+    }
+
+    #[test]
+    fn streams_direct_hash() {
+        let data = (0..255).collect::<Vec<u8>>();
+        let streamed = streaming_hash(false, &[&data[..100], &data[100..]]);
+        assert_eq!(
+            streamed,
+            DirectHasher.hash(Celo::DOMAIN, &data, 64).unwrap()
+        );
+    }
+
+    #[test]
+    fn streams_composite_hash() {
+        let data = (0..1000).map(|i| i as u8).collect::<Vec<u8>>();
+        let streamed = streaming_hash(true, &[&data[..10], &data[10..600], &data[600..]]);
+        assert_eq!(
+            streamed,
+            COMPOSITE_HASHER.hash(Celo::DOMAIN, &data, 64).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        let mut state: *mut StreamingHash = std::ptr::null_mut();
+        assert!(!streaming_hash_init(
+            false,
+            false,
+            -1,
+            &mut state as *mut *mut StreamingHash
+        ));
+        assert!(state.is_null());
+
+        let data = [1u8; 4];
+        assert!(!streaming_hash_update(
+            std::ptr::null_mut(),
+            data.as_ptr(),
+            data.len() as c_int
+        ));
+
+        assert!(streaming_hash_init(
+            false,
+            false,
+            64,
+            &mut state as *mut *mut StreamingHash
+        ));
+        assert!(!streaming_hash_update(state, data.as_ptr(), -1));
+        assert!(unsafe { destroy_streaming_hash(state) });
+    }
+}
@@ -1,8 +1,13 @@
 /// FFI Utilities
 ///
 /// Utilities for working with variable length data structures.
-use super::{PublicKey, Signature};
-use std::slice;
+use super::{raw, PreparedPublicKey, PublicKey, Signature};
+use bls_crypto::{domain::DOMAIN_LENGTH, Domain};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Display},
+    mem,
+};
 
 /// A per-epoch block witness to be used with the batch sig verification
 #[derive(Clone, Debug, PartialEq)]
@@ -17,10 +22,83 @@ pub struct Message<'a> {
     pub sig: &'a Signature,
 }
 
+/// The layout version of `MessageFFI` implemented by this library
+pub const MESSAGE_FFI_VERSION: u32 = 1;
+
+/// Error raised when a `MessageFFI` does not have the layout expected by this library
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageFFIError {
+    /// The message declares a layout version which is not supported
+    UnsupportedVersion(u32),
+    /// The reserved bytes of the message are not zeroed
+    NonZeroReserved,
+    /// The message's domain is neither empty nor `DOMAIN_LENGTH` bytes long
+    InvalidDomainLength(usize),
+    /// The message's prepared public key is not its public key
+    PreparedKeyMismatch,
+    /// The pointer to the messages is not aligned for the message struct
+    Misaligned,
+    /// A pointer of the message is null
+    NullPointer,
+    /// A buffer of the message is longer than the address space
+    BufferTooLong(u64),
+}
+
+impl Display for MessageFFIError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageFFIError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported message version {}, expected {}",
+                version, MESSAGE_FFI_VERSION
+            ),
+            MessageFFIError::NonZeroReserved => write!(f, "message reserved bytes must be zero"),
+            MessageFFIError::InvalidDomainLength(len) => write!(
+                f,
+                "message domain has length {}, expected 0 or {}",
+                len, DOMAIN_LENGTH
+            ),
+            MessageFFIError::PreparedKeyMismatch => {
+                write!(
+                    f,
+                    "message prepared public key does not match its public key"
+                )
+            }
+            MessageFFIError::Misaligned => write!(f, "messages are not aligned"),
+            MessageFFIError::NullPointer => write!(f, "message has a null pointer"),
+            MessageFFIError::BufferTooLong(len) => {
+                write!(
+                    f,
+                    "message buffer has length {}, which does not fit in memory",
+                    len
+                )
+            }
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq)]
 /// Pointers to the necessary data for signature verification of an epoch
+///
+/// The layout is fixed and checked at compile time: on 64-bit targets the struct is 64 bytes
+/// long, 8-byte aligned, and its fields are at the offsets below. Any change to the layout
+/// must bump `MESSAGE_FFI_VERSION`.
+///
+/// | offset | size | field        |
+/// |--------|------|--------------|
+/// | 0      | 4    | `version`    |
+/// | 4      | 4    | `reserved`   |
+/// | 8      | 16   | `data`       |
+/// | 24     | 16   | `extra`      |
+/// | 40     | 8    | `public_key` |
+/// | 48     | 8    | `sig`        |
+/// | 56     | 8    | `padding`    |
 pub struct MessageFFI {
+    /// The layout version of the struct, must be `MESSAGE_FFI_VERSION`
+    pub version: u32,
+    /// Reserved for future use, must be zero
+    pub reserved: u32,
     /// Pointer to the data which was signed
     pub data: Buffer,
     /// Pointer to the extra data which was signed alongside the `data`
@@ -29,28 +107,291 @@ pub struct MessageFFI {
     pub public_key: *const PublicKey,
     /// Pointer to the aggregate signature corresponding the aggregate public key
     pub sig: *const Signature,
+    /// Reserved for future fields, must be zero
+    pub padding: [u8; 8],
 }
 
-impl<'a> From<&'a MessageFFI> for Message<'a> {
-    fn from(src: &'a MessageFFI) -> Message<'a> {
-        let data = <&[u8]>::from(&src.data);
-        let extra = <&[u8]>::from(&src.extra);
-        Message {
+// Compile time layout assertions, the build fails if the sizes below do not match
+#[cfg(target_pointer_width = "64")]
+const _: [(); 16] = [(); mem::size_of::<Buffer>()];
+#[cfg(target_pointer_width = "64")]
+const _: [(); 8] = [(); mem::align_of::<Buffer>()];
+#[cfg(target_pointer_width = "64")]
+const _: [(); 64] = [(); mem::size_of::<MessageFFI>()];
+#[cfg(target_pointer_width = "64")]
+const _: [(); 8] = [(); mem::align_of::<MessageFFI>()];
+
+impl MessageFFI {
+    /// Checks that the message has the layout version and zeroed reserved bytes expected
+    /// by this library
+    pub fn check_version(&self) -> Result<(), MessageFFIError> {
+        if self.version != MESSAGE_FFI_VERSION {
+            return Err(MessageFFIError::UnsupportedVersion(self.version));
+        }
+        if self.reserved != 0 || self.padding != [0; 8] {
+            return Err(MessageFFIError::NonZeroReserved);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> TryFrom<&'a MessageFFI> for Message<'a> {
+    type Error = MessageFFIError;
+
+    fn try_from(src: &'a MessageFFI) -> Result<Message<'a>, MessageFFIError> {
+        src.check_version()?;
+        if src.public_key.is_null() || src.sig.is_null() {
+            return Err(MessageFFIError::NullPointer);
+        }
+        let data = <&[u8]>::try_from(&src.data)?;
+        let extra = <&[u8]>::try_from(&src.extra)?;
+        Ok(Message {
             data,
             extra,
-            public_key: unsafe { &*src.public_key },
-            sig: unsafe { &*src.sig },
-        }
+            public_key: unsafe { raw::reference(src.public_key) },
+            sig: unsafe { raw::reference(src.sig) },
+        })
     }
 }
 
 impl From<&Message<'_>> for MessageFFI {
     fn from(src: &Message) -> MessageFFI {
         MessageFFI {
+            version: MESSAGE_FFI_VERSION,
+            reserved: 0,
             data: Buffer::from(src.data),
             extra: Buffer::from(src.extra),
             public_key: src.public_key as *const PublicKey,
             sig: src.sig as *const Signature,
+            padding: [0; 8],
+        }
+    }
+}
+
+/// A message with the optional fields of `MessageV2FFI`, which let the verification skip
+/// work the caller has already done
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageV2<'a> {
+    /// The data which was signed
+    pub data: &'a [u8],
+    /// Extra data which was signed alongside the `data`
+    pub extra: &'a [u8],
+    /// The domain the data was signed in
+    pub domain: Domain,
+    /// The aggregate public key of the epoch which signed the data/extra pair
+    pub public_key: &'a PublicKey,
+    /// The public key prepared for the Miller loop, if the caller holds one
+    pub prepared_public_key: Option<&'a PreparedPublicKey>,
+    /// The aggregate signature corresponding the aggregate public key
+    pub sig: &'a Signature,
+    /// Ignored, see `MessageV2FFI`
+    pub hash_hint: u32,
+}
+
+/// The layout version of `MessageV2FFI` implemented by this library
+pub const MESSAGE_V2_FFI_VERSION: u32 = 2;
+
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+/// Pointers to the data for signature verification of an epoch, with optional fields for
+/// the optimized verification paths. Zeroed optional fields select the behavior of
+/// `MessageFFI`.
+///
+/// - `hash_hint` is ignored, and is kept for the layout of existing callers. A hint cannot
+///   be trusted without hashing the earlier counters, which is what it was meant to skip, so
+///   every message is hashed from the first counter.
+/// - `domain` is empty for the `SIG_DOMAIN`, or an 8 byte domain.
+/// - `prepared_public_key` is either null or a handle from `prepare_public_key` for
+///   `public_key`.
+///
+/// The layout is fixed and checked at compile time: on 64-bit targets the struct is 88 bytes
+/// long, 8-byte aligned, and its fields are at the offsets below.
+///
+/// | offset | size | field                 |
+/// |--------|------|-----------------------|
+/// | 0      | 4    | `version`             |
+/// | 4      | 4    | `hash_hint`           |
+/// | 8      | 16   | `data`                |
+/// | 24     | 16   | `extra`               |
+/// | 40     | 16   | `domain`              |
+/// | 56     | 8    | `public_key`          |
+/// | 64     | 8    | `prepared_public_key` |
+/// | 72     | 8    | `sig`                 |
+/// | 80     | 8    | `reserved`            |
+pub struct MessageV2FFI {
+    /// The layout version of the struct, must be `MESSAGE_V2_FFI_VERSION`
+    pub version: u32,
+    /// Ignored, kept for the layout of existing callers
+    pub hash_hint: u32,
+    /// Pointer to the data which was signed
+    pub data: Buffer,
+    /// Pointer to the extra data which was signed alongside the `data`
+    pub extra: Buffer,
+    /// Pointer to the domain, empty for the `SIG_DOMAIN`
+    pub domain: Buffer,
+    /// Pointer to the aggregate public key of the epoch which signed the data/extra pair
+    pub public_key: *const PublicKey,
+    /// Pointer to the prepared public key, or null
+    pub prepared_public_key: *const PreparedPublicKey,
+    /// Pointer to the aggregate signature corresponding the aggregate public key
+    pub sig: *const Signature,
+    /// Reserved for future fields, must be zero
+    pub reserved: [u8; 8],
+}
+
+#[cfg(target_pointer_width = "64")]
+const _: [(); 88] = [(); mem::size_of::<MessageV2FFI>()];
+#[cfg(target_pointer_width = "64")]
+const _: [(); 8] = [(); mem::align_of::<MessageV2FFI>()];
+
+impl MessageV2FFI {
+    /// Checks that the message has the layout version and zeroed reserved bytes expected
+    /// by this library
+    pub fn check_version(&self) -> Result<(), MessageFFIError> {
+        if self.version != MESSAGE_V2_FFI_VERSION {
+            return Err(MessageFFIError::UnsupportedVersion(self.version));
+        }
+        if self.reserved != [0; 8] {
+            return Err(MessageFFIError::NonZeroReserved);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> TryFrom<&'a MessageV2FFI> for MessageV2<'a> {
+    type Error = MessageFFIError;
+
+    fn try_from(src: &'a MessageV2FFI) -> Result<MessageV2<'a>, MessageFFIError> {
+        src.check_version()?;
+        if src.public_key.is_null() || src.sig.is_null() {
+            return Err(MessageFFIError::NullPointer);
+        }
+        let domain = match <&[u8]>::try_from(&src.domain)? {
+            [] => Domain::SIG,
+            bytes => <[u8; DOMAIN_LENGTH]>::try_from(bytes)
+                .map(Domain::unchecked)
+                .map_err(|_| MessageFFIError::InvalidDomainLength(bytes.len()))?,
+        };
+        let public_key = unsafe { raw::reference(src.public_key) };
+        let prepared_public_key = unsafe { raw::optional(src.prepared_public_key) };
+        if prepared_public_key.map_or(false, |prepared| prepared.as_ref() != public_key) {
+            return Err(MessageFFIError::PreparedKeyMismatch);
+        }
+        Ok(MessageV2 {
+            data: <&[u8]>::try_from(&src.data)?,
+            extra: <&[u8]>::try_from(&src.extra)?,
+            domain,
+            public_key,
+            prepared_public_key,
+            sig: unsafe { raw::reference(src.sig) },
+            hash_hint: src.hash_hint,
+        })
+    }
+}
+
+impl From<&MessageV2<'_>> for MessageV2FFI {
+    fn from(src: &MessageV2) -> MessageV2FFI {
+        MessageV2FFI {
+            version: MESSAGE_V2_FFI_VERSION,
+            hash_hint: src.hash_hint,
+            data: Buffer::from(src.data),
+            extra: Buffer::from(src.extra),
+            domain: Buffer::from(src.domain.as_bytes()),
+            public_key: src.public_key as *const PublicKey,
+            prepared_public_key: src
+                .prepared_public_key
+                .map_or(std::ptr::null(), |pk| pk as *const PreparedPublicKey),
+            sig: src.sig as *const Signature,
+            reserved: [0; 8],
+        }
+    }
+}
+
+/// A proof of possession of a BLS key, as submitted when registering a validator
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pop<'a> {
+    /// The message which was signed, i.e. the validator's address
+    pub message: &'a [u8],
+    /// The public key whose possession is proven
+    pub public_key: &'a PublicKey,
+    /// The proof of possession, a signature over the message in the `POP_DOMAIN`
+    pub pop: &'a Signature,
+}
+
+/// The layout version of `PopFFI` implemented by this library
+pub const POP_FFI_VERSION: u32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+/// Pointers to the necessary data for verifying a proof of possession
+///
+/// The layout is fixed and checked at compile time: on 64-bit targets the struct is 40 bytes
+/// long, 8-byte aligned, and its fields are at the offsets below. Any change to the layout
+/// must bump `POP_FFI_VERSION`.
+///
+/// | offset | size | field        |
+/// |--------|------|--------------|
+/// | 0      | 4    | `version`    |
+/// | 4      | 4    | `reserved`   |
+/// | 8      | 16   | `message`    |
+/// | 24     | 8    | `public_key` |
+/// | 32     | 8    | `pop`        |
+pub struct PopFFI {
+    /// The layout version of the struct, must be `POP_FFI_VERSION`
+    pub version: u32,
+    /// Reserved for future use, must be zero
+    pub reserved: u32,
+    /// Pointer to the message which was signed
+    pub message: Buffer,
+    /// Pointer to the public key whose possession is proven
+    pub public_key: *const PublicKey,
+    /// Pointer to the proof of possession
+    pub pop: *const Signature,
+}
+
+#[cfg(target_pointer_width = "64")]
+const _: [(); 40] = [(); mem::size_of::<PopFFI>()];
+#[cfg(target_pointer_width = "64")]
+const _: [(); 8] = [(); mem::align_of::<PopFFI>()];
+
+impl PopFFI {
+    /// Checks that the proof has the layout version and zeroed reserved bytes expected by
+    /// this library
+    pub fn check_version(&self) -> Result<(), MessageFFIError> {
+        if self.version != POP_FFI_VERSION {
+            return Err(MessageFFIError::UnsupportedVersion(self.version));
+        }
+        if self.reserved != 0 {
+            return Err(MessageFFIError::NonZeroReserved);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> TryFrom<&'a PopFFI> for Pop<'a> {
+    type Error = MessageFFIError;
+
+    fn try_from(src: &'a PopFFI) -> Result<Pop<'a>, MessageFFIError> {
+        src.check_version()?;
+        if src.public_key.is_null() || src.pop.is_null() {
+            return Err(MessageFFIError::NullPointer);
+        }
+        Ok(Pop {
+            message: <&[u8]>::try_from(&src.message)?,
+            public_key: unsafe { raw::reference(src.public_key) },
+            pop: unsafe { raw::reference(src.pop) },
+        })
+    }
+}
+
+impl From<&Pop<'_>> for PopFFI {
+    fn from(src: &Pop) -> PopFFI {
+        PopFFI {
+            version: POP_FFI_VERSION,
+            reserved: 0,
+            message: Buffer::from(src.message),
+            public_key: src.public_key as *const PublicKey,
+            pop: src.pop as *const Signature,
         }
     }
 }
@@ -62,21 +403,28 @@ pub struct Buffer {
     /// Pointer to the message
     pub ptr: *const u8,
     /// The length of the buffer
-    pub len: usize,
+    pub len: u64,
 }
 
 impl From<&[u8]> for Buffer {
     fn from(src: &[u8]) -> Self {
         Self {
-            ptr: &src[0] as *const u8,
-            len: src.len(),
+            ptr: src.as_ptr(),
+            len: src.len() as u64,
         }
     }
 }
 
-impl<'a> From<&Buffer> for &'a [u8] {
-    fn from(src: &Buffer) -> &'a [u8] {
-        unsafe { slice::from_raw_parts(src.ptr, src.len) }
+impl<'a> TryFrom<&Buffer> for &'a [u8] {
+    type Error = MessageFFIError;
+
+    /// Fails if the buffer is null with a nonzero length, or does not fit in memory
+    fn try_from(src: &Buffer) -> Result<&'a [u8], MessageFFIError> {
+        let len = usize::try_from(src.len).map_err(|_| MessageFFIError::BufferTooLong(src.len))?;
+        if src.ptr.is_null() && len != 0 {
+            return Err(MessageFFIError::NullPointer);
+        }
+        Ok(unsafe { raw::slice(src.ptr, len) })
     }
 }
 
@@ -93,8 +441,36 @@ mod tests {
         let buf = vec![1u8, 2, 3, 4];
         let buffer = Buffer::from(buf.as_ref());
         assert_eq!(buffer.len, 4);
-        let de: &[u8] = <&[u8]>::from(&buffer);
+        let de: &[u8] = <&[u8]>::try_from(&buffer).unwrap();
         assert_eq!(buf.as_ref() as &[u8], de);
+
+        let null = Buffer {
+            ptr: std::ptr::null(),
+            len: 4,
+        };
+        assert_eq!(
+            <&[u8]>::try_from(&null).unwrap_err(),
+            MessageFFIError::NullPointer
+        );
+        let empty = Buffer {
+            ptr: std::ptr::null(),
+            len: 0,
+        };
+        assert!(<&[u8]>::try_from(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "32")]
+    fn buffer_rejects_lengths_beyond_the_address_space() {
+        let buf = [0u8; 4];
+        let buffer = Buffer {
+            ptr: buf.as_ptr(),
+            len: u64::from(u32::MAX) + 1,
+        };
+        assert_eq!(
+            <&[u8]>::try_from(&buffer).unwrap_err(),
+            MessageFFIError::BufferTooLong(u64::from(u32::MAX) + 1)
+        );
     }
 
     #[test]
@@ -113,7 +489,162 @@ mod tests {
         };
         let m = msg.clone();
         let msg_ffi = MessageFFI::from(&m);
-        let original = Message::from(&msg_ffi);
+        let original = Message::try_from(&msg_ffi).unwrap();
         assert_eq!(msg, original);
     }
+
+    #[test]
+    fn msg_rejects_other_versions() {
+        let rng = &mut rand::thread_rng();
+        let public_key = PublicKey::from(G2Projective::rand(rng));
+        let sig = Signature::from(G1Projective::rand(rng));
+        let msg = Message {
+            data: &[1, 2, 3, 4],
+            extra: &[5, 6, 7, 8],
+            public_key: &public_key,
+            sig: &sig,
+        };
+
+        let mut msg_ffi = MessageFFI::from(&msg);
+        msg_ffi.version = MESSAGE_FFI_VERSION + 1;
+        assert_eq!(
+            Message::try_from(&msg_ffi).unwrap_err(),
+            MessageFFIError::UnsupportedVersion(MESSAGE_FFI_VERSION + 1)
+        );
+
+        let mut msg_ffi = MessageFFI::from(&msg);
+        msg_ffi.padding[3] = 1;
+        assert_eq!(
+            Message::try_from(&msg_ffi).unwrap_err(),
+            MessageFFIError::NonZeroReserved
+        );
+
+        let mut msg_ffi = MessageFFI::from(&msg);
+        msg_ffi.sig = std::ptr::null();
+        assert_eq!(
+            Message::try_from(&msg_ffi).unwrap_err(),
+            MessageFFIError::NullPointer
+        );
+
+        let mut msg_ffi = MessageFFI::from(&msg);
+        msg_ffi.data.ptr = std::ptr::null();
+        assert_eq!(
+            Message::try_from(&msg_ffi).unwrap_err(),
+            MessageFFIError::NullPointer
+        );
+    }
+
+    #[test]
+    fn msg_v2_convert_ok() {
+        let rng = &mut rand::thread_rng();
+        let public_key = PublicKey::from(G2Projective::rand(rng));
+        let prepared = PreparedPublicKey::from(&public_key);
+        let sig = Signature::from(G1Projective::rand(rng));
+        let msg = MessageV2 {
+            data: &[1, 2, 3, 4],
+            extra: &[5, 6, 7, 8],
+            domain: Domain::new(b"myapp_v1").unwrap(),
+            public_key: &public_key,
+            prepared_public_key: Some(&prepared),
+            sig: &sig,
+            hash_hint: 3,
+        };
+        let msg_ffi = MessageV2FFI::from(&msg);
+        assert_eq!(MessageV2::try_from(&msg_ffi).unwrap(), msg);
+
+        // zeroed optional fields
+        let mut msg_ffi = MessageV2FFI::from(&msg);
+        msg_ffi.domain = Buffer::from(&b""[..]);
+        msg_ffi.prepared_public_key = std::ptr::null();
+        let converted = MessageV2::try_from(&msg_ffi).unwrap();
+        assert_eq!(converted.domain, Domain::SIG);
+        assert_eq!(converted.prepared_public_key, None);
+
+        let other = PreparedPublicKey::from(&PublicKey::from(G2Projective::rand(rng)));
+        msg_ffi.prepared_public_key = &other as *const PreparedPublicKey;
+        assert_eq!(
+            MessageV2::try_from(&msg_ffi).unwrap_err(),
+            MessageFFIError::PreparedKeyMismatch
+        );
+        msg_ffi.prepared_public_key = std::ptr::null();
+
+        msg_ffi.domain = Buffer::from(&b"short"[..]);
+        assert_eq!(
+            MessageV2::try_from(&msg_ffi).unwrap_err(),
+            MessageFFIError::InvalidDomainLength(5)
+        );
+        msg_ffi.version = MESSAGE_FFI_VERSION;
+        assert_eq!(
+            MessageV2::try_from(&msg_ffi).unwrap_err(),
+            MessageFFIError::UnsupportedVersion(MESSAGE_FFI_VERSION)
+        );
+    }
+
+    #[test]
+    fn pop_convert_ok() {
+        let rng = &mut rand::thread_rng();
+        let public_key = PublicKey::from(G2Projective::rand(rng));
+        let sig = Signature::from(G1Projective::rand(rng));
+        let pop = Pop {
+            message: &[1, 2, 3],
+            public_key: &public_key,
+            pop: &sig,
+        };
+        let pop_ffi = PopFFI::from(&pop);
+        assert_eq!(Pop::try_from(&pop_ffi).unwrap(), pop);
+
+        let mut pop_ffi = PopFFI::from(&pop);
+        pop_ffi.reserved = 1;
+        assert_eq!(
+            Pop::try_from(&pop_ffi).unwrap_err(),
+            MessageFFIError::NonZeroReserved
+        );
+
+        let nulls: [fn(&mut PopFFI); 3] = [
+            |pop| pop.public_key = std::ptr::null(),
+            |pop| pop.pop = std::ptr::null(),
+            |pop| pop.message.ptr = std::ptr::null(),
+        ];
+        for null in &nulls {
+            let mut pop_ffi = PopFFI::from(&pop);
+            null(&mut pop_ffi);
+            assert_eq!(
+                Pop::try_from(&pop_ffi).unwrap_err(),
+                MessageFFIError::NullPointer
+            );
+        }
+        // an empty message may be null
+        let mut pop_ffi = PopFFI::from(&Pop {
+            message: &[],
+            ..pop
+        });
+        pop_ffi.message.ptr = std::ptr::null();
+        assert_eq!(Pop::try_from(&pop_ffi).unwrap().message, &[] as &[u8]);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn msg_layout() {
+        fn offset(msg: &MessageFFI, field: *const u8) -> usize {
+            field as usize - msg as *const MessageFFI as usize
+        }
+
+        let msg = MessageFFI {
+            version: MESSAGE_FFI_VERSION,
+            reserved: 0,
+            data: Buffer::from(&[1u8][..]),
+            extra: Buffer::from(&[2u8][..]),
+            public_key: std::ptr::null(),
+            sig: std::ptr::null(),
+            padding: [0; 8],
+        };
+        assert_eq!(offset(&msg, &msg.version as *const _ as *const u8), 0);
+        assert_eq!(offset(&msg, &msg.reserved as *const _ as *const u8), 4);
+        assert_eq!(offset(&msg, &msg.data as *const _ as *const u8), 8);
+        assert_eq!(offset(&msg, &msg.data.len as *const _ as *const u8), 16);
+        assert_eq!(offset(&msg, &msg.extra as *const _ as *const u8), 24);
+        assert_eq!(offset(&msg, &msg.public_key as *const _ as *const u8), 40);
+        assert_eq!(offset(&msg, &msg.sig as *const _ as *const u8), 48);
+        assert_eq!(offset(&msg, &msg.padding as *const _ as *const u8), 56);
+    }
 }
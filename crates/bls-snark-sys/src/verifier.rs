@@ -0,0 +1,247 @@
+//! Batch verification sessions, for signatures which arrive over time.
+//!
+//! A session accumulates (message, public key, signature) entries across calls, hashing each
+//! message as it is added, and `verifier_finalize` checks all the entries added since the
+//! previous check with a single batch verification. This suits callers which receive blocks
+//! continuously and verify them once per tick, instead of holding every message until one
+//! `batch_verify_signature` call.
+use crate::{
+    convert_result_to_bool, raw, signatures::MAX_BATCH_SIZE, PublicKey, Signature,
+    COMPOSITE_HASH_TO_G1, DIRECT_HASH_TO_G1,
+};
+use algebra::{
+    bls12_377::{G1Projective, Parameters},
+    curves::models::bls12::Bls12Parameters,
+    Zero,
+};
+use bls_crypto::{
+    bls::{BlsScheme, Celo},
+    hash_to_curve::try_and_increment::TryAndIncrement,
+    hashers::{
+        composite::{CompositeHasher, CRH},
+        DirectHasher,
+    },
+    BLSError, BatchVerifier,
+};
+use std::os::raw::c_int;
+
+type G1Parameters = <Parameters as Bls12Parameters>::G1Parameters;
+type Batch<H> = BatchVerifier<'static, TryAndIncrement<'static, H, G1Parameters>>;
+
+enum Entries {
+    Direct(Batch<DirectHasher>),
+    Composite(Batch<CompositeHasher<CRH>>),
+}
+
+impl Entries {
+    fn new(use_composite: bool) -> Self {
+        if use_composite {
+            Entries::Composite(BatchVerifier::new(Celo::DOMAIN, &*COMPOSITE_HASH_TO_G1))
+        } else {
+            Entries::Direct(BatchVerifier::new(Celo::DOMAIN, &*DIRECT_HASH_TO_G1))
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Entries::Direct(batch) => batch.len(),
+            Entries::Composite(batch) => batch.len(),
+        }
+    }
+}
+
+/// The entries added to a batch verification session since its last check, and the
+/// aggregate of their signatures
+pub struct VerifierSession {
+    use_composite: bool,
+    entries: Entries,
+    signature: G1Projective,
+}
+
+impl VerifierSession {
+    fn new(use_composite: bool) -> Self {
+        Self {
+            use_composite,
+            entries: Entries::new(use_composite),
+            signature: G1Projective::zero(),
+        }
+    }
+
+    fn add(
+        &mut self,
+        public_key: &PublicKey,
+        message: &[u8],
+        extra_data: &[u8],
+        signature: &Signature,
+    ) -> Result<(), BLSError> {
+        if self.entries.len() >= MAX_BATCH_SIZE {
+            return Err(BLSError::InvalidBatchEntries(vec![(
+                self.entries.len(),
+                "the session is full, it must be finalized first",
+            )]));
+        }
+        match &mut self.entries {
+            Entries::Direct(batch) => batch.add_transient(public_key, message, extra_data)?,
+            Entries::Composite(batch) => batch.add_transient(public_key, message, extra_data)?,
+        }
+        // only aggregated once the message was hashed successfully
+        self.signature += signature.as_ref();
+        Ok(())
+    }
+
+    // Verifies the entries and starts over with an empty session. An empty session is not
+    // verified, as with `Signature::aggregate_verify`.
+    fn finalize(&mut self) -> bool {
+        let use_composite = self.use_composite;
+        let session = std::mem::replace(self, Self::new(use_composite));
+        let signature = Signature::from(session.signature);
+        match session.entries {
+            Entries::Direct(batch) => !batch.is_empty() && batch.verify(&signature).is_ok(),
+            Entries::Composite(batch) => !batch.is_empty() && batch.verify(&signature).is_ok(),
+        }
+    }
+}
+
+#[no_mangle]
+/// Starts a batch verification session for messages signed with the `Celo` scheme. The handle
+/// must be freed with `destroy_verifier`.
+pub extern "C" fn verifier_new(
+    use_composite: bool,
+    out_verifier: *mut *mut VerifierSession,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        unsafe { raw::write_boxed(out_verifier, VerifierSession::new(use_composite)) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+/// Adds a message, the public key which signed it and its signature to the session. The
+/// message is hashed by this call, so its buffers only need to be valid for its duration.
+///
+/// Fails without adding the entry if the message is longer than the hasher accepts, or if
+/// the session already holds `MAX_BATCH_SIZE` entries.
+pub extern "C" fn verifier_add(
+    in_verifier: *mut VerifierSession,
+    in_message: *const u8,
+    in_message_len: c_int,
+    in_extra_data: *const u8,
+    in_extra_data_len: c_int,
+    in_public_key: *const PublicKey,
+    in_signature: *const Signature,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let session = unsafe { raw::mutable(in_verifier) };
+        let message = unsafe { raw::bytes(in_message, in_message_len) };
+        let extra_data = unsafe { raw::bytes(in_extra_data, in_extra_data_len) };
+        let public_key = unsafe { raw::reference(in_public_key) };
+        let signature = unsafe { raw::reference(in_signature) };
+        session.add(public_key, message, extra_data, signature)
+    })
+}
+
+#[no_mangle]
+/// Verifies the aggregate of the signatures added since the previous call against their
+/// messages and public keys, with one batch verification, and writes the result to
+/// `out_verified`. The session is then emptied and can be used for the next entries.
+///
+/// A session without entries is reported as not verified.
+pub extern "C" fn verifier_finalize(
+    in_verifier: *mut VerifierSession,
+    out_verified: *mut bool,
+) -> bool {
+    convert_result_to_bool::<_, BLSError, _>(|| {
+        let session = unsafe { raw::mutable(in_verifier) };
+        // an invalid pointer fails here, before the entries are discarded
+        unsafe { raw::write(out_verified, false) };
+        unsafe { raw::write(out_verified, session.finalize()) };
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// This function must only be called on a valid VerifierSession instance pointer.
+#[no_mangle]
+pub unsafe extern "C" fn destroy_verifier(verifier: *mut VerifierSession) -> bool {
+    unsafe { raw::destroy(verifier) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrivateKey;
+
+    fn new_session(use_composite: bool) -> *mut VerifierSession {
+        let mut session: *mut VerifierSession = std::ptr::null_mut();
+        assert!(verifier_new(
+            use_composite,
+            &mut session as *mut *mut VerifierSession
+        ));
+        session
+    }
+
+    fn add(
+        session: *mut VerifierSession,
+        message: &[u8],
+        key: &PrivateKey,
+        signature: &Signature,
+    ) -> bool {
+        verifier_add(
+            session,
+            message.as_ptr(),
+            message.len() as c_int,
+            std::ptr::null(),
+            0,
+            &key.to_public() as *const PublicKey,
+            signature as *const Signature,
+        )
+    }
+
+    fn finalize(session: *mut VerifierSession) -> bool {
+        let mut verified = false;
+        assert!(verifier_finalize(session, &mut verified as *mut bool));
+        verified
+    }
+
+    fn sign(use_composite: bool, key: &PrivateKey, message: &[u8]) -> Signature {
+        if use_composite {
+            key.sign(message, &[], &*COMPOSITE_HASH_TO_G1).unwrap()
+        } else {
+            key.sign(message, &[], &*DIRECT_HASH_TO_G1).unwrap()
+        }
+    }
+
+    #[test]
+    fn verifies_entries_in_ticks() {
+        let rng = &mut rand::thread_rng();
+        for use_composite in &[false, true] {
+            let use_composite = *use_composite;
+            let session = new_session(use_composite);
+            let keys = (0..3)
+                .map(|_| PrivateKey::generate(rng))
+                .collect::<Vec<_>>();
+
+            // the first tick, with a repeated message
+            let messages = [&b"block 1"[..], b"block 1", b"block 2"];
+            for (key, message) in keys.iter().zip(&messages) {
+                let signature = sign(use_composite, key, message);
+                assert!(add(session, message, key, &signature));
+            }
+            assert!(finalize(session));
+
+            // the session was emptied
+            assert!(!finalize(session));
+
+            // a tick with an invalid signature fails, but the next one is unaffected
+            let forged = sign(use_composite, &keys[1], b"block 3");
+            assert!(add(session, b"block 3", &keys[0], &forged));
+            assert!(!finalize(session));
+            let signature = sign(use_composite, &keys[0], b"block 3");
+            assert!(add(session, b"block 3", &keys[0], &signature));
+            assert!(finalize(session));
+
+            assert!(unsafe { destroy_verifier(session) });
+        }
+    }
+}
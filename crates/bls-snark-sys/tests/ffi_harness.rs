@@ -0,0 +1,1114 @@
+//! Loads the compiled library the way the celo-blockchain Go bindings do and exercises every
+//! exported symbol with valid and adversarial arguments: null pointers, zero lengths,
+//! misaligned structs and malformed encodings. A crash of the library aborts the test
+//! process, otherwise each call must fail cleanly with the expected return value and error
+//! code.
+//!
+//! The library is loaded from the path in the `BLS_SNARK_SYS_LIB` environment variable if it
+//! is set, and otherwise from the target directory the test was built in, e.g.
+//!
+//! ```bash
+//! cargo test -p bls-snark-sys --test ffi_harness
+//! BLS_SNARK_SYS_LIB=/path/to/libbls_snark_sys.so cargo test -p bls-snark-sys --test ffi_harness
+//! ```
+//!
+//! The structs below are declared from their documented layouts rather than imported from
+//! the crate, as a foreign consumer would.
+use libloading::{Library, Symbol};
+use std::{
+    env::{
+        self,
+        consts::{DLL_PREFIX, DLL_SUFFIX},
+    },
+    ffi::c_void,
+    mem,
+    os::raw::{c_int, c_uint, c_ushort},
+    path::PathBuf,
+    ptr,
+};
+
+type Handle = *mut c_void;
+
+const NULL: Handle = ptr::null_mut();
+
+const MESSAGE_FFI_VERSION: u32 = 1;
+const MESSAGE_V2_FFI_VERSION: u32 = 2;
+const POP_FFI_VERSION: u32 = 1;
+
+const BATCH_OK: c_int = 0;
+const BATCH_ERROR_TOO_MANY_MESSAGES: c_int = 1;
+const BATCH_ERROR_COST_EXCEEDED: c_int = 2;
+const BATCH_ERROR_INVALID_MESSAGE: c_int = 3;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Buffer {
+    ptr: *const u8,
+    len: u64,
+}
+
+impl Buffer {
+    fn new(bytes: &[u8]) -> Self {
+        Self {
+            ptr: bytes.as_ptr(),
+            len: bytes.len() as u64,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MessageFFI {
+    version: u32,
+    reserved: u32,
+    data: Buffer,
+    extra: Buffer,
+    public_key: Handle,
+    sig: Handle,
+    padding: [u8; 8],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MessageV2FFI {
+    version: u32,
+    hash_hint: u32,
+    data: Buffer,
+    extra: Buffer,
+    domain: Buffer,
+    public_key: Handle,
+    prepared_public_key: Handle,
+    sig: Handle,
+    reserved: [u8; 8],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PopFFI {
+    version: u32,
+    reserved: u32,
+    message: Buffer,
+    public_key: Handle,
+    pop: Handle,
+}
+
+#[repr(C)]
+struct EpochBlockFFI {
+    index: u16,
+    pubkeys: *const u8,
+    pubkeys_num: usize,
+    maximum_non_signers: u32,
+}
+
+type Deserialize = unsafe extern "C" fn(*const u8, c_int, *mut Handle) -> bool;
+type DeserializeWithValidation = unsafe extern "C" fn(*const u8, c_int, c_int, *mut Handle) -> bool;
+type Serialize = unsafe extern "C" fn(Handle, *mut *mut u8, *mut c_int) -> bool;
+type Compress = unsafe extern "C" fn(*const u8, c_int, *mut *mut u8, *mut c_int) -> bool;
+type Destroy = unsafe extern "C" fn(Handle) -> bool;
+type FreeVec = unsafe extern "C" fn(*mut u8, c_int) -> bool;
+type GeneratePrivateKey = unsafe extern "C" fn(*mut Handle) -> bool;
+type PrivateKeyFromSeed =
+    unsafe extern "C" fn(*const u8, c_int, *const u8, c_int, *mut Handle) -> bool;
+type ToPublicKey = unsafe extern "C" fn(Handle, *mut Handle) -> bool;
+type SignMessage =
+    unsafe extern "C" fn(Handle, *const u8, c_int, *const u8, c_int, bool, *mut Handle) -> bool;
+type SignPop = unsafe extern "C" fn(Handle, *const u8, c_int, *mut Handle) -> bool;
+type HashDirect = unsafe extern "C" fn(*const u8, c_int, *mut *mut u8, *mut c_int, bool) -> bool;
+type HashComposite =
+    unsafe extern "C" fn(*const u8, c_int, *const u8, c_int, *mut *mut u8, *mut c_int) -> bool;
+type VerifySignature = unsafe extern "C" fn(
+    Handle,
+    *const u8,
+    c_int,
+    *const u8,
+    c_int,
+    Handle,
+    bool,
+    *mut bool,
+) -> bool;
+type BatchVerify = unsafe extern "C" fn(*const MessageFFI, usize, bool, *mut bool) -> bool;
+type BatchVerifyBounded = unsafe extern "C" fn(
+    *const MessageFFI,
+    usize,
+    bool,
+    usize,
+    usize,
+    *mut bool,
+    *mut c_int,
+) -> bool;
+type BatchVerifyV2 = unsafe extern "C" fn(
+    *const MessageV2FFI,
+    usize,
+    bool,
+    usize,
+    usize,
+    *mut bool,
+    *mut c_int,
+) -> bool;
+type VerifyPop = unsafe extern "C" fn(Handle, *const u8, c_int, Handle, *mut bool) -> bool;
+type VerifyPops = unsafe extern "C" fn(*const PopFFI, usize, *mut bool) -> bool;
+type Aggregate = unsafe extern "C" fn(*const Handle, c_int, *mut Handle) -> bool;
+type AggregateSubtract = unsafe extern "C" fn(Handle, *const Handle, c_int, *mut Handle) -> bool;
+type EncodeEpochBlock = unsafe extern "C" fn(
+    c_ushort,
+    c_uint,
+    *const Handle,
+    c_int,
+    bool,
+    *mut *mut u8,
+    *mut c_int,
+) -> bool;
+type VerifyProof =
+    unsafe extern "C" fn(*const u8, u32, *const u8, u32, EpochBlockFFI, EpochBlockFFI) -> bool;
+type StreamingHashInit = unsafe extern "C" fn(bool, bool, c_int, *mut Handle) -> bool;
+type StreamingHashUpdate = unsafe extern "C" fn(Handle, *const u8, c_int) -> bool;
+type VerifierNew = unsafe extern "C" fn(bool, *mut Handle) -> bool;
+type VerifierAdd =
+    unsafe extern "C" fn(Handle, *const u8, c_int, *const u8, c_int, Handle, Handle) -> bool;
+type VerifierFinalize = unsafe extern "C" fn(Handle, *mut bool) -> bool;
+
+/// Every symbol exported by the library
+const SYMBOLS: &[&str] = &[
+    "init",
+    "core_forbids_unsafe",
+    "generate_private_key",
+    "private_key_from_seed",
+    "private_key_to_public_key",
+    "sign_message",
+    "sign_pop",
+    "sign_key_pop",
+    "set_sign_callback",
+    "hash_direct",
+    "hash_composite",
+    "verify_signature",
+    "batch_verify_signature",
+    "batch_verify_signature_bounded",
+    "batch_verify_signature_v2",
+    "prepare_public_key",
+    "verify_pop",
+    "verify_key_pop",
+    "verify_pops",
+    "aggregate_public_keys",
+    "aggregate_public_keys_with_pop_check",
+    "aggregate_public_keys_subtract",
+    "aggregate_signatures",
+    "max_non_signers",
+    "is_quorum",
+    "new_quorum_certificate",
+    "deserialize_quorum_certificate",
+    "serialize_quorum_certificate",
+    "quorum_certificate_epoch_id",
+    "quorum_certificate_message",
+    "quorum_certificate_bitmap",
+    "quorum_certificate_signature",
+    "verify_quorum_certificate",
+    "destroy_quorum_certificate",
+    "encode_epoch_block_to_bytes",
+    "verify",
+    "deserialize_parameters",
+    "serialize_verifying_key",
+    "prove",
+    "destroy_parameters",
+    "deserialize_private_key",
+    "serialize_private_key",
+    "deserialize_public_key",
+    "deserialize_public_key_cached",
+    "deserialize_public_key_with_validation",
+    "deserialize_public_key_cached_with_validation",
+    "serialize_public_key",
+    "deserialize_signature",
+    "deserialize_signature_with_validation",
+    "serialize_signature",
+    "deserialize_public_key_with_profile",
+    "serialize_public_key_with_profile",
+    "deserialize_signature_with_profile",
+    "serialize_signature_with_profile",
+    "compress_signature",
+    "compress_pubkey",
+    "destroy_private_key",
+    "free_vec",
+    "destroy_public_key",
+    "destroy_prepared_public_key",
+    "destroy_signature",
+    "streaming_hash_init",
+    "streaming_hash_update",
+    "streaming_hash_finalize",
+    "destroy_streaming_hash",
+    "verifier_new",
+    "verifier_add",
+    "verifier_finalize",
+    "destroy_verifier",
+];
+
+struct Ffi {
+    lib: Library,
+}
+
+impl Ffi {
+    fn load() -> Self {
+        let path = env::var_os("BLS_SNARK_SYS_LIB")
+            .map(PathBuf::from)
+            .unwrap_or_else(default_path);
+        let lib = Library::new(&path)
+            .unwrap_or_else(|e| panic!("could not load {}: {}", path.display(), e));
+        let ffi = Self { lib };
+        unsafe { ffi.get::<unsafe extern "C" fn()>("init")() };
+        ffi
+    }
+
+    // Looks up an exported symbol, failing the test if it is missing
+    fn get<T>(&self, name: &str) -> Symbol<T> {
+        unsafe { self.lib.get(format!("{}\0", name).as_bytes()) }
+            .unwrap_or_else(|e| panic!("missing symbol {}: {}", name, e))
+    }
+
+    fn generate_private_key(&self) -> Handle {
+        let mut key = NULL;
+        assert!(unsafe { self.get::<GeneratePrivateKey>("generate_private_key")(&mut key) });
+        key
+    }
+
+    fn public_key(&self, private_key: Handle) -> Handle {
+        let mut public_key = NULL;
+        let to_public = self.get::<ToPublicKey>("private_key_to_public_key");
+        assert!(unsafe { to_public(private_key, &mut public_key) });
+        public_key
+    }
+
+    fn sign(&self, private_key: Handle, message: &[u8]) -> Handle {
+        let mut signature = NULL;
+        let sign = self.get::<SignMessage>("sign_message");
+        assert!(unsafe {
+            sign(
+                private_key,
+                message.as_ptr(),
+                message.len() as c_int,
+                ptr::null(),
+                0,
+                false,
+                &mut signature,
+            )
+        });
+        signature
+    }
+
+    // Calls a function returning bytes owned by the library, and frees them after copying
+    fn bytes(&self, f: impl FnOnce(*mut *mut u8, *mut c_int) -> bool) -> Option<Vec<u8>> {
+        let mut bytes = ptr::null_mut();
+        let mut len = 0;
+        if !f(&mut bytes, &mut len) {
+            return None;
+        }
+        let copy = unsafe { std::slice::from_raw_parts(bytes, len as usize) }.to_vec();
+        assert!(unsafe { self.get::<FreeVec>("free_vec")(bytes, len) });
+        Some(copy)
+    }
+
+    fn serialize(&self, name: &str, handle: Handle) -> Vec<u8> {
+        let serialize = self.get::<Serialize>(name);
+        self.bytes(|out, len| unsafe { serialize(handle, out, len) })
+            .unwrap()
+    }
+
+    fn destroy(&self, name: &str, handle: Handle) {
+        assert!(unsafe { self.get::<Destroy>(name)(handle) });
+    }
+}
+
+// The test binary is in `target/<profile>/deps`, the library in `target/<profile>`
+fn default_path() -> PathBuf {
+    let mut path = env::current_exe().unwrap();
+    path.pop();
+    path.pop();
+    path.push(format!("{}bls_snark_sys{}", DLL_PREFIX, DLL_SUFFIX));
+    path
+}
+
+// Returns a pointer one byte past an 8-byte aligned buffer large enough for a `T`
+fn misaligned<T>(storage: &mut Vec<u64>) -> *const T {
+    storage.resize(mem::size_of::<T>() / 8 + 2, 0);
+    (storage.as_ptr() as usize + 1) as *const T
+}
+
+fn message_ffi(data: &[u8], public_key: Handle, sig: Handle) -> MessageFFI {
+    MessageFFI {
+        version: MESSAGE_FFI_VERSION,
+        reserved: 0,
+        data: Buffer::new(data),
+        extra: Buffer::new(&[]),
+        public_key,
+        sig,
+        padding: [0; 8],
+    }
+}
+
+#[test]
+fn exports_every_symbol() {
+    let ffi = Ffi::load();
+    for name in SYMBOLS {
+        ffi.get::<*const c_void>(name);
+    }
+    let forbids = ffi.get::<unsafe extern "C" fn() -> bool>("core_forbids_unsafe");
+    unsafe { forbids() };
+}
+
+#[test]
+fn signs_and_verifies() {
+    let ffi = Ffi::load();
+    let message = b"hello";
+    let private_key = ffi.generate_private_key();
+    let public_key = ffi.public_key(private_key);
+    let signature = ffi.sign(private_key, message);
+
+    let verify = ffi.get::<VerifySignature>("verify_signature");
+    for (data, expected) in &[(&b"hello"[..], true), (&b"other"[..], false)] {
+        let mut verified = !expected;
+        assert!(unsafe {
+            verify(
+                public_key,
+                data.as_ptr(),
+                data.len() as c_int,
+                ptr::null(),
+                0,
+                signature,
+                false,
+                &mut verified,
+            )
+        });
+        assert_eq!(verified, *expected);
+    }
+
+    let mut pop = NULL;
+    let sign_pop = ffi.get::<SignPop>("sign_pop");
+    assert!(unsafe { sign_pop(private_key, message.as_ptr(), 5, &mut pop) });
+    let mut verified = false;
+    let verify_pop = ffi.get::<VerifyPop>("verify_pop");
+    assert!(unsafe { verify_pop(public_key, message.as_ptr(), 5, pop, &mut verified) });
+    assert!(verified);
+
+    let pops = [PopFFI {
+        version: POP_FFI_VERSION,
+        reserved: 0,
+        message: Buffer::new(message),
+        public_key,
+        pop,
+    }];
+    let mut verified = false;
+    let verify_pops = ffi.get::<VerifyPops>("verify_pops");
+    assert!(unsafe { verify_pops(pops.as_ptr(), 1, &mut verified) });
+    assert!(verified);
+
+    let mut prepared = NULL;
+    let prepare = ffi.get::<ToPublicKey>("prepare_public_key");
+    assert!(unsafe { prepare(public_key, &mut prepared) });
+    let messages = [MessageV2FFI {
+        version: MESSAGE_V2_FFI_VERSION,
+        hash_hint: 0,
+        data: Buffer::new(message),
+        extra: Buffer::new(&[]),
+        domain: Buffer::new(&[]),
+        public_key,
+        prepared_public_key: prepared,
+        sig: signature,
+        reserved: [0; 8],
+    }];
+    let (mut verified, mut code) = (false, -1);
+    let batch_verify_v2 = ffi.get::<BatchVerifyV2>("batch_verify_signature_v2");
+    assert!(unsafe {
+        batch_verify_v2(
+            messages.as_ptr(),
+            1,
+            false,
+            1,
+            1 << 20,
+            &mut verified,
+            &mut code,
+        )
+    });
+    assert!(verified);
+    assert_eq!(code, BATCH_OK);
+
+    let messages = [message_ffi(message, public_key, signature)];
+    let mut verified = false;
+    let batch_verify = ffi.get::<BatchVerify>("batch_verify_signature");
+    assert!(unsafe { batch_verify(messages.as_ptr(), 1, false, &mut verified) });
+    assert!(verified);
+
+    ffi.destroy("destroy_prepared_public_key", prepared);
+    ffi.destroy("destroy_signature", pop);
+    ffi.destroy("destroy_signature", signature);
+    ffi.destroy("destroy_public_key", public_key);
+    ffi.destroy("destroy_private_key", private_key);
+}
+
+#[test]
+fn serializes_and_aggregates() {
+    let ffi = Ffi::load();
+    let private_key = ffi.generate_private_key();
+    let other_key = ffi.generate_private_key();
+    let public_key = ffi.public_key(private_key);
+    let other_public_key = ffi.public_key(other_key);
+    let signature = ffi.sign(private_key, b"hello");
+
+    for (kind, handle) in &[
+        ("private_key", private_key),
+        ("public_key", public_key),
+        ("signature", signature),
+    ] {
+        let bytes = ffi.serialize(&format!("serialize_{}", kind), *handle);
+        let mut decoded = NULL;
+        let deserialize = ffi.get::<Deserialize>(&format!("deserialize_{}", kind));
+        assert!(unsafe { deserialize(bytes.as_ptr(), bytes.len() as c_int, &mut decoded) });
+        assert_eq!(
+            ffi.serialize(&format!("serialize_{}", kind), decoded),
+            bytes
+        );
+        ffi.destroy(&format!("destroy_{}", kind), decoded);
+    }
+
+    for (kind, handle) in &[("public_key", public_key), ("signature", signature)] {
+        let bytes = ffi.serialize(&format!("serialize_{}", kind), *handle);
+        let deserialize =
+            ffi.get::<DeserializeWithValidation>(&format!("deserialize_{}_with_validation", kind));
+        for level in 0..3 {
+            let mut decoded = NULL;
+            assert!(unsafe {
+                deserialize(bytes.as_ptr(), bytes.len() as c_int, level, &mut decoded)
+            });
+            assert_eq!(
+                ffi.serialize(&format!("serialize_{}", kind), decoded),
+                bytes
+            );
+            ffi.destroy(&format!("destroy_{}", kind), decoded);
+        }
+        let mut decoded = NULL;
+        assert!(!unsafe { deserialize(bytes.as_ptr(), bytes.len() as c_int, 3, &mut decoded) });
+    }
+
+    let bytes = ffi.serialize("serialize_public_key", public_key);
+    let mut cached = NULL;
+    let deserialize_cached = ffi.get::<Deserialize>("deserialize_public_key_cached");
+    assert!(unsafe { deserialize_cached(bytes.as_ptr(), bytes.len() as c_int, &mut cached) });
+    ffi.destroy("destroy_public_key", cached);
+
+    let keys = [public_key, other_public_key];
+    let mut aggregate = NULL;
+    let aggregate_public_keys = ffi.get::<Aggregate>("aggregate_public_keys");
+    assert!(unsafe { aggregate_public_keys(keys.as_ptr(), 2, &mut aggregate) });
+    let mut remaining = NULL;
+    let subtract = ffi.get::<AggregateSubtract>("aggregate_public_keys_subtract");
+    assert!(unsafe { subtract(aggregate, &other_public_key, 1, &mut remaining) });
+    assert_eq!(ffi.serialize("serialize_public_key", remaining), bytes);
+
+    let signatures = [signature, signature];
+    let mut aggregate_signature = NULL;
+    let aggregate_signatures = ffi.get::<Aggregate>("aggregate_signatures");
+    assert!(unsafe { aggregate_signatures(signatures.as_ptr(), 2, &mut aggregate_signature) });
+
+    let encode = ffi.get::<EncodeEpochBlock>("encode_epoch_block_to_bytes");
+    let encoded = ffi.bytes(|out, len| unsafe { encode(1, 0, keys.as_ptr(), 2, true, out, len) });
+    assert!(!encoded.unwrap().is_empty());
+
+    for handle in &[aggregate, remaining, public_key, other_public_key] {
+        ffi.destroy("destroy_public_key", *handle);
+    }
+    ffi.destroy("destroy_signature", aggregate_signature);
+    ffi.destroy("destroy_signature", signature);
+    ffi.destroy("destroy_private_key", private_key);
+    ffi.destroy("destroy_private_key", other_key);
+}
+
+#[test]
+fn hashes() {
+    let ffi = Ffi::load();
+    let message = b"hello";
+    let hash_direct = ffi.get::<HashDirect>("hash_direct");
+    let direct = ffi.bytes(|out, len| unsafe { hash_direct(message.as_ptr(), 5, out, len, false) });
+    assert!(!direct.unwrap().is_empty());
+    let hash_composite = ffi.get::<HashComposite>("hash_composite");
+    let composite = ffi
+        .bytes(|out, len| unsafe { hash_composite(message.as_ptr(), 5, ptr::null(), 0, out, len) });
+    assert!(!composite.unwrap().is_empty());
+
+    let mut state = NULL;
+    let init = ffi.get::<StreamingHashInit>("streaming_hash_init");
+    assert!(unsafe { init(false, false, 64, &mut state) });
+    let update = ffi.get::<StreamingHashUpdate>("streaming_hash_update");
+    assert!(unsafe { update(state, message.as_ptr(), 5) });
+    assert!(unsafe { update(state, ptr::null(), 0) });
+    let finalize = ffi.get::<Serialize>("streaming_hash_finalize");
+    let hash = ffi.bytes(|out, len| unsafe { finalize(state, out, len) });
+    assert_eq!(hash.unwrap().len(), 64);
+
+    // a state which is destroyed without being finalized
+    assert!(unsafe { init(true, true, 32, &mut state) });
+    ffi.destroy("destroy_streaming_hash", state);
+}
+
+#[test]
+fn rejects_null_pointers() {
+    let ffi = Ffi::load();
+    let private_key = ffi.generate_private_key();
+    let public_key = ffi.public_key(private_key);
+    let signature = ffi.sign(private_key, b"hello");
+    let (mut bytes, mut len, mut verified, mut code) = (ptr::null_mut(), 0, false, -1);
+    let mut out = NULL;
+
+    unsafe {
+        assert!(!ffi.get::<GeneratePrivateKey>("generate_private_key")(
+            ptr::null_mut()
+        ));
+        let seed = [1u8; 32];
+        let from_seed = ffi.get::<PrivateKeyFromSeed>("private_key_from_seed");
+        assert!(!from_seed(ptr::null(), 32, ptr::null(), 0, &mut out));
+        assert!(!from_seed(
+            seed.as_ptr(),
+            32,
+            ptr::null(),
+            0,
+            ptr::null_mut()
+        ));
+        // too short
+        assert!(!from_seed(seed.as_ptr(), 31, ptr::null(), 0, &mut out));
+        assert!(from_seed(seed.as_ptr(), 32, ptr::null(), 0, &mut out));
+        ffi.destroy("destroy_private_key", out);
+        assert!(!ffi.get::<ToPublicKey>("private_key_to_public_key")(
+            NULL, &mut out
+        ));
+        assert!(!ffi.get::<ToPublicKey>("private_key_to_public_key")(
+            private_key,
+            ptr::null_mut()
+        ));
+        assert!(!ffi.get::<ToPublicKey>("prepare_public_key")(
+            NULL, &mut out
+        ));
+        assert!(!ffi.get::<SignMessage>("sign_message")(
+            NULL,
+            ptr::null(),
+            0,
+            ptr::null(),
+            0,
+            false,
+            &mut out
+        ));
+        // a null buffer with a non-zero length
+        assert!(!ffi.get::<SignMessage>("sign_message")(
+            private_key,
+            ptr::null(),
+            5,
+            ptr::null(),
+            0,
+            false,
+            &mut out
+        ));
+        assert!(!ffi.get::<SignPop>("sign_pop")(
+            NULL,
+            ptr::null(),
+            0,
+            &mut out
+        ));
+        assert!(!ffi.get::<HashDirect>("hash_direct")(
+            ptr::null(),
+            0,
+            ptr::null_mut(),
+            &mut len,
+            false
+        ));
+        assert!(!ffi.get::<HashComposite>("hash_composite")(
+            ptr::null(),
+            3,
+            ptr::null(),
+            0,
+            &mut bytes,
+            &mut len
+        ));
+        assert!(!ffi.get::<VerifySignature>("verify_signature")(
+            public_key,
+            ptr::null(),
+            0,
+            ptr::null(),
+            0,
+            NULL,
+            false,
+            &mut verified
+        ));
+        assert!(!ffi.get::<VerifySignature>("verify_signature")(
+            public_key,
+            ptr::null(),
+            0,
+            ptr::null(),
+            0,
+            signature,
+            false,
+            ptr::null_mut()
+        ));
+        assert!(!ffi.get::<VerifyPop>("verify_pop")(
+            NULL,
+            ptr::null(),
+            0,
+            signature,
+            &mut verified
+        ));
+        assert!(!ffi.get::<VerifyPops>("verify_pops")(
+            ptr::null(),
+            1,
+            &mut verified
+        ));
+        assert!(!ffi.get::<BatchVerify>("batch_verify_signature")(
+            ptr::null(),
+            1,
+            false,
+            &mut verified
+        ));
+        assert!(!ffi
+            .get::<BatchVerifyBounded>("batch_verify_signature_bounded")(
+            ptr::null(),
+            1,
+            false,
+            1,
+            1 << 20,
+            &mut verified,
+            ptr::null_mut()
+        ));
+        assert!(!ffi.get::<BatchVerifyV2>("batch_verify_signature_v2")(
+            ptr::null(),
+            1,
+            false,
+            1,
+            1 << 20,
+            &mut verified,
+            &mut code
+        ));
+
+        // a message pointing to a null signature
+        let messages = [message_ffi(b"hello", public_key, NULL)];
+        assert!(!ffi
+            .get::<BatchVerifyBounded>("batch_verify_signature_bounded")(
+            messages.as_ptr(),
+            1,
+            false,
+            1,
+            1 << 20,
+            &mut verified,
+            &mut code
+        ));
+
+        let keys = [public_key, NULL];
+        assert!(!ffi.get::<Aggregate>("aggregate_public_keys")(
+            keys.as_ptr(),
+            2,
+            &mut out
+        ));
+        assert!(!ffi.get::<Aggregate>("aggregate_public_keys")(
+            ptr::null(),
+            2,
+            &mut out
+        ));
+        assert!(!ffi.get::<Aggregate>("aggregate_signatures")(
+            ptr::null(),
+            1,
+            &mut out
+        ));
+        assert!(!ffi
+            .get::<AggregateSubtract>("aggregate_public_keys_subtract")(
+            NULL,
+            keys.as_ptr(),
+            1,
+            &mut out
+        ));
+        assert!(!ffi.get::<EncodeEpochBlock>("encode_epoch_block_to_bytes")(
+            0,
+            0,
+            keys.as_ptr(),
+            2,
+            false,
+            &mut bytes,
+            &mut len
+        ));
+
+        for kind in &["private_key", "public_key", "signature"] {
+            let serialize = ffi.get::<Serialize>(&format!("serialize_{}", kind));
+            assert!(!serialize(NULL, &mut bytes, &mut len));
+            let deserialize = ffi.get::<Deserialize>(&format!("deserialize_{}", kind));
+            assert!(!deserialize(ptr::null(), 48, &mut out));
+            let destroy = ffi.get::<Destroy>(&format!("destroy_{}", kind));
+            assert!(!destroy(NULL));
+        }
+        assert!(!ffi.get::<Serialize>("serialize_public_key")(
+            public_key,
+            ptr::null_mut(),
+            &mut len
+        ));
+        assert!(!ffi.get::<Deserialize>("deserialize_public_key_cached")(
+            ptr::null(),
+            96,
+            &mut out
+        ));
+        for name in &[
+            "deserialize_public_key_with_validation",
+            "deserialize_public_key_cached_with_validation",
+            "deserialize_signature_with_validation",
+        ] {
+            let deserialize = ffi.get::<DeserializeWithValidation>(name);
+            assert!(!deserialize(ptr::null(), 96, 2, &mut out));
+        }
+        assert!(!ffi.get::<Destroy>("destroy_prepared_public_key")(NULL));
+        assert!(!ffi.get::<Destroy>("destroy_streaming_hash")(NULL));
+        assert!(!ffi.get::<Destroy>("destroy_verifier")(NULL));
+        assert!(!ffi.get::<FreeVec>("free_vec")(ptr::null_mut(), 0));
+        assert!(!ffi.get::<Compress>("compress_signature")(
+            ptr::null(),
+            96,
+            &mut bytes,
+            &mut len
+        ));
+        assert!(!ffi.get::<Compress>("compress_pubkey")(
+            ptr::null(),
+            192,
+            &mut bytes,
+            &mut len
+        ));
+
+        assert!(!ffi.get::<StreamingHashInit>("streaming_hash_init")(
+            false,
+            false,
+            64,
+            ptr::null_mut()
+        ));
+        assert!(!ffi.get::<StreamingHashUpdate>("streaming_hash_update")(
+            NULL,
+            ptr::null(),
+            0
+        ));
+        assert!(!ffi.get::<Serialize>("streaming_hash_finalize")(
+            NULL, &mut bytes, &mut len
+        ));
+
+        let mut session = NULL;
+        assert!(!ffi.get::<VerifierNew>("verifier_new")(
+            false,
+            ptr::null_mut()
+        ));
+        assert!(ffi.get::<VerifierNew>("verifier_new")(false, &mut session));
+        let verifier_add = ffi.get::<VerifierAdd>("verifier_add");
+        assert!(!verifier_add(
+            NULL,
+            ptr::null(),
+            0,
+            ptr::null(),
+            0,
+            public_key,
+            signature
+        ));
+        assert!(!verifier_add(
+            session,
+            ptr::null(),
+            0,
+            ptr::null(),
+            0,
+            NULL,
+            signature
+        ));
+        assert!(!verifier_add(
+            session,
+            ptr::null(),
+            0,
+            ptr::null(),
+            0,
+            public_key,
+            NULL
+        ));
+        let verifier_finalize = ffi.get::<VerifierFinalize>("verifier_finalize");
+        assert!(!verifier_finalize(NULL, &mut verified));
+        assert!(!verifier_finalize(session, ptr::null_mut()));
+        ffi.destroy("destroy_verifier", session);
+
+        let epoch = || EpochBlockFFI {
+            index: 0,
+            pubkeys: ptr::null(),
+            pubkeys_num: 1,
+            maximum_non_signers: 0,
+        };
+        assert!(!ffi.get::<VerifyProof>("verify")(
+            ptr::null(),
+            10,
+            ptr::null(),
+            10,
+            epoch(),
+            epoch()
+        ));
+    }
+
+    // the library is still usable after the rejected calls
+    let bytes = ffi.serialize("serialize_public_key", public_key);
+    let mut cached = NULL;
+    let deserialize_cached = ffi.get::<Deserialize>("deserialize_public_key_cached");
+    assert!(unsafe { deserialize_cached(bytes.as_ptr(), bytes.len() as c_int, &mut cached) });
+
+    ffi.destroy("destroy_public_key", cached);
+    ffi.destroy("destroy_signature", signature);
+    ffi.destroy("destroy_public_key", public_key);
+    ffi.destroy("destroy_private_key", private_key);
+}
+
+#[test]
+fn handles_zero_lengths() {
+    let ffi = Ffi::load();
+    let mut out = NULL;
+    let (mut bytes, mut len, mut verified, mut code) = (ptr::null_mut(), 0, false, -1);
+
+    unsafe {
+        for kind in &["private_key", "public_key", "signature"] {
+            let deserialize = ffi.get::<Deserialize>(&format!("deserialize_{}", kind));
+            assert!(!deserialize(ptr::null(), 0, &mut out));
+        }
+        assert!(!ffi.get::<Deserialize>("deserialize_public_key_cached")(
+            ptr::null(),
+            0,
+            &mut out
+        ));
+        assert!(!ffi.get::<Compress>("compress_signature")(
+            ptr::null(),
+            0,
+            &mut bytes,
+            &mut len
+        ));
+        assert!(!ffi.get::<Compress>("compress_pubkey")(
+            ptr::null(),
+            0,
+            &mut bytes,
+            &mut len
+        ));
+
+        let epoch = || EpochBlockFFI {
+            index: 0,
+            pubkeys: ptr::null(),
+            pubkeys_num: 0,
+            maximum_non_signers: 0,
+        };
+        assert!(!ffi.get::<VerifyProof>("verify")(
+            ptr::null(),
+            0,
+            ptr::null(),
+            0,
+            epoch(),
+            epoch()
+        ));
+
+        // empty batches are within limits
+        ffi.get::<BatchVerifyBounded>("batch_verify_signature_bounded")(
+            ptr::null(),
+            0,
+            false,
+            1,
+            1 << 20,
+            &mut verified,
+            &mut code,
+        );
+        assert_eq!(code, BATCH_OK);
+        ffi.get::<BatchVerifyV2>("batch_verify_signature_v2")(
+            ptr::null(),
+            0,
+            false,
+            1,
+            1 << 20,
+            &mut verified,
+            &mut code,
+        );
+        assert_eq!(code, BATCH_OK);
+        // but no proofs of possession are an error
+        assert!(!ffi.get::<VerifyPops>("verify_pops")(
+            ptr::null(),
+            0,
+            &mut verified
+        ));
+
+        // aggregating no keys gives the point at infinity
+        assert!(ffi.get::<Aggregate>("aggregate_public_keys")(
+            ptr::null(),
+            0,
+            &mut out
+        ));
+        ffi.destroy("destroy_public_key", out);
+        assert!(ffi.get::<Aggregate>("aggregate_signatures")(
+            ptr::null(),
+            0,
+            &mut out
+        ));
+        ffi.destroy("destroy_signature", out);
+    }
+
+    let hash_direct = ffi.get::<HashDirect>("hash_direct");
+    let hash = ffi.bytes(|out, len| unsafe { hash_direct(ptr::null(), 0, out, len, false) });
+    assert!(!hash.unwrap().is_empty());
+}
+
+#[test]
+fn rejects_misaligned_structs() {
+    let ffi = Ffi::load();
+    let private_key = ffi.generate_private_key();
+    let mut storage = vec![];
+    let (mut verified, mut code) = (false, -1);
+
+    unsafe {
+        let messages = misaligned::<MessageFFI>(&mut storage);
+        assert!(!ffi
+            .get::<BatchVerifyBounded>("batch_verify_signature_bounded")(
+            messages,
+            1,
+            false,
+            1,
+            1 << 20,
+            &mut verified,
+            &mut code
+        ));
+        assert_eq!(code, BATCH_ERROR_INVALID_MESSAGE);
+        assert!(!ffi.get::<BatchVerify>("batch_verify_signature")(
+            messages,
+            1,
+            false,
+            &mut verified
+        ));
+
+        let messages = misaligned::<MessageV2FFI>(&mut storage);
+        code = -1;
+        assert!(!ffi.get::<BatchVerifyV2>("batch_verify_signature_v2")(
+            messages,
+            1,
+            false,
+            1,
+            1 << 20,
+            &mut verified,
+            &mut code
+        ));
+        assert_eq!(code, BATCH_ERROR_INVALID_MESSAGE);
+
+        let pops = misaligned::<PopFFI>(&mut storage);
+        assert!(!ffi.get::<VerifyPops>("verify_pops")(
+            pops,
+            1,
+            &mut verified
+        ));
+
+        // misaligned handles
+        let handle = misaligned::<u64>(&mut storage) as Handle;
+        let mut out = NULL;
+        assert!(!ffi.get::<ToPublicKey>("private_key_to_public_key")(
+            handle, &mut out
+        ));
+        assert!(!ffi.get::<Destroy>("destroy_public_key")(handle));
+        let out_misaligned = misaligned::<Handle>(&mut storage) as *mut Handle;
+        assert!(!ffi.get::<ToPublicKey>("private_key_to_public_key")(
+            private_key,
+            out_misaligned
+        ));
+    }
+
+    ffi.destroy("destroy_private_key", private_key);
+}
+
+#[test]
+fn reports_batch_error_codes() {
+    let ffi = Ffi::load();
+    let private_key = ffi.generate_private_key();
+    let public_key = ffi.public_key(private_key);
+    let signature = ffi.sign(private_key, b"hello");
+    let batch_verify = ffi.get::<BatchVerifyBounded>("batch_verify_signature_bounded");
+    let run = |messages: &[MessageFFI], max_batch_size: usize, max_cost: usize| {
+        let (mut verified, mut code) = (false, -1);
+        let ok = unsafe {
+            batch_verify(
+                messages.as_ptr(),
+                messages.len(),
+                false,
+                max_batch_size,
+                max_cost,
+                &mut verified,
+                &mut code,
+            )
+        };
+        (ok, verified, code)
+    };
+
+    let valid = message_ffi(b"hello", public_key, signature);
+    assert_eq!(run(&[valid], 1, 1 << 20), (true, true, BATCH_OK));
+    let wrong = message_ffi(b"other", public_key, signature);
+    assert_eq!(run(&[wrong], 1, 1 << 20), (true, false, BATCH_OK));
+
+    let (ok, _, code) = run(&[valid, valid], 1, 1 << 20);
+    assert!(!ok);
+    assert_eq!(code, BATCH_ERROR_TOO_MANY_MESSAGES);
+    let (ok, _, code) = run(&[valid], 1, 1);
+    assert!(!ok);
+    assert_eq!(code, BATCH_ERROR_COST_EXCEEDED);
+
+    let mut unknown_version = valid;
+    unknown_version.version = MESSAGE_FFI_VERSION + 1;
+    let (ok, _, code) = run(&[valid, unknown_version], 2, 1 << 20);
+    assert!(!ok);
+    assert_eq!(code, BATCH_ERROR_INVALID_MESSAGE);
+    let mut reserved = valid;
+    reserved.padding[0] = 1;
+    let (ok, _, code) = run(&[reserved], 1, 1 << 20);
+    assert!(!ok);
+    assert_eq!(code, BATCH_ERROR_INVALID_MESSAGE);
+
+    // a buffer length which does not fit the cost is rejected before reading the buffer
+    let mut huge = valid;
+    huge.data = Buffer {
+        ptr: ptr::null(),
+        len: u64::max_value(),
+    };
+    let (ok, _, code) = run(&[huge], 1, 1 << 20);
+    assert!(!ok);
+    assert_eq!(code, BATCH_ERROR_COST_EXCEEDED);
+
+    ffi.destroy("destroy_signature", signature);
+    ffi.destroy("destroy_public_key", public_key);
+    ffi.destroy("destroy_private_key", private_key);
+}
+
+#[test]
+fn rejects_malformed_encodings() {
+    let ffi = Ffi::load();
+    let garbage = [0xffu8; 192];
+    let mut out = NULL;
+    let (mut bytes, mut len) = (ptr::null_mut(), 0);
+
+    unsafe {
+        for (kind, size) in &[("private_key", 32), ("public_key", 96), ("signature", 48)] {
+            let deserialize = ffi.get::<Deserialize>(&format!("deserialize_{}", kind));
+            assert!(!deserialize(garbage.as_ptr(), *size, &mut out));
+            // truncated
+            assert!(!deserialize(garbage.as_ptr(), *size - 1, &mut out));
+        }
+        // too short to hold the coordinates
+        assert!(!ffi.get::<Compress>("compress_signature")(
+            garbage.as_ptr(),
+            10,
+            &mut bytes,
+            &mut len
+        ));
+        assert!(!ffi.get::<Compress>("compress_pubkey")(
+            garbage.as_ptr(),
+            10,
+            &mut bytes,
+            &mut len
+        ));
+
+        let epoch = || EpochBlockFFI {
+            index: 0,
+            pubkeys: garbage.as_ptr(),
+            pubkeys_num: 2,
+            maximum_non_signers: 0,
+        };
+        assert!(!ffi.get::<VerifyProof>("verify")(
+            garbage.as_ptr(),
+            192,
+            garbage.as_ptr(),
+            192,
+            epoch(),
+            epoch()
+        ));
+    }
+}
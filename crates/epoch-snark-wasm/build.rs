@@ -0,0 +1,28 @@
+//! Embeds the verifying key at `EPOCH_SNARK_VK` into the module.
+//!
+//! The file must contain a verifying key serialized with `CanonicalSerialize`. Without the
+//! variable an empty key is embedded, so that the crate still builds as part of the
+//! workspace, and `verify` returns `VK_MISSING`.
+use std::{env, fs, path::PathBuf};
+
+const VK_ENV: &str = "EPOCH_SNARK_VK";
+
+fn main() {
+    println!("cargo:rerun-if-env-changed={}", VK_ENV);
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("vk.bin");
+
+    let vk = match env::var(VK_ENV) {
+        Ok(path) => {
+            println!("cargo:rerun-if-changed={}", path);
+            fs::read(&path).unwrap_or_else(|e| panic!("could not read {}: {}", path, e))
+        }
+        Err(_) => {
+            println!(
+                "cargo:warning={} is not set, the module will not verify any proof",
+                VK_ENV
+            );
+            vec![]
+        }
+    };
+    fs::write(&out, vk).unwrap();
+}
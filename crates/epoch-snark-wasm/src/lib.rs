@@ -0,0 +1,170 @@
+//! Verify-only WebAssembly module for epoch proofs.
+//!
+//! The verifying key is embedded by the build script, so that wallets only ship this module
+//! and do not have to distribute and check a verifying key separately. The host copies the
+//! proof and the encoded first and last epochs into the module's memory with `alloc` and
+//! calls `verify`.
+//!
+//! With the `bindgen` feature, the `wasm` module also exports wasm-bindgen wrappers for keys,
+//! signatures and proof verification.
+use algebra::{
+    bw6_761::BW6_761,
+    serialize::{CanonicalDeserialize, SerializationError},
+};
+use epoch_snark::decode_epoch;
+use groth16::{Proof, VerifyingKey};
+use once_cell::sync::Lazy;
+use std::{mem, slice};
+
+#[cfg(feature = "bindgen")]
+pub mod wasm;
+
+/// The proof is valid
+pub const VALID: i32 = 1;
+/// The proof is invalid
+pub const INVALID: i32 = 0;
+/// The module was built without a verifying key, or the embedded key could not be decoded
+pub const VK_MISSING: i32 = -1;
+/// The proof could not be decoded
+pub const MALFORMED_PROOF: i32 = -2;
+/// The first epoch could not be decoded
+pub const MALFORMED_FIRST_EPOCH: i32 = -3;
+/// The last epoch could not be decoded
+pub const MALFORMED_LAST_EPOCH: i32 = -4;
+
+static VK_BYTES: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/vk.bin"));
+
+static VK: Lazy<Option<VerifyingKey<BW6_761>>> =
+    Lazy::new(|| VerifyingKey::deserialize(&mut &VK_BYTES[..]).ok());
+
+#[no_mangle]
+/// Verifies the proof of the transition from the first to the last epoch with the embedded
+/// verifying key. Returns `VALID`, `INVALID` or one of the negative error codes.
+///
+/// # Safety
+/// The pointers must point to the given number of bytes, e.g. allocated with `alloc`
+pub unsafe extern "C" fn verify(
+    proof: *const u8,
+    proof_len: usize,
+    first_epoch: *const u8,
+    first_epoch_len: usize,
+    last_epoch: *const u8,
+    last_epoch_len: usize,
+) -> i32 {
+    let vk = match VK.as_ref() {
+        Some(vk) => vk,
+        None => return VK_MISSING,
+    };
+    verify_with_vk(
+        vk,
+        read(proof, proof_len),
+        read(first_epoch, first_epoch_len),
+        read(last_epoch, last_epoch_len),
+    )
+}
+
+#[no_mangle]
+/// Allocates `len` bytes in the module's memory, for the host to copy the inputs to
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    mem::forget(buf);
+    ptr
+}
+
+#[no_mangle]
+/// Frees memory which was allocated with `alloc`
+///
+/// # Safety
+/// The pointer must have been returned by `alloc` with the same length
+pub unsafe extern "C" fn dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+unsafe fn read<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
+
+/// Verifies the encoded proof and epochs with the verifying key
+pub fn verify_with_vk(
+    vk: &VerifyingKey<BW6_761>,
+    proof: &[u8],
+    first_epoch: &[u8],
+    last_epoch: &[u8],
+) -> i32 {
+    let proof = match decode_proof(proof) {
+        Ok(proof) => proof,
+        Err(_) => return MALFORMED_PROOF,
+    };
+    let first_epoch = match decode_epoch(first_epoch) {
+        Ok(epoch) => epoch,
+        Err(_) => return MALFORMED_FIRST_EPOCH,
+    };
+    let last_epoch = match decode_epoch(last_epoch) {
+        Ok(epoch) => epoch,
+        Err(_) => return MALFORMED_LAST_EPOCH,
+    };
+
+    match epoch_snark::verify(vk, &first_epoch, &last_epoch, &proof) {
+        Ok(()) => VALID,
+        Err(_) => INVALID,
+    }
+}
+
+fn decode_proof(bytes: &[u8]) -> Result<Proof<BW6_761>, SerializationError> {
+    let reader = &mut &bytes[..];
+    let proof = Proof::deserialize(reader)?;
+    if !reader.is_empty() {
+        return Err(SerializationError::InvalidData);
+    }
+    Ok(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G2Projective, CanonicalSerialize, UniformRand};
+    use bls_crypto::PublicKey;
+    use epoch_snark::{encode_epoch, EpochBlock, ENTROPY_BYTES};
+
+    #[test]
+    fn decodes_epochs_of_both_versions() {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..4)
+            .map(|_| PublicKey::from(G2Projective::rand(rng)))
+            .collect::<Vec<_>>();
+        let v1 = EpochBlock::new(7, 1, keys.clone());
+        let v2 = EpochBlock::new(7, 1, keys).with_entropy(&[1; ENTROPY_BYTES], &[2; ENTROPY_BYTES]);
+        for epoch in &[v1, v2] {
+            let bytes = encode_epoch(epoch).unwrap();
+            assert_eq!(&decode_epoch(&bytes).unwrap(), epoch);
+            decode_epoch(&bytes[..5]).unwrap_err();
+            decode_epoch(&bytes[..bytes.len() - 1]).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn reports_malformed_inputs() {
+        let vk = VerifyingKey::<BW6_761>::default();
+        let epoch = encode_epoch(&EpochBlock::new(0, 0, vec![])).unwrap();
+        assert_eq!(
+            verify_with_vk(&vk, &[1, 2, 3], &epoch, &epoch),
+            MALFORMED_PROOF
+        );
+
+        let mut proof = vec![];
+        Proof::<BW6_761>::default().serialize(&mut proof).unwrap();
+        assert_eq!(
+            verify_with_vk(&vk, &proof, &[0], &epoch),
+            MALFORMED_FIRST_EPOCH
+        );
+        assert_eq!(
+            verify_with_vk(&vk, &proof, &epoch, &[0]),
+            MALFORMED_LAST_EPOCH
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! wasm-bindgen wrappers, for browsers and React Native.
+//!
+//! All the values are passed as byte arrays: private keys are serialized with
+//! `CanonicalSerialize`, public keys and signatures are compressed (96 and 48 bytes), and
+//! lists of them are concatenated. Malformed inputs raise an exception with the error's
+//! message, while signatures and proofs which do not verify return `false`.
+//!
+//! Built with the `bindgen` feature, e.g. with
+//! `wasm-pack build crates/epoch-snark-wasm -- --features bindgen`.
+use crate::{
+    verify_with_vk, INVALID, MALFORMED_FIRST_EPOCH, MALFORMED_LAST_EPOCH, MALFORMED_PROOF, VALID,
+    VK, VK_MISSING,
+};
+use algebra::{
+    bw6_761::BW6_761,
+    serialize::{CanonicalDeserialize, CanonicalSerialize},
+};
+use bls_crypto::{
+    hash_to_curve::try_and_increment::{COMPACT_COMPOSITE_HASH_TO_G1, DIRECT_HASH_TO_G1},
+    PrivateKey, PublicKey, Signature, ValidationLevel,
+};
+use groth16::VerifyingKey;
+use std::fmt::Display;
+use wasm_bindgen::prelude::*;
+
+/// Length of a compressed public key, a BLS G2 element
+const PUBKEY_BYTES: usize = 96;
+
+/// Length of a compressed signature
+const SIGNATURE_BYTES: usize = 48;
+
+/// Generates a private key with the host's randomness, `crypto.getRandomValues` in browsers
+#[wasm_bindgen(js_name = generatePrivateKey)]
+pub fn generate_private_key() -> Result<Vec<u8>, JsValue> {
+    encode(&PrivateKey::generate(&mut rand::thread_rng()))
+}
+
+/// Derives a private key from a seed of at least 32 bytes
+#[wasm_bindgen(js_name = privateKeyFromSeed)]
+pub fn private_key_from_seed(seed: &[u8], info: &[u8]) -> Result<Vec<u8>, JsValue> {
+    encode(&PrivateKey::from_seed(seed, info).map_err(js_error)?)
+}
+
+/// Returns the compressed public key of the private key
+#[wasm_bindgen(js_name = privateKeyToPublicKey)]
+pub fn private_key_to_public_key(private_key: &[u8]) -> Result<Vec<u8>, JsValue> {
+    encode(&decode_private_key(private_key)?.to_public())
+}
+
+/// Signs the message and extra data, hashed with the composite hasher if `composite` is set
+/// and with the direct hasher otherwise. The composite hasher runs in its small-memory
+/// configuration, which produces the same hashes.
+#[wasm_bindgen]
+pub fn sign(
+    private_key: &[u8],
+    message: &[u8],
+    extra_data: &[u8],
+    composite: bool,
+) -> Result<Vec<u8>, JsValue> {
+    let private_key = decode_private_key(private_key)?;
+    let signature = if composite {
+        private_key.sign(message, extra_data, &*COMPACT_COMPOSITE_HASH_TO_G1)
+    } else {
+        private_key.sign(message, extra_data, &*DIRECT_HASH_TO_G1)
+    };
+    encode(&signature.map_err(js_error)?)
+}
+
+/// Verifies the signature of the message and extra data, see `sign`
+#[wasm_bindgen(js_name = verifySignature)]
+pub fn verify_signature(
+    public_key: &[u8],
+    message: &[u8],
+    extra_data: &[u8],
+    signature: &[u8],
+    composite: bool,
+) -> Result<bool, JsValue> {
+    let public_key = decode_public_key(public_key)?;
+    let signature = decode_signature(signature)?;
+    let verified = if composite {
+        public_key.verify(
+            message,
+            extra_data,
+            &signature,
+            &*COMPACT_COMPOSITE_HASH_TO_G1,
+        )
+    } else {
+        public_key.verify(message, extra_data, &signature, &*DIRECT_HASH_TO_G1)
+    };
+    Ok(verified.is_ok())
+}
+
+/// Aggregates the concatenated compressed public keys
+#[wasm_bindgen(js_name = aggregatePublicKeys)]
+pub fn aggregate_public_keys(public_keys: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let public_keys = split(public_keys, PUBKEY_BYTES)?
+        .map(decode_public_key)
+        .collect::<Result<Vec<_>, _>>()?;
+    encode(&PublicKey::aggregate(&public_keys))
+}
+
+/// Aggregates the concatenated compressed signatures
+#[wasm_bindgen(js_name = aggregateSignatures)]
+pub fn aggregate_signatures(signatures: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let signatures = split(signatures, SIGNATURE_BYTES)?
+        .map(decode_signature)
+        .collect::<Result<Vec<_>, _>>()?;
+    encode(&Signature::aggregate(&signatures))
+}
+
+/// Verifies the proof of the transition from the first to the last epoch with the verifying
+/// key embedded at build time. The epochs are encoded as for `verify`.
+#[wasm_bindgen(js_name = verifyEpochProof)]
+pub fn verify_epoch_proof(
+    proof: &[u8],
+    first_epoch: &[u8],
+    last_epoch: &[u8],
+) -> Result<bool, JsValue> {
+    let vk = VK.as_ref().ok_or_else(|| from_code(VK_MISSING))?;
+    from_result(verify_with_vk(vk, proof, first_epoch, last_epoch))
+}
+
+/// Same as `verifyEpochProof`, with a verifying key serialized with `CanonicalSerialize`
+#[wasm_bindgen(js_name = verifyEpochProofWithKey)]
+pub fn verify_epoch_proof_with_key(
+    verifying_key: &[u8],
+    proof: &[u8],
+    first_epoch: &[u8],
+    last_epoch: &[u8],
+) -> Result<bool, JsValue> {
+    let vk = VerifyingKey::<BW6_761>::deserialize(&mut &verifying_key[..]).map_err(js_error)?;
+    from_result(verify_with_vk(&vk, proof, first_epoch, last_epoch))
+}
+
+fn decode_private_key(bytes: &[u8]) -> Result<PrivateKey, JsValue> {
+    PrivateKey::deserialize(&mut &bytes[..]).map_err(js_error)
+}
+
+fn decode_public_key(bytes: &[u8]) -> Result<PublicKey, JsValue> {
+    PublicKey::from_bytes_with_validation(bytes, ValidationLevel::Subgroup).map_err(js_error)
+}
+
+fn decode_signature(bytes: &[u8]) -> Result<Signature, JsValue> {
+    Signature::from_bytes_with_validation(bytes, ValidationLevel::Subgroup).map_err(js_error)
+}
+
+fn encode<T: CanonicalSerialize>(value: &T) -> Result<Vec<u8>, JsValue> {
+    let mut bytes = Vec::with_capacity(value.serialized_size());
+    value.serialize(&mut bytes).map_err(js_error)?;
+    Ok(bytes)
+}
+
+fn split(bytes: &[u8], size: usize) -> Result<std::slice::Chunks<'_, u8>, JsValue> {
+    if bytes.len() % size != 0 {
+        return Err(JsValue::from_str(&format!(
+            "the length {} is not a multiple of {}",
+            bytes.len(),
+            size
+        )));
+    }
+    Ok(bytes.chunks(size))
+}
+
+fn from_result(code: i32) -> Result<bool, JsValue> {
+    match code {
+        VALID => Ok(true),
+        INVALID => Ok(false),
+        code => Err(from_code(code)),
+    }
+}
+
+fn from_code(code: i32) -> JsValue {
+    JsValue::from_str(match code {
+        VK_MISSING => "the module was built without a verifying key",
+        MALFORMED_PROOF => "the proof could not be decoded",
+        MALFORMED_FIRST_EPOCH => "the first epoch could not be decoded",
+        MALFORMED_LAST_EPOCH => "the last epoch could not be decoded",
+        _ => "unknown error",
+    })
+}
+
+fn js_error<E: Display>(err: E) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the error paths create `JsValue`s, which only works on wasm targets
+    #[test]
+    fn signs_and_aggregates() {
+        let keys = (0..3)
+            .map(|i| private_key_from_seed(&[i; 32], b"wasm").unwrap())
+            .collect::<Vec<_>>();
+        let public_keys = keys
+            .iter()
+            .map(|key| private_key_to_public_key(key).unwrap())
+            .collect::<Vec<_>>();
+        let signatures = keys
+            .iter()
+            .map(|key| sign(key, b"hello", &[], false).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(public_keys[0].len(), PUBKEY_BYTES);
+        assert_eq!(signatures[0].len(), SIGNATURE_BYTES);
+        assert!(verify_signature(&public_keys[0], b"hello", &[], &signatures[0], false).unwrap());
+        assert!(!verify_signature(&public_keys[0], b"hello", &[], &signatures[1], false).unwrap());
+        // signed with the direct hasher
+        assert!(!verify_signature(&public_keys[0], b"hello", &[], &signatures[0], true).unwrap());
+
+        let public_key = aggregate_public_keys(&public_keys.concat()).unwrap();
+        let signature = aggregate_signatures(&signatures.concat()).unwrap();
+        assert!(verify_signature(&public_key, b"hello", &[], &signature, false).unwrap());
+    }
+}
@@ -0,0 +1,82 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use algebra::{bw6_761, ProjectiveCurve, UniformRand};
+use epoch_snark::{num_public_inputs, CPCurve, NativeVerifier, VerificationStage};
+use groth16::{prepare_verifying_key, verify_proof, Proof, VerifyingKey};
+use rand::Rng;
+
+// Measures each stage of a verification, to be run on the targets of the light clients, e.g.
+// `cargo bench -p epoch-snark --bench native_verify --target armv7-linux-androideabi` with a
+// runner for the device. The key and proof are random points, so the verification fails, but
+// it costs the same as a valid one.
+fn native_verification(c: &mut Criterion) {
+    let mut group = c.benchmark_group("native verification");
+    group.sample_size(10);
+    let rng = &mut rand::thread_rng();
+    let num_inputs = num_public_inputs();
+
+    let vk = VerifyingKey::<CPCurve> {
+        alpha_g1: g1(rng),
+        beta_g2: g2(rng),
+        gamma_g2: g2(rng),
+        delta_g2: g2(rng),
+        gamma_abc_g1: (0..=num_inputs).map(|_| g1(rng)).collect(),
+    };
+    let proof = Proof::<CPCurve> {
+        a: g1(rng),
+        b: g2(rng),
+        c: g1(rng),
+    };
+    let inputs = (0..num_inputs)
+        .map(|_| bw6_761::Fr::rand(rng))
+        .collect::<Vec<_>>();
+    let verifier = NativeVerifier::new(&vk);
+
+    group.bench_function("prepare verifying key", |b| {
+        b.iter(|| NativeVerifier::new(&vk))
+    });
+    group.bench_function("unprepared", |b| {
+        b.iter(|| verify_proof(&prepare_verifying_key(&vk), &proof, &inputs).unwrap())
+    });
+    group.bench_function("prepared", |b| {
+        b.iter(|| verify_proof(verifier.prepared_key(), &proof, &inputs).unwrap())
+    });
+
+    let stages = [
+        ("accumulate inputs", VerificationStage::AccumulateInputs),
+        ("miller loop", VerificationStage::MillerLoop),
+        (
+            "final exponentiation",
+            VerificationStage::FinalExponentiation,
+        ),
+    ];
+    for (name, stage) in stages.iter() {
+        group.bench_function(*name, |b| {
+            b.iter_batched(
+                || {
+                    // runs the previous stages in the setup, which is not measured
+                    let mut verification =
+                        verifier.start_with_inputs(inputs.clone(), &proof).unwrap();
+                    while verification.stage() != *stage {
+                        verification.step().unwrap();
+                    }
+                    verification
+                },
+                |mut verification| verification.step(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn g1<R: Rng>(rng: &mut R) -> bw6_761::G1Affine {
+    bw6_761::G1Projective::rand(rng).into_affine()
+}
+
+fn g2<R: Rng>(rng: &mut R) -> bw6_761::G2Affine {
+    bw6_761::G2Projective::rand(rng).into_affine()
+}
+
+criterion_group!(benches, native_verification);
+criterion_main!(benches);
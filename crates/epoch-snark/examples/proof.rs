@@ -19,8 +19,23 @@ fn main() {
         .init();
 
     let rng = &mut rand::thread_rng();
-    let mut args = env::args();
+    let mut args = env::args().peekable();
     args.next().unwrap(); // discard the program name
+    if args.peek().map(String::as_str) == Some("--describe") {
+        args.next();
+        let bundle = args.next().expect("path to the bundle was expected");
+        describe(&bundle, args.next());
+        return;
+    }
+    if args.peek().map(String::as_str) == Some("--inputs") {
+        args.next();
+        let inputs = args.next().expect("path to the inputs was expected");
+        let hashes_in_bls12_377 = args
+            .next()
+            .map_or(false, |arg| arg.parse().expect("not a bool"));
+        prove_inputs(&inputs, hashes_in_bls12_377);
+        return;
+    }
     let num_validators = args
         .next()
         .expect("num validators was expected")
@@ -67,3 +82,72 @@ fn main() {
     end_timer!(time);
     assert!(res.is_ok());
 }
+
+// Proves the epochs of a JSON inputs file in the format of `schema/prover-inputs-v1.json`,
+// with parameters generated for its shape
+#[cfg(feature = "json")]
+fn prove_inputs(path: &str, hashes_in_bls12_377: bool) {
+    use epoch_snark::{trusted_setup_with_version, ProverInputs};
+
+    let json = std::fs::read_to_string(path).expect("could not read the inputs");
+    let inputs = ProverInputs::load(&json).expect("invalid inputs");
+    let num_validators = inputs.num_validators as usize;
+
+    let time = start_timer!(|| "Trusted setup");
+    let params = trusted_setup_with_version(
+        num_validators,
+        inputs.max_transitions,
+        inputs.initial_epoch.maximum_non_signers as usize,
+        &mut rand::thread_rng(),
+        hashes_in_bls12_377,
+        inputs.initial_epoch.version,
+    )
+    .unwrap();
+    end_timer!(time);
+
+    let time = start_timer!(|| "Generate proof");
+    let proof = prove(
+        &params,
+        inputs.num_validators,
+        &inputs.initial_epoch,
+        &inputs.transitions,
+        inputs.max_transitions,
+    )
+    .unwrap();
+    end_timer!(time);
+
+    let last_epoch = &inputs.transitions.last().unwrap().block;
+    verify(&params.epochs.vk, &inputs.initial_epoch, last_epoch, &proof).unwrap();
+}
+
+#[cfg(not(feature = "json"))]
+fn prove_inputs(_path: &str, _hashes_in_bls12_377: bool) {
+    panic!("loading inputs requires the `json` feature");
+}
+
+// Prints a report on a bundle in the stream format of `encode_bundle`, and the fingerprint
+// of the serialized verifying key if one is given
+#[cfg(feature = "net")]
+fn describe(bundle: &str, vk: Option<String>) {
+    use algebra::CanonicalDeserialize;
+    use epoch_snark::{read_bundle, CPCurve};
+    use groth16::VerifyingKey;
+
+    let bytes = std::fs::read(bundle).expect("could not read the bundle");
+    let bundle = futures::executor::block_on(read_bundle(&mut &bytes[..]))
+        .expect("could not decode the bundle");
+    let vk = vk.map(|path| {
+        let bytes = std::fs::read(path).expect("could not read the verifying key");
+        VerifyingKey::<CPCurve>::deserialize(&mut &bytes[..])
+            .expect("could not decode the verifying key")
+    });
+    let report = bundle
+        .describe(vk.as_ref())
+        .expect("could not describe the bundle");
+    println!("{}", report);
+}
+
+#[cfg(not(feature = "net"))]
+fn describe(_bundle: &str, _vk: Option<String>) {
+    panic!("decoding bundles requires the `net` feature");
+}
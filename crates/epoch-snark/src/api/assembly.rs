@@ -0,0 +1,279 @@
+//! Synthesis of a circuit into the scalars of the multi-scalar multiplications (MSMs) of its
+//! proof, so that the MSMs can run apart from the synthesis: on the workers holding the
+//! shards of the proving key with `prove_distributed`, or in the MSM slots of a
+//! `JobScheduler`.
+//!
+//! The witness polynomial is computed as in `groth16`'s prover, which must be kept in sync
+//! with the version of `groth16` the parameters are generated with.
+use super::{CPCurve, CPField};
+use algebra::{msm::VariableBaseMSM, AffineCurve, One, PrimeField, ProjectiveCurve, Zero};
+use ff_fft::{EvaluationDomain, GeneralEvaluationDomain};
+use groth16::{Parameters as Groth16Parameters, Proof};
+use r1cs_core::{
+    ConstraintSynthesizer, ConstraintSystem, Index, LinearCombination, SynthesisError, Variable,
+};
+use rayon::prelude::*;
+
+/// The scalars of the MSMs of a proof: the assignment of the variables and the coefficients
+/// of the witness polynomial `h`. The constraints of the circuit are dropped once they are
+/// computed, see `EpochsJob`.
+pub struct ProofScalars {
+    /// The assignment of the inputs, starting with the constant one, followed by the
+    /// assignment of the auxiliary variables
+    pub(crate) assignment: Vec<CPField>,
+    num_inputs: usize,
+    pub(crate) h: Vec<CPField>,
+}
+
+impl ProofScalars {
+    /// Synthesizes the circuit and computes its witness polynomial
+    pub(crate) fn synthesize<C: ConstraintSynthesizer<CPField>>(
+        circuit: C,
+    ) -> Result<Self, SynthesisError> {
+        let mut assembly = WitnessAssembly::default();
+        assembly.alloc_input(|| "one", || Ok(CPField::one()))?;
+        circuit.generate_constraints(&mut assembly)?;
+        let h = assembly.witness_map()?;
+
+        let num_inputs = assembly.input.len();
+        let mut assignment = assembly.input;
+        assignment.extend(assembly.aux);
+        Ok(Self {
+            assignment,
+            num_inputs,
+            h,
+        })
+    }
+
+    /// The assignment of the auxiliary variables
+    pub(crate) fn aux(&self) -> &[CPField] {
+        &self.assignment[self.num_inputs..]
+    }
+
+    /// Runs the MSMs of the proof with the whole proving key. The proof is the one
+    /// `create_proof_no_zk` produces.
+    pub(crate) fn prove(
+        &self,
+        parameters: &Groth16Parameters<CPCurve>,
+    ) -> Result<Proof<CPCurve>, SynthesisError> {
+        // the parameters were not generated for this circuit
+        if parameters.a_query.len() != self.assignment.len()
+            || parameters.b_g2_query.len() != self.assignment.len()
+            || parameters.l_query.len() != self.aux().len()
+            || parameters.h_query.len() > self.h.len()
+        {
+            return Err(SynthesisError::MalformedVerifyingKey);
+        }
+        let to_repr = |scalars: &[CPField]| {
+            scalars
+                .par_iter()
+                .map(|scalar| scalar.into_repr())
+                .collect::<Vec<_>>()
+        };
+        let assignment = to_repr(&self.assignment);
+        let h = to_repr(&self.h[..parameters.h_query.len()]);
+        let aux = to_repr(self.aux());
+
+        let a = parameters.vk.alpha_g1.into_projective()
+            + &VariableBaseMSM::multi_scalar_mul(&parameters.a_query, &assignment);
+        let b = parameters.vk.beta_g2.into_projective()
+            + &VariableBaseMSM::multi_scalar_mul(&parameters.b_g2_query, &assignment);
+        let c = VariableBaseMSM::multi_scalar_mul(&parameters.h_query, &h)
+            + &VariableBaseMSM::multi_scalar_mul(&parameters.l_query, &aux);
+        Ok(Proof {
+            a: a.into_affine(),
+            b: b.into_affine(),
+            c: c.into_affine(),
+        })
+    }
+}
+
+/// Records the constraints and the assignment of a circuit
+#[derive(Default)]
+struct WitnessAssembly {
+    at: Vec<Vec<(CPField, Index)>>,
+    bt: Vec<Vec<(CPField, Index)>>,
+    ct: Vec<Vec<(CPField, Index)>>,
+    input: Vec<CPField>,
+    aux: Vec<CPField>,
+}
+
+impl WitnessAssembly {
+    /// Computes the coefficients of the witness polynomial `h`, as in `groth16`'s
+    /// `R1CStoQAP::witness_map`
+    fn witness_map(&self) -> Result<Vec<CPField>, SynthesisError> {
+        let num_inputs = self.input.len();
+        let num_constraints = self.at.len();
+        let domain = GeneralEvaluationDomain::<CPField>::new(num_constraints + num_inputs)
+            .ok_or(SynthesisError::PolynomialDegreeTooLarge)?;
+        let domain_size = domain.size();
+
+        let evaluate = |terms: &[Vec<(CPField, Index)>]| {
+            let mut evaluations = vec![CPField::zero(); domain_size];
+            evaluations[..num_constraints]
+                .par_iter_mut()
+                .zip(terms)
+                .for_each(|(evaluation, terms)| *evaluation = self.evaluate(terms));
+            evaluations
+        };
+
+        let mut a = evaluate(&self.at);
+        let mut b = evaluate(&self.bt);
+        a[num_constraints..num_constraints + num_inputs].copy_from_slice(&self.input);
+        domain.ifft_in_place(&mut a);
+        domain.ifft_in_place(&mut b);
+        domain.coset_fft_in_place(&mut a);
+        domain.coset_fft_in_place(&mut b);
+        let mut ab = domain.mul_polynomials_in_evaluation_domain(&a, &b);
+        drop(a);
+        drop(b);
+
+        let mut c = evaluate(&self.ct);
+        domain.ifft_in_place(&mut c);
+        domain.coset_fft_in_place(&mut c);
+
+        ab.par_iter_mut()
+            .zip(c)
+            .for_each(|(ab_i, c_i)| *ab_i -= &c_i);
+        domain.divide_by_vanishing_poly_on_coset_in_place(&mut ab);
+        domain.coset_ifft_in_place(&mut ab);
+
+        Ok(ab)
+    }
+
+    fn evaluate(&self, terms: &[(CPField, Index)]) -> CPField {
+        terms
+            .iter()
+            .map(|(coeff, index)| {
+                let value = match index {
+                    Index::Input(i) => self.input[*i],
+                    Index::Aux(i) => self.aux[*i],
+                };
+                value * coeff
+            })
+            .fold(CPField::zero(), |sum, term| sum + &term)
+    }
+}
+
+fn terms(lc: LinearCombination<CPField>) -> Vec<(CPField, Index)> {
+    lc.as_ref()
+        .iter()
+        .map(|(variable, coeff)| (*coeff, variable.get_unchecked()))
+        .collect()
+}
+
+impl ConstraintSystem<CPField> for WitnessAssembly {
+    type Root = Self;
+
+    fn alloc<FN, A, AR>(&mut self, _: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<CPField, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.aux.push(f()?);
+        Ok(Variable::new_unchecked(Index::Aux(self.aux.len() - 1)))
+    }
+
+    fn alloc_input<FN, A, AR>(&mut self, _: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<CPField, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.input.push(f()?);
+        Ok(Variable::new_unchecked(Index::Input(self.input.len() - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<CPField>) -> LinearCombination<CPField>,
+        LB: FnOnce(LinearCombination<CPField>) -> LinearCombination<CPField>,
+        LC: FnOnce(LinearCombination<CPField>) -> LinearCombination<CPField>,
+    {
+        self.at.push(terms(a(LinearCombination::zero())));
+        self.bt.push(terms(b(LinearCombination::zero())));
+        self.ct.push(terms(c(LinearCombination::zero())));
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+
+    fn num_constraints(&self) -> usize {
+        self.at.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{Field, UniformRand};
+    use groth16::{create_proof_no_zk, generate_random_parameters};
+
+    /// Proves knowledge of the cube roots of the public inputs
+    #[derive(Clone)]
+    struct CubeRoots(Vec<Option<CPField>>);
+
+    impl ConstraintSynthesizer<CPField> for CubeRoots {
+        fn generate_constraints<CS: ConstraintSystem<CPField>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            for (i, root) in self.0.into_iter().enumerate() {
+                let value = |f: fn(CPField) -> CPField| {
+                    root.map(f).ok_or(SynthesisError::AssignmentMissing)
+                };
+                let x = cs.alloc(|| format!("x {}", i), || value(|x| x))?;
+                let square = cs.alloc(|| format!("square {}", i), || value(|x| x.square()))?;
+                let cube =
+                    cs.alloc_input(|| format!("cube {}", i), || value(|x| x.square() * &x))?;
+                cs.enforce(
+                    || format!("enforce square {}", i),
+                    |lc| lc + x,
+                    |lc| lc + x,
+                    |lc| lc + square,
+                );
+                cs.enforce(
+                    || format!("enforce cube {}", i),
+                    |lc| lc + square,
+                    |lc| lc + x,
+                    |lc| lc + cube,
+                );
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn matches_the_groth16_prover() {
+        let rng = &mut rand::thread_rng();
+        let roots = (0..5).map(|_| Some(CPField::rand(rng))).collect::<Vec<_>>();
+        let params = generate_random_parameters(CubeRoots(vec![None; roots.len()]), rng).unwrap();
+
+        let scalars = ProofScalars::synthesize(CubeRoots(roots.clone())).unwrap();
+        assert_eq!(scalars.aux().len(), 10);
+        assert_eq!(
+            scalars.prove(&params).unwrap(),
+            create_proof_no_zk(CubeRoots(roots), &params).unwrap()
+        );
+
+        // the parameters of another circuit are rejected
+        let other = ProofScalars::synthesize(CubeRoots(vec![Some(CPField::one()); 4])).unwrap();
+        match other.prove(&params) {
+            Err(SynthesisError::MalformedVerifyingKey) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
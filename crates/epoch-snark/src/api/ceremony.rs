@@ -0,0 +1,578 @@
+//! Delta-only ceremony for the parameters of the epochs and HashToBits circuits.
+//!
+//! `trusted_setup` generates the Groth16 parameters with a single RNG, whose owner could
+//! forge proofs. In the ceremony, parties take turns randomizing the `delta` part of the
+//! parameters: each one `contribute`s a secret `δ` which multiplies `delta_g1` and
+//! `delta_g2`, and divides the `h_query` and the `l_query`.
+//!
+//! This is NOT a trustless setup. The powers of tau, `alpha` and `beta` (phase 1) are taken
+//! from the initial parameters as they are, and are not imported from a powers of tau
+//! ceremony, so whoever generated the initial parameters can still forge proofs. The
+//! contributions only protect against a leak of the initial `delta`.
+//!
+//! Each contribution proves that it knows its secret, by publishing `s` and `s·δ` in G1 and
+//! `r·δ` in G2, where `r` is hashed from `s`, `s·δ` and the hash of the transcript before
+//! the contribution. The hashes are chained: the transcript starts at the hash of the
+//! initial parameters, and each contribution moves it to the hash of the previous one and of
+//! the contribution. `verify_transcript` checks the contributions against the initial
+//! parameters, and returns the hashes so that each party can find its own.
+//!
+//! The epochs parameters and the parameters of the HashToBits helper, when there are any, go
+//! through separate ceremonies, with `CeremonyParameters<CPCurve>` and
+//! `CeremonyParameters<BLSCurve>`. Partial parameters are exchanged in their envelope, see
+//! `CEREMONY_KIND` and `HASH_TO_BITS_CEREMONY_KIND`.
+use super::{BLSCurve, CPCurve};
+
+use algebra::{
+    msm::VariableBaseMSM,
+    serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError},
+    AffineCurve, Field, PairingEngine, PrimeField, ProjectiveCurve, UniformRand, Zero,
+};
+use blake2s_simd::Params;
+use bls_crypto::{envelope::Versioned, BLSError, BlsResult};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use groth16::Parameters as Groth16Parameters;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
+use std::{
+    io::{Read, Write},
+    ptr,
+    sync::atomic::{compiler_fence, Ordering},
+};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+/// The kind of the envelopes of the partial parameters of an epochs ceremony
+pub const CEREMONY_KIND: u8 = 18;
+
+/// The kind of the envelopes of the partial parameters of a HashToBits ceremony
+pub const HASH_TO_BITS_CEREMONY_KIND: u8 = 19;
+
+/// The curves of the parameters which go through a ceremony
+pub trait CeremonyEngine: PairingEngine {
+    /// The kind of the envelopes of the partial parameters
+    const KIND: u8;
+}
+
+impl CeremonyEngine for CPCurve {
+    const KIND: u8 = CEREMONY_KIND;
+}
+
+impl CeremonyEngine for BLSCurve {
+    const KIND: u8 = HASH_TO_BITS_CEREMONY_KIND;
+}
+
+const TRANSCRIPT_PERSONALIZATION: &[u8; 8] = b"ULcrmtrn";
+const SECRET_PERSONALIZATION: &[u8; 8] = b"ULcrmsec";
+const HASH_TO_G2_PERSONALIZATION: &[u8; 8] = b"ULcrmg2h";
+
+#[derive(Debug, Error)]
+/// Errors of the verification of a ceremony's transcript
+pub enum CeremonyError {
+    /// The partial parameters did not start from the initial parameters
+    #[error("the transcript does not start from the initial parameters")]
+    InitialMismatch,
+    /// A part of the parameters which the contributions do not change was changed
+    #[error("the {0} of the parameters was changed")]
+    ParametersChanged(&'static str),
+    /// The contribution at the index does not follow the transcript or does not prove the
+    /// knowledge of its secret
+    #[error("contribution {0} is invalid")]
+    InvalidContribution(usize),
+    /// The `delta` or the queries of the parameters do not match the contributions
+    #[error("the {0} of the parameters does not match the contributions")]
+    InconsistentParameters(&'static str),
+    /// Serialization error
+    #[error("Serialization error: {0}")]
+    SerializationError(#[from] SerializationError),
+}
+
+/// A party's contribution to the parameters
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contribution<E: PairingEngine> {
+    /// The `delta_g1` of the parameters after the contribution
+    pub delta_after: E::G1Affine,
+    /// A random point
+    pub s: E::G1Affine,
+    /// `s` multiplied by the secret of the contribution
+    pub s_delta: E::G1Affine,
+    /// The point hashed from `s`, `s_delta` and `transcript`, multiplied by the secret
+    pub r_delta: E::G2Affine,
+    /// The hash of the transcript before the contribution
+    pub transcript: [u8; 32],
+}
+
+impl<E: PairingEngine> Contribution<E> {
+    /// Returns the hash of the transcript after the contribution
+    pub fn hash(&self) -> Result<[u8; 32], SerializationError> {
+        let mut bytes = vec![];
+        self.write(&mut bytes)?;
+        Ok(hash(TRANSCRIPT_PERSONALIZATION, &[&bytes[..]]))
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), SerializationError> {
+        self.delta_after.serialize(&mut *writer)?;
+        self.s.serialize(&mut *writer)?;
+        self.s_delta.serialize(&mut *writer)?;
+        self.r_delta.serialize(&mut *writer)?;
+        writer.write_all(&self.transcript)?;
+        Ok(())
+    }
+
+    fn read<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let delta_after = E::G1Affine::deserialize(&mut *reader)?;
+        let s = E::G1Affine::deserialize(&mut *reader)?;
+        let s_delta = E::G1Affine::deserialize(&mut *reader)?;
+        let r_delta = E::G2Affine::deserialize(&mut *reader)?;
+        let mut transcript = [0u8; 32];
+        reader.read_exact(&mut transcript)?;
+        Ok(Contribution {
+            delta_after,
+            s,
+            s_delta,
+            r_delta,
+            transcript,
+        })
+    }
+}
+
+/// The parameters of a circuit in the middle of a ceremony
+#[derive(Clone)]
+pub struct CeremonyParameters<E: PairingEngine> {
+    /// The parameters with all the contributions so far
+    pub parameters: Groth16Parameters<E>,
+    /// The hash of the initial parameters
+    pub initial_hash: [u8; 32],
+    /// The contributions, in order
+    pub contributions: Vec<Contribution<E>>,
+}
+
+impl<E: PairingEngine> CeremonyParameters<E> {
+    /// Starts a ceremony from the initial parameters, e.g. the `epochs` or the
+    /// `hash_to_bits` parameters of `trusted_setup`
+    pub fn new(parameters: Groth16Parameters<E>) -> Result<Self, SerializationError> {
+        let initial_hash = hash_parameters(&parameters)?;
+        Ok(CeremonyParameters {
+            parameters,
+            initial_hash,
+            contributions: vec![],
+        })
+    }
+
+    /// Returns the hash of the transcript after the last contribution
+    pub fn transcript(&self) -> Result<[u8; 32], SerializationError> {
+        match self.contributions.last() {
+            Some(contribution) => contribution.hash(),
+            None => Ok(self.initial_hash),
+        }
+    }
+}
+
+/// Version 1: the hash of the initial parameters, the parameters, the number of
+/// contributions as a `u32`, and the contributions
+impl<E: CeremonyEngine> Versioned for CeremonyParameters<E> {
+    const KIND: u8 = E::KIND;
+    const VERSION: u16 = 1;
+
+    fn encode_payload(&self) -> BlsResult<Vec<u8>> {
+        let mut bytes = vec![];
+        bytes.write_all(&self.initial_hash)?;
+        self.parameters.serialize(&mut bytes)?;
+        bytes.write_u32::<LittleEndian>(self.contributions.len() as u32)?;
+        for contribution in &self.contributions {
+            contribution.write(&mut bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    fn decode_payload(version: u16, payload: &[u8]) -> BlsResult<Self> {
+        if version != Self::VERSION {
+            return Err(BLSError::UnsupportedVersion {
+                kind: Self::KIND,
+                version,
+            });
+        }
+        let reader = &mut &payload[..];
+        let mut initial_hash = [0u8; 32];
+        reader.read_exact(&mut initial_hash)?;
+        let parameters = Groth16Parameters::deserialize(&mut *reader)?;
+        let num_contributions = reader.read_u32::<LittleEndian>()?;
+        let contributions = (0..num_contributions)
+            .map(|_| Contribution::read(&mut *reader))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !reader.is_empty() {
+            return Err(BLSError::InvalidEncoding(
+                "trailing bytes after the payload",
+            ));
+        }
+        Ok(CeremonyParameters {
+            parameters,
+            initial_hash,
+            contributions,
+        })
+    }
+}
+
+/// Randomizes the parameters with a secret derived from the entropy and from the thread's
+/// RNG, and appends the contribution. Returns the hash of the transcript after it, which the
+/// party should publish so that it can find its contribution in the transcript.
+///
+/// The secret, its inverse, the RNG they were drawn from and its seed are zeroized before
+/// the function returns. The entropy is owned by the caller, who must zeroize it.
+pub fn contribute<E: PairingEngine>(
+    params: &mut CeremonyParameters<E>,
+    entropy: &[u8],
+) -> Result<[u8; 32], SerializationError> {
+    let transcript = params.transcript()?;
+
+    let mut local = Zeroizing::new([0u8; 32]);
+    rand::thread_rng().fill_bytes(&mut *local);
+    let mut seed = Zeroizing::new(hash(SECRET_PERSONALIZATION, &[entropy, &local[..]]));
+    let mut rng = Zeroizing::new(SecretRng(ChaCha20Rng::from_seed(*seed)));
+    let mut delta = Zeroizing::new(SecretScalar(E::Fr::zero()));
+    while delta.0.is_zero() {
+        delta.0 = E::Fr::rand(&mut *rng);
+    }
+    let mut delta_inverse =
+        Zeroizing::new(SecretScalar(delta.0.inverse().expect("delta is not zero")));
+
+    let s = E::G1Projective::rand(&mut *rng).into_affine();
+    let s_delta = s.mul(delta.0.into_repr()).into_affine();
+    let r = hash_to_g2::<E>(&s, &s_delta, &transcript)?;
+    let r_delta = r.mul(delta.0.into_repr()).into_affine();
+
+    let parameters = &mut params.parameters;
+    parameters.delta_g1 = parameters.delta_g1.mul(delta.0.into_repr()).into_affine();
+    parameters.vk.delta_g2 = parameters
+        .vk
+        .delta_g2
+        .mul(delta.0.into_repr())
+        .into_affine();
+    parameters.h_query = scale::<E>(&parameters.h_query, &delta_inverse.0);
+    parameters.l_query = scale::<E>(&parameters.l_query, &delta_inverse.0);
+
+    // clear the secrets now rather than when they are dropped, after the transcript is hashed
+    local.zeroize();
+    seed.zeroize();
+    rng.zeroize();
+    delta.zeroize();
+    delta_inverse.zeroize();
+
+    params.contributions.push(Contribution {
+        delta_after: parameters.delta_g1,
+        s,
+        s_delta,
+        r_delta,
+        transcript,
+    });
+    params.transcript()
+}
+
+/// Checks that the partial parameters are the initial parameters with the contributions
+/// applied, and returns the hash of the transcript after each contribution
+pub fn verify_transcript<E: PairingEngine>(
+    initial: &Groth16Parameters<E>,
+    params: &CeremonyParameters<E>,
+) -> Result<Vec<[u8; 32]>, CeremonyError> {
+    if hash_parameters(initial)? != params.initial_hash {
+        return Err(CeremonyError::InitialMismatch);
+    }
+
+    let current = &params.parameters;
+    let unchanged = [
+        ("alpha_g1", initial.vk.alpha_g1 == current.vk.alpha_g1),
+        ("beta_g2", initial.vk.beta_g2 == current.vk.beta_g2),
+        ("gamma_g2", initial.vk.gamma_g2 == current.vk.gamma_g2),
+        (
+            "gamma_abc_g1",
+            initial.vk.gamma_abc_g1 == current.vk.gamma_abc_g1,
+        ),
+        ("beta_g1", initial.beta_g1 == current.beta_g1),
+        ("a_query", initial.a_query == current.a_query),
+        ("b_g1_query", initial.b_g1_query == current.b_g1_query),
+        ("b_g2_query", initial.b_g2_query == current.b_g2_query),
+        (
+            "h_query length",
+            initial.h_query.len() == current.h_query.len(),
+        ),
+        (
+            "l_query length",
+            initial.l_query.len() == current.l_query.len(),
+        ),
+    ];
+    if let Some((name, _)) = unchanged.iter().find(|(_, unchanged)| !unchanged) {
+        return Err(CeremonyError::ParametersChanged(*name));
+    }
+
+    let mut delta = initial.delta_g1;
+    let mut transcript = params.initial_hash;
+    let mut hashes = Vec::with_capacity(params.contributions.len());
+    for (i, contribution) in params.contributions.iter().enumerate() {
+        if contribution.transcript != transcript || contribution.s.is_zero() {
+            return Err(CeremonyError::InvalidContribution(i));
+        }
+        let r = hash_to_g2::<E>(&contribution.s, &contribution.s_delta, &transcript)?;
+        if !same_ratio::<E>(
+            (contribution.s, contribution.s_delta),
+            (r, contribution.r_delta),
+        ) || !same_ratio::<E>((delta, contribution.delta_after), (r, contribution.r_delta))
+        {
+            return Err(CeremonyError::InvalidContribution(i));
+        }
+        delta = contribution.delta_after;
+        transcript = contribution.hash()?;
+        hashes.push(transcript);
+    }
+
+    if current.delta_g1 != delta
+        || !same_ratio::<E>(
+            (E::G1Affine::prime_subgroup_generator(), current.delta_g1),
+            (E::G2Affine::prime_subgroup_generator(), current.vk.delta_g2),
+        )
+    {
+        return Err(CeremonyError::InconsistentParameters("delta"));
+    }
+
+    // each query was divided by the same delta as delta_g2 was multiplied by, which is
+    // checked on a random linear combination of its points
+    let rng = &mut rand::thread_rng();
+    for (name, before, after) in &[
+        ("h_query", &initial.h_query, &current.h_query),
+        ("l_query", &initial.l_query, &current.l_query),
+    ] {
+        let scalars = (0..before.len())
+            .map(|_| E::Fr::rand(rng).into_repr())
+            .collect::<Vec<_>>();
+        let before = VariableBaseMSM::multi_scalar_mul(&before[..], &scalars).into_affine();
+        let after = VariableBaseMSM::multi_scalar_mul(&after[..], &scalars).into_affine();
+        if E::pairing(before, initial.vk.delta_g2) != E::pairing(after, current.vk.delta_g2) {
+            return Err(CeremonyError::InconsistentParameters(*name));
+        }
+    }
+
+    Ok(hashes)
+}
+
+/// Checks that `g1.1 / g1.0 == g2.1 / g2.0`
+fn same_ratio<E: PairingEngine>(
+    g1: (E::G1Affine, E::G1Affine),
+    g2: (E::G2Affine, E::G2Affine),
+) -> bool {
+    E::pairing(g1.0, g2.1) == E::pairing(g1.1, g2.0)
+}
+
+/// Derives the point which the secret of a contribution multiplies in G2, from the
+/// contribution's points in G1 and the transcript before it
+fn hash_to_g2<E: PairingEngine>(
+    s: &E::G1Affine,
+    s_delta: &E::G1Affine,
+    transcript: &[u8; 32],
+) -> Result<E::G2Affine, SerializationError> {
+    let mut bytes = vec![];
+    s.serialize(&mut bytes)?;
+    s_delta.serialize(&mut bytes)?;
+    let seed = hash(HASH_TO_G2_PERSONALIZATION, &[&transcript[..], &bytes[..]]);
+    Ok(E::G2Projective::rand(&mut ChaCha20Rng::from_seed(seed)).into_affine())
+}
+
+fn hash_parameters<E: PairingEngine>(
+    parameters: &Groth16Parameters<E>,
+) -> Result<[u8; 32], SerializationError> {
+    let mut bytes = vec![];
+    parameters.serialize(&mut bytes)?;
+    Ok(hash(TRANSCRIPT_PERSONALIZATION, &[&bytes[..]]))
+}
+
+fn hash(personalization: &[u8; 8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut state = Params::new()
+        .hash_length(32)
+        .personal(personalization)
+        .to_state();
+    for part in parts {
+        state.update(part);
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(state.finalize().as_ref());
+    out
+}
+
+fn scale<E: PairingEngine>(query: &[E::G1Affine], scalar: &E::Fr) -> Vec<E::G1Affine> {
+    let scalar = scalar.into_repr();
+    let mut scaled = query
+        .par_iter()
+        .map(|point| point.mul(scalar))
+        .collect::<Vec<E::G1Projective>>();
+    E::G1Projective::batch_normalization(&mut scaled);
+    scaled
+        .into_iter()
+        .map(|point| point.into_affine())
+        .collect()
+}
+
+/// A secret scalar of a contribution, overwritten with zero when zeroized
+struct SecretScalar<F: Field>(F);
+
+impl<F: Field> Zeroize for SecretScalar<F> {
+    fn zeroize(&mut self) {
+        // the volatile write cannot be optimized away
+        unsafe { ptr::write_volatile(&mut self.0, F::zero()) };
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// The RNG which the secret of a contribution is drawn from, reset to a fixed state when
+/// zeroized
+struct SecretRng(ChaCha20Rng);
+
+impl Zeroize for SecretRng {
+    fn zeroize(&mut self) {
+        // overwrite the key, counter and buffered output
+        unsafe { ptr::write_volatile(&mut self.0, ChaCha20Rng::from_seed(Default::default())) };
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl RngCore for SecretRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+impl CryptoRng for SecretRng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::bw6_761::{Fr, G1Projective};
+    use groth16::{
+        create_random_proof, generate_random_parameters, prepare_verifying_key, verify_proof,
+    };
+    use r1cs_core::{ConstraintSynthesizer, ConstraintSystem, SynthesisError};
+
+    /// Proves the knowledge of a square root of its public input
+    struct Square<F: Field>(Option<F>);
+
+    impl<F: Field> ConstraintSynthesizer<F> for Square<F> {
+        fn generate_constraints<CS: ConstraintSystem<F>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let root = self.0;
+            let x = cs.alloc(|| "root", || root.ok_or(SynthesisError::AssignmentMissing))?;
+            let y = cs.alloc_input(
+                || "square",
+                || {
+                    root.map(|r| r.square())
+                        .ok_or(SynthesisError::AssignmentMissing)
+                },
+            )?;
+            cs.enforce(|| "square", |lc| lc + x, |lc| lc + x, |lc| lc + y);
+            Ok(())
+        }
+    }
+
+    fn contributions_are_verified_and_prove<E: CeremonyEngine>() {
+        let rng = &mut rand::thread_rng();
+        let initial = generate_random_parameters::<E, _, _>(Square(None), rng).unwrap();
+
+        let mut params = CeremonyParameters::new(initial.clone()).unwrap();
+        let first = contribute(&mut params, b"first party").unwrap();
+        let bytes = params.to_envelope().unwrap();
+        let mut params = CeremonyParameters::<E>::from_envelope(&bytes).unwrap();
+        let second = contribute(&mut params, b"second party").unwrap();
+        assert_ne!(params.parameters.delta_g1, initial.delta_g1);
+
+        assert_eq!(
+            verify_transcript(&initial, &params).unwrap(),
+            vec![first, second]
+        );
+
+        let root = E::Fr::rand(rng);
+        let proof = create_random_proof(Square(Some(root)), &params.parameters, rng).unwrap();
+        let pvk = prepare_verifying_key(&params.parameters.vk);
+        assert!(verify_proof(&pvk, &proof, &[root.square()]).unwrap());
+    }
+
+    #[test]
+    fn epochs_contributions_are_verified_and_prove() {
+        contributions_are_verified_and_prove::<CPCurve>();
+    }
+
+    #[test]
+    fn hash_to_bits_contributions_are_verified_and_prove() {
+        contributions_are_verified_and_prove::<BLSCurve>();
+    }
+
+    #[test]
+    fn ceremonies_of_different_curves_are_not_mixed() {
+        let rng = &mut rand::thread_rng();
+        let initial = generate_random_parameters::<BLSCurve, _, _>(Square(None), rng).unwrap();
+        let bytes = CeremonyParameters::new(initial)
+            .unwrap()
+            .to_envelope()
+            .unwrap();
+        assert!(CeremonyParameters::<CPCurve>::from_envelope(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_transcripts() {
+        let rng = &mut rand::thread_rng();
+        let initial = generate_random_parameters::<CPCurve, _, _>(Square(None), rng).unwrap();
+        let mut params = CeremonyParameters::new(initial.clone()).unwrap();
+        contribute(&mut params, b"first party").unwrap();
+        contribute(&mut params, b"second party").unwrap();
+
+        // a contribution whose delta does not follow from its secret
+        let mut tampered = params.clone();
+        let delta_after = &mut tampered.contributions[1].delta_after;
+        *delta_after = delta_after.mul(Fr::rand(rng).into_repr()).into_affine();
+        match verify_transcript(&initial, &tampered) {
+            Err(CeremonyError::InvalidContribution(1)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // a contribution which does not follow the transcript
+        let mut tampered = params.clone();
+        tampered.contributions.swap(0, 1);
+        match verify_transcript(&initial, &tampered) {
+            Err(CeremonyError::InvalidContribution(0)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // a query which was not divided by the contributions' deltas
+        let mut tampered = params.clone();
+        tampered.parameters.l_query = initial.l_query.clone();
+        match verify_transcript(&initial, &tampered) {
+            Err(CeremonyError::InconsistentParameters("l_query")) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // a changed part of the parameters
+        let mut tampered = params.clone();
+        tampered.parameters.vk.alpha_g1 = G1Projective::rand(rng).into_affine();
+        match verify_transcript(&initial, &tampered) {
+            Err(CeremonyError::ParametersChanged("alpha_g1")) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // other initial parameters
+        let other = generate_random_parameters::<CPCurve, _, _>(Square(None), rng).unwrap();
+        match verify_transcript(&other, &params) {
+            Err(CeremonyError::InitialMismatch) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! INSECURE parameters for fast integration tests, enabled by the `insecure-dev-params`
+//! feature.
+//!
+//! A real setup and proof take hours for production sizes. `dev_trusted_setup` instead
+//! generates parameters for `DevEpochs`, a tiny circuit with the same public inputs as the
+//! epochs circuit, and `prove_dev` proves transitions with them in seconds. The proofs are
+//! checked with `verify` like real ones, so the whole prove/verify flow of a downstream
+//! system can be tested. The circuit checks no signature nor validator set: anyone can prove
+//! any pair of epochs with these parameters, which must never leave a test environment.
+use super::{setup::Parameters, BLSCurve, CPCurve};
+use crate::{
+    encoding::EncodingVersion,
+    epoch_block::{EpochBlock, EpochTransition},
+    epoch_index::EpochIndex,
+    gadgets::DevEpochs,
+    progress::ProvingError,
+    witness_check::WitnessError,
+};
+
+use groth16::{create_proof_no_zk, generate_random_parameters, Proof};
+use r1cs_core::SynthesisError;
+use rand::Rng;
+use tracing::{error, warn};
+
+/// Generates INSECURE parameters for epochs of `num_validators` validators encoded with
+/// `version`, which only prove with `prove_dev`. They are not bound to a number of epochs.
+pub fn dev_trusted_setup<R: Rng>(
+    num_validators: usize,
+    version: EncodingVersion,
+    rng: &mut R,
+) -> Result<Parameters<CPCurve, BLSCurve>, SynthesisError> {
+    warn!("Generating INSECURE dev parameters, which must only be used in tests");
+    let epochs = generate_random_parameters(DevEpochs::empty(num_validators, version), rng)?;
+    Ok(Parameters {
+        epochs,
+        hash_to_bits: None,
+        min_validators: 0,
+    })
+}
+
+/// Same as `prove`, with parameters from `dev_trusted_setup`. The transitions must follow
+/// the initial epoch with consecutive indices, and the first and last epochs must have
+/// `num_validators` keys and the version of the parameters, but nothing else is checked.
+/// Failed checks are reported with `ProvingError::InvalidWitness`.
+pub fn prove_dev(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+) -> Result<Proof<CPCurve>, ProvingError> {
+    warn!("Generating an INSECURE dev proof, which must only be used in tests");
+    EpochIndex::check_transitions(initial_epoch, transitions, u16::MAX as usize)
+        .map_err(WitnessError::from)?;
+    // `check_transitions` rejects empty transitions
+    let position = transitions.len() - 1;
+    let last_epoch = &transitions[position].block;
+    for epoch in &[initial_epoch, last_epoch] {
+        if epoch.new_public_keys.len() != num_validators as usize {
+            return Err(WitnessError::KeyCount {
+                epoch: epoch.index,
+                got: epoch.new_public_keys.len(),
+                expected: num_validators as usize,
+            }
+            .into());
+        }
+    }
+    if last_epoch.version != initial_epoch.version {
+        return Err(WitnessError::VersionMismatch {
+            position,
+            expected: initial_epoch.version,
+            got: last_epoch.version,
+        }
+        .into());
+    }
+
+    let circuit =
+        DevEpochs::new(initial_epoch, last_epoch, transitions.len() as u64).map_err(|err| {
+            error!("Cannot encode the epochs: {}", err);
+            SynthesisError::Unsatisfiable
+        })?;
+    Ok(create_proof_no_zk(circuit, &parameters.epochs)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::verify, epoch_index::EpochIndexError};
+    use algebra::{
+        bls12_377::{G1Projective, G2Projective},
+        ProjectiveCurve,
+    };
+    use bls_crypto::{PublicKey, Signature};
+
+    fn transition(index: u16, keys: &[PublicKey]) -> EpochTransition {
+        EpochTransition {
+            block: EpochBlock::new(index, 1, keys.to_vec()),
+            aggregate_signature: Signature::from(G1Projective::prime_subgroup_generator()),
+            bitmap: vec![true; keys.len()],
+        }
+    }
+
+    #[test]
+    fn proves_and_verifies() {
+        let rng = &mut rand::thread_rng();
+        let keys = vec![PublicKey::from(G2Projective::prime_subgroup_generator()); 2];
+        let params = dev_trusted_setup(2, EncodingVersion::V1, rng).unwrap();
+
+        let first = EpochBlock::new(2, 1, keys.clone());
+        let transitions = vec![transition(3, &keys), transition(4, &keys)];
+        let proof = prove_dev(&params, 2, &first, &transitions).unwrap();
+        verify(&params.epochs.vk, &first, &transitions[1].block, &proof).unwrap();
+        // the proof is bound to its epochs
+        verify(&params.epochs.vk, &first, &transitions[0].block, &proof).unwrap_err();
+
+        // the epochs must be consecutive and have the expected number of validators
+        match prove_dev(&params, 2, &first, &transitions[1..]) {
+            Err(ProvingError::InvalidWitness(WitnessError::EpochIndexError(
+                EpochIndexError::NotConsecutive { position: 0, .. },
+            ))) => {}
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+        match prove_dev(&params, 3, &first, &transitions) {
+            Err(ProvingError::InvalidWitness(WitnessError::KeyCount {
+                epoch: 2,
+                got: 2,
+                expected: 3,
+            })) => {}
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,925 @@
+//! Proving with the proving key sharded across machines.
+//!
+//! Most of the work of the Groth16 prover is spent in the multi-scalar multiplications
+//! (MSMs) of the witness with the queries of the proving key, whose size grows with the
+//! number of epochs. In the distributed mode the queries are split into shards which are
+//! held by worker processes, so that no machine needs to hold the whole key:
+//!
+//! 1. the coordinator synthesizes the circuit and computes the witness polynomial `h`,
+//! 2. each worker receives a `WorkUnit` with the scalars of one of its shards and returns
+//!    the MSM of the shard's bases with them as a `PartialResult`,
+//! 3. the coordinator adds up the partial results into the proof.
+//!
+//! As with `prove`, the proofs are not zero knowledge, and they are identical to the ones
+//! `prove` produces with the same parameters. Shards, work units and partial results can be
+//! exchanged as files with their `to_bytes` and `from_bytes` methods, or over TCP with
+//! `serve` on the workers and a `TcpExecutor` on the coordinator.
+use super::{
+    assembly::ProofScalars, prover::epochs_circuit, setup::Parameters, BLSCurve, CPCurve, CPField,
+};
+use crate::{
+    epoch_block::{EpochBlock, EpochTransition},
+    progress::SilentReporter,
+    witness_check::{check_witness, WitnessError},
+};
+use algebra::{
+    bw6_761::{G1Affine, G1Projective, G2Affine, G2Projective},
+    msm::VariableBaseMSM,
+    serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError},
+    AffineCurve, PrimeField, ProjectiveCurve, Zero,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use groth16::{Parameters as Groth16Parameters, Proof, VerifyingKey};
+use r1cs_core::{ConstraintSynthesizer, SynthesisError};
+use rayon::prelude::*;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    ops::Range,
+};
+use thiserror::Error;
+use tracing::{error, info, span, Level};
+
+/// The maximum length of a message exchanged with a worker. Received messages are further
+/// bounded by the largest message which the peer can legitimately send, see `read_message`.
+pub const MAX_MESSAGE_LENGTH: u32 = 1 << 30;
+
+#[derive(Debug, Error)]
+/// Error raised while proving with a sharded proving key
+pub enum DistributedError {
+    #[error("I/O Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Zexe Error: {0}")]
+    ZexeSerialization(#[from] SerializationError),
+    #[error("Synthesis Error: {0}")]
+    ZexeSynthesisError(#[from] SynthesisError),
+    #[error("Malformed message: {0}")]
+    Malformed(&'static str),
+    #[error("A message has length {0}, which is too large")]
+    MessageTooLarge(u32),
+    #[error("No worker holds shard {0}")]
+    UnknownShard(usize),
+    #[error("Shard {shard} has {expected} bases but {found} scalars were provided")]
+    ScalarCountMismatch {
+        shard: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("No partial result was received for shard {0}")]
+    MissingResult(usize),
+    #[error("An unexpected partial result was received for shard {0}")]
+    UnexpectedResult(usize),
+    #[error("The circuit does not match the sharded proving key")]
+    ShapeMismatch,
+    #[error("Invalid Witness: {0}")]
+    InvalidWitness(#[from] WitnessError),
+}
+
+/// The queries of the proving key which are needed by the prover
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Query {
+    /// The G1 query of `A`, over all variables
+    A,
+    /// The G2 query of `B`, over all variables
+    B,
+    /// The query of the coefficients of the witness polynomial `h`
+    H,
+    /// The query of the auxiliary variables
+    L,
+}
+
+impl Query {
+    fn tag(self) -> u8 {
+        match self {
+            Query::A => 0,
+            Query::B => 1,
+            Query::H => 2,
+            Query::L => 3,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Query::A),
+            1 => Some(Query::B),
+            2 => Some(Query::H),
+            3 => Some(Query::L),
+            _ => None,
+        }
+    }
+}
+
+/// Identifies a shard: the range of bases of a query which it holds
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardInfo {
+    /// The index of the shard in the `ShardedKey`
+    pub id: usize,
+    pub query: Query,
+    pub range: Range<usize>,
+}
+
+/// The part of a proving key which is held by the coordinator
+#[derive(Clone, Debug)]
+pub struct ShardedKey {
+    pub vk: VerifyingKey<CPCurve>,
+    /// The shards of the proving key, indexed by their id
+    pub shards: Vec<ShardInfo>,
+    num_variables: usize,
+    num_aux: usize,
+    num_h: usize,
+}
+
+/// The Public Parameters of `prove_distributed`: the sharded key of the epochs SNARK and
+/// the parameters of the HashToBits SNARK, which is proven by the coordinator
+pub struct DistributedParameters {
+    pub epochs: ShardedKey,
+    pub hash_to_bits: Option<Groth16Parameters<BLSCurve>>,
+    pub min_validators: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Bases {
+    G1(Vec<G1Affine>),
+    G2(Vec<G2Affine>),
+}
+
+/// The bases of a query which are held by a worker
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProvingKeyShard {
+    pub info: ShardInfo,
+    bases: Bases,
+}
+
+/// The scalars which a worker multiplies with the bases of one of its shards
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkUnit {
+    pub shard: usize,
+    pub scalars: Vec<CPField>,
+}
+
+/// The MSM of the bases of a shard
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PartialSum {
+    G1(G1Projective),
+    G2(G2Projective),
+}
+
+/// The result of a `WorkUnit`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialResult {
+    pub shard: usize,
+    pub sum: PartialSum,
+}
+
+/// Runs the work units of a proof on the workers which hold their shards
+pub trait ShardExecutor {
+    /// Returns the partial results of the work units, in any order
+    fn execute(&self, units: Vec<WorkUnit>) -> Result<Vec<PartialResult>, DistributedError>;
+}
+
+/// Splits each query of the proving key into shards of at most `shard_size` bases. The
+/// shards can then be distributed to the workers, each worker holding any number of them.
+pub fn shard_proving_key(
+    parameters: &Groth16Parameters<CPCurve>,
+    shard_size: usize,
+) -> (ShardedKey, Vec<ProvingKeyShard>) {
+    assert!(shard_size > 0, "shards must hold at least one base");
+    let mut shards = Vec::new();
+    split(
+        &mut shards,
+        Query::A,
+        &parameters.a_query,
+        shard_size,
+        Bases::G1,
+    );
+    split(
+        &mut shards,
+        Query::B,
+        &parameters.b_g2_query,
+        shard_size,
+        Bases::G2,
+    );
+    split(
+        &mut shards,
+        Query::H,
+        &parameters.h_query,
+        shard_size,
+        Bases::G1,
+    );
+    split(
+        &mut shards,
+        Query::L,
+        &parameters.l_query,
+        shard_size,
+        Bases::G1,
+    );
+
+    let key = ShardedKey {
+        vk: parameters.vk.clone(),
+        shards: shards.iter().map(|shard| shard.info.clone()).collect(),
+        num_variables: parameters.a_query.len(),
+        num_aux: parameters.l_query.len(),
+        num_h: parameters.h_query.len(),
+    };
+    (key, shards)
+}
+
+fn split<G: Clone>(
+    shards: &mut Vec<ProvingKeyShard>,
+    query: Query,
+    bases: &[G],
+    shard_size: usize,
+    wrap: fn(Vec<G>) -> Bases,
+) {
+    for (i, chunk) in bases.chunks(shard_size).enumerate() {
+        let start = i * shard_size;
+        shards.push(ProvingKeyShard {
+            info: ShardInfo {
+                id: shards.len(),
+                query,
+                range: start..start + chunk.len(),
+            },
+            bases: wrap(chunk.to_vec()),
+        });
+    }
+}
+
+/// Shards the proving key of the epochs SNARK, keeping the rest of the parameters with the
+/// coordinator
+pub fn shard_parameters(
+    parameters: Parameters<CPCurve, BLSCurve>,
+    shard_size: usize,
+) -> (DistributedParameters, Vec<ProvingKeyShard>) {
+    let (epochs, shards) = shard_proving_key(&parameters.epochs, shard_size);
+    let parameters = DistributedParameters {
+        epochs,
+        hash_to_bits: parameters.hash_to_bits,
+        min_validators: parameters.min_validators,
+    };
+    (parameters, shards)
+}
+
+/// Same as `prove`, but the MSMs of the epochs SNARK are computed by the workers of the
+/// executor. The proof of the HashToBits helper is generated by the coordinator. Inputs
+/// which fail `check_witness` are reported with `DistributedError::InvalidWitness` before
+/// any work is sent to the workers.
+pub fn prove_distributed<E: ShardExecutor>(
+    parameters: &DistributedParameters,
+    executor: &E,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    max_transitions: usize,
+) -> Result<Proof<CPCurve>, DistributedError> {
+    check_witness(
+        parameters.min_validators,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+    )?;
+    let circuit = epochs_circuit(
+        parameters.hash_to_bits.as_ref(),
+        parameters.min_validators,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+        &SilentReporter::silent(),
+    )?;
+    info!("BLS (distributed)");
+    prove_sharded(&parameters.epochs, executor, circuit)
+}
+
+/// Proves the circuit with the sharded key, sending the MSMs to the workers of the executor
+pub fn prove_sharded<C: ConstraintSynthesizer<CPField>, E: ShardExecutor>(
+    key: &ShardedKey,
+    executor: &E,
+    circuit: C,
+) -> Result<Proof<CPCurve>, DistributedError> {
+    let span = span!(Level::TRACE, "prove_sharded");
+    let _enter = span.enter();
+
+    let scalars = ProofScalars::synthesize(circuit)?;
+    if scalars.assignment.len() != key.num_variables
+        || scalars.aux().len() != key.num_aux
+        || scalars.h.len() < key.num_h
+    {
+        return Err(DistributedError::ShapeMismatch);
+    }
+
+    let units = key
+        .shards
+        .iter()
+        .map(|shard| {
+            let query_scalars = match shard.query {
+                Query::A | Query::B => &scalars.assignment[..],
+                Query::H => &scalars.h[..],
+                Query::L => scalars.aux(),
+            };
+            WorkUnit {
+                shard: shard.id,
+                scalars: query_scalars[shard.range.clone()].to_vec(),
+            }
+        })
+        .collect::<Vec<_>>();
+    info!("Sending {} work units", units.len());
+
+    let mut sums = vec![None; key.shards.len()];
+    for result in executor.execute(units)? {
+        let slot = sums
+            .get_mut(result.shard)
+            .filter(|slot| slot.is_none())
+            .ok_or(DistributedError::UnexpectedResult(result.shard))?;
+        *slot = Some(result.sum);
+    }
+
+    let mut a = key.vk.alpha_g1.into_projective();
+    let mut b = key.vk.beta_g2.into_projective();
+    let mut c = G1Projective::zero();
+    for (shard, sum) in key.shards.iter().zip(sums) {
+        match (shard.query, sum) {
+            (_, None) => return Err(DistributedError::MissingResult(shard.id)),
+            (Query::A, Some(PartialSum::G1(sum))) => a += &sum,
+            (Query::B, Some(PartialSum::G2(sum))) => b += &sum,
+            (Query::H, Some(PartialSum::G1(sum))) | (Query::L, Some(PartialSum::G1(sum))) => {
+                c += &sum
+            }
+            _ => return Err(DistributedError::UnexpectedResult(shard.id)),
+        }
+    }
+
+    Ok(Proof {
+        a: a.into_affine(),
+        b: b.into_affine(),
+        c: c.into_affine(),
+    })
+}
+
+impl ProvingKeyShard {
+    /// Computes the MSM of the shard's bases with the scalars of the work unit
+    pub fn evaluate(&self, unit: &WorkUnit) -> Result<PartialResult, DistributedError> {
+        if unit.shard != self.info.id {
+            return Err(DistributedError::UnknownShard(unit.shard));
+        }
+        if unit.scalars.len() != self.info.range.len() {
+            return Err(DistributedError::ScalarCountMismatch {
+                shard: self.info.id,
+                expected: self.info.range.len(),
+                found: unit.scalars.len(),
+            });
+        }
+        let scalars = unit
+            .scalars
+            .par_iter()
+            .map(|scalar| scalar.into_repr())
+            .collect::<Vec<_>>();
+        let sum = match &self.bases {
+            Bases::G1(bases) => PartialSum::G1(VariableBaseMSM::multi_scalar_mul(bases, &scalars)),
+            Bases::G2(bases) => PartialSum::G2(VariableBaseMSM::multi_scalar_mul(bases, &scalars)),
+        };
+        Ok(PartialResult {
+            shard: self.info.id,
+            sum,
+        })
+    }
+
+    /// Encodes the shard to bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DistributedError> {
+        let mut bytes = vec![];
+        bytes.write_u32::<LittleEndian>(self.info.id as u32)?;
+        bytes.write_u8(self.info.query.tag())?;
+        bytes.write_u32::<LittleEndian>(self.info.range.start as u32)?;
+        match &self.bases {
+            Bases::G1(bases) => write_all(&mut bytes, bases)?,
+            Bases::G2(bases) => write_all(&mut bytes, bases)?,
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a shard from bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DistributedError> {
+        let reader = &mut &bytes[..];
+        let id = reader.read_u32::<LittleEndian>()? as usize;
+        let query = Query::from_tag(reader.read_u8()?)
+            .ok_or(DistributedError::Malformed("unknown query"))?;
+        let start = reader.read_u32::<LittleEndian>()? as usize;
+        let bases = match query {
+            Query::B => Bases::G2(read_all(reader)?),
+            _ => Bases::G1(read_all(reader)?),
+        };
+        ensure_empty(reader)?;
+        let len = match &bases {
+            Bases::G1(bases) => bases.len(),
+            Bases::G2(bases) => bases.len(),
+        };
+        Ok(Self {
+            info: ShardInfo {
+                id,
+                query,
+                range: start..start + len,
+            },
+            bases,
+        })
+    }
+}
+
+impl WorkUnit {
+    /// Encodes the work unit to bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DistributedError> {
+        let mut bytes = vec![];
+        bytes.write_u32::<LittleEndian>(self.shard as u32)?;
+        write_all(&mut bytes, &self.scalars)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a work unit from bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DistributedError> {
+        let reader = &mut &bytes[..];
+        let shard = reader.read_u32::<LittleEndian>()? as usize;
+        let scalars = read_all(reader)?;
+        ensure_empty(reader)?;
+        Ok(Self { shard, scalars })
+    }
+}
+
+impl PartialResult {
+    /// Encodes the partial result to bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DistributedError> {
+        let mut bytes = vec![];
+        bytes.write_u32::<LittleEndian>(self.shard as u32)?;
+        match self.sum {
+            PartialSum::G1(sum) => {
+                bytes.write_u8(1)?;
+                sum.into_affine().serialize(&mut bytes)?;
+            }
+            PartialSum::G2(sum) => {
+                bytes.write_u8(2)?;
+                sum.into_affine().serialize(&mut bytes)?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a partial result from bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DistributedError> {
+        let reader = &mut &bytes[..];
+        let shard = reader.read_u32::<LittleEndian>()? as usize;
+        let sum = match reader.read_u8()? {
+            1 => PartialSum::G1(G1Affine::deserialize(reader)?.into_projective()),
+            2 => PartialSum::G2(G2Affine::deserialize(reader)?.into_projective()),
+            _ => return Err(DistributedError::Malformed("unknown group")),
+        };
+        ensure_empty(reader)?;
+        Ok(Self { shard, sum })
+    }
+}
+
+fn write_all<T: CanonicalSerialize, W: Write>(
+    writer: &mut W,
+    values: &[T],
+) -> Result<(), DistributedError> {
+    writer.write_u32::<LittleEndian>(values.len() as u32)?;
+    for value in values {
+        value.serialize(writer)?;
+    }
+    Ok(())
+}
+
+fn read_all<T: CanonicalDeserialize, R: Read>(reader: &mut R) -> Result<Vec<T>, DistributedError> {
+    let len = reader.read_u32::<LittleEndian>()?;
+    // the length is not trusted to preallocate the values
+    let mut values = Vec::new();
+    for _ in 0..len {
+        values.push(T::deserialize(reader)?);
+    }
+    Ok(values)
+}
+
+fn ensure_empty(reader: &[u8]) -> Result<(), DistributedError> {
+    if !reader.is_empty() {
+        return Err(DistributedError::Malformed("trailing bytes"));
+    }
+    Ok(())
+}
+
+/// Runs the work units in this process, on shards which it holds
+pub struct LocalExecutor {
+    shards: Vec<ProvingKeyShard>,
+}
+
+impl LocalExecutor {
+    pub fn new(shards: Vec<ProvingKeyShard>) -> Self {
+        Self { shards }
+    }
+}
+
+impl ShardExecutor for LocalExecutor {
+    fn execute(&self, units: Vec<WorkUnit>) -> Result<Vec<PartialResult>, DistributedError> {
+        units
+            .par_iter()
+            .map(|unit| find_shard(&self.shards, unit.shard)?.evaluate(unit))
+            .collect()
+    }
+}
+
+fn find_shard(shards: &[ProvingKeyShard], id: usize) -> Result<&ProvingKeyShard, DistributedError> {
+    shards
+        .iter()
+        .find(|shard| shard.info.id == id)
+        .ok_or(DistributedError::UnknownShard(id))
+}
+
+/// Sends the work units to workers listening with `serve`. Each worker is sent the units of
+/// the shards it holds over its own connection, and the workers are run in parallel.
+pub struct TcpExecutor {
+    workers: Vec<(SocketAddr, Vec<usize>)>,
+}
+
+impl TcpExecutor {
+    /// Creates an executor from the address of each worker and the ids of the shards it holds
+    pub fn new(workers: Vec<(SocketAddr, Vec<usize>)>) -> Self {
+        Self { workers }
+    }
+}
+
+impl ShardExecutor for TcpExecutor {
+    fn execute(&self, units: Vec<WorkUnit>) -> Result<Vec<PartialResult>, DistributedError> {
+        let mut assigned = vec![Vec::new(); self.workers.len()];
+        for unit in units {
+            let worker = self
+                .workers
+                .iter()
+                .position(|(_, shards)| shards.contains(&unit.shard))
+                .ok_or(DistributedError::UnknownShard(unit.shard))?;
+            assigned[worker].push(unit);
+        }
+
+        let results = self
+            .workers
+            .par_iter()
+            .zip(assigned)
+            .filter(|(_, units)| !units.is_empty())
+            .map(|((address, _), units)| -> Result<_, DistributedError> {
+                let mut stream = TcpStream::connect(address)?;
+                units
+                    .iter()
+                    .map(|unit| -> Result<_, DistributedError> {
+                        write_message(&mut stream, &unit.to_bytes()?)?;
+                        let response = read_message(&mut stream, partial_result_length())?
+                            .ok_or_else(|| std::io::Error::from(ErrorKind::UnexpectedEof))?;
+                        PartialResult::from_bytes(&response)
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, DistributedError>>()?;
+        Ok(results.into_iter().flatten().collect())
+    }
+}
+
+/// Serves the work units of the coordinators connecting to the listener with the shards,
+/// until the listener fails. The connections are served one at a time, and a failed
+/// connection is logged and closed.
+pub fn serve(listener: &TcpListener, shards: &[ProvingKeyShard]) -> Result<(), DistributedError> {
+    for stream in listener.incoming() {
+        if let Err(err) = serve_connection(stream?, shards) {
+            error!("Closing the connection to the coordinator: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Serves the work units sent over the stream until it is closed
+pub fn serve_connection(
+    mut stream: TcpStream,
+    shards: &[ProvingKeyShard],
+) -> Result<(), DistributedError> {
+    // the largest work unit is the one of the largest shard
+    let max_length = shards
+        .iter()
+        .map(|shard| work_unit_length(shard.info.range.len()))
+        .max()
+        .unwrap_or(0);
+    while let Some(message) = read_message(&mut stream, max_length)? {
+        let unit = WorkUnit::from_bytes(&message)?;
+        let result = find_shard(shards, unit.shard)?.evaluate(&unit)?;
+        write_message(&mut stream, &result.to_bytes()?)?;
+    }
+    Ok(())
+}
+
+fn write_message<W: Write>(writer: &mut W, message: &[u8]) -> Result<(), DistributedError> {
+    if message.len() > MAX_MESSAGE_LENGTH as usize {
+        return Err(DistributedError::MessageTooLarge(message.len() as u32));
+    }
+    writer.write_u32::<LittleEndian>(message.len() as u32)?;
+    writer.write_all(message)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The length of the encoding of a work unit with `num_scalars` scalars
+fn work_unit_length(num_scalars: usize) -> usize {
+    4 + 4 + num_scalars * CPField::zero().serialized_size()
+}
+
+/// The length of the encoding of the largest partial result, a sum in G2
+fn partial_result_length() -> usize {
+    4 + 1 + G2Affine::zero().serialized_size()
+}
+
+/// Reads a length-prefixed message, or returns `None` if the stream ended before it. The
+/// length is sent by the peer, so messages longer than `max_length` are rejected before
+/// anything is allocated.
+fn read_message<R: Read>(
+    reader: &mut R,
+    max_length: usize,
+) -> Result<Option<Vec<u8>>, DistributedError> {
+    let length = match reader.read_u32::<LittleEndian>() {
+        Ok(length) => length,
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if length > MAX_MESSAGE_LENGTH || length as usize > max_length {
+        return Err(DistributedError::MessageTooLarge(length));
+    }
+    let mut message = vec![0u8; length as usize];
+    reader.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{Field, One, UniformRand};
+    use groth16::{
+        create_proof_no_zk, generate_random_parameters, prepare_verifying_key, verify_proof,
+    };
+    use r1cs_core::ConstraintSystem;
+
+    /// Proves knowledge of `x` such that `x^(2^n) = y` for the public `y`
+    #[derive(Clone)]
+    struct Squarings {
+        x: Option<CPField>,
+        n: usize,
+    }
+
+    impl ConstraintSynthesizer<CPField> for Squarings {
+        fn generate_constraints<CS: ConstraintSystem<CPField>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let mut value = self.x;
+            let mut var = cs.alloc(|| "x", || value.ok_or(SynthesisError::AssignmentMissing))?;
+            for i in 0..self.n {
+                value = value.map(|v| v.square());
+                let square = if i + 1 == self.n {
+                    cs.alloc_input(|| "y", || value.ok_or(SynthesisError::AssignmentMissing))?
+                } else {
+                    cs.alloc(
+                        || format!("square {}", i),
+                        || value.ok_or(SynthesisError::AssignmentMissing),
+                    )?
+                };
+                cs.enforce(
+                    || format!("enforce square {}", i),
+                    |lc| lc + var,
+                    |lc| lc + var,
+                    |lc| lc + square,
+                );
+                var = square;
+            }
+            Ok(())
+        }
+    }
+
+    fn setup(n: usize) -> Groth16Parameters<CPCurve> {
+        let rng = &mut rand::thread_rng();
+        generate_random_parameters(Squarings { x: None, n }, rng).unwrap()
+    }
+
+    fn circuit(n: usize) -> (Squarings, CPField) {
+        let x = CPField::from(3u64);
+        let y = (0..n).fold(x, |v, _| v.square());
+        (Squarings { x: Some(x), n }, y)
+    }
+
+    #[test]
+    fn matches_the_local_prover() {
+        let params = setup(10);
+        let (circuit, y) = circuit(10);
+        let (key, shards) = shard_proving_key(&params, 3);
+        assert!(key.shards.len() > 4);
+        for (id, shard) in shards.iter().enumerate() {
+            assert_eq!(shard.info.id, id);
+            assert!(shard.info.range.len() <= 3);
+        }
+
+        let proof = prove_sharded(&key, &LocalExecutor::new(shards), circuit.clone()).unwrap();
+        assert_eq!(proof, create_proof_no_zk(circuit, &params).unwrap());
+        let pvk = prepare_verifying_key(&params.vk);
+        assert!(verify_proof(&pvk, &proof, &[y]).unwrap());
+    }
+
+    #[test]
+    fn proves_over_tcp() {
+        let params = setup(6);
+        let (circuit, y) = circuit(6);
+        let (key, shards) = shard_proving_key(&params, 4);
+
+        // two workers holding half of the shards each
+        let (first, second) = shards.split_at(shards.len() / 2);
+        let workers = vec![first.to_vec(), second.to_vec()]
+            .into_iter()
+            .map(|shards| {
+                let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                let address = listener.local_addr().unwrap();
+                let ids = shards.iter().map(|shard| shard.info.id).collect();
+                std::thread::spawn(move || serve(&listener, &shards));
+                (address, ids)
+            })
+            .collect();
+
+        let proof = prove_sharded(&key, &TcpExecutor::new(workers), circuit).unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        assert!(verify_proof(&pvk, &proof, &[y]).unwrap());
+    }
+
+    #[test]
+    fn roundtrips_messages() {
+        let params = setup(4);
+        let (_, shards) = shard_proving_key(&params, 2);
+        for shard in &shards {
+            let decoded = ProvingKeyShard::from_bytes(&shard.to_bytes().unwrap()).unwrap();
+            assert_eq!(&decoded, shard);
+
+            let unit = WorkUnit {
+                shard: shard.info.id,
+                scalars: vec![CPField::from(7u64); shard.info.range.len()],
+            };
+            assert_eq!(
+                WorkUnit::from_bytes(&unit.to_bytes().unwrap()).unwrap(),
+                unit
+            );
+            let result = shard.evaluate(&unit).unwrap();
+            let decoded = PartialResult::from_bytes(&result.to_bytes().unwrap()).unwrap();
+            assert_eq!(decoded, result);
+        }
+
+        let mut bytes = shards[0].to_bytes().unwrap();
+        bytes.push(0);
+        match ProvingKeyShard::from_bytes(&bytes) {
+            Err(DistributedError::Malformed(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    /// Proves knowledge of the factors of the public `products`, offset by the constant
+    /// `offset`, so that `C` has constant and input terms
+    #[derive(Clone)]
+    struct Factorizations {
+        factors: Vec<Option<(CPField, CPField)>>,
+        offset: CPField,
+    }
+
+    impl ConstraintSynthesizer<CPField> for Factorizations {
+        fn generate_constraints<CS: ConstraintSystem<CPField>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let offset = self.offset;
+            for (i, factors) in self.factors.into_iter().enumerate() {
+                let value = |f: fn((CPField, CPField)) -> CPField| {
+                    factors.map(f).ok_or(SynthesisError::AssignmentMissing)
+                };
+                let x = cs.alloc(|| format!("x {}", i), || value(|(x, _)| x))?;
+                let y = cs.alloc(|| format!("y {}", i), || value(|(_, y)| y))?;
+                let product = cs.alloc_input(
+                    || format!("product {}", i),
+                    || value(|(x, y)| x * &y - &offset),
+                )?;
+                cs.enforce(
+                    || format!("enforce product {}", i),
+                    |lc| lc + x,
+                    |lc| lc + y,
+                    |lc| lc + product + (offset, CS::one()),
+                );
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn distributed_proofs_verify() {
+        let rng = &mut rand::thread_rng();
+        let offset = CPField::from(5u64);
+        let factors = (0..7)
+            .map(|_| (CPField::rand(rng), CPField::rand(rng)))
+            .collect::<Vec<_>>();
+        let products = factors
+            .iter()
+            .map(|(x, y)| *x * y - &offset)
+            .collect::<Vec<_>>();
+        let setup_circuit = Factorizations {
+            factors: vec![None; factors.len()],
+            offset,
+        };
+        let circuit = Factorizations {
+            factors: factors.into_iter().map(Some).collect(),
+            offset,
+        };
+        let params = generate_random_parameters(setup_circuit, rng).unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+
+        for &shard_size in &[1, 2, 5, 64] {
+            let (key, shards) = shard_proving_key(&params, shard_size);
+            let proof = prove_sharded(&key, &LocalExecutor::new(shards), circuit.clone()).unwrap();
+            assert!(verify_proof(&pvk, &proof, &products).unwrap());
+            // a proof of other products does not verify
+            let mut other = products.clone();
+            other[0] += &CPField::one();
+            assert!(!verify_proof(&pvk, &proof, &other).unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_messages_before_allocating() {
+        let mut bytes = vec![];
+        bytes.write_u32::<LittleEndian>(101).unwrap();
+        bytes.extend_from_slice(&[0; 101]);
+        match read_message(&mut &bytes[..], 100) {
+            Err(DistributedError::MessageTooLarge(101)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(
+            read_message(&mut &bytes[..], 101).unwrap().unwrap(),
+            vec![0; 101]
+        );
+        // the stream is never read past the length of a message claiming to be huge
+        let huge = u32::MAX.to_le_bytes();
+        match read_message(&mut &huge[..], partial_result_length()) {
+            Err(DistributedError::MessageTooLarge(u32::MAX)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn rejects_mismatched_work() {
+        let params = setup(4);
+        let (key, shards) = shard_proving_key(&params, 2);
+        let unit = WorkUnit {
+            shard: 0,
+            scalars: vec![CPField::one()],
+        };
+        match shards[0].evaluate(&unit) {
+            Err(DistributedError::ScalarCountMismatch {
+                shard: 0,
+                expected: 2,
+                found: 1,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // every shard must be held by a worker
+        let executor = LocalExecutor::new(shards[1..].to_vec());
+        match prove_sharded(&key, &executor, circuit(4).0) {
+            Err(DistributedError::UnknownShard(0)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // and the circuit must be the one of the key
+        let executor = LocalExecutor::new(shards);
+        match prove_sharded(&key, &executor, circuit(5).0) {
+            Err(DistributedError::ShapeMismatch) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    struct UnreachableExecutor;
+
+    impl ShardExecutor for UnreachableExecutor {
+        fn execute(&self, _: Vec<WorkUnit>) -> Result<Vec<PartialResult>, DistributedError> {
+            panic!("no work must be sent for an invalid witness");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_witnesses_before_sharding() {
+        let (epochs, _) = shard_proving_key(&setup(2), 1);
+        let parameters = DistributedParameters {
+            epochs,
+            hash_to_bits: None,
+            min_validators: 1,
+        };
+        // the initial epoch has no validator
+        let initial_epoch = EpochBlock::new(0, 0, vec![]);
+        match prove_distributed(&parameters, &UnreachableExecutor, 2, &initial_epoch, &[], 1) {
+            Err(DistributedError::InvalidWitness(WitnessError::KeyCount {
+                epoch: 0,
+                got: 0,
+                expected: 2,
+            })) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -1,11 +1,60 @@
 mod prover;
-pub use prover::prove;
+pub use prover::{prove, prove_with_progress};
 
 mod setup;
-pub use setup::{trusted_setup, Parameters};
+pub use setup::{
+    trusted_setup, trusted_setup_with_min_validators, trusted_setup_with_version, Parameters,
+};
 
 mod verifier;
-pub use verifier::{verify, VerificationError};
+pub(crate) use verifier::verify_with_inputs;
+pub use verifier::{public_inputs, verify, verify_with_shape, CircuitShape, VerificationError};
+
+mod native;
+pub use native::{NativeVerifier, StagedVerification, VerificationStage};
+
+mod ranges;
+pub use ranges::{prove_range, verify_stitched, RangeProof};
+
+mod simulate;
+pub use simulate::simulate_proof;
+
+#[cfg(feature = "insecure-dev-params")]
+mod dev;
+#[cfg(feature = "insecure-dev-params")]
+pub use dev::{dev_trusted_setup, prove_dev};
+
+#[cfg(feature = "delta-ceremony")]
+mod ceremony;
+#[cfg(feature = "delta-ceremony")]
+pub use ceremony::{
+    contribute, verify_transcript, CeremonyEngine, CeremonyError, CeremonyParameters, Contribution,
+    CEREMONY_KIND, HASH_TO_BITS_CEREMONY_KIND,
+};
+
+#[cfg(any(feature = "distributed", feature = "scheduler"))]
+mod assembly;
+
+#[cfg(feature = "scheduler")]
+mod scheduler;
+#[cfg(feature = "scheduler")]
+pub use assembly::ProofScalars;
+#[cfg(feature = "scheduler")]
+pub use prover::EpochsWitness;
+#[cfg(feature = "scheduler")]
+pub use scheduler::{
+    EpochsJob, JobHandle, JobScheduler, Priority, ProverJob, SchedulerConfig, SchedulerError,
+};
+
+#[cfg(feature = "distributed")]
+mod distributed;
+#[cfg(feature = "distributed")]
+pub use distributed::{
+    prove_distributed, prove_sharded, serve, serve_connection, shard_parameters, shard_proving_key,
+    DistributedError, DistributedParameters, LocalExecutor, PartialResult, PartialSum,
+    ProvingKeyShard, Query, ShardExecutor, ShardInfo, ShardedKey, TcpExecutor, WorkUnit,
+    MAX_MESSAGE_LENGTH,
+};
 
 // Instantiate certain types to avoid confusion
 use algebra::{bls12_377, bw6_761};
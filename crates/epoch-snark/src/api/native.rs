@@ -0,0 +1,259 @@
+//! Verification of epoch proofs on constrained devices, e.g. light clients on older 32-bit ARM
+//! phones.
+//!
+//! Verifying a proof is dominated by BW6-761 arithmetic, which uses algebra's portable 64-bit
+//! limbs on every target: on 32-bit targets each limb multiplication is emulated, so a single
+//! verification takes several seconds and can exceed the time a mobile app may spend in one
+//! call. `NativeVerifier` prepares the verifying key once, which skips the pairing and the
+//! line computations of `prepare_verifying_key` in each verification, and
+//! `StagedVerification` splits a verification into stages which can each run in their own
+//! call, e.g. between frames or in separate background tasks.
+//!
+//! The `native_verify` benchmark measures each stage, and can be run on the device's target
+//! to pick how to schedule them.
+use super::{verifier::public_inputs, CPCurve, CPField, VerificationError};
+use crate::epoch_block::EpochBlock;
+use algebra::{AffineCurve, PairingEngine, PrimeField, ProjectiveCurve};
+use groth16::{prepare_verifying_key, PreparedVerifyingKey, Proof, VerifyingKey};
+use r1cs_core::SynthesisError;
+
+type CPG1 = <CPCurve as PairingEngine>::G1Projective;
+type CPFqk = <CPCurve as PairingEngine>::Fqk;
+
+/// Verifies epoch proofs with a verifying key which is prepared once
+#[derive(Clone)]
+pub struct NativeVerifier {
+    pvk: PreparedVerifyingKey<CPCurve>,
+}
+
+impl From<PreparedVerifyingKey<CPCurve>> for NativeVerifier {
+    fn from(pvk: PreparedVerifyingKey<CPCurve>) -> Self {
+        NativeVerifier { pvk }
+    }
+}
+
+impl NativeVerifier {
+    /// Prepares the verifying key
+    pub fn new(vk: &VerifyingKey<CPCurve>) -> Self {
+        Self::from(prepare_verifying_key(vk))
+    }
+
+    /// The prepared verifying key
+    pub fn prepared_key(&self) -> &PreparedVerifyingKey<CPCurve> {
+        &self.pvk
+    }
+
+    /// Verifies the proof of the transition from the first to the last epoch at once. Same
+    /// as `verify`, without preparing the verifying key again.
+    pub fn verify(
+        &self,
+        first_epoch: &EpochBlock,
+        last_epoch: &EpochBlock,
+        proof: &Proof<CPCurve>,
+    ) -> Result<(), VerificationError> {
+        self.start(first_epoch, last_epoch, proof)?.run()
+    }
+
+    /// Starts a verification of the proof of the transition from the first to the last
+    /// epoch, whose stages are run with `StagedVerification::step`. The public inputs are
+    /// computed by this call, which hashes the two epochs.
+    pub fn start<'a>(
+        &'a self,
+        first_epoch: &EpochBlock,
+        last_epoch: &EpochBlock,
+        proof: &'a Proof<CPCurve>,
+    ) -> Result<StagedVerification<'a>, VerificationError> {
+        self.start_with_inputs(public_inputs(first_epoch, last_epoch)?, proof)
+    }
+
+    /// Same as `start`, with the public inputs which were already computed with
+    /// `public_inputs`
+    pub fn start_with_inputs<'a>(
+        &'a self,
+        public_inputs: Vec<CPField>,
+        proof: &'a Proof<CPCurve>,
+    ) -> Result<StagedVerification<'a>, VerificationError> {
+        let expected = self.pvk.vk.gamma_abc_g1.len().saturating_sub(1);
+        if public_inputs.len() != expected {
+            return Err(VerificationError::PublicInputCountMismatch {
+                expected,
+                got: public_inputs.len(),
+            });
+        }
+        Ok(StagedVerification {
+            pvk: &self.pvk,
+            proof,
+            public_inputs,
+            state: State::Inputs,
+        })
+    }
+}
+
+/// The next stage of a `StagedVerification`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerificationStage {
+    /// Combines the public inputs with the verifying key, one scalar multiplication per input
+    AccumulateInputs,
+    /// Computes the Miller loop of the three pairings of the verification equation
+    MillerLoop,
+    /// Computes the final exponentiation and compares the result with the verifying key
+    FinalExponentiation,
+    /// The verification is over
+    Done,
+}
+
+enum State {
+    Inputs,
+    Accumulated(CPG1),
+    MillerLoop(CPFqk),
+    Verified,
+    Failed,
+}
+
+/// A verification of a Groth16 proof which runs one stage per call to `step`. The stages
+/// compute the same equation as `groth16::verify_proof`.
+pub struct StagedVerification<'a> {
+    pvk: &'a PreparedVerifyingKey<CPCurve>,
+    proof: &'a Proof<CPCurve>,
+    public_inputs: Vec<CPField>,
+    state: State,
+}
+
+impl<'a> StagedVerification<'a> {
+    /// The stage which the next call to `step` runs
+    pub fn stage(&self) -> VerificationStage {
+        match self.state {
+            State::Inputs => VerificationStage::AccumulateInputs,
+            State::Accumulated(_) => VerificationStage::MillerLoop,
+            State::MillerLoop(_) => VerificationStage::FinalExponentiation,
+            State::Verified | State::Failed => VerificationStage::Done,
+        }
+    }
+
+    /// Runs the next stage and returns the one after it. The last stage fails with
+    /// `VerificationError::VerificationFailed` if the proof is invalid, and so do the
+    /// following calls.
+    pub fn step(&mut self) -> Result<VerificationStage, VerificationError> {
+        self.state = match std::mem::replace(&mut self.state, State::Failed) {
+            State::Inputs => State::Accumulated(self.accumulate_inputs()),
+            State::Accumulated(inputs) => State::MillerLoop(self.miller_loop(&inputs)),
+            State::MillerLoop(product) => {
+                let pairing = CPCurve::final_exponentiation(&product)
+                    .ok_or(SynthesisError::UnexpectedIdentity)?;
+                if pairing != self.pvk.alpha_g1_beta_g2 {
+                    return Err(VerificationError::VerificationFailed);
+                }
+                State::Verified
+            }
+            State::Verified => State::Verified,
+            State::Failed => return Err(VerificationError::VerificationFailed),
+        };
+        Ok(self.stage())
+    }
+
+    /// Runs the remaining stages
+    pub fn run(mut self) -> Result<(), VerificationError> {
+        while self.step()? != VerificationStage::Done {}
+        Ok(())
+    }
+
+    fn accumulate_inputs(&self) -> CPG1 {
+        let bases = &self.pvk.vk.gamma_abc_g1;
+        let mut accumulated = bases[0].into_projective();
+        for (input, base) in self.public_inputs.iter().zip(&bases[1..]) {
+            accumulated += &base.mul(input.into_repr());
+        }
+        accumulated
+    }
+
+    fn miller_loop(&self, inputs: &CPG1) -> CPFqk {
+        CPCurve::miller_loop(&[
+            (self.proof.a.into(), self.proof.b.into()),
+            (
+                inputs.into_affine().into(),
+                self.pvk.gamma_g2_neg_pc.clone(),
+            ),
+            (self.proof.c.into(), self.pvk.delta_g2_neg_pc.clone()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{Field, UniformRand};
+    use groth16::{create_random_proof, generate_random_parameters, verify_proof};
+    use r1cs_core::{ConstraintSynthesizer, ConstraintSystem};
+
+    // proves the knowledge of a square root of the public input
+    struct SquareRoot(Option<CPField>);
+
+    impl ConstraintSynthesizer<CPField> for SquareRoot {
+        fn generate_constraints<CS: ConstraintSystem<CPField>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let root = cs.alloc(
+                || "root",
+                || self.0.ok_or(SynthesisError::AssignmentMissing),
+            )?;
+            let square = cs.alloc_input(
+                || "square",
+                || {
+                    self.0
+                        .map(|root| root.square())
+                        .ok_or(SynthesisError::AssignmentMissing)
+                },
+            )?;
+            cs.enforce(
+                || "root * root = square",
+                |lc| lc + root,
+                |lc| lc + root,
+                |lc| lc + square,
+            );
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn staged_verification_matches_groth16() {
+        let rng = &mut rand::thread_rng();
+        let params = generate_random_parameters::<CPCurve, _, _>(SquareRoot(None), rng).unwrap();
+        let root = CPField::rand(rng);
+        let proof = create_random_proof(SquareRoot(Some(root)), &params, rng).unwrap();
+        let verifier = NativeVerifier::new(&params.vk);
+        assert!(verify_proof(verifier.prepared_key(), &proof, &[root.square()]).unwrap());
+
+        let mut verification = verifier
+            .start_with_inputs(vec![root.square()], &proof)
+            .unwrap();
+        assert_eq!(verification.stage(), VerificationStage::AccumulateInputs);
+        assert_eq!(verification.step().unwrap(), VerificationStage::MillerLoop);
+        assert_eq!(
+            verification.step().unwrap(),
+            VerificationStage::FinalExponentiation
+        );
+        assert_eq!(verification.step().unwrap(), VerificationStage::Done);
+        assert_eq!(verification.step().unwrap(), VerificationStage::Done);
+
+        // another input fails in the last stage, and keeps failing
+        let mut verification = verifier.start_with_inputs(vec![root], &proof).unwrap();
+        verification.step().unwrap();
+        verification.step().unwrap();
+        match verification.step() {
+            Err(VerificationError::VerificationFailed) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        verification.step().unwrap_err();
+        assert_eq!(verification.stage(), VerificationStage::Done);
+
+        match verifier.start_with_inputs(vec![], &proof) {
+            Err(VerificationError::PublicInputCountMismatch {
+                expected: 1,
+                got: 0,
+            }) => {}
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("the input count must be checked"),
+        }
+    }
+}
@@ -1,25 +1,52 @@
 use super::{setup::Parameters, BLSCurve, BLSCurveG1, BLSCurveG2, CPCurve};
 use crate::{
+    encoding::{EncodingVersion, ENTROPY_BYTES},
     epoch_block::{EpochBlock, EpochTransition},
+    epoch_index::EpochIndex,
     gadgets::{EpochData, HashToBits, HashToBitsHelper, SingleUpdate, ValidatorSetUpdate},
+    progress::{
+        Progress, ProgressCircuit, ProgressReporter, ProvingError, ProvingStage, SilentReporter,
+    },
+    witness_check::check_witness,
 };
 use algebra::ProjectiveCurve;
 use bls_crypto::{
+    bls::{BlsScheme, Celo},
     hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1,
     hashers::{Hasher, COMPOSITE_HASHER},
-    Signature, SIG_DOMAIN,
+    Signature,
 };
 use bls_gadgets::utils::bytes_to_bits;
 
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use groth16::{create_proof_no_zk, Parameters as Groth16Parameters, Proof as Groth16Proof};
 use r1cs_core::SynthesisError;
 
-use tracing::{info, span, Level};
+use tracing::{error, info, span, Level};
+
+// Buffers holding the prover's witness, which are locked and wiped when dropped if the
+// `secure-memory` feature is enabled
+#[cfg(feature = "secure-memory")]
+fn witness_bytes(bytes: Vec<u8>) -> crate::secure::SecretVec<u8> {
+    bytes.into()
+}
+#[cfg(not(feature = "secure-memory"))]
+fn witness_bytes(bytes: Vec<u8>) -> Vec<u8> {
+    bytes
+}
 
 /// Given the SNARK's Public Parameters, the initial epoch, and a list of state transitions,
 /// generates a SNARK which proves that the final epoch is correctly calculated from the first
 /// epoch. The proof can then be verified only with constant amount of data (the first and last
 /// epochs)
+///
+/// All blocks must use the same `EncodingVersion`, the one which the parameters were
+/// generated for. The transitions must follow the initial epoch with consecutive indices and
+/// there must be between 1 and `max_transitions` of them. These and the other checks of
+/// `check_witness` run before any work is done, and `SynthesisError::Unsatisfiable` is
+/// returned if they fail. Use `prove_with_progress` to get the failing check.
 pub fn prove(
     parameters: &Parameters<CPCurve, BLSCurve>,
     num_validators: u32,
@@ -27,6 +54,218 @@ pub fn prove(
     transitions: &[EpochTransition],
     max_transitions: usize,
 ) -> Result<Groth16Proof<CPCurve>, SynthesisError> {
+    let span = span!(Level::TRACE, "prove");
+    let _enter = span.enter();
+
+    if let Err(err) = check_witness(
+        parameters.min_validators,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+    ) {
+        error!("Cannot prove the transitions: {}", err);
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    prove_reporting(
+        parameters,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+        &SilentReporter::silent(),
+    )
+}
+
+/// Same as `prove`, but calls `progress` as the proof is generated, see the `Progress` and
+/// `ProvingStage` reports. The proof is cancelled if the callback returns `false`, in which
+/// case `ProvingError::Cancelled` is returned. The callback is never called concurrently,
+/// although it may be called from several threads. Inputs which fail `check_witness` are
+/// reported with `ProvingError::InvalidWitness` before any work is done.
+pub fn prove_with_progress<F: FnMut(Progress) -> bool + Send>(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    max_transitions: usize,
+    progress: F,
+) -> Result<Groth16Proof<CPCurve>, ProvingError> {
+    let span = span!(Level::TRACE, "prove_with_progress");
+    let _enter = span.enter();
+
+    check_witness(
+        parameters.min_validators,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+    )?;
+    let reporter = ProgressReporter::new(progress);
+    let result = prove_reporting(
+        parameters,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+        &reporter,
+    );
+    reporter.finish(result)
+}
+
+fn prove_reporting<F: FnMut(Progress) -> bool + Send>(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    max_transitions: usize,
+    reporter: &ProgressReporter<F>,
+) -> Result<Groth16Proof<CPCurve>, SynthesisError> {
+    let witness = epochs_witness(
+        parameters,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+        reporter,
+    )?;
+    prove_epochs_witness(parameters, witness, reporter)
+}
+
+/// The witness of a proof of the epochs circuit, computed before any of the multi-scalar
+/// multiplications of the proof, see `EpochsJob`
+pub struct EpochsWitness {
+    circuit: ValidatorSetUpdate<BLSCurve>,
+    message_bits: Option<Vec<Vec<Option<bool>>>>,
+}
+
+/// Computes the witness of the proof of the transitions, searching the try-and-increment
+/// attempts of the HashToBits helper if the parameters have one
+pub(crate) fn epochs_witness<F: FnMut(Progress) -> bool + Send>(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    max_transitions: usize,
+    reporter: &ProgressReporter<F>,
+) -> Result<EpochsWitness, SynthesisError> {
+    let circuit = update_circuit(
+        parameters.min_validators,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+    )?;
+    let message_bits = if parameters.hash_to_bits.is_some() {
+        Some(hash_witness(transitions, reporter)?)
+    } else {
+        None
+    };
+    Ok(EpochsWitness {
+        circuit,
+        message_bits,
+    })
+}
+
+/// Proves the witness, which runs the multi-scalar multiplications of the HashToBits helper
+/// and of the epochs circuit
+pub(crate) fn prove_epochs_witness<F: FnMut(Progress) -> bool + Send>(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+    witness: EpochsWitness,
+    reporter: &ProgressReporter<F>,
+) -> Result<Groth16Proof<CPCurve>, SynthesisError> {
+    let circuit = with_hash_helper(parameters, witness, reporter)?;
+
+    info!("BLS");
+    let circuit = ProgressCircuit::new(
+        circuit,
+        reporter,
+        ProvingStage::Epochs,
+        parameters.epochs.a_query.len(),
+    );
+    #[cfg(feature = "synthesis-timing")]
+    let circuit = crate::TimedCircuit::new(circuit);
+    let bls_proof = create_proof_no_zk(circuit, &parameters.epochs)?;
+
+    Ok(bls_proof)
+}
+
+/// Proves the HashToBits helper of the witness if the parameters have one, and returns the
+/// epochs circuit verifying it
+fn with_hash_helper<F: FnMut(Progress) -> bool + Send>(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+    witness: EpochsWitness,
+    reporter: &ProgressReporter<F>,
+) -> Result<ValidatorSetUpdate<BLSCurve>, SynthesisError> {
+    let EpochsWitness {
+        mut circuit,
+        message_bits,
+    } = witness;
+    if let (Some(params), Some(message_bits)) = (parameters.hash_to_bits.as_ref(), message_bits) {
+        circuit.hash_helper = Some(prove_hash_helper(params, message_bits, reporter)?);
+    }
+    Ok(circuit)
+}
+
+/// Proves the HashToBits helper of the witness and synthesizes the epochs circuit, without
+/// running the multi-scalar multiplications of the epochs proof, see `EpochsJob`
+#[cfg(feature = "scheduler")]
+pub(crate) fn synthesize_epochs_witness<F: FnMut(Progress) -> bool + Send>(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+    witness: EpochsWitness,
+    reporter: &ProgressReporter<F>,
+) -> Result<super::ProofScalars, SynthesisError> {
+    let circuit = with_hash_helper(parameters, witness, reporter)?;
+
+    info!("BLS synthesis");
+    let circuit = ProgressCircuit::new(
+        circuit,
+        reporter,
+        ProvingStage::Epochs,
+        parameters.epochs.a_query.len(),
+    );
+    super::ProofScalars::synthesize(circuit)
+}
+
+/// Builds the epochs circuit proving the transitions, padded with dummy epochs to
+/// `max_transitions`. The proof of the HashToBits helper is generated if its parameters are
+/// provided, reporting its progress to the reporter.
+pub(crate) fn epochs_circuit<F: FnMut(Progress) -> bool + Send>(
+    hash_to_bits: Option<&Groth16Parameters<BLSCurve>>,
+    min_validators: u32,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    max_transitions: usize,
+    reporter: &ProgressReporter<F>,
+) -> Result<ValidatorSetUpdate<BLSCurve>, SynthesisError> {
+    let mut circuit = update_circuit(
+        min_validators,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+    )?;
+
+    // Generate a helping proof if a Proving Key for the HashToBits
+    // circuit was provided
+    if let Some(params) = hash_to_bits {
+        let message_bits = hash_witness(transitions, reporter)?;
+        circuit.hash_helper = Some(prove_hash_helper(params, message_bits, reporter)?);
+    }
+
+    Ok(circuit)
+}
+
+/// Builds the epochs circuit without the proof of the HashToBits helper. The transitions must
+/// have passed `check_witness`, which the provers run first to report why they cannot be
+/// proven.
+fn update_circuit(
+    min_validators: u32,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    max_transitions: usize,
+) -> Result<ValidatorSetUpdate<BLSCurve>, SynthesisError> {
     info!(
         "Generating proof for {} epochs (first epoch: {}, {} validators per epoch)",
         transitions.len(),
@@ -34,11 +273,13 @@ pub fn prove(
         num_validators,
     );
 
-    let span = span!(Level::TRACE, "prove");
-    let _enter = span.enter();
+    debug_assert!(
+        EpochIndex::check_transitions(initial_epoch, transitions, max_transitions).is_ok(),
+        "the transitions were not checked with `check_witness`"
+    );
 
     let mut epochs = transitions
-        .iter()
+        .par_iter()
         .map(|transition| to_update(transition))
         .collect::<Vec<_>>();
 
@@ -47,22 +288,14 @@ pub fn prove(
         epochs = [
             &epochs[..num_epochs - 1],
             &(0..max_transitions - num_epochs)
-                .map(|_| to_dummy_update(num_validators))
+                .map(|_| to_dummy_update(num_validators, initial_epoch.version))
                 .collect::<Vec<_>>(),
             &[epochs[num_epochs - 1].clone()],
         ]
         .concat();
     }
 
-    // Generate a helping proof if a Proving Key for the HashToBits
-    // circuit was provided
-    let hash_helper = if let Some(ref params) = parameters.hash_to_bits {
-        Some(generate_hash_helper(&params, transitions)?)
-    } else {
-        None
-    };
-
-    // Generate the BLS proof
+    // Aggregate the signature, with the generator as the signature of each dummy epoch
     let asig = Signature::aggregate(transitions.iter().map(|epoch| &epoch.aggregate_signature));
     let mut asig_dummy = (0..max_transitions - num_epochs)
         .map(|_| Signature::from(BLSCurveG1::prime_subgroup_generator()))
@@ -70,54 +303,73 @@ pub fn prove(
     asig_dummy.push(asig);
     let asig = Signature::aggregate(&asig_dummy);
 
-    let circuit = ValidatorSetUpdate::<BLSCurve> {
+    Ok(ValidatorSetUpdate::<BLSCurve> {
         initial_epoch: to_epoch_data(initial_epoch),
         epochs,
         aggregated_signature: Some(*asig.as_ref()),
         num_validators,
-        hash_helper,
-    };
-    info!("BLS");
-    let bls_proof = create_proof_no_zk(circuit, &parameters.epochs)?;
-
-    Ok(bls_proof)
+        min_validators,
+        hash_helper: None,
+    })
 }
 
-/// Helper which creates the hashproof inside BLS12-377
-fn generate_hash_helper(
-    params: &Groth16Parameters<BLSCurve>,
+/// Computes the CRH bits of each epoch, the witness of the HashToBits helper
+fn hash_witness<F: FnMut(Progress) -> bool + Send>(
     transitions: &[EpochTransition],
-) -> Result<HashToBitsHelper<BLSCurve>, SynthesisError> {
+    reporter: &ProgressReporter<F>,
+) -> Result<Vec<Vec<Option<bool>>>, SynthesisError> {
     let hash_to_g1 = &COMPOSITE_HASH_TO_G1;
     let composite_hasher = &COMPOSITE_HASHER;
 
-    // Generate the CRH per epoch
-    let message_bits = transitions
-        .iter()
-        .map(|transition| {
+    // Generate the CRH per epoch. Finding the try-and-increment attempt dominates the
+    // witness generation and is independent for each epoch, so the epochs are processed in
+    // parallel
+    let num_epochs = transitions.len();
+    let num_done = AtomicUsize::new(0);
+    reporter.report(ProvingStage::HashWitness, 0, num_epochs)?;
+    transitions
+        .par_iter()
+        .map(|transition| -> Result<Vec<Option<bool>>, SynthesisError> {
             let block = &transition.block;
-            let epoch_bytes = block.encode_to_bytes().unwrap();
+            let epoch_bytes = witness_bytes(block.encode_to_bytes().unwrap());
 
             // We need to find the counter so that the CRH hash we use will eventually result on an element on the curve
             let (_, counter) = hash_to_g1
-                .hash_with_attempt(SIG_DOMAIN, &epoch_bytes, &[])
-                .unwrap();
-            let crh_bytes = composite_hasher
-                .crh(&[], &[&[counter as u8][..], &epoch_bytes].concat(), 0)
+                .hash_with_attempt(Celo::DOMAIN, &epoch_bytes, &[])
                 .unwrap();
+            let crh_input = witness_bytes([&[counter as u8][..], &epoch_bytes[..]].concat());
+            let crh_bytes = witness_bytes(composite_hasher.crh(&[], &crh_input, 0).unwrap());
             // The verifier should run both the crh and the xof here to generate a
             // valid statement for the verify
-            bytes_to_bits(&crh_bytes, 384)
+            let bits = bytes_to_bits(&crh_bytes, 384)
                 .iter()
                 .map(|b| Some(*b))
-                .collect()
+                .collect();
+            let done = num_done.fetch_add(1, Ordering::SeqCst) + 1;
+            reporter.report(ProvingStage::HashWitness, done, num_epochs)?;
+            Ok(bits)
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
 
+/// Helper which creates the hashproof inside BLS12-377
+fn prove_hash_helper<F: FnMut(Progress) -> bool + Send>(
+    params: &Groth16Parameters<BLSCurve>,
+    message_bits: Vec<Vec<Option<bool>>>,
+    reporter: &ProgressReporter<F>,
+) -> Result<HashToBitsHelper<BLSCurve>, SynthesisError> {
     // Generate proof of correct calculation of the CRH->Blake hashes
     // to make Hash to G1 cheaper
     let circuit = HashToBits { message_bits };
     info!("CRH->XOF");
+    let circuit = ProgressCircuit::new(
+        circuit,
+        reporter,
+        ProvingStage::HashToBits,
+        params.a_query.len(),
+    );
+    #[cfg(feature = "synthesis-timing")]
+    let circuit = crate::TimedCircuit::new(circuit);
     let hash_proof = create_proof_no_zk(circuit, params)?;
 
     Ok(HashToBitsHelper {
@@ -135,6 +387,9 @@ fn to_epoch_data(block: &EpochBlock) -> EpochData<BLSCurve> {
             .iter()
             .map(|pubkey| Some(*pubkey.as_ref()))
             .collect(),
+        version: block.version,
+        epoch_entropy: block.epoch_entropy.clone(),
+        parent_entropy: block.parent_entropy.clone(),
     }
 }
 
@@ -149,7 +404,13 @@ fn to_update(transition: &EpochTransition) -> SingleUpdate<BLSCurve> {
     }
 }
 
-fn to_dummy_update(num_validators: u32) -> SingleUpdate<BLSCurve> {
+fn to_dummy_update(num_validators: u32, version: EncodingVersion) -> SingleUpdate<BLSCurve> {
+    // dummy epochs are not chained, so their entropy is arbitrary
+    let entropy = if version.has_entropy() {
+        Some(vec![0; ENTROPY_BYTES])
+    } else {
+        None
+    };
     SingleUpdate {
         epoch_data: EpochData {
             maximum_non_signers: 0,
@@ -157,6 +418,9 @@ fn to_dummy_update(num_validators: u32) -> SingleUpdate<BLSCurve> {
             public_keys: (0..num_validators)
                 .map(|_| Some(BLSCurveG2::prime_subgroup_generator()))
                 .collect::<Vec<_>>(),
+            version,
+            epoch_entropy: entropy.clone(),
+            parent_entropy: entropy,
         },
         signed_bitmap: (0..num_validators).map(|_| Some(true)).collect::<Vec<_>>(),
     }
@@ -0,0 +1,98 @@
+use super::{prove, verifier::verify_prepared, BLSCurve, CPCurve, Parameters, VerificationError};
+use crate::epoch_block::{EpochBlock, EpochTransition};
+use groth16::{prepare_verifying_key, Proof, VerifyingKey};
+use r1cs_core::SynthesisError;
+use std::ops::Range;
+use tracing::info;
+
+/// A proof for a sub-range of the transitions of a chain of epochs, along with the
+/// epochs at its endpoints which are required for verifying it
+#[derive(Clone, Debug)]
+pub struct RangeProof {
+    /// The epoch preceding the first transition of the range
+    pub first_epoch: EpochBlock,
+    /// The epoch produced by the last transition of the range
+    pub last_epoch: EpochBlock,
+    /// The SNARK proof for the range
+    pub proof: Proof<CPCurve>,
+}
+
+/// Generates a proof for the transitions in `range`, where `range` indexes into `transitions`
+/// and `initial_epoch` is the epoch preceding `transitions[0]`. Proofs over consecutive ranges
+/// can be generated in parallel and later verified together with `verify_stitched`.
+///
+/// # Panics
+///
+/// If `range` is empty or out of the bounds of `transitions`
+pub fn prove_range(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    range: Range<usize>,
+    max_transitions: usize,
+) -> Result<RangeProof, SynthesisError> {
+    assert!(
+        range.start < range.end && range.end <= transitions.len(),
+        "invalid range {:?} for {} transitions",
+        range,
+        transitions.len()
+    );
+    info!(
+        "Generating proof for transitions {} to {}",
+        range.start, range.end
+    );
+
+    let first_epoch = if range.start == 0 {
+        initial_epoch.clone()
+    } else {
+        transitions[range.start - 1].block.clone()
+    };
+    let last_epoch = transitions[range.end - 1].block.clone();
+
+    let proof = prove(
+        parameters,
+        num_validators,
+        &first_epoch,
+        &transitions[range],
+        max_transitions,
+    )?;
+
+    Ok(RangeProof {
+        first_epoch,
+        last_epoch,
+        proof,
+    })
+}
+
+/// Verifies a chain of range proofs. The last epoch of each proof must match the first epoch
+/// of the next one, so that together they prove the transition from the first epoch of the
+/// first proof to the last epoch of the last proof.
+pub fn verify_stitched(
+    vk: &VerifyingKey<CPCurve>,
+    proofs: &[RangeProof],
+) -> Result<(), VerificationError> {
+    if proofs.is_empty() {
+        return Err(VerificationError::EmptyProofChain);
+    }
+
+    // Check that the endpoints match before doing any expensive work
+    for (i, pair) in proofs.windows(2).enumerate() {
+        if pair[0].last_epoch != pair[1].first_epoch {
+            return Err(VerificationError::DisconnectedRanges(i));
+        }
+    }
+
+    info!("Verifying {} stitched proofs", proofs.len());
+    let pvk = prepare_verifying_key(vk);
+    for range_proof in proofs {
+        verify_prepared(
+            &pvk,
+            &range_proof.first_epoch,
+            &range_proof.last_epoch,
+            &range_proof.proof,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,832 @@
+//! Scheduling of the proofs of a prover service shared by several clients.
+//!
+//! A `JobScheduler` runs the proofs submitted by its clients on a fixed number of worker
+//! threads. Each proof runs in three phases:
+//!
+//! 1. the witness generation, which for the epochs circuit is dominated by the search of the
+//!    try-and-increment attempts of the HashToBits helper, runs concurrently on all workers,
+//! 2. the synthesis of the circuit into the scalars of the proof, which also runs
+//!    concurrently on all workers,
+//! 3. the multi-scalar multiplications (MSMs) of the proof, which hold the proving key's
+//!    queries and the scalars in memory at once, only run in `msm_slots` jobs at a time. The
+//!    other workers wait for a slot with their circuit synthesized.
+//!
+//! A job which panics fails with `SchedulerError::Panicked`, and its worker moves on to the
+//! next job.
+//!
+//! Queued jobs are started by priority. Among the jobs of the same priority, the ones of the
+//! client with the fewest running jobs start first, then the ones of the client whose last job
+//! started the longest ago, and then the ones submitted first, so that a client submitting many
+//! jobs does not starve the others. Each client may have at most its quota of jobs queued or
+//! running, further submissions are rejected until one of them is done.
+use super::{
+    prover::{epochs_witness, synthesize_epochs_witness, EpochsWitness},
+    setup::Parameters,
+    BLSCurve, CPCurve, ProofScalars,
+};
+use crate::{
+    epoch_block::{EpochBlock, EpochTransition},
+    progress::{ProgressReporter, ProvingError},
+    witness_check::check_witness,
+};
+use groth16::Proof as Groth16Proof;
+use std::{
+    any::Any,
+    cmp::Reverse,
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Condvar, Mutex, MutexGuard, Weak,
+    },
+    thread::{self, JoinHandle},
+};
+use thiserror::Error;
+use tracing::{info, warn};
+
+#[derive(Debug, Error)]
+/// Errors of the jobs of a `JobScheduler`
+pub enum SchedulerError {
+    /// The client already has its quota of jobs queued or running
+    #[error("client {client} already has {quota} jobs queued or running")]
+    QuotaExceeded { client: String, quota: usize },
+    /// The scheduler was dropped before the job ran
+    #[error("the scheduler was shut down")]
+    ShutDown,
+    /// The proof failed or was cancelled
+    #[error(transparent)]
+    Proving(#[from] ProvingError),
+    /// The job panicked, with the message of the panic
+    #[error("the job panicked: {0}")]
+    Panicked(String),
+}
+
+/// The priority of a job. Queued jobs with a higher priority always start first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low = 0,
+    Normal = 1,
+    High = 2,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
+}
+
+/// A proof run by a `JobScheduler`, split in the phases of the scheduler
+pub trait ProverJob: Send + 'static {
+    /// The witness of the proof
+    type Witness: Send;
+    /// The synthesized circuit of the proof, i.e. the scalars of its MSMs
+    type Assignment: Send;
+    /// The proof
+    type Proof: Send + 'static;
+
+    /// Computes the witness of the proof. The witnesses of several jobs are computed
+    /// concurrently. The job should stop with `ProvingError::Cancelled` once `cancelled` is set.
+    fn witness(&self, cancelled: &AtomicBool) -> Result<Self::Witness, ProvingError>;
+
+    /// Synthesizes the circuit of the proof with its witness. The circuits of several jobs
+    /// are synthesized concurrently, before they wait for an MSM slot.
+    fn synthesize(
+        &self,
+        witness: Self::Witness,
+        cancelled: &AtomicBool,
+    ) -> Result<Self::Assignment, ProvingError>;
+
+    /// Computes the proof from its assignment, which runs the MSMs of the proof. Only
+    /// `msm_slots` jobs prove at a time.
+    fn prove(
+        &self,
+        assignment: Self::Assignment,
+        cancelled: &AtomicBool,
+    ) -> Result<Self::Proof, ProvingError>;
+}
+
+/// A proof of epoch transitions, as generated by `prove`
+pub struct EpochsJob {
+    /// The parameters of the epochs circuit, usually shared by all the jobs of a scheduler
+    pub parameters: Arc<Parameters<CPCurve, BLSCurve>>,
+    /// The number of validators of each epoch
+    pub num_validators: u32,
+    /// The initial epoch
+    pub initial_epoch: EpochBlock,
+    /// The transitions following the initial epoch
+    pub transitions: Vec<EpochTransition>,
+    /// The maximum number of transitions of the circuit
+    pub max_transitions: usize,
+}
+
+/// The HashToBits helper is proven while synthesizing the epochs circuit, which verifies it,
+/// so only the MSMs of the epochs proof hold an MSM slot.
+impl ProverJob for EpochsJob {
+    type Witness = EpochsWitness;
+    type Assignment = ProofScalars;
+    type Proof = Groth16Proof<CPCurve>;
+
+    fn witness(&self, cancelled: &AtomicBool) -> Result<Self::Witness, ProvingError> {
+        check_witness(
+            self.parameters.min_validators,
+            self.num_validators,
+            &self.initial_epoch,
+            &self.transitions,
+            self.max_transitions,
+        )?;
+        let reporter = ProgressReporter::new(|_| !cancelled.load(Ordering::SeqCst));
+        let result = epochs_witness(
+            &self.parameters,
+            self.num_validators,
+            &self.initial_epoch,
+            &self.transitions,
+            self.max_transitions,
+            &reporter,
+        );
+        reporter.finish(result)
+    }
+
+    fn synthesize(
+        &self,
+        witness: Self::Witness,
+        cancelled: &AtomicBool,
+    ) -> Result<Self::Assignment, ProvingError> {
+        let reporter = ProgressReporter::new(|_| !cancelled.load(Ordering::SeqCst));
+        let result = synthesize_epochs_witness(&self.parameters, witness, &reporter);
+        reporter.finish(result)
+    }
+
+    fn prove(
+        &self,
+        assignment: Self::Assignment,
+        cancelled: &AtomicBool,
+    ) -> Result<Self::Proof, ProvingError> {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(ProvingError::Cancelled);
+        }
+        Ok(assignment.prove(&self.parameters.epochs)?)
+    }
+}
+
+/// The configuration of a `JobScheduler`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchedulerConfig {
+    /// The number of worker threads, i.e. the number of jobs running at once. Each of them
+    /// computes its witness on rayon's thread pool.
+    pub workers: usize,
+    /// The number of jobs computing their MSMs at once
+    pub msm_slots: usize,
+    /// The number of jobs each client may have queued or running, unless `set_quota`
+    /// changed it
+    pub default_quota: usize,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            msm_slots: 1,
+            default_quota: 4,
+        }
+    }
+}
+
+/// A job submitted to a `JobScheduler`, to wait for its proof or cancel it
+pub struct JobHandle<P> {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+    receiver: mpsc::Receiver<Result<P, SchedulerError>>,
+    scheduler: Weak<dyn Discard>,
+}
+
+impl<P> JobHandle<P> {
+    /// The identifier of the job, unique among the jobs of its scheduler
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Cancels the job. A queued job is never started, and a running job stops at its next
+    /// progress report, see `ProvingError::Cancelled`.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Some(scheduler) = self.scheduler.upgrade() {
+            scheduler.discard_cancelled();
+        }
+    }
+
+    /// Waits for the proof of the job
+    pub fn wait(self) -> Result<P, SchedulerError> {
+        self.receiver
+            .recv()
+            .unwrap_or(Err(SchedulerError::ShutDown))
+    }
+
+    /// Returns the proof of the job if it is done, or the handle otherwise
+    pub fn try_wait(self) -> Result<Result<P, SchedulerError>, Self> {
+        match self.receiver.try_recv() {
+            Ok(result) => Ok(result),
+            Err(mpsc::TryRecvError::Empty) => Err(self),
+            Err(mpsc::TryRecvError::Disconnected) => Ok(Err(SchedulerError::ShutDown)),
+        }
+    }
+}
+
+/// Runs the proofs of several clients with priorities and quotas, see the module docs
+pub struct JobScheduler<J: ProverJob> {
+    shared: Arc<Shared<J>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared<J: ProverJob> {
+    config: SchedulerConfig,
+    state: Mutex<State<J>>,
+    /// Notified when a job is queued or the scheduler shuts down
+    queued: Condvar,
+    /// Notified when an MSM slot is released
+    released: Condvar,
+}
+
+struct State<J: ProverJob> {
+    queue: Vec<QueuedJob<J>>,
+    /// The number of queued or running jobs of each client
+    admitted: HashMap<String, usize>,
+    /// The number of running jobs of each client
+    running: HashMap<String, usize>,
+    /// When the last job of each client started, counted in started jobs
+    last_started: HashMap<String, u64>,
+    num_started: u64,
+    quotas: HashMap<String, usize>,
+    msm_running: usize,
+    next_id: u64,
+    shut_down: bool,
+}
+
+struct QueuedJob<J: ProverJob> {
+    id: u64,
+    client: String,
+    priority: Priority,
+    job: J,
+    cancelled: Arc<AtomicBool>,
+    sender: mpsc::Sender<Result<J::Proof, SchedulerError>>,
+}
+
+impl<J: ProverJob> JobScheduler<J> {
+    /// Starts the worker threads of the scheduler
+    ///
+    /// # Panics
+    ///
+    /// - If the configuration has no workers or no MSM slots
+    pub fn new(config: SchedulerConfig) -> Self {
+        assert!(config.workers > 0, "the scheduler needs a worker");
+        assert!(config.msm_slots > 0, "the scheduler needs an MSM slot");
+        let shared = Arc::new(Shared {
+            config,
+            state: Mutex::new(State {
+                queue: Vec::new(),
+                admitted: HashMap::new(),
+                running: HashMap::new(),
+                last_started: HashMap::new(),
+                num_started: 0,
+                quotas: HashMap::new(),
+                msm_running: 0,
+                next_id: 0,
+                shut_down: false,
+            }),
+            queued: Condvar::new(),
+            released: Condvar::new(),
+        });
+        let workers = (0..shared.config.workers)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || shared.work())
+            })
+            .collect();
+        Self { shared, workers }
+    }
+
+    /// Sets the number of jobs the client may have queued or running. Lowering it does not
+    /// affect the jobs already submitted.
+    pub fn set_quota(&self, client: &str, quota: usize) {
+        self.shared.lock().quotas.insert(client.to_string(), quota);
+    }
+
+    /// Queues the job of the client, failing with `SchedulerError::QuotaExceeded` if the
+    /// client already has its quota of jobs queued or running
+    pub fn submit(
+        &self,
+        client: &str,
+        priority: Priority,
+        job: J,
+    ) -> Result<JobHandle<J::Proof>, SchedulerError> {
+        let mut state = self.shared.lock();
+        if state.shut_down {
+            return Err(SchedulerError::ShutDown);
+        }
+        state.discard_cancelled();
+        let quota = state
+            .quotas
+            .get(client)
+            .copied()
+            .unwrap_or(self.shared.config.default_quota);
+        let admitted = state.admitted.entry(client.to_string()).or_insert(0);
+        if *admitted >= quota {
+            return Err(SchedulerError::QuotaExceeded {
+                client: client.to_string(),
+                quota,
+            });
+        }
+        *admitted += 1;
+
+        let id = state.next_id;
+        state.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        state.queue.push(QueuedJob {
+            id,
+            client: client.to_string(),
+            priority,
+            job,
+            cancelled: cancelled.clone(),
+            sender,
+        });
+        info!("Queued job {} of client {}", id, client);
+        self.shared.queued.notify_one();
+
+        let scheduler = Arc::downgrade(&self.shared) as Weak<dyn Discard>;
+        Ok(JobHandle {
+            id,
+            cancelled,
+            receiver,
+            scheduler,
+        })
+    }
+
+    /// The number of jobs waiting for a worker
+    pub fn num_queued(&self) -> usize {
+        let mut state = self.shared.lock();
+        state.discard_cancelled();
+        state.queue.len()
+    }
+}
+
+impl<J: ProverJob> Drop for JobScheduler<J> {
+    /// Fails the queued jobs with `SchedulerError::ShutDown`, and waits for the running jobs
+    fn drop(&mut self) {
+        {
+            let mut state = self.shared.lock();
+            state.shut_down = true;
+            for queued in state.queue.drain(..) {
+                let _ = queued.sender.send(Err(SchedulerError::ShutDown));
+            }
+        }
+        self.shared.queued.notify_all();
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                warn!("A worker of the scheduler panicked");
+            }
+        }
+    }
+}
+
+impl<J: ProverJob> Shared<J> {
+    fn lock(&self) -> MutexGuard<State<J>> {
+        self.state
+            .lock()
+            .expect("a worker of the scheduler panicked")
+    }
+
+    /// Runs the queued jobs until the scheduler shuts down
+    fn work(&self) {
+        while let Some(queued) = self.next_job() {
+            let admission = Admission {
+                shared: self,
+                client: &queued.client,
+            };
+            let result = if queued.cancelled.load(Ordering::SeqCst) {
+                Err(ProvingError::Cancelled.into())
+            } else {
+                info!("Running job {} of client {}", queued.id, queued.client);
+                panic::catch_unwind(AssertUnwindSafe(|| self.run(&queued))).unwrap_or_else(
+                    |payload| {
+                        warn!("Job {} of client {} panicked", queued.id, queued.client);
+                        Err(SchedulerError::Panicked(panic_message(payload)))
+                    },
+                )
+            };
+
+            drop(admission);
+            let _ = queued.sender.send(result);
+        }
+    }
+
+    /// Runs the phases of the job, holding an MSM slot for its MSMs only
+    fn run(&self, queued: &QueuedJob<J>) -> Result<J::Proof, SchedulerError> {
+        let witness = queued.job.witness(&queued.cancelled)?;
+        let assignment = queued.job.synthesize(witness, &queued.cancelled)?;
+        let _slot = self.acquire_msm_slot();
+        Ok(queued.job.prove(assignment, &queued.cancelled)?)
+    }
+
+    /// Waits for the next job to run, or returns `None` once the scheduler shuts down
+    fn next_job(&self) -> Option<QueuedJob<J>> {
+        let mut state = self.lock();
+        loop {
+            if state.shut_down {
+                return None;
+            }
+            state.discard_cancelled();
+            if let Some(next) = state.next_index() {
+                let queued = state.queue.remove(next);
+                *state.running.entry(queued.client.clone()).or_insert(0) += 1;
+                state.num_started += 1;
+                let num_started = state.num_started;
+                state
+                    .last_started
+                    .insert(queued.client.clone(), num_started);
+                return Some(queued);
+            }
+            state = self
+                .queued
+                .wait(state)
+                .expect("a worker of the scheduler panicked");
+        }
+    }
+
+    /// Waits for an MSM slot, which is released when the returned guard is dropped
+    fn acquire_msm_slot(&self) -> MsmSlot<J> {
+        let mut state = self.lock();
+        while state.msm_running >= self.config.msm_slots {
+            state = self
+                .released
+                .wait(state)
+                .expect("a worker of the scheduler panicked");
+        }
+        state.msm_running += 1;
+        MsmSlot { shared: self }
+    }
+}
+
+impl<J: ProverJob> State<J> {
+    /// The index in the queue of the job to run next, see the module docs
+    fn next_index(&self) -> Option<usize> {
+        self.queue
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, queued)| {
+                let running = self.running.get(&queued.client).copied().unwrap_or(0);
+                let last_started = self.last_started.get(&queued.client).copied();
+                (Reverse(queued.priority), running, last_started, queued.id)
+            })
+            .map(|(index, _)| index)
+    }
+
+    /// Removes the cancelled jobs from the queue, releasing their clients' quota
+    fn discard_cancelled(&mut self) {
+        let (cancelled, queue) = self
+            .queue
+            .drain(..)
+            .partition::<Vec<_>, _>(|queued| queued.cancelled.load(Ordering::SeqCst));
+        self.queue = queue;
+        for queued in cancelled {
+            release(&mut self.admitted, &queued.client);
+            let _ = queued.sender.send(Err(ProvingError::Cancelled.into()));
+        }
+    }
+}
+
+/// Discards the cancelled jobs of a scheduler when their handle cancels them
+trait Discard: Send + Sync {
+    fn discard_cancelled(&self);
+}
+
+impl<J: ProverJob> Discard for Shared<J> {
+    fn discard_cancelled(&self) {
+        self.lock().discard_cancelled();
+    }
+}
+
+fn release(counts: &mut HashMap<String, usize>, client: &str) {
+    if let Some(count) = counts.get_mut(client) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(client);
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or_else(
+            || "unknown panic".to_string(),
+            |message| message.to_string(),
+        ),
+    }
+}
+
+/// The counts of a running job in the quota of its client, which are released when it is
+/// done, even if it panicked
+struct Admission<'a, J: ProverJob> {
+    shared: &'a Shared<J>,
+    client: &'a str,
+}
+
+impl<'a, J: ProverJob> Drop for Admission<'a, J> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        release(&mut state.running, self.client);
+        release(&mut state.admitted, self.client);
+    }
+}
+
+/// An MSM slot held by a running job
+struct MsmSlot<'a, J: ProverJob> {
+    shared: &'a Shared<J>,
+}
+
+impl<'a, J: ProverJob> Drop for MsmSlot<'a, J> {
+    fn drop(&mut self) {
+        self.shared.lock().msm_running -= 1;
+        self.shared.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::AtomicUsize, time::Duration};
+
+    /// A job which records the order in which the jobs run and how many prove at once
+    struct Recorder {
+        name: &'static str,
+        order: Arc<Mutex<Vec<&'static str>>>,
+        synthesized: Arc<Mutex<Vec<&'static str>>>,
+        proving: Arc<AtomicUsize>,
+        max_proving: Arc<AtomicUsize>,
+        gate: Option<Arc<AtomicBool>>,
+        msm_gate: Option<Arc<AtomicBool>>,
+        panics: bool,
+    }
+
+    /// Blocks the worker until the gate opens or the job is cancelled
+    fn wait_open(
+        gate: &Option<Arc<AtomicBool>>,
+        cancelled: &AtomicBool,
+    ) -> Result<(), ProvingError> {
+        while let Some(gate) = gate {
+            if gate.load(Ordering::SeqCst) {
+                break;
+            }
+            if cancelled.load(Ordering::SeqCst) {
+                return Err(ProvingError::Cancelled);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+
+    impl ProverJob for Recorder {
+        type Witness = ();
+        type Assignment = ();
+        type Proof = &'static str;
+
+        fn witness(&self, cancelled: &AtomicBool) -> Result<(), ProvingError> {
+            self.order.lock().unwrap().push(self.name);
+            if self.panics {
+                panic!("{} panicked", self.name);
+            }
+            // blocks the worker until the gate opens, so that the other jobs stay queued
+            wait_open(&self.gate, cancelled)
+        }
+
+        fn synthesize(&self, _: (), _: &AtomicBool) -> Result<(), ProvingError> {
+            self.synthesized.lock().unwrap().push(self.name);
+            Ok(())
+        }
+
+        fn prove(&self, _: (), cancelled: &AtomicBool) -> Result<&'static str, ProvingError> {
+            let proving = self.proving.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_proving.fetch_max(proving, Ordering::SeqCst);
+            wait_open(&self.msm_gate, cancelled)?;
+            thread::sleep(Duration::from_millis(5));
+            self.proving.fetch_sub(1, Ordering::SeqCst);
+            Ok(self.name)
+        }
+    }
+
+    #[derive(Default)]
+    struct Jobs {
+        order: Arc<Mutex<Vec<&'static str>>>,
+        synthesized: Arc<Mutex<Vec<&'static str>>>,
+        proving: Arc<AtomicUsize>,
+        max_proving: Arc<AtomicUsize>,
+    }
+
+    impl Jobs {
+        fn job(&self, name: &'static str) -> Recorder {
+            Recorder {
+                name,
+                order: self.order.clone(),
+                synthesized: self.synthesized.clone(),
+                proving: self.proving.clone(),
+                max_proving: self.max_proving.clone(),
+                gate: None,
+                msm_gate: None,
+                panics: false,
+            }
+        }
+
+        fn gated(&self, name: &'static str, gate: &Arc<AtomicBool>) -> Recorder {
+            Recorder {
+                gate: Some(gate.clone()),
+                ..self.job(name)
+            }
+        }
+
+        fn msm_gated(&self, name: &'static str, gate: &Arc<AtomicBool>) -> Recorder {
+            Recorder {
+                msm_gate: Some(gate.clone()),
+                ..self.job(name)
+            }
+        }
+
+        fn panicking(&self, name: &'static str) -> Recorder {
+            Recorder {
+                panics: true,
+                ..self.job(name)
+            }
+        }
+    }
+
+    fn config(workers: usize, msm_slots: usize) -> SchedulerConfig {
+        SchedulerConfig {
+            workers,
+            msm_slots,
+            ..Default::default()
+        }
+    }
+
+    /// Waits until the jobs submitted before are picked by a worker
+    fn wait_started(scheduler: &JobScheduler<Recorder>, queued: usize) {
+        while scheduler.num_queued() > queued {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn runs_by_priority_and_fairness() {
+        let jobs = Jobs::default();
+        let gate = Arc::new(AtomicBool::new(false));
+        let scheduler = JobScheduler::new(config(1, 1));
+        let blocker = scheduler
+            .submit("a", Priority::Normal, jobs.gated("blocker", &gate))
+            .unwrap();
+        wait_started(&scheduler, 0);
+
+        // the last job of "a" started after the ones of "b", so the job of "b" of the same
+        // priority starts first
+        let handles = vec![
+            scheduler.submit("a", Priority::Normal, jobs.job("a1")),
+            scheduler.submit("b", Priority::Normal, jobs.job("b1")),
+            scheduler.submit("a", Priority::Low, jobs.job("a2")),
+            scheduler.submit("c", Priority::High, jobs.job("c1")),
+        ];
+        gate.store(true, Ordering::SeqCst);
+        assert_eq!(blocker.wait().unwrap(), "blocker");
+        for handle in handles {
+            handle.unwrap().wait().unwrap();
+        }
+        assert_eq!(
+            *jobs.order.lock().unwrap(),
+            vec!["blocker", "c1", "b1", "a1", "a2"]
+        );
+    }
+
+    #[test]
+    fn serializes_the_msms() {
+        let jobs = Jobs::default();
+        let scheduler = JobScheduler::new(config(4, 1));
+        let handles = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| scheduler.submit(name, Priority::Normal, jobs.job(*name)))
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.unwrap().wait().unwrap();
+        }
+        assert_eq!(jobs.max_proving.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn synthesizes_outside_the_msm_slots() {
+        let jobs = Jobs::default();
+        let gate = Arc::new(AtomicBool::new(false));
+        let scheduler = JobScheduler::new(config(2, 1));
+        let first = scheduler
+            .submit("a", Priority::Normal, jobs.msm_gated("a1", &gate))
+            .unwrap();
+        while jobs.proving.load(Ordering::SeqCst) == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // the circuit of the second job is synthesized while the first one holds the only
+        // MSM slot
+        let second = scheduler
+            .submit("b", Priority::Normal, jobs.job("b1"))
+            .unwrap();
+        while !jobs.synthesized.lock().unwrap().contains(&"b1") {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(jobs.proving.load(Ordering::SeqCst), 1);
+
+        gate.store(true, Ordering::SeqCst);
+        assert_eq!(first.wait().unwrap(), "a1");
+        assert_eq!(second.wait().unwrap(), "b1");
+        assert_eq!(jobs.max_proving.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn reports_panicked_jobs() {
+        let jobs = Jobs::default();
+        let scheduler = JobScheduler::new(config(1, 1));
+        scheduler.set_quota("a", 1);
+        let panicked = scheduler
+            .submit("a", Priority::Normal, jobs.panicking("a1"))
+            .unwrap();
+        match panicked.wait() {
+            Err(SchedulerError::Panicked(message)) => assert_eq!(message, "a1 panicked"),
+            _ => panic!("the panic of the job was not reported"),
+        }
+
+        // the quota of the client is released, and the worker runs the next jobs
+        let next = scheduler
+            .submit("a", Priority::Normal, jobs.job("a2"))
+            .unwrap();
+        assert_eq!(next.wait().unwrap(), "a2");
+    }
+
+    #[test]
+    fn enforces_quotas() {
+        let jobs = Jobs::default();
+        let gate = Arc::new(AtomicBool::new(false));
+        let scheduler = JobScheduler::new(config(1, 1));
+        scheduler.set_quota("a", 2);
+        let first = scheduler
+            .submit("a", Priority::Normal, jobs.gated("a1", &gate))
+            .unwrap();
+        let second = scheduler
+            .submit("a", Priority::Normal, jobs.job("a2"))
+            .unwrap();
+        match scheduler.submit("a", Priority::High, jobs.job("a3")) {
+            Err(SchedulerError::QuotaExceeded { quota: 2, .. }) => {}
+            _ => panic!("the quota of the client was not enforced"),
+        }
+        // the quota of the other clients is the default one
+        let other = scheduler
+            .submit("b", Priority::Normal, jobs.job("b1"))
+            .unwrap();
+
+        // cancelling a queued job releases its quota
+        second.cancel();
+        match second.wait() {
+            Err(SchedulerError::Proving(ProvingError::Cancelled)) => {}
+            _ => panic!("the job was not cancelled"),
+        }
+        let third = scheduler
+            .submit("a", Priority::Normal, jobs.job("a3"))
+            .unwrap();
+
+        gate.store(true, Ordering::SeqCst);
+        assert_eq!(first.wait().unwrap(), "a1");
+        assert_eq!(other.wait().unwrap(), "b1");
+        assert_eq!(third.wait().unwrap(), "a3");
+        assert!(!jobs.order.lock().unwrap().contains(&"a2"));
+    }
+
+    #[test]
+    fn fails_queued_jobs_on_shutdown() {
+        let jobs = Jobs::default();
+        let gate = Arc::new(AtomicBool::new(false));
+        let scheduler = JobScheduler::new(config(1, 1));
+        let running = scheduler
+            .submit("a", Priority::Normal, jobs.gated("a1", &gate))
+            .unwrap();
+        wait_started(&scheduler, 0);
+        let queued = scheduler
+            .submit("a", Priority::Normal, jobs.job("a2"))
+            .unwrap();
+
+        // the running job completes once the queued one failed, while the scheduler is
+        // dropped
+        let opener = thread::spawn(move || {
+            let result = queued.wait();
+            gate.store(true, Ordering::SeqCst);
+            result
+        });
+        drop(scheduler);
+        match opener.join().unwrap() {
+            Err(SchedulerError::ShutDown) => {}
+            _ => panic!("the queued job ran after the shutdown"),
+        }
+        assert_eq!(running.wait().unwrap(), "a1");
+    }
+}
@@ -1,7 +1,10 @@
 /// Prover Verifier Generator
 ///
 /// Setup: Trusted setup over Groth16 for the Hash To Bits and the Epoch Transition circuits
-use crate::gadgets::{HashToBits, ValidatorSetUpdate};
+use crate::{
+    encoding::EncodingVersion,
+    gadgets::{HashToBits, ValidatorSetUpdate},
+};
 
 use algebra::PairingEngine;
 use r1cs_core::SynthesisError;
@@ -18,6 +21,9 @@ type Result<T> = std::result::Result<T, SynthesisError>;
 pub struct Parameters<CP: PairingEngine, BLS: PairingEngine> {
     pub epochs: Groth16Parameters<CP>,
     pub hash_to_bits: Option<Groth16Parameters<BLS>>,
+    /// The minimum number of validators in each epoch which are not padding slots, which
+    /// the epochs circuit was generated with
+    pub min_validators: u32,
 }
 
 /// Initializes the Hash To Bits and Validator Set Update circuits with random parameters
@@ -27,6 +33,9 @@ pub struct Parameters<CP: PairingEngine, BLS: PairingEngine> {
 /// which will perform 2 setups, one for the CRH->XOF hashes in BLS12-377 and the rest
 /// of the circuit in BW6_761. If set to `false, only 1 setup will be done (at the expense
 /// of having a longer proving time due to CRH->XOF hashes being done in BW6_761)
+///
+/// With the `secure-memory` feature, the randomness of the setup is drawn from a locked RNG
+/// seeded by `rng`, whose state is wiped once the parameters are generated.
 pub fn trusted_setup<R: Rng>(
     num_validators: usize,
     num_epochs: usize,
@@ -34,14 +43,70 @@ pub fn trusted_setup<R: Rng>(
     rng: &mut R,
     hashes_in_bls12_377: bool,
 ) -> Result<Parameters<CPCurve, BLSCurve>> {
+    trusted_setup_with_version(
+        num_validators,
+        num_epochs,
+        maximum_non_signers,
+        rng,
+        hashes_in_bls12_377,
+        EncodingVersion::V1,
+    )
+}
+
+/// Same as `trusted_setup`, but generates parameters for epoch blocks encoded with the
+/// provided `EncodingVersion`. Proofs can only be produced for blocks of that version.
+pub fn trusted_setup_with_version<R: Rng>(
+    num_validators: usize,
+    num_epochs: usize,
+    maximum_non_signers: usize,
+    rng: &mut R,
+    hashes_in_bls12_377: bool,
+    version: EncodingVersion,
+) -> Result<Parameters<CPCurve, BLSCurve>> {
+    trusted_setup_with_min_validators(
+        num_validators,
+        num_epochs,
+        maximum_non_signers,
+        0,
+        rng,
+        hashes_in_bls12_377,
+        version,
+    )
+}
+
+/// Same as `trusted_setup_with_version`, but the circuit also enforces that every epoch has
+/// at least `min_validators` validators which are not padding slots, so that a prover cannot
+/// pad a validator set down to a few keys which it controls. The bound is recorded in the
+/// parameters, and must be checked by verifiers through `CircuitShape`.
+///
+/// Fails with `SynthesisError::Unsatisfiable` if `min_validators > num_validators`.
+pub fn trusted_setup_with_min_validators<R: Rng>(
+    num_validators: usize,
+    num_epochs: usize,
+    maximum_non_signers: usize,
+    min_validators: u32,
+    rng: &mut R,
+    hashes_in_bls12_377: bool,
+    version: EncodingVersion,
+) -> Result<Parameters<CPCurve, BLSCurve>> {
+    if min_validators as usize > num_validators {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+
+    // Draw the toxic randomness from an RNG whose state is wiped after the setup
+    #[cfg(feature = "secure-memory")]
+    let rng = &mut crate::secure::LockedRng::from_rng(rng);
+
     setup(
         num_validators,
         num_epochs,
         maximum_non_signers,
+        min_validators,
         rng,
         |c, rng| generate_random_parameters(c, rng),
         |c, rng| generate_random_parameters(c, rng),
         hashes_in_bls12_377,
+        version,
     )
 }
 
@@ -53,6 +118,23 @@ mod tests {
         let rng = &mut rand::thread_rng();
         assert!(trusted_setup(3, 2, 1, rng, false).is_ok())
     }
+
+    #[test]
+    fn runs_setup_with_entropy() {
+        let rng = &mut rand::thread_rng();
+        assert!(trusted_setup_with_version(3, 2, 1, rng, false, EncodingVersion::V2).is_ok())
+    }
+
+    #[test]
+    fn runs_setup_with_min_validators() {
+        let rng = &mut rand::thread_rng();
+        let params =
+            trusted_setup_with_min_validators(3, 2, 1, 2, rng, false, EncodingVersion::V1).unwrap();
+        assert_eq!(params.min_validators, 2);
+        assert!(
+            trusted_setup_with_min_validators(3, 2, 1, 4, rng, false, EncodingVersion::V1).is_err()
+        );
+    }
 }
 
 /// Performs a Groth16 setup over the 2 provided Pairing-friendly curves for the Hash to Bits and Validator set update circuits
@@ -60,14 +142,17 @@ mod tests {
 /// parameters which were computed via an [MPC](https://eprint.iacr.org/2017/1050)
 ///
 /// If you do not know what this means, use the `trusted_setup` function
+#[allow(clippy::too_many_arguments)]
 fn setup<CP, BLS, F, G, R>(
     num_validators: usize,
     num_epochs: usize,
     maximum_non_signers: usize,
+    min_validators: u32,
     rng: &mut R,
     hash_to_bits_setup: F,
     validator_setup_fn: G,
     hashes_in_bls12_377: bool,
+    version: EncodingVersion,
 ) -> Result<Parameters<CP, BLS>>
 where
     CP: PairingEngine,
@@ -94,12 +179,19 @@ where
     };
 
     info!("BLS");
-    let empty_epochs =
-        ValidatorSetUpdate::empty(num_validators, num_epochs, maximum_non_signers, vk);
+    let empty_epochs = ValidatorSetUpdate::empty_with_version(
+        num_validators,
+        num_epochs,
+        maximum_non_signers,
+        vk,
+        version,
+    )
+    .with_min_validators(min_validators);
     let epochs = validator_setup_fn(empty_epochs, rng)?;
 
     Ok(Parameters {
         epochs,
         hash_to_bits,
+        min_validators,
     })
 }
@@ -0,0 +1,48 @@
+use super::{verifier::public_inputs, CPCurve, RangeProof, VerificationError};
+use crate::{
+    epoch_block::{EpochBlock, EpochTransition},
+    epoch_index::EpochIndex,
+};
+use algebra::{bw6_761, ProjectiveCurve, UniformRand};
+use groth16::Proof;
+use rand::Rng;
+use tracing::info;
+
+/// Produces a proof bundle for the transitions which has the structure of a real one, but
+/// takes no time to generate. The bundle's epochs are the same as in a real proof, so it
+/// has the same public inputs, and the proof's elements are random valid curve points, so it
+/// serializes to the same size. It fails verification, except with negligible probability.
+///
+/// This is meant for integration tests of the systems which distribute and consume proofs,
+/// which do not need to run the prover. The epochs are encoded to check that a real proof
+/// could be produced for them, and their indices are checked to be consecutive.
+pub fn simulate_proof<R: Rng>(
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    rng: &mut R,
+) -> Result<RangeProof, VerificationError> {
+    info!(
+        "Simulating proof for {} epochs (first epoch: {})",
+        transitions.len(),
+        initial_epoch.index
+    );
+
+    EpochIndex::check_transitions(initial_epoch, transitions, u16::MAX as usize)?;
+    let last_epoch = transitions
+        .last()
+        .map(|transition| transition.block.clone())
+        .unwrap_or_else(|| initial_epoch.clone());
+    public_inputs(initial_epoch, &last_epoch)?;
+
+    let proof = Proof::<CPCurve> {
+        a: bw6_761::G1Projective::rand(rng).into_affine(),
+        b: bw6_761::G2Projective::rand(rng).into_affine(),
+        c: bw6_761::G1Projective::rand(rng).into_affine(),
+    };
+
+    Ok(RangeProof {
+        first_epoch: initial_epoch.clone(),
+        last_epoch,
+        proof,
+    })
+}
@@ -1,8 +1,9 @@
-use super::{CPCurve, CPField, CPFrParams};
-use crate::encoding::EncodingError;
+use super::{CPCurve, CPField};
+use crate::encoding::{EncodingError, EncodingVersion};
 use crate::epoch_block::{hash_first_last_epoch_block, EpochBlock};
-use crate::gadgets::pack;
-use groth16::{prepare_verifying_key, verify_proof, Proof, VerifyingKey};
+use crate::epoch_index::EpochIndexError;
+use crate::gadgets::{encode_public_inputs, EDGE_HASH_BITS};
+use groth16::{prepare_verifying_key, verify_proof, PreparedVerifyingKey, Proof, VerifyingKey};
 use r1cs_core::SynthesisError;
 use thiserror::Error;
 use tracing::info;
@@ -16,10 +17,96 @@ pub enum VerificationError {
     ZexeSynthesisError(#[from] SynthesisError),
     #[error("Encoding Error: {0}")]
     EpochEncodingError(#[from] EncodingError),
+    #[error("No proofs were provided")]
+    EmptyProofChain,
+    #[error("The last epoch of range proof {0} does not match the first epoch of the next one")]
+    DisconnectedRanges(usize),
+    #[error("Invalid epoch range: {0}")]
+    InvalidEpochRange(#[from] EpochIndexError),
+    #[error("The verifying key expects {expected} public inputs, but the proof has {got}")]
+    PublicInputCountMismatch { expected: usize, got: usize },
+    #[error("Epoch {index} has {got} validators, but the circuit is for {expected}")]
+    ValidatorCountMismatch {
+        index: u16,
+        expected: usize,
+        got: usize,
+    },
+    #[error("Epoch {index} is encoded with {got:?}, but the circuit is for {expected:?}")]
+    EncodingVersionMismatch {
+        index: u16,
+        expected: EncodingVersion,
+        got: EncodingVersion,
+    },
+    #[error("The proof spans {got} epochs, but the circuit supports at most {max}")]
+    TooManyEpochs { max: usize, got: usize },
+    #[error("Epoch {index} has {got} active validators, but the circuit requires {min}")]
+    TooFewValidators { index: u16, min: usize, got: usize },
+}
+
+/// The shape of the circuit which a verifying key was generated for, i.e. the arguments
+/// which were passed to the trusted setup
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitShape {
+    /// The number of validators in each epoch
+    pub num_validators: usize,
+    /// The maximum number of epochs in a proof
+    pub max_epochs: usize,
+    /// The encoding of the epoch blocks
+    pub version: EncodingVersion,
+    /// The minimum number of validators in each epoch which are not padding slots
+    pub min_validators: usize,
+}
+
+impl CircuitShape {
+    /// Checks that a proof from the first to the last epoch could have been produced by a
+    /// circuit of this shape
+    pub fn check(
+        &self,
+        first_epoch: &EpochBlock,
+        last_epoch: &EpochBlock,
+    ) -> Result<(), VerificationError> {
+        for epoch in &[first_epoch, last_epoch] {
+            if epoch.new_public_keys.len() != self.num_validators {
+                return Err(VerificationError::ValidatorCountMismatch {
+                    index: epoch.index,
+                    expected: self.num_validators,
+                    got: epoch.new_public_keys.len(),
+                });
+            }
+            let active = epoch.num_active_validators();
+            if active < self.min_validators {
+                return Err(VerificationError::TooFewValidators {
+                    index: epoch.index,
+                    min: self.min_validators,
+                    got: active,
+                });
+            }
+            if epoch.version != self.version {
+                return Err(VerificationError::EncodingVersionMismatch {
+                    index: epoch.index,
+                    expected: self.version,
+                    got: epoch.version,
+                });
+            }
+        }
+        match first_epoch
+            .epoch_index()
+            .epochs_until(last_epoch.epoch_index(), self.max_epochs)
+        {
+            Err(EpochIndexError::RangeTooLong { max, got }) => {
+                Err(VerificationError::TooManyEpochs { max, got })
+            }
+            res => res.map(|_| ()).map_err(Into::into),
+        }
+    }
 }
 
 /// Given the Verifying Key for the circuit and the SNARK proof and _only the first and last epoch_,
 /// this function ensures that the state transition between epochs has been calculated correctly.
+///
+/// The same verifying key verifies proofs over any number of epochs up to the maximum the
+/// parameters were generated for. The number of epochs, which is a public input of the
+/// circuit, is derived from the indices of the first and last epoch.
 pub fn verify(
     vk: &VerifyingKey<CPCurve>,
     first_epoch: &EpochBlock,
@@ -27,14 +114,72 @@ pub fn verify(
     proof: &Proof<CPCurve>,
 ) -> Result<(), VerificationError> {
     info!("Verifying proof");
-    // Hash the first-last block together
-    let hash = hash_first_last_epoch_block(first_epoch, last_epoch)?;
-    // packs them
-    let public_inputs = pack::<CPField, CPFrParams>(&hash)?;
+    verify_prepared(&prepare_verifying_key(vk), first_epoch, last_epoch, proof)
+}
+
+/// Same as `verify`, but first checks that the epochs fit the shape of the circuit which the
+/// verifying key was generated for, so that a mismatched key is reported with a dedicated
+/// error instead of a failed verification
+pub fn verify_with_shape(
+    vk: &VerifyingKey<CPCurve>,
+    shape: &CircuitShape,
+    first_epoch: &EpochBlock,
+    last_epoch: &EpochBlock,
+    proof: &Proof<CPCurve>,
+) -> Result<(), VerificationError> {
+    shape.check(first_epoch, last_epoch)?;
+    verify(vk, first_epoch, last_epoch, proof)
+}
+
+/// Same as `verify`, but takes an already prepared verifying key
+pub(super) fn verify_prepared(
+    pvk: &PreparedVerifyingKey<CPCurve>,
+    first_epoch: &EpochBlock,
+    last_epoch: &EpochBlock,
+    proof: &Proof<CPCurve>,
+) -> Result<(), VerificationError> {
+    let public_inputs = public_inputs(first_epoch, last_epoch)?;
+    verify_with_inputs(pvk, &public_inputs, proof)
+}
+
+/// Same as `verify_prepared`, but takes the public inputs which were already computed from
+/// the first and last epoch
+pub(crate) fn verify_with_inputs(
+    pvk: &PreparedVerifyingKey<CPCurve>,
+    public_inputs: &[CPField],
+    proof: &Proof<CPCurve>,
+) -> Result<(), VerificationError> {
+    // a key for another circuit is reported before doing any pairings
+    let expected = pvk.vk.gamma_abc_g1.len().saturating_sub(1);
+    if public_inputs.len() != expected {
+        return Err(VerificationError::PublicInputCountMismatch {
+            expected,
+            got: public_inputs.len(),
+        });
+    }
     // verifies the BLS proof by using the First/Last epoch as public inputs over CP
-    if verify_proof(&prepare_verifying_key(vk), proof, &public_inputs)? {
+    if verify_proof(pvk, proof, public_inputs)? {
         Ok(())
     } else {
         Err(VerificationError::VerificationFailed)
     }
 }
+
+/// Returns the public inputs of the proof of the transition from the first to the last epoch
+pub fn public_inputs(
+    first_epoch: &EpochBlock,
+    last_epoch: &EpochBlock,
+) -> Result<Vec<CPField>, VerificationError> {
+    // Hash the first-last block together
+    let hash = hash_first_last_epoch_block(first_epoch, last_epoch)?;
+    // the epochs are consecutive, so their number follows from the indices
+    let num_epochs = first_epoch
+        .epoch_index()
+        .epochs_until(last_epoch.epoch_index(), u16::MAX as usize)?;
+    let (first_hash, last_hash) = hash.split_at(EDGE_HASH_BITS);
+    Ok(encode_public_inputs(
+        first_hash,
+        last_hash,
+        num_epochs as u64,
+    )?)
+}
@@ -0,0 +1,175 @@
+//! Versioned envelopes for the SNARK's proving and verifying keys.
+//!
+//! The keys are stored in the envelopes of `bls_crypto::envelope`, so that stored keys can be
+//! recognized and migrated when their encoding changes. With the `serde-envelope` feature,
+//! they are also serialized in their envelope with serde.
+use crate::api::{BLSCurve, CPCurve, Parameters};
+
+use algebra::serialize::{CanonicalDeserialize, CanonicalSerialize};
+use bls_crypto::{envelope::Versioned, BLSError, BlsResult};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use groth16::{Parameters as Groth16Parameters, VerifyingKey};
+
+/// The kind of the envelopes of the proving keys, i.e. the `Parameters` of the setup
+pub const PARAMETERS_KIND: u8 = 16;
+
+/// The kind of the envelopes of the epochs circuit's verifying key
+pub const VERIFYING_KEY_KIND: u8 = 17;
+
+/// The verifying key of the epochs circuit, which can be stored in an envelope
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpochsVerifyingKey(pub VerifyingKey<CPCurve>);
+
+impl From<VerifyingKey<CPCurve>> for EpochsVerifyingKey {
+    fn from(vk: VerifyingKey<CPCurve>) -> Self {
+        EpochsVerifyingKey(vk)
+    }
+}
+
+/// Version 1: the minimum number of validators as a `u32`, the epochs parameters, and a
+/// byte set to 1 if the hash to bits parameters follow, 0 otherwise
+impl Versioned for Parameters<CPCurve, BLSCurve> {
+    const KIND: u8 = PARAMETERS_KIND;
+    const VERSION: u16 = 1;
+
+    fn encode_payload(&self) -> BlsResult<Vec<u8>> {
+        let mut bytes = vec![];
+        bytes.write_u32::<LittleEndian>(self.min_validators)?;
+        self.epochs.serialize(&mut bytes)?;
+        match self.hash_to_bits {
+            Some(ref hash_to_bits) => {
+                bytes.write_u8(1)?;
+                hash_to_bits.serialize(&mut bytes)?;
+            }
+            None => bytes.write_u8(0)?,
+        }
+        Ok(bytes)
+    }
+
+    fn decode_payload(version: u16, payload: &[u8]) -> BlsResult<Self> {
+        check_version::<Self>(version)?;
+        let reader = &mut &payload[..];
+        let min_validators = reader.read_u32::<LittleEndian>()?;
+        let epochs = Groth16Parameters::deserialize(&mut *reader)?;
+        let hash_to_bits = match reader.read_u8()? {
+            0 => None,
+            1 => Some(Groth16Parameters::deserialize(&mut *reader)?),
+            _ => return Err(BLSError::InvalidEncoding("invalid hash to bits flag")),
+        };
+        check_consumed(reader)?;
+        Ok(Parameters {
+            epochs,
+            hash_to_bits,
+            min_validators,
+        })
+    }
+}
+
+/// Version 1: the compressed verifying key
+impl Versioned for EpochsVerifyingKey {
+    const KIND: u8 = VERIFYING_KEY_KIND;
+    const VERSION: u16 = 1;
+
+    fn encode_payload(&self) -> BlsResult<Vec<u8>> {
+        let mut bytes = vec![];
+        self.0.serialize(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn decode_payload(version: u16, payload: &[u8]) -> BlsResult<Self> {
+        check_version::<Self>(version)?;
+        let reader = &mut &payload[..];
+        let vk = VerifyingKey::deserialize(&mut *reader)?;
+        check_consumed(reader)?;
+        Ok(EpochsVerifyingKey(vk))
+    }
+}
+
+fn check_version<T: Versioned>(version: u16) -> BlsResult<()> {
+    if version != 1 {
+        return Err(BLSError::UnsupportedVersion {
+            kind: T::KIND,
+            version,
+        });
+    }
+    Ok(())
+}
+
+fn check_consumed(reader: &[u8]) -> BlsResult<()> {
+    if !reader.is_empty() {
+        return Err(BLSError::InvalidEncoding(
+            "trailing bytes after the payload",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "serde-envelope")]
+impl serde::Serialize for Parameters<CPCurve, BLSCurve> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        bls_crypto::envelope::serialize(self, serializer)
+    }
+}
+
+#[cfg(feature = "serde-envelope")]
+impl<'de> serde::Deserialize<'de> for Parameters<CPCurve, BLSCurve> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        bls_crypto::envelope::deserialize(deserializer)
+    }
+}
+
+#[cfg(feature = "serde-envelope")]
+impl serde::Serialize for EpochsVerifyingKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        bls_crypto::envelope::serialize(self, serializer)
+    }
+}
+
+#[cfg(feature = "serde-envelope")]
+impl<'de> serde::Deserialize<'de> for EpochsVerifyingKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        bls_crypto::envelope::deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::trusted_setup;
+    use bls_crypto::envelope::Envelope;
+
+    #[test]
+    fn roundtrips_keys_in_envelopes() {
+        let rng = &mut rand::thread_rng();
+        let params = trusted_setup(2, 1, 1, rng, false).unwrap();
+
+        let bytes = params.to_envelope().unwrap();
+        let decoded = Parameters::<CPCurve, BLSCurve>::from_envelope(&bytes).unwrap();
+        assert_eq!(decoded.epochs.vk, params.epochs.vk);
+        assert!(decoded.hash_to_bits.is_none());
+        assert_eq!(decoded.min_validators, params.min_validators);
+
+        let vk = EpochsVerifyingKey::from(params.epochs.vk.clone());
+        let bytes = vk.to_envelope().unwrap();
+        assert_eq!(EpochsVerifyingKey::from_envelope(&bytes).unwrap(), vk);
+
+        // the kinds are checked
+        match Parameters::<CPCurve, BLSCurve>::from_envelope(&bytes) {
+            Err(BLSError::UnexpectedKind {
+                expected: PARAMETERS_KIND,
+                got: VERIFYING_KEY_KIND,
+            }) => {}
+            _ => panic!("unexpected result"),
+        }
+        // and so are the versions
+        let mut envelope = Envelope::from_bytes(&bytes).unwrap();
+        envelope.version = 2;
+        match envelope.open::<EpochsVerifyingKey>() {
+            Err(BLSError::UnsupportedVersion {
+                kind: VERIFYING_KEY_KIND,
+                version: 2,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
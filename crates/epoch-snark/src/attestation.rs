@@ -0,0 +1,466 @@
+//! Attestations binding a proof to the inputs and parameters it was generated from.
+//!
+//! A prover signs an attestation with its operator's BLS key, listing the hash of the
+//! proof, the hashes of the epochs it was generated from and a fingerprint of the
+//! parameters. The distribution pipeline checks the attestation before publishing the
+//! proof, so that a bad proof can be traced back to its inputs and to the prover instance
+//! which produced it.
+use crate::{
+    api::{prove, BLSCurve, CPCurve, Parameters},
+    encoding::EncodingError,
+    epoch_block::{EpochBlock, EpochTransition},
+};
+use algebra::{
+    bls12_377::G1Projective,
+    serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError},
+};
+use blake2s_simd::Params;
+use bls_crypto::{
+    hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, BLSError, HashToCurve, PrivateKey,
+    PublicKey, Signature, ATTESTATION_DOMAIN,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use groth16::{Proof, VerifyingKey};
+use r1cs_core::SynthesisError;
+use std::io::{Read, Write};
+use thiserror::Error;
+
+/// Magic bytes at the start of every encoded attestation
+const MAGIC: &[u8; 8] = b"ULattest";
+
+/// The version of the attestation encoding
+const FORMAT_VERSION: u8 = 1;
+
+const PROOF_PERSONALIZATION: &[u8; 8] = b"ULproof_";
+const EPOCH_PERSONALIZATION: &[u8; 8] = b"ULepoch_";
+const PARAMETERS_PERSONALIZATION: &[u8; 8] = b"ULparams";
+
+/// Length of the hashes in an attestation
+pub const ATTESTATION_HASH_LENGTH: usize = 32;
+
+type Hash = [u8; ATTESTATION_HASH_LENGTH];
+
+#[derive(Debug, Error)]
+/// Error raised while producing or checking an attestation
+pub enum AttestationError {
+    #[error("I/O Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Zexe Error: {0}")]
+    ZexeSerialization(#[from] SerializationError),
+    #[error("Synthesis Error: {0}")]
+    ZexeSynthesisError(#[from] SynthesisError),
+    #[error("BLS Error: {0}")]
+    BLSError(#[from] BLSError),
+    #[error("Encoding Error: {0}")]
+    EpochEncodingError(#[from] EncodingError),
+    #[error("Malformed attestation: {0}")]
+    Malformed(&'static str),
+    #[error("The proof does not match the attested proof hash")]
+    ProofMismatch,
+    #[error("The parameters do not match the attested fingerprint")]
+    ParametersMismatch,
+    #[error("Epoch {0} of the inputs does not match the attested epochs")]
+    InputMismatch(usize),
+}
+
+/// The statement signed by a prover's operator about a proof
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attestation {
+    /// The operator key of the prover instance
+    pub operator: PublicKey,
+    /// The hash of the serialized proof
+    pub proof_hash: Hash,
+    /// The fingerprint of the parameters, as returned by `parameters_fingerprint`
+    pub parameters_fingerprint: Hash,
+    /// The hashes of the encoded epochs, the initial epoch first and then the block of each
+    /// transition
+    pub epoch_hashes: Vec<Hash>,
+}
+
+/// Generates a proof like `prove`, along with an attestation signed by the operator key
+pub fn prove_attested(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    max_transitions: usize,
+    operator_key: &PrivateKey,
+) -> Result<(Proof<CPCurve>, SignedAttestation), AttestationError> {
+    let proof = prove(
+        parameters,
+        num_validators,
+        initial_epoch,
+        transitions,
+        max_transitions,
+    )?;
+    let attestation = Attestation::new(
+        operator_key.to_public(),
+        &proof,
+        parameters_fingerprint(parameters)?,
+        initial_epoch,
+        transitions,
+    )?;
+    let signed = attestation.sign(operator_key)?;
+    Ok((proof, signed))
+}
+
+/// Returns the fingerprint of the parameters, the hash of their verifying keys. The proving
+/// keys are not hashed, as they are large and determined by the verifying keys for a
+/// correct setup.
+pub fn parameters_fingerprint(
+    parameters: &Parameters<CPCurve, BLSCurve>,
+) -> Result<Hash, AttestationError> {
+    let mut bytes = vec![];
+    parameters.epochs.vk.serialize(&mut bytes)?;
+    if let Some(ref hash_to_bits) = parameters.hash_to_bits {
+        hash_to_bits.vk.serialize(&mut bytes)?;
+    }
+    Ok(hash(PARAMETERS_PERSONALIZATION, &bytes))
+}
+
+/// Returns the fingerprint of a verifying key, which is the `parameters_fingerprint` of
+/// parameters without the hash to bits proof
+pub(crate) fn verifying_key_fingerprint(
+    vk: &VerifyingKey<CPCurve>,
+) -> Result<Hash, SerializationError> {
+    let mut bytes = vec![];
+    vk.serialize(&mut bytes)?;
+    Ok(hash(PARAMETERS_PERSONALIZATION, &bytes))
+}
+
+impl Attestation {
+    /// Describes the proof generated by the operator from the parameters and epochs
+    pub fn new(
+        operator: PublicKey,
+        proof: &Proof<CPCurve>,
+        parameters_fingerprint: Hash,
+        initial_epoch: &EpochBlock,
+        transitions: &[EpochTransition],
+    ) -> Result<Self, AttestationError> {
+        Ok(Self {
+            operator,
+            proof_hash: hash_proof(proof)?,
+            parameters_fingerprint,
+            epoch_hashes: hash_epochs(initial_epoch, transitions)?,
+        })
+    }
+
+    /// Signs the attestation with the operator key
+    pub fn sign(&self, operator_key: &PrivateKey) -> Result<SignedAttestation, AttestationError> {
+        if operator_key.to_public() != self.operator {
+            return Err(AttestationError::Malformed(
+                "the key does not match the operator",
+            ));
+        }
+        let signature = operator_key.sign_hashed(&self.hash_to_g1()?);
+        Ok(SignedAttestation {
+            attestation: self.clone(),
+            signature,
+        })
+    }
+
+    /// Checks that the proof is the attested one
+    pub fn check_proof(&self, proof: &Proof<CPCurve>) -> Result<(), AttestationError> {
+        if hash_proof(proof)? != self.proof_hash {
+            return Err(AttestationError::ProofMismatch);
+        }
+        Ok(())
+    }
+
+    /// Checks that the parameters are the attested ones
+    pub fn check_parameters(
+        &self,
+        parameters: &Parameters<CPCurve, BLSCurve>,
+    ) -> Result<(), AttestationError> {
+        if parameters_fingerprint(parameters)? != self.parameters_fingerprint {
+            return Err(AttestationError::ParametersMismatch);
+        }
+        Ok(())
+    }
+
+    /// Checks that the epochs are the attested inputs. Reports the position of the first
+    /// differing epoch, where 0 is the initial epoch.
+    pub fn check_inputs(
+        &self,
+        initial_epoch: &EpochBlock,
+        transitions: &[EpochTransition],
+    ) -> Result<(), AttestationError> {
+        let epoch_hashes = hash_epochs(initial_epoch, transitions)?;
+        if let Some(position) = epoch_hashes
+            .iter()
+            .zip(&self.epoch_hashes)
+            .position(|(a, b)| a != b)
+        {
+            return Err(AttestationError::InputMismatch(position));
+        }
+        if epoch_hashes.len() != self.epoch_hashes.len() {
+            return Err(AttestationError::InputMismatch(
+                epoch_hashes.len().min(self.epoch_hashes.len()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Encodes the attestation to bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, AttestationError> {
+        let mut bytes = vec![];
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decodes an attestation from bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AttestationError> {
+        let reader = &mut &bytes[..];
+        let attestation = Self::read(reader)?;
+        if !reader.is_empty() {
+            return Err(AttestationError::Malformed("trailing bytes"));
+        }
+        Ok(attestation)
+    }
+
+    /// Hashes the encoded attestation to G1 under the attestation domain
+    fn hash_to_g1(&self) -> Result<G1Projective, AttestationError> {
+        Ok(DIRECT_HASH_TO_G1.hash(ATTESTATION_DOMAIN, &self.to_bytes()?, &[])?)
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), AttestationError> {
+        writer.write_all(MAGIC)?;
+        writer.write_u8(FORMAT_VERSION)?;
+        self.operator.serialize(writer)?;
+        writer.write_all(&self.proof_hash)?;
+        writer.write_all(&self.parameters_fingerprint)?;
+        writer.write_u32::<LittleEndian>(self.epoch_hashes.len() as u32)?;
+        for epoch_hash in &self.epoch_hashes {
+            writer.write_all(epoch_hash)?;
+        }
+        Ok(())
+    }
+
+    fn read<R: Read>(reader: &mut R) -> Result<Self, AttestationError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(AttestationError::Malformed("invalid magic bytes"));
+        }
+        if reader.read_u8()? != FORMAT_VERSION {
+            return Err(AttestationError::Malformed("unsupported format version"));
+        }
+        let operator = PublicKey::deserialize(reader)?;
+        let mut proof_hash = [0u8; ATTESTATION_HASH_LENGTH];
+        reader.read_exact(&mut proof_hash)?;
+        let mut parameters_fingerprint = [0u8; ATTESTATION_HASH_LENGTH];
+        reader.read_exact(&mut parameters_fingerprint)?;
+        let num_epochs = reader.read_u32::<LittleEndian>()?;
+        let epoch_hashes = (0..num_epochs)
+            .map(|_| {
+                let mut epoch_hash = [0u8; ATTESTATION_HASH_LENGTH];
+                reader.read_exact(&mut epoch_hash)?;
+                Ok(epoch_hash)
+            })
+            .collect::<Result<Vec<_>, AttestationError>>()?;
+
+        Ok(Self {
+            operator,
+            proof_hash,
+            parameters_fingerprint,
+            epoch_hashes,
+        })
+    }
+}
+
+/// An attestation along with the operator key's signature over it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedAttestation {
+    attestation: Attestation,
+    signature: Signature,
+}
+
+impl SignedAttestation {
+    /// Verifies the signature against the attestation's operator key and returns the
+    /// attestation. Callers must check separately that the operator is a trusted prover.
+    pub fn verify(&self) -> Result<&Attestation, AttestationError> {
+        self.signature.batch_verify_hashes(
+            &[&self.attestation.operator],
+            &[self.attestation.hash_to_g1()?],
+        )?;
+        Ok(&self.attestation)
+    }
+
+    /// Encodes the attestation followed by its signature
+    pub fn to_bytes(&self) -> Result<Vec<u8>, AttestationError> {
+        let mut bytes = self.attestation.to_bytes()?;
+        self.signature.serialize(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a signed attestation. The signature is not verified, call `verify` for that.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AttestationError> {
+        let reader = &mut &bytes[..];
+        let attestation = Attestation::read(reader)?;
+        let signature = Signature::deserialize(reader)?;
+        if !reader.is_empty() {
+            return Err(AttestationError::Malformed("trailing bytes"));
+        }
+        Ok(Self {
+            attestation,
+            signature,
+        })
+    }
+}
+
+fn hash_proof(proof: &Proof<CPCurve>) -> Result<Hash, AttestationError> {
+    let mut bytes = vec![];
+    proof.serialize(&mut bytes)?;
+    Ok(hash(PROOF_PERSONALIZATION, &bytes))
+}
+
+fn hash_epochs(
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+) -> Result<Vec<Hash>, AttestationError> {
+    std::iter::once(initial_epoch)
+        .chain(transitions.iter().map(|transition| &transition.block))
+        .map(|epoch| Ok(hash(EPOCH_PERSONALIZATION, &epoch.encode_to_bytes()?)))
+        .collect()
+}
+
+fn hash(personalization: &[u8], bytes: &[u8]) -> Hash {
+    let hash = Params::new()
+        .hash_length(ATTESTATION_HASH_LENGTH)
+        .personal(personalization)
+        .to_state()
+        .update(bytes)
+        .finalize();
+    let mut out = [0u8; ATTESTATION_HASH_LENGTH];
+    out.copy_from_slice(hash.as_ref());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bw6_761, ProjectiveCurve, UniformRand};
+
+    fn epoch(index: u16) -> EpochBlock {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..3)
+            .map(|_| PrivateKey::generate(rng).to_public())
+            .collect();
+        EpochBlock::new(index, 1, keys)
+    }
+
+    fn transition(index: u16) -> EpochTransition {
+        EpochTransition {
+            block: epoch(index),
+            aggregate_signature: Signature::aggregate(&[] as &[Signature]),
+            bitmap: vec![true; 3],
+        }
+    }
+
+    fn random_proof() -> Proof<CPCurve> {
+        let rng = &mut rand::thread_rng();
+        Proof {
+            a: bw6_761::G1Projective::rand(rng).into_affine(),
+            b: bw6_761::G2Projective::rand(rng).into_affine(),
+            c: bw6_761::G1Projective::rand(rng).into_affine(),
+        }
+    }
+
+    #[test]
+    fn sign_verify_and_trace_inputs() {
+        let rng = &mut rand::thread_rng();
+        let operator_key = PrivateKey::generate(rng);
+        let initial = epoch(1);
+        let transitions = vec![transition(2), transition(3)];
+        let proof = random_proof();
+
+        let attestation = Attestation::new(
+            operator_key.to_public(),
+            &proof,
+            [7; ATTESTATION_HASH_LENGTH],
+            &initial,
+            &transitions,
+        )
+        .unwrap();
+        let signed = attestation.sign(&operator_key).unwrap();
+        let decoded = SignedAttestation::from_bytes(&signed.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, signed);
+
+        let attestation = decoded.verify().unwrap();
+        attestation.check_proof(&proof).unwrap();
+        attestation.check_inputs(&initial, &transitions).unwrap();
+
+        match attestation.check_proof(&random_proof()) {
+            Err(AttestationError::ProofMismatch) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match attestation.check_inputs(&initial, &[transitions[0].clone(), transition(3)]) {
+            Err(AttestationError::InputMismatch(2)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match attestation.check_inputs(&initial, &transitions[..1]) {
+            Err(AttestationError::InputMismatch(2)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn rejects_forged_attestations() {
+        let rng = &mut rand::thread_rng();
+        let operator_key = PrivateKey::generate(rng);
+        let attestation = Attestation::new(
+            operator_key.to_public(),
+            &random_proof(),
+            [0; ATTESTATION_HASH_LENGTH],
+            &epoch(1),
+            &[transition(2)],
+        )
+        .unwrap();
+        attestation.sign(&PrivateKey::generate(rng)).unwrap_err();
+
+        // claiming another operator invalidates the signature
+        let mut bytes = attestation.sign(&operator_key).unwrap().to_bytes().unwrap();
+        let mut other = vec![];
+        PrivateKey::generate(rng)
+            .to_public()
+            .serialize(&mut other)
+            .unwrap();
+        bytes[MAGIC.len() + 1..MAGIC.len() + 1 + other.len()].copy_from_slice(&other);
+        SignedAttestation::from_bytes(&bytes)
+            .unwrap()
+            .verify()
+            .unwrap_err();
+    }
+
+    #[test]
+    fn rejects_trailing_bytes_and_consensus_signatures() {
+        let rng = &mut rand::thread_rng();
+        let operator_key = PrivateKey::generate(rng);
+        let attestation = Attestation::new(
+            operator_key.to_public(),
+            &random_proof(),
+            [0; ATTESTATION_HASH_LENGTH],
+            &epoch(1),
+            &[transition(2)],
+        )
+        .unwrap();
+
+        let mut padded = attestation.to_bytes().unwrap();
+        padded.push(0);
+        match Attestation::from_bytes(&padded) {
+            Err(AttestationError::Malformed(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // a signature over the same bytes under the consensus domain does not verify
+        let signature = operator_key
+            .sign(&attestation.to_bytes().unwrap(), &[], &*DIRECT_HASH_TO_G1)
+            .unwrap();
+        SignedAttestation {
+            attestation,
+            signature,
+        }
+        .verify()
+        .unwrap_err();
+    }
+}
@@ -0,0 +1,238 @@
+//! Human-readable reports on proof bundles, for triaging proofs which fail verification.
+use crate::{
+    api::{CPCurve, RangeProof},
+    attestation::verifying_key_fingerprint,
+    encoding::EncodingVersion,
+    epoch_block::EpochBlock,
+    ATTESTATION_HASH_LENGTH,
+};
+use algebra::serialize::{CanonicalSerialize, SerializationError};
+use groth16::VerifyingKey;
+use std::fmt;
+
+/// The parameters of one of the epochs at the ends of a bundle
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpochSummary {
+    /// The epoch's index
+    pub index: u16,
+    /// The number of validators of the epoch
+    pub num_validators: usize,
+    /// The maximum number of validators which may not sign the next epoch
+    pub maximum_non_signers: u32,
+    /// The minimum number of signers of the next epoch, i.e. the number of validators minus
+    /// the maximum number of non-signers
+    pub signing_threshold: usize,
+    /// The encoding of the epoch
+    pub version: EncodingVersion,
+}
+
+impl From<&EpochBlock> for EpochSummary {
+    fn from(epoch: &EpochBlock) -> Self {
+        let num_validators = epoch.new_public_keys.len();
+        Self {
+            index: epoch.index,
+            num_validators,
+            maximum_non_signers: epoch.maximum_non_signers,
+            signing_threshold: num_validators.saturating_sub(epoch.maximum_non_signers as usize),
+            version: epoch.version,
+        }
+    }
+}
+
+/// A report on the contents of a proof bundle, see `RangeProof::describe`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BundleReport {
+    /// The epoch preceding the first proven transition
+    pub first_epoch: EpochSummary,
+    /// The epoch produced by the last proven transition
+    pub last_epoch: EpochSummary,
+    /// The fingerprint of the verifying key the bundle is checked against, if one was given
+    pub vk_fingerprint: Option<[u8; ATTESTATION_HASH_LENGTH]>,
+    /// The compressed points of the proof, hex encoded
+    pub proof_a: String,
+    /// See `proof_a`
+    pub proof_b: String,
+    /// See `proof_a`
+    pub proof_c: String,
+    /// Inconsistencies of the bundle which make its verification fail regardless of the proof
+    pub issues: Vec<&'static str>,
+}
+
+impl BundleReport {
+    /// The number of transitions proven by the bundle, or `None` if the last epoch does not
+    /// come after the first one
+    pub fn num_transitions(&self) -> Option<u16> {
+        self.last_epoch
+            .index
+            .checked_sub(self.first_epoch.index)
+            .filter(|n| *n > 0)
+    }
+}
+
+impl RangeProof {
+    /// Describes the epochs and proof of the bundle. If `vk` is provided, its fingerprint is
+    /// included, so that it can be compared with the parameters the prover used.
+    pub fn describe(
+        &self,
+        vk: Option<&VerifyingKey<CPCurve>>,
+    ) -> Result<BundleReport, SerializationError> {
+        let vk_fingerprint = vk.map(verifying_key_fingerprint).transpose()?;
+
+        let mut issues = vec![];
+        if self.last_epoch.index <= self.first_epoch.index {
+            issues.push("the last epoch does not come after the first epoch");
+        }
+        if self.first_epoch.new_public_keys.is_empty() {
+            issues.push("the first epoch has no validators");
+        }
+        if self.first_epoch.maximum_non_signers as usize >= self.first_epoch.new_public_keys.len() {
+            issues.push("the first epoch does not require any signer");
+        }
+        if self.first_epoch.new_public_keys.len() != self.last_epoch.new_public_keys.len() {
+            issues.push("the epochs have different numbers of validators");
+        }
+        if self.first_epoch.version != self.last_epoch.version {
+            issues.push("the epochs have different encodings");
+        }
+
+        Ok(BundleReport {
+            first_epoch: EpochSummary::from(&self.first_epoch),
+            last_epoch: EpochSummary::from(&self.last_epoch),
+            vk_fingerprint,
+            proof_a: to_hex(&self.proof.a)?,
+            proof_b: to_hex(&self.proof.b)?,
+            proof_c: to_hex(&self.proof.c)?,
+            issues,
+        })
+    }
+}
+
+fn to_hex<T: CanonicalSerialize>(point: &T) -> Result<String, SerializationError> {
+    let mut bytes = vec![];
+    point.serialize(&mut bytes)?;
+    Ok(hex::encode(bytes))
+}
+
+impl fmt::Display for EpochSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "index {}, {} validators, at most {} non-signers (threshold {}), encoding {:?}",
+            self.index,
+            self.num_validators,
+            self.maximum_non_signers,
+            self.signing_threshold,
+            self.version
+        )
+    }
+}
+
+impl fmt::Display for BundleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.num_transitions() {
+            Some(n) => writeln!(
+                f,
+                "epochs:         {} to {} ({} transitions)",
+                self.first_epoch.index, self.last_epoch.index, n
+            )?,
+            None => writeln!(
+                f,
+                "epochs:         {} to {} (invalid range)",
+                self.first_epoch.index, self.last_epoch.index
+            )?,
+        }
+        writeln!(f, "first epoch:    {}", self.first_epoch)?;
+        writeln!(f, "last epoch:     {}", self.last_epoch)?;
+        match self.vk_fingerprint {
+            Some(fingerprint) => writeln!(f, "vk fingerprint: {}", hex::encode(fingerprint))?,
+            None => writeln!(f, "vk fingerprint: unknown")?,
+        }
+        writeln!(f, "proof a:        {}", self.proof_a)?;
+        writeln!(f, "proof b:        {}", self.proof_b)?;
+        writeln!(f, "proof c:        {}", self.proof_c)?;
+        if self.issues.is_empty() {
+            write!(f, "issues:         none")
+        } else {
+            write!(f, "issues:")?;
+            for issue in &self.issues {
+                write!(f, "\n  - {}", issue)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G2Projective, bw6_761, UniformRand};
+    use bls_crypto::PublicKey;
+    use groth16::Proof;
+
+    fn block(index: u16, num_keys: usize) -> EpochBlock {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..num_keys)
+            .map(|_| PublicKey::from(G2Projective::rand(rng)))
+            .collect();
+        EpochBlock::new(index, 1, keys)
+    }
+
+    fn bundle(first: EpochBlock, last: EpochBlock) -> RangeProof {
+        let rng = &mut rand::thread_rng();
+        RangeProof {
+            first_epoch: first,
+            last_epoch: last,
+            proof: Proof {
+                a: bw6_761::G1Projective::rand(rng).into(),
+                b: bw6_761::G2Projective::rand(rng).into(),
+                c: bw6_761::G1Projective::rand(rng).into(),
+            },
+        }
+    }
+
+    #[test]
+    fn describes_bundle() {
+        let report = bundle(block(3, 4), block(5, 4)).describe(None).unwrap();
+        assert_eq!(report.num_transitions(), Some(2));
+        assert_eq!(report.first_epoch.signing_threshold, 3);
+        assert!(report.issues.is_empty());
+        assert_eq!(report.vk_fingerprint, None);
+        // compressed BW6-761 points
+        assert_eq!(report.proof_a.len(), 2 * 96);
+        assert_eq!(report.proof_b.len(), 2 * 96);
+
+        let printed = report.to_string();
+        assert!(printed.contains("3 to 5 (2 transitions)"));
+        assert!(printed.contains(&report.proof_c));
+    }
+
+    #[test]
+    fn reports_issues() {
+        let report = bundle(block(5, 1), block(5, 3)).describe(None).unwrap();
+        assert_eq!(report.num_transitions(), None);
+        assert_eq!(report.issues.len(), 3);
+        assert!(report.to_string().contains("invalid range"));
+    }
+
+    #[test]
+    fn fingerprints_the_verifying_key() {
+        let rng = &mut rand::thread_rng();
+        let mut vk = VerifyingKey::<CPCurve> {
+            alpha_g1: bw6_761::G1Projective::rand(rng).into(),
+            beta_g2: bw6_761::G2Projective::rand(rng).into(),
+            gamma_g2: bw6_761::G2Projective::rand(rng).into(),
+            delta_g2: bw6_761::G2Projective::rand(rng).into(),
+            gamma_abc_g1: vec![bw6_761::G1Projective::rand(rng).into(); 2],
+        };
+        let bundle = bundle(block(0, 2), block(1, 2));
+        let report = bundle.describe(Some(&vk)).unwrap();
+        assert_eq!(
+            report.vk_fingerprint,
+            Some(verifying_key_fingerprint(&vk).unwrap())
+        );
+
+        vk.gamma_abc_g1.pop();
+        let other = bundle.describe(Some(&vk)).unwrap();
+        assert_ne!(other.vk_fingerprint, report.vk_fingerprint);
+    }
+}
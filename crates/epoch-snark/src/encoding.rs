@@ -1,12 +1,8 @@
-use algebra::{
-    bls12_377::{Fq, FqParameters},
-    FpParameters, PrimeField, ProjectiveCurve, ToBytes,
-};
-use bls_crypto::PublicKey;
-use bls_gadgets::utils::bytes_to_bits;
-use byteorder::{LittleEndian, WriteBytesExt};
+use bls_crypto::{epoch, BLSError, PublicKey};
 use thiserror::Error;
 
+pub use bls_crypto::epoch::{EncodingVersion, ENTROPY_BYTES};
+
 use algebra::serialize::SerializationError;
 #[derive(Debug, Error)]
 /// Union type for data serialization errors
@@ -15,65 +11,57 @@ pub enum EncodingError {
     ZexeSerialization(#[from] SerializationError),
     #[error("I/O Error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("entropy has length {0}, expected {1}")]
+    InvalidEntropyLength(usize, usize),
+    #[error("the epoch entropy and parent entropy are required by encoding version {0:?}")]
+    MissingEntropy(EncodingVersion),
+    #[error("malformed epoch: {0}")]
+    Malformed(&'static str),
+    #[error(transparent)]
+    Bls(BLSError),
+}
+
+impl From<BLSError> for EncodingError {
+    fn from(err: BLSError) -> Self {
+        match err {
+            BLSError::InvalidEntropyLength(len, expected) => {
+                EncodingError::InvalidEntropyLength(len, expected)
+            }
+            err => EncodingError::Bls(err),
+        }
+    }
 }
 
 /// The function assumes that the public key is not the point in infinity, which is true for
 /// BLS public keys
 pub fn encode_public_key(public_key: &PublicKey) -> Result<Vec<bool>, EncodingError> {
-    let pk_affine = public_key.as_ref().into_affine();
-    let x = pk_affine.x;
-    let y = pk_affine.y;
-
-    let y_c0_big = y.c0.into_repr();
-    let y_c1_big = y.c1.into_repr();
-
-    let half = Fq::modulus_minus_one_div_two();
-    let is_over_half = y_c1_big > half || (y_c1_big == half && y_c0_big > half);
-
-    let mut bits = vec![];
-    let mut x_bytes_c0 = vec![];
-    x.c0.write(&mut x_bytes_c0)?;
-    let bits_c0 = bytes_to_bits(&x_bytes_c0, FqParameters::MODULUS_BITS as usize);
-    bits.extend_from_slice(&bits_c0);
-    let mut x_bytes_c1 = vec![];
-    x.c1.write(&mut x_bytes_c1)?;
-    let bits_c1 = bytes_to_bits(&x_bytes_c1, FqParameters::MODULUS_BITS as usize);
-    bits.extend_from_slice(&bits_c1);
-    bits.push(is_over_half);
-
-    Ok(bits)
+    Ok(epoch::encode_public_key(public_key))
 }
 
-/// LE Encodes a U16 to **bits**
-pub(crate) fn encode_u16(num: u16) -> Result<Vec<bool>, EncodingError> {
-    let mut bytes = vec![];
-    bytes.write_u16::<LittleEndian>(num)?;
-    let bits = bytes
-        .into_iter()
-        .map(|x| (0..8).map(move |i| (((x as u16) & u16::pow(2, i)) >> i) == 1))
-        .flatten()
-        .collect::<Vec<_>>();
-    Ok(bits)
-}
-
-/// LE Encodes a U32 to **bits**
-pub(crate) fn encode_u32(num: u32) -> Result<Vec<bool>, EncodingError> {
-    let mut bytes = vec![];
-    bytes.write_u32::<LittleEndian>(num)?;
-    let bits = bytes
-        .into_iter()
-        .map(|x| (0..8).map(move |i| (((x as u32) & u32::pow(2, i)) >> i) == 1))
-        .flatten()
-        .collect::<Vec<_>>();
-    Ok(bits)
+/// Encodes the entropy to **bits**, each byte in LE
+pub(crate) fn encode_entropy(entropy: &[u8]) -> Result<Vec<bool>, EncodingError> {
+    Ok(epoch::encode_entropy(entropy)?)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use algebra::{bls12_377::FqParameters, FpParameters};
-    use bls_gadgets::utils::bits_to_bytes;
+    use algebra::{
+        bls12_377::{Fq, Fq2, FqParameters, G2Projective, Parameters},
+        CanonicalDeserializeWithFlags, CanonicalSerialize, FpParameters, ProjectiveCurve, SWFlags,
+        ToBytes, UniformRand,
+    };
+    use bls_crypto::PointCompression;
+    use bls_gadgets::{
+        utils::{bits_to_bytes, bytes_to_bits},
+        YToBitGadget,
+    };
     use byteorder::{LittleEndian, WriteBytesExt};
+    use r1cs_core::ConstraintSystem;
+    use r1cs_std::{
+        alloc::AllocGadget, groups::curves::short_weierstrass::bls12::G2Gadget,
+        test_constraint_system::TestConstraintSystem,
+    };
     use rand::{Rng, SeedableRng};
     use rand_xorshift::XorShiftRng;
 
@@ -120,4 +108,64 @@ mod test {
             assert_eq!(bytes, result_bytes);
         }
     }
+
+    #[test]
+    fn y_bit_conventions_agree() {
+        let rng = &mut rand::thread_rng();
+        for _ in 0..20 {
+            let point = G2Projective::rand(rng);
+            let public_key = PublicKey::from(point);
+            let bit = PointCompression::point_bit(&point.into_affine());
+
+            // the serialization
+            let mut bytes = vec![];
+            public_key.serialize(&mut bytes).unwrap();
+            let (_, flags): (Fq2, SWFlags) =
+                CanonicalDeserializeWithFlags::deserialize_with_flags(&mut &bytes[..]).unwrap();
+            assert_eq!(flags.is_positive(), Some(bit));
+
+            // the epoch encoding
+            assert_eq!(encode_public_key(&public_key).unwrap().last(), Some(&bit));
+
+            // the gadget
+            let mut cs = TestConstraintSystem::<Fq>::new();
+            let allocated =
+                G2Gadget::<Parameters>::alloc(&mut cs.ns(|| "alloc"), || Ok(point)).unwrap();
+            let y_bit =
+                YToBitGadget::<Parameters>::y_to_bit_g2(cs.ns(|| "y to bit"), &allocated).unwrap();
+            assert_eq!(y_bit.get_value(), Some(bit));
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn encodes_public_keys_from_their_coordinates() {
+        let rng = &mut rand::thread_rng();
+        for _ in 0..20 {
+            let public_key = PublicKey::from(G2Projective::rand(rng));
+            let affine = public_key.as_ref().into_affine();
+
+            // the bytes of each coordinate of x, in descending order
+            let mut expected = vec![];
+            for coordinate in &[affine.x.c0, affine.x.c1] {
+                let mut bytes = vec![];
+                coordinate.write(&mut bytes).unwrap();
+                expected.extend(bytes_to_bits(&bytes, FqParameters::MODULUS_BITS as usize));
+            }
+            expected.push(PointCompression::fp2_y_bit(&affine.y));
+            assert_eq!(encode_public_key(&public_key).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_encode_entropy() {
+        let mut entropy = [0u8; ENTROPY_BYTES];
+        entropy[0] = 0b101;
+        let bits = encode_entropy(&entropy).unwrap();
+        assert_eq!(bits.len(), ENTROPY_BYTES * 8);
+        assert_eq!(&bits[..4], &[true, false, true, false]);
+        assert!(bits[4..].iter().all(|b| !b));
+
+        encode_entropy(&entropy[1..]).unwrap_err();
+    }
 }
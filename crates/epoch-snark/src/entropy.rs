@@ -0,0 +1,171 @@
+//! Checks of the epoch entropy committed by proof bundles, for consumers of the randomness.
+//!
+//! With `EncodingVersion::V2`, the encoding of each epoch includes its entropy and the entropy
+//! of its parent, and the public inputs of a proof are hashes of the encodings of its first
+//! and last epoch. A verified bundle therefore commits to the entropy of four epochs at most:
+//! its first and last epoch and their parents.
+use crate::{
+    api::{verify, CPCurve, RangeProof, VerificationError},
+    encoding::{EncodingVersion, ENTROPY_BYTES},
+};
+use groth16::VerifyingKey;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// Errors of the checks of the entropy committed by a proof bundle
+pub enum EntropyError {
+    /// The epochs of the bundle are encoded without entropy
+    #[error("the epochs are encoded with {0:?}, which does not commit to entropy")]
+    NoEntropy(EncodingVersion),
+    /// The bundle does not commit to the entropy of the epoch
+    #[error("the bundle does not commit to the entropy of epoch {0}")]
+    NotCommitted(u16),
+    /// The claimed entropy does not have the length of the epoch entropy
+    #[error("entropy has length {0}, expected {1}")]
+    InvalidLength(usize, usize),
+    /// The claimed entropy differs from the one committed for the epoch
+    #[error("the entropy of epoch {0} differs from the committed one")]
+    Mismatch(u16),
+    /// The bundle's proof is invalid
+    #[error("Verification Error: {0}")]
+    VerificationError(#[from] VerificationError),
+}
+
+impl RangeProof {
+    /// Returns the entropy which the bundle commits to for the epoch at `index`. The bundle
+    /// must have been verified, otherwise the entropy is whatever its epochs hold.
+    pub fn committed_entropy(&self, index: u16) -> Result<&[u8], EntropyError> {
+        self.entropy_commitments(index)?
+            .next()
+            .ok_or(EntropyError::NotCommitted(index))
+    }
+
+    /// Checks that `entropy` is the entropy which the bundle commits to for the epoch at
+    /// `index`. The bundle must have been verified, e.g. with `verify` or `verify_stitched`;
+    /// use `verify_with_entropy` otherwise.
+    pub fn verify_entropy(&self, index: u16, entropy: &[u8]) -> Result<(), EntropyError> {
+        if entropy.len() != ENTROPY_BYTES {
+            return Err(EntropyError::InvalidLength(entropy.len(), ENTROPY_BYTES));
+        }
+        let mut committed = false;
+        // the epoch's own entropy and the parent entropy of its child must agree
+        for commitment in self.entropy_commitments(index)? {
+            if commitment != entropy {
+                return Err(EntropyError::Mismatch(index));
+            }
+            committed = true;
+        }
+        if !committed {
+            return Err(EntropyError::NotCommitted(index));
+        }
+        Ok(())
+    }
+
+    /// Verifies the bundle's proof and then the entropy of the epoch at `index`, see
+    /// `verify_entropy`
+    pub fn verify_with_entropy(
+        &self,
+        vk: &VerifyingKey<CPCurve>,
+        index: u16,
+        entropy: &[u8],
+    ) -> Result<(), EntropyError> {
+        verify(vk, &self.first_epoch, &self.last_epoch, &self.proof)?;
+        self.verify_entropy(index, entropy)
+    }
+
+    // The entropy of the epoch held by the bundle's first and last epoch
+    fn entropy_commitments(&self, index: u16) -> Result<impl Iterator<Item = &[u8]>, EntropyError> {
+        let version = self.last_epoch.version;
+        if !version.has_entropy() || !self.first_epoch.version.has_entropy() {
+            return Err(EntropyError::NoEntropy(version));
+        }
+        let (first, last) = (&self.first_epoch, &self.last_epoch);
+        let commitments = vec![
+            (Some(first.index), &first.epoch_entropy),
+            (first.index.checked_sub(1), &first.parent_entropy),
+            (Some(last.index), &last.epoch_entropy),
+            (last.index.checked_sub(1), &last.parent_entropy),
+        ];
+        Ok(commitments
+            .into_iter()
+            .filter(move |(epoch_index, _)| *epoch_index == Some(index))
+            .filter_map(|(_, entropy)| entropy.as_ref().map(|entropy| &entropy[..])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epoch_block::EpochBlock;
+    use algebra::{bls12_377::G2Projective, bw6_761, UniformRand};
+    use bls_crypto::PublicKey;
+    use groth16::Proof;
+
+    fn block(index: u16) -> EpochBlock {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..2)
+            .map(|_| PublicKey::from(G2Projective::rand(rng)))
+            .collect();
+        EpochBlock::new(index, 1, keys)
+    }
+
+    fn bundle(first: EpochBlock, last: EpochBlock) -> RangeProof {
+        let rng = &mut rand::thread_rng();
+        RangeProof {
+            first_epoch: first,
+            last_epoch: last,
+            proof: Proof {
+                a: bw6_761::G1Projective::rand(rng).into(),
+                b: bw6_761::G2Projective::rand(rng).into(),
+                c: bw6_761::G1Projective::rand(rng).into(),
+            },
+        }
+    }
+
+    #[test]
+    fn checks_committed_entropy() {
+        let entropy = |index| [index as u8; ENTROPY_BYTES];
+        let consistent = bundle(
+            block(3).with_entropy(&entropy(3), &entropy(2)),
+            block(7).with_entropy(&entropy(7), &entropy(6)),
+        );
+
+        for &index in &[2, 3, 6, 7] {
+            assert_eq!(
+                consistent.committed_entropy(index).unwrap(),
+                &entropy(index)
+            );
+            consistent.verify_entropy(index, &entropy(index)).unwrap();
+            match consistent.verify_entropy(index, &entropy(index + 1)) {
+                Err(EntropyError::Mismatch(i)) if i == index => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+        for &index in &[0, 4, 5, 8] {
+            match consistent.verify_entropy(index, &entropy(index)) {
+                Err(EntropyError::NotCommitted(i)) if i == index => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+        match consistent.verify_entropy(3, &entropy(3)[1..]) {
+            Err(EntropyError::InvalidLength(15, ENTROPY_BYTES)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // the parent entropy of the last epoch must agree with the first epoch's entropy
+        let inconsistent = bundle(
+            block(3).with_entropy(&entropy(3), &entropy(2)),
+            block(4).with_entropy(&entropy(4), &entropy(9)),
+        );
+        inconsistent.verify_entropy(3, &entropy(3)).unwrap_err();
+        inconsistent.verify_entropy(3, &entropy(9)).unwrap_err();
+    }
+
+    #[test]
+    fn epochs_without_entropy_commit_to_none() {
+        match bundle(block(3), block(7)).committed_entropy(3) {
+            Err(EntropyError::NoEntropy(EncodingVersion::V1)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -1,11 +1,20 @@
-use super::encoding::{encode_public_key, encode_u16, encode_u32, EncodingError};
-use algebra::bls12_377::G1Projective;
+use super::encoding::{encode_public_key, EncodingError, EncodingVersion, ENTROPY_BYTES};
+use super::epoch_index::EpochIndex;
+use algebra::{
+    bls12_377::{G1Projective, G2Projective},
+    serialize::{CanonicalDeserialize, CanonicalSerialize},
+    ProjectiveCurve,
+};
 use blake2s_simd::Params;
 use bls_crypto::{
+    bls::{BlsScheme, Celo},
+    epoch,
     hash_to_curve::{try_and_increment::COMPOSITE_HASH_TO_G1, HashToCurve},
-    PublicKey, Signature, OUT_DOMAIN, SIG_DOMAIN,
+    quorum, PublicKey, QuorumCertificate, Signature, OUT_DOMAIN,
 };
 use bls_gadgets::utils::{bits_to_bytes, bytes_to_bits};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
 
 /// A header as parsed after being fetched from the Celo Blockchain
 /// It contains information about the new epoch, as well as an aggregated
@@ -22,6 +31,19 @@ pub struct EpochTransition {
     pub bitmap: Vec<bool>,
 }
 
+impl EpochTransition {
+    /// Returns the seal of the transition as a quorum certificate. The block is signed by
+    /// the validators of the previous epoch, whose index is the certificate's epoch id.
+    pub fn certificate(&self) -> Result<QuorumCertificate, EncodingError> {
+        Ok(QuorumCertificate {
+            epoch_id: self.block.index.saturating_sub(1) as u64,
+            message: self.block.encode_to_bytes()?,
+            bitmap: self.bitmap.clone(),
+            aggregate_signature: self.aggregate_signature.clone(),
+        })
+    }
+}
+
 /// Metadata about the next epoch
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct EpochBlock {
@@ -31,24 +53,69 @@ pub struct EpochBlock {
     pub maximum_non_signers: u32,
     /// The public keys of the new validators
     pub new_public_keys: Vec<PublicKey>,
+    /// The encoding of the block which is signed by the validators
+    pub version: EncodingVersion,
+    /// The entropy of the epoch, required by `EncodingVersion::V2`
+    pub epoch_entropy: Option<Vec<u8>>,
+    /// The entropy of the parent epoch, required by `EncodingVersion::V2`
+    pub parent_entropy: Option<Vec<u8>>,
 }
 
 impl EpochBlock {
-    /// Creates a new epoch block
+    /// Creates a new epoch block, encoded with `EncodingVersion::V1`
     pub fn new(index: u16, maximum_non_signers: u32, new_public_keys: Vec<PublicKey>) -> Self {
         Self {
             index,
             maximum_non_signers,
             new_public_keys,
+            version: EncodingVersion::V1,
+            epoch_entropy: None,
+            parent_entropy: None,
         }
     }
 
+    /// Creates a new epoch block whose maximum number of non-signers follows Istanbul's
+    /// quorum rules for its validators, see `bls_crypto::quorum`
+    pub fn with_istanbul_quorum(index: u16, new_public_keys: Vec<PublicKey>) -> Self {
+        let maximum_non_signers = quorum::max_non_signers(new_public_keys.len()) as u32;
+        Self::new(index, maximum_non_signers, new_public_keys)
+    }
+
+    /// Returns the block's index, for checked arithmetic
+    pub fn epoch_index(&self) -> EpochIndex {
+        EpochIndex::new(self.index)
+    }
+
+    /// Returns the number of validator slots which are not padding. Padding slots hold the
+    /// G2 generator as their public key.
+    pub fn num_active_validators(&self) -> usize {
+        let padding = G2Projective::prime_subgroup_generator();
+        self.new_public_keys
+            .iter()
+            .filter(|pk| *pk.as_ref() != padding)
+            .count()
+    }
+
+    /// Sets the epoch's entropy and the entropy of its parent, and switches the block's
+    /// encoding to `EncodingVersion::V2`
+    pub fn with_entropy(
+        mut self,
+        epoch_entropy: &[u8; ENTROPY_BYTES],
+        parent_entropy: &[u8; ENTROPY_BYTES],
+    ) -> Self {
+        self.version = EncodingVersion::V2;
+        self.epoch_entropy = Some(epoch_entropy.to_vec());
+        self.parent_entropy = Some(parent_entropy.to_vec());
+        self
+    }
+
     /// Encodes the block to bytes and then proceeds to hash it to BLS12-377's G1
-    /// group using `SIG_DOMAIN` as a domain separator
+    /// group in the domain of the `Celo` scheme
     pub fn hash_to_g1(&self) -> Result<G1Projective, EncodingError> {
         let input = self.encode_to_bytes()?;
-        let expected_hash: G1Projective =
-            COMPOSITE_HASH_TO_G1.hash(SIG_DOMAIN, &input, &[]).unwrap();
+        let expected_hash: G1Projective = COMPOSITE_HASH_TO_G1
+            .hash(Celo::DOMAIN, &input, &[])
+            .unwrap();
         Ok(expected_hash)
     }
 
@@ -62,15 +129,24 @@ impl EpochBlock {
         Ok(hash_to_bits(&self.encode_to_bytes_with_aggregated_pk()?))
     }
 
-    /// Encodes the block to LE bits
+    /// Encodes the block to LE bits with `bls_crypto::epoch::encode_block`. Blocks with
+    /// `EncodingVersion::V2` also encode the epoch entropy and the parent entropy after the
+    /// maximum non signers.
     pub fn encode_to_bits(&self) -> Result<Vec<bool>, EncodingError> {
-        let mut epoch_bits = vec![];
-        epoch_bits.extend_from_slice(&encode_u16(self.index)?);
-        epoch_bits.extend_from_slice(&encode_u32(self.maximum_non_signers)?);
-        for added_public_key in &self.new_public_keys {
-            epoch_bits.extend_from_slice(encode_public_key(&added_public_key)?.as_slice());
-        }
-        Ok(epoch_bits)
+        let entropy = if self.version.has_entropy() {
+            let missing = || EncodingError::MissingEntropy(self.version);
+            let epoch_entropy = self.epoch_entropy.as_ref().ok_or_else(missing)?;
+            let parent_entropy = self.parent_entropy.as_ref().ok_or_else(missing)?;
+            Some((&epoch_entropy[..], &parent_entropy[..]))
+        } else {
+            None
+        };
+        Ok(epoch::encode_block(
+            self.epoch_index().get(),
+            self.maximum_non_signers,
+            entropy,
+            &self.new_public_keys,
+        )?)
     }
 
     /// Encodes the block with the aggregated public key from the vector of pubkeys to LE bits
@@ -103,6 +179,75 @@ pub fn hash_first_last_epoch_block(
     Ok([h1, h2].concat())
 }
 
+/// Encodes the epoch's index, maximum number of non-signers, public keys, encoding version
+/// and, for `EncodingVersion::V2`, its entropies. Unlike `EpochBlock::encode_to_bytes`, this
+/// encoding can be decoded, and is used to transfer epochs, e.g. to the WebAssembly verifier.
+pub fn encode_epoch(epoch: &EpochBlock) -> Result<Vec<u8>, EncodingError> {
+    let mut bytes = vec![];
+    bytes.write_u16::<LittleEndian>(epoch.index)?;
+    bytes.write_u32::<LittleEndian>(epoch.maximum_non_signers)?;
+    bytes.write_u32::<LittleEndian>(epoch.new_public_keys.len() as u32)?;
+    for pk in &epoch.new_public_keys {
+        pk.serialize(&mut bytes)?;
+    }
+    bytes.write_u8(epoch.version.has_entropy() as u8)?;
+    if epoch.version.has_entropy() {
+        for entropy in &[&epoch.epoch_entropy, &epoch.parent_entropy] {
+            let entropy = entropy
+                .as_ref()
+                .ok_or(EncodingError::MissingEntropy(epoch.version))?;
+            if entropy.len() != ENTROPY_BYTES {
+                return Err(EncodingError::InvalidEntropyLength(
+                    entropy.len(),
+                    ENTROPY_BYTES,
+                ));
+            }
+            bytes.write_u8(ENTROPY_BYTES as u8)?;
+            bytes.write_all(entropy)?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decodes an epoch encoded with `encode_epoch`, which must span all of `bytes`
+pub fn decode_epoch(bytes: &[u8]) -> Result<EpochBlock, EncodingError> {
+    let reader = &mut &bytes[..];
+    let index = reader.read_u16::<LittleEndian>()?;
+    let maximum_non_signers = reader.read_u32::<LittleEndian>()?;
+    let num_keys = reader.read_u32::<LittleEndian>()? as usize;
+    // each key takes at least one byte, so this bounds the allocation by the input length
+    if num_keys > reader.len() {
+        return Err(EncodingError::Malformed("too many public keys"));
+    }
+    let keys = (0..num_keys)
+        .map(|_| PublicKey::deserialize(&mut *reader))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut epoch = EpochBlock::new(index, maximum_non_signers, keys);
+    match reader.read_u8()? {
+        0 => {}
+        1 => {
+            let epoch_entropy = read_entropy(reader)?;
+            let parent_entropy = read_entropy(reader)?;
+            epoch = epoch.with_entropy(&epoch_entropy, &parent_entropy);
+        }
+        _ => return Err(EncodingError::Malformed("unknown encoding version")),
+    }
+    if !reader.is_empty() {
+        return Err(EncodingError::Malformed("trailing bytes after the epoch"));
+    }
+    Ok(epoch)
+}
+
+fn read_entropy<R: Read>(reader: &mut R) -> Result<[u8; ENTROPY_BYTES], EncodingError> {
+    let len = reader.read_u8()? as usize;
+    if len != ENTROPY_BYTES {
+        return Err(EncodingError::InvalidEntropyLength(len, ENTROPY_BYTES));
+    }
+    let mut entropy = [0u8; ENTROPY_BYTES];
+    reader.read_exact(&mut entropy)?;
+    Ok(entropy)
+}
+
 /// Blake2 hash of the input personalized to `OUT_DOMAIN`
 pub fn hash_to_bits(bytes: &[u8]) -> Vec<bool> {
     let hash = Params::new()
@@ -117,3 +262,128 @@ pub fn hash_to_bits(bytes: &[u8]) -> Vec<bool> {
     bits.reverse();
     bits
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G2Projective, UniformRand};
+
+    fn block() -> EpochBlock {
+        let rng = &mut rand::thread_rng();
+        let pubkeys = (0..3)
+            .map(|_| PublicKey::from(G2Projective::rand(rng)))
+            .collect::<Vec<_>>();
+        EpochBlock::new(5, 1, pubkeys)
+    }
+
+    #[test]
+    fn encodes_entropy() {
+        let v1 = block();
+        let v2 = v1
+            .clone()
+            .with_entropy(&[1; ENTROPY_BYTES], &[2; ENTROPY_BYTES]);
+        let v1_bits = v1.encode_to_bits().unwrap();
+        let v2_bits = v2.encode_to_bits().unwrap();
+        assert_eq!(v2_bits.len(), v1_bits.len() + 2 * ENTROPY_BYTES * 8);
+        assert_eq!(&v2_bits[..48], &v1_bits[..48]);
+        assert_eq!(&v2_bits[48 + 2 * ENTROPY_BYTES * 8..], &v1_bits[48..]);
+
+        let other = v1.with_entropy(&[1; ENTROPY_BYTES], &[3; ENTROPY_BYTES]);
+        assert_ne!(other.encode_to_bits().unwrap(), v2_bits);
+    }
+
+    #[test]
+    fn rejects_missing_or_malformed_entropy() {
+        let mut missing = block();
+        missing.version = EncodingVersion::V2;
+        missing.encode_to_bits().unwrap_err();
+
+        let mut short = block().with_entropy(&[1; ENTROPY_BYTES], &[2; ENTROPY_BYTES]);
+        short.epoch_entropy = Some(vec![1; ENTROPY_BYTES - 1]);
+        short.encode_to_bits().unwrap_err();
+        match encode_epoch(&short) {
+            Err(EncodingError::InvalidEntropyLength(len, ENTROPY_BYTES)) => {
+                assert_eq!(len, ENTROPY_BYTES - 1)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // a longer entropy whose length wraps around to the expected one in its one byte prefix
+        short.epoch_entropy = Some(vec![1; 256 + ENTROPY_BYTES]);
+        encode_epoch(&short).unwrap_err();
+
+        let mut bytes =
+            encode_epoch(&block().with_entropy(&[1; ENTROPY_BYTES], &[2; ENTROPY_BYTES])).unwrap();
+        let prefix = bytes.len() - 2 * (ENTROPY_BYTES + 1);
+        bytes[prefix] -= 1;
+        match decode_epoch(&bytes) {
+            Err(EncodingError::InvalidEntropyLength(len, ENTROPY_BYTES)) => {
+                assert_eq!(len, ENTROPY_BYTES - 1)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn converts_transitions_to_certificates() {
+        let rng = &mut rand::thread_rng();
+        let transition = EpochTransition {
+            block: block(),
+            aggregate_signature: Signature::from(G1Projective::rand(rng)),
+            bitmap: vec![true, false, true],
+        };
+        let certificate = transition.certificate().unwrap();
+        assert_eq!(certificate.epoch_id, 4);
+        assert_eq!(certificate.signers(), vec![0, 2]);
+        assert_eq!(
+            certificate.message,
+            transition.block.encode_to_bytes().unwrap()
+        );
+        assert_eq!(
+            certificate.aggregate_signature,
+            transition.aggregate_signature
+        );
+    }
+
+    #[test]
+    fn encodes_as_the_light_client_blocks() {
+        let block = block();
+        let light_client_block =
+            bls_crypto::EpochBlock::new(block.index, 0, block.new_public_keys.clone(), 2);
+        assert_eq!(
+            block.encode_to_bytes().unwrap(),
+            light_client_block.encode_to_bytes().unwrap()
+        );
+    }
+
+    #[test]
+    fn encodes_v2_as_the_light_client_blocks() {
+        let block = block().with_entropy(&[1; ENTROPY_BYTES], &[2; ENTROPY_BYTES]);
+        let light_client_block =
+            bls_crypto::EpochBlock::new(block.index, 0, block.new_public_keys.clone(), 2)
+                .with_entropy([1; ENTROPY_BYTES], [2; ENTROPY_BYTES]);
+        let bytes = block.encode_to_bytes().unwrap();
+        assert_eq!(bytes, light_client_block.encode_to_bytes().unwrap());
+
+        // the transfer encoding round-trips to a block with the same signed message
+        let decoded = decode_epoch(&encode_epoch(&block).unwrap()).unwrap();
+        assert_eq!(decoded, block);
+        assert_eq!(decoded.encode_to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn follows_istanbul_quorum() {
+        let keys = block().new_public_keys;
+        assert_eq!(
+            EpochBlock::with_istanbul_quorum(5, keys).maximum_non_signers,
+            1
+        );
+        let keys = (0..100)
+            .map(|_| PublicKey::from(G2Projective::rand(&mut rand::thread_rng())))
+            .collect();
+        assert_eq!(
+            EpochBlock::with_istanbul_quorum(5, keys).maximum_non_signers,
+            33
+        );
+    }
+}
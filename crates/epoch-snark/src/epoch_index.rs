@@ -0,0 +1,197 @@
+//! Checked arithmetic over epoch indices.
+//!
+//! Epoch indices are encoded as `u16` in the epoch blocks, and the circuit uses index 0 to
+//! mark padding epochs. `EpochIndex` wraps the raw index so that the ranges which are passed
+//! to the prover and the verifier are checked to be non-empty, ordered and within the
+//! circuit's capacity, instead of silently wrapping or producing an unverifiable proof.
+use crate::epoch_block::{EpochBlock, EpochTransition};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+/// Error raised when epoch indices do not form a valid range
+pub enum EpochIndexError {
+    #[error("Epoch index {index} + {offset} overflows")]
+    Overflow { index: u16, offset: usize },
+    #[error("The last epoch's index {last} is not after the first epoch's index {first}")]
+    NotIncreasing { first: u16, last: u16 },
+    #[error("The range spans {got} epochs, but at most {max} are supported")]
+    RangeTooLong { max: usize, got: usize },
+    #[error("Transition {position} has index {got}, but the next epoch is {expected}")]
+    NotConsecutive {
+        position: usize,
+        expected: u16,
+        got: u16,
+    },
+}
+
+/// The index of an epoch
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EpochIndex(u16);
+
+impl EpochIndex {
+    /// The largest representable epoch index
+    pub const MAX: EpochIndex = EpochIndex(u16::MAX);
+
+    /// Wraps a raw index
+    pub const fn new(index: u16) -> Self {
+        Self(index)
+    }
+
+    /// Returns the raw index
+    pub const fn get(self) -> u16 {
+        self.0
+    }
+
+    /// Returns the index `offset` epochs after this one, or an error if it does not fit
+    /// in a `u16`
+    pub fn checked_add(self, offset: usize) -> Result<Self, EpochIndexError> {
+        let overflow = EpochIndexError::Overflow {
+            index: self.0,
+            offset,
+        };
+        if offset > u16::MAX as usize {
+            return Err(overflow);
+        }
+        self.0.checked_add(offset as u16).map(Self).ok_or(overflow)
+    }
+
+    /// Returns the next epoch's index
+    pub fn next(self) -> Result<Self, EpochIndexError> {
+        self.checked_add(1)
+    }
+
+    /// Returns the number of epochs from this index (exclusive) to `last` (inclusive). Fails
+    /// if `last` is not after this index, or if there are more than `max_epochs` epochs.
+    pub fn epochs_until(self, last: Self, max_epochs: usize) -> Result<usize, EpochIndexError> {
+        if last <= self {
+            return Err(EpochIndexError::NotIncreasing {
+                first: self.0,
+                last: last.0,
+            });
+        }
+        let num_epochs = (last.0 - self.0) as usize;
+        if num_epochs > max_epochs {
+            return Err(EpochIndexError::RangeTooLong {
+                max: max_epochs,
+                got: num_epochs,
+            });
+        }
+        Ok(num_epochs)
+    }
+
+    /// Checks that the transitions are non-empty, at most `max_epochs` long and follow the
+    /// initial epoch with consecutive indices. Since the indices increase, none of them is
+    /// the padding index 0.
+    pub fn check_transitions(
+        initial_epoch: &EpochBlock,
+        transitions: &[EpochTransition],
+        max_epochs: usize,
+    ) -> Result<(), EpochIndexError> {
+        let first = initial_epoch.epoch_index();
+        let last = transitions
+            .last()
+            .map(|transition| transition.block.epoch_index())
+            .unwrap_or(first);
+        first.epochs_until(last, max_epochs)?;
+
+        let mut expected = first;
+        for (position, transition) in transitions.iter().enumerate() {
+            expected = expected.next()?;
+            let got = transition.block.epoch_index();
+            if got != expected {
+                return Err(EpochIndexError::NotConsecutive {
+                    position,
+                    expected: expected.0,
+                    got: got.0,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<u16> for EpochIndex {
+    fn from(index: u16) -> Self {
+        Self(index)
+    }
+}
+
+impl From<EpochIndex> for u16 {
+    fn from(index: EpochIndex) -> Self {
+        index.0
+    }
+}
+
+impl fmt::Display for EpochIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G1Projective, Zero};
+    use bls_crypto::Signature;
+
+    fn transition(index: u16) -> EpochTransition {
+        EpochTransition {
+            block: EpochBlock::new(index, 0, vec![]),
+            aggregate_signature: Signature::from(G1Projective::zero()),
+            bitmap: vec![],
+        }
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let index = EpochIndex::new(10);
+        assert_eq!(index.next().unwrap(), EpochIndex::new(11));
+        assert_eq!(index.checked_add(5).unwrap().get(), 15);
+        assert_eq!(
+            EpochIndex::MAX.next(),
+            Err(EpochIndexError::Overflow {
+                index: u16::MAX,
+                offset: 1
+            })
+        );
+        index.checked_add(1 << 20).unwrap_err();
+
+        assert_eq!(index.epochs_until(EpochIndex::new(13), 3), Ok(3));
+        assert_eq!(
+            index.epochs_until(EpochIndex::new(13), 2),
+            Err(EpochIndexError::RangeTooLong { max: 2, got: 3 })
+        );
+        // swapped endpoints are rejected instead of wrapping around
+        assert_eq!(
+            EpochIndex::new(13).epochs_until(index, 100),
+            Err(EpochIndexError::NotIncreasing {
+                first: 13,
+                last: 10
+            })
+        );
+        index.epochs_until(index, 100).unwrap_err();
+    }
+
+    #[test]
+    fn transitions_must_be_consecutive() {
+        let initial = EpochBlock::new(4, 0, vec![]);
+        let transitions = (5..8).map(transition).collect::<Vec<_>>();
+        EpochIndex::check_transitions(&initial, &transitions, 3).unwrap();
+        assert_eq!(
+            EpochIndex::check_transitions(&initial, &transitions, 2),
+            Err(EpochIndexError::RangeTooLong { max: 2, got: 3 })
+        );
+        EpochIndex::check_transitions(&initial, &[], 3).unwrap_err();
+
+        let gap = [transition(5), transition(7)];
+        assert_eq!(
+            EpochIndex::check_transitions(&initial, &gap, 3),
+            Err(EpochIndexError::NotConsecutive {
+                position: 1,
+                expected: 6,
+                got: 7
+            })
+        );
+    }
+}
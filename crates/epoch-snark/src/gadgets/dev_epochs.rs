@@ -0,0 +1,103 @@
+use algebra::{bls12_377::G2Projective, bw6_761::Fr, ProjectiveCurve};
+use bls_crypto::PublicKey;
+use r1cs_core::{ConstraintSynthesizer, ConstraintSystem, SynthesisError};
+use tracing::warn;
+
+use super::{constrain_bool, to_fr, PublicInputsGadget};
+use crate::{
+    encoding::{EncodingError, EncodingVersion, ENTROPY_BYTES},
+    epoch_block::EpochBlock,
+};
+
+/// INSECURE stand-in for `ValidatorSetUpdate` with the same public inputs, which only
+/// hashes the first and last epoch. It does not verify any signature or transition, so its
+/// proofs show nothing about the epochs, and it must only be used in tests.
+pub struct DevEpochs {
+    /// The bits of the first epoch, as encoded by `EpochBlock::encode_to_bits`
+    pub first_epoch_bits: Vec<Option<bool>>,
+    /// The bits of the last epoch, as encoded by
+    /// `EpochBlock::encode_to_bits_with_aggregated_pk`
+    pub last_epoch_bits: Vec<Option<bool>>,
+    /// The number of epochs from the first to the last epoch
+    pub num_epochs: Option<u64>,
+}
+
+impl DevEpochs {
+    /// An empty circuit for epochs of `num_validators` validators encoded with `version`,
+    /// which is used for the setup
+    pub fn empty(num_validators: usize, version: EncodingVersion) -> Self {
+        let keys = vec![PublicKey::from(G2Projective::prime_subgroup_generator()); num_validators];
+        let mut epoch = EpochBlock::new(0, 0, keys);
+        if version.has_entropy() {
+            epoch = epoch.with_entropy(&[0; ENTROPY_BYTES], &[0; ENTROPY_BYTES]);
+        }
+        let first_len = epoch
+            .encode_to_bits()
+            .expect("the epoch has the entropy of its version")
+            .len();
+        let last_len = epoch
+            .encode_to_bits_with_aggregated_pk()
+            .expect("the epoch has the entropy of its version")
+            .len();
+        DevEpochs {
+            first_epoch_bits: vec![None; first_len],
+            last_epoch_bits: vec![None; last_len],
+            num_epochs: None,
+        }
+    }
+
+    /// The circuit for the first and last epoch, which are `num_epochs` epochs apart
+    pub fn new(
+        first_epoch: &EpochBlock,
+        last_epoch: &EpochBlock,
+        num_epochs: u64,
+    ) -> Result<Self, EncodingError> {
+        let to_option = |bits: Vec<bool>| bits.into_iter().map(Some).collect();
+        Ok(DevEpochs {
+            first_epoch_bits: to_option(first_epoch.encode_to_bits()?),
+            last_epoch_bits: to_option(last_epoch.encode_to_bits_with_aggregated_pk()?),
+            num_epochs: Some(num_epochs),
+        })
+    }
+}
+
+impl ConstraintSynthesizer<Fr> for DevEpochs {
+    fn generate_constraints<CS: ConstraintSystem<Fr>>(
+        self,
+        cs: &mut CS,
+    ) -> Result<(), SynthesisError> {
+        warn!("generating the INSECURE dev epochs circuit");
+        let first_epoch_bits =
+            constrain_bool(&mut cs.ns(|| "first epoch"), &self.first_epoch_bits)?;
+        let last_epoch_bits = constrain_bool(&mut cs.ns(|| "last epoch"), &self.last_epoch_bits)?;
+        let num_epochs = to_fr(&mut cs.ns(|| "num epochs"), self.num_epochs)?;
+        PublicInputsGadget::enforce(
+            &mut cs.ns(|| "public inputs"),
+            &first_epoch_bits,
+            &last_epoch_bits,
+            &num_epochs,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r1cs_std::test_constraint_system::TestConstraintSystem;
+
+    #[test]
+    fn matches_empty_circuit() {
+        let keys = vec![PublicKey::from(G2Projective::prime_subgroup_generator()); 2];
+        let first = EpochBlock::new(3, 1, keys.clone());
+        let last = EpochBlock::new(5, 1, keys);
+        let circuit = DevEpochs::new(&first, &last, 2).unwrap();
+        let empty = DevEpochs::empty(2, EncodingVersion::V1);
+        assert_eq!(circuit.first_epoch_bits.len(), empty.first_epoch_bits.len());
+        assert_eq!(circuit.last_epoch_bits.len(), empty.last_epoch_bits.len());
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        circuit.generate_constraints(&mut cs).unwrap();
+        assert!(cs.is_satisfied());
+    }
+}
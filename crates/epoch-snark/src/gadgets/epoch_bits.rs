@@ -1,6 +1,6 @@
 use algebra::{
     bls12_377::{Bls12_377, Fr as BlsFr, FrParameters as BlsFrParameters},
-    bw6_761::{Fr, FrParameters},
+    bw6_761::Fr,
     FpParameters,
 };
 use r1cs_std::bls12_377::PairingGadget;
@@ -9,22 +9,18 @@ use r1cs_std::prelude::*;
 use r1cs_core::{ConstraintSystem, SynthesisError};
 
 // Groth16 Specific imports
-use crypto_primitives::{
-    nizk::{
-        constraints::NIZKVerifierGadget,
-        groth16::{
-            constraints::{Groth16VerifierGadget, ProofGadget, VerifyingKeyGadget},
-            Groth16,
-        },
+use crypto_primitives::nizk::{
+    constraints::NIZKVerifierGadget,
+    groth16::{
+        constraints::{Groth16VerifierGadget, ProofGadget, VerifyingKeyGadget},
+        Groth16,
     },
-    prf::blake2s::{constraints::blake2s_gadget_with_parameters, Blake2sWithParameterBlock},
 };
 
 use r1cs_std::fields::fp::FpGadget;
 type FrGadget = FpGadget<Fr>;
 
-use crate::gadgets::{HashToBits, HashToBitsHelper, MultipackGadget};
-use bls_crypto::OUT_DOMAIN;
+use crate::gadgets::{HashToBits, HashToBitsHelper, PublicInputsGadget};
 
 /// Contains the first and last epoch's bits, along with auxiliary CRH and XOF bits
 /// which are used for verifying the CRH -> XOF hash calculation
@@ -37,6 +33,8 @@ pub struct EpochBits {
     pub crh_bits: Vec<Boolean>,
     /// The XOF bits for all intermediate state transitions
     pub xof_bits: Vec<Boolean>,
+    /// The number of non-dummy epochs, which is exposed as the last public input
+    pub num_epochs: FrGadget,
 }
 
 impl EpochBits {
@@ -50,62 +48,13 @@ impl EpochBits {
         if let Some(helper) = helper {
             self.verify_proof(&mut cs.ns(|| "verify proof"), &helper)?;
         }
-        self.verify_edges(&mut cs.ns(|| "verify edges"))?;
-        Ok(())
-    }
-
-    fn verify_edges<CS: ConstraintSystem<Fr>>(
-        &self,
-        cs: &mut CS,
-    ) -> Result<Vec<FrGadget>, SynthesisError> {
-        // Verify the edges
-        let mut xof_bits = vec![];
-        let first_and_last_bits = [self.first_epoch_bits.clone(), self.last_epoch_bits.clone()];
-        for (i, bits) in first_and_last_bits.iter().enumerate() {
-            let mut message = bits.to_owned();
-            message.reverse();
-            let message_rounded_len = 8 * ((message.len() + 7) / 8);
-            message.resize(message_rounded_len, Boolean::constant(false));
-
-            let mut personalization = [0; 8];
-            personalization.copy_from_slice(OUT_DOMAIN);
-
-            let blake2s_parameters = Blake2sWithParameterBlock {
-                digest_length: 32,
-                key_length: 0,
-                fan_out: 1,
-                depth: 1,
-                leaf_length: 0,
-                node_offset: 0,
-                xof_digest_length: 0,
-                node_depth: 0,
-                inner_length: 0,
-                salt: [0; 8],
-                personalization,
-            };
-            let xof_result = blake2s_gadget_with_parameters(
-                cs.ns(|| format!("first and last xof result {}", i)),
-                &message,
-                &blake2s_parameters.parameters(),
-            )?;
-            let xof_bits_i = xof_result
-                .into_iter()
-                .map(|n| n.to_bits_le())
-                .flatten()
-                .collect::<Vec<Boolean>>();
-            xof_bits.extend_from_slice(&xof_bits_i);
-        }
-
-        // Make the edges public inputs
-        // packed over BW6_761 Fr.
-        let packed = MultipackGadget::pack(
-            cs.ns(|| "pack output hash"),
-            &xof_bits,
-            FrParameters::CAPACITY as usize,
-            true,
+        PublicInputsGadget::enforce(
+            &mut cs.ns(|| "verify edges"),
+            &self.first_epoch_bits,
+            &self.last_epoch_bits,
+            &self.num_epochs,
         )?;
-
-        Ok(packed)
+        Ok(())
     }
 
     /// Ensure that the intermediate BH and Blake2 hashes match
@@ -155,56 +104,3 @@ fn le_chunks(iter: &[Boolean], chunk_size: u32) -> Vec<Vec<Boolean>> {
         })
         .collect::<Vec<_>>()
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use bls_gadgets::utils::bytes_to_bits;
-    use rand::RngCore;
-
-    use crate::epoch_block::hash_to_bits;
-    use crate::gadgets::pack;
-    use r1cs_std::test_constraint_system::TestConstraintSystem;
-
-    fn to_bool(iter: &[bool]) -> Vec<Boolean> {
-        iter.iter().map(|b| Boolean::constant(*b)).collect()
-    }
-
-    #[test]
-    fn correct_blake2_hash() {
-        let rng = &mut rand::thread_rng();
-        let mut first_bytes = vec![0; 32];
-        rng.fill_bytes(&mut first_bytes);
-        let mut last_bytes = vec![0; 32];
-        rng.fill_bytes(&mut last_bytes);
-
-        let both_blake_bits = [first_bytes.clone(), last_bytes.clone()]
-            .iter()
-            .map(|b| hash_to_bits(b))
-            .flatten()
-            .collect::<Vec<bool>>();
-
-        // encode each epoch's bytes to LE and pas them to the constraint system
-        let first_epoch_bits = bytes_to_bits(&first_bytes, 256);
-        let last_epoch_bits = bytes_to_bits(&last_bytes, 256);
-        let bits = EpochBits {
-            crh_bits: vec![],
-            xof_bits: vec![],
-            first_epoch_bits: to_bool(&first_epoch_bits),
-            last_epoch_bits: to_bool(&last_epoch_bits),
-        };
-
-        let mut cs = TestConstraintSystem::<Fr>::new();
-        let packed = bits.verify_edges(&mut cs).unwrap();
-        assert!(cs.is_satisfied());
-
-        // get the inner packed value
-        let inner = packed
-            .into_iter()
-            .map(|i| i.get_value().unwrap())
-            .collect::<Vec<_>>();
-        // pack our bits to Fr as well, and see if they match
-        let public_inputs = pack::<Fr, FrParameters>(&both_blake_bits).unwrap();
-        assert_eq!(inner, public_inputs);
-    }
-}
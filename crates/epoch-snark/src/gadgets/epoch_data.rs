@@ -3,7 +3,10 @@ use algebra::{
     bw6_761::Fr,
     One, PairingEngine,
 };
-use bls_gadgets::{utils::is_setup, HashToGroupGadget, YToBitGadget};
+use bls_gadgets::{
+    bits_to_bytes, conditional_enforce_equal_with_label, utils::is_setup, HashToGroupGadget,
+    YToBitGadget,
+};
 use r1cs_core::{ConstraintSystem, SynthesisError};
 use r1cs_std::{
     bls12_377::{G1Gadget, G2Gadget},
@@ -12,9 +15,13 @@ use r1cs_std::{
     Assignment,
 };
 
-use bls_crypto::{hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1, SIG_DOMAIN};
+use bls_crypto::{
+    bls::{BlsScheme, Celo},
+    hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1,
+};
 
-use super::{fr_to_bits, g2_to_bits, to_fr};
+use super::{constrain_bool, fr_to_bits, g2_to_bits, to_fr};
+use crate::encoding::{encode_entropy, EncodingVersion, ENTROPY_BYTES};
 use tracing::{span, trace, Level};
 
 type FrGadget = FpGadget<Fr>;
@@ -31,6 +38,12 @@ pub struct EpochData<E: PairingEngine> {
     pub index: Option<u16>,
     /// The public keys at the epoch
     pub public_keys: Vec<Option<E::G2Projective>>,
+    /// The encoding of the epoch which is signed by the validators
+    pub version: EncodingVersion,
+    /// The entropy of the epoch, used by `EncodingVersion::V2`
+    pub epoch_entropy: Option<Vec<u8>>,
+    /// The entropy of the parent epoch, used by `EncodingVersion::V2`
+    pub parent_entropy: Option<Vec<u8>>,
 }
 
 /// [`EpochData`] is constrained to a `ConstrainedEpochData` via [`EpochData.constrain`]
@@ -46,6 +59,10 @@ pub struct ConstrainedEpochData {
     pub message_hash: G1Gadget,
     /// The new validators for this epoch
     pub pubkeys: Vec<G2Gadget>,
+    /// The epoch's entropy bits, empty unless the epoch uses `EncodingVersion::V2`
+    pub epoch_entropy: Vec<Boolean>,
+    /// The parent epoch's entropy bits, empty unless the epoch uses `EncodingVersion::V2`
+    pub parent_entropy: Vec<Boolean>,
     /// Serialized epoch data containing the index, max non signers, aggregated pubkey and the pubkeys array
     pub bits: Vec<Boolean>,
     /// Aux data for proving the CRH->XOF hash outside of BW6_761
@@ -57,10 +74,22 @@ pub struct ConstrainedEpochData {
 impl<E: PairingEngine> EpochData<E> {
     /// Initializes an empty epoch, to be used for the setup
     pub fn empty(num_validators: usize, maximum_non_signers: usize) -> Self {
+        Self::empty_with_version(num_validators, maximum_non_signers, EncodingVersion::V1)
+    }
+
+    /// Initializes an empty epoch with the provided encoding, to be used for the setup
+    pub fn empty_with_version(
+        num_validators: usize,
+        maximum_non_signers: usize,
+        version: EncodingVersion,
+    ) -> Self {
         EpochData::<E> {
             index: None,
             maximum_non_signers: maximum_non_signers as u32,
             public_keys: vec![None; num_validators],
+            version,
+            epoch_entropy: None,
+            parent_entropy: None,
         }
     }
 }
@@ -77,7 +106,8 @@ impl EpochData<Bls12_377> {
     ) -> Result<ConstrainedEpochData, SynthesisError> {
         let span = span!(Level::TRACE, "EpochData");
         let _enter = span.enter();
-        let (bits, index, maximum_non_signers, pubkeys) = self.to_bits(cs)?;
+        let (bits, index, maximum_non_signers, pubkeys, (epoch_entropy, parent_entropy)) =
+            self.to_bits(cs)?;
         Self::enforce_next_epoch(&mut cs.ns(|| "enforce next epoch"), previous_index, &index)?;
 
         // Hash to G1
@@ -92,17 +122,31 @@ impl EpochData<Bls12_377> {
             index,
             maximum_non_signers,
             pubkeys,
+            epoch_entropy,
+            parent_entropy,
             message_hash,
             crh_bits,
             xof_bits,
         })
     }
 
-    /// Encodes the epoch to bits (index and non-signers encoded as LE)
+    /// Encodes the epoch to bits (index and non-signers encoded as LE). With
+    /// `EncodingVersion::V2`, the epoch entropy and parent entropy bits are encoded after the
+    /// non-signers and also returned separately, otherwise they are empty.
+    #[allow(clippy::type_complexity)]
     pub fn to_bits<CS: ConstraintSystem<Fr>>(
         &self,
         cs: &mut CS,
-    ) -> Result<(Vec<Boolean>, FrGadget, FrGadget, Vec<G2Gadget>), SynthesisError> {
+    ) -> Result<
+        (
+            Vec<Boolean>,
+            FrGadget,
+            FrGadget,
+            Vec<G2Gadget>,
+            (Vec<Boolean>, Vec<Boolean>),
+        ),
+        SynthesisError,
+    > {
         let index = to_fr(&mut cs.ns(|| "index"), self.index)?;
         let index_bits = fr_to_bits(&mut cs.ns(|| "index bits"), &index, 16)?;
 
@@ -118,6 +162,18 @@ impl EpochData<Bls12_377> {
 
         let mut epoch_bits: Vec<Boolean> = [index_bits, maximum_non_signers_bits].concat();
 
+        let (epoch_entropy, parent_entropy) = if self.version.has_entropy() {
+            let epoch_entropy =
+                Self::entropy_to_bits(&mut cs.ns(|| "epoch entropy"), &self.epoch_entropy)?;
+            let parent_entropy =
+                Self::entropy_to_bits(&mut cs.ns(|| "parent entropy"), &self.parent_entropy)?;
+            epoch_bits.extend_from_slice(&epoch_entropy);
+            epoch_bits.extend_from_slice(&parent_entropy);
+            (epoch_entropy, parent_entropy)
+        } else {
+            (vec![], vec![])
+        };
+
         let mut pubkey_vars = Vec::with_capacity(self.public_keys.len());
         for (j, maybe_pk) in self.public_keys.iter().enumerate() {
             let pk_var = G2Gadget::alloc(cs.ns(|| format!("pub key {}", j)), || maybe_pk.get())?;
@@ -130,7 +186,30 @@ impl EpochData<Bls12_377> {
             pubkey_vars.push(pk_var);
         }
 
-        Ok((epoch_bits, index, maximum_non_signers, pubkey_vars))
+        Ok((
+            epoch_bits,
+            index,
+            maximum_non_signers,
+            pubkey_vars,
+            (epoch_entropy, parent_entropy),
+        ))
+    }
+
+    /// Allocates the entropy's bits in the same order as the native encoding. During the
+    /// setup the entropy is not known and the bits are left unassigned.
+    fn entropy_to_bits<CS: ConstraintSystem<Fr>>(
+        cs: &mut CS,
+        entropy: &Option<Vec<u8>>,
+    ) -> Result<Vec<Boolean>, SynthesisError> {
+        let bits: Vec<Option<bool>> = match entropy {
+            Some(entropy) => encode_entropy(entropy)
+                .map_err(|_| SynthesisError::Unsatisfiable)?
+                .into_iter()
+                .map(Some)
+                .collect(),
+            None => vec![None; ENTROPY_BYTES * 8],
+        };
+        constrain_bool(cs, &bits)
     }
 
     /// Enforces that `index = previous_index + 1`
@@ -145,8 +224,10 @@ impl EpochData<Bls12_377> {
 
         let index_bit =
             YToBitGadget::<Parameters>::is_eq_zero(&mut cs.ns(|| "is index zero"), index)?.not();
-        index.conditional_enforce_equal(
+        conditional_enforce_equal_with_label(
             cs.ns(|| "index enforce equal"),
+            || "epoch index is previous index plus one",
+            index,
             &previous_plus_one,
             &index_bit,
         )?;
@@ -161,23 +242,10 @@ impl EpochData<Bls12_377> {
         generate_constraints_for_hash: bool,
     ) -> Result<(G1Gadget, Vec<Boolean>, Vec<Boolean>), SynthesisError> {
         trace!("hashing epoch to g1");
-        // Reverse to LE
-        let mut epoch_bits = epoch_bits.to_vec();
-        epoch_bits.reverse();
-
         let is_setup = is_setup(&epoch_bits);
 
         // Pack them to Uint8s
-        let input_bytes_var: Vec<UInt8> = epoch_bits
-            .chunks(8)
-            .map(|chunk| {
-                let mut chunk = chunk.to_vec();
-                if chunk.len() < 8 {
-                    chunk.resize(8, Boolean::constant(false));
-                }
-                UInt8::from_bits_le(&chunk)
-            })
-            .collect();
+        let input_bytes_var: Vec<UInt8> = bits_to_bytes(epoch_bits);
 
         // Get the inner values
         let counter = if is_setup {
@@ -190,7 +258,7 @@ impl EpochData<Bls12_377> {
                 .collect::<Result<Vec<_>, _>>()?;
 
             let (_, counter) = COMPOSITE_HASH_TO_G1
-                .hash_with_attempt(SIG_DOMAIN, &input_bytes, &[])
+                .hash_with_attempt(Celo::DOMAIN, &input_bytes, &[])
                 .map_err(|_| SynthesisError::Unsatisfiable)?;
             counter
         };
@@ -217,6 +285,7 @@ mod tests {
 
     use crate::epoch_block::EpochBlock;
     use bls_crypto::PublicKey;
+    use std::convert::TryInto;
 
     fn test_epoch(index: u16) -> EpochData<Bls12_377> {
         let rng = &mut rand::thread_rng();
@@ -227,6 +296,34 @@ mod tests {
             index: Some(index),
             maximum_non_signers: 12,
             public_keys: pubkeys,
+            version: EncodingVersion::V1,
+            epoch_entropy: None,
+            parent_entropy: None,
+        }
+    }
+
+    fn test_epoch_with_entropy(index: u16) -> EpochData<Bls12_377> {
+        EpochData::<Bls12_377> {
+            version: EncodingVersion::V2,
+            epoch_entropy: Some(vec![index as u8; ENTROPY_BYTES]),
+            parent_entropy: Some(vec![index as u8 - 1; ENTROPY_BYTES]),
+            ..test_epoch(index)
+        }
+    }
+
+    fn to_block(epoch: &EpochData<Bls12_377>) -> EpochBlock {
+        let pubkeys = epoch
+            .public_keys
+            .iter()
+            .map(|pk| PublicKey::from(pk.unwrap()))
+            .collect();
+        let block = EpochBlock::new(epoch.index.unwrap(), epoch.maximum_non_signers, pubkeys);
+        match (&epoch.epoch_entropy, &epoch.parent_entropy) {
+            (Some(epoch_entropy), Some(parent_entropy)) => block.with_entropy(
+                epoch_entropy.as_slice().try_into().unwrap(),
+                parent_entropy.as_slice().try_into().unwrap(),
+            ),
+            _ => block,
         }
     }
 
@@ -254,7 +351,7 @@ mod tests {
             .encode_to_bytes()
             .unwrap();
         let (hash, _) = COMPOSITE_HASH_TO_G1
-            .hash_with_attempt(SIG_DOMAIN, &epoch_bytes, &[])
+            .hash_with_attempt(Celo::DOMAIN, &epoch_bytes, &[])
             .unwrap();
 
         // compare it with the one calculated in the circuit from its bytes
@@ -318,4 +415,45 @@ mod tests {
         assert_eq!(bits_inner, bits);
         assert_ne!(bits_inner, bits_wrong);
     }
+
+    #[test]
+    fn epoch_with_entropy_to_bits_ok() {
+        let epoch = test_epoch_with_entropy(18);
+        let bits = to_block(&epoch).encode_to_bits().unwrap();
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let ret = epoch.to_bits(&mut cs).unwrap();
+        let bits_inner = ret
+            .0
+            .iter()
+            .map(|x| x.get_value().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(bits_inner, bits);
+        assert_eq!((ret.4).0.len(), ENTROPY_BYTES * 8);
+        assert_eq!((ret.4).1.len(), ENTROPY_BYTES * 8);
+
+        // the v1 encoding of the same epoch is different
+        let v1 = EpochData {
+            version: EncodingVersion::V1,
+            ..epoch
+        };
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let v1_bits = v1.to_bits(&mut cs).unwrap().0;
+        assert_eq!(v1_bits.len(), bits.len() - 2 * ENTROPY_BYTES * 8);
+    }
+
+    #[test]
+    fn test_hash_epoch_with_entropy_to_g1() {
+        let epoch = test_epoch_with_entropy(10);
+        let epoch_bytes = to_block(&epoch).encode_to_bytes().unwrap();
+        let (hash, _) = COMPOSITE_HASH_TO_G1
+            .hash_with_attempt(Celo::DOMAIN, &epoch_bytes, &[])
+            .unwrap();
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let bits = epoch.to_bits(&mut cs.ns(|| "epoch2bits")).unwrap().0;
+        let ret =
+            EpochData::hash_bits_to_g1(&mut cs.ns(|| "hash epoch bits"), &bits, false).unwrap();
+        assert_eq!(ret.0.get_value().unwrap(), hash);
+    }
 }
@@ -5,7 +5,7 @@
 use algebra::{
     bls12_377::{Bls12_377, G1Projective, G2Projective, Parameters},
     bw6_761::Fr,
-    PairingEngine, ProjectiveCurve,
+    One, PairingEngine, ProjectiveCurve,
 };
 use r1cs_std::prelude::*;
 use r1cs_std::{
@@ -17,13 +17,20 @@ use r1cs_std::{
 };
 use tracing::{debug, info, span, Level};
 
-use r1cs_core::{ConstraintSynthesizer, ConstraintSystem, SynthesisError};
+use r1cs_core::{ConstraintSynthesizer, ConstraintSystem, LinearCombination, SynthesisError};
 
 use groth16::{Proof, VerifyingKey};
 
-use crate::gadgets::{g2_to_bits, single_update::SingleUpdate, EpochBits, EpochData};
+use crate::{
+    encoding::EncodingVersion,
+    gadgets::{
+        enforce_min_validators, g2_to_bits, single_update::SingleUpdate, EpochBits, EpochData,
+    },
+};
 
-use bls_gadgets::{BlsVerifyGadget, YToBitGadget};
+use bls_gadgets::{
+    conditional_enforce_equal_with_label, enforce_equal_with_label, BlsVerifyGadget, YToBitGadget,
+};
 type BlsGadget = BlsVerifyGadget<Bls12_377, Fr, PairingGadget>;
 type FrGadget = FpGadget<Fr>;
 
@@ -35,6 +42,9 @@ pub struct ValidatorSetUpdate<E: PairingEngine> {
     pub initial_epoch: EpochData<E>,
     /// The number of validators over all the epochs
     pub num_validators: u32,
+    /// The minimum number of validator slots which are not padding in every epoch. No
+    /// constraints are added if it is 0.
+    pub min_validators: u32,
     /// A list of all the updates for multiple epochs
     pub epochs: Vec<SingleUpdate<E>>,
     /// The aggregated signature of all the validators over all the epoch changes
@@ -62,20 +72,51 @@ impl<E: PairingEngine> ValidatorSetUpdate<E> {
         maximum_non_signers: usize,
         vk: Option<VerifyingKey<E>>,
     ) -> Self {
-        let empty_update = SingleUpdate::empty(num_validators, maximum_non_signers);
+        Self::empty_with_version(
+            num_validators,
+            num_epochs,
+            maximum_non_signers,
+            vk,
+            EncodingVersion::V1,
+        )
+    }
+
+    /// Initializes an empty validator set update for epochs with the provided encoding. This
+    /// is used when running the trusted setup.
+    pub fn empty_with_version(
+        num_validators: usize,
+        num_epochs: usize,
+        maximum_non_signers: usize,
+        vk: Option<VerifyingKey<E>>,
+        version: EncodingVersion,
+    ) -> Self {
+        let empty_update =
+            SingleUpdate::empty_with_version(num_validators, maximum_non_signers, version);
         let hash_helper = vk.map(|vk| HashToBitsHelper {
             proof: Proof::<E>::default(),
             verifying_key: vk,
         });
 
         ValidatorSetUpdate {
-            initial_epoch: EpochData::empty(num_validators, maximum_non_signers),
+            initial_epoch: EpochData::empty_with_version(
+                num_validators,
+                maximum_non_signers,
+                version,
+            ),
             num_validators: num_validators as u32,
+            min_validators: 0,
             epochs: vec![empty_update; num_epochs],
             aggregated_signature: None,
             hash_helper,
         }
     }
+
+    /// Requires each epoch to have at least `min_validators` validators which are not
+    /// padding slots
+    pub fn with_min_validators(mut self, min_validators: u32) -> Self {
+        self.min_validators = min_validators;
+        self
+    }
 }
 
 impl ConstraintSynthesizer<Fr> for ValidatorSetUpdate<Bls12_377> {
@@ -104,8 +145,21 @@ impl ValidatorSetUpdate<Bls12_377> {
 
         debug!("converting initial EpochData to_bits");
         // Constrain the initial epoch and get its bits
-        let (first_epoch_bits, first_epoch_index, initial_maximum_non_signers, initial_pubkey_vars) =
-            self.initial_epoch.to_bits(&mut cs.ns(|| "initial epoch"))?;
+        let (
+            first_epoch_bits,
+            first_epoch_index,
+            initial_maximum_non_signers,
+            initial_pubkey_vars,
+            (initial_epoch_entropy, _),
+        ) = self.initial_epoch.to_bits(&mut cs.ns(|| "initial epoch"))?;
+        if self.min_validators > 0 {
+            enforce_min_validators(
+                &mut cs.ns(|| "initial epoch min validators"),
+                &initial_pubkey_vars,
+                self.min_validators,
+                &Boolean::constant(true),
+            )?;
+        }
 
         // Constrain all intermediate epochs, and get the aggregate pubkey and epoch hash
         // from each one, to be used for the batch verification
@@ -116,11 +170,13 @@ impl ValidatorSetUpdate<Bls12_377> {
             xof_bits,
             prepared_aggregated_public_keys,
             prepared_message_hashes,
+            num_epochs,
         ) = self.verify_intermediate_epochs(
             &mut cs.ns(|| "verify epochs"),
             first_epoch_index,
             initial_pubkey_vars,
             initial_maximum_non_signers,
+            initial_epoch_entropy,
         )?;
 
         // Verify the aggregate BLS signature
@@ -136,12 +192,19 @@ impl ValidatorSetUpdate<Bls12_377> {
             last_epoch_bits,
             crh_bits,
             xof_bits,
+            num_epochs,
         })
     }
 
     /// Ensure that all epochs's bitmaps have been correctly computed
     /// and generates the witness data necessary for the final BLS Sig
-    /// verification and witness compression
+    /// verification and witness compression. For epochs with entropy, also ensures that
+    /// each epoch's parent entropy is the entropy of the previous (non-dummy) epoch.
+    ///
+    /// Dummy epochs (with index 0) are no-ops: they do not update the validator set and
+    /// contribute a fixed, trivially valid pair to the batch verification. The number of
+    /// non-dummy epochs is returned, so that the range's length can be exposed as a public
+    /// input and a single verifying key can serve ranges of any length up to the maximum.
     #[allow(clippy::type_complexity)]
     fn verify_intermediate_epochs<CS: ConstraintSystem<Fr>>(
         &self,
@@ -149,6 +212,7 @@ impl ValidatorSetUpdate<Bls12_377> {
         first_epoch_index: FrGadget,
         initial_pubkey_vars: Vec<G2Gadget>,
         initial_max_non_signers: FrGadget,
+        initial_epoch_entropy: Vec<Boolean>,
     ) -> Result<
         (
             Vec<Boolean>,
@@ -156,6 +220,7 @@ impl ValidatorSetUpdate<Bls12_377> {
             Vec<Boolean>,
             Vec<G2PreparedGadget>,
             Vec<G1PreparedGadget>,
+            FrGadget,
         ),
         SynthesisError,
     > {
@@ -177,6 +242,9 @@ impl ValidatorSetUpdate<Bls12_377> {
         let mut previous_epoch_index = first_epoch_index;
         let mut previous_pubkey_vars = initial_pubkey_vars;
         let mut previous_max_non_signers = initial_max_non_signers;
+        let mut previous_epoch_entropy = initial_epoch_entropy;
+        let mut num_epochs_lc = LinearCombination::zero();
+        let mut num_epochs_value = Some(0u64);
         let mut all_crh_bits = vec![];
         let mut all_xof_bits = vec![];
         for (i, epoch) in self.epochs.iter().enumerate() {
@@ -197,6 +265,22 @@ impl ValidatorSetUpdate<Bls12_377> {
             )?
             .not();
 
+            if self.min_validators > 0 {
+                enforce_min_validators(
+                    &mut cs.ns(|| format!("epoch {} min validators", i)),
+                    &constrained_epoch.new_pubkeys,
+                    self.min_validators,
+                    &index_bit,
+                )?;
+            }
+
+            // Count the non-dummy epochs
+            num_epochs_lc = num_epochs_lc + index_bit.lc(CS::one(), Fr::one());
+            num_epochs_value = match (num_epochs_value, index_bit.get_value()) {
+                (Some(num), Some(bit)) => Some(num + bit as u64),
+                _ => None,
+            };
+
             // Update the pubkeys for the next iteration
             previous_epoch_index = FrGadget::conditionally_select(
                 cs.ns(|| format!("conditionally update previous epoch index {}", i)),
@@ -227,6 +311,46 @@ impl ValidatorSetUpdate<Bls12_377> {
                 &previous_max_non_signers,
             )?;
 
+            // Chain the entropy (no-op for epochs without entropy, whose bits are empty)
+            for (j, (parent, previous)) in constrained_epoch
+                .parent_entropy
+                .iter()
+                .zip(&previous_epoch_entropy)
+                .enumerate()
+            {
+                conditional_enforce_equal_with_label(
+                    cs.ns(|| format!("enforce parent entropy bit {} in epoch {}", j, i)),
+                    || {
+                        format!(
+                            "parent entropy bit {} of epoch {} is previous entropy",
+                            j, i
+                        )
+                    },
+                    parent,
+                    previous,
+                    &index_bit,
+                )?;
+            }
+            previous_epoch_entropy = constrained_epoch
+                .epoch_entropy
+                .iter()
+                .zip(&previous_epoch_entropy)
+                .enumerate()
+                .map(|(j, (new, old))| {
+                    Boolean::conditionally_select(
+                        cs.ns(|| {
+                            format!(
+                                "conditionally update previous entropy bit {} in epoch {}",
+                                j, i
+                            )
+                        }),
+                        &index_bit,
+                        new,
+                        old,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
             let aggregate_pk = G2Gadget::conditionally_select(
                 cs.ns(|| format!("conditionally select aggregate pk in epoch {}", i)),
                 &index_bit,
@@ -242,7 +366,7 @@ impl ValidatorSetUpdate<Bls12_377> {
             let message_hash = G1Gadget::conditionally_select(
                 cs.ns(|| format!("conditionally select message hash in epoch {}", i)),
                 &index_bit,
-                &constrained_epoch.message_hash,
+                &constrained_epoch.certificate.message_hash,
                 &dummy_message,
             )?;
 
@@ -269,8 +393,10 @@ impl ValidatorSetUpdate<Bls12_377> {
                 last_epoch_bits.extend_from_slice(&last_apk_bits);
 
                 // make sure the last epoch index is not zero
-                index_bit.enforce_equal(
+                enforce_equal_with_label(
                     cs.ns(|| "last epoch index is not zero"),
+                    || "last epoch index is not zero",
+                    &index_bit,
                     &Boolean::Constant(true),
                 )?;
             }
@@ -279,12 +405,23 @@ impl ValidatorSetUpdate<Bls12_377> {
 
         debug!("intermediate epochs verified");
 
+        let num_epochs = FrGadget::alloc(cs.ns(|| "num epochs"), || {
+            Ok(Fr::from(*num_epochs_value.get()?))
+        })?;
+        cs.enforce(
+            || "num epochs is the number of non-dummy epochs",
+            |_| num_epochs_lc,
+            |lc| lc + (Fr::one(), CS::one()),
+            |lc| num_epochs.get_variable() + lc,
+        );
+
         Ok((
             last_epoch_bits,
             all_crh_bits,
             all_xof_bits,
             prepared_aggregated_public_keys,
             prepared_message_hashes,
+            num_epochs,
         ))
     }
 
@@ -315,7 +452,7 @@ mod tests {
 
     use crate::gadgets::single_update::test_helpers::generate_single_update;
     use algebra::{bls12_377::G1Projective, ProjectiveCurve};
-    use bls_crypto::test_helpers::{keygen_batch, keygen_mul, sign_batch, sum};
+    use bls_crypto::testing::{keygen_batch, keygen_mul, sign_batch, sum};
     use r1cs_std::test_constraint_system::TestConstraintSystem;
 
     type Curve = Bls12_377;
@@ -391,6 +528,7 @@ mod tests {
                 initial_epoch,
                 epochs,
                 num_validators,
+                min_validators: 0,
                 aggregated_signature: Some(aggregated_signature),
                 hash_helper: None,
             };
@@ -481,16 +619,22 @@ mod tests {
                 initial_epoch,
                 epochs,
                 num_validators,
+                min_validators: 0,
                 aggregated_signature: Some(aggregated_signature),
                 hash_helper: None,
             };
 
             let mut cs = TestConstraintSystem::<Fr>::new();
-            valset.enforce(&mut cs).unwrap();
+            let epoch_bits = valset.enforce(&mut cs).unwrap();
             if !cs.is_satisfied() {
                 println!("unsatisfied: {}", cs.which_is_unsatisfied().unwrap());
             }
             assert!(cs.is_satisfied());
+            // the dummy epochs are not counted
+            assert_eq!(epoch_bits.num_epochs.get_value(), Some(Fr::from(4u64)));
+            let path = "verify epochs/num epochs/alloc";
+            cs.set(path, Fr::from(6u64));
+            assert!(!cs.is_satisfied());
         }
 
         #[test]
@@ -574,6 +718,7 @@ mod tests {
                 initial_epoch,
                 epochs,
                 num_validators,
+                min_validators: 0,
                 aggregated_signature: Some(aggregated_signature),
                 hash_helper: None,
             };
@@ -586,4 +731,95 @@ mod tests {
             assert!(cs.is_satisfied());
         }
     }
+
+    mod entropy {
+        use super::*;
+        use crate::{encoding::ENTROPY_BYTES, gadgets::test_helpers::hash_epoch};
+
+        fn with_entropy(
+            mut update: SingleUpdate<Curve>,
+            epoch_entropy: u8,
+            parent_entropy: u8,
+        ) -> SingleUpdate<Curve> {
+            update.epoch_data.version = EncodingVersion::V2;
+            update.epoch_data.epoch_entropy = Some(vec![epoch_entropy; ENTROPY_BYTES]);
+            update.epoch_data.parent_entropy = Some(vec![parent_entropy; ENTROPY_BYTES]);
+            update
+        }
+
+        fn valset(parent_entropy_of_last: u8) -> ValidatorSetUpdate<Curve> {
+            let num_validators = 4;
+            let bitmap = [true; 4];
+            let initial_validator_set = keygen_mul::<Curve>(num_validators);
+            let initial_epoch = with_entropy(
+                generate_single_update::<Curve>(0, 1, &initial_validator_set.1, &[]),
+                1,
+                0,
+            )
+            .epoch_data;
+
+            let validators = keygen_batch::<Curve>(2, num_validators);
+            let epochs = vec![
+                with_entropy(
+                    generate_single_update::<Curve>(1, 1, &validators.1[0], &bitmap),
+                    2,
+                    1,
+                ),
+                with_entropy(
+                    generate_single_update::<Curve>(2, 1, &validators.1[1], &bitmap),
+                    3,
+                    parent_entropy_of_last,
+                ),
+            ];
+
+            let signers = vec![initial_validator_set.0, validators.0[0].clone()];
+            let epoch_hashes = epochs
+                .iter()
+                .map(|update| hash_epoch(&update.epoch_data))
+                .collect::<Vec<G1Projective>>();
+            let asigs = sign_batch::<Bls12_377>(&signers, &epoch_hashes);
+
+            ValidatorSetUpdate::<Curve> {
+                initial_epoch,
+                epochs,
+                num_validators: num_validators as u32,
+                min_validators: 0,
+                aggregated_signature: Some(sum(&asigs)),
+                hash_helper: None,
+            }
+        }
+
+        #[test]
+        fn chained_entropy_ok() {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            valset(2).enforce(&mut cs).unwrap();
+            assert!(cs.is_satisfied());
+        }
+
+        #[test]
+        fn broken_entropy_chain_fails() {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            valset(1).enforce(&mut cs).unwrap();
+            assert!(!cs.is_satisfied());
+            assert!(cs
+                .which_is_unsatisfied()
+                .unwrap()
+                .contains("parent entropy bit"));
+        }
+
+        #[test]
+        fn min_validators_enforced() {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            valset(2).with_min_validators(4).enforce(&mut cs).unwrap();
+            assert!(cs.is_satisfied());
+
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            valset(2).with_min_validators(5).enforce(&mut cs).unwrap();
+            assert!(!cs.is_satisfied());
+            assert!(cs
+                .which_is_unsatisfied()
+                .unwrap()
+                .contains("enough active validators"));
+        }
+    }
 }
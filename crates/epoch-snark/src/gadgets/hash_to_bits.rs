@@ -5,7 +5,7 @@ use algebra::{
 use r1cs_core::{ConstraintSynthesizer, ConstraintSystem, SynthesisError};
 use tracing::{debug, info, span, trace, Level};
 
-use bls_crypto::SIG_DOMAIN;
+use bls_crypto::bls::{BlsScheme, Celo};
 use bls_gadgets::hash_to_bits;
 
 use super::{constrain_bool, MultipackGadget};
@@ -42,7 +42,7 @@ impl ConstraintSynthesizer<Fr> for HashToBits {
         info!("generating constraints");
         let _enter = span.enter();
         let mut personalization = [0; 8];
-        personalization.copy_from_slice(SIG_DOMAIN);
+        personalization.copy_from_slice(Celo::DOMAIN);
 
         let mut all_bits = vec![];
         let mut xof_bits = vec![];
@@ -96,7 +96,7 @@ mod tests {
     // applies the XOF to the input
     fn hash_to_bits_fn(message: &[bool]) -> Vec<bool> {
         let mut personalization = [0; 8];
-        personalization.copy_from_slice(SIG_DOMAIN);
+        personalization.copy_from_slice(Celo::DOMAIN);
         let message = bits_to_bytes(&message);
         let hash_result = DirectHasher.xof(&personalization, &message, 64).unwrap();
         let mut bits = bytes_to_bits(&hash_result, 512);
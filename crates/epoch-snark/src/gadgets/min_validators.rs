@@ -0,0 +1,134 @@
+use algebra::{
+    bls12_377::{G2Projective, Parameters},
+    bw6_761::Fr,
+    Field, One, ProjectiveCurve,
+};
+use bls_gadgets::YToBitGadget;
+use r1cs_core::{ConstraintSystem, LinearCombination, SynthesisError};
+use r1cs_std::{bls12_377::G2Gadget, prelude::*};
+
+/// Enforces that, if `condition` is true, at least `min_validators` of the public keys are
+/// not padding slots. A slot is padding if its key is the G2 generator, the same key which
+/// is used for dummy epochs.
+///
+/// The number of active validators minus the bound is decomposed to as many bits as needed
+/// to represent `pubkeys.len()`, which is only possible if it is not negative.
+pub fn enforce_min_validators<CS: ConstraintSystem<Fr>>(
+    cs: &mut CS,
+    pubkeys: &[G2Gadget],
+    min_validators: u32,
+    condition: &Boolean,
+) -> Result<(), SynthesisError> {
+    let padding = G2Projective::prime_subgroup_generator().into_affine();
+
+    let mut active_lc = LinearCombination::zero();
+    let mut active_value = Some(0u64);
+    for (i, pk) in pubkeys.iter().enumerate() {
+        let coordinates = [
+            (&pk.x.c0, padding.x.c0),
+            (&pk.x.c1, padding.x.c1),
+            (&pk.y.c0, padding.y.c0),
+            (&pk.y.c1, padding.y.c1),
+        ];
+        let mut is_padding = Boolean::constant(true);
+        for (j, (coordinate, expected)) in coordinates.iter().enumerate() {
+            let diff = coordinate.sub_constant(
+                cs.ns(|| format!("pubkey {} coordinate {} difference", i, j)),
+                expected,
+            )?;
+            let is_equal = YToBitGadget::<Parameters>::is_eq_zero(
+                &mut cs.ns(|| format!("pubkey {} coordinate {} is padding", i, j)),
+                &diff,
+            )?;
+            is_padding = Boolean::and(
+                cs.ns(|| format!("pubkey {} coordinates {} are padding", i, j)),
+                &is_padding,
+                &is_equal,
+            )?;
+        }
+
+        let is_active = is_padding.not();
+        active_lc = active_lc + is_active.lc(CS::one(), Fr::one());
+        active_value = match (active_value, is_active.get_value()) {
+            (Some(num), Some(bit)) => Some(num + bit as u64),
+            _ => None,
+        };
+    }
+
+    // If the condition is false or there are too few validators, the bits are set to 0
+    // and the constraint is only satisfied in the former case
+    let num_bits = 64 - (pubkeys.len() as u64).leading_zeros() as usize;
+    let surplus = active_value.map(|active| match condition.get_value() {
+        Some(true) => active.saturating_sub(min_validators as u64),
+        _ => 0,
+    });
+    let surplus_bits = (0..num_bits)
+        .map(|k| {
+            Boolean::alloc(cs.ns(|| format!("surplus bit {}", k)), || {
+                surplus
+                    .map(|surplus| (surplus >> k) & 1 == 1)
+                    .ok_or(SynthesisError::AssignmentMissing)
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut surplus_lc = LinearCombination::zero();
+    let mut coeff = Fr::one();
+    for bit in &surplus_bits {
+        surplus_lc = surplus_lc + bit.lc(CS::one(), coeff);
+        coeff.double_in_place();
+    }
+
+    // condition * (active - min_validators - surplus) == 0
+    cs.enforce(
+        || "enough active validators",
+        |_| active_lc - (Fr::from(min_validators as u64), CS::one()) - surplus_lc,
+        |_| condition.lc(CS::one(), Fr::one()),
+        |lc| lc,
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::UniformRand;
+    use r1cs_std::test_constraint_system::TestConstraintSystem;
+
+    fn alloc_keys(
+        cs: &mut TestConstraintSystem<Fr>,
+        num_active: usize,
+        num_padding: usize,
+    ) -> Vec<G2Gadget> {
+        let rng = &mut rand::thread_rng();
+        (0..num_active)
+            .map(|_| G2Projective::rand(rng))
+            .chain((0..num_padding).map(|_| G2Projective::prime_subgroup_generator()))
+            .enumerate()
+            .map(|(i, pk)| G2Gadget::alloc(cs.ns(|| format!("pk {}", i)), || Ok(pk)).unwrap())
+            .collect()
+    }
+
+    fn check(num_active: usize, num_padding: usize, min_validators: u32, condition: bool) -> bool {
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let pubkeys = alloc_keys(&mut cs, num_active, num_padding);
+        let condition = Boolean::alloc(cs.ns(|| "condition"), || Ok(condition)).unwrap();
+        enforce_min_validators(&mut cs, &pubkeys, min_validators, &condition).unwrap();
+        cs.is_satisfied()
+    }
+
+    #[test]
+    fn counts_active_validators() {
+        assert!(check(3, 2, 3, true));
+        assert!(check(3, 2, 1, true));
+        assert!(check(5, 0, 5, true));
+        assert!(!check(3, 2, 4, true));
+        assert!(!check(1, 4, 2, true));
+    }
+
+    #[test]
+    fn not_enforced_without_condition() {
+        assert!(check(1, 4, 2, false));
+    }
+}
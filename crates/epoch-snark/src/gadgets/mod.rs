@@ -10,9 +10,23 @@ pub use single_update::{ConstrainedEpoch, SingleUpdate};
 mod pack;
 pub use pack::MultipackGadget;
 
+mod public_inputs;
+pub use public_inputs::{
+    decode_public_inputs, encode_public_inputs, hash_edge, num_public_inputs, PublicInputsGadget,
+    EDGE_HASH_BITS,
+};
+
+#[cfg(feature = "insecure-dev-params")]
+mod dev_epochs;
+#[cfg(feature = "insecure-dev-params")]
+pub use dev_epochs::DevEpochs;
+
 mod epoch_bits;
 pub use epoch_bits::EpochBits;
 
+mod min_validators;
+pub use min_validators::enforce_min_validators;
+
 mod epochs;
 pub use epochs::{HashToBitsHelper, ValidatorSetUpdate};
 
@@ -22,7 +36,7 @@ use r1cs_std::prelude::*;
 use r1cs_std::{bls12_377::G2Gadget, fields::fp::FpGadget, Assignment};
 
 type FrGadget = FpGadget<Fr>;
-use bls_gadgets::YToBitGadget;
+use bls_gadgets::{RangeCheck, YToBitGadget};
 
 use r1cs_core::{ConstraintSystem, SynthesisError};
 
@@ -32,7 +46,9 @@ pub mod test_helpers {
     use crate::epoch_block::EpochBlock;
     use algebra::{bls12_377::G1Projective, Bls12_377};
     use bls_crypto::{
-        hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1, PublicKey, SIG_DOMAIN,
+        bls::{BlsScheme, Celo},
+        hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1,
+        PublicKey,
     };
 
     pub fn to_option_iter<T: Copy>(it: &[T]) -> Vec<Option<T>> {
@@ -46,11 +62,15 @@ pub mod test_helpers {
         }
 
         // Calculate the hash from our to_bytes function
-        let epoch_bytes = EpochBlock::new(epoch.index.unwrap(), epoch.maximum_non_signers, pubkeys)
-            .encode_to_bytes()
-            .unwrap();
+        let mut block = EpochBlock::new(epoch.index.unwrap(), epoch.maximum_non_signers, pubkeys);
+        if let (Some(epoch_entropy), Some(parent_entropy)) =
+            (&epoch.epoch_entropy, &epoch.parent_entropy)
+        {
+            block = block.with_entropy(epoch_entropy, parent_entropy);
+        }
+        let epoch_bytes = block.encode_to_bytes().unwrap();
         let (hash, _) = COMPOSITE_HASH_TO_G1
-            .hash_with_attempt(SIG_DOMAIN, &epoch_bytes, &[])
+            .hash_with_attempt(Celo::DOMAIN, &epoch_bytes, &[])
             .unwrap();
 
         hash
@@ -76,14 +96,13 @@ fn to_fr<T: Into<u64>, CS: ConstraintSystem<Fr>>(
     FrGadget::alloc(cs, || Ok(Fr::from(num.get()?.into())))
 }
 
+/// Decomposes the input to `length` LE bits, which enforces that it is smaller than `2^length`
 fn fr_to_bits<CS: ConstraintSystem<Fr>>(
     cs: &mut CS,
     input: &FrGadget,
     length: usize,
 ) -> Result<Vec<Boolean>, SynthesisError> {
-    let mut input = input.to_bits(cs.ns(|| "input to bits"))?;
-    input.reverse();
-    Ok(input[0..length].to_vec())
+    Ok(RangeCheck::new(&mut cs.ns(|| "input to bits"), input, length)?.into_bits())
 }
 
 fn g2_to_bits<CS: ConstraintSystem<Fr>>(
@@ -1,5 +1,5 @@
 use algebra::{BigInteger, FpParameters, PrimeField};
-use bls_gadgets::utils::is_setup;
+use bls_gadgets::{enforce_equal_with_label, utils::is_setup};
 use r1cs_core::SynthesisError;
 use r1cs_std::{fields::fp::FpGadget, prelude::*, Assignment};
 use tracing::{span, trace, Level};
@@ -43,8 +43,12 @@ impl MultipackGadget {
             let fp_bits = fp.to_bits(cs.ns(|| format!("chunk bits {}", i)))?;
             let chunk_len = chunk.len();
             for j in 0..chunk_len {
-                fp_bits[F::Params::MODULUS_BITS as usize - chunk_len + j]
-                    .enforce_equal(cs.ns(|| format!("fp bit {} for chunk {}", j, i)), &chunk[j])?;
+                enforce_equal_with_label(
+                    cs.ns(|| format!("fp bit {} for chunk {}", j, i)),
+                    || format!("packed bit {} of chunk {} matches input", j, i),
+                    &fp_bits[F::Params::MODULUS_BITS as usize - chunk_len + j],
+                    &chunk[j],
+                )?;
             }
 
             packed.push(fp);
@@ -0,0 +1,305 @@
+//! The encoding of the first and last epoch into the public inputs of the epochs circuit,
+//! shared by the circuit and the verifier so that both always agree on it.
+//!
+//! The bits of each edge epoch are read as the LE bits of bytes, the last byte being padded
+//! with zeros, and hashed with Blake2s personalized to `OUT_DOMAIN`. The two hashes, first
+//! epoch first, are split in chunks of `Fr::CAPACITY` bits, each packed in a field element
+//! with the first bit of the chunk as its most significant bit. The number of epochs is the
+//! last public input.
+//!
+//! `encode_public_inputs` computes natively what `PublicInputsGadget::enforce` allocates.
+use algebra::{
+    bw6_761::{Fr, FrParameters},
+    BigInteger, FpParameters, PrimeField,
+};
+use bls_crypto::OUT_DOMAIN;
+use bls_gadgets::{enforce_equal_with_label, utils::bits_to_bytes};
+use crypto_primitives::prf::blake2s::{
+    constraints::blake2s_gadget_with_parameters, Blake2sWithParameterBlock,
+};
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::{fields::fp::FpGadget, prelude::*, Assignment};
+
+use super::{pack, MultipackGadget};
+use crate::epoch_block::hash_to_bits;
+
+type FrGadget = FpGadget<Fr>;
+
+/// The number of bits of the hash of an edge epoch
+pub const EDGE_HASH_BITS: usize = 256;
+
+/// The number of public inputs of the epochs circuit: the packed hashes of the two edge
+/// epochs, and the number of epochs
+pub fn num_public_inputs() -> usize {
+    let capacity = FrParameters::CAPACITY as usize;
+    (2 * EDGE_HASH_BITS + capacity - 1) / capacity + 1
+}
+
+/// Hashes the bits of an edge epoch, as encoded by `EpochBlock::encode_to_bits`
+pub fn hash_edge(epoch_bits: &[bool]) -> Vec<bool> {
+    hash_to_bits(&bits_to_bytes(epoch_bits))
+}
+
+/// Packs the hashes of the first and last epoch, followed by the number of epochs, into
+/// the public inputs. Fails if a hash does not have `EDGE_HASH_BITS` bits.
+pub fn encode_public_inputs(
+    first_hash: &[bool],
+    last_hash: &[bool],
+    num_epochs: u64,
+) -> Result<Vec<Fr>, SynthesisError> {
+    if first_hash.len() != EDGE_HASH_BITS || last_hash.len() != EDGE_HASH_BITS {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    let mut inputs = pack::<Fr, FrParameters>(&[first_hash, last_hash].concat())?;
+    inputs.push(Fr::from(num_epochs));
+    Ok(inputs)
+}
+
+/// Recovers the hashes of the first and last epoch and the number of epochs from the public
+/// inputs. Returns `None` if the inputs are not an encoding of `encode_public_inputs`.
+pub fn decode_public_inputs(inputs: &[Fr]) -> Option<(Vec<bool>, Vec<bool>, u64)> {
+    let (num_epochs, packed) = inputs.split_last()?;
+    if inputs.len() != num_public_inputs() {
+        return None;
+    }
+    let capacity = FrParameters::CAPACITY as usize;
+    let mut bits = Vec::with_capacity(2 * EDGE_HASH_BITS);
+    for (i, element) in packed.iter().enumerate() {
+        let chunk_len = capacity.min(2 * EDGE_HASH_BITS - i * capacity);
+        // BE bits, padded with leading zeros to the size of the integer
+        let element_bits = element.into_repr().to_bits();
+        let (padding, chunk) = element_bits.split_at(element_bits.len() - chunk_len);
+        if padding.iter().any(|bit| *bit) {
+            return None;
+        }
+        bits.extend_from_slice(chunk);
+    }
+
+    let num_epochs = num_epochs.into_repr();
+    if num_epochs.as_ref()[1..].iter().any(|limb| *limb != 0) {
+        return None;
+    }
+    let last_hash = bits.split_off(EDGE_HASH_BITS);
+    Some((bits, last_hash, num_epochs.as_ref()[0]))
+}
+
+/// Gadget which enforces the public inputs of the epochs circuit
+pub struct PublicInputsGadget;
+
+impl PublicInputsGadget {
+    /// Hashes the edge epochs and allocates the public inputs, in the order of
+    /// `encode_public_inputs`
+    pub fn enforce<CS: ConstraintSystem<Fr>>(
+        cs: &mut CS,
+        first_epoch_bits: &[Boolean],
+        last_epoch_bits: &[Boolean],
+        num_epochs: &FrGadget,
+    ) -> Result<Vec<FrGadget>, SynthesisError> {
+        let mut hashes = Self::hash_edge(&mut cs.ns(|| "first epoch hash"), first_epoch_bits)?;
+        hashes.extend(Self::hash_edge(
+            &mut cs.ns(|| "last epoch hash"),
+            last_epoch_bits,
+        )?);
+
+        // packed over BW6_761 Fr
+        let mut inputs = MultipackGadget::pack(
+            cs.ns(|| "pack output hash"),
+            &hashes,
+            FrParameters::CAPACITY as usize,
+            true,
+        )?;
+
+        let num_epochs_input = FrGadget::alloc_input(cs.ns(|| "num epochs input"), || {
+            Ok(*num_epochs.get_value().get()?)
+        })?;
+        enforce_equal_with_label(
+            cs.ns(|| "num epochs input equals counted epochs"),
+            || "num epochs public input",
+            &num_epochs_input,
+            num_epochs,
+        )?;
+        inputs.push(num_epochs_input);
+        Ok(inputs)
+    }
+
+    /// Hashes the bits of an edge epoch, same as `hash_edge`
+    pub fn hash_edge<CS: ConstraintSystem<Fr>>(
+        cs: &mut CS,
+        epoch_bits: &[Boolean],
+    ) -> Result<Vec<Boolean>, SynthesisError> {
+        let mut message = epoch_bits.to_owned();
+        message.reverse();
+        let message_rounded_len = 8 * ((message.len() + 7) / 8);
+        message.resize(message_rounded_len, Boolean::constant(false));
+
+        let mut personalization = [0; 8];
+        personalization.copy_from_slice(OUT_DOMAIN);
+
+        let blake2s_parameters = Blake2sWithParameterBlock {
+            digest_length: 32,
+            key_length: 0,
+            fan_out: 1,
+            depth: 1,
+            leaf_length: 0,
+            node_offset: 0,
+            xof_digest_length: 0,
+            node_depth: 0,
+            inner_length: 0,
+            salt: [0; 8],
+            personalization,
+        };
+        let xof_result = blake2s_gadget_with_parameters(
+            cs.ns(|| "xof result"),
+            &message,
+            &blake2s_parameters.parameters(),
+        )?;
+        Ok(xof_result
+            .into_iter()
+            .map(|n| n.to_bits_le())
+            .flatten()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epoch_block::hash_first_last_epoch_block;
+    use bls_gadgets::utils::bytes_to_bits;
+    use r1cs_std::test_constraint_system::TestConstraintSystem;
+    use rand::{Rng, RngCore};
+
+    fn to_bool(bits: &[bool]) -> Vec<Boolean> {
+        bits.iter().map(|b| Boolean::constant(*b)).collect()
+    }
+
+    fn random_bits(len: usize) -> Vec<bool> {
+        let rng = &mut rand::thread_rng();
+        (0..len).map(|_| rng.gen()).collect()
+    }
+
+    #[test]
+    fn counts_public_inputs() {
+        let inputs = encode_public_inputs(&[false; 256], &[true; 256], 3).unwrap();
+        assert_eq!(inputs.len(), num_public_inputs());
+        assert_eq!(num_public_inputs(), 3);
+    }
+
+    #[test]
+    fn round_trips_natively() {
+        let rng = &mut rand::thread_rng();
+        let edges = vec![
+            (vec![false; EDGE_HASH_BITS], vec![false; EDGE_HASH_BITS]),
+            (vec![true; EDGE_HASH_BITS], vec![true; EDGE_HASH_BITS]),
+            (vec![true; EDGE_HASH_BITS], vec![false; EDGE_HASH_BITS]),
+            (random_bits(EDGE_HASH_BITS), random_bits(EDGE_HASH_BITS)),
+        ];
+        for (first, last) in edges {
+            // flipping each bit in turn catches any reordering of the bits
+            for position in 0..2 * EDGE_HASH_BITS {
+                let mut bits = [first.clone(), last.clone()].concat();
+                bits[position] = !bits[position];
+                let num_epochs = rng.gen::<u16>() as u64;
+                let inputs = encode_public_inputs(
+                    &bits[..EDGE_HASH_BITS],
+                    &bits[EDGE_HASH_BITS..],
+                    num_epochs,
+                )
+                .unwrap();
+                let (decoded_first, decoded_last, decoded_num_epochs) =
+                    decode_public_inputs(&inputs).unwrap();
+                assert_eq!([decoded_first, decoded_last].concat(), bits);
+                assert_eq!(decoded_num_epochs, num_epochs);
+            }
+        }
+
+        assert!(encode_public_inputs(&[false; 255], &[false; 256], 1).is_err());
+        let inputs = encode_public_inputs(&[true; 256], &[true; 256], 1).unwrap();
+        assert!(decode_public_inputs(&inputs[1..]).is_none());
+        assert!(decode_public_inputs(&[]).is_none());
+        // a chunk with bits beyond its length
+        let mut overflowing = inputs.clone();
+        overflowing[1] = -Fr::from(1u64);
+        assert!(decode_public_inputs(&overflowing).is_none());
+    }
+
+    #[test]
+    fn gadget_matches_native_encoding() {
+        // every length of the last byte's padding, and the length of a full hash
+        for len in (1..=16).chain(vec![EDGE_HASH_BITS]) {
+            let first_epoch = random_bits(len);
+            let last_epoch = random_bits(len + 3);
+            let num_epochs = 7u64;
+
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            let counted =
+                FrGadget::alloc(cs.ns(|| "num epochs"), || Ok(Fr::from(num_epochs))).unwrap();
+            let inputs = PublicInputsGadget::enforce(
+                &mut cs,
+                &to_bool(&first_epoch),
+                &to_bool(&last_epoch),
+                &counted,
+            )
+            .unwrap();
+            assert!(cs.is_satisfied());
+
+            let expected = encode_public_inputs(
+                &hash_edge(&first_epoch),
+                &hash_edge(&last_epoch),
+                num_epochs,
+            )
+            .unwrap();
+            let inputs = inputs
+                .into_iter()
+                .map(|input| input.get_value().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(inputs, expected, "epochs of {} bits", len);
+        }
+    }
+
+    #[test]
+    fn correct_blake2_hash() {
+        let rng = &mut rand::thread_rng();
+        let mut first_bytes = vec![0; 32];
+        rng.fill_bytes(&mut first_bytes);
+        let mut last_bytes = vec![0; 32];
+        rng.fill_bytes(&mut last_bytes);
+
+        // encode each epoch's bytes to LE and pass them to the constraint system
+        let first_epoch_bits = bytes_to_bits(&first_bytes, 256);
+        let last_epoch_bits = bytes_to_bits(&last_bytes, 256);
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let hash = PublicInputsGadget::hash_edge(&mut cs, &to_bool(&first_epoch_bits)).unwrap();
+        assert!(cs.is_satisfied());
+        let hash = hash
+            .into_iter()
+            .map(|bit| bit.get_value().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(hash, hash_to_bits(&first_bytes));
+        assert_eq!(hash_edge(&first_epoch_bits), hash_to_bits(&first_bytes));
+        assert_eq!(hash_edge(&last_epoch_bits), hash_to_bits(&last_bytes));
+    }
+
+    #[test]
+    fn matches_verifier_hashes() {
+        use crate::epoch_block::EpochBlock;
+        use algebra::{bls12_377::G2Projective, UniformRand};
+        use bls_crypto::PublicKey;
+
+        let rng = &mut rand::thread_rng();
+        let keys = (0..3)
+            .map(|_| PublicKey::from(G2Projective::rand(rng)))
+            .collect::<Vec<_>>();
+        let first = EpochBlock::new(1, 1, keys.clone());
+        let last = EpochBlock::new(2, 1, keys);
+        let hashes = hash_first_last_epoch_block(&first, &last).unwrap();
+        assert_eq!(
+            hashes[..EDGE_HASH_BITS],
+            hash_edge(&first.encode_to_bits().unwrap())[..]
+        );
+        assert_eq!(
+            hashes[EDGE_HASH_BITS..],
+            hash_edge(&last.encode_to_bits_with_aggregated_pk().unwrap())[..]
+        );
+    }
+}
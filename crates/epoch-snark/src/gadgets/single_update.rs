@@ -7,7 +7,8 @@ use r1cs_std::{
 };
 
 use super::{constrain_bool, EpochData};
-use bls_gadgets::BlsVerifyGadget;
+use crate::encoding::EncodingVersion;
+use bls_gadgets::{BlsVerifyGadget, QuorumCertificateGadget};
 use tracing::{span, Level};
 
 // Instantiate the BLS Verification gadget
@@ -27,8 +28,22 @@ pub struct SingleUpdate<E: PairingEngine> {
 impl<E: PairingEngine> SingleUpdate<E> {
     /// Returns an empty update. This function is used when running the trusted setup.
     pub fn empty(num_validators: usize, maximum_non_signers: usize) -> Self {
+        Self::empty_with_version(num_validators, maximum_non_signers, EncodingVersion::V1)
+    }
+
+    /// Returns an empty update with the provided encoding. This function is used when running
+    /// the trusted setup.
+    pub fn empty_with_version(
+        num_validators: usize,
+        maximum_non_signers: usize,
+        version: EncodingVersion,
+    ) -> Self {
         Self {
-            epoch_data: EpochData::<E>::empty(num_validators, maximum_non_signers),
+            epoch_data: EpochData::<E>::empty_with_version(
+                num_validators,
+                maximum_non_signers,
+                version,
+            ),
             signed_bitmap: vec![None; num_validators],
         }
     }
@@ -43,13 +58,18 @@ pub struct ConstrainedEpoch {
     pub new_pubkeys: Vec<G2Gadget>,
     /// The new threshold needed for signatures
     pub new_max_non_signers: FrGadget,
-    /// The epoch's G1 Hash
-    pub message_hash: G1Gadget,
+    /// The seal of the epoch: its G1 Hash and the bitmap of the validators of the previous
+    /// epoch which signed it
+    pub certificate: QuorumCertificateGadget<G1Gadget>,
     /// The aggregate pubkey based on the bitmap of the validators
     /// of the previous epoch
     pub aggregate_pk: G2Gadget,
     /// The epoch's index
     pub index: FrGadget,
+    /// The epoch's entropy bits, empty unless the epoch uses `EncodingVersion::V2`
+    pub epoch_entropy: Vec<Boolean>,
+    /// The parent epoch's entropy bits, empty unless the epoch uses `EncodingVersion::V2`
+    pub parent_entropy: Vec<Boolean>,
     /// Serialized epoch data containing the index, max non signers, aggregated pubkey and the pubkeys array
     pub bits: Vec<Boolean>,
     /// Aux data for proving the CRH->XOF hash outside of BW6_761
@@ -87,24 +107,28 @@ impl SingleUpdate<Bls12_377> {
         )?;
 
         // convert the bitmap to constraints
-        let signed_bitmap = constrain_bool(&mut cs.ns(|| "signed bitmap"), &self.signed_bitmap)?;
+        let certificate = QuorumCertificateGadget {
+            bitmap: constrain_bool(&mut cs.ns(|| "signed bitmap"), &self.signed_bitmap)?,
+            message_hash: epoch_data.message_hash,
+        };
 
         // Verify that the bitmap is consistent with the pubkeys read from the
-        // previous epoch and prepare the message hash and the aggregate pk
-        let (message_hash, aggregated_public_key) = BlsGadget::enforce_bitmap(
+        // previous epoch and prepare the aggregate pk
+        let aggregated_public_key = BlsGadget::enforce_bitmap(
             cs.ns(|| "verify signature partial"),
             previous_pubkeys,
-            &signed_bitmap,
-            &epoch_data.message_hash,
+            &certificate,
             &previous_max_non_signers,
         )?;
 
         Ok(ConstrainedEpoch {
             new_pubkeys: epoch_data.pubkeys,
             new_max_non_signers: epoch_data.maximum_non_signers,
-            message_hash,
+            certificate,
             aggregate_pk: aggregated_public_key,
             index: epoch_data.index,
+            epoch_entropy: epoch_data.epoch_entropy,
+            parent_entropy: epoch_data.parent_entropy,
             bits: epoch_data.bits,
             xof_bits: epoch_data.xof_bits,
             crh_bits: epoch_data.crh_bits,
@@ -128,6 +152,9 @@ pub mod test_helpers {
             index: Some(index),
             maximum_non_signers,
             public_keys: to_option_iter(public_keys),
+            version: EncodingVersion::V1,
+            epoch_entropy: None,
+            parent_entropy: None,
         };
 
         SingleUpdate::<E> {
@@ -145,6 +172,9 @@ pub mod test_helpers {
             index: Some(0),
             maximum_non_signers: 0u32,
             public_keys: to_option_iter(public_keys.as_slice()),
+            version: EncodingVersion::V1,
+            epoch_entropy: None,
+            parent_entropy: None,
         };
 
         SingleUpdate::<E> {
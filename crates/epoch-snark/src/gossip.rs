@@ -0,0 +1,274 @@
+//! Canonical encoding of the epoch attestations gossiped between services.
+//!
+//! An attestation is an epoch block along with the aggregate signature of the previous
+//! epoch's validators and the bitmap of the validators which signed, i.e. an
+//! `EpochTransition`, optionally with the try-and-increment counter of the block's hash to
+//! G1. The encoding is canonical: every attestation has exactly one encoding and any other
+//! byte string is rejected, so encodings can be compared and hashed directly.
+//!
+//! | Field              | Length          | Contents                                      |
+//! |--------------------|-----------------|-----------------------------------------------|
+//! | magic              | 8               | `ULgossip`                                    |
+//! | version            | 1               | `PACKET_VERSION`                              |
+//! | flags              | 1               | bit 0 is set if a hash hint is present        |
+//! | epoch length       | 4               | LE                                            |
+//! | epoch              | epoch length    | index, max non-signers, keys and entropies    |
+//! | bitmap length      | 2               | number of bits, LE                            |
+//! | bitmap             | (length + 7) / 8| LSB first, the unused bits must be unset      |
+//! | signature          | 48              | compressed G1 point                           |
+//! | hash hint          | 4 or 0          | LE                                            |
+use crate::{
+    encoding::EncodingError,
+    epoch_block::{decode_epoch, encode_epoch, EpochTransition},
+};
+use algebra::serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use bls_crypto::Signature;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+use thiserror::Error;
+
+/// Magic bytes at the start of every packet
+const MAGIC: &[u8; 8] = b"ULgossip";
+
+/// The version of the packet encoding
+pub const PACKET_VERSION: u8 = 1;
+
+/// The maximum length of an encoded packet, which bounds the memory used to decode one
+pub const MAX_PACKET_LENGTH: usize = 1 << 17;
+
+const FLAG_HASH_HINT: u8 = 1;
+
+#[derive(Debug, Error)]
+/// Error raised while encoding or decoding a gossip packet
+pub enum GossipError {
+    #[error("I/O Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Zexe Error: {0}")]
+    ZexeSerialization(#[from] SerializationError),
+    #[error("Encoding Error: {0}")]
+    EpochEncodingError(#[from] EncodingError),
+    #[error("Malformed packet: {0}")]
+    Malformed(&'static str),
+    #[error("Unsupported packet version {0}")]
+    UnsupportedVersion(u8),
+    #[error("The packet has length {0}, which is too large")]
+    TooLarge(usize),
+}
+
+/// An epoch transition as gossiped between services
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpochAttestation {
+    /// The epoch block, its aggregate signature and bitmap
+    pub transition: EpochTransition,
+    /// The try-and-increment counter returned when hashing the block to G1 with the
+    /// composite hasher, if known. This is only an optimization for the receiver and must
+    /// not be trusted, see `TryAndIncrement::hash_from_attempt`.
+    pub hash_hint: Option<u32>,
+}
+
+impl From<EpochTransition> for EpochAttestation {
+    fn from(transition: EpochTransition) -> Self {
+        Self {
+            transition,
+            hash_hint: None,
+        }
+    }
+}
+
+impl EpochAttestation {
+    /// Sets the hash hint of the attestation
+    pub fn with_hash_hint(mut self, hash_hint: u32) -> Self {
+        self.hash_hint = Some(hash_hint);
+        self
+    }
+
+    /// Encodes the attestation to its canonical packet
+    pub fn encode(&self) -> Result<Vec<u8>, GossipError> {
+        let transition = &self.transition;
+        let mut bytes = MAGIC.to_vec();
+        bytes.write_u8(PACKET_VERSION)?;
+        bytes.write_u8(if self.hash_hint.is_some() {
+            FLAG_HASH_HINT
+        } else {
+            0
+        })?;
+
+        let epoch = encode_epoch(&transition.block)?;
+        bytes.write_u32::<LittleEndian>(epoch.len() as u32)?;
+        bytes.write_all(&epoch)?;
+
+        if transition.bitmap.len() > u16::max_value() as usize {
+            return Err(GossipError::Malformed("bitmap too long"));
+        }
+        bytes.write_u16::<LittleEndian>(transition.bitmap.len() as u16)?;
+        bytes.write_all(&pack_bitmap(&transition.bitmap))?;
+
+        transition.aggregate_signature.serialize(&mut bytes)?;
+        if let Some(hash_hint) = self.hash_hint {
+            bytes.write_u32::<LittleEndian>(hash_hint)?;
+        }
+
+        if bytes.len() > MAX_PACKET_LENGTH {
+            return Err(GossipError::TooLarge(bytes.len()));
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a packet, rejecting any encoding which is not canonical
+    pub fn decode(bytes: &[u8]) -> Result<Self, GossipError> {
+        if bytes.len() > MAX_PACKET_LENGTH {
+            return Err(GossipError::TooLarge(bytes.len()));
+        }
+        let reader = &mut &bytes[..];
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(GossipError::Malformed("not a gossip packet"));
+        }
+        let version = reader.read_u8()?;
+        if version != PACKET_VERSION {
+            return Err(GossipError::UnsupportedVersion(version));
+        }
+        let flags = reader.read_u8()?;
+        if flags & !FLAG_HASH_HINT != 0 {
+            return Err(GossipError::Malformed("unknown flags"));
+        }
+
+        let epoch_length = reader.read_u32::<LittleEndian>()? as usize;
+        if epoch_length > reader.len() {
+            return Err(GossipError::Malformed("truncated epoch"));
+        }
+        let (epoch, rest) = reader.split_at(epoch_length);
+        *reader = rest;
+        let block = decode_epoch(epoch)?;
+
+        let bitmap_length = reader.read_u16::<LittleEndian>()? as usize;
+        let mut packed = vec![0u8; (bitmap_length + 7) / 8];
+        reader.read_exact(&mut packed)?;
+        let bitmap = unpack_bitmap(&packed, bitmap_length)?;
+
+        let aggregate_signature = Signature::deserialize(&mut *reader)?;
+        let hash_hint = if flags & FLAG_HASH_HINT != 0 {
+            Some(reader.read_u32::<LittleEndian>()?)
+        } else {
+            None
+        };
+        if !reader.is_empty() {
+            return Err(GossipError::Malformed("trailing bytes"));
+        }
+
+        Ok(Self {
+            transition: EpochTransition {
+                block,
+                aggregate_signature,
+                bitmap,
+            },
+            hash_hint,
+        })
+    }
+}
+
+fn pack_bitmap(bitmap: &[bool]) -> Vec<u8> {
+    bitmap
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, bit)| byte | ((*bit as u8) << i))
+        })
+        .collect()
+}
+
+fn unpack_bitmap(packed: &[u8], length: usize) -> Result<Vec<bool>, GossipError> {
+    let bitmap = (0..length)
+        .map(|i| packed[i / 8] & (1 << (i % 8)) != 0)
+        .collect::<Vec<_>>();
+    if pack_bitmap(&bitmap) != packed {
+        return Err(GossipError::Malformed("unused bitmap bits are set"));
+    }
+    Ok(bitmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{epoch_block::EpochBlock, ENTROPY_BYTES};
+    use algebra::{
+        bls12_377::{G1Projective, G2Projective},
+        UniformRand,
+    };
+    use bls_crypto::PublicKey;
+
+    fn attestation(num_keys: usize, bitmap_length: usize) -> EpochAttestation {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..num_keys)
+            .map(|_| PublicKey::from(G2Projective::rand(rng)))
+            .collect::<Vec<_>>();
+        EpochAttestation::from(EpochTransition {
+            block: EpochBlock::new(7, 2, keys),
+            aggregate_signature: Signature::from(G1Projective::rand(rng)),
+            bitmap: (0..bitmap_length).map(|i| i % 3 != 0).collect(),
+        })
+    }
+
+    #[test]
+    fn roundtrip() {
+        let plain = attestation(4, 11);
+        let bytes = plain.encode().unwrap();
+        assert_eq!(EpochAttestation::decode(&bytes).unwrap(), plain);
+
+        let mut with_entropy = attestation(3, 16).with_hash_hint(5);
+        with_entropy.transition.block = with_entropy
+            .transition
+            .block
+            .with_entropy(&[1; ENTROPY_BYTES], &[2; ENTROPY_BYTES]);
+        let bytes = with_entropy.encode().unwrap();
+        assert_eq!(EpochAttestation::decode(&bytes).unwrap(), with_entropy);
+        // the encoding is canonical
+        let decoded = EpochAttestation::decode(&bytes).unwrap();
+        assert_eq!(decoded.encode().unwrap(), bytes);
+    }
+
+    #[test]
+    fn rejects_non_canonical_packets() {
+        let bytes = attestation(2, 5).encode().unwrap();
+        let epoch_length = u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]);
+        let bitmap_offset = 8 + 2 + 4 + epoch_length as usize + 2;
+
+        let mut padding = bytes.clone();
+        padding[bitmap_offset] |= 0x80;
+        match EpochAttestation::decode(&padding) {
+            Err(GossipError::Malformed(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut flags = bytes.clone();
+        flags[9] |= 2;
+        EpochAttestation::decode(&flags).unwrap_err();
+
+        let mut version = bytes.clone();
+        version[8] = PACKET_VERSION + 1;
+        match EpochAttestation::decode(&version) {
+            Err(GossipError::UnsupportedVersion(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let trailing = [&bytes[..], &[0]].concat();
+        EpochAttestation::decode(&trailing).unwrap_err();
+        EpochAttestation::decode(&bytes[..bytes.len() - 1]).unwrap_err();
+    }
+
+    #[test]
+    fn enforces_size_limits() {
+        let oversized = attestation(MAX_PACKET_LENGTH / 96 + 1, 0);
+        match oversized.encode() {
+            Err(GossipError::TooLarge(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match EpochAttestation::decode(&vec![0; MAX_PACKET_LENGTH + 1]) {
+            Err(GossipError::TooLarge(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
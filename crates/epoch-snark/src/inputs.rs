@@ -0,0 +1,400 @@
+//! The JSON format of the prover's inputs.
+//!
+//! The inputs of `prove` are the initial epoch and the transitions which follow it, along
+//! with the shape of the parameters. Tooling which fetches the epochs from the chain is not
+//! necessarily written in Rust, so the inputs are exchanged as JSON documents following the
+//! versioned schema in `INPUTS_SCHEMA`:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "num_validators": 2,
+//!   "max_transitions": 4,
+//!   "initial_epoch": {
+//!     "index": 10,
+//!     "maximum_non_signers": 0,
+//!     "encoding_version": 1,
+//!     "public_keys": ["<192 hex chars>", "<192 hex chars>"]
+//!   },
+//!   "transitions": [
+//!     {
+//!       "block": { "index": 11, "...": "..." },
+//!       "bitmap": [true, true],
+//!       "aggregate_signature": "<96 hex chars>"
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Points are encoded in the compressed format of celo-blockchain, see
+//! `PublicKey::to_celo_bytes`. Decoding a document checks everything which can be checked
+//! without the parameters: the points are on their curves and in their subgroups, every
+//! epoch has `num_validators` keys and bitmaps, the entropies match the encoding version and
+//! the transitions follow the initial epoch, so that malformed inputs are reported with the
+//! path of the offending field before any proving work.
+use crate::{
+    encoding::{EncodingVersion, ENTROPY_BYTES},
+    epoch_block::{EpochBlock, EpochTransition},
+    epoch_index::{EpochIndex, EpochIndexError},
+};
+use bls_crypto::{BLSError, PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The version of the inputs format
+pub const INPUTS_VERSION: u32 = 1;
+
+/// The JSON Schema of the current version of the inputs format
+pub const INPUTS_SCHEMA: &str = include_str!("../schema/prover-inputs-v1.json");
+
+#[derive(Debug, Error)]
+/// Error raised while decoding prover inputs
+pub enum InputsError {
+    #[error("JSON Error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error(
+        "Inputs have version {0}, but only version {} is supported",
+        INPUTS_VERSION
+    )]
+    UnsupportedVersion(u32),
+    #[error("{0} is not valid hex")]
+    InvalidHex(String),
+    #[error("{field} is not a valid point: {source}")]
+    InvalidPoint {
+        field: String,
+        #[source]
+        source: BLSError,
+    },
+    #[error("{field} has length {got}, expected {expected}")]
+    LengthMismatch {
+        field: String,
+        got: usize,
+        expected: usize,
+    },
+    #[error("{0} is not a supported encoding version")]
+    UnsupportedEncoding(u8),
+    #[error("{0} must be set if and only if the encoding version is 2")]
+    EntropyMismatch(String),
+    #[error("Epoch Index Error: {0}")]
+    EpochIndexError(#[from] EpochIndexError),
+}
+
+/// The inputs of `prove`, as exchanged in JSON
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProverInputs {
+    pub version: u32,
+    pub num_validators: u32,
+    pub max_transitions: usize,
+    pub initial_epoch: EpochJson,
+    pub transitions: Vec<TransitionJson>,
+}
+
+/// An `EpochBlock` as exchanged in JSON
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EpochJson {
+    pub index: u16,
+    pub maximum_non_signers: u32,
+    pub encoding_version: u8,
+    /// The hex encoded compressed public keys
+    pub public_keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch_entropy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_entropy: Option<String>,
+}
+
+/// An `EpochTransition` as exchanged in JSON
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransitionJson {
+    pub block: EpochJson,
+    pub bitmap: Vec<bool>,
+    /// The hex encoded compressed aggregate signature
+    pub aggregate_signature: String,
+}
+
+/// The decoded and validated inputs of `prove`
+#[derive(Clone, Debug)]
+pub struct DecodedInputs {
+    pub num_validators: u32,
+    pub max_transitions: usize,
+    pub initial_epoch: EpochBlock,
+    pub transitions: Vec<EpochTransition>,
+}
+
+impl ProverInputs {
+    /// Creates the inputs proving the transitions from the initial epoch
+    pub fn new(
+        num_validators: u32,
+        max_transitions: usize,
+        initial_epoch: &EpochBlock,
+        transitions: &[EpochTransition],
+    ) -> Self {
+        Self {
+            version: INPUTS_VERSION,
+            num_validators,
+            max_transitions,
+            initial_epoch: EpochJson::from(initial_epoch),
+            transitions: transitions
+                .iter()
+                .map(|transition| TransitionJson {
+                    block: EpochJson::from(&transition.block),
+                    bitmap: transition.bitmap.clone(),
+                    aggregate_signature: hex::encode(
+                        transition.aggregate_signature.to_celo_bytes(),
+                    ),
+                })
+                .collect(),
+        }
+    }
+
+    /// Parses and decodes the inputs from a JSON document
+    pub fn load(json: &str) -> Result<DecodedInputs, InputsError> {
+        serde_json::from_str::<Self>(json)?.decode()
+    }
+
+    /// Encodes the inputs to a JSON document
+    pub fn to_json(&self) -> Result<String, InputsError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Decodes and validates the inputs
+    pub fn decode(&self) -> Result<DecodedInputs, InputsError> {
+        if self.version != INPUTS_VERSION {
+            return Err(InputsError::UnsupportedVersion(self.version));
+        }
+        let num_validators = self.num_validators as usize;
+        let initial_epoch = self.initial_epoch.decode("initial_epoch", num_validators)?;
+        let transitions = self
+            .transitions
+            .iter()
+            .enumerate()
+            .map(|(i, transition)| {
+                let path = format!("transitions[{}]", i);
+                let block = transition
+                    .block
+                    .decode(&format!("{}.block", path), num_validators)?;
+                check_length(
+                    format!("{}.bitmap", path),
+                    transition.bitmap.len(),
+                    num_validators,
+                )?;
+                let field = format!("{}.aggregate_signature", path);
+                let bytes = decode_hex(&field, &transition.aggregate_signature)?;
+                let aggregate_signature = Signature::from_celo_bytes(&bytes)
+                    .map_err(|source| InputsError::InvalidPoint { field, source })?;
+                Ok(EpochTransition {
+                    block,
+                    aggregate_signature,
+                    bitmap: transition.bitmap.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, InputsError>>()?;
+        EpochIndex::check_transitions(&initial_epoch, &transitions, self.max_transitions)?;
+
+        Ok(DecodedInputs {
+            num_validators: self.num_validators,
+            max_transitions: self.max_transitions,
+            initial_epoch,
+            transitions,
+        })
+    }
+}
+
+impl From<&EpochBlock> for EpochJson {
+    fn from(block: &EpochBlock) -> Self {
+        Self {
+            index: block.index,
+            maximum_non_signers: block.maximum_non_signers,
+            encoding_version: match block.version {
+                EncodingVersion::V1 => 1,
+                EncodingVersion::V2 => 2,
+            },
+            public_keys: block
+                .new_public_keys
+                .iter()
+                .map(|key| hex::encode(key.to_celo_bytes()))
+                .collect(),
+            epoch_entropy: block.epoch_entropy.as_ref().map(hex::encode),
+            parent_entropy: block.parent_entropy.as_ref().map(hex::encode),
+        }
+    }
+}
+
+impl EpochJson {
+    fn decode(&self, path: &str, num_validators: usize) -> Result<EpochBlock, InputsError> {
+        let version = match self.encoding_version {
+            1 => EncodingVersion::V1,
+            2 => EncodingVersion::V2,
+            version => return Err(InputsError::UnsupportedEncoding(version)),
+        };
+        check_length(
+            format!("{}.public_keys", path),
+            self.public_keys.len(),
+            num_validators,
+        )?;
+        let new_public_keys = self
+            .public_keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                let field = format!("{}.public_keys[{}]", path, i);
+                let bytes = decode_hex(&field, key)?;
+                PublicKey::from_celo_bytes(&bytes)
+                    .map_err(|source| InputsError::InvalidPoint { field, source })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let epoch_entropy = decode_entropy(path, "epoch_entropy", &self.epoch_entropy, version)?;
+        let parent_entropy = decode_entropy(path, "parent_entropy", &self.parent_entropy, version)?;
+
+        Ok(EpochBlock {
+            index: self.index,
+            maximum_non_signers: self.maximum_non_signers,
+            new_public_keys,
+            version,
+            epoch_entropy,
+            parent_entropy,
+        })
+    }
+}
+
+fn decode_entropy(
+    path: &str,
+    name: &str,
+    entropy: &Option<String>,
+    version: EncodingVersion,
+) -> Result<Option<Vec<u8>>, InputsError> {
+    let field = format!("{}.{}", path, name);
+    match entropy {
+        Some(entropy) if version.has_entropy() => {
+            let bytes = decode_hex(&field, entropy)?;
+            check_length(field, bytes.len(), ENTROPY_BYTES)?;
+            Ok(Some(bytes))
+        }
+        None if !version.has_entropy() => Ok(None),
+        _ => Err(InputsError::EntropyMismatch(field)),
+    }
+}
+
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>, InputsError> {
+    hex::decode(value).map_err(|_| InputsError::InvalidHex(field.to_owned()))
+}
+
+fn check_length(field: String, got: usize, expected: usize) -> Result<(), InputsError> {
+    if got != expected {
+        return Err(InputsError::LengthMismatch {
+            field,
+            got,
+            expected,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{
+        bls12_377::{G1Projective, G2Projective},
+        UniformRand,
+    };
+
+    fn epochs() -> (EpochBlock, Vec<EpochTransition>) {
+        let rng = &mut rand::thread_rng();
+        let mut keys = || {
+            (0..3)
+                .map(|_| PublicKey::from(G2Projective::rand(rng)))
+                .collect::<Vec<_>>()
+        };
+        let initial = EpochBlock::new(10, 1, keys());
+        let transitions = (11..13)
+            .map(|index| EpochTransition {
+                block: EpochBlock::new(index, 1, keys())
+                    .with_entropy(&[index as u8; ENTROPY_BYTES], &[0; ENTROPY_BYTES]),
+                aggregate_signature: Signature::from(G1Projective::rand(&mut rand::thread_rng())),
+                bitmap: vec![true, false, true],
+            })
+            .collect();
+        (initial, transitions)
+    }
+
+    #[test]
+    fn roundtrips_through_json() {
+        let (initial, transitions) = epochs();
+        let inputs = ProverInputs::new(3, 4, &initial, &transitions);
+        let json = inputs.to_json().unwrap();
+        assert_eq!(serde_json::from_str::<ProverInputs>(&json).unwrap(), inputs);
+
+        let decoded = ProverInputs::load(&json).unwrap();
+        assert_eq!(decoded.num_validators, 3);
+        assert_eq!(decoded.max_transitions, 4);
+        assert_eq!(decoded.initial_epoch, initial);
+        assert_eq!(decoded.transitions, transitions);
+    }
+
+    #[test]
+    fn reports_invalid_fields() {
+        let (initial, transitions) = epochs();
+        let inputs = ProverInputs::new(3, 4, &initial, &transitions);
+        let check = |inputs: &ProverInputs, expected: &str| {
+            let err = inputs.decode().unwrap_err();
+            assert!(
+                err.to_string().contains(expected),
+                "{} does not mention {}",
+                err,
+                expected
+            );
+        };
+
+        let mut bad = inputs.clone();
+        bad.version = 2;
+        check(&bad, "version 2");
+
+        let mut bad = inputs.clone();
+        bad.transitions[1].block.public_keys[2] = "zz".to_owned();
+        check(&bad, "transitions[1].block.public_keys[2] is not valid hex");
+
+        let mut bad = inputs.clone();
+        bad.initial_epoch.public_keys[0] = hex::encode(&[0u8; 96][..]);
+        check(&bad, "initial_epoch.public_keys[0] is not a valid point");
+
+        let mut bad = inputs.clone();
+        bad.transitions[0].bitmap.pop();
+        check(&bad, "transitions[0].bitmap has length 2, expected 3");
+
+        let mut bad = inputs.clone();
+        bad.transitions[0].block.epoch_entropy = None;
+        check(&bad, "transitions[0].block.epoch_entropy must be set");
+
+        let mut bad = inputs.clone();
+        bad.transitions[1].block.index = 13;
+        check(&bad, "next epoch is 12");
+
+        let mut bad = inputs;
+        bad.max_transitions = 1;
+        check(&bad, "at most 1");
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let (initial, transitions) = epochs();
+        let mut json: serde_json::Value =
+            serde_json::to_value(ProverInputs::new(3, 4, &initial, &transitions)).unwrap();
+        json["initial_epoch"]["extra"] = serde_json::Value::Bool(true);
+        match ProverInputs::load(&json.to_string()) {
+            Err(InputsError::JsonError(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn schema_matches_the_version() {
+        let schema: serde_json::Value = serde_json::from_str(INPUTS_SCHEMA).unwrap();
+        assert_eq!(
+            schema["properties"]["version"]["const"],
+            serde_json::Value::from(INPUTS_VERSION)
+        );
+    }
+}
@@ -25,10 +25,75 @@ mod api;
 pub use api::*;
 
 mod encoding;
-pub use encoding::EncodingError;
+pub use encoding::{EncodingError, EncodingVersion, ENTROPY_BYTES};
 
 mod epoch_block;
-pub use epoch_block::{EpochBlock, EpochTransition};
+pub use epoch_block::{decode_epoch, encode_epoch, EpochBlock, EpochTransition};
+
+mod epoch_index;
+pub use epoch_index::{EpochIndex, EpochIndexError};
+
+mod witness_check;
+pub use witness_check::{check_witness, WitnessError};
 
 mod gadgets;
-pub use gadgets::ValidatorSetUpdate;
+pub use gadgets::{
+    decode_public_inputs, encode_public_inputs, hash_edge, num_public_inputs, ValidatorSetUpdate,
+    EDGE_HASH_BITS,
+};
+
+#[cfg(feature = "secure-memory")]
+mod secure;
+
+mod progress;
+pub use progress::{Progress, ProvingError, ProvingStage};
+
+mod timing;
+pub use timing::{NamespaceTiming, TimedCircuit, TimingConstraintSystem};
+
+mod light_client;
+pub use light_client::{LightClientError, LightClientState, VALIDATOR_SET_HASH_LENGTH};
+
+mod manifest;
+pub use manifest::{Artifact, CurveId, Manifest, ManifestError, SignedManifest};
+// the domain is defined in bls-crypto, which reserves it against application domains
+pub use bls_crypto::MANIFEST_DOMAIN;
+
+mod source;
+#[cfg(feature = "http")]
+pub use source::HttpSource;
+pub use source::{FileSource, MemorySource, ParameterSource, SourceError, VerifiedSource};
+
+#[cfg(feature = "net")]
+mod net;
+#[cfg(feature = "net")]
+pub use net::{
+    encode_bundle, read_bundle, read_verified_bundle, StreamError, CHECKSUM_LENGTH,
+    MAX_SECTION_LENGTH,
+};
+
+#[cfg(feature = "json")]
+mod inputs;
+#[cfg(feature = "json")]
+pub use inputs::{
+    DecodedInputs, EpochJson, InputsError, ProverInputs, TransitionJson, INPUTS_SCHEMA,
+    INPUTS_VERSION,
+};
+
+mod artifacts;
+pub use artifacts::{EpochsVerifyingKey, PARAMETERS_KIND, VERIFYING_KEY_KIND};
+
+mod describe;
+pub use describe::{BundleReport, EpochSummary};
+
+mod entropy;
+pub use entropy::EntropyError;
+
+mod gossip;
+pub use gossip::{EpochAttestation, GossipError, MAX_PACKET_LENGTH, PACKET_VERSION};
+
+mod attestation;
+pub use attestation::{
+    parameters_fingerprint, prove_attested, Attestation, AttestationError, SignedAttestation,
+    ATTESTATION_HASH_LENGTH,
+};
@@ -0,0 +1,180 @@
+//! The state which an ultralight client persists between proofs.
+//!
+//! A client which follows the chain with epoch proofs only needs to remember the index of
+//! the last epoch it verified, a hash of that epoch's block (which commits to its validator
+//! set) and the aggregate public key of the validators. The next proof is checked to start
+//! from that epoch before it is verified, so that proofs cannot be applied out of order or
+//! for a different chain.
+use crate::{
+    api::{verify, RangeProof, VerificationError},
+    encoding::EncodingError,
+    epoch_block::EpochBlock,
+};
+use algebra::{
+    bw6_761::BW6_761,
+    serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError},
+};
+use bls_crypto::PublicKey;
+use bls_gadgets::utils::bits_to_bytes;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use groth16::VerifyingKey;
+use std::io::{Read, Write};
+use thiserror::Error;
+
+/// Length of the hash of the epoch block
+pub const VALIDATOR_SET_HASH_LENGTH: usize = 32;
+
+/// The version of the state encoding
+const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Error)]
+/// Error raised while applying a proof to the light client state
+pub enum LightClientError {
+    #[error("I/O Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Zexe Error: {0}")]
+    ZexeSerialization(#[from] SerializationError),
+    #[error("Encoding Error: {0}")]
+    EpochEncodingError(#[from] EncodingError),
+    #[error("Verification Error: {0}")]
+    VerificationError(#[from] VerificationError),
+    #[error("Malformed state: {0}")]
+    Malformed(&'static str),
+    #[error("The proof starts at epoch {got}, but the state is at epoch {expected}")]
+    EpochMismatch { expected: u16, got: u16 },
+    #[error("The proof's first epoch does not match the state's validator set")]
+    ValidatorSetMismatch,
+}
+
+/// The state of an ultralight client, which has a constant size
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LightClientState {
+    /// The index of the last verified epoch
+    pub epoch: u16,
+    /// The Blake2s hash of the last verified epoch block's encoding, as used in the
+    /// proofs' public inputs
+    pub validator_set_hash: [u8; VALIDATOR_SET_HASH_LENGTH],
+    /// The aggregate public key of the last verified epoch's validators
+    pub aggregated_pk: PublicKey,
+}
+
+impl LightClientState {
+    /// Creates the state for a trusted epoch, e.g. the genesis block or a checkpoint
+    pub fn new(trusted_epoch: &EpochBlock) -> Result<Self, LightClientError> {
+        Ok(Self {
+            epoch: trusted_epoch.index,
+            validator_set_hash: hash_epoch(trusted_epoch)?,
+            aggregated_pk: PublicKey::aggregate(&trusted_epoch.new_public_keys),
+        })
+    }
+
+    /// Returns true if the block is the last verified epoch
+    pub fn matches(&self, block: &EpochBlock) -> Result<bool, LightClientError> {
+        Ok(block.index == self.epoch && hash_epoch(block)? == self.validator_set_hash)
+    }
+
+    /// Verifies the proof bundle, which must start from the state's epoch, and advances the
+    /// state to the bundle's last epoch. The state is left unchanged if the proof is invalid.
+    pub fn apply_proof(
+        &mut self,
+        vk: &VerifyingKey<BW6_761>,
+        bundle: &RangeProof,
+    ) -> Result<(), LightClientError> {
+        if bundle.first_epoch.index != self.epoch {
+            return Err(LightClientError::EpochMismatch {
+                expected: self.epoch,
+                got: bundle.first_epoch.index,
+            });
+        }
+        if !self.matches(&bundle.first_epoch)? {
+            return Err(LightClientError::ValidatorSetMismatch);
+        }
+
+        verify(vk, &bundle.first_epoch, &bundle.last_epoch, &bundle.proof)?;
+
+        *self = Self::new(&bundle.last_epoch)?;
+        Ok(())
+    }
+
+    /// Encodes the state to bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, LightClientError> {
+        let mut bytes = vec![];
+        bytes.write_u8(FORMAT_VERSION)?;
+        bytes.write_u16::<LittleEndian>(self.epoch)?;
+        bytes.write_all(&self.validator_set_hash)?;
+        self.aggregated_pk.serialize(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decodes the state from the bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LightClientError> {
+        let reader = &mut &bytes[..];
+        let state = Self::read(reader)?;
+        if !reader.is_empty() {
+            return Err(LightClientError::Malformed("trailing bytes"));
+        }
+        Ok(state)
+    }
+
+    fn read<R: Read>(reader: &mut R) -> Result<Self, LightClientError> {
+        if reader.read_u8()? != FORMAT_VERSION {
+            return Err(LightClientError::Malformed("unsupported version"));
+        }
+        let epoch = reader.read_u16::<LittleEndian>()?;
+        let mut validator_set_hash = [0u8; VALIDATOR_SET_HASH_LENGTH];
+        reader.read_exact(&mut validator_set_hash)?;
+        let aggregated_pk = PublicKey::deserialize(reader)?;
+        Ok(Self {
+            epoch,
+            validator_set_hash,
+            aggregated_pk,
+        })
+    }
+}
+
+fn hash_epoch(block: &EpochBlock) -> Result<[u8; VALIDATOR_SET_HASH_LENGTH], LightClientError> {
+    let bytes = bits_to_bytes(&block.blake2()?);
+    let mut hash = [0u8; VALIDATOR_SET_HASH_LENGTH];
+    hash.copy_from_slice(&bytes);
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G2Projective, UniformRand};
+
+    fn block(index: u16) -> EpochBlock {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..4)
+            .map(|_| PublicKey::from(G2Projective::rand(rng)))
+            .collect();
+        EpochBlock::new(index, 1, keys)
+    }
+
+    #[test]
+    fn serialization_roundtrip() {
+        let state = LightClientState::new(&block(3)).unwrap();
+        let bytes = state.to_bytes().unwrap();
+        // constant size regardless of the number of validators
+        assert_eq!(bytes.len(), 1 + 2 + VALIDATOR_SET_HASH_LENGTH + 96);
+        assert_eq!(LightClientState::from_bytes(&bytes).unwrap(), state);
+
+        LightClientState::from_bytes(&bytes[1..]).unwrap_err();
+        LightClientState::from_bytes(&[&bytes[..], &[0]].concat()).unwrap_err();
+    }
+
+    #[test]
+    fn matches_only_its_epoch() {
+        let trusted = block(3);
+        let state = LightClientState::new(&trusted).unwrap();
+        assert!(state.matches(&trusted).unwrap());
+
+        // same index, different validators
+        assert!(!state.matches(&block(3)).unwrap());
+
+        let mut other = trusted;
+        other.maximum_non_signers += 1;
+        assert!(!state.matches(&other).unwrap());
+    }
+}
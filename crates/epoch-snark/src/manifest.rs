@@ -0,0 +1,415 @@
+//! Manifests describing the artifacts (proving keys, verifying keys) of a circuit release.
+//!
+//! A manifest lists the name, version, curve, size and Blake2s hash of each artifact, and is
+//! signed with the release's BLS key. Operators fetching artifacts from untrusted mirrors
+//! verify the manifest against the release key, and then check each artifact against it
+//! when loading it.
+use algebra::{
+    bls12_377::G1Projective,
+    serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError},
+};
+use blake2s_simd::Params;
+use bls_crypto::{
+    hash_to_curve::try_and_increment::DIRECT_HASH_TO_G1, BLSError, HashToCurve, PrivateKey,
+    PublicKey, Signature, MANIFEST_DOMAIN,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+use thiserror::Error;
+
+/// Magic bytes at the start of every encoded manifest
+const MAGIC: &[u8; 8] = b"ULmanfst";
+
+/// The version of the manifest encoding
+const FORMAT_VERSION: u8 = 1;
+
+/// Personalization of the Blake2s hash of each artifact
+const ARTIFACT_PERSONALIZATION: &[u8; 8] = b"ULartfct";
+
+#[derive(Debug, Error)]
+/// Error raised while encoding, verifying or validating against a manifest
+pub enum ManifestError {
+    #[error("I/O Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Zexe Error: {0}")]
+    ZexeSerialization(#[from] SerializationError),
+    #[error("BLS Error: {0}")]
+    BLSError(#[from] BLSError),
+    #[error("Malformed manifest: {0}")]
+    Malformed(&'static str),
+    #[error("Unknown curve id {0}")]
+    UnknownCurve(u8),
+    #[error("Artifact {0} is not listed in the manifest")]
+    UnknownArtifact(String),
+    #[error("Artifact {name} has size {got}, expected {expected}")]
+    SizeMismatch {
+        name: String,
+        expected: u64,
+        got: u64,
+    },
+    #[error("Artifact {0} does not match its hash in the manifest")]
+    HashMismatch(String),
+}
+
+/// The curves over which artifacts are defined
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveId {
+    /// BLS12-377, used for the CRH->XOF helper circuit
+    Bls12_377,
+    /// BW6-761, used for the epoch transition circuit
+    BW6_761,
+}
+
+impl CurveId {
+    fn to_byte(self) -> u8 {
+        match self {
+            CurveId::Bls12_377 => 0,
+            CurveId::BW6_761 => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, ManifestError> {
+        match byte {
+            0 => Ok(CurveId::Bls12_377),
+            1 => Ok(CurveId::BW6_761),
+            _ => Err(ManifestError::UnknownCurve(byte)),
+        }
+    }
+}
+
+/// A single artifact listed in a manifest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Artifact {
+    /// The name of the artifact, e.g. `epochs.vk`
+    pub name: String,
+    /// The version of the artifact
+    pub version: u32,
+    /// The curve over which the artifact is defined
+    pub curve: CurveId,
+    /// The size of the artifact in bytes
+    pub size: u64,
+    /// The Blake2s hash of the artifact
+    pub hash: [u8; 32],
+}
+
+impl Artifact {
+    /// Describes the artifact with the provided serialized contents
+    pub fn new(name: &str, version: u32, curve: CurveId, bytes: &[u8]) -> Self {
+        Self {
+            name: name.to_owned(),
+            version,
+            curve,
+            size: bytes.len() as u64,
+            hash: hash_artifact(bytes),
+        }
+    }
+
+    /// Checks that the provided serialized contents match the artifact's size and hash
+    pub fn validate(&self, bytes: &[u8]) -> Result<(), ManifestError> {
+        if bytes.len() as u64 != self.size {
+            return Err(ManifestError::SizeMismatch {
+                name: self.name.clone(),
+                expected: self.size,
+                got: bytes.len() as u64,
+            });
+        }
+        if hash_artifact(bytes) != self.hash {
+            return Err(ManifestError::HashMismatch(self.name.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// The list of artifacts of a circuit release
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    /// The version of the circuit which the artifacts were generated for
+    pub circuit_version: u32,
+    /// The artifacts of the release
+    pub artifacts: Vec<Artifact>,
+}
+
+impl Manifest {
+    /// Returns the artifact with the provided name
+    pub fn artifact(&self, name: &str) -> Result<&Artifact, ManifestError> {
+        self.artifacts
+            .iter()
+            .find(|artifact| artifact.name == name)
+            .ok_or_else(|| ManifestError::UnknownArtifact(name.to_owned()))
+    }
+
+    /// Checks the serialized artifact against the manifest and then deserializes it
+    pub fn load<T: CanonicalDeserialize>(
+        &self,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<T, ManifestError> {
+        self.artifact(name)?.validate(bytes)?;
+        deserialize_exact(bytes)
+    }
+
+    /// Signs the manifest with the release key
+    pub fn sign(&self, release_key: &PrivateKey) -> Result<SignedManifest, ManifestError> {
+        let signature = release_key.sign_hashed(&self.hash()?);
+        Ok(SignedManifest {
+            manifest: self.clone(),
+            signature,
+        })
+    }
+
+    /// Encodes the manifest to bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ManifestError> {
+        let mut bytes = vec![];
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a manifest from bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ManifestError> {
+        let reader = &mut &bytes[..];
+        let manifest = Self::read(reader)?;
+        if !reader.is_empty() {
+            return Err(ManifestError::Malformed("trailing bytes"));
+        }
+        Ok(manifest)
+    }
+
+    /// Hashes the encoded manifest to G1 under the manifest domain
+    fn hash(&self) -> Result<G1Projective, ManifestError> {
+        Ok(DIRECT_HASH_TO_G1.hash(MANIFEST_DOMAIN, &self.to_bytes()?, &[])?)
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ManifestError> {
+        writer.write_all(MAGIC)?;
+        writer.write_u8(FORMAT_VERSION)?;
+        writer.write_u32::<LittleEndian>(self.circuit_version)?;
+        writer.write_u32::<LittleEndian>(self.artifacts.len() as u32)?;
+        for artifact in &self.artifacts {
+            let name = artifact.name.as_bytes();
+            if name.len() > u16::MAX as usize {
+                return Err(ManifestError::Malformed("artifact name too long"));
+            }
+            writer.write_u16::<LittleEndian>(name.len() as u16)?;
+            writer.write_all(name)?;
+            writer.write_u32::<LittleEndian>(artifact.version)?;
+            writer.write_u8(artifact.curve.to_byte())?;
+            writer.write_u64::<LittleEndian>(artifact.size)?;
+            writer.write_all(&artifact.hash)?;
+        }
+        Ok(())
+    }
+
+    fn read<R: Read>(reader: &mut R) -> Result<Self, ManifestError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ManifestError::Malformed("invalid magic bytes"));
+        }
+        if reader.read_u8()? != FORMAT_VERSION {
+            return Err(ManifestError::Malformed("unsupported format version"));
+        }
+        let circuit_version = reader.read_u32::<LittleEndian>()?;
+        let num_artifacts = reader.read_u32::<LittleEndian>()?;
+        let artifacts = (0..num_artifacts)
+            .map(|_| {
+                let name_len = reader.read_u16::<LittleEndian>()?;
+                let mut name = vec![0u8; name_len as usize];
+                reader.read_exact(&mut name)?;
+                let name = String::from_utf8(name)
+                    .map_err(|_| ManifestError::Malformed("artifact name is not UTF-8"))?;
+                let version = reader.read_u32::<LittleEndian>()?;
+                let curve = CurveId::from_byte(reader.read_u8()?)?;
+                let size = reader.read_u64::<LittleEndian>()?;
+                let mut hash = [0u8; 32];
+                reader.read_exact(&mut hash)?;
+                Ok(Artifact {
+                    name,
+                    version,
+                    curve,
+                    size,
+                    hash,
+                })
+            })
+            .collect::<Result<Vec<_>, ManifestError>>()?;
+
+        Ok(Self {
+            circuit_version,
+            artifacts,
+        })
+    }
+}
+
+/// A manifest along with the release key's signature over it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedManifest {
+    manifest: Manifest,
+    signature: Signature,
+}
+
+impl SignedManifest {
+    /// Verifies the signature against the release key and returns the manifest
+    pub fn verify(&self, release_key: &PublicKey) -> Result<&Manifest, ManifestError> {
+        self.signature
+            .batch_verify_hashes(&[release_key], &[self.manifest.hash()?])?;
+        Ok(&self.manifest)
+    }
+
+    /// Encodes the manifest followed by its signature
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ManifestError> {
+        let mut bytes = self.manifest.to_bytes()?;
+        self.signature.serialize(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a signed manifest. The signature is not verified, call `verify` for that.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ManifestError> {
+        let reader = &mut &bytes[..];
+        let manifest = Manifest::read(reader)?;
+        let signature = Signature::deserialize(reader)?;
+        if !reader.is_empty() {
+            return Err(ManifestError::Malformed("trailing bytes"));
+        }
+        Ok(Self {
+            manifest,
+            signature,
+        })
+    }
+}
+
+/// Deserializes an artifact, rejecting any bytes left over after it
+pub(crate) fn deserialize_exact<T: CanonicalDeserialize>(bytes: &[u8]) -> Result<T, ManifestError> {
+    let reader = &mut &bytes[..];
+    let value = T::deserialize(reader)?;
+    if !reader.is_empty() {
+        return Err(ManifestError::Malformed(
+            "trailing bytes after the artifact",
+        ));
+    }
+    Ok(value)
+}
+
+fn hash_artifact(bytes: &[u8]) -> [u8; 32] {
+    let hash = Params::new()
+        .hash_length(32)
+        .personal(ARTIFACT_PERSONALIZATION)
+        .to_state()
+        .update(bytes)
+        .finalize();
+    let mut out = [0u8; 32];
+    out.copy_from_slice(hash.as_ref());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{bls12_377::G2Projective, UniformRand};
+
+    fn manifest_for(vk: &[u8]) -> Manifest {
+        Manifest {
+            circuit_version: 1,
+            artifacts: vec![
+                Artifact::new("epochs.vk", 1, CurveId::BW6_761, vk),
+                Artifact::new("hash_to_bits.vk", 1, CurveId::Bls12_377, b"unused"),
+            ],
+        }
+    }
+
+    #[test]
+    fn sign_verify_load() {
+        let rng = &mut rand::thread_rng();
+        let release_key = PrivateKey::generate(rng);
+
+        // any serializable artifact works, use a group element as a stand-in for a key
+        let element = G2Projective::rand(rng);
+        let mut artifact = vec![];
+        element.serialize(&mut artifact).unwrap();
+
+        let signed = manifest_for(&artifact).sign(&release_key).unwrap();
+        let decoded = SignedManifest::from_bytes(&signed.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, signed);
+
+        let manifest = decoded.verify(&release_key.to_public()).unwrap();
+        let loaded: G2Projective = manifest.load("epochs.vk", &artifact).unwrap();
+        assert_eq!(loaded, element);
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let rng = &mut rand::thread_rng();
+        let element = G2Projective::rand(rng);
+        let mut artifact = vec![];
+        element.serialize(&mut artifact).unwrap();
+        let manifest = manifest_for(&artifact);
+
+        let mut padded = manifest.to_bytes().unwrap();
+        padded.push(0);
+        match Manifest::from_bytes(&padded) {
+            Err(ManifestError::Malformed(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // the padded artifact is listed in the manifest, but still must not load
+        artifact.push(0);
+        let manifest = manifest_for(&artifact);
+        match manifest.load::<G2Projective>("epochs.vk", &artifact) {
+            Err(ManifestError::Malformed(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn rejects_consensus_signatures() {
+        let rng = &mut rand::thread_rng();
+        let release_key = PrivateKey::generate(rng);
+        let manifest = manifest_for(b"vk");
+
+        // a signature over the same bytes under the consensus domain is not a manifest signature
+        let signature = release_key
+            .sign(&manifest.to_bytes().unwrap(), &[], &*DIRECT_HASH_TO_G1)
+            .unwrap();
+        let signed = SignedManifest {
+            manifest,
+            signature,
+        };
+        signed.verify(&release_key.to_public()).unwrap_err();
+    }
+
+    #[test]
+    fn rejects_wrong_release_key() {
+        let rng = &mut rand::thread_rng();
+        let signed = manifest_for(b"vk")
+            .sign(&PrivateKey::generate(rng))
+            .unwrap();
+        signed
+            .verify(&PrivateKey::generate(rng).to_public())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn rejects_tampered_artifacts() {
+        let manifest = manifest_for(b"verifying key");
+        manifest
+            .artifact("epochs.vk")
+            .unwrap()
+            .validate(b"verifying key")
+            .unwrap();
+
+        match manifest
+            .artifact("epochs.vk")
+            .unwrap()
+            .validate(b"verifying kex")
+        {
+            Err(ManifestError::HashMismatch(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match manifest.artifact("epochs.vk").unwrap().validate(b"vk") {
+            Err(ManifestError::SizeMismatch { .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match manifest.artifact("epochs.pk") {
+            Err(ManifestError::UnknownArtifact(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Downloading and verifying proof bundles from a byte stream.
+//!
+//! A bundle is streamed as a header followed by three sections, the first epoch, the last
+//! epoch and the proof. Each section is prefixed by its tag and length and followed by its
+//! Blake2s checksum, so that a corrupted or truncated download is detected as soon as the
+//! affected section arrives. The public inputs are computed from the epochs while the proof
+//! is still downloading, so only the pairings are left once the stream ends.
+use crate::{
+    api::{public_inputs, verify_with_inputs, CPCurve, RangeProof, VerificationError},
+    encoding::EncodingError,
+    epoch_block::{decode_epoch, encode_epoch},
+};
+use algebra::serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use blake2s_simd::Params;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use futures::io::{AsyncRead, AsyncReadExt};
+use groth16::{prepare_verifying_key, PreparedVerifyingKey, Proof, VerifyingKey};
+use std::io::{ErrorKind, Write};
+use thiserror::Error;
+
+/// Magic bytes at the start of every bundle stream
+const MAGIC: &[u8; 8] = b"ULbundle";
+
+/// The version of the stream encoding
+const FORMAT_VERSION: u8 = 1;
+
+/// Personalization of the Blake2s checksum of each section
+const CHECKSUM_PERSONALIZATION: &[u8; 8] = b"ULsectck";
+
+/// Length of the checksum of each section
+pub const CHECKSUM_LENGTH: usize = 32;
+
+/// The maximum length of a section, so that a malicious stream cannot make the reader
+/// allocate arbitrary amounts of memory
+pub const MAX_SECTION_LENGTH: u32 = 1 << 24;
+
+#[derive(Debug, Error)]
+/// Error raised while reading a proof bundle from a stream
+pub enum StreamError {
+    #[error("I/O Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("The stream ended in the {0} section")]
+    Truncated(&'static str),
+    #[error("Zexe Error: {0}")]
+    ZexeSerialization(#[from] SerializationError),
+    #[error("Encoding Error: {0}")]
+    EpochEncodingError(#[from] EncodingError),
+    #[error("Verification Error: {0}")]
+    VerificationError(#[from] VerificationError),
+    #[error("Malformed stream: {0}")]
+    Malformed(&'static str),
+    #[error("The {section} section has length {length}, which is too large")]
+    SectionTooLarge { section: &'static str, length: u32 },
+    #[error("The {0} section does not match its checksum")]
+    ChecksumMismatch(&'static str),
+}
+
+/// The sections of a bundle stream, in the order in which they are sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Section {
+    Header,
+    FirstEpoch,
+    LastEpoch,
+    Proof,
+}
+
+impl Section {
+    fn tag(self) -> u8 {
+        self as u8
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Section::Header => "header",
+            Section::FirstEpoch => "first epoch",
+            Section::LastEpoch => "last epoch",
+            Section::Proof => "proof",
+        }
+    }
+}
+
+/// Encodes the bundle to the stream format read by `read_verified_bundle`
+pub fn encode_bundle(bundle: &RangeProof) -> Result<Vec<u8>, StreamError> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.write_u8(FORMAT_VERSION)?;
+
+    let mut proof = vec![];
+    bundle.proof.serialize(&mut proof)?;
+    let sections = [
+        (Section::FirstEpoch, encode_epoch(&bundle.first_epoch)?),
+        (Section::LastEpoch, encode_epoch(&bundle.last_epoch)?),
+        (Section::Proof, proof),
+    ];
+    for (section, payload) in sections.iter() {
+        bytes.write_u8(section.tag())?;
+        bytes.write_u32::<LittleEndian>(payload.len() as u32)?;
+        bytes.write_all(payload)?;
+        bytes.write_all(&checksum(payload))?;
+    }
+    Ok(bytes)
+}
+
+/// Reads a bundle from the stream, checking each section against its checksum as it
+/// arrives, and verifies its proof. Returns the verified bundle.
+pub async fn read_verified_bundle<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    vk: &VerifyingKey<CPCurve>,
+) -> Result<RangeProof, StreamError> {
+    read(reader, Some(&prepare_verifying_key(vk))).await
+}
+
+/// Reads a bundle from the stream, checking each section against its checksum, without
+/// verifying its proof
+pub async fn read_bundle<R: AsyncRead + Unpin>(reader: &mut R) -> Result<RangeProof, StreamError> {
+    read(reader, None).await
+}
+
+async fn read<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    pvk: Option<&PreparedVerifyingKey<CPCurve>>,
+) -> Result<RangeProof, StreamError> {
+    let mut header = [0u8; 9];
+    read_exact(reader, &mut header, Section::Header).await?;
+    if &header[..8] != MAGIC {
+        return Err(StreamError::Malformed("not a bundle stream"));
+    }
+    if header[8] != FORMAT_VERSION {
+        return Err(StreamError::Malformed("unsupported version"));
+    }
+
+    let first_epoch = decode_epoch(&read_section(reader, Section::FirstEpoch).await?)?;
+    let last_epoch = decode_epoch(&read_section(reader, Section::LastEpoch).await?)?;
+    // done before the proof arrives
+    let inputs = match pvk {
+        Some(_) => Some(public_inputs(&first_epoch, &last_epoch)?),
+        None => None,
+    };
+
+    let proof_bytes = read_section(reader, Section::Proof).await?;
+    let proof = deserialize_exact::<Proof<CPCurve>>(&proof_bytes)?;
+    if reader.read(&mut [0u8; 1]).await? != 0 {
+        return Err(StreamError::Malformed("trailing bytes"));
+    }
+
+    if let (Some(pvk), Some(inputs)) = (pvk, inputs) {
+        verify_with_inputs(pvk, &inputs, &proof)?;
+    }
+    Ok(RangeProof {
+        first_epoch,
+        last_epoch,
+        proof,
+    })
+}
+
+async fn read_exact<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+    section: Section,
+) -> Result<(), StreamError> {
+    reader.read_exact(buf).await.map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => StreamError::Truncated(section.name()),
+        _ => StreamError::IoError(e),
+    })
+}
+
+async fn read_section<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    section: Section,
+) -> Result<Vec<u8>, StreamError> {
+    let mut prefix = [0u8; 5];
+    read_exact(reader, &mut prefix, section).await?;
+    if prefix[0] != section.tag() {
+        return Err(StreamError::Malformed("unexpected section"));
+    }
+    let length = (&prefix[1..]).read_u32::<LittleEndian>()?;
+    if length > MAX_SECTION_LENGTH {
+        return Err(StreamError::SectionTooLarge {
+            section: section.name(),
+            length,
+        });
+    }
+
+    let mut payload = vec![0u8; length as usize];
+    read_exact(reader, &mut payload, section).await?;
+    let mut expected = [0u8; CHECKSUM_LENGTH];
+    read_exact(reader, &mut expected, section).await?;
+    if checksum(&payload) != expected {
+        return Err(StreamError::ChecksumMismatch(section.name()));
+    }
+    Ok(payload)
+}
+
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    let hash = Params::new()
+        .hash_length(CHECKSUM_LENGTH)
+        .personal(CHECKSUM_PERSONALIZATION)
+        .hash(payload);
+    let mut checksum = [0u8; CHECKSUM_LENGTH];
+    checksum.copy_from_slice(hash.as_bytes());
+    checksum
+}
+
+fn deserialize_exact<T: CanonicalDeserialize>(bytes: &[u8]) -> Result<T, StreamError> {
+    let reader = &mut &bytes[..];
+    let value = T::deserialize(reader)?;
+    if !reader.is_empty() {
+        return Err(StreamError::Malformed("trailing bytes in section"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{epoch_block::EpochBlock, simulate_proof};
+    use algebra::{bls12_377::G2Projective, UniformRand};
+    use bls_crypto::PublicKey;
+    use futures::{executor::block_on, io::Cursor, task::Context, task::Poll};
+    use std::pin::Pin;
+
+    /// Yields at most one byte per read, like a slow connection
+    struct Trickle(Cursor<Vec<u8>>);
+
+    impl AsyncRead for Trickle {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let len = buf.len().min(1);
+            Pin::new(&mut self.0).poll_read(cx, &mut buf[..len])
+        }
+    }
+
+    fn bundle() -> RangeProof {
+        let rng = &mut rand::thread_rng();
+        let keys = (0..3)
+            .map(|_| PublicKey::from(G2Projective::rand(rng)))
+            .collect::<Vec<_>>();
+        let first_epoch = EpochBlock::new(4, 1, keys.clone())
+            .with_entropy(&[1; crate::ENTROPY_BYTES], &[2; crate::ENTROPY_BYTES]);
+        let last_epoch = EpochBlock::new(4, 1, keys);
+        simulate_proof(&first_epoch, &[], rng)
+            .map(|bundle| RangeProof {
+                last_epoch,
+                ..bundle
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn roundtrip_over_slow_stream() {
+        let bundle = bundle();
+        let bytes = encode_bundle(&bundle).unwrap();
+        let read = block_on(read_bundle(&mut Trickle(Cursor::new(bytes)))).unwrap();
+        assert_eq!(read.first_epoch, bundle.first_epoch);
+        assert_eq!(read.last_epoch, bundle.last_epoch);
+        assert_eq!(read.proof, bundle.proof);
+    }
+
+    #[test]
+    fn detects_corruption_and_truncation() {
+        let bytes = encode_bundle(&bundle()).unwrap();
+
+        let mut corrupted = bytes.clone();
+        let proof_byte = bytes.len() - CHECKSUM_LENGTH - 1;
+        corrupted[proof_byte] ^= 1;
+        match block_on(read_bundle(&mut Cursor::new(corrupted))) {
+            Err(StreamError::ChecksumMismatch(section)) => assert_eq!(section, "proof"),
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+
+        match block_on(read_bundle(&mut Cursor::new(&bytes[..100]))) {
+            Err(StreamError::Truncated(section)) => assert_eq!(section, "first epoch"),
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+
+        let trailing = [&bytes[..], &[0]].concat();
+        block_on(read_bundle(&mut Cursor::new(trailing))).unwrap_err();
+    }
+
+    #[test]
+    fn verifies_the_proof() {
+        // the default key expects no public inputs, so the bundle is checked against it
+        let bytes = encode_bundle(&bundle()).unwrap();
+        match block_on(read_verified_bundle(
+            &mut Cursor::new(bytes),
+            &VerifyingKey::default(),
+        )) {
+            Err(StreamError::VerificationError(VerificationError::PublicInputCountMismatch {
+                expected: 0,
+                ..
+            })) => {}
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Progress reporting and cancellation of proof generation.
+//!
+//! Generating a proof for a few dozen epochs takes minutes. `prove_with_progress` calls a
+//! callback as the witness of the HashToBits helper is generated and as each circuit is
+//! synthesized, so that callers can show the status of the proof and cancel it by returning
+//! `false`. The multi-scalar multiplications which follow the synthesis of each circuit cannot
+//! be interrupted: the proof is cancelled at the next report after them.
+use crate::witness_check::WitnessError;
+use algebra::Field;
+use r1cs_core::{
+    ConstraintSynthesizer, ConstraintSystem, LinearCombination, SynthesisError, Variable,
+};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+use thiserror::Error;
+
+/// The number of reports made while synthesizing each circuit
+const REPORTS_PER_CIRCUIT: usize = 100;
+
+/// The stages of proof generation, in the order in which they run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProvingStage {
+    /// Searching the try-and-increment attempt of each epoch for the HashToBits helper. The
+    /// progress is counted in epochs.
+    HashWitness = 0,
+    /// Synthesizing and proving the HashToBits helper. The progress is counted in allocated
+    /// variables.
+    HashToBits = 1,
+    /// Synthesizing and proving the epochs circuit. The progress is counted in allocated
+    /// variables.
+    Epochs = 2,
+}
+
+/// A report of the progress of proof generation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The stage which is running
+    pub stage: ProvingStage,
+    /// The units of work of the stage which are done
+    pub done: usize,
+    /// The total units of work of the stage. Once `done` reaches it, the multi-scalar
+    /// multiplications of the stage's proof are computed.
+    pub total: usize,
+}
+
+#[derive(Debug, Error)]
+/// Errors of proof generation with progress reports
+pub enum ProvingError {
+    /// The progress callback cancelled the proof
+    #[error("Proof generation was cancelled")]
+    Cancelled,
+    /// The inputs cannot satisfy the circuit, as found by `check_witness` before proving
+    #[error("Invalid Witness: {0}")]
+    InvalidWitness(#[from] WitnessError),
+    /// The proof could not be generated
+    #[error("Synthesis Error: {0}")]
+    ZexeSynthesisError(#[from] SynthesisError),
+}
+
+/// Calls the progress callback, one report at a time, until it cancels the proof
+pub(crate) struct ProgressReporter<F> {
+    callback: Mutex<F>,
+    cancelled: AtomicBool,
+}
+
+/// The reporter of proofs generated without a callback
+pub(crate) type SilentReporter = ProgressReporter<fn(Progress) -> bool>;
+
+impl<F: FnMut(Progress) -> bool + Send> ProgressReporter<F> {
+    pub(crate) fn new(callback: F) -> Self {
+        Self {
+            callback: Mutex::new(callback),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// Reports the progress, failing if the proof was cancelled. The error is only used to
+    /// abort the synthesis, and is replaced by `ProvingError::Cancelled` in `finish`.
+    pub(crate) fn report(
+        &self,
+        stage: ProvingStage,
+        done: usize,
+        total: usize,
+    ) -> Result<(), SynthesisError> {
+        if !self.cancelled.load(Ordering::SeqCst) {
+            let mut callback = self.callback.lock().expect("progress callback panicked");
+            let progress = Progress {
+                stage,
+                done: done.min(total),
+                total,
+            };
+            if !(*callback)(progress) {
+                self.cancelled.store(true, Ordering::SeqCst);
+            }
+        }
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        Ok(())
+    }
+
+    /// Returns the result of the proof generation, unless it was cancelled
+    pub(crate) fn finish<T>(&self, result: Result<T, SynthesisError>) -> Result<T, ProvingError> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(ProvingError::Cancelled);
+        }
+        Ok(result?)
+    }
+}
+
+impl SilentReporter {
+    pub(crate) fn silent() -> Self {
+        fn proceed(_: Progress) -> bool {
+            true
+        }
+        Self::new(proceed)
+    }
+}
+
+/// Wraps a circuit and reports the variables allocated while it is synthesized
+pub(crate) struct ProgressCircuit<'a, C, F> {
+    circuit: C,
+    reporter: &'a ProgressReporter<F>,
+    stage: ProvingStage,
+    num_variables: usize,
+}
+
+impl<'a, C, F> ProgressCircuit<'a, C, F> {
+    /// Wraps the circuit, which allocates `num_variables` variables in total, e.g. the
+    /// length of the `a_query` of its parameters
+    pub(crate) fn new(
+        circuit: C,
+        reporter: &'a ProgressReporter<F>,
+        stage: ProvingStage,
+        num_variables: usize,
+    ) -> Self {
+        Self {
+            circuit,
+            reporter,
+            stage,
+            num_variables,
+        }
+    }
+}
+
+impl<'a, Fl, C, F> ConstraintSynthesizer<Fl> for ProgressCircuit<'a, C, F>
+where
+    Fl: Field,
+    C: ConstraintSynthesizer<Fl>,
+    F: FnMut(Progress) -> bool + Send,
+{
+    fn generate_constraints<CS: ConstraintSystem<Fl>>(
+        self,
+        cs: &mut CS,
+    ) -> Result<(), SynthesisError> {
+        let total = self.num_variables;
+        self.reporter.report(self.stage, 0, total)?;
+        let mut cs = ProgressConstraintSystem {
+            inner: cs,
+            reporter: self.reporter,
+            stage: self.stage,
+            allocated: 0,
+            total,
+            interval: (total / REPORTS_PER_CIRCUIT).max(1),
+        };
+        self.circuit.generate_constraints(&mut cs)?;
+        self.reporter.report(self.stage, total, total)
+    }
+}
+
+/// Counts the allocated variables, reporting them every `interval` allocations
+struct ProgressConstraintSystem<'a, CS, F> {
+    inner: CS,
+    reporter: &'a ProgressReporter<F>,
+    stage: ProvingStage,
+    allocated: usize,
+    total: usize,
+    interval: usize,
+}
+
+impl<'a, CS, F: FnMut(Progress) -> bool + Send> ProgressConstraintSystem<'a, CS, F> {
+    fn allocated(&mut self) -> Result<(), SynthesisError> {
+        self.allocated += 1;
+        if self.allocated % self.interval == 0 {
+            self.reporter
+                .report(self.stage, self.allocated, self.total)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, Fl, CS, F> ConstraintSystem<Fl> for ProgressConstraintSystem<'a, CS, F>
+where
+    Fl: Field,
+    CS: ConstraintSystem<Fl>,
+    F: FnMut(Progress) -> bool + Send,
+{
+    type Root = Self;
+
+    fn alloc<FN, A, AR>(&mut self, annotation: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<Fl, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.allocated()?;
+        self.inner.alloc(annotation, f)
+    }
+
+    fn alloc_input<FN, A, AR>(&mut self, annotation: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<Fl, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.allocated()?;
+        self.inner.alloc_input(annotation, f)
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<Fl>) -> LinearCombination<Fl>,
+        LB: FnOnce(LinearCombination<Fl>) -> LinearCombination<Fl>,
+        LC: FnOnce(LinearCombination<Fl>) -> LinearCombination<Fl>,
+    {
+        self.inner.enforce(annotation, a, b, c)
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.inner.push_namespace(name_fn)
+    }
+
+    fn pop_namespace(&mut self) {
+        self.inner.pop_namespace()
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+
+    fn num_constraints(&self) -> usize {
+        self.inner.num_constraints()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::bls12_377::Fr;
+    use r1cs_std::test_constraint_system::TestConstraintSystem;
+
+    struct Allocations(usize);
+
+    impl ConstraintSynthesizer<Fr> for Allocations {
+        fn generate_constraints<CS: ConstraintSystem<Fr>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            for i in 0..self.0 {
+                cs.alloc(|| format!("var {}", i), || Ok(Fr::from(i as u64)))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reports_allocations() {
+        let mut reports = vec![];
+        let reporter = ProgressReporter::new(|progress| {
+            reports.push(progress.done);
+            true
+        });
+        let circuit = ProgressCircuit::new(Allocations(250), &reporter, ProvingStage::Epochs, 250);
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        reporter
+            .finish(circuit.generate_constraints(&mut cs))
+            .unwrap();
+        drop(reporter);
+
+        // one report every 2 variables, and the ones before and after the synthesis
+        assert_eq!(reports.len(), 127);
+        assert_eq!(reports[..3], [0, 2, 4]);
+        assert_eq!(reports[reports.len() - 2..], [250, 250]);
+        assert!(cs.is_satisfied());
+    }
+
+    #[test]
+    fn cancels_synthesis() {
+        let mut num_reports = 0;
+        let reporter = ProgressReporter::new(|progress| {
+            num_reports += 1;
+            progress.done < 100
+        });
+        let circuit =
+            ProgressCircuit::new(Allocations(1000), &reporter, ProvingStage::Epochs, 1000);
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let result = reporter.finish(circuit.generate_constraints(&mut cs));
+        match result {
+            Err(ProvingError::Cancelled) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        // the callback is not called again once it cancelled the proof
+        assert!(reporter.report(ProvingStage::Epochs, 0, 1).is_err());
+        drop(reporter);
+        assert_eq!(num_reports, 11);
+    }
+}
@@ -0,0 +1,163 @@
+//! Best-effort protection of prover secrets and witness buffers in memory.
+//!
+//! Buffers are locked in RAM with `mlock` so that they are never written to swap, and are
+//! zeroized when dropped. Locking can fail (e.g. when `RLIMIT_MEMLOCK` is too low), in which
+//! case a warning is logged and the buffer is only zeroized. Memory owned by the proving
+//! system itself (e.g. the full witness assignment inside Groth16) is out of our reach.
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::{
+    mem,
+    ops::{Deref, DerefMut},
+    ptr,
+};
+use tracing::warn;
+use zeroize::Zeroize;
+
+#[cfg(unix)]
+fn lock(ptr: *const u8, len: usize) -> bool {
+    unsafe { libc::mlock(ptr as *const libc::c_void, len) == 0 }
+}
+
+#[cfg(unix)]
+fn unlock(ptr: *const u8, len: usize) {
+    unsafe {
+        libc::munlock(ptr as *const libc::c_void, len);
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_ptr: *const u8, _len: usize) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn unlock(_ptr: *const u8, _len: usize) {}
+
+/// A fixed-size vector which is locked in RAM and zeroized when dropped. It cannot grow, so
+/// that its contents are never copied to a new, unprotected allocation.
+pub struct SecretVec<T: Zeroize> {
+    inner: Vec<T>,
+    locked: bool,
+}
+
+impl<T: Zeroize> From<Vec<T>> for SecretVec<T> {
+    fn from(inner: Vec<T>) -> Self {
+        let len = inner.capacity() * mem::size_of::<T>();
+        let locked = len > 0 && lock(inner.as_ptr() as *const u8, len);
+        if len > 0 && !locked {
+            warn!("could not lock {} bytes of secret memory", len);
+        }
+        Self { inner, locked }
+    }
+}
+
+impl<T: Zeroize> Deref for SecretVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.inner
+    }
+}
+
+impl<T: Zeroize> DerefMut for SecretVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.inner
+    }
+}
+
+impl<T: Zeroize> Drop for SecretVec<T> {
+    fn drop(&mut self) {
+        let ptr = self.inner.as_ptr() as *const u8;
+        let len = self.inner.capacity() * mem::size_of::<T>();
+        self.inner.zeroize();
+        if self.locked {
+            unlock(ptr, len);
+        }
+    }
+}
+
+/// A CSPRNG seeded from the caller's RNG, whose state is locked in RAM and wiped when
+/// dropped. Used for drawing the toxic randomness of the trusted setup, so that it does not
+/// outlive the setup in the RNG's state.
+pub struct LockedRng {
+    inner: Box<ChaCha20Rng>,
+    locked: bool,
+}
+
+impl LockedRng {
+    /// Seeds a new RNG from the provided one
+    pub fn from_rng<R: RngCore>(rng: &mut R) -> Self {
+        let mut seed = <ChaCha20Rng as SeedableRng>::Seed::default();
+        rng.fill_bytes(&mut seed);
+        let inner = Box::new(ChaCha20Rng::from_seed(seed));
+        seed.zeroize();
+
+        let ptr = &*inner as *const ChaCha20Rng as *const u8;
+        let locked = lock(ptr, mem::size_of::<ChaCha20Rng>());
+        if !locked {
+            warn!("could not lock the setup RNG's memory");
+        }
+        Self { inner, locked }
+    }
+}
+
+impl RngCore for LockedRng {
+    fn next_u32(&mut self) -> u32 {
+        self.inner.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.inner.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.inner.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.inner.try_fill_bytes(dest)
+    }
+}
+
+impl CryptoRng for LockedRng {}
+
+impl Drop for LockedRng {
+    fn drop(&mut self) {
+        let ptr = &mut *self.inner as *mut ChaCha20Rng;
+        // Overwrite the key, counter and buffered output with a fixed state. The volatile
+        // write cannot be optimized away.
+        unsafe { ptr::write_volatile(ptr, ChaCha20Rng::from_seed(Default::default())) };
+        if self.locked {
+            unlock(ptr as *const u8, mem::size_of::<ChaCha20Rng>());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn secret_vec_derefs_to_contents() {
+        let mut secret = SecretVec::from(vec![1u8, 2, 3]);
+        assert_eq!(&*secret, &[1, 2, 3]);
+        secret[0] = 4;
+        assert_eq!(&*secret, &[4, 2, 3]);
+
+        // empty vectors are not locked
+        let empty = SecretVec::<u8>::from(vec![]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn locked_rng_is_deterministic_in_its_seed() {
+        let mut a = LockedRng::from_rng(&mut ChaCha20Rng::from_seed([1; 32]));
+        let mut b = LockedRng::from_rng(&mut ChaCha20Rng::from_seed([1; 32]));
+        let mut c = LockedRng::from_rng(&mut ChaCha20Rng::from_seed([2; 32]));
+        let x: u64 = a.gen();
+        assert_eq!(x, b.gen::<u64>());
+        assert_ne!(x, c.gen::<u64>());
+    }
+}
@@ -0,0 +1,335 @@
+//! Sources of the SNARK's artifacts, e.g. its proving and verifying keys.
+//!
+//! Provers and light clients obtain the keys of a release from wherever the operator keeps
+//! them: a local directory, memory, or with the `http` feature an HTTP server or an S3 bucket.
+//! A `ParameterSource` fetches the serialized artifact with the provided id, and
+//! `VerifiedSource` checks it against the size and hash of the release's `Manifest` before
+//! it is used, so that every source goes through the same checks. The sources stop reading
+//! an artifact once it is larger than the size listed in the manifest.
+use crate::manifest::{deserialize_exact, Manifest, ManifestError};
+use algebra::serialize::CanonicalDeserialize;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// Error raised while fetching an artifact
+pub enum SourceError {
+    #[error("I/O Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Manifest Error: {0}")]
+    ManifestError(#[from] ManifestError),
+    #[error("Artifact {0} was not found")]
+    NotFound(String),
+    #[error("{0} is not a valid artifact id")]
+    InvalidId(String),
+    #[error("Artifact {id} is larger than its expected size of {size} bytes")]
+    TooLarge { id: String, size: u64 },
+    #[cfg(feature = "http")]
+    #[error("HTTP Error: {0}")]
+    HttpError(String),
+}
+
+/// Fetches the serialized artifacts of a release by id, e.g. `epochs.vk`
+pub trait ParameterSource {
+    /// Returns the bytes of the artifact, or `SourceError::NotFound` if the source does not
+    /// have it. If its expected size is provided, fails with `SourceError::TooLarge` without
+    /// reading more than one byte past it. The bytes are not checked, see `VerifiedSource`.
+    fn fetch(&self, id: &str, size: Option<u64>) -> Result<Vec<u8>, SourceError>;
+}
+
+impl<S: ParameterSource + ?Sized> ParameterSource for &S {
+    fn fetch(&self, id: &str, size: Option<u64>) -> Result<Vec<u8>, SourceError> {
+        (**self).fetch(id, size)
+    }
+}
+
+impl<S: ParameterSource + ?Sized> ParameterSource for Box<S> {
+    fn fetch(&self, id: &str, size: Option<u64>) -> Result<Vec<u8>, SourceError> {
+        (**self).fetch(id, size)
+    }
+}
+
+/// Reads the artifact from the reader, up to one byte past its expected size
+fn read_bounded<R: Read>(reader: R, id: &str, size: Option<u64>) -> Result<Vec<u8>, SourceError> {
+    // one byte past the size tells that the artifact is too large
+    let limit = size.map_or(u64::MAX, |size| size.saturating_add(1));
+    let mut bytes = vec![];
+    reader.take(limit).read_to_end(&mut bytes)?;
+    check_size(&bytes, id, size)?;
+    Ok(bytes)
+}
+
+fn check_size(bytes: &[u8], id: &str, size: Option<u64>) -> Result<(), SourceError> {
+    match size {
+        Some(size) if bytes.len() as u64 > size => Err(SourceError::TooLarge {
+            id: id.to_owned(),
+            size,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Artifact ids are used as file names and URL paths, so they may only contain ASCII
+/// alphanumerics, `-`, `_` and `.`, and may not start with a `.`
+fn check_id(id: &str) -> Result<(), SourceError> {
+    let valid = !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !valid {
+        return Err(SourceError::InvalidId(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Reads the artifacts from the files of a directory, named after their ids
+#[derive(Clone, Debug)]
+pub struct FileSource {
+    directory: PathBuf,
+}
+
+impl FileSource {
+    /// Reads the artifacts from the directory
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        Self {
+            directory: directory.as_ref().to_owned(),
+        }
+    }
+}
+
+impl ParameterSource for FileSource {
+    fn fetch(&self, id: &str, size: Option<u64>) -> Result<Vec<u8>, SourceError> {
+        check_id(id)?;
+        let file = File::open(self.directory.join(id)).map_err(|err| match err.kind() {
+            ErrorKind::NotFound => SourceError::NotFound(id.to_owned()),
+            _ => SourceError::IoError(err),
+        })?;
+        read_bounded(file, id, size)
+    }
+}
+
+/// Holds the artifacts in memory, e.g. when they are embedded in the binary or in tests
+#[derive(Clone, Debug, Default)]
+pub struct MemorySource {
+    artifacts: HashMap<String, Vec<u8>>,
+}
+
+impl MemorySource {
+    /// Creates an empty source
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the artifact, replacing any artifact with the same id
+    pub fn insert(&mut self, id: &str, bytes: Vec<u8>) {
+        self.artifacts.insert(id.to_owned(), bytes);
+    }
+}
+
+impl ParameterSource for MemorySource {
+    fn fetch(&self, id: &str, size: Option<u64>) -> Result<Vec<u8>, SourceError> {
+        let bytes = self
+            .artifacts
+            .get(id)
+            .ok_or_else(|| SourceError::NotFound(id.to_owned()))?;
+        check_size(bytes, id, size)?;
+        Ok(bytes.clone())
+    }
+}
+
+/// Downloads the artifacts with HTTP GET requests to `<base url>/<id>`
+#[cfg(feature = "http")]
+#[derive(Clone, Debug)]
+pub struct HttpSource {
+    base_url: String,
+}
+
+#[cfg(feature = "http")]
+impl HttpSource {
+    /// Downloads the artifacts from the base URL, e.g. `https://example.com/epochs/v1`
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    /// Downloads the artifacts under `prefix` from a publicly readable S3 bucket. The
+    /// requests are not signed, so private buckets must be served through a presigned or
+    /// proxied URL with `new`.
+    pub fn s3(bucket: &str, region: &str, prefix: &str) -> Self {
+        let url = format!("https://{}.s3.{}.amazonaws.com", bucket, region);
+        let prefix = prefix.trim_matches('/');
+        if prefix.is_empty() {
+            Self::new(&url)
+        } else {
+            Self::new(&format!("{}/{}", url, prefix))
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+impl ParameterSource for HttpSource {
+    fn fetch(&self, id: &str, size: Option<u64>) -> Result<Vec<u8>, SourceError> {
+        check_id(id)?;
+        let response = ureq::get(&format!("{}/{}", self.base_url, id)).call();
+        if let Some(err) = response.synthetic_error() {
+            return Err(SourceError::HttpError(err.to_string()));
+        }
+        match response.status() {
+            // S3 answers 403 for missing keys of buckets which cannot be listed
+            403 | 404 => return Err(SourceError::NotFound(id.to_owned())),
+            status if !response.ok() => {
+                return Err(SourceError::HttpError(format!(
+                    "{} answered with status {}",
+                    self.base_url, status
+                )))
+            }
+            _ => {}
+        }
+        // the body is read through `read_bounded`, so that a server sending more than the
+        // artifact's size cannot exhaust the memory
+        read_bounded(response.into_reader(), id, size)
+    }
+}
+
+/// Fetches the artifacts from a source, and checks each of them against the release's
+/// manifest, which should have been verified with `SignedManifest::verify`
+#[derive(Clone, Debug)]
+pub struct VerifiedSource<S> {
+    source: S,
+    manifest: Manifest,
+}
+
+impl<S: ParameterSource> VerifiedSource<S> {
+    /// Checks the artifacts of the source against the manifest
+    pub fn new(source: S, manifest: Manifest) -> Self {
+        Self { source, manifest }
+    }
+
+    /// The manifest which the artifacts are checked against
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Fetches the artifact and deserializes it, e.g. the `VerifyingKey` of `epochs.vk`
+    pub fn load<T: CanonicalDeserialize>(&self, id: &str) -> Result<T, SourceError> {
+        let bytes = self.fetch(id, None)?;
+        Ok(deserialize_exact(&bytes)?)
+    }
+}
+
+impl<S: ParameterSource> ParameterSource for VerifiedSource<S> {
+    /// Fetches the artifact, failing if it is not listed in the manifest or does not match
+    /// its size and hash. The size of the manifest bounds the read, whichever `size` is
+    /// provided.
+    fn fetch(&self, id: &str, _: Option<u64>) -> Result<Vec<u8>, SourceError> {
+        // unlisted artifacts are rejected before fetching anything
+        let artifact = self.manifest.artifact(id)?;
+        let bytes = self.source.fetch(id, Some(artifact.size))?;
+        artifact.validate(&bytes)?;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{Artifact, CurveId};
+    use algebra::{bls12_377::G2Projective, serialize::CanonicalSerialize, UniformRand};
+
+    fn source_with(id: &str, bytes: &[u8]) -> MemorySource {
+        let mut source = MemorySource::new();
+        source.insert(id, bytes.to_vec());
+        source
+    }
+
+    #[test]
+    fn verified_source_checks_the_manifest() {
+        let rng = &mut rand::thread_rng();
+        let point = G2Projective::rand(rng);
+        let mut bytes = vec![];
+        point.serialize(&mut bytes).unwrap();
+        let manifest = Manifest {
+            circuit_version: 1,
+            artifacts: vec![Artifact::new("point", 1, CurveId::Bls12_377, &bytes)],
+        };
+
+        let source = VerifiedSource::new(source_with("point", &bytes), manifest.clone());
+        assert_eq!(source.load::<G2Projective>("point").unwrap(), point);
+
+        let mut tampered = bytes.clone();
+        tampered[0] ^= 1;
+        let source = VerifiedSource::new(source_with("point", &tampered), manifest.clone());
+        match source.fetch("point", None) {
+            Err(SourceError::ManifestError(ManifestError::HashMismatch(_))) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let source = VerifiedSource::new(source_with("other", &bytes), manifest);
+        match source.fetch("other", None) {
+            Err(SourceError::ManifestError(ManifestError::UnknownArtifact(_))) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match source.fetch("point", None) {
+            Err(SourceError::NotFound(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn stops_reading_past_the_expected_size() {
+        let bytes = vec![7u8; 100];
+        let manifest = Manifest {
+            circuit_version: 1,
+            artifacts: vec![Artifact::new("key", 1, CurveId::BW6_761, &bytes[..60])],
+        };
+        let source = VerifiedSource::new(source_with("key", &bytes), manifest);
+        match source.fetch("key", None) {
+            Err(SourceError::TooLarge { size: 60, .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // a stream is not read further than one byte past the size
+        let mut reader = &bytes[..];
+        match read_bounded(&mut reader, "key", Some(60)) {
+            Err(SourceError::TooLarge { size: 60, .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(reader.len(), 39);
+        assert_eq!(read_bounded(&bytes[..], "key", Some(100)).unwrap(), bytes);
+        assert_eq!(read_bounded(&bytes[..], "key", None).unwrap(), bytes);
+    }
+
+    #[test]
+    fn file_source_reads_the_directory() {
+        let directory =
+            std::env::temp_dir().join(format!("epoch-snark-source-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("epochs.vk"), b"key").unwrap();
+
+        let source = FileSource::new(&directory);
+        assert_eq!(source.fetch("epochs.vk", None).unwrap(), b"key");
+        assert_eq!(source.fetch("epochs.vk", Some(3)).unwrap(), b"key");
+        match source.fetch("epochs.vk", Some(2)) {
+            Err(SourceError::TooLarge { size: 2, .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match source.fetch("epochs.pk", None) {
+            Err(SourceError::NotFound(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        for id in &["", "../epochs.vk", ".hidden", "sub/epochs.vk"] {
+            match source.fetch(id, None) {
+                Err(SourceError::InvalidId(_)) => {}
+                res => panic!("unexpected result {:?} for {}", res, id),
+            }
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
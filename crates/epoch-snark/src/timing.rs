@@ -0,0 +1,234 @@
+//! Instrumentation for measuring how long each namespace of a circuit takes to synthesize.
+//!
+//! Constraint counts only tell part of the story: witness generation for the epoch circuit
+//! takes minutes, and most of it is spent in a handful of gadgets. Wrap a circuit in a
+//! `TimedCircuit` (or a constraint system in a `TimingConstraintSystem`) to find out which.
+use algebra::Field;
+use r1cs_core::{
+    ConstraintSynthesizer, ConstraintSystem, LinearCombination, SynthesisError, Variable,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+/// The default depth up to which namespaces are timed individually
+pub const DEFAULT_MAX_DEPTH: usize = 4;
+
+/// The time spent synthesizing a namespace, including the time spent in its children
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamespaceTiming {
+    /// The full path of the namespace
+    pub path: String,
+    /// The total time spent in the namespace across all the times it was entered
+    pub elapsed: Duration,
+    /// The number of times the namespace was entered
+    pub count: usize,
+}
+
+/// Wraps a root constraint system and times each namespace entered during synthesis.
+///
+/// Namespaces deeper than `max_depth` are not timed individually and are accounted for in
+/// their ancestor at `max_depth`, which keeps the overhead low and the report readable.
+pub struct TimingConstraintSystem<CS> {
+    inner: CS,
+    max_depth: usize,
+    // The path and start time of each open namespace, `None` for the ones which are too deep
+    stack: Vec<Option<(String, Instant)>>,
+    timings: HashMap<String, (Duration, usize)>,
+}
+
+impl<CS> TimingConstraintSystem<CS> {
+    /// Wraps the constraint system, timing namespaces up to `max_depth` levels deep
+    pub fn new(inner: CS, max_depth: usize) -> Self {
+        Self {
+            inner,
+            max_depth,
+            stack: Vec::new(),
+            timings: HashMap::new(),
+        }
+    }
+
+    /// Returns the timings of all namespaces, slowest first
+    pub fn report(&self) -> Vec<NamespaceTiming> {
+        let mut report = self
+            .timings
+            .iter()
+            .map(|(path, (elapsed, count))| NamespaceTiming {
+                path: path.clone(),
+                elapsed: *elapsed,
+                count: *count,
+            })
+            .collect::<Vec<_>>();
+        report.sort_by(|a, b| b.elapsed.cmp(&a.elapsed).then_with(|| a.path.cmp(&b.path)));
+        report
+    }
+
+    /// Logs the `num` slowest namespaces
+    pub fn log_report(&self, num: usize) {
+        for timing in self.report().iter().take(num) {
+            info!(
+                "{}: {:?} ({} times)",
+                timing.path, timing.elapsed, timing.count
+            );
+        }
+    }
+
+    /// Returns the wrapped constraint system
+    pub fn into_inner(self) -> CS {
+        self.inner
+    }
+}
+
+impl<F: Field, CS: ConstraintSystem<F>> ConstraintSystem<F> for TimingConstraintSystem<CS> {
+    type Root = Self;
+
+    fn alloc<FN, A, AR>(&mut self, annotation: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.inner.alloc(annotation, f)
+    }
+
+    fn alloc_input<FN, A, AR>(&mut self, annotation: A, f: FN) -> Result<Variable, SynthesisError>
+    where
+        FN: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.inner.alloc_input(annotation, f)
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LB: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LC: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+    {
+        self.inner.enforce(annotation, a, b, c)
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        let name: String = name_fn().into();
+        let entry = if self.stack.len() < self.max_depth {
+            let path = match self.stack.last() {
+                Some(Some((parent, _))) => format!("{}/{}", parent, name),
+                _ => name.clone(),
+            };
+            Some((path, Instant::now()))
+        } else {
+            None
+        };
+        self.stack.push(entry);
+        self.inner.push_namespace(|| name);
+    }
+
+    fn pop_namespace(&mut self) {
+        self.inner.pop_namespace();
+        if let Some(Some((path, start))) = self.stack.pop() {
+            let timing = self.timings.entry(path).or_insert((Duration::default(), 0));
+            timing.0 += start.elapsed();
+            timing.1 += 1;
+        }
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+
+    fn num_constraints(&self) -> usize {
+        self.inner.num_constraints()
+    }
+}
+
+/// Wraps a circuit and logs its slowest namespaces once it has been synthesized
+pub struct TimedCircuit<C> {
+    circuit: C,
+    max_depth: usize,
+    num_reported: usize,
+}
+
+impl<C> TimedCircuit<C> {
+    /// Wraps the circuit, timing namespaces up to `DEFAULT_MAX_DEPTH` levels deep and
+    /// reporting the 10 slowest ones
+    pub fn new(circuit: C) -> Self {
+        Self {
+            circuit,
+            max_depth: DEFAULT_MAX_DEPTH,
+            num_reported: 10,
+        }
+    }
+
+    /// Sets the depth up to which namespaces are timed individually
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the number of namespaces which are reported
+    pub fn num_reported(mut self, num_reported: usize) -> Self {
+        self.num_reported = num_reported;
+        self
+    }
+}
+
+impl<F: Field, C: ConstraintSynthesizer<F>> ConstraintSynthesizer<F> for TimedCircuit<C> {
+    fn generate_constraints<CS: ConstraintSystem<F>>(
+        self,
+        cs: &mut CS,
+    ) -> Result<(), SynthesisError> {
+        let mut timed = TimingConstraintSystem::new(cs, self.max_depth);
+        let start = Instant::now();
+        self.circuit.generate_constraints(&mut timed)?;
+        info!("Synthesized circuit in {:?}", start.elapsed());
+        timed.log_report(self.num_reported);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::bls12_377::Fr;
+    use r1cs_std::test_constraint_system::TestConstraintSystem;
+
+    #[test]
+    fn times_namespaces_up_to_max_depth() {
+        let mut cs = TimingConstraintSystem::new(TestConstraintSystem::<Fr>::new(), 2);
+        for i in 0..3 {
+            let mut outer = cs.ns(|| format!("outer {}", i % 2));
+            let mut inner = outer.ns(|| "inner");
+            let mut deep = inner.ns(|| "deep");
+            deep.alloc(|| "var", || Ok(Fr::from(1u64))).unwrap();
+        }
+
+        let report = cs.report();
+        let mut paths = report
+            .iter()
+            .map(|t| (t.path.as_str(), t.count))
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                ("outer 0", 2),
+                ("outer 0/inner", 2),
+                ("outer 1", 1),
+                ("outer 1/inner", 1),
+            ]
+        );
+
+        // the wrapped constraint system saw the full paths
+        let mut inner = cs.into_inner();
+        assert_eq!(inner.get("outer 1/inner/deep/var"), Fr::from(1u64));
+    }
+}
@@ -0,0 +1,203 @@
+//! Native checks of the prover's inputs.
+//!
+//! An invalid transition only shows up as an unsatisfied constraint once the whole epochs
+//! circuit has been synthesized and proven, which takes hours for long ranges. `check_witness`
+//! runs the circuit's checks natively and reports the offending epoch or transition, and is
+//! called by the provers before any multi-scalar multiplication. Verifying the seals hashes
+//! each block to G1 once, which is negligible next to the proof.
+use crate::{
+    encoding::{EncodingError, EncodingVersion},
+    epoch_block::{EpochBlock, EpochTransition},
+    epoch_index::{EpochIndex, EpochIndexError},
+};
+use algebra::ProjectiveCurve;
+use bls_crypto::{
+    bls::subgroup::{is_in_g1_subgroup, is_valid_g2},
+    hash_to_curve::try_and_increment::COMPOSITE_HASH_TO_G1,
+    BLSError, PublicKey,
+};
+use rayon::prelude::*;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// Error raised when the prover's inputs cannot satisfy the epochs circuit
+pub enum WitnessError {
+    #[error("Epoch Index Error: {0}")]
+    EpochIndexError(#[from] EpochIndexError),
+    #[error("Epoch {epoch} has {got} public keys, expected {expected}")]
+    KeyCount {
+        epoch: u16,
+        got: usize,
+        expected: usize,
+    },
+    #[error("Public key {key} of epoch {epoch} is not in the G2 subgroup")]
+    KeyNotInSubgroup { epoch: u16, key: usize },
+    #[error("Epoch {epoch} has {active} active validators, but at least {min} are required")]
+    TooFewValidators { epoch: u16, active: usize, min: u32 },
+    #[error("Transition {position} uses encoding version {got:?}, expected {expected:?}")]
+    VersionMismatch {
+        position: usize,
+        expected: EncodingVersion,
+        got: EncodingVersion,
+    },
+    #[error("The parent entropy of transition {position} is not the previous epoch's entropy")]
+    EntropyMismatch { position: usize },
+    #[error("Transition {position} has a bitmap of length {got}, expected {expected}")]
+    BitmapLength {
+        position: usize,
+        got: usize,
+        expected: usize,
+    },
+    #[error(
+        "Transition {position} has {non_signers} non-signers, but at most {maximum} are allowed"
+    )]
+    ThresholdNotMet {
+        position: usize,
+        non_signers: usize,
+        maximum: u32,
+    },
+    #[error("The aggregate signature of transition {position} is not in the G1 subgroup")]
+    SignatureNotInSubgroup { position: usize },
+    #[error("Transition {position} cannot be encoded: {source}")]
+    Encoding {
+        position: usize,
+        #[source]
+        source: EncodingError,
+    },
+    #[error("The seal of transition {position} does not verify: {source}")]
+    InvalidSeal {
+        position: usize,
+        #[source]
+        source: BLSError,
+    },
+}
+
+/// Checks natively that the transitions from the initial epoch satisfy the epochs circuit
+/// with `num_validators` validators per epoch, at least `min_validators` of them active, and
+/// room for `max_transitions` transitions: the epochs are consecutive, the keys are in the
+/// G2 subgroup, each bitmap leaves at most the previous epoch's `maximum_non_signers` out,
+/// the entropies are chained and each aggregate signature verifies against the signers of the
+/// previous epoch.
+///
+/// The first failing check is returned, with the index of the offending epoch or the
+/// position of the offending transition.
+pub fn check_witness(
+    min_validators: u32,
+    num_validators: u32,
+    initial_epoch: &EpochBlock,
+    transitions: &[EpochTransition],
+    max_transitions: usize,
+) -> Result<(), WitnessError> {
+    EpochIndex::check_transitions(initial_epoch, transitions, max_transitions)?;
+
+    let epochs = std::iter::once(initial_epoch)
+        .chain(transitions.iter().map(|transition| &transition.block))
+        .collect::<Vec<_>>();
+    for epoch in &epochs {
+        check_keys(epoch, min_validators, num_validators as usize)?;
+    }
+
+    // each transition is signed by the validators of the epoch before it
+    for (position, (previous, transition)) in epochs.iter().zip(transitions).enumerate() {
+        let block = &transition.block;
+        if block.version != initial_epoch.version {
+            return Err(WitnessError::VersionMismatch {
+                position,
+                expected: initial_epoch.version,
+                got: block.version,
+            });
+        }
+        if block.version.has_entropy() && block.parent_entropy != previous.epoch_entropy {
+            return Err(WitnessError::EntropyMismatch { position });
+        }
+        if transition.bitmap.len() != num_validators as usize {
+            return Err(WitnessError::BitmapLength {
+                position,
+                got: transition.bitmap.len(),
+                expected: num_validators as usize,
+            });
+        }
+        let non_signers = transition.bitmap.iter().filter(|signed| !**signed).count();
+        if non_signers > previous.maximum_non_signers as usize {
+            return Err(WitnessError::ThresholdNotMet {
+                position,
+                non_signers,
+                maximum: previous.maximum_non_signers,
+            });
+        }
+        let signature = transition.aggregate_signature.as_ref().into_affine();
+        if !is_in_g1_subgroup(&signature) {
+            return Err(WitnessError::SignatureNotInSubgroup { position });
+        }
+    }
+
+    // The seals are the expensive part, so they are verified in parallel once everything
+    // else is known to be consistent. The error of the first invalid seal is returned.
+    (0..transitions.len())
+        .into_par_iter()
+        .map(|position| check_seal(position, epochs[position], &transitions[position]))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Checks the number of keys of the epoch, that they are in the G2 subgroup, and that enough
+/// of them are active
+fn check_keys(
+    epoch: &EpochBlock,
+    min_validators: u32,
+    num_validators: usize,
+) -> Result<(), WitnessError> {
+    let keys = &epoch.new_public_keys;
+    if keys.len() != num_validators {
+        return Err(WitnessError::KeyCount {
+            epoch: epoch.index,
+            got: keys.len(),
+            expected: num_validators,
+        });
+    }
+    if let Some(key) = keys
+        .iter()
+        .position(|key| !is_valid_g2(&key.as_ref().into_affine()))
+    {
+        return Err(WitnessError::KeyNotInSubgroup {
+            epoch: epoch.index,
+            key,
+        });
+    }
+    let active = epoch.num_active_validators();
+    if active < min_validators as usize {
+        return Err(WitnessError::TooFewValidators {
+            epoch: epoch.index,
+            active,
+            min: min_validators,
+        });
+    }
+    Ok(())
+}
+
+/// Verifies the aggregate signature of the transition's certificate against the keys of the
+/// previous epoch which are set in its bitmap. The bitmap was checked against the previous
+/// epoch's threshold, rather than Istanbul's quorum which `QuorumCertificate::verify`
+/// enforces.
+fn check_seal(
+    position: usize,
+    previous: &EpochBlock,
+    transition: &EpochTransition,
+) -> Result<(), WitnessError> {
+    let certificate = transition
+        .certificate()
+        .map_err(|source| WitnessError::Encoding { position, source })?;
+    let signers = certificate
+        .signers()
+        .into_iter()
+        .map(|index| &previous.new_public_keys[index]);
+    PublicKey::aggregate(signers)
+        .verify(
+            &certificate.message,
+            &[],
+            &certificate.aggregate_signature,
+            &*COMPOSITE_HASH_TO_G1,
+        )
+        .map_err(|source| WitnessError::InvalidSeal { position, source })
+}
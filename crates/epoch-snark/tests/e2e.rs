@@ -1,5 +1,11 @@
-use algebra::serialize::CanonicalSerialize;
-use epoch_snark::{prove, trusted_setup, verify};
+use algebra::{bw6_761, serialize::CanonicalSerialize, ProjectiveCurve, UniformRand};
+use epoch_snark::{
+    check_witness, prove, prove_range, prove_with_progress, public_inputs, simulate_proof,
+    trusted_setup, verify, verify_stitched, verify_with_shape, CircuitShape, EncodingVersion,
+    EpochIndexError, LightClientError, LightClientState, ProvingError, ProvingStage,
+    VerificationError, WitnessError,
+};
+use groth16::VerifyingKey;
 
 mod fixtures;
 use fixtures::generate_test_data;
@@ -65,6 +71,68 @@ fn prover_verifier_groth16() {
     dbg!(hex::encode(&last_pubkeys));
 }
 
+#[test]
+#[ignore] // This test makes CI run out of memory and takes too long. It works though!
+fn reports_progress_and_cancels() {
+    let rng = &mut rand::thread_rng();
+    let num_transitions = 2;
+    let faults = 1;
+    let num_validators = 3 * faults + 1;
+    let params = trusted_setup(num_validators, num_transitions, faults, rng, true).unwrap();
+    let (first_epoch, transitions, last_epoch) =
+        generate_test_data(num_validators, faults, num_transitions);
+
+    let mut reports = vec![];
+    let proof = prove_with_progress(
+        &params,
+        num_validators as u32,
+        &first_epoch,
+        &transitions,
+        num_transitions,
+        |progress| {
+            reports.push(progress);
+            true
+        },
+    )
+    .unwrap();
+    verify(&params.epochs.vk, &first_epoch, &last_epoch, &proof).unwrap();
+
+    // the stages run in order, and each one runs to completion
+    let mut stages = reports.iter().map(|p| p.stage).collect::<Vec<_>>();
+    stages.dedup();
+    assert_eq!(
+        stages,
+        vec![
+            ProvingStage::HashWitness,
+            ProvingStage::HashToBits,
+            ProvingStage::Epochs
+        ]
+    );
+    for stage in stages {
+        let last = reports.iter().filter(|p| p.stage == stage).last().unwrap();
+        assert_eq!(last.done, last.total);
+    }
+
+    // cancelling stops before the epochs circuit is synthesized
+    let mut reached_epochs = false;
+    let result = prove_with_progress(
+        &params,
+        num_validators as u32,
+        &first_epoch,
+        &transitions,
+        num_transitions,
+        |progress| {
+            reached_epochs |= progress.stage == ProvingStage::Epochs;
+            progress.stage != ProvingStage::HashToBits
+        },
+    );
+    match result {
+        Err(ProvingError::Cancelled) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(!reached_epochs);
+}
+
 #[test]
 #[ignore] // This test makes CI run out of memory and takes too long. It works though!
 fn prover_verifier_groth16_with_dummy() {
@@ -126,3 +194,272 @@ fn prover_verifier_groth16_with_dummy() {
     dbg!(hex::encode(&first_pubkeys));
     dbg!(hex::encode(&last_pubkeys));
 }
+
+#[test]
+#[ignore] // This test makes CI run out of memory and takes too long. It works though!
+fn stitched_range_proofs() {
+    let rng = &mut rand::thread_rng();
+    let num_transitions = 4;
+    let max_transitions = 2;
+    let faults = 1;
+    let num_validators = 3 * faults + 1;
+
+    let params = trusted_setup(num_validators, max_transitions, faults, rng, false).unwrap();
+
+    let (first_epoch, transitions, last_epoch) =
+        generate_test_data(num_validators, faults, num_transitions);
+
+    // Each half of the chain is proven separately
+    let proofs = [0..2, 2..4]
+        .iter()
+        .map(|range| {
+            prove_range(
+                &params,
+                num_validators as u32,
+                &first_epoch,
+                &transitions,
+                range.clone(),
+                max_transitions,
+            )
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(proofs[0].first_epoch, first_epoch);
+    assert_eq!(proofs[1].last_epoch, last_epoch);
+
+    verify_stitched(&params.epochs.vk, &proofs).unwrap();
+
+    // Proofs whose endpoints do not match are rejected
+    let reversed = vec![proofs[1].clone(), proofs[0].clone()];
+    match verify_stitched(&params.epochs.vk, &reversed) {
+        Err(VerificationError::DisconnectedRanges(0)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+// A verifying key for `num_inputs` public inputs which rejects all proofs, except with
+// negligible probability
+fn random_vk<R: rand::Rng>(num_inputs: usize, rng: &mut R) -> VerifyingKey<bw6_761::BW6_761> {
+    VerifyingKey {
+        alpha_g1: bw6_761::G1Projective::rand(rng).into_affine(),
+        beta_g2: bw6_761::G2Projective::rand(rng).into_affine(),
+        gamma_g2: bw6_761::G2Projective::rand(rng).into_affine(),
+        delta_g2: bw6_761::G2Projective::rand(rng).into_affine(),
+        gamma_abc_g1: (0..=num_inputs)
+            .map(|_| bw6_761::G1Projective::rand(rng).into_affine())
+            .collect(),
+    }
+}
+
+#[test]
+fn simulated_proofs_have_real_structure_but_fail() {
+    let rng = &mut rand::thread_rng();
+    let (first_epoch, transitions, last_epoch) = generate_test_data(4, 1, 2);
+
+    let simulated = simulate_proof(&first_epoch, &transitions, rng).unwrap();
+    assert_eq!(simulated.first_epoch, first_epoch);
+    assert_eq!(simulated.last_epoch, last_epoch);
+    let mut serialized = vec![];
+    simulated.proof.serialize(&mut serialized).unwrap();
+    assert_eq!(serialized.len(), simulated.proof.serialized_size());
+
+    // any verifying key with the right number of public inputs rejects it
+    let num_inputs = public_inputs(&first_epoch, &last_epoch).unwrap().len();
+    let vk = random_vk(num_inputs, rng);
+    match verify(&vk, &first_epoch, &last_epoch, &simulated.proof) {
+        Err(VerificationError::VerificationFailed) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    // the epochs must be a valid range
+    simulate_proof(&first_epoch, &[], rng).unwrap_err();
+}
+
+#[test]
+fn swapped_and_non_consecutive_epochs_are_rejected() {
+    let rng = &mut rand::thread_rng();
+    let (first_epoch, transitions, last_epoch) = generate_test_data(4, 1, 3);
+
+    match public_inputs(&last_epoch, &first_epoch) {
+        Err(VerificationError::InvalidEpochRange(EpochIndexError::NotIncreasing {
+            first: 3,
+            last: 0,
+        })) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let gap = [transitions[0].clone(), transitions[2].clone()];
+    match simulate_proof(&first_epoch, &gap, rng) {
+        Err(VerificationError::InvalidEpochRange(EpochIndexError::NotConsecutive {
+            position: 1,
+            expected: 2,
+            got: 3,
+        })) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn light_client_rejects_invalid_and_out_of_order_proofs() {
+    let rng = &mut rand::thread_rng();
+    let (first_epoch, transitions, _) = generate_test_data(4, 1, 2);
+    let mut state = LightClientState::new(&first_epoch).unwrap();
+    let initial = state.clone();
+    let num_inputs = public_inputs(&first_epoch, &transitions[1].block)
+        .unwrap()
+        .len();
+    let vk = random_vk(num_inputs, rng);
+
+    // an invalid proof does not advance the state
+    let bundle = simulate_proof(&first_epoch, &transitions, rng).unwrap();
+    match state.apply_proof(&vk, &bundle) {
+        Err(LightClientError::VerificationError(VerificationError::VerificationFailed)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert_eq!(state, initial);
+
+    // proofs must start from the state's epoch
+    let later = simulate_proof(&transitions[0].block, &transitions[1..], rng).unwrap();
+    match state.apply_proof(&vk, &later) {
+        Err(LightClientError::EpochMismatch {
+            expected: 0,
+            got: 1,
+        }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn reports_circuit_mismatches_before_verifying() {
+    let rng = &mut rand::thread_rng();
+    let (first_epoch, transitions, last_epoch) = generate_test_data(4, 1, 2);
+    let bundle = simulate_proof(&first_epoch, &transitions, rng).unwrap();
+    let num_inputs = public_inputs(&first_epoch, &last_epoch).unwrap().len();
+
+    // a key for a circuit with another number of public inputs
+    let vk = random_vk(num_inputs + 1, rng);
+    match verify(&vk, &first_epoch, &last_epoch, &bundle.proof) {
+        Err(VerificationError::PublicInputCountMismatch { expected, got })
+            if expected == num_inputs + 1 && got == num_inputs => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let vk = random_vk(num_inputs, rng);
+    let shape = CircuitShape {
+        num_validators: 4,
+        max_epochs: 2,
+        version: EncodingVersion::V1,
+        min_validators: 0,
+    };
+    let check = |shape: CircuitShape| {
+        verify_with_shape(&vk, &shape, &first_epoch, &last_epoch, &bundle.proof)
+    };
+    match check(shape) {
+        Err(VerificationError::VerificationFailed) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    match check(CircuitShape {
+        num_validators: 5,
+        ..shape
+    }) {
+        Err(VerificationError::ValidatorCountMismatch {
+            index: 0,
+            expected: 5,
+            got: 4,
+        }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    match check(CircuitShape {
+        version: EncodingVersion::V2,
+        ..shape
+    }) {
+        Err(VerificationError::EncodingVersionMismatch { .. }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    match check(CircuitShape {
+        max_epochs: 1,
+        ..shape
+    }) {
+        Err(VerificationError::TooManyEpochs { max: 1, got: 2 }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    match check(CircuitShape {
+        min_validators: 5,
+        ..shape
+    }) {
+        Err(VerificationError::TooFewValidators {
+            index: 0,
+            min: 5,
+            got: 4,
+        }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn invalid_witnesses_are_rejected_before_proving() {
+    let (first_epoch, transitions, _) = generate_test_data(4, 1, 3);
+    let check = |min_validators: u32, transitions: &[_]| {
+        check_witness(min_validators, 4, &first_epoch, transitions, 3)
+    };
+    check(4, &transitions).unwrap();
+
+    match check(5, &transitions) {
+        Err(WitnessError::TooFewValidators {
+            epoch: 0,
+            active: 4,
+            min: 5,
+        }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let gap = [transitions[0].clone(), transitions[2].clone()];
+    match check(0, &gap) {
+        Err(WitnessError::EpochIndexError(EpochIndexError::NotConsecutive {
+            position: 1, ..
+        })) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let mut missing_key = transitions.clone();
+    missing_key[1].block.new_public_keys.pop();
+    match check(0, &missing_key) {
+        Err(WitnessError::KeyCount {
+            epoch: 2,
+            got: 3,
+            expected: 4,
+        }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let mut short_bitmap = transitions.clone();
+    short_bitmap[2].bitmap.pop();
+    match check(0, &short_bitmap) {
+        Err(WitnessError::BitmapLength {
+            position: 2,
+            got: 3,
+            expected: 4,
+        }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    // the epochs allow a single non-signer
+    let mut below_threshold = transitions.clone();
+    below_threshold[1].bitmap = vec![false, false, true, true];
+    match check(0, &below_threshold) {
+        Err(WitnessError::ThresholdNotMet {
+            position: 1,
+            non_signers: 2,
+            maximum: 1,
+        }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let mut swapped_seals = transitions.clone();
+    swapped_seals[1].aggregate_signature = transitions[2].aggregate_signature.clone();
+    swapped_seals[2].aggregate_signature = transitions[1].aggregate_signature.clone();
+    match check(0, &swapped_seals) {
+        Err(WitnessError::InvalidSeal { position: 1, .. }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+}
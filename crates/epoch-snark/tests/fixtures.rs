@@ -3,7 +3,7 @@ use algebra::{
     ProjectiveCurve, Zero,
 };
 
-use bls_crypto::test_helpers::{keygen_batch, keygen_mul};
+use bls_crypto::testing::{keygen_batch, keygen_mul};
 use bls_crypto::{PublicKey, Signature};
 use epoch_snark::{EpochBlock, EpochTransition};
 
@@ -68,11 +68,7 @@ pub fn generate_test_data(
 }
 
 fn generate_block(index: usize, non_signers: usize, pubkeys: &[PublicKey]) -> EpochBlock {
-    EpochBlock {
-        index: index as u16,
-        maximum_non_signers: non_signers as u32,
-        new_public_keys: pubkeys.to_vec(),
-    }
+    EpochBlock::new(index as u16, non_signers as u32, pubkeys.to_vec())
 }
 
 // generates `num_epochs` bitmaps with `num_validators - faults` 1 bits set and `faults` 0 bits set